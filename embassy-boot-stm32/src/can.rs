@@ -0,0 +1,18 @@
+use embassy_boot::can::CanBus;
+use embassy_stm32::can::enums::BusError;
+use embassy_stm32::can::{Can, Frame};
+
+impl<'d> CanBus for Can<'d> {
+    type Frame = Frame;
+    type Error = BusError;
+
+    async fn transmit(&mut self, frame: &Frame) -> Result<(), BusError> {
+        // A frame replaced in a mailbox can only be an earlier response, which the tool gave up on.
+        let _ = self.write(frame).await;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Frame, BusError> {
+        self.read().await.map(|envelope| envelope.frame)
+    }
+}
@@ -2,12 +2,19 @@
 #![warn(missing_docs)]
 #![doc = include_str!("../README.md")]
 mod fmt;
+#[cfg(feature = "can")]
+mod can;
+
+use core::convert::Infallible;
 
 pub use embassy_boot::{
     AlignedBuffer, BlockingFirmwareState, BlockingFirmwareUpdater, BootError, BootLoaderConfig, FirmwareState,
-    FirmwareUpdater, FirmwareUpdaterConfig, State,
+    FirmwareUpdater, FirmwareUpdaterConfig, Manifest, RollbackCounter, State,
 };
+use embassy_stm32::rtc::Rtc;
 use embedded_storage::nor_flash::NorFlash;
+#[cfg(feature = "can")]
+pub use embassy_boot::can::{CanBus, CanUpdater, Config as CanUpdaterConfig};
 
 /// A bootloader for STM32 devices.
 pub struct BootLoader {
@@ -49,3 +56,35 @@ impl BootLoader {
         cortex_m::asm::bootload(start as *const u32)
     }
 }
+
+/// Anti-rollback counter in an RTC backup register.
+///
+/// Backup registers keep their value through resets and while V_BAT is powered, but they're
+/// cleared when both VDD and V_BAT are lost, or on a tamper event, which resets the counter to 0.
+pub struct BackupRegisterCounter<'a> {
+    rtc: &'a Rtc,
+    register: usize,
+}
+
+impl<'a> BackupRegisterCounter<'a> {
+    /// Create a counter stored in backup register `register`.
+    pub fn new(rtc: &'a Rtc, register: usize) -> Self {
+        assert!(register < Rtc::BACKUP_REGISTER_COUNT);
+        Self { rtc, register }
+    }
+}
+
+impl<'a> RollbackCounter for BackupRegisterCounter<'a> {
+    type Error = Infallible;
+
+    fn get(&mut self) -> Result<u32, Self::Error> {
+        Ok(self.rtc.read_backup_register(self.register).unwrap_or(0))
+    }
+
+    fn raise(&mut self, value: u32) -> Result<(), Self::Error> {
+        if value > self.get()? {
+            self.rtc.write_backup_register(self.register, value);
+        }
+        Ok(())
+    }
+}
@@ -280,6 +280,78 @@ impl<ACTIVE: NorFlash, DFU: NorFlash, STATE: NorFlash> BootLoader<ACTIVE, DFU, S
         Ok(state)
     }
 
+    /// Perform boot preparations like [`prepare_boot`](Self::prepare_boot), but only swap in an
+    /// update with a valid [`Manifest`](crate::Manifest).
+    ///
+    /// The manifest signature is checked with `public_key`, and its security version must be at
+    /// least the value of `counter`. A rejected update is cancelled and the active image boots, so
+    /// an image written to the DFU partition without the application's own checks never runs.
+    #[cfg(feature = "_verify")]
+    pub fn prepare_boot_verified(
+        &mut self,
+        aligned_buf: &mut [u8],
+        public_key: &[u8; 32],
+        counter: &mut impl crate::RollbackCounter,
+    ) -> Result<State, BootError> {
+        // The swap overwrites the manifest, so it can only be checked before the swap starts
+        if self.read_state(aligned_buf)? == State::Swap
+            && self.current_progress(aligned_buf)? == 0
+            && !self.verify_dfu(aligned_buf, public_key, counter)?
+        {
+            warn!("Rejecting update");
+            self.state.erase(0, self.state.capacity() as u32)?;
+
+            let state_word = &mut aligned_buf[..STATE::WRITE_SIZE];
+            state_word.fill(BOOT_MAGIC);
+            self.state.write(0, state_word)?;
+        }
+
+        self.prepare_boot(aligned_buf)
+    }
+
+    #[cfg(feature = "_verify")]
+    fn verify_dfu(
+        &mut self,
+        aligned_buf: &mut [u8],
+        public_key: &[u8; 32],
+        counter: &mut impl crate::RollbackCounter,
+    ) -> Result<bool, BootError> {
+        use digest::Digest;
+
+        use crate::manifest::Sha512;
+        use crate::Manifest;
+
+        let capacity = self.dfu.capacity();
+        let mut bytes = [0; Manifest::SIZE];
+        for offset in (0..Manifest::SIZE).step_by(aligned_buf.len()) {
+            let chunk_len = aligned_buf.len().min(Manifest::SIZE - offset);
+            self.dfu.read(
+                Manifest::offset(capacity) + offset as u32,
+                &mut aligned_buf[..chunk_len],
+            )?;
+            bytes[offset..offset + chunk_len].copy_from_slice(&aligned_buf[..chunk_len]);
+        }
+        let Some(manifest) = Manifest::from_bytes(&bytes).filter(|m| m.fits(capacity)) else {
+            return Ok(false);
+        };
+        match counter.get() {
+            Ok(min_version) if manifest.security_version >= min_version => {}
+            _ => return Ok(false),
+        }
+
+        let mut digest = Sha512::new();
+        for offset in (0..manifest.image_len).step_by(aligned_buf.len()) {
+            self.dfu.read(offset, aligned_buf)?;
+            let len = core::cmp::min((manifest.image_len - offset) as usize, aligned_buf.len());
+            digest.update(&aligned_buf[..len]);
+        }
+        digest.update(manifest.header());
+
+        let mut message = [0; 64];
+        message.copy_from_slice(digest.finalize().as_slice());
+        Ok(manifest.verify(public_key, &message).is_ok())
+    }
+
     fn is_swapped(&mut self, aligned_buf: &mut [u8]) -> Result<bool, BootError> {
         let page_count = self.active.capacity() / Self::PAGE_SIZE as usize;
         let progress = self.current_progress(aligned_buf)?;
@@ -0,0 +1,282 @@
+//! Firmware updates over CAN.
+//!
+//! [`CanUpdater`] receives a firmware image over a CAN bus and writes it with a [`FirmwareUpdater`],
+//! so that every node of a CAN network can be updated from a single tool connected to the bus.
+//! Each node answers on its own pair of CAN ids, see [`Config::for_node`].
+//!
+//! The protocol follows the block transfer of UDS (ISO 14229) in single classic CAN frames. Requests
+//! start with a service id, positive responses start with the service id plus `0x40`, and negative
+//! responses are `[0x7F, service id, response code]`:
+//!
+//! | Request                             | Positive response   | Service               |
+//! |-------------------------------------|---------------------|-----------------------|
+//! | `[0x34, size (u32, big endian)]`    | `[0x74, block size]`| Request download      |
+//! | `[0x36, sequence, data (1-6 bytes)]`| `[0x76, sequence]`  | Transfer data         |
+//! | `[0x37]`                            | `[0x77]`            | Request transfer exit |
+//! | `[0x11, 0x01]`                      | `[0x51, 0x01]`      | ECU reset             |
+//!
+//! Transfer data frames are numbered from 1, wrapping from 255 to 0. The node acknowledges every
+//! `block size` frames and the last frame of the image, and the tool waits for each acknowledgement
+//! before sending the next block. Any error aborts the transfer, which is then restarted with a new
+//! request download. Once all the data is received, request transfer exit marks the update, which is
+//! applied when the tool requests a reset.
+
+use embedded_can::{Frame, Id, StandardId};
+use embedded_storage_async::nor_flash::NorFlash;
+
+use crate::FirmwareUpdater;
+
+const SID_REQUEST_DOWNLOAD: u8 = 0x34;
+const SID_TRANSFER_DATA: u8 = 0x36;
+const SID_REQUEST_TRANSFER_EXIT: u8 = 0x37;
+const SID_ECU_RESET: u8 = 0x11;
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+
+const NRC_SERVICE_NOT_SUPPORTED: u8 = 0x11;
+const NRC_INCORRECT_MESSAGE_LENGTH: u8 = 0x13;
+const NRC_REQUEST_SEQUENCE_ERROR: u8 = 0x24;
+const NRC_REQUEST_OUT_OF_RANGE: u8 = 0x31;
+const NRC_TRANSFER_DATA_SUSPENDED: u8 = 0x71;
+const NRC_GENERAL_PROGRAMMING_FAILURE: u8 = 0x72;
+const NRC_WRONG_BLOCK_SEQUENCE_COUNTER: u8 = 0x73;
+
+/// Hard reset, the only reset type supported.
+const RESET_HARD: u8 = 0x01;
+
+/// A CAN bus, as seen by [`CanUpdater`].
+///
+/// Implement this for the driver of the CAN peripheral, e.g. by forwarding to its `read` and
+/// `write` methods.
+pub trait CanBus {
+    /// CAN frame type of the driver.
+    type Frame: Frame;
+    /// Error type of the driver.
+    type Error;
+
+    /// Send a frame.
+    async fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error>;
+
+    /// Wait for a frame.
+    async fn receive(&mut self) -> Result<Self::Frame, Self::Error>;
+}
+
+/// Configuration of a [`CanUpdater`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// CAN id of the requests to this node. Frames with any other id are ignored.
+    pub request_id: Id,
+    /// CAN id of the responses of this node.
+    pub response_id: Id,
+    /// Number of transfer data frames between acknowledgements, at least 1.
+    pub block_size: u8,
+}
+
+impl Config {
+    /// Configuration for node `node` of the network, in `0..0x80`: requests use the standard id
+    /// `0x600 + node`, and responses `0x680 + node`.
+    pub fn for_node(node: u8) -> Self {
+        assert!(node < 0x80);
+        Self {
+            request_id: Id::Standard(unwrap!(StandardId::new(0x600 + node as u16))),
+            response_id: Id::Standard(unwrap!(StandardId::new(0x680 + node as u16))),
+            block_size: 16,
+        }
+    }
+}
+
+/// Positive response to a request.
+enum Reply {
+    /// No response, the frame is acknowledged later.
+    None,
+    /// Response with no parameter.
+    Positive,
+    /// Response with one parameter.
+    PositiveWith(u8),
+}
+
+/// A transfer in progress.
+struct Transfer {
+    /// Size of the image, in bytes.
+    size: usize,
+    /// Number of bytes received.
+    received: usize,
+    /// Number of received bytes held in the buffer, not yet written.
+    buffered: usize,
+    /// Sequence number of the last frame received.
+    sequence: u8,
+    /// Number of frames received since the last acknowledgement.
+    unacknowledged: u8,
+}
+
+/// Receives firmware updates over CAN, see the [module documentation](self).
+pub struct CanUpdater<'b, C> {
+    bus: C,
+    config: Config,
+    buf: &'b mut [u8],
+    transfer: Option<Transfer>,
+    complete: bool,
+}
+
+impl<'b, C: CanBus> CanUpdater<'b, C> {
+    /// Create an updater on `bus`.
+    ///
+    /// Received data is collected in `buf` and written to the DFU partition each time it is full.
+    /// `buf` must be aligned as required by the DFU flash, e.g. an [`AlignedBuffer`](crate::AlignedBuffer),
+    /// and its length must be a multiple of the flash write size.
+    pub fn new(bus: C, config: Config, buf: &'b mut [u8]) -> Self {
+        assert!(config.block_size > 0);
+        assert!(!buf.is_empty());
+        Self {
+            bus,
+            config,
+            buf,
+            transfer: None,
+            complete: false,
+        }
+    }
+
+    /// Return the CAN bus.
+    pub fn into_inner(self) -> C {
+        self.bus
+    }
+
+    /// Serve requests until the tool requests a reset.
+    ///
+    /// Returns whether an update was marked, in which case resetting the device applies it. Errors of
+    /// the bus are returned as they occur; calling `run` again resumes serving, keeping the transfer
+    /// in progress.
+    pub async fn run<DFU: NorFlash, STATE: NorFlash>(
+        &mut self,
+        updater: &mut FirmwareUpdater<'_, DFU, STATE>,
+    ) -> Result<bool, C::Error> {
+        loop {
+            let frame = self.bus.receive().await?;
+            if frame.is_remote_frame() || frame.id() != self.config.request_id || frame.data().is_empty() {
+                continue;
+            }
+
+            let data = frame.data();
+            let sid = data[0];
+            let response = match sid {
+                SID_REQUEST_DOWNLOAD => self.request_download(&data[1..]),
+                SID_TRANSFER_DATA => self.transfer_data(updater, &data[1..]).await,
+                SID_REQUEST_TRANSFER_EXIT => self.request_transfer_exit(updater).await,
+                SID_ECU_RESET => match data[1..] {
+                    [RESET_HARD] => {
+                        self.send(&[sid + POSITIVE_RESPONSE_OFFSET, RESET_HARD]).await?;
+                        return Ok(self.complete);
+                    }
+                    [_] => Err(NRC_REQUEST_OUT_OF_RANGE),
+                    _ => Err(NRC_INCORRECT_MESSAGE_LENGTH),
+                },
+                _ => Err(NRC_SERVICE_NOT_SUPPORTED),
+            };
+
+            match response {
+                Ok(Reply::None) => {}
+                Ok(Reply::Positive) => self.send(&[sid + POSITIVE_RESPONSE_OFFSET]).await?,
+                Ok(Reply::PositiveWith(parameter)) => self.send(&[sid + POSITIVE_RESPONSE_OFFSET, parameter]).await?,
+                Err(code) => {
+                    if sid == SID_TRANSFER_DATA || sid == SID_REQUEST_TRANSFER_EXIT {
+                        self.transfer = None;
+                    }
+                    self.send(&[NEGATIVE_RESPONSE, sid, code]).await?;
+                }
+            }
+        }
+    }
+
+    fn request_download(&mut self, params: &[u8]) -> Result<Reply, u8> {
+        let size: [u8; 4] = params.try_into().map_err(|_| NRC_INCORRECT_MESSAGE_LENGTH)?;
+        let size = u32::from_be_bytes(size) as usize;
+        if size == 0 {
+            return Err(NRC_REQUEST_OUT_OF_RANGE);
+        }
+
+        self.complete = false;
+        self.transfer = Some(Transfer {
+            size,
+            received: 0,
+            buffered: 0,
+            sequence: 0,
+            unacknowledged: 0,
+        });
+        Ok(Reply::PositiveWith(self.config.block_size))
+    }
+
+    /// Receive a transfer data frame, returning the sequence number to acknowledge, if any.
+    async fn transfer_data<DFU: NorFlash, STATE: NorFlash>(
+        &mut self,
+        updater: &mut FirmwareUpdater<'_, DFU, STATE>,
+        params: &[u8],
+    ) -> Result<Reply, u8> {
+        let transfer = self.transfer.as_mut().ok_or(NRC_REQUEST_SEQUENCE_ERROR)?;
+        let (&sequence, data) = params.split_first().ok_or(NRC_INCORRECT_MESSAGE_LENGTH)?;
+        if sequence != transfer.sequence.wrapping_add(1) {
+            return Err(NRC_WRONG_BLOCK_SEQUENCE_COUNTER);
+        }
+        if data.is_empty() {
+            return Err(NRC_INCORRECT_MESSAGE_LENGTH);
+        }
+        if transfer.received + data.len() > transfer.size {
+            return Err(NRC_TRANSFER_DATA_SUSPENDED);
+        }
+
+        transfer.sequence = sequence;
+        for &b in data {
+            self.buf[transfer.buffered] = b;
+            transfer.buffered += 1;
+            transfer.received += 1;
+            if transfer.buffered == self.buf.len() {
+                let offset = transfer.received - transfer.buffered;
+                updater
+                    .write_firmware(offset, self.buf)
+                    .await
+                    .map_err(|_| NRC_GENERAL_PROGRAMMING_FAILURE)?;
+                transfer.buffered = 0;
+            }
+        }
+
+        transfer.unacknowledged += 1;
+        if transfer.unacknowledged == self.config.block_size || transfer.received == transfer.size {
+            transfer.unacknowledged = 0;
+            Ok(Reply::PositiveWith(sequence))
+        } else {
+            Ok(Reply::None)
+        }
+    }
+
+    async fn request_transfer_exit<DFU: NorFlash, STATE: NorFlash>(
+        &mut self,
+        updater: &mut FirmwareUpdater<'_, DFU, STATE>,
+    ) -> Result<Reply, u8> {
+        let transfer = self.transfer.take().ok_or(NRC_REQUEST_SEQUENCE_ERROR)?;
+        if transfer.received != transfer.size {
+            return Err(NRC_REQUEST_SEQUENCE_ERROR);
+        }
+
+        if transfer.buffered > 0 {
+            // Pad the last chunk to the write size with the erase value.
+            let len = transfer.buffered.next_multiple_of(DFU::WRITE_SIZE).min(self.buf.len());
+            self.buf[transfer.buffered..len].fill(crate::STATE_ERASE_VALUE);
+            let offset = transfer.received - transfer.buffered;
+            updater
+                .write_firmware(offset, &self.buf[..len])
+                .await
+                .map_err(|_| NRC_GENERAL_PROGRAMMING_FAILURE)?;
+        }
+        updater
+            .mark_updated()
+            .await
+            .map_err(|_| NRC_GENERAL_PROGRAMMING_FAILURE)?;
+
+        self.complete = true;
+        Ok(Reply::Positive)
+    }
+
+    async fn send(&mut self, data: &[u8]) -> Result<(), C::Error> {
+        let frame = unwrap!(<C::Frame as Frame>::new(self.config.response_id, data));
+        self.bus.transmit(&frame).await
+    }
+}
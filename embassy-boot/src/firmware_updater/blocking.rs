@@ -6,7 +6,7 @@ use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embedded_storage::nor_flash::NorFlash;
 
 use super::FirmwareUpdaterConfig;
-use crate::{FirmwareUpdaterError, State, BOOT_MAGIC, DFU_DETACH_MAGIC, STATE_ERASE_VALUE, SWAP_MAGIC};
+use crate::{FirmwareUpdaterError, Manifest, State, BOOT_MAGIC, DFU_DETACH_MAGIC, STATE_ERASE_VALUE, SWAP_MAGIC};
 
 /// Blocking FirmwareUpdater is an application API for interacting with the BootLoader without the ability to
 /// 'mess up' the internal bootloader state
@@ -175,6 +175,56 @@ impl<'d, DFU: NorFlash, STATE: NorFlash> BlockingFirmwareUpdater<'d, DFU, STATE>
         self.state.mark_updated()
     }
 
+    /// Verify the DFU against its [`Manifest`] and `counter`, then mark to trigger firmware swap on
+    /// next boot. If there is an error then DO NOT proceed with updating the firmware.
+    ///
+    /// The manifest must have been written at [`Manifest::offset`] along with the image. Its
+    /// signature is checked with `public_key`, and its security version must be at least the
+    /// value of `counter`.
+    #[cfg(feature = "_verify")]
+    pub fn verify_manifest_and_mark_updated(
+        &mut self,
+        public_key: &[u8; 32],
+        counter: &mut impl crate::RollbackCounter,
+    ) -> Result<Manifest, FirmwareUpdaterError> {
+        use crate::manifest::Sha512;
+
+        self.state.verify_booted()?;
+
+        let manifest = self.read_manifest()?;
+        let min_version = counter.get().map_err(|_| FirmwareUpdaterError::Rollback)?;
+        if manifest.security_version < min_version {
+            return Err(FirmwareUpdaterError::Rollback);
+        }
+
+        let mut digest = Sha512::new();
+        let mut chunk_buf = [0; 32];
+        for offset in (0..manifest.image_len).step_by(chunk_buf.len()) {
+            self.dfu.read(offset, &mut chunk_buf)?;
+            let len = core::cmp::min((manifest.image_len - offset) as usize, chunk_buf.len());
+            digest.update(&chunk_buf[..len]);
+        }
+        digest.update(manifest.header());
+
+        let mut message = [0; 64];
+        message.copy_from_slice(digest.finalize().as_slice());
+        manifest
+            .verify(public_key, &message)
+            .map_err(FirmwareUpdaterError::Signature)?;
+
+        self.state.mark_updated()?;
+        Ok(manifest)
+    }
+
+    /// Read the [`Manifest`] of the update in DFU.
+    pub fn read_manifest(&mut self) -> Result<Manifest, FirmwareUpdaterError> {
+        let mut bytes = [0; Manifest::SIZE];
+        self.dfu.read(Manifest::offset(self.dfu.capacity()), &mut bytes)?;
+        Manifest::from_bytes(&bytes)
+            .filter(|m| m.fits(self.dfu.capacity()))
+            .ok_or(FirmwareUpdaterError::Manifest)
+    }
+
     /// Verify the update in DFU with any digest.
     pub fn hash<D: Digest>(
         &mut self,
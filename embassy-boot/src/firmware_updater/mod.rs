@@ -26,6 +26,10 @@ pub enum FirmwareUpdaterError {
     Signature(signature::Error),
     /// Bad state.
     BadState,
+    /// The DFU partition has no valid manifest.
+    Manifest,
+    /// The update is older than allowed by the rollback counter, or the counter couldn't be read.
+    Rollback,
 }
 
 #[cfg(feature = "defmt")]
@@ -35,6 +39,8 @@ impl defmt::Format for FirmwareUpdaterError {
             FirmwareUpdaterError::Flash(_) => defmt::write!(fmt, "FirmwareUpdaterError::Flash(_)"),
             FirmwareUpdaterError::Signature(_) => defmt::write!(fmt, "FirmwareUpdaterError::Signature(_)"),
             FirmwareUpdaterError::BadState => defmt::write!(fmt, "FirmwareUpdaterError::BadState"),
+            FirmwareUpdaterError::Manifest => defmt::write!(fmt, "FirmwareUpdaterError::Manifest"),
+            FirmwareUpdaterError::Rollback => defmt::write!(fmt, "FirmwareUpdaterError::Rollback"),
         }
     }
 }
@@ -5,8 +5,10 @@
 mod fmt;
 
 mod boot_loader;
+pub mod can;
 mod digest_adapters;
 mod firmware_updater;
+mod manifest;
 #[cfg(test)]
 mod mem_flash;
 #[cfg(test)]
@@ -20,6 +22,7 @@ pub use firmware_updater::{
     BlockingFirmwareState, BlockingFirmwareUpdater, FirmwareState, FirmwareUpdater, FirmwareUpdaterConfig,
     FirmwareUpdaterError,
 };
+pub use manifest::{Manifest, RollbackCounter};
 
 pub(crate) const BOOT_MAGIC: u8 = 0xD0;
 pub(crate) const SWAP_MAGIC: u8 = 0xF0;
@@ -320,4 +323,174 @@ mod tests {
         ))
         .is_ok());
     }
+
+    #[cfg(feature = "_verify")]
+    struct TestCounter(u32);
+
+    #[cfg(feature = "_verify")]
+    impl RollbackCounter for TestCounter {
+        type Error = core::convert::Infallible;
+
+        fn get(&mut self) -> Result<u32, Self::Error> {
+            Ok(self.0)
+        }
+
+        fn raise(&mut self, value: u32) -> Result<(), Self::Error> {
+            self.0 = self.0.max(value);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "_verify")]
+    fn sign_manifest(keypair: &ed25519_dalek::SigningKey, image: &[u8], security_version: u32) -> Manifest {
+        use ed25519_dalek::{Digest, Sha512, Signer};
+
+        let mut manifest = Manifest {
+            security_version,
+            image_len: image.len() as u32,
+            signature: [0; 64],
+        };
+        let mut digest = Sha512::new();
+        digest.update(image);
+        digest.update(manifest.header());
+        manifest.signature = keypair.sign(&digest.finalize()).to_bytes();
+        manifest
+    }
+
+    /// Write `image` and `manifest` to a DFU partition, and verify them with the updater.
+    #[cfg(feature = "_verify")]
+    fn verify_manifest(
+        image: &[u8],
+        manifest: &Manifest,
+        public_key: &[u8; 32],
+        counter: u32,
+    ) -> Result<Manifest, FirmwareUpdaterError> {
+        let flash = BlockingTestFlash::new(BootLoaderConfig {
+            active: MemFlash::<0, 0, 0>::default(),
+            dfu: MemFlash::<4096, 4096, 4>::default(),
+            state: MemFlash::<4096, 4096, 4>::default(),
+        });
+
+        let mut write_buf = [0; 4096];
+        write_buf[..image.len()].copy_from_slice(image);
+        write_buf[Manifest::offset(4096) as usize..].copy_from_slice(&manifest.to_bytes());
+        flash.dfu().write(0, &write_buf).unwrap();
+
+        let flash = flash.into_async();
+        let mut aligned = [0; 4];
+        let mut updater = FirmwareUpdater::new(
+            FirmwareUpdaterConfig {
+                dfu: flash.dfu(),
+                state: flash.state(),
+            },
+            &mut aligned,
+        );
+        block_on(updater.verify_manifest_and_mark_updated(public_key, &mut TestCounter(counter)))
+    }
+
+    #[test]
+    #[cfg(feature = "_verify")]
+    fn test_verify_manifest() {
+        use ed25519_dalek::SigningKey;
+        use rand::rngs::OsRng;
+
+        let keypair = SigningKey::generate(&mut OsRng {});
+        let public_key = keypair.verifying_key().to_bytes();
+        let image: &[u8] = b"This are bytes that would otherwise be firmware bytes for DFU.";
+        let manifest = sign_manifest(&keypair, image, 3);
+
+        assert_eq!(verify_manifest(image, &manifest, &public_key, 3).unwrap(), manifest);
+        assert_eq!(verify_manifest(image, &manifest, &public_key, 0).unwrap(), manifest);
+    }
+
+    #[test]
+    #[cfg(feature = "_verify")]
+    fn test_verify_manifest_bad_signature() {
+        use ed25519_dalek::SigningKey;
+        use rand::rngs::OsRng;
+
+        let keypair = SigningKey::generate(&mut OsRng {});
+        let public_key = keypair.verifying_key().to_bytes();
+        let other_key = SigningKey::generate(&mut OsRng {}).verifying_key().to_bytes();
+        let image: &[u8] = b"This are bytes that would otherwise be firmware bytes for DFU.";
+        let manifest = sign_manifest(&keypair, image, 1);
+
+        let res = verify_manifest(image, &manifest, &other_key, 0);
+        assert!(matches!(res, Err(FirmwareUpdaterError::Signature(_))));
+
+        // The image was altered after signing
+        let res = verify_manifest(b"Other bytes.", &manifest, &public_key, 0);
+        assert!(matches!(res, Err(FirmwareUpdaterError::Signature(_))));
+
+        // The security version was raised after signing
+        let raised = Manifest {
+            security_version: 2,
+            ..manifest.clone()
+        };
+        let res = verify_manifest(image, &raised, &public_key, 0);
+        assert!(matches!(res, Err(FirmwareUpdaterError::Signature(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "_verify")]
+    fn test_verify_manifest_rollback() {
+        use ed25519_dalek::SigningKey;
+        use rand::rngs::OsRng;
+
+        let keypair = SigningKey::generate(&mut OsRng {});
+        let public_key = keypair.verifying_key().to_bytes();
+        let image: &[u8] = b"This are bytes that would otherwise be firmware bytes for DFU.";
+        let manifest = sign_manifest(&keypair, image, 1);
+
+        let res = verify_manifest(image, &manifest, &public_key, 2);
+        assert!(matches!(res, Err(FirmwareUpdaterError::Rollback)));
+    }
+
+    #[test]
+    #[cfg(feature = "_verify")]
+    fn test_prepare_boot_verified() {
+        use ed25519_dalek::SigningKey;
+        use rand::rngs::OsRng;
+
+        const FIRMWARE_SIZE: usize = 4096;
+        const ORIGINAL: [u8; FIRMWARE_SIZE] = [0x55; FIRMWARE_SIZE];
+        const UPDATE: [u8; FIRMWARE_SIZE] = [0xAA; FIRMWARE_SIZE];
+
+        let keypair = SigningKey::generate(&mut OsRng {});
+        let public_key = keypair.verifying_key().to_bytes();
+        let manifest = sign_manifest(&keypair, &UPDATE, 1);
+
+        // Boot with the rollback counter at `counter`, returning the state and active image
+        let boot = |counter: u32| {
+            let flash = BlockingTestFlash::new(BootLoaderConfig {
+                active: MemFlash::<FIRMWARE_SIZE, 4096, 4>::default(),
+                dfu: MemFlash::<8192, 4096, 4>::default(),
+                state: MemFlash::<4096, 4096, 4>::default(),
+            });
+            flash.active().write(0, &ORIGINAL).unwrap();
+            flash.dfu().write(0, &UPDATE).unwrap();
+            flash.dfu().write(Manifest::offset(8192), &manifest.to_bytes()).unwrap();
+            let mut aligned = [0; 4];
+            BlockingFirmwareState::new(flash.state(), &mut aligned)
+                .mark_updated()
+                .unwrap();
+
+            let mut bootloader = BootLoader::new(BootLoaderConfig {
+                active: flash.active(),
+                dfu: flash.dfu(),
+                state: flash.state(),
+            });
+            let mut page = [0; 1024];
+            let state = bootloader
+                .prepare_boot_verified(&mut page, &public_key, &mut TestCounter(counter))
+                .unwrap();
+
+            let mut read_buf = [0; FIRMWARE_SIZE];
+            flash.active().read(0, &mut read_buf).unwrap();
+            (state, read_buf)
+        };
+
+        assert_eq!(boot(1), (State::Swap, UPDATE));
+        assert_eq!(boot(2), (State::Boot, ORIGINAL));
+    }
 }
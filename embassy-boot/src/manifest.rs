@@ -0,0 +1,120 @@
+/// Monotonic counter holding the lowest security version allowed to boot.
+///
+/// Images with a [`Manifest::security_version`] lower than the counter are rejected, so that
+/// an attacker can't install an older, validly signed image with known vulnerabilities. The
+/// application raises the counter to its own security version once it has marked itself booted,
+/// so a failed update can still be reverted.
+pub trait RollbackCounter {
+    /// Error type.
+    type Error;
+
+    /// Read the counter.
+    fn get(&mut self) -> Result<u32, Self::Error>;
+
+    /// Raise the counter to `value`. Values lower than the counter are ignored.
+    fn raise(&mut self, value: u32) -> Result<(), Self::Error>;
+}
+
+/// Manifest of a firmware image, stored in the last [`Manifest::SIZE`] bytes of the DFU partition.
+///
+/// The signature covers the image followed by the manifest header: magic, security version and
+/// image length, all little-endian. It's an Ed25519 signature of the SHA-512 digest of these
+/// bytes, like the signature of `verify_and_mark_updated`.
+///
+/// The image must leave the end of the DFU partition free for the manifest, which is always the
+/// case when the DFU partition is one page bigger than the active partition, as the bootloader
+/// requires. The swap overwrites that page, so the manifest is only checked before swapping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Manifest {
+    /// Security version of the image, checked against the [`RollbackCounter`].
+    pub security_version: u32,
+    /// Length of the image, in bytes.
+    pub image_len: u32,
+    /// Signature of the image and manifest header.
+    pub signature: [u8; 64],
+}
+
+impl Manifest {
+    /// Size reserved for the manifest at the end of the DFU partition, a multiple of the write
+    /// size of all supported flashes.
+    pub const SIZE: usize = 128;
+    const MAGIC: u32 = 0x4D42_4545;
+    const HEADER_SIZE: usize = 12;
+
+    /// Offset of the manifest in a DFU partition of `dfu_capacity` bytes.
+    ///
+    /// Panics if the partition is smaller than [`Manifest::SIZE`].
+    pub const fn offset(dfu_capacity: usize) -> u32 {
+        assert!(dfu_capacity >= Self::SIZE, "DFU partition is smaller than the manifest");
+        (dfu_capacity - Self::SIZE) as u32
+    }
+
+    /// Parse a manifest, returning `None` if it doesn't have the manifest magic.
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Option<Self> {
+        let word = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        if word(0) != Self::MAGIC {
+            return None;
+        }
+
+        let mut signature = [0; 64];
+        signature.copy_from_slice(&bytes[Self::HEADER_SIZE..][..64]);
+        Some(Self {
+            security_version: word(4),
+            image_len: word(8),
+            signature,
+        })
+    }
+
+    /// Serialize the manifest, padded with `0xFF` to [`Manifest::SIZE`] bytes.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0xFF; Self::SIZE];
+        bytes[..Self::HEADER_SIZE].copy_from_slice(&self.header());
+        bytes[Self::HEADER_SIZE..][..64].copy_from_slice(&self.signature);
+        bytes
+    }
+
+    /// The header, which is signed after the image.
+    pub fn header(&self) -> [u8; Self::HEADER_SIZE] {
+        let mut header = [0; Self::HEADER_SIZE];
+        header[0..4].copy_from_slice(&Self::MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&self.security_version.to_le_bytes());
+        header[8..12].copy_from_slice(&self.image_len.to_le_bytes());
+        header
+    }
+
+    /// Whether the image fits before the manifest in a DFU partition of `dfu_capacity` bytes.
+    pub(crate) fn fits(&self, dfu_capacity: usize) -> bool {
+        self.image_len <= Self::offset(dfu_capacity)
+    }
+
+    /// Verify the signature, given the SHA-512 `digest` of the image and header.
+    #[cfg(feature = "_verify")]
+    pub(crate) fn verify(&self, public_key: &[u8; 32], digest: &[u8; 64]) -> Result<(), signature::Error> {
+        #[cfg(feature = "ed25519-dalek")]
+        {
+            use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+
+            let public_key = VerifyingKey::from_bytes(public_key)?;
+            let signature = Signature::from_bytes(&self.signature);
+            public_key.verify(digest, &signature)?;
+        }
+        #[cfg(feature = "ed25519-salty")]
+        {
+            use salty::{PublicKey, Signature};
+
+            let public_key = PublicKey::try_from(public_key).map_err(|_| signature::Error::default())?;
+            let signature = Signature::try_from(&self.signature).map_err(|_| signature::Error::default())?;
+            public_key
+                .verify(digest, &signature)
+                .map_err(|_| signature::Error::default())?;
+        }
+        Ok(())
+    }
+}
+
+/// SHA-512 implementation of the enabled signature backend.
+#[cfg(feature = "ed25519-dalek")]
+pub(crate) use crate::digest_adapters::ed25519_dalek::Sha512;
+#[cfg(all(feature = "ed25519-salty", not(feature = "ed25519-dalek")))]
+pub(crate) use crate::digest_adapters::salty::Sha512;
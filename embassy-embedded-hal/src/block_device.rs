@@ -0,0 +1,75 @@
+//! Block devices, such as SD cards.
+//!
+//! [`BlockDevice`] is implemented by the SD card drivers, over SPI ([`SdSpi`](crate::sd_spi::SdSpi))
+//! and by HALs with an SD/MMC peripheral, so filesystems can be written once for both.
+
+use core::ops::{Deref, DerefMut};
+
+/// Size of a block, in bytes.
+pub const BLOCK_SIZE: usize = 512;
+
+/// Aligned data block.
+///
+/// This is a 512-byte array, aligned to 4 bytes to satisfy DMA requirements.
+#[repr(align(4))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Block(pub [u8; BLOCK_SIZE]);
+
+impl Block {
+    /// A block of zeros.
+    pub const fn new() -> Self {
+        Self([0; BLOCK_SIZE])
+    }
+}
+
+impl Default for Block {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for Block {
+    type Target = [u8; BLOCK_SIZE];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Block {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Device read and written in [`Block`]s.
+pub trait BlockDevice {
+    /// Error type.
+    type Error: core::fmt::Debug;
+
+    /// Read the block at `block_idx`.
+    async fn read_block(&mut self, block_idx: u32, buffer: &mut Block) -> Result<(), Self::Error>;
+
+    /// Write the block at `block_idx`.
+    async fn write_block(&mut self, block_idx: u32, buffer: &Block) -> Result<(), Self::Error>;
+
+    /// Number of blocks of the device.
+    fn block_count(&self) -> Result<u32, Self::Error>;
+}
+
+impl<T: BlockDevice + ?Sized> BlockDevice for &mut T {
+    type Error = T::Error;
+
+    async fn read_block(&mut self, block_idx: u32, buffer: &mut Block) -> Result<(), Self::Error> {
+        T::read_block(self, block_idx, buffer).await
+    }
+
+    async fn write_block(&mut self, block_idx: u32, buffer: &Block) -> Result<(), Self::Error> {
+        T::write_block(self, block_idx, buffer).await
+    }
+
+    fn block_count(&self) -> Result<u32, Self::Error> {
+        T::block_count(self)
+    }
+}
@@ -0,0 +1,423 @@
+//! External EEPROM and FRAM.
+//!
+//! Drivers for the common serial EEPROMs (24xx on I2C, 25xx on SPI) and the FRAMs that share their
+//! command sets (FM24/MB85RC on I2C, FM25/MB85RS on SPI), over any `embedded-hal` I2C bus or SPI
+//! device. The blocking methods need the blocking traits, and the async ones the
+//! `embedded-hal-async` traits; a driver implementing both can use either.
+//!
+//! EEPROM writes are split at page boundaries, since a write wraps around within its page, and
+//! each page write is followed by polling until the memory has finished writing it. FRAM has no
+//! pages and writes immediately, so set [`Config::page_size`] to its size; the polling then
+//! returns at once.
+//!
+//! Both drivers implement the [`embedded_storage`] `ReadStorage` and `Storage` traits.
+
+use embedded_hal_1::i2c::{Error as _, ErrorKind as I2cErrorKind};
+use embedded_hal_1::spi::Operation;
+
+/// EEPROM or FRAM configuration.
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Memory size, in bytes.
+    pub size: u32,
+    /// Write page size, in bytes. For FRAM, this is the memory size.
+    pub page_size: u32,
+    /// Number of address bytes sent, from 1 to 3.
+    ///
+    /// Address bits that don't fit are sent in the low bits of the I2C device address, or in bit 3
+    /// of the SPI instruction, as small memories do.
+    pub address_bytes: u8,
+}
+
+impl Config {
+    /// Configuration for an EEPROM of `size` bytes, with `page_size` byte pages.
+    pub const fn eeprom(size: u32, page_size: u32, address_bytes: u8) -> Self {
+        Self {
+            size,
+            page_size,
+            address_bytes,
+        }
+    }
+
+    /// Configuration for a FRAM of `size` bytes.
+    pub const fn fram(size: u32, address_bytes: u8) -> Self {
+        Self::eeprom(size, size, address_bytes)
+    }
+
+    fn validate(&self) {
+        assert!(self.address_bytes >= 1 && self.address_bytes <= 3);
+        assert!(self.page_size > 0 && self.page_size <= self.size);
+    }
+
+    /// Split `offset..offset + len` at page boundaries and in chunks of at most `max_chunk` bytes,
+    /// into `(offset, len)` pairs.
+    fn pages(self, mut offset: u32, len: usize, max_chunk: u32) -> impl Iterator<Item = (u32, usize)> {
+        let end = offset + len as u32;
+        core::iter::from_fn(move || {
+            if offset >= end {
+                return None;
+            }
+            let chunk = (self.page_size - offset % self.page_size)
+                .min(end - offset)
+                .min(max_chunk);
+            let page = (offset, chunk as usize);
+            offset += chunk;
+            Some(page)
+        })
+    }
+
+    fn check_bounds<E>(&self, offset: u32, len: usize) -> Result<(), Error<E>> {
+        match (offset as usize).checked_add(len) {
+            Some(end) if end <= self.size as usize => Ok(()),
+            _ => Err(Error::OutOfBounds),
+        }
+    }
+
+    /// Address bytes, big endian, and the address bits left over.
+    fn address(&self, offset: u32) -> ([u8; 3], usize, u8) {
+        let n = self.address_bytes as usize;
+        let bytes = offset.to_be_bytes();
+        let mut address = [0; 3];
+        address[..n].copy_from_slice(&bytes[4 - n..]);
+        (address, n, (offset >> (8 * n)) as u8)
+    }
+}
+
+/// EEPROM or FRAM error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Bus error.
+    Bus(E),
+    /// The arguments are out of bounds.
+    OutOfBounds,
+    /// The memory didn't finish writing in time, e.g. because it is missing.
+    Timeout,
+}
+
+/// Number of times an I2C device is polled for the end of a write before giving up.
+///
+/// A poll takes about 100 µs at 100 kHz, and EEPROMs finish writing in at most 10 ms.
+const POLL_ATTEMPTS: u32 = 1000;
+
+/// Number of times the status register of an SPI device is read for the end of a write before
+/// giving up.
+///
+/// A read takes a few µs at 10 MHz, and EEPROMs finish writing in at most 10 ms.
+const SPI_POLL_ATTEMPTS: u32 = 10_000;
+
+/// Largest I2C page size supported, in bytes.
+const MAX_PAGE_SIZE: usize = 256;
+
+/// I2C EEPROM or FRAM.
+pub struct I2cEeprom<I2C> {
+    i2c: I2C,
+    address: u8,
+    config: Config,
+}
+
+impl<I2C> I2cEeprom<I2C> {
+    /// Create a new driver, for the device at 7-bit `address` with the address pins low.
+    ///
+    /// Writes are split in chunks of at most 256 bytes, even on FRAM.
+    pub fn new(i2c: I2C, address: u8, config: Config) -> Self {
+        config.validate();
+        Self { i2c, address, config }
+    }
+
+    /// Release the I2C bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Write the address and `data` to `buf`, returning the device address and frame length.
+    fn frame(&self, buf: &mut [u8; 3 + MAX_PAGE_SIZE], offset: u32, data: &[u8]) -> (u8, usize) {
+        let (address, n, high) = self.config.address(offset);
+        buf[..n].copy_from_slice(&address[..n]);
+        buf[n..][..data.len()].copy_from_slice(data);
+        (self.address | high, n + data.len())
+    }
+}
+
+/// Whether an I2C error means the device didn't acknowledge, so it's still writing.
+fn is_nack<E: embedded_hal_1::i2c::Error>(e: &E) -> bool {
+    matches!(e.kind(), I2cErrorKind::NoAcknowledge(_))
+}
+
+impl<I2C: embedded_hal_1::i2c::I2c> I2cEeprom<I2C> {
+    /// Read data, blocking.
+    pub fn blocking_read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error<I2C::Error>> {
+        self.config.check_bounds(offset, bytes.len())?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let (address, n, high) = self.config.address(offset);
+        self.i2c
+            .write_read(self.address | high, &address[..n], bytes)
+            .map_err(Error::Bus)
+    }
+
+    /// Write data, blocking until the memory has finished writing it.
+    pub fn blocking_write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error<I2C::Error>> {
+        self.config.check_bounds(offset, bytes.len())?;
+
+        let mut buf = [0; 3 + MAX_PAGE_SIZE];
+        for (page, len) in self.config.pages(offset, bytes.len(), MAX_PAGE_SIZE as u32) {
+            let data = &bytes[(page - offset) as usize..][..len];
+            let (address, len) = self.frame(&mut buf, page, data);
+            self.i2c.write(address, &buf[..len]).map_err(Error::Bus)?;
+
+            // The device doesn't acknowledge its address until the write is done.
+            let mut attempts = 0;
+            loop {
+                match self.i2c.write(address, &buf[..len - data.len()]) {
+                    Ok(()) => break,
+                    Err(e) if is_nack(&e) && attempts < POLL_ATTEMPTS => attempts += 1,
+                    Err(e) if is_nack(&e) => return Err(Error::Timeout),
+                    Err(e) => return Err(Error::Bus(e)),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I2C: embedded_hal_async::i2c::I2c> I2cEeprom<I2C> {
+    /// Read data.
+    pub async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error<I2C::Error>> {
+        self.config.check_bounds(offset, bytes.len())?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let (address, n, high) = self.config.address(offset);
+        self.i2c
+            .write_read(self.address | high, &address[..n], bytes)
+            .await
+            .map_err(Error::Bus)
+    }
+
+    /// Write data, waiting until the memory has finished writing it.
+    pub async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error<I2C::Error>> {
+        self.config.check_bounds(offset, bytes.len())?;
+
+        let mut buf = [0; 3 + MAX_PAGE_SIZE];
+        for (page, len) in self.config.pages(offset, bytes.len(), MAX_PAGE_SIZE as u32) {
+            let data = &bytes[(page - offset) as usize..][..len];
+            let (address, len) = self.frame(&mut buf, page, data);
+            self.i2c.write(address, &buf[..len]).await.map_err(Error::Bus)?;
+
+            // The device doesn't acknowledge its address until the write is done.
+            let mut attempts = 0;
+            loop {
+                match self.i2c.write(address, &buf[..len - data.len()]).await {
+                    Ok(()) => break,
+                    Err(e) if is_nack(&e) && attempts < POLL_ATTEMPTS => attempts += 1,
+                    Err(e) if is_nack(&e) => return Err(Error::Timeout),
+                    Err(e) => return Err(Error::Bus(e)),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I2C: embedded_hal_1::i2c::I2c> embedded_storage::ReadStorage for I2cEeprom<I2C> {
+    type Error = Error<I2C::Error>;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.blocking_read(offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        self.config.size as usize
+    }
+}
+
+impl<I2C: embedded_hal_1::i2c::I2c> embedded_storage::Storage for I2cEeprom<I2C> {
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.blocking_write(offset, bytes)
+    }
+}
+
+const CMD_WRITE_ENABLE: u8 = 0x06;
+const CMD_READ_STATUS: u8 = 0x05;
+const CMD_READ: u8 = 0x03;
+const CMD_WRITE: u8 = 0x02;
+
+/// Write in progress bit of the status register.
+const STATUS_WIP: u8 = 0x01;
+
+/// SPI EEPROM or FRAM.
+///
+/// The SPI device must be in mode 0 or 3.
+pub struct SpiEeprom<SPI> {
+    spi: SPI,
+    config: Config,
+}
+
+impl<SPI> SpiEeprom<SPI> {
+    /// Create a new driver.
+    pub fn new(spi: SPI, config: Config) -> Self {
+        config.validate();
+        Self { spi, config }
+    }
+
+    /// Release the SPI device.
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+
+    /// Instruction and address bytes.
+    fn header(&self, instruction: u8, offset: u32) -> ([u8; 4], usize) {
+        let (address, n, high) = self.config.address(offset);
+        let mut header = [0; 4];
+        header[0] = instruction | (high & 1) << 3;
+        header[1..][..n].copy_from_slice(&address[..n]);
+        (header, 1 + n)
+    }
+}
+
+impl<SPI: embedded_hal_1::spi::SpiDevice> SpiEeprom<SPI> {
+    /// Read data, blocking.
+    pub fn blocking_read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error<SPI::Error>> {
+        self.config.check_bounds(offset, bytes.len())?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let (header, len) = self.header(CMD_READ, offset);
+        self.spi
+            .transaction(&mut [Operation::Write(&header[..len]), Operation::Read(bytes)])
+            .map_err(Error::Bus)
+    }
+
+    /// Write data, blocking until the memory has finished writing it.
+    pub fn blocking_write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error<SPI::Error>> {
+        self.config.check_bounds(offset, bytes.len())?;
+
+        for (page, len) in self.config.pages(offset, bytes.len(), u32::MAX) {
+            let data = &bytes[(page - offset) as usize..][..len];
+
+            // The write enable latch is reset after every write.
+            self.spi.write(&[CMD_WRITE_ENABLE]).map_err(Error::Bus)?;
+
+            let (header, len) = self.header(CMD_WRITE, page);
+            self.spi
+                .transaction(&mut [Operation::Write(&header[..len]), Operation::Write(data)])
+                .map_err(Error::Bus)?;
+
+            let mut attempts = 0;
+            loop {
+                let mut status = [CMD_READ_STATUS, 0];
+                self.spi.transfer_in_place(&mut status).map_err(Error::Bus)?;
+                if status[1] & STATUS_WIP == 0 {
+                    break;
+                }
+                attempts += 1;
+                if attempts >= SPI_POLL_ATTEMPTS {
+                    return Err(Error::Timeout);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<SPI: embedded_hal_async::spi::SpiDevice> SpiEeprom<SPI> {
+    /// Read data.
+    pub async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error<SPI::Error>> {
+        self.config.check_bounds(offset, bytes.len())?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let (header, len) = self.header(CMD_READ, offset);
+        self.spi
+            .transaction(&mut [Operation::Write(&header[..len]), Operation::Read(bytes)])
+            .await
+            .map_err(Error::Bus)
+    }
+
+    /// Write data, waiting until the memory has finished writing it.
+    pub async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error<SPI::Error>> {
+        self.config.check_bounds(offset, bytes.len())?;
+
+        for (page, len) in self.config.pages(offset, bytes.len(), u32::MAX) {
+            let data = &bytes[(page - offset) as usize..][..len];
+
+            // The write enable latch is reset after every write.
+            self.spi.write(&[CMD_WRITE_ENABLE]).await.map_err(Error::Bus)?;
+
+            let (header, len) = self.header(CMD_WRITE, page);
+            self.spi
+                .transaction(&mut [Operation::Write(&header[..len]), Operation::Write(data)])
+                .await
+                .map_err(Error::Bus)?;
+
+            let mut attempts = 0;
+            loop {
+                let mut status = [CMD_READ_STATUS, 0];
+                self.spi.transfer_in_place(&mut status).await.map_err(Error::Bus)?;
+                if status[1] & STATUS_WIP == 0 {
+                    break;
+                }
+                attempts += 1;
+                if attempts >= SPI_POLL_ATTEMPTS {
+                    return Err(Error::Timeout);
+                }
+                embassy_futures::yield_now().await;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<SPI: embedded_hal_1::spi::SpiDevice> embedded_storage::ReadStorage for SpiEeprom<SPI> {
+    type Error = Error<SPI::Error>;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.blocking_read(offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        self.config.size as usize
+    }
+}
+
+impl<SPI: embedded_hal_1::spi::SpiDevice> embedded_storage::Storage for SpiEeprom<SPI> {
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.blocking_write(offset, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_split_at_boundaries() {
+        let config = Config::eeprom(1024, 64, 2);
+        let pages: Vec<_> = config.pages(60, 140, u32::MAX).collect();
+        assert_eq!(pages, [(60, 4), (64, 64), (128, 64), (192, 8)]);
+    }
+
+    #[test]
+    fn fram_pages_split_at_max_chunk() {
+        let config = Config::fram(2048, 2);
+        let pages: Vec<_> = config.pages(10, 600, MAX_PAGE_SIZE as u32).collect();
+        assert_eq!(pages, [(10, 256), (266, 256), (522, 88)]);
+    }
+
+    #[test]
+    fn high_address_bits() {
+        // 24C16: 2 kB with a single address byte, the upper 3 bits go in the device address.
+        let config = Config::eeprom(2048, 16, 1);
+        assert_eq!(config.address(0x5A3), ([0xA3, 0, 0], 1, 0x05));
+        assert!(config.check_bounds::<()>(2040, 8).is_ok());
+        assert_eq!(config.check_bounds::<()>(2040, 9), Err(Error::OutOfBounds));
+    }
+}
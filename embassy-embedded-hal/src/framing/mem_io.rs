@@ -0,0 +1,47 @@
+use core::convert::Infallible;
+
+use embedded_io_async::{ErrorType, Read, Write};
+
+/// Reads bytes written beforehand.
+pub(crate) struct MemInput<'a>(pub &'a [u8]);
+
+impl ErrorType for MemInput<'_> {
+    type Error = Infallible;
+}
+
+impl Read for MemInput<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let n = buf.len().min(self.0.len());
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+/// Collects written bytes.
+pub(crate) struct MemOutput {
+    pub buf: [u8; 1024],
+    pub len: usize,
+}
+
+impl MemOutput {
+    pub const fn new() -> Self {
+        Self { buf: [0; 1024], len: 0 }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl ErrorType for MemOutput {
+    type Error = Infallible;
+}
+
+impl Write for MemOutput {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.buf[self.len..self.len + buf.len()].copy_from_slice(buf);
+        self.len += buf.len();
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,558 @@
+//! CRC-checked framing over a byte stream.
+//!
+//! [`Framed`] sends and receives whole frames over any [`embedded_io_async`] reader and writer,
+//! e.g. a buffered UART, for links between an MCU and a host or between two MCUs. Each frame is
+//! its payload followed by a CRC, encoded so that a delimiter byte never appears inside it:
+//!
+//! - [`Encoding::Cobs`], Consistent Overhead Byte Stuffing: frames end with a zero byte, and the
+//!   overhead is at most one byte per 254 bytes.
+//! - [`Encoding::Slip`], the framing of RFC 1055: frames start and end with `0xC0`, and `0xC0` and
+//!   `0xDB` are escaped as two bytes each.
+//!
+//! The receiver resynchronizes on the next delimiter after lost or corrupted bytes: the damaged
+//! frame is reported as an error, and the next frame is received normally.
+
+use embedded_io_async::{Read, Write};
+
+#[cfg(test)]
+pub(crate) mod mem_io;
+
+/// Frame encoding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Encoding {
+    /// Consistent Overhead Byte Stuffing, with zero as the delimiter.
+    Cobs,
+    /// Serial Line Internet Protocol (RFC 1055).
+    Slip,
+}
+
+/// Frame checksum, appended to the payload in little-endian order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Checksum {
+    /// CRC-16/CCITT-FALSE: polynomial `0x1021`, initial value `0xFFFF`.
+    Crc16,
+    /// CRC-32 (IEEE 802.3), as used by Ethernet and zlib.
+    Crc32,
+}
+
+impl Checksum {
+    /// Length of the checksum, in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            Self::Crc16 => 2,
+            Self::Crc32 => 4,
+        }
+    }
+
+    /// Compute the checksum of `data`.
+    pub fn compute(self, data: &[u8]) -> u32 {
+        match self {
+            Self::Crc16 => {
+                let mut crc: u16 = 0xFFFF;
+                for &b in data {
+                    crc ^= (b as u16) << 8;
+                    for _ in 0..8 {
+                        crc = if crc & 0x8000 != 0 {
+                            (crc << 1) ^ 0x1021
+                        } else {
+                            crc << 1
+                        };
+                    }
+                }
+                crc as u32
+            }
+            Self::Crc32 => {
+                let mut crc: u32 = 0xFFFF_FFFF;
+                for &b in data {
+                    crc ^= b as u32;
+                    for _ in 0..8 {
+                        crc = if crc & 1 != 0 {
+                            (crc >> 1) ^ 0xEDB8_8320
+                        } else {
+                            crc >> 1
+                        };
+                    }
+                }
+                !crc
+            }
+        }
+    }
+}
+
+/// Framing configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Config {
+    /// Frame encoding.
+    pub encoding: Encoding,
+    /// Frame checksum.
+    pub checksum: Checksum,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Cobs,
+            checksum: Checksum::Crc16,
+        }
+    }
+}
+
+/// Framing error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Error of the underlying reader or writer.
+    Io(E),
+    /// The reader reached its end in the middle of a frame.
+    UnexpectedEof,
+    /// The frame doesn't fit in the buffer. It was discarded.
+    BufferTooSmall,
+    /// The frame is not validly encoded, or too short to hold a checksum.
+    Malformed,
+    /// The checksum of the frame doesn't match its payload.
+    Checksum,
+}
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// Longest run of non-zero bytes in a COBS block.
+const COBS_MAX_RUN: usize = 254;
+
+/// Framed transport, see the [module documentation](self).
+pub struct Framed<R, W> {
+    reader: R,
+    writer: W,
+    config: Config,
+    rx_buf: [u8; 32],
+    rx_pos: usize,
+    rx_len: usize,
+}
+
+impl<R: Read, W: Write<Error = R::Error>> Framed<R, W> {
+    /// Create a framed transport over `reader` and `writer`.
+    pub fn new(reader: R, writer: W, config: Config) -> Self {
+        Self {
+            reader,
+            writer,
+            config,
+            rx_buf: [0; 32],
+            rx_pos: 0,
+            rx_len: 0,
+        }
+    }
+
+    /// Return the reader and writer.
+    ///
+    /// Bytes already read from the reader but not yet decoded are lost.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+
+    /// Send a frame carrying `payload`.
+    pub async fn send_frame(&mut self, payload: &[u8]) -> Result<(), Error<R::Error>> {
+        let checksum = self.config.checksum;
+        let crc = checksum.compute(payload).to_le_bytes();
+        let data = payload.iter().chain(&crc[..checksum.size()]).copied();
+
+        let mut out = Output::new(&mut self.writer);
+        match self.config.encoding {
+            Encoding::Cobs => {
+                let mut block = [0; COBS_MAX_RUN];
+                let mut len = 0;
+                // A block of `COBS_MAX_RUN` bytes has no implicit zero after it, so when the
+                // data ends right after one, no empty block is needed to end it.
+                let mut ended_full = false;
+                for b in data {
+                    if b == 0 {
+                        out.block(len as u8 + 1, &block[..len]).await?;
+                        len = 0;
+                        ended_full = false;
+                    } else {
+                        block[len] = b;
+                        len += 1;
+                        ended_full = false;
+                        if len == COBS_MAX_RUN {
+                            out.block(0xFF, &block).await?;
+                            len = 0;
+                            ended_full = true;
+                        }
+                    }
+                }
+                if !ended_full {
+                    out.block(len as u8 + 1, &block[..len]).await?;
+                }
+                out.push(0).await?;
+            }
+            Encoding::Slip => {
+                // Ends any noise received before the frame.
+                out.push(SLIP_END).await?;
+                for b in data {
+                    match b {
+                        SLIP_END => out.block(SLIP_ESC, &[SLIP_ESC_END]).await?,
+                        SLIP_ESC => out.block(SLIP_ESC, &[SLIP_ESC_ESC]).await?,
+                        b => out.push(b).await?,
+                    }
+                }
+                out.push(SLIP_END).await?;
+            }
+        }
+        out.flush().await
+    }
+
+    /// Receive a frame, writing its payload to `buf` and returning its length.
+    ///
+    /// Empty frames, such as the leading delimiter of SLIP frames, are skipped.
+    pub async fn recv_frame(&mut self, buf: &mut [u8]) -> Result<usize, Error<R::Error>> {
+        let mut decoder = Decoder::new(self.config.encoding);
+        // The last bytes are only known to be the checksum once the frame ends, so they are held
+        // in `crc` until then.
+        let mut len = 0;
+        let mut crc = [0; 4];
+        let crc_len = self.config.checksum.size();
+        let mut overflow = false;
+
+        loop {
+            let byte = self.read_byte().await?;
+            match decoder.feed(byte) {
+                Step::Nothing => {}
+                Step::Byte(b) => {
+                    // Move the oldest held byte to the payload.
+                    if len >= crc_len {
+                        let old = crc[(len - crc_len) % crc_len];
+                        match buf.get_mut(len - crc_len) {
+                            Some(slot) => *slot = old,
+                            None => overflow = true,
+                        }
+                    }
+                    crc[len % crc_len] = b;
+                    len += 1;
+                }
+                Step::End { valid } => {
+                    if overflow {
+                        return Err(Error::BufferTooSmall);
+                    }
+                    if !valid || len < crc_len {
+                        return Err(Error::Malformed);
+                    }
+
+                    let payload_len = len - crc_len;
+                    let mut received = [0; 4];
+                    for (i, r) in received[..crc_len].iter_mut().enumerate() {
+                        *r = crc[(payload_len + i) % crc_len];
+                    }
+                    if u32::from_le_bytes(received) != self.config.checksum.compute(&buf[..payload_len]) {
+                        return Err(Error::Checksum);
+                    }
+                    return Ok(payload_len);
+                }
+            }
+        }
+    }
+
+    async fn read_byte(&mut self) -> Result<u8, Error<R::Error>> {
+        if self.rx_pos == self.rx_len {
+            let n = self.reader.read(&mut self.rx_buf).await.map_err(Error::Io)?;
+            if n == 0 {
+                return Err(Error::UnexpectedEof);
+            }
+            self.rx_pos = 0;
+            self.rx_len = n;
+        }
+        let b = self.rx_buf[self.rx_pos];
+        self.rx_pos += 1;
+        Ok(b)
+    }
+}
+
+/// Buffers encoded bytes, to write them in chunks.
+struct Output<'a, W> {
+    writer: &'a mut W,
+    buf: [u8; 64],
+    len: usize,
+}
+
+impl<'a, W: Write> Output<'a, W> {
+    fn new(writer: &'a mut W) -> Self {
+        Self {
+            writer,
+            buf: [0; 64],
+            len: 0,
+        }
+    }
+
+    async fn push(&mut self, b: u8) -> Result<(), Error<W::Error>> {
+        if self.len == self.buf.len() {
+            self.writer.write_all(&self.buf).await.map_err(Error::Io)?;
+            self.len = 0;
+        }
+        self.buf[self.len] = b;
+        self.len += 1;
+        Ok(())
+    }
+
+    async fn block(&mut self, first: u8, rest: &[u8]) -> Result<(), Error<W::Error>> {
+        self.push(first).await?;
+        for &b in rest {
+            self.push(b).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Error<W::Error>> {
+        self.writer.write_all(&self.buf[..self.len]).await.map_err(Error::Io)?;
+        self.len = 0;
+        self.writer.flush().await.map_err(Error::Io)
+    }
+}
+
+/// Result of feeding a byte to a [`Decoder`].
+enum Step {
+    /// The byte was consumed without decoding a byte.
+    Nothing,
+    /// A byte of the frame was decoded.
+    Byte(u8),
+    /// The frame ended. It is invalid if it was not properly encoded.
+    End { valid: bool },
+}
+
+/// Streaming frame decoder.
+struct Decoder {
+    encoding: Encoding,
+    /// Whether bytes were received since the last delimiter.
+    started: bool,
+    invalid: bool,
+    /// SLIP: the previous byte was an escape.
+    escaped: bool,
+    /// COBS: code of the current block, and bytes of it still to come.
+    code: u8,
+    remaining: u8,
+}
+
+impl Decoder {
+    fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            started: false,
+            invalid: false,
+            escaped: false,
+            code: 0,
+            remaining: 0,
+        }
+    }
+
+    fn feed(&mut self, b: u8) -> Step {
+        match self.encoding {
+            Encoding::Cobs => self.feed_cobs(b),
+            Encoding::Slip => self.feed_slip(b),
+        }
+    }
+
+    fn end(&mut self, valid: bool) -> Step {
+        let started = self.started;
+        *self = Self::new(self.encoding);
+        match started {
+            true => Step::End { valid },
+            false => Step::Nothing,
+        }
+    }
+
+    fn feed_cobs(&mut self, b: u8) -> Step {
+        if b == 0 {
+            let valid = !self.invalid && self.remaining == 0;
+            return self.end(valid);
+        }
+
+        if self.remaining == 0 {
+            // New block: the previous one, unless full, was followed by a zero. This is only known
+            // now, as the last block of a frame has no zero after it.
+            let zero = self.started && self.code != 0xFF;
+            self.started = true;
+            self.code = b;
+            self.remaining = b - 1;
+            match zero {
+                true => Step::Byte(0),
+                false => Step::Nothing,
+            }
+        } else {
+            self.remaining -= 1;
+            Step::Byte(b)
+        }
+    }
+
+    fn feed_slip(&mut self, b: u8) -> Step {
+        if b == SLIP_END {
+            let valid = !self.invalid && !self.escaped;
+            return self.end(valid);
+        }
+
+        self.started = true;
+        if self.escaped {
+            self.escaped = false;
+            match b {
+                SLIP_ESC_END => Step::Byte(SLIP_END),
+                SLIP_ESC_ESC => Step::Byte(SLIP_ESC),
+                _ => {
+                    self.invalid = true;
+                    Step::Nothing
+                }
+            }
+        } else if b == SLIP_ESC {
+            self.escaped = true;
+            Step::Nothing
+        } else {
+            Step::Byte(b)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mem_io::{MemInput, MemOutput};
+    use super::*;
+
+    fn framed(input: &[u8], encoding: Encoding, checksum: Checksum) -> Framed<MemInput<'_>, MemOutput> {
+        Framed::new(MemInput(input), MemOutput::new(), Config { encoding, checksum })
+    }
+
+    /// Bytes of the frame carrying `payload`.
+    async fn encode(payload: &[u8], encoding: Encoding, checksum: Checksum) -> MemOutput {
+        let mut sender = framed(&[], encoding, checksum);
+        sender.send_frame(payload).await.unwrap();
+        sender.into_inner().1
+    }
+
+    const CONFIGS: [(Encoding, Checksum); 4] = [
+        (Encoding::Cobs, Checksum::Crc16),
+        (Encoding::Cobs, Checksum::Crc32),
+        (Encoding::Slip, Checksum::Crc16),
+        (Encoding::Slip, Checksum::Crc32),
+    ];
+
+    #[test]
+    fn crc_check_values() {
+        assert_eq!(Checksum::Crc16.compute(b"123456789"), 0x29B1);
+        assert_eq!(Checksum::Crc32.compute(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[futures_test::test]
+    async fn round_trip() {
+        let mut long = [0; 600];
+        for (i, b) in long.iter_mut().enumerate() {
+            *b = (i % 255) as u8 + 1;
+        }
+        let payloads: [&[u8]; 8] = [
+            &[],
+            &[0],
+            &[1, 2, 3],
+            &[0, 0, 1, 0],
+            &[SLIP_END, SLIP_ESC, SLIP_ESC_END, SLIP_ESC_ESC],
+            &long[..COBS_MAX_RUN],
+            &long[..COBS_MAX_RUN + 1],
+            &long,
+        ];
+
+        for (encoding, checksum) in CONFIGS {
+            for payload in payloads {
+                let frame = encode(payload, encoding, checksum).await;
+                let mut receiver = framed(frame.bytes(), encoding, checksum);
+                let mut buf = [0; 600];
+                let len = receiver.recv_frame(&mut buf).await.unwrap();
+                assert_eq!(&buf[..len], payload);
+            }
+        }
+    }
+
+    #[futures_test::test]
+    async fn cobs_has_no_zero_inside_frame() {
+        let frame = encode(&[0, 0, 5, 0], Encoding::Cobs, Checksum::Crc16).await;
+        let (last, body) = frame.bytes().split_last().unwrap();
+        assert_eq!(*last, 0);
+        assert!(!body.contains(&0));
+    }
+
+    #[futures_test::test]
+    async fn slip_escapes_delimiter_and_escape_bytes() {
+        let frame = encode(&[SLIP_END, 1, SLIP_ESC], Encoding::Slip, Checksum::Crc16).await;
+        let bytes = frame.bytes();
+        assert_eq!(
+            &bytes[..6],
+            &[SLIP_END, SLIP_ESC, SLIP_ESC_END, 1, SLIP_ESC, SLIP_ESC_ESC]
+        );
+        assert_eq!(bytes.last(), Some(&SLIP_END));
+        assert!(!bytes[1..bytes.len() - 1].contains(&SLIP_END));
+    }
+
+    #[futures_test::test]
+    async fn slip_invalid_escape_is_malformed() {
+        let frame = [SLIP_END, 1, SLIP_ESC, 2, 3, 4, SLIP_END];
+        let mut receiver = framed(&frame, Encoding::Slip, Checksum::Crc16);
+        assert_eq!(receiver.recv_frame(&mut [0; 16]).await, Err(Error::Malformed));
+    }
+
+    #[futures_test::test]
+    async fn truncated_frame_ends_with_eof() {
+        for (encoding, checksum) in CONFIGS {
+            let frame = encode(&[1, 2, 3, 4], encoding, checksum).await;
+            let bytes = frame.bytes();
+            let mut receiver = framed(&bytes[..bytes.len() - 1], encoding, checksum);
+            assert_eq!(receiver.recv_frame(&mut [0; 16]).await, Err(Error::UnexpectedEof));
+        }
+    }
+
+    #[futures_test::test]
+    async fn resynchronizes_after_truncated_frame() {
+        for (encoding, checksum) in CONFIGS {
+            let first = encode(&[1, 2, 3, 4, 5, 6], encoding, checksum).await;
+            let second = encode(&[7, 8, 9], encoding, checksum).await;
+            // The first frame loses bytes in its middle.
+            let mut input = [0; 64];
+            let mut len = 0;
+            for part in [&first.bytes()[..3], &first.bytes()[6..], second.bytes()] {
+                input[len..len + part.len()].copy_from_slice(part);
+                len += part.len();
+            }
+
+            let mut receiver = framed(&input[..len], encoding, checksum);
+            let mut buf = [0; 16];
+            assert!(matches!(
+                receiver.recv_frame(&mut buf).await,
+                Err(Error::Malformed | Error::Checksum)
+            ));
+            let len = receiver.recv_frame(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], &[7, 8, 9]);
+        }
+    }
+
+    #[futures_test::test]
+    async fn bad_checksum() {
+        for (encoding, checksum) in CONFIGS {
+            let mut frame = encode(&[1, 2, 3, 4], encoding, checksum).await;
+            // Byte 2 is a payload byte in both encodings, and needs no escaping.
+            frame.buf[2] ^= 0x10;
+            let mut receiver = framed(frame.bytes(), encoding, checksum);
+            assert_eq!(receiver.recv_frame(&mut [0; 16]).await, Err(Error::Checksum));
+        }
+    }
+
+    #[futures_test::test]
+    async fn frame_larger_than_buffer() {
+        let frame = encode(&[1; 20], Encoding::Cobs, Checksum::Crc16).await;
+        let next = encode(&[2; 4], Encoding::Cobs, Checksum::Crc16).await;
+        let mut input = [0; 64];
+        input[..frame.len].copy_from_slice(frame.bytes());
+        input[frame.len..frame.len + next.len].copy_from_slice(next.bytes());
+
+        let mut receiver = framed(&input[..frame.len + next.len], Encoding::Cobs, Checksum::Crc16);
+        let mut buf = [0; 8];
+        assert_eq!(receiver.recv_frame(&mut buf).await, Err(Error::BufferTooSmall));
+        assert_eq!(receiver.recv_frame(&mut buf).await, Ok(4));
+        assert_eq!(buf[..4], [2; 4]);
+    }
+}
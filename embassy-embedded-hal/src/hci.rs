@@ -0,0 +1,167 @@
+//! Bluetooth HCI transports.
+//!
+//! A Bluetooth host stack talks to the controller with HCI packets: commands, events, and ACL,
+//! synchronous and isochronous data. [`Transport`] reads and writes whole packets, whatever the
+//! link to the controller is, so a host stack written against it runs on any of:
+//!
+//! - [`H4`], the UART transport of the Bluetooth Core specification, over any
+//!   [`embedded_io_async`] reader and writer, e.g. a buffered UART.
+//! - `embassy_stm32_wpan::sub::ble::Ble`, the IPCC link to the radio coprocessor of STM32WB chips.
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::mutex::Mutex;
+use embedded_io_async::{Read, ReadExactError, Write};
+
+/// Kind of an HCI packet, with its H4 packet indicator as discriminant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PacketKind {
+    /// Command, from the host to the controller.
+    Command = 0x01,
+    /// ACL data.
+    AclData = 0x02,
+    /// Synchronous data.
+    SyncData = 0x03,
+    /// Event, from the controller to the host.
+    Event = 0x04,
+    /// Isochronous data.
+    IsoData = 0x05,
+}
+
+impl PacketKind {
+    /// The packet kind for an H4 packet indicator.
+    pub fn from_indicator(indicator: u8) -> Option<Self> {
+        match indicator {
+            0x01 => Some(Self::Command),
+            0x02 => Some(Self::AclData),
+            0x03 => Some(Self::SyncData),
+            0x04 => Some(Self::Event),
+            0x05 => Some(Self::IsoData),
+            _ => None,
+        }
+    }
+
+    /// Length of the header of packets of this kind.
+    pub fn header_len(self) -> usize {
+        match self {
+            Self::Command | Self::SyncData => 3,
+            Self::AclData | Self::IsoData => 4,
+            Self::Event => 2,
+        }
+    }
+
+    /// Length of the parameters or data following `header`, from its length field.
+    pub fn payload_len(self, header: &[u8]) -> usize {
+        match self {
+            Self::Command | Self::SyncData => header[2] as usize,
+            Self::AclData => u16::from_le_bytes([header[2], header[3]]) as usize,
+            // The two top bits are reserved
+            Self::IsoData => (u16::from_le_bytes([header[2], header[3]]) & 0x3FFF) as usize,
+            Self::Event => header[1] as usize,
+        }
+    }
+}
+
+/// HCI transport, see the [module documentation](self).
+///
+/// Packets are read and written whole, as their header followed by their parameters or data,
+/// without the H4 packet indicator. Reads and writes may run concurrently.
+pub trait Transport {
+    /// Error type.
+    type Error;
+
+    /// Read a packet into `buf`, returning its kind and length.
+    async fn read(&self, buf: &mut [u8]) -> Result<(PacketKind, usize), Self::Error>;
+
+    /// Write a packet of kind `kind`.
+    async fn write(&self, kind: PacketKind, packet: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// H4 transport error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Error of the underlying reader or writer.
+    Io(E),
+    /// The reader reached its end in the middle of a packet.
+    UnexpectedEof,
+    /// A packet started with an unknown packet indicator.
+    InvalidPacketKind(u8),
+    /// The packet doesn't fit in the buffer. It was discarded.
+    BufferTooSmall,
+}
+
+impl<E> From<ReadExactError<E>> for Error<E> {
+    fn from(e: ReadExactError<E>) -> Self {
+        match e {
+            ReadExactError::UnexpectedEof => Error::UnexpectedEof,
+            ReadExactError::Other(e) => Error::Io(e),
+        }
+    }
+}
+
+/// UART (H4) HCI transport.
+///
+/// Each packet is sent as its packet indicator followed by the packet. H4 has no way to recover
+/// from lost bytes, so the UART should use hardware flow control (RTS/CTS), which controllers
+/// usually require anyway. Reads and writes are serialized separately by mutexes of kind `M`, so
+/// a packet can be written while another task waits for one.
+pub struct H4<M: RawMutex, R, W> {
+    reader: Mutex<M, R>,
+    writer: Mutex<M, W>,
+}
+
+impl<M: RawMutex, R: Read, W: Write<Error = R::Error>> H4<M, R, W> {
+    /// Create a transport from the receiving and transmitting halves of a UART.
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader: Mutex::new(reader),
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Return the reader and writer.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader.into_inner(), self.writer.into_inner())
+    }
+}
+
+impl<M: RawMutex, R: Read, W: Write<Error = R::Error>> Transport for H4<M, R, W> {
+    type Error = Error<R::Error>;
+
+    async fn read(&self, buf: &mut [u8]) -> Result<(PacketKind, usize), Self::Error> {
+        let mut reader = self.reader.lock().await;
+
+        let mut indicator = [0];
+        reader.read_exact(&mut indicator).await?;
+        let kind = PacketKind::from_indicator(indicator[0]).ok_or(Error::InvalidPacketKind(indicator[0]))?;
+
+        let mut header = [0; 4];
+        let header = &mut header[..kind.header_len()];
+        reader.read_exact(header).await?;
+        let len = header.len() + kind.payload_len(header);
+
+        if buf.len() < len {
+            // Skip the payload, to stay in sync with the packet boundaries
+            let mut remaining = len - header.len();
+            let mut scratch = [0; 16];
+            while remaining > 0 {
+                let n = remaining.min(scratch.len());
+                reader.read_exact(&mut scratch[..n]).await?;
+                remaining -= n;
+            }
+            return Err(Error::BufferTooSmall);
+        }
+
+        buf[..header.len()].copy_from_slice(header);
+        reader.read_exact(&mut buf[header.len()..len]).await?;
+        Ok((kind, len))
+    }
+
+    async fn write(&self, kind: PacketKind, packet: &[u8]) -> Result<(), Self::Error> {
+        let mut writer = self.writer.lock().await;
+        writer.write_all(&[kind as u8]).await.map_err(Error::Io)?;
+        writer.write_all(packet).await.map_err(Error::Io)?;
+        writer.flush().await.map_err(Error::Io)
+    }
+}
@@ -0,0 +1,554 @@
+//! Power-fail safe settings storage on flash.
+//!
+//! [`Journal`] keeps an `N`-byte settings image in RAM, backed by a log of records in two erase
+//! areas of a [`NorFlash`], such as a [`Partition`](crate::flash::partition::Partition) of the
+//! internal flash or an external SPI NOR flash. Changes are made in a [`Transaction`], and
+//! [`Transaction::commit`] appends a single record holding all the bytes that changed, so many
+//! small writes cost a single flash write, and either all of them survive a power loss or none.
+//!
+//! A record is only valid once its CRC, written last, matches: a record torn by a power loss is
+//! ignored when the journal is opened again. When the active area is full, the whole image is
+//! written to the other area, which only becomes active once its header, also written last, is
+//! valid.
+//!
+//! ```rust,ignore
+//! let mut journal = Journal::<_, 64>::new(flash, 16 * 1024).await?;
+//! let mut tx = journal.transaction();
+//! tx.write(0, &baud_rate.to_le_bytes())?;
+//! tx.write(4, &[parity, stop_bits])?;
+//! tx.commit().await?;
+//! ```
+
+use embedded_storage_async::nor_flash::NorFlash;
+
+/// Area header magic number.
+const MAGIC: u32 = 0x4A52_4E4C;
+/// Size of a record entry header: offset and length, both `u16`.
+const ENTRY_HEADER_LEN: usize = 4;
+/// Size of the buffer records are written through, a multiple of every flash write size.
+const CHUNK: usize = 64;
+
+/// Journal error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Flash error.
+    Flash(E),
+    /// The arguments are out of bounds.
+    OutOfBounds,
+}
+
+/// Journaled settings image of `N` bytes, see the [module documentation](self).
+pub struct Journal<F: NorFlash, const N: usize> {
+    flash: F,
+    area_size: u32,
+    image: [u8; N],
+    /// Active area and its generation, `None` until the first commit on blank flash.
+    active: Option<(u32, u32)>,
+    /// Offset of the next record within the active area.
+    write_offset: u32,
+    /// The active area ends with a torn record, so appending must start in a fresh area.
+    torn: bool,
+}
+
+impl<F: NorFlash, const N: usize> Journal<F, N> {
+    /// Open the journal stored in the first `2 * area_size` bytes of `flash`.
+    ///
+    /// `area_size` must be a multiple of the erase size, and fit a record of the whole image. On
+    /// blank flash, the image is all `0xFF` until the first commit.
+    pub async fn new(mut flash: F, area_size: u32) -> Result<Self, Error<F::Error>> {
+        assert!(F::READ_SIZE == 1);
+        assert!(F::WRITE_SIZE <= CHUNK && CHUNK % F::WRITE_SIZE == 0);
+        assert!(N > 0 && N <= u16::MAX as usize);
+        assert!(area_size % F::ERASE_SIZE as u32 == 0);
+        assert!(2 * area_size as usize <= flash.capacity());
+        assert!(header_len::<F>() + record_len::<F>(ENTRY_HEADER_LEN + N) <= area_size);
+
+        let mut active = None;
+        for area in 0..2 {
+            if let Some(generation) = read_header(&mut flash, area * area_size).await? {
+                match active {
+                    Some((_, g)) if newer(g, generation) => {}
+                    _ => active = Some((area, generation)),
+                }
+            }
+        }
+
+        let mut this = Self {
+            flash,
+            area_size,
+            image: [0xFF; N],
+            active,
+            write_offset: header_len::<F>(),
+            torn: false,
+        };
+        if active.is_some() {
+            this.replay().await?;
+        }
+        Ok(this)
+    }
+
+    /// Release the flash.
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    /// The committed settings image.
+    pub fn data(&self) -> &[u8; N] {
+        &self.image
+    }
+
+    /// Read committed bytes at `offset`.
+    pub fn read(&self, offset: usize, bytes: &mut [u8]) -> Result<(), Error<F::Error>> {
+        let src = self.image.get(offset..offset + bytes.len()).ok_or(Error::OutOfBounds)?;
+        bytes.copy_from_slice(src);
+        Ok(())
+    }
+
+    /// Start a transaction, whose writes are committed together.
+    pub fn transaction(&mut self) -> Transaction<'_, F, N> {
+        Transaction {
+            data: self.image,
+            journal: self,
+        }
+    }
+
+    /// Apply the records of the active area to the image.
+    async fn replay(&mut self) -> Result<(), Error<F::Error>> {
+        let Some((area, _)) = self.active else {
+            return Ok(());
+        };
+        let base = area * self.area_size;
+        let mut offset = header_len::<F>();
+
+        loop {
+            let mut len = [0; 4];
+            if offset + 4 > self.area_size {
+                break;
+            }
+            self.flash.read(base + offset, &mut len).await.map_err(Error::Flash)?;
+            let len = u32::from_le_bytes(len);
+            // Blank space, records are never empty.
+            if len == 0 || len == u32::MAX {
+                break;
+            }
+
+            let end = (offset as u64) + record_len::<F>(len as usize) as u64;
+            if end > self.area_size as u64 || !self.check_record(base + offset, len).await? {
+                self.torn = true;
+                break;
+            }
+            self.apply_record(base + offset + 4, len).await?;
+            offset = end as u32;
+        }
+
+        self.write_offset = offset;
+        Ok(())
+    }
+
+    /// Check the CRC of the record at `addr` with a payload of `len` bytes.
+    async fn check_record(&mut self, addr: u32, len: u32) -> Result<bool, Error<F::Error>> {
+        let mut buf = [0; CHUNK];
+        let mut crc = Crc::new();
+        let mut pos = 0;
+        while pos < 4 + len {
+            let n = (4 + len - pos).min(CHUNK as u32) as usize;
+            self.flash.read(addr + pos, &mut buf[..n]).await.map_err(Error::Flash)?;
+            crc.update(&buf[..n]);
+            pos += n as u32;
+        }
+
+        let mut stored = [0; 4];
+        let crc_addr = addr + align::<F>(4 + len as usize);
+        self.flash.read(crc_addr, &mut stored).await.map_err(Error::Flash)?;
+        Ok(u32::from_le_bytes(stored) == crc.finish())
+    }
+
+    /// Copy the entries of a payload of `len` bytes at `addr` to the image.
+    async fn apply_record(&mut self, addr: u32, len: u32) -> Result<(), Error<F::Error>> {
+        let mut pos = 0;
+        while pos + ENTRY_HEADER_LEN as u32 <= len {
+            let mut header = [0; ENTRY_HEADER_LEN];
+            self.flash.read(addr + pos, &mut header).await.map_err(Error::Flash)?;
+            let offset = u16::from_le_bytes([header[0], header[1]]) as usize;
+            let n = u16::from_le_bytes([header[2], header[3]]) as usize;
+            pos += ENTRY_HEADER_LEN as u32;
+
+            // The CRC matched, so this was written by a journal of another size.
+            let Some(dst) = self.image.get_mut(offset..offset + n) else {
+                return Err(Error::OutOfBounds);
+            };
+            self.flash.read(addr + pos, dst).await.map_err(Error::Flash)?;
+            pos += n as u32;
+        }
+        Ok(())
+    }
+
+    async fn commit(&mut self, data: &[u8; N]) -> Result<(), Error<F::Error>> {
+        let payload_len: usize = changes(&self.image, data).map(|(_, n)| ENTRY_HEADER_LEN + n).sum();
+        if payload_len == 0 {
+            return Ok(());
+        }
+
+        let fits = self.write_offset + record_len::<F>(payload_len) <= self.area_size;
+        match self.active {
+            Some((area, _)) if fits && !self.torn => {
+                let addr = area * self.area_size + self.write_offset;
+                let entries = changes(&self.image, data).map(|(offset, n)| (offset, &data[offset..offset + n]));
+                write_record(&mut self.flash, addr, payload_len, entries).await?;
+                self.write_offset += record_len::<F>(payload_len);
+            }
+            _ => self.compact(data).await?,
+        }
+
+        self.image = *data;
+        Ok(())
+    }
+
+    /// Write the whole image to the inactive area, and make it the active one.
+    async fn compact(&mut self, data: &[u8; N]) -> Result<(), Error<F::Error>> {
+        let (area, generation) = match self.active {
+            Some((area, generation)) => (1 - area, generation.wrapping_add(1)),
+            None => (0, 0),
+        };
+        let base = area * self.area_size;
+
+        self.flash
+            .erase(base, base + self.area_size)
+            .await
+            .map_err(Error::Flash)?;
+
+        let payload_len = ENTRY_HEADER_LEN + N;
+        let entries = core::iter::once((0, &data[..]));
+        write_record(&mut self.flash, base + header_len::<F>(), payload_len, entries).await?;
+
+        // The area becomes valid, and newer than the other one, with its header.
+        let mut header = [0xFF; CHUNK];
+        header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&generation.to_le_bytes());
+        let mut crc = Crc::new();
+        crc.update(&header[0..8]);
+        header[8..12].copy_from_slice(&crc.finish().to_le_bytes());
+        let len = header_len::<F>() as usize;
+        self.flash.write(base, &header[..len]).await.map_err(Error::Flash)?;
+
+        self.active = Some((area, generation));
+        self.write_offset = header_len::<F>() + record_len::<F>(payload_len);
+        self.torn = false;
+        Ok(())
+    }
+}
+
+/// Changes to a journal, committed together.
+///
+/// Dropping the transaction without committing discards the changes.
+pub struct Transaction<'a, F: NorFlash, const N: usize> {
+    journal: &'a mut Journal<F, N>,
+    data: [u8; N],
+}
+
+impl<'a, F: NorFlash, const N: usize> Transaction<'a, F, N> {
+    /// Write `bytes` at `offset`.
+    pub fn write(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error<F::Error>> {
+        let dst = self
+            .data
+            .get_mut(offset..offset + bytes.len())
+            .ok_or(Error::OutOfBounds)?;
+        dst.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// The settings image, with the changes made so far.
+    pub fn data(&self) -> &[u8; N] {
+        &self.data
+    }
+
+    /// The settings image, to change in place.
+    pub fn data_mut(&mut self) -> &mut [u8; N] {
+        &mut self.data
+    }
+
+    /// Write the changes to flash.
+    ///
+    /// Once this returns, the changes survive a power loss. If power is lost or an error occurs
+    /// before, the journal is left unchanged.
+    pub async fn commit(self) -> Result<(), Error<F::Error>> {
+        self.journal.commit(&self.data).await
+    }
+}
+
+/// Write a record at `addr`: the payload length, the entries, then the CRC, which validates it.
+async fn write_record<'a, F: NorFlash>(
+    flash: &mut F,
+    addr: u32,
+    payload_len: usize,
+    entries: impl Iterator<Item = (usize, &'a [u8])>,
+) -> Result<(), Error<F::Error>> {
+    let mut w = RecordWriter {
+        flash,
+        addr,
+        buf: [0xFF; CHUNK],
+        fill: 0,
+        crc: Crc::new(),
+    };
+
+    w.push(&(payload_len as u32).to_le_bytes()).await?;
+    for (offset, data) in entries {
+        w.push(&(offset as u16).to_le_bytes()).await?;
+        w.push(&(data.len() as u16).to_le_bytes()).await?;
+        w.push(data).await?;
+    }
+    w.finish().await
+}
+
+struct RecordWriter<'f, F: NorFlash> {
+    flash: &'f mut F,
+    addr: u32,
+    buf: [u8; CHUNK],
+    fill: usize,
+    crc: Crc,
+}
+
+impl<'f, F: NorFlash> RecordWriter<'f, F> {
+    async fn push(&mut self, mut data: &[u8]) -> Result<(), Error<F::Error>> {
+        self.crc.update(data);
+        while !data.is_empty() {
+            let n = data.len().min(CHUNK - self.fill);
+            self.buf[self.fill..self.fill + n].copy_from_slice(&data[..n]);
+            self.fill += n;
+            data = &data[n..];
+            if self.fill == CHUNK {
+                self.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the buffer, padded to the write size.
+    async fn flush(&mut self) -> Result<(), Error<F::Error>> {
+        let len = align::<F>(self.fill) as usize;
+        self.buf[self.fill..len].fill(0xFF);
+        self.flash
+            .write(self.addr, &self.buf[..len])
+            .await
+            .map_err(Error::Flash)?;
+        self.addr += len as u32;
+        self.fill = 0;
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<(), Error<F::Error>> {
+        if self.fill > 0 {
+            self.flush().await?;
+        }
+        let crc = self.crc.finish();
+        self.buf[..4].copy_from_slice(&crc.to_le_bytes());
+        self.fill = 4;
+        self.flush().await
+    }
+}
+
+/// Read the header of the area at `addr`, returning its generation if it's valid.
+async fn read_header<F: NorFlash>(flash: &mut F, addr: u32) -> Result<Option<u32>, Error<F::Error>> {
+    let mut header = [0; 12];
+    flash.read(addr, &mut header).await.map_err(Error::Flash)?;
+
+    let mut crc = Crc::new();
+    crc.update(&header[0..8]);
+    let word = |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+    match word(0) == MAGIC && word(8) == crc.finish() {
+        true => Ok(Some(word(4))),
+        false => Ok(None),
+    }
+}
+
+/// Whether generation `a` is newer than `b`, allowing wrap-around.
+fn newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+/// Ranges of `new` that differ from `old`, as `(offset, len)`.
+///
+/// Ranges separated by fewer unchanged bytes than an entry header are merged.
+fn changes<'a>(old: &'a [u8], new: &'a [u8]) -> impl Iterator<Item = (usize, usize)> + 'a {
+    let mut i = 0;
+    core::iter::from_fn(move || {
+        while i < new.len() && old[i] == new[i] {
+            i += 1;
+        }
+        if i == new.len() {
+            return None;
+        }
+
+        let start = i;
+        let mut end = i + 1;
+        while i < new.len() && i < end + ENTRY_HEADER_LEN {
+            if old[i] != new[i] {
+                end = i + 1;
+            }
+            i += 1;
+        }
+        i = end;
+        Some((start, end - start))
+    })
+}
+
+fn align<F: NorFlash>(len: usize) -> u32 {
+    len.next_multiple_of(F::WRITE_SIZE) as u32
+}
+
+fn header_len<F: NorFlash>() -> u32 {
+    align::<F>(12)
+}
+
+/// Size in flash of a record with a payload of `payload_len` bytes.
+fn record_len<F: NorFlash>(payload_len: usize) -> u32 {
+    align::<F>(4 + payload_len) + align::<F>(4)
+}
+
+/// CRC-32 (IEEE 802.3).
+struct Crc(u32);
+
+impl Crc {
+    fn new() -> Self {
+        Self(!0)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 ^= byte as u32;
+            for _ in 0..8 {
+                self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & (self.0 & 1).wrapping_neg());
+            }
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flash::mem_flash::MemFlash;
+
+    const AREA: u32 = 1024;
+
+    type Flash = MemFlash<2048, 256, 4>;
+
+    fn flash_erases<const N: usize>(journal: &Journal<Flash, N>) -> usize {
+        journal.flash.erases.len()
+    }
+
+    #[test]
+    fn changes_merges_close_ranges() {
+        let old = [0u8; 16];
+        let mut new = old;
+        assert_eq!(changes(&old, &new).next(), None);
+
+        new[2] = 1;
+        new[4] = 1;
+        new[15] = 1;
+        let mut ranges = changes(&old, &new);
+        assert_eq!(ranges.next(), Some((2, 3)));
+        assert_eq!(ranges.next(), Some((15, 1)));
+        assert_eq!(ranges.next(), None);
+
+        let mut new = old;
+        new[0] = 1;
+        new[ENTRY_HEADER_LEN + 1] = 1;
+        let mut ranges = changes(&old, &new);
+        assert_eq!(ranges.next(), Some((0, 1)));
+        assert_eq!(ranges.next(), Some((ENTRY_HEADER_LEN + 1, 1)));
+        assert_eq!(ranges.next(), None);
+    }
+
+    #[futures_test::test]
+    async fn blank_flash_reads_erased() {
+        let journal = Journal::<_, 16>::new(Flash::default(), AREA).await.unwrap();
+        assert_eq!(journal.data(), &[0xFF; 16]);
+    }
+
+    #[futures_test::test]
+    async fn commits_survive_reopen() {
+        let mut journal = Journal::<_, 16>::new(Flash::default(), AREA).await.unwrap();
+        let mut tx = journal.transaction();
+        tx.write(0, &[1, 2, 3, 4]).unwrap();
+        tx.commit().await.unwrap();
+
+        let mut tx = journal.transaction();
+        tx.write(10, &[5, 6]).unwrap();
+        tx.write(2, &[7]).unwrap();
+        tx.commit().await.unwrap();
+
+        let mut expected = [0xFF; 16];
+        expected[..4].copy_from_slice(&[1, 2, 7, 4]);
+        expected[10..12].copy_from_slice(&[5, 6]);
+        assert_eq!(journal.data(), &expected);
+
+        let journal = Journal::<_, 16>::new(journal.release(), AREA).await.unwrap();
+        assert_eq!(journal.data(), &expected);
+    }
+
+    #[futures_test::test]
+    async fn dropped_transaction_is_discarded() {
+        let mut journal = Journal::<_, 16>::new(Flash::default(), AREA).await.unwrap();
+        let mut tx = journal.transaction();
+        tx.write(0, &[1]).unwrap();
+        drop(tx);
+        assert_eq!(journal.data(), &[0xFF; 16]);
+        assert_eq!(journal.transaction().write(15, &[0; 2]), Err(Error::OutOfBounds));
+    }
+
+    #[futures_test::test]
+    async fn torn_record_is_ignored() {
+        let mut journal = Journal::<_, 16>::new(Flash::default(), AREA).await.unwrap();
+        let mut tx = journal.transaction();
+        tx.write(0, &[1]).unwrap();
+        tx.commit().await.unwrap();
+        let committed = *journal.data();
+
+        let mut tx = journal.transaction();
+        tx.write(8, &[2]).unwrap();
+        tx.commit().await.unwrap();
+
+        // Corrupt the CRC of the last record, as if power was lost while writing it.
+        let area = journal.active.unwrap().0;
+        let crc = (area * AREA + journal.write_offset) as usize - 4;
+        let mut flash = journal.release();
+        flash.mem[crc] ^= 0xFF;
+
+        let mut journal = Journal::<_, 16>::new(flash, AREA).await.unwrap();
+        assert_eq!(journal.data(), &committed);
+
+        // Appending after a torn record moves to the other area.
+        let mut tx = journal.transaction();
+        tx.write(9, &[3]).unwrap();
+        tx.commit().await.unwrap();
+        assert_ne!(journal.active.unwrap().0, area);
+
+        let mut expected = committed;
+        expected[9] = 3;
+        let journal = Journal::<_, 16>::new(journal.release(), AREA).await.unwrap();
+        assert_eq!(journal.data(), &expected);
+    }
+
+    #[futures_test::test]
+    async fn compacts_when_area_is_full() {
+        let mut journal = Journal::<_, 16>::new(Flash::default(), AREA).await.unwrap();
+        let mut expected = [0xFF; 16];
+        for i in 0..200u8 {
+            let mut tx = journal.transaction();
+            tx.write((i % 16) as usize, &[i]).unwrap();
+            tx.commit().await.unwrap();
+            expected[(i % 16) as usize] = i;
+        }
+        assert!(flash_erases(&journal) > 1);
+
+        assert_eq!(journal.data(), &expected);
+
+        let journal = Journal::<_, 16>::new(journal.release(), AREA).await.unwrap();
+        assert_eq!(journal.data(), &expected);
+    }
+}
@@ -0,0 +1,209 @@
+//! Matrix keypad scanner.
+//!
+//! A matrix keypad has a switch at each crossing of its rows and columns. [`Keypad`] drives one
+//! column low at a time and reads which rows follow it, with the rows pulled up: a low row means
+//! the key at that crossing is pressed. Key changes are debounced and sent as [`Event`]s to a
+//! channel, so the application just waits for key presses.
+//!
+//! With three keys of a rectangle pressed, current flows through them to the fourth corner and it
+//! reads as pressed too ("ghosting"). Keypads without a diode per key can't tell this apart from
+//! four real presses, so scans where four keys form a rectangle are ignored.
+//!
+//! ```rust,ignore
+//! static EVENTS: Channel<CriticalSectionRawMutex, Event, 4> = Channel::new();
+//!
+//! #[embassy_executor::task]
+//! async fn keypad_task(mut keypad: Keypad<Input<'static>, OutputOpenDrain<'static>, 4, 4>) {
+//!     keypad.run(EVENTS.sender()).await.unwrap();
+//! }
+//!
+//! let rows = [r0, r1, r2, r3].map(|p| Input::new(p, Pull::Up));
+//! let cols = [c0, c1, c2, c3].map(|p| OutputOpenDrain::new(p, Level::High, Speed::Low));
+//! spawner.must_spawn(keypad_task(Keypad::new(rows, cols, LAYOUT_4X4, Config::default())));
+//! ```
+
+use core::convert::Infallible;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::Sender;
+use embassy_time::{Duration, Ticker, Timer};
+use embedded_hal_1::digital::{InputPin, OutputPin};
+
+/// The common 4x4 keypad layout.
+pub const LAYOUT_4X4: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+/// The common 4x3 (telephone) keypad layout.
+pub const LAYOUT_4X3: [[char; 3]; 4] = [['1', '2', '3'], ['4', '5', '6'], ['7', '8', '9'], ['*', '0', '#']];
+
+/// Keypad configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Config {
+    /// Time between scans.
+    pub scan_period: Duration,
+    /// Number of identical consecutive scans needed to accept a change, so that a key changes at
+    /// most once per `scan_period * debounce_scans`.
+    pub debounce_scans: u8,
+    /// Time between driving a column low and reading the rows.
+    pub settle_time: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scan_period: Duration::from_millis(5),
+            debounce_scans: 4,
+            settle_time: Duration::from_micros(10),
+        }
+    }
+}
+
+/// A key of the keypad.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Key {
+    /// Row, from 0.
+    pub row: u8,
+    /// Column, from 0.
+    pub col: u8,
+    /// Label of the key in the layout.
+    pub label: char,
+}
+
+/// Key event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The key was pressed.
+    Pressed(Key),
+    /// The key was released.
+    Released(Key),
+}
+
+/// Matrix keypad scanner, see the [module documentation](self).
+///
+/// `ROWS` input pins, pulled up, and `COLS` output pins, preferably open-drain so that two keys
+/// of a row pressed together don't short two columns. Up to 32 columns are supported.
+pub struct Keypad<R, C, const ROWS: usize, const COLS: usize> {
+    rows: [R; ROWS],
+    cols: [C; COLS],
+    layout: [[char; COLS]; ROWS],
+    config: Config,
+    /// Debounced state, a bit per column for each row.
+    state: [u32; ROWS],
+    /// Last scan differing from `state`, and how many consecutive scans gave it.
+    candidate: [u32; ROWS],
+    candidate_scans: u8,
+}
+
+impl<R, C, E, const ROWS: usize, const COLS: usize> Keypad<R, C, ROWS, COLS>
+where
+    R: InputPin<Error = E>,
+    C: OutputPin<Error = E>,
+{
+    /// Create a scanner, with keys labelled by `layout`.
+    pub fn new(rows: [R; ROWS], cols: [C; COLS], layout: [[char; COLS]; ROWS], config: Config) -> Self {
+        assert!(COLS <= 32);
+
+        Self {
+            rows,
+            cols,
+            layout,
+            config,
+            state: [0; ROWS],
+            candidate: [0; ROWS],
+            candidate_scans: 0,
+        }
+    }
+
+    /// Scan the keypad forever, sending key events to `sender`.
+    ///
+    /// Scanning pauses while the channel is full, so key changes are never lost, only delayed.
+    pub async fn run<M: RawMutex, const N: usize>(&mut self, sender: Sender<'_, M, Event, N>) -> Result<Infallible, E> {
+        for col in self.cols.iter_mut() {
+            col.set_high()?;
+        }
+
+        let mut ticker = Ticker::every(self.config.scan_period);
+        loop {
+            ticker.next().await;
+
+            let Some(scan) = self.scan().await? else {
+                continue;
+            };
+            for event in self.debounce(scan) {
+                sender.send(event).await;
+            }
+        }
+    }
+
+    /// Whether a key is pressed, after debouncing.
+    pub fn is_pressed(&self, row: usize, col: usize) -> bool {
+        self.state[row] & (1 << col) != 0
+    }
+
+    /// Read the state of all keys, or `None` if the scan is ambiguous because of ghosting.
+    async fn scan(&mut self) -> Result<Option<[u32; ROWS]>, E> {
+        let mut scan = [0; ROWS];
+        for (c, col) in self.cols.iter_mut().enumerate() {
+            col.set_low()?;
+            Timer::after(self.config.settle_time).await;
+            for (r, row) in self.rows.iter_mut().enumerate() {
+                if row.is_low()? {
+                    scan[r] |= 1 << c;
+                }
+            }
+            col.set_high()?;
+        }
+
+        for r1 in 0..ROWS {
+            for r2 in r1 + 1..ROWS {
+                if (scan[r1] & scan[r2]).count_ones() >= 2 {
+                    return Ok(None);
+                }
+            }
+        }
+        Ok(Some(scan))
+    }
+
+    /// Update the debounced state with `scan`, returning the resulting events.
+    fn debounce(&mut self, scan: [u32; ROWS]) -> impl Iterator<Item = Event> + '_ {
+        let previous = self.state;
+        if scan == self.state {
+            self.candidate_scans = 0;
+        } else {
+            if scan != self.candidate {
+                self.candidate = scan;
+                self.candidate_scans = 0;
+            }
+            self.candidate_scans = self.candidate_scans.saturating_add(1);
+            if self.candidate_scans >= self.config.debounce_scans {
+                self.state = scan;
+                self.candidate_scans = 0;
+            }
+        }
+
+        let state = self.state;
+        let layout = &self.layout;
+        (0..ROWS).flat_map(move |r| {
+            let changed = previous[r] ^ state[r];
+            (0..COLS).filter(move |c| changed & (1 << c) != 0).map(move |c| {
+                let key = Key {
+                    row: r as u8,
+                    col: c as u8,
+                    label: layout[r][c],
+                };
+                match state[r] & (1 << c) != 0 {
+                    true => Event::Pressed(key),
+                    false => Event::Released(key),
+                }
+            })
+        })
+    }
+}
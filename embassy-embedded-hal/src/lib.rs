@@ -4,7 +4,18 @@
 #![doc = include_str!("../README.md")]
 
 pub mod adapter;
+pub mod block_device;
+pub mod eeprom;
 pub mod flash;
+pub mod framing;
+pub mod hci;
+pub mod journal;
+#[cfg(feature = "time")]
+pub mod keypad;
+#[cfg(feature = "postcard")]
+pub mod message;
+pub mod nor_flash;
+pub mod sd_spi;
 pub mod shared_bus;
 
 /// Set the configuration of a peripheral driver.
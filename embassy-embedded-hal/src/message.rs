@@ -0,0 +1,217 @@
+//! Typed messages over a framed transport.
+//!
+//! [`MessageLink`] exchanges [`serde`] types serialized with [`postcard`], one per frame of a
+//! [`Framed`] transport, for structured communication between an MCU and a host or another MCU.
+//! Messages are requests and responses: each request carries an id that its response echoes, so
+//! the calling side matches responses to its calls, and drops late responses to calls that timed
+//! out.
+//!
+//! A link is used by one side as the calling side, with [`call`](MessageLink::call), and by the
+//! other side as the serving side, with [`recv_request`](MessageLink::recv_request) and
+//! [`respond`](MessageLink::respond). For calls in both directions, use two links.
+//!
+//! ```rust,ignore
+//! #[derive(Serialize, Deserialize)]
+//! enum Request { ReadTemperature, SetLed(bool) }
+//! #[derive(Serialize, Deserialize)]
+//! enum Response { Temperature(f32), Done }
+//!
+//! // On the MCU
+//! let mut link = MessageLink::<_, _, 64>::new(Framed::new(rx, tx, Config::default()));
+//! loop {
+//!     let (id, request) = link.recv_request::<Request>().await?;
+//!     let response = match request {
+//!         Request::ReadTemperature => Response::Temperature(read_temperature()),
+//!         Request::SetLed(on) => { led.set_level(on.into()); Response::Done }
+//!     };
+//!     link.respond(id, &response).await?;
+//! }
+//! ```
+
+use embassy_time::{with_timeout, Duration};
+use embedded_io_async::{Read, Write};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::framing::{self, Framed};
+
+const KIND_REQUEST: u8 = 0;
+const KIND_RESPONSE: u8 = 1;
+
+/// Id of a request, to pass to [`MessageLink::respond`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RequestId(u16);
+
+/// Message link error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Error of the framed transport.
+    Framing(framing::Error<E>),
+    /// The message doesn't fit in the buffer once serialized.
+    Serialize,
+    /// A received message couldn't be deserialized as the expected type.
+    Deserialize,
+    /// No response was received in time.
+    Timeout,
+}
+
+impl<E> From<framing::Error<E>> for Error<E> {
+    fn from(e: framing::Error<E>) -> Self {
+        Error::Framing(e)
+    }
+}
+
+/// Link exchanging typed messages, see the [module documentation](self).
+///
+/// Messages are serialized into a buffer of `N` bytes, which bounds their size.
+pub struct MessageLink<R, W, const N: usize> {
+    framed: Framed<R, W>,
+    next_id: u16,
+    buf: [u8; N],
+}
+
+impl<R: Read, W: Write<Error = R::Error>, const N: usize> MessageLink<R, W, N> {
+    /// Create a link over `framed`.
+    pub fn new(framed: Framed<R, W>) -> Self {
+        Self {
+            framed,
+            next_id: 0,
+            buf: [0; N],
+        }
+    }
+
+    /// Return the framed transport.
+    pub fn into_inner(self) -> Framed<R, W> {
+        self.framed
+    }
+
+    /// Send `request` and wait up to `timeout` for its response.
+    ///
+    /// Responses to other requests, e.g. to earlier calls that timed out, and requests from the
+    /// other side, are dropped. Frames that fail their checksum are dropped too, as they can't be
+    /// told apart from the response.
+    pub async fn call<Req: Serialize, Resp: DeserializeOwned>(
+        &mut self,
+        request: &Req,
+        timeout: Duration,
+    ) -> Result<Resp, Error<R::Error>> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.send(KIND_REQUEST, id, request).await?;
+
+        with_timeout(timeout, async {
+            loop {
+                match self.recv_kind::<Resp>(KIND_RESPONSE, Some(id)).await {
+                    Ok(Some((_, response))) => return Ok(response),
+                    Ok(None) => {}
+                    Err(Error::Framing(framing::Error::Checksum | framing::Error::Malformed)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        })
+        .await
+        .map_err(|_| Error::Timeout)?
+    }
+
+    /// Wait for a request, skipping any other message.
+    pub async fn recv_request<Req: DeserializeOwned>(&mut self) -> Result<(RequestId, Req), Error<R::Error>> {
+        loop {
+            if let Some((id, request)) = self.recv_kind(KIND_REQUEST, None).await? {
+                return Ok((RequestId(id), request));
+            }
+        }
+    }
+
+    /// Send `response` to the request `id`.
+    pub async fn respond<Resp: Serialize>(&mut self, id: RequestId, response: &Resp) -> Result<(), Error<R::Error>> {
+        self.send(KIND_RESPONSE, id.0, response).await
+    }
+
+    async fn send<T: Serialize>(&mut self, kind: u8, id: u16, message: &T) -> Result<(), Error<R::Error>> {
+        let frame = postcard::to_slice(&(kind, id, message), &mut self.buf).map_err(|_| Error::Serialize)?;
+        self.framed.send_frame(frame).await?;
+        Ok(())
+    }
+
+    /// Receive a message, returning it if it is of `kind`, and has the id `id` if given.
+    ///
+    /// Other messages are skipped before deserializing their payload, which can be of another type.
+    async fn recv_kind<T: DeserializeOwned>(
+        &mut self,
+        kind: u8,
+        id: Option<u16>,
+    ) -> Result<Option<(u16, T)>, Error<R::Error>> {
+        let len = self.framed.recv_frame(&mut self.buf).await?;
+        let ((received_kind, received_id), payload) =
+            postcard::take_from_bytes::<(u8, u16)>(&self.buf[..len]).map_err(|_| Error::Deserialize)?;
+        if received_kind != kind || id.is_some_and(|id| id != received_id) {
+            return Ok(None);
+        }
+        let message = postcard::from_bytes(payload).map_err(|_| Error::Deserialize)?;
+        Ok(Some((received_id, message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::mem_io::{MemInput, MemOutput};
+    use crate::framing::Config;
+
+    fn link(input: &[u8]) -> MessageLink<MemInput<'_>, MemOutput, 64> {
+        MessageLink::new(Framed::new(MemInput(input), MemOutput::new(), Config::default()))
+    }
+
+    /// Bytes sent by a link responding `response` to the request `id`.
+    async fn response_bytes<T: Serialize>(id: u16, response: &T) -> MemOutput {
+        let mut server = link(&[]);
+        server.respond(RequestId(id), response).await.unwrap();
+        server.into_inner().into_inner().1
+    }
+
+    #[futures_test::test]
+    async fn round_trip() {
+        let response = response_bytes(0, &(42u32, true)).await;
+
+        let mut client = link(response.bytes());
+        let received: (u32, bool) = client.call(&[1u8, 2, 3], Duration::from_secs(1)).await.unwrap();
+        assert_eq!(received, (42, true));
+
+        let request = client.into_inner().into_inner().1;
+        let mut server = link(request.bytes());
+        let (id, received) = server.recv_request::<[u8; 3]>().await.unwrap();
+        assert_eq!(id, RequestId(0));
+        assert_eq!(received, [1, 2, 3]);
+    }
+
+    #[futures_test::test]
+    async fn late_response_of_another_type_is_dropped() {
+        // A response to an earlier call that timed out, whose payload isn't a bool.
+        let late = response_bytes(7, &200u8).await;
+        let response = response_bytes(0, &true).await;
+        let mut input = [0; 64];
+        input[..late.len].copy_from_slice(late.bytes());
+        input[late.len..late.len + response.len].copy_from_slice(response.bytes());
+
+        let mut client = link(&input[..late.len + response.len]);
+        let received: bool = client.call(&(), Duration::from_secs(1)).await.unwrap();
+        assert!(received);
+    }
+
+    #[futures_test::test]
+    async fn requests_are_skipped_by_call() {
+        let mut other = link(&[]);
+        other.send(KIND_REQUEST, 0, &5u8).await.unwrap();
+        let request = other.into_inner().into_inner().1;
+        let response = response_bytes(0, &6u8).await;
+        let mut input = [0; 64];
+        input[..request.len].copy_from_slice(request.bytes());
+        input[request.len..request.len + response.len].copy_from_slice(response.bytes());
+
+        let mut client = link(&input[..request.len + response.len]);
+        let received: u8 = client.call(&(), Duration::from_secs(1)).await.unwrap();
+        assert_eq!(received, 6);
+    }
+}
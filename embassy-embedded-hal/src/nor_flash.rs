@@ -0,0 +1,632 @@
+//! External serial NOR flash memory.
+//!
+//! [`SpiNorFlash`] drives any JEDEC compliant serial NOR flash, configured at runtime from its
+//! SFDP (Serial Flash Discoverable Parameters) table: size, 4 kB sector erase and page size, and,
+//! if the bus has four data lines, how to enable quad mode and which quad read command to use.
+//! Memories larger than 16 MB are switched to 4-byte addressing.
+//!
+//! The flash is accessed through a [`NorBus`]. [`SpiNorBus`] implements it over any
+//! `embedded-hal-async` SPI device, on a single lane; HALs implement it for their quad SPI
+//! peripherals.
+
+use embedded_hal_1::spi::Operation;
+use embedded_storage_async::nor_flash::{NorFlashError, NorFlashErrorKind};
+
+/// Sector erase size, in bytes.
+pub const ERASE_SIZE: usize = 4096;
+
+const CMD_READ_JEDEC_ID: u8 = 0x9F;
+const CMD_READ_SFDP: u8 = 0x5A;
+const CMD_READ_STATUS1: u8 = 0x05;
+const CMD_READ_STATUS2: u8 = 0x35;
+const CMD_READ_STATUS2_ALT: u8 = 0x3F;
+const CMD_WRITE_STATUS1: u8 = 0x01;
+const CMD_WRITE_STATUS2: u8 = 0x31;
+const CMD_WRITE_STATUS2_ALT: u8 = 0x3E;
+const CMD_WRITE_ENABLE: u8 = 0x06;
+const CMD_ENTER_4BYTE: u8 = 0xB7;
+const CMD_FAST_READ: u8 = 0x0B;
+const CMD_PAGE_PROGRAM: u8 = 0x02;
+
+/// Write in progress bit of status register 1.
+const STATUS_WIP: u8 = 0x01;
+
+/// A flash command, sent with the instruction and address on a single lane.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Command {
+    /// Instruction byte.
+    pub instruction: u8,
+    /// Address, if any.
+    pub address: Option<u32>,
+    /// Send the address as 4 bytes instead of 3.
+    pub four_byte_address: bool,
+    /// Send 8 dummy clock cycles between the address and the data.
+    pub dummy: bool,
+    /// Transfer the data on four lanes.
+    pub quad_data: bool,
+}
+
+impl Command {
+    /// Command with only an instruction.
+    pub const fn new(instruction: u8) -> Self {
+        Self {
+            instruction,
+            address: None,
+            four_byte_address: false,
+            dummy: false,
+            quad_data: false,
+        }
+    }
+}
+
+/// Bus to a serial NOR flash.
+pub trait NorBus {
+    /// Bus error.
+    type Error: core::fmt::Debug;
+
+    /// Whether the bus has four data lanes, so [`Command::quad_data`] may be set.
+    const QUAD: bool;
+
+    /// Send a command without data.
+    async fn command(&mut self, command: &Command) -> Result<(), Self::Error>;
+
+    /// Send a command, then read `buf.len()` bytes.
+    async fn read(&mut self, command: &Command, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Send a command, then write `buf`.
+    async fn write(&mut self, command: &Command, buf: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// NOR flash error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Bus error.
+    Bus(E),
+    /// The memory doesn't have a valid SFDP table, or lacks a required feature.
+    Sfdp,
+    /// The arguments are not properly aligned.
+    NotAligned,
+    /// The arguments are out of bounds.
+    OutOfBounds,
+}
+
+impl<E: core::fmt::Debug> NorFlashError for Error<E> {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::NotAligned => NorFlashErrorKind::NotAligned,
+            Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// How quad mode is enabled, from the SFDP quad enable requirements (QER) field.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum QuadEnable {
+    /// No quad enable bit.
+    None,
+    /// Bit 1 of status register 2, written along with status register 1.
+    Status2Bit1,
+    /// Bit 6 of status register 1.
+    Status1Bit6,
+    /// Bit 7 of status register 2, with dedicated read and write instructions.
+    Status2Bit7,
+    /// Bit 1 of status register 2, with dedicated read and write instructions.
+    Status2Bit1Separate,
+}
+
+/// Parameters read from the basic flash parameter table.
+#[derive(Debug, Copy, Clone)]
+struct Parameters {
+    size: u32,
+    page_size: u32,
+    erase_instruction: u8,
+    four_byte_address: bool,
+    enter_4byte: bool,
+    enter_4byte_needs_wren: bool,
+    quad_read_instruction: Option<u8>,
+    quad_enable: QuadEnable,
+}
+
+impl Parameters {
+    fn parse(bfpt: &[u32; 16], dwords: usize) -> Option<Self> {
+        if dwords < 9 {
+            return None;
+        }
+
+        // DWORD 1: 4 kB erase support and address bytes.
+        if bfpt[0] & 0b11 != 0b01 {
+            return None;
+        }
+        let erase_instruction = (bfpt[0] >> 8) as u8;
+        let address_bytes = (bfpt[0] >> 17) & 0b11;
+        let supports_1_1_4 = bfpt[0] & (1 << 22) != 0;
+
+        // DWORD 2: density, in bits.
+        let size = match bfpt[1] & (1 << 31) {
+            0 => (bfpt[1] + 1) / 8,
+            _ => {
+                // Memories of 4 GB and more can't be addressed.
+                let n = bfpt[1] & 0x7FFF_FFFF;
+                if !(3..35).contains(&n) {
+                    return None;
+                }
+                1 << (n - 3)
+            }
+        };
+
+        // 3 or 4-byte memories start in 3-byte mode, and need switching if they're over 16 MB.
+        let (four_byte_address, enter_4byte) = match address_bytes {
+            0b00 => (false, false),
+            0b01 => (size > 1 << 24, size > 1 << 24),
+            0b10 => (true, false),
+            _ => return None,
+        };
+
+        // DWORD 3: 1-1-4 fast read, used only if it takes 8 dummy clocks in total.
+        let quad_read_instruction = match supports_1_1_4 {
+            true => {
+                let dummy = ((bfpt[2] >> 16) & 0x1F) + ((bfpt[2] >> 21) & 0x7);
+                let instruction = (bfpt[2] >> 24) as u8;
+                (dummy == 8 && instruction != 0).then_some(instruction)
+            }
+            false => None,
+        };
+
+        // DWORD 11 and later were added in JESD216A.
+        let page_size = match dwords >= 11 {
+            true => 1 << ((bfpt[10] >> 4) & 0xF),
+            false => 256,
+        };
+        let quad_enable = match dwords >= 15 {
+            true => match (bfpt[14] >> 20) & 0b111 {
+                0b000 => QuadEnable::None,
+                0b001 | 0b100 | 0b101 => QuadEnable::Status2Bit1,
+                0b010 => QuadEnable::Status1Bit6,
+                0b011 => QuadEnable::Status2Bit7,
+                0b110 => QuadEnable::Status2Bit1Separate,
+                _ => return None,
+            },
+            false => QuadEnable::None,
+        };
+        // DWORD 16: 4-byte address entry methods, B7h alone (bit 24) or after write enable (bit 25).
+        let enter_4byte_needs_wren = dwords >= 16 && bfpt[15] & (1 << 25) != 0 && bfpt[15] & (1 << 24) == 0;
+
+        Some(Self {
+            size,
+            page_size,
+            erase_instruction,
+            four_byte_address,
+            enter_4byte,
+            enter_4byte_needs_wren,
+            quad_read_instruction,
+            quad_enable,
+        })
+    }
+}
+
+/// Serial NOR flash driver.
+pub struct SpiNorFlash<B: NorBus> {
+    bus: B,
+    jedec_id: [u8; 3],
+    params: Parameters,
+    quad: bool,
+}
+
+impl<B: NorBus> SpiNorFlash<B> {
+    /// Detect the flash memory on `bus`, and configure it.
+    ///
+    /// If the bus is [quad](NorBus::QUAD) and the memory supports it, quad mode is enabled and reads
+    /// use four lanes. Programming and erasing always use a single lane.
+    pub async fn new(mut bus: B) -> Result<Self, Error<B::Error>> {
+        let mut jedec_id = [0; 3];
+        bus.read(&Command::new(CMD_READ_JEDEC_ID), &mut jedec_id)
+            .await
+            .map_err(Error::Bus)?;
+
+        // SFDP header, then the first parameter header, which is the basic flash parameter table.
+        let mut header = [0; 16];
+        read_sfdp(&mut bus, 0, &mut header).await?;
+        if &header[0..4] != b"SFDP" || header[8] != 0x00 || header[15] != 0xFF {
+            return Err(Error::Sfdp);
+        }
+        let dwords = (header[11] as usize).min(16);
+        let offset = u32::from_le_bytes([header[12], header[13], header[14], 0]);
+
+        let mut raw = [0; 64];
+        read_sfdp(&mut bus, offset, &mut raw[..dwords * 4]).await?;
+        let mut bfpt = [0u32; 16];
+        for (dword, bytes) in bfpt.iter_mut().zip(raw.chunks_exact(4)) {
+            *dword = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        let params = Parameters::parse(&bfpt, dwords).ok_or(Error::Sfdp)?;
+
+        let mut this = Self {
+            bus,
+            jedec_id,
+            params,
+            quad: false,
+        };
+
+        if params.enter_4byte {
+            if params.enter_4byte_needs_wren {
+                this.command(CMD_WRITE_ENABLE).await?;
+            }
+            this.command(CMD_ENTER_4BYTE).await?;
+        }
+        if B::QUAD && params.quad_read_instruction.is_some() {
+            this.enable_quad().await?;
+            this.quad = true;
+        }
+
+        Ok(this)
+    }
+
+    /// JEDEC manufacturer and device ID.
+    pub fn jedec_id(&self) -> [u8; 3] {
+        self.jedec_id
+    }
+
+    /// Size of the memory, in bytes.
+    pub fn capacity(&self) -> usize {
+        self.params.size as usize
+    }
+
+    /// Whether reads use four data lanes.
+    pub fn is_quad(&self) -> bool {
+        self.quad
+    }
+
+    /// Command to read from the memory, with the address still to be filled in.
+    ///
+    /// This is the command to use when mapping the memory.
+    pub fn read_command(&self) -> Command {
+        let mut command = match self.params.quad_read_instruction {
+            Some(instruction) if self.quad => Command {
+                quad_data: true,
+                ..Command::new(instruction)
+            },
+            _ => Command::new(CMD_FAST_READ),
+        };
+        command.four_byte_address = self.params.four_byte_address;
+        command.dummy = true;
+        command
+    }
+
+    /// Get a mutable reference to the bus.
+    pub fn bus(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// Release the bus.
+    pub fn release(self) -> B {
+        self.bus
+    }
+
+    /// Read data.
+    pub async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error<B::Error>> {
+        self.check_bounds(offset, bytes.len())?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let command = Command {
+            address: Some(offset),
+            ..self.read_command()
+        };
+        self.bus.read(&command, bytes).await.map_err(Error::Bus)
+    }
+
+    /// Program data, which must be in erased sectors.
+    pub async fn write(&mut self, mut offset: u32, mut bytes: &[u8]) -> Result<(), Error<B::Error>> {
+        self.check_bounds(offset, bytes.len())?;
+
+        // Page program wraps around within the page, so split at page boundaries.
+        while !bytes.is_empty() {
+            let page_left = self.params.page_size - offset % self.params.page_size;
+            let (chunk, rest) = bytes.split_at(bytes.len().min(page_left as usize));
+
+            self.command(CMD_WRITE_ENABLE).await?;
+            let command = Command {
+                address: Some(offset),
+                four_byte_address: self.params.four_byte_address,
+                ..Command::new(CMD_PAGE_PROGRAM)
+            };
+            self.bus.write(&command, chunk).await.map_err(Error::Bus)?;
+            self.wait_ready().await?;
+
+            offset += chunk.len() as u32;
+            bytes = rest;
+        }
+        Ok(())
+    }
+
+    /// Erase the sectors from `from` to `to`, which must be multiples of [`ERASE_SIZE`].
+    pub async fn erase(&mut self, from: u32, to: u32) -> Result<(), Error<B::Error>> {
+        if from > to || to > self.params.size {
+            return Err(Error::OutOfBounds);
+        }
+        if from as usize % ERASE_SIZE != 0 || to as usize % ERASE_SIZE != 0 {
+            return Err(Error::NotAligned);
+        }
+
+        for address in (from..to).step_by(ERASE_SIZE) {
+            self.command(CMD_WRITE_ENABLE).await?;
+            let command = Command {
+                address: Some(address),
+                four_byte_address: self.params.four_byte_address,
+                ..Command::new(self.params.erase_instruction)
+            };
+            self.bus.command(&command).await.map_err(Error::Bus)?;
+            self.wait_ready().await?;
+        }
+        Ok(())
+    }
+
+    fn check_bounds(&self, offset: u32, len: usize) -> Result<(), Error<B::Error>> {
+        match (offset as usize).checked_add(len) {
+            Some(end) if end <= self.params.size as usize => Ok(()),
+            _ => Err(Error::OutOfBounds),
+        }
+    }
+
+    async fn enable_quad(&mut self) -> Result<(), Error<B::Error>> {
+        match self.params.quad_enable {
+            QuadEnable::None => return Ok(()),
+            QuadEnable::Status2Bit1 => {
+                let sr1 = self.read_register(CMD_READ_STATUS1).await?;
+                let sr2 = self.read_register(CMD_READ_STATUS2).await?;
+                if sr2 & 0x02 != 0 {
+                    return Ok(());
+                }
+                self.write_registers(CMD_WRITE_STATUS1, &[sr1, sr2 | 0x02]).await?;
+            }
+            QuadEnable::Status1Bit6 => {
+                let sr1 = self.read_register(CMD_READ_STATUS1).await?;
+                if sr1 & 0x40 != 0 {
+                    return Ok(());
+                }
+                self.write_registers(CMD_WRITE_STATUS1, &[sr1 | 0x40]).await?;
+            }
+            QuadEnable::Status2Bit7 => {
+                let sr2 = self.read_register(CMD_READ_STATUS2_ALT).await?;
+                if sr2 & 0x80 != 0 {
+                    return Ok(());
+                }
+                self.write_registers(CMD_WRITE_STATUS2_ALT, &[sr2 | 0x80]).await?;
+            }
+            QuadEnable::Status2Bit1Separate => {
+                let sr2 = self.read_register(CMD_READ_STATUS2).await?;
+                if sr2 & 0x02 != 0 {
+                    return Ok(());
+                }
+                self.write_registers(CMD_WRITE_STATUS2, &[sr2 | 0x02]).await?;
+            }
+        }
+        self.wait_ready().await
+    }
+
+    async fn command(&mut self, instruction: u8) -> Result<(), Error<B::Error>> {
+        self.bus.command(&Command::new(instruction)).await.map_err(Error::Bus)
+    }
+
+    async fn read_register(&mut self, instruction: u8) -> Result<u8, Error<B::Error>> {
+        let mut value = [0];
+        self.bus
+            .read(&Command::new(instruction), &mut value)
+            .await
+            .map_err(Error::Bus)?;
+        Ok(value[0])
+    }
+
+    async fn write_registers(&mut self, instruction: u8, values: &[u8]) -> Result<(), Error<B::Error>> {
+        self.command(CMD_WRITE_ENABLE).await?;
+        self.bus
+            .write(&Command::new(instruction), values)
+            .await
+            .map_err(Error::Bus)
+    }
+
+    async fn wait_ready(&mut self) -> Result<(), Error<B::Error>> {
+        while self.read_register(CMD_READ_STATUS1).await? & STATUS_WIP != 0 {
+            embassy_futures::yield_now().await;
+        }
+        Ok(())
+    }
+}
+
+async fn read_sfdp<B: NorBus>(bus: &mut B, offset: u32, buf: &mut [u8]) -> Result<(), Error<B::Error>> {
+    // SFDP is always read with a 3-byte address and 8 dummy clocks.
+    let command = Command {
+        address: Some(offset),
+        dummy: true,
+        ..Command::new(CMD_READ_SFDP)
+    };
+    bus.read(&command, buf).await.map_err(Error::Bus)
+}
+
+impl<B: NorBus> embedded_storage_async::nor_flash::ErrorType for SpiNorFlash<B> {
+    type Error = Error<B::Error>;
+}
+
+impl<B: NorBus> embedded_storage_async::nor_flash::ReadNorFlash for SpiNorFlash<B> {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.read(offset, bytes).await
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<B: NorBus> embedded_storage_async::nor_flash::NorFlash for SpiNorFlash<B> {
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.write(offset, bytes).await
+    }
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.erase(from, to).await
+    }
+}
+
+/// [`NorBus`] over a SPI device, single lane only.
+///
+/// The SPI device must be in mode 0 or 3.
+pub struct SpiNorBus<SPI> {
+    spi: SPI,
+}
+
+impl<SPI> SpiNorBus<SPI> {
+    /// Create a new SPI flash bus.
+    pub fn new(spi: SPI) -> Self {
+        Self { spi }
+    }
+
+    /// Release the SPI device.
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+impl<SPI: embedded_hal_async::spi::SpiDevice> SpiNorBus<SPI> {
+    async fn transaction(&mut self, command: &Command, data: Operation<'_, u8>) -> Result<(), SPI::Error> {
+        assert!(!command.quad_data);
+
+        let mut header = [0; 6];
+        header[0] = command.instruction;
+        let mut len = 1;
+        if let Some(address) = command.address {
+            let bytes = address.to_be_bytes();
+            match command.four_byte_address {
+                true => header[1..5].copy_from_slice(&bytes),
+                false => header[1..4].copy_from_slice(&bytes[1..]),
+            }
+            len += if command.four_byte_address { 4 } else { 3 };
+        }
+        if command.dummy {
+            len += 1;
+        }
+
+        self.spi
+            .transaction(&mut [Operation::Write(&header[..len]), data])
+            .await
+    }
+}
+
+impl<SPI: embedded_hal_async::spi::SpiDevice> NorBus for SpiNorBus<SPI> {
+    type Error = SPI::Error;
+    const QUAD: bool = false;
+
+    async fn command(&mut self, command: &Command) -> Result<(), SPI::Error> {
+        self.transaction(command, Operation::Write(&[])).await
+    }
+
+    async fn read(&mut self, command: &Command, buf: &mut [u8]) -> Result<(), SPI::Error> {
+        self.transaction(command, Operation::Read(buf)).await
+    }
+
+    async fn write(&mut self, command: &Command, buf: &[u8]) -> Result<(), SPI::Error> {
+        self.transaction(command, Operation::Write(buf)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Basic flash parameter table of a 16 MB memory with 1-1-4 fast read (6Bh) and the
+    /// quad enable bit in status register 2.
+    fn bfpt_16mb() -> [u32; 16] {
+        let mut bfpt = [0xFFFF_FFFF; 16];
+        bfpt[0] = 0xFFF9_20E5;
+        bfpt[1] = 0x07FF_FFFF;
+        bfpt[2] = 0x6B08_EB44;
+        bfpt[10] = 0x0000_0080;
+        bfpt[14] = 0x0040_0000;
+        bfpt[15] = 0x0000_0000;
+        bfpt
+    }
+
+    #[test]
+    fn parse_16mb() {
+        let params = Parameters::parse(&bfpt_16mb(), 16).unwrap();
+        assert_eq!(params.size, 16 * 1024 * 1024);
+        assert_eq!(params.page_size, 256);
+        assert_eq!(params.erase_instruction, 0x20);
+        assert!(!params.four_byte_address);
+        assert!(!params.enter_4byte);
+        assert_eq!(params.quad_read_instruction, Some(0x6B));
+        assert_eq!(params.quad_enable, QuadEnable::Status2Bit1);
+    }
+
+    #[test]
+    fn parse_jesd216_defaults() {
+        // Without DWORD 11 and 15, the page size defaults to 256 bytes and there's no quad enable.
+        let mut bfpt = bfpt_16mb();
+        bfpt[10] = 0x0000_0050;
+        let params = Parameters::parse(&bfpt, 9).unwrap();
+        assert_eq!(params.page_size, 256);
+        assert_eq!(params.quad_enable, QuadEnable::None);
+    }
+
+    #[test]
+    fn parse_large_density() {
+        // 2^28 bits, 3 or 4-byte addressing: switched to 4-byte mode with B7h, after write enable.
+        let mut bfpt = bfpt_16mb();
+        bfpt[0] = (bfpt[0] & !(0b11 << 17)) | (0b01 << 17);
+        bfpt[1] = 0x8000_0000 | 28;
+        bfpt[15] = 1 << 25;
+        let params = Parameters::parse(&bfpt, 16).unwrap();
+        assert_eq!(params.size, 32 * 1024 * 1024);
+        assert!(params.four_byte_address);
+        assert!(params.enter_4byte);
+        assert!(params.enter_4byte_needs_wren);
+
+        // B7h alone is preferred when supported.
+        bfpt[15] = 0b11 << 24;
+        assert!(!Parameters::parse(&bfpt, 16).unwrap().enter_4byte_needs_wren);
+
+        // 4 GB and more can't be addressed.
+        bfpt[1] = 0x8000_0000 | 35;
+        assert!(Parameters::parse(&bfpt, 16).is_none());
+    }
+
+    #[test]
+    fn parse_quad_read_needs_8_dummy_clocks() {
+        let mut bfpt = bfpt_16mb();
+        bfpt[2] = 0x6B06_EB44;
+        assert_eq!(Parameters::parse(&bfpt, 16).unwrap().quad_read_instruction, None);
+
+        bfpt[2] = 0x6B08_EB44;
+        bfpt[0] &= !(1 << 22);
+        assert_eq!(Parameters::parse(&bfpt, 16).unwrap().quad_read_instruction, None);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let bfpt = bfpt_16mb();
+        assert!(Parameters::parse(&bfpt, 8).is_none());
+
+        // No 4 kB erase.
+        let mut no_4k = bfpt;
+        no_4k[0] |= 0b11;
+        assert!(Parameters::parse(&no_4k, 16).is_none());
+
+        // Reserved address bytes value.
+        let mut bad_address = bfpt;
+        bad_address[0] |= 0b11 << 17;
+        assert!(Parameters::parse(&bad_address, 16).is_none());
+
+        // Reserved quad enable requirement.
+        let mut bad_qer = bfpt;
+        bad_qer[14] = 0b111 << 20;
+        assert!(Parameters::parse(&bad_qer, 16).is_none());
+    }
+}
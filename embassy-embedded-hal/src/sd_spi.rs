@@ -0,0 +1,441 @@
+//! SD card over SPI.
+//!
+//! For chips without an SD/MMC peripheral, or boards that route the card to a SPI bus.
+//! [`SdSpi`] is [initialized](SdSpi::init_card) once, then read and written in 512-byte
+//! [`Block`]s through the [`BlockDevice`] trait, like the HAL SD/MMC drivers.
+//!
+//! SD, SDHC and SDXC cards are supported. SPI mode is slower than the 4-bit SD bus, and is limited
+//! to 25 MHz.
+//!
+//! The driver takes the SPI bus and the chip select pin rather than a `SpiDevice`: the card needs
+//! clocks with chip select high, both at power up and after each command to release MISO, and
+//! chip select must stay low while polling for a response. The bus therefore can't be shared.
+
+use embedded_hal_1::digital::OutputPin;
+use embedded_hal_async::spi::SpiBus;
+use sdio_host::{CardCapacity, CID, CSD, OCR};
+
+use crate::block_device::{Block, BlockDevice, BLOCK_SIZE};
+use crate::SetConfig;
+
+/// Number of polls of the card before giving up on a response or the end of a busy period.
+///
+/// At the 400 kHz initialization clock, this is over 1 second.
+const POLL_ATTEMPTS: u32 = 50_000;
+
+/// Start block token for single block reads and writes.
+const TOKEN_START_BLOCK: u8 = 0xFE;
+
+/// R1 response bits.
+const R1_IDLE: u8 = 0x01;
+const R1_ILLEGAL_COMMAND: u8 = 0x04;
+const R1_CRC_ERROR: u8 = 0x08;
+
+/// Errors
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The card didn't respond, or stayed busy for too long.
+    Timeout,
+    /// Unsupported card version.
+    UnsupportedCardVersion,
+    /// CRC error.
+    Crc,
+    /// No card inserted, or [`init_card`](SdSpi::init_card) hasn't succeeded.
+    NoCard,
+    /// The card rejected a command, with the given R1 response.
+    Command(u8),
+    /// The card rejected written data, with the given data response token.
+    Write(u8),
+    /// SPI error.
+    Spi(E),
+    /// The SPI bus configuration couldn't be set.
+    Config,
+    /// The chip select pin couldn't be set.
+    ChipSelect,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Self::Spi(e)
+    }
+}
+
+/// SD card over SPI configuration.
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Enable CRC checking of commands and data, which is optional in SPI mode.
+    pub crc: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { crc: true }
+    }
+}
+
+/// Registers of an initialized card.
+#[derive(Clone, Copy)]
+struct Card {
+    card_type: CardCapacity,
+    cid: CID,
+    csd: CSD,
+}
+
+/// SD card over SPI driver.
+pub struct SdSpi<SPI, CS> {
+    spi: SPI,
+    cs: CS,
+    config: Config,
+    card: Option<Card>,
+}
+
+impl<SPI, CS> SdSpi<SPI, CS>
+where
+    SPI: SpiBus + SetConfig,
+    CS: OutputPin,
+{
+    /// Create a new driver. The SPI bus must be in mode 0, and MISO should have a pull-up.
+    pub fn new(spi: SPI, cs: CS, config: Config) -> Self {
+        Self {
+            spi,
+            cs,
+            config,
+            card: None,
+        }
+    }
+
+    /// Initialize the card, if present.
+    ///
+    /// `init_config` is applied to the bus during initialization, and must set a clock between
+    /// 100 and 400 kHz. `config` is applied afterwards, for clocks up to 25 MHz.
+    pub async fn init_card(
+        &mut self,
+        init_config: &SPI::Config,
+        config: &SPI::Config,
+    ) -> Result<(), Error<SPI::Error>> {
+        self.card = None;
+        self.spi.set_config(init_config).map_err(|_| Error::Config)?;
+        self.cs.set_high().map_err(|_| Error::ChipSelect)?;
+
+        // At least 74 clocks with CS high, for the card to enter native mode.
+        self.spi.write(&[0xFFu8; 10]).await?;
+
+        // CMD0 with CS low switches to SPI mode.
+        let mut attempts = 0;
+        loop {
+            match self.command(0, 0).await {
+                Ok(R1_IDLE) => break,
+                Ok(_) | Err(Error::Timeout) if attempts < 10 => attempts += 1,
+                Ok(_) => return Err(Error::NoCard),
+                Err(e) => return Err(e),
+            }
+        }
+
+        // CMD8: SEND_IF_COND, 2.7-3.6 V and check pattern. Version 1 cards don't know it.
+        let mut r7 = [0; 4];
+        let v2 = match self.command_with_response(8, 0x1AA, &mut r7).await? {
+            R1_IDLE => {
+                if r7[2] & 0x0F != 0x01 || r7[3] != 0xAA {
+                    return Err(Error::UnsupportedCardVersion);
+                }
+                true
+            }
+            r1 if r1 & R1_ILLEGAL_COMMAND != 0 => false,
+            r1 => return Err(Error::Command(r1)),
+        };
+
+        // CMD59: CRC_ON_OFF
+        check_r1(self.command(59, self.config.crc as u32).await?)?;
+
+        // ACMD41: SD_SEND_OP_COND, announcing high capacity support to version 2 cards.
+        let hcs = if v2 { 1 << 30 } else { 0 };
+        let mut attempts = 0;
+        loop {
+            check_r1(self.command(55, 0).await?)?;
+            match self.command(41, hcs).await? {
+                0 => break,
+                R1_IDLE if attempts < POLL_ATTEMPTS / 10 => attempts += 1,
+                R1_IDLE => return Err(Error::Timeout),
+                r1 => return Err(Error::Command(r1)),
+            }
+        }
+
+        // CMD58: READ_OCR, whose CCS bit tells byte (SDSC) from block addressing.
+        let mut ocr = [0; 4];
+        check_r1(self.command_with_response(58, 0, &mut ocr).await?)?;
+        let ocr: OCR = u32::from_be_bytes(ocr).into();
+        let card_type = match v2 && ocr.high_capacity() {
+            true => CardCapacity::SDHC,
+            false => CardCapacity::SDSC,
+        };
+
+        // CMD9: SEND_CSD, CMD10: SEND_CID
+        let mut csd = [0; 16];
+        self.read_command(9, 0, &mut csd).await?;
+        let mut cid = [0; 16];
+        self.read_command(10, 0, &mut cid).await?;
+
+        // CMD16: SET_BLOCKLEN, which is fixed at 512 on high capacity cards.
+        if matches!(card_type, CardCapacity::SDSC) {
+            check_r1(self.command(16, BLOCK_SIZE as u32).await?)?;
+        }
+
+        self.spi.set_config(config).map_err(|_| Error::Config)?;
+        self.card = Some(Card {
+            card_type,
+            cid: u128::from_be_bytes(cid).into(),
+            csd: u128::from_be_bytes(csd).into(),
+        });
+        Ok(())
+    }
+
+    /// Card identification register of the initialized card.
+    pub fn cid(&self) -> Result<CID, Error<SPI::Error>> {
+        Ok(self.card()?.cid)
+    }
+
+    /// Card specific data register of the initialized card.
+    pub fn csd(&self) -> Result<CSD, Error<SPI::Error>> {
+        Ok(self.card()?.csd)
+    }
+
+    /// Release the SPI bus and chip select pin.
+    pub fn release(self) -> (SPI, CS) {
+        (self.spi, self.cs)
+    }
+
+    fn card(&self) -> Result<&Card, Error<SPI::Error>> {
+        self.card.as_ref().ok_or(Error::NoCard)
+    }
+
+    fn address(&self, block_idx: u32) -> Result<u32, Error<SPI::Error>> {
+        // SDSC cards are byte addressed hence the blockaddress is in multiples of 512 bytes
+        Ok(match self.card()?.card_type {
+            CardCapacity::SDSC => block_idx * BLOCK_SIZE as u32,
+            _ => block_idx,
+        })
+    }
+
+    /// Send a command, and return its R1 response.
+    async fn command(&mut self, cmd: u8, arg: u32) -> Result<u8, Error<SPI::Error>> {
+        self.command_with_response(cmd, arg, &mut []).await
+    }
+
+    /// Send a command, and return its R1 response, reading the rest of the response to `rest`.
+    async fn command_with_response(&mut self, cmd: u8, arg: u32, rest: &mut [u8]) -> Result<u8, Error<SPI::Error>> {
+        self.select()?;
+        let result = self.command_inner(cmd, arg, rest).await;
+        self.deselect().await?;
+        result
+    }
+
+    /// Send a command followed by a data block from the card, read to `buf`.
+    async fn read_command(&mut self, cmd: u8, arg: u32, buf: &mut [u8]) -> Result<(), Error<SPI::Error>> {
+        self.select()?;
+        let result = match self.command_inner(cmd, arg, &mut []).await {
+            Ok(r1) => match check_r1(r1) {
+                Ok(()) => self.read_data(buf).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        self.deselect().await?;
+        result
+    }
+
+    fn select(&mut self) -> Result<(), Error<SPI::Error>> {
+        self.cs.set_low().map_err(|_| Error::ChipSelect)
+    }
+
+    async fn deselect(&mut self) -> Result<(), Error<SPI::Error>> {
+        self.cs.set_high().map_err(|_| Error::ChipSelect)?;
+        // One more byte for the card to release MISO.
+        self.spi.write(&[0xFFu8]).await?;
+        Ok(())
+    }
+
+    async fn command_inner(&mut self, cmd: u8, arg: u32, rest: &mut [u8]) -> Result<u8, Error<SPI::Error>> {
+        if cmd != 0 {
+            self.wait_not_busy().await?;
+        }
+
+        let mut frame = [0; 6];
+        frame[0] = 0x40 | cmd;
+        frame[1..5].copy_from_slice(&arg.to_be_bytes());
+        frame[5] = crc7(&frame[..5]) << 1 | 1;
+        self.spi.write(&frame).await?;
+
+        // The response comes after 0 to 8 bytes.
+        let mut r1 = 0xFF;
+        for _ in 0..10 {
+            r1 = self.read_byte().await?;
+            if r1 & 0x80 == 0 {
+                break;
+            }
+        }
+        if r1 & 0x80 != 0 {
+            return Err(Error::Timeout);
+        }
+
+        if !rest.is_empty() {
+            rest.fill(0xFF);
+            self.spi.transfer_in_place(rest).await?;
+        }
+        Ok(r1)
+    }
+
+    /// Read a data block following a command, checking its CRC.
+    async fn read_data(&mut self, buf: &mut [u8]) -> Result<(), Error<SPI::Error>> {
+        let mut attempts = 0;
+        loop {
+            match self.read_byte().await? {
+                TOKEN_START_BLOCK => break,
+                0xFF if attempts < POLL_ATTEMPTS => attempts += 1,
+                0xFF => return Err(Error::Timeout),
+                // Data error token.
+                token => return Err(Error::Command(token)),
+            }
+        }
+
+        // MOSI must stay high while reading.
+        buf.fill(0xFF);
+        self.spi.transfer_in_place(buf).await?;
+        let mut crc = [0xFF; 2];
+        self.spi.transfer_in_place(&mut crc).await?;
+
+        if self.config.crc && u16::from_be_bytes(crc) != crc16(buf) {
+            return Err(Error::Crc);
+        }
+        Ok(())
+    }
+
+    async fn write_data(&mut self, buf: &[u8], crc: u16) -> Result<(), Error<SPI::Error>> {
+        self.spi.write(&[0xFF, TOKEN_START_BLOCK]).await?;
+        self.spi.write(buf).await?;
+        self.spi.write(&crc.to_be_bytes()).await?;
+
+        let response = self.read_byte().await? & 0x1F;
+        match response {
+            0x05 => {}
+            0x0B => return Err(Error::Crc),
+            _ => return Err(Error::Write(response)),
+        }
+        self.wait_not_busy().await
+    }
+
+    async fn wait_not_busy(&mut self) -> Result<(), Error<SPI::Error>> {
+        for _ in 0..POLL_ATTEMPTS {
+            if self.read_byte().await? == 0xFF {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    async fn read_byte(&mut self) -> Result<u8, Error<SPI::Error>> {
+        let mut byte = [0xFFu8];
+        self.spi.transfer_in_place(&mut byte).await?;
+        Ok(byte[0])
+    }
+}
+
+impl<SPI, CS> BlockDevice for SdSpi<SPI, CS>
+where
+    SPI: SpiBus + SetConfig,
+    CS: OutputPin,
+{
+    type Error = Error<SPI::Error>;
+
+    async fn read_block(&mut self, block_idx: u32, buffer: &mut Block) -> Result<(), Self::Error> {
+        let address = self.address(block_idx)?;
+
+        // CMD17: READ_SINGLE_BLOCK
+        self.read_command(17, address, &mut buffer.0).await
+    }
+
+    async fn write_block(&mut self, block_idx: u32, buffer: &Block) -> Result<(), Self::Error> {
+        let address = self.address(block_idx)?;
+
+        let crc = match self.config.crc {
+            true => crc16(&buffer.0),
+            false => 0xFFFF,
+        };
+
+        // CMD24: WRITE_BLOCK
+        self.select()?;
+        let result = match self.command_inner(24, address, &mut []).await {
+            Ok(r1) => match check_r1(r1) {
+                Ok(()) => self.write_data(&buffer.0, crc).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        self.deselect().await?;
+        result
+    }
+
+    fn block_count(&self) -> Result<u32, Self::Error> {
+        Ok(self.card()?.csd.block_count() as u32)
+    }
+}
+
+fn check_r1<E>(r1: u8) -> Result<(), Error<E>> {
+    match r1 & !R1_IDLE {
+        0 => Ok(()),
+        R1_CRC_ERROR => Err(Error::Crc),
+        _ => Err(Error::Command(r1)),
+    }
+}
+
+/// CRC7 of a command, polynomial x^7 + x^3 + 1.
+fn crc7(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        for i in (0..8).rev() {
+            let bit = ((byte >> i) & 1) ^ ((crc >> 6) & 1);
+            crc = (crc << 1) & 0x7F;
+            if bit != 0 {
+                crc ^= 0x09;
+            }
+        }
+    }
+    crc
+}
+
+/// CRC16 of a data block, polynomial x^16 + x^12 + x^5 + 1 (CCITT), initial value 0.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_crc7() {
+        // CMD0 and CMD8 frames from the SD specification.
+        assert_eq!(crc7(&[0x40, 0, 0, 0, 0]) << 1 | 1, 0x95);
+        assert_eq!(crc7(&[0x48, 0, 0, 0x01, 0xAA]) << 1 | 1, 0x87);
+    }
+
+    #[test]
+    fn data_crc16() {
+        // 512 bytes of 0xFF, from the SD specification.
+        assert_eq!(crc16(&[0xFF; 512]), 0x7FA1);
+    }
+}
@@ -98,6 +98,9 @@ mod thread {
         ///
         /// This function never returns.
         pub fn run(&'static mut self, init: impl FnOnce(Spawner)) -> ! {
+            #[cfg(feature = "stack-watermark")]
+            super::stack::paint();
+
             init(self.inner.spawner());
 
             loop {
@@ -229,3 +232,64 @@ mod interrupt {
         }
     }
 }
+
+/// Main stack usage measurement.
+///
+/// Tasks don't have stacks of their own: all tasks of the thread executor, and of interrupt
+/// executors, run on the main stack, so its size is what has to be budgeted. The unused part of
+/// the main stack is painted with a pattern, by `Executor::run` or [`paint`], and
+/// [`stack_high_watermark`] finds how much of it has been overwritten since.
+///
+/// The stack spans from `_stack_end` to `_stack_start`, both provided by `cortex-m-rt` 0.7.4 and
+/// later. `_stack_end` defaults to the end of the statics; a heap placed after them, at `__sheap`,
+/// must move it up by defining `_stack_end` in `memory.x`, or painting overwrites the heap.
+#[cfg(feature = "stack-watermark")]
+pub mod stack {
+    /// Pattern the unused stack is painted with.
+    const PAINT: u32 = 0xCCCC_CCCC;
+
+    extern "C" {
+        static _stack_start: u32;
+        static _stack_end: u32;
+    }
+
+    fn top() -> usize {
+        unsafe { core::ptr::addr_of!(_stack_start) as usize }
+    }
+
+    fn bottom() -> usize {
+        unsafe { core::ptr::addr_of!(_stack_end) as usize }
+    }
+
+    /// Paint the unused part of the main stack, below the current stack pointer.
+    ///
+    /// The thread executor calls this when it starts. Calling it again resets the watermark to the
+    /// current stack usage.
+    pub fn paint() {
+        let sp = cortex_m::register::msp::read() as usize;
+        let mut p = bottom() as *mut u32;
+        // Memory below the stack pointer is free, interrupt handlers may only use it temporarily.
+        while (p as usize) + 4 <= sp {
+            unsafe {
+                p.write_volatile(PAINT);
+                p = p.add(1);
+            }
+        }
+    }
+
+    /// Size of the main stack, in bytes.
+    pub fn stack_size() -> usize {
+        top() - bottom()
+    }
+
+    /// Largest main stack usage since it was painted, in bytes.
+    ///
+    /// If this gets close to [`stack_size`], the stack may have overflowed already.
+    pub fn stack_high_watermark() -> usize {
+        let mut p = bottom() as *const u32;
+        while (p as usize) < top() && unsafe { p.read_volatile() } == PAINT {
+            p = unsafe { p.add(1) };
+        }
+        top() - p as usize
+    }
+}
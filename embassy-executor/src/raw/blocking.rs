@@ -0,0 +1,33 @@
+use core::cell::Cell;
+
+use critical_section::Mutex;
+
+use super::TaskRef;
+
+/// Default threshold: 10 ms.
+const DEFAULT_THRESHOLD: u64 = embassy_time_driver::TICK_HZ / 100;
+
+static THRESHOLD: Mutex<Cell<u64>> = Mutex::new(Cell::new(DEFAULT_THRESHOLD));
+
+/// Set how long a task can run in a single poll before a warning is logged, in ticks of the
+/// time driver (`embassy_time_driver::TICK_HZ` per second).
+///
+/// The default is 10 ms. A task that runs that long without yielding keeps all other tasks of
+/// the same executor from running, which is usually caused by a busy-wait or a blocking driver
+/// call in async code.
+pub fn set_blocking_threshold(ticks: u64) {
+    critical_section::with(|cs| THRESHOLD.borrow(cs).set(ticks));
+}
+
+/// Warn if the poll of `task` started at `start` took longer than the threshold.
+pub(super) fn check(task: TaskRef, start: u64) {
+    let elapsed = embassy_time_driver::now().saturating_sub(start);
+    let threshold = critical_section::with(|cs| THRESHOLD.borrow(cs).get());
+    if elapsed > threshold {
+        warn!(
+            "task {:x} blocked the executor for {} ticks",
+            task.as_ptr() as usize,
+            elapsed
+        );
+    }
+}
@@ -16,6 +16,8 @@ mod run_queue;
 #[cfg_attr(not(target_has_atomic = "8"), path = "state_critical_section.rs")]
 mod state;
 
+#[cfg(feature = "blocking-detection")]
+mod blocking;
 #[cfg(feature = "integrated-timers")]
 mod timer_queue;
 pub(crate) mod util;
@@ -37,6 +39,8 @@ use rtos_trace::trace;
 use self::run_queue::{RunQueue, RunQueueItem};
 use self::state::State;
 use self::util::{SyncUnsafeCell, UninitCell};
+#[cfg(feature = "blocking-detection")]
+pub use self::blocking::set_blocking_threshold;
 pub use self::waker::task_from_waker;
 use super::SpawnToken;
 
@@ -401,9 +405,15 @@ impl SyncExecutor {
                 #[cfg(feature = "rtos-trace")]
                 trace::task_exec_begin(p.as_ptr() as u32);
 
+                #[cfg(feature = "blocking-detection")]
+                let poll_start = embassy_time_driver::now();
+
                 // Run the task
                 task.poll_fn.get().unwrap_unchecked()(p);
 
+                #[cfg(feature = "blocking-detection")]
+                blocking::check(p, poll_start);
+
                 #[cfg(feature = "rtos-trace")]
                 trace::task_exec_end();
 
@@ -1,4 +1,5 @@
 #![no_std]
+#![allow(async_fn_in_trait)]
 #![doc = include_str!("../README.md")]
 #![warn(missing_docs)]
 
@@ -10,6 +11,7 @@ mod yield_now;
 
 pub mod join;
 pub mod select;
+pub mod stream;
 
 pub use block_on::*;
 pub use yield_now::*;
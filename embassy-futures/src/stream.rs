@@ -0,0 +1,34 @@
+//! Asynchronous streams of items.
+
+/// An asynchronous source of items, such as the samples of an ADC or the frames received by a
+/// CAN controller.
+///
+/// This is a lightweight alternative to `futures::Stream`: it needs no pinning and no `poll_next`
+/// implementation, only an `async fn`. A stream never ends; errors are reported through the item
+/// type, which is usually a `Result`.
+///
+/// ```rust
+/// # use embassy_futures::stream::Stream;
+/// async fn sum<S: Stream<Item = u16>>(source: &mut S, count: usize) -> u32 {
+///     let mut sum = 0;
+///     for _ in 0..count {
+///         sum += source.next().await as u32;
+///     }
+///     sum
+/// }
+/// ```
+pub trait Stream {
+    /// Type of the items.
+    type Item;
+
+    /// Wait for the next item.
+    async fn next(&mut self) -> Self::Item;
+}
+
+impl<S: Stream + ?Sized> Stream for &mut S {
+    type Item = S::Item;
+
+    async fn next(&mut self) -> Self::Item {
+        S::next(self).await
+    }
+}
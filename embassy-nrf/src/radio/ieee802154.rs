@@ -5,6 +5,8 @@ use core::task::Poll;
 
 use embassy_hal_internal::drop::OnDrop;
 use embassy_hal_internal::{into_ref, PeripheralRef};
+#[cfg(feature = "time")]
+use embassy_time::{with_timeout, Duration, Instant};
 
 use super::{state, Error, Instance, InterruptHandler, RadioState, TxPower};
 use crate::interrupt::typelevel::Interrupt;
@@ -14,6 +16,19 @@ use crate::Peripheral;
 /// Default (IEEE compliant) Start of Frame Delimiter
 pub const DEFAULT_SFD: u8 = 0xA7;
 
+/// Time to wait for an acknowledgment after sending a frame: macAckWaitDuration (54 symbols of
+/// 16 us), plus the receiver ramp-up time
+#[cfg(feature = "time")]
+const ACK_WAIT: Duration = Duration::from_micros(54 * 16 + 140);
+
+/// Frame control field bits
+const FCF_FRAME_TYPE_MASK: u16 = 0b111;
+const FCF_FRAME_TYPE_ACK: u16 = 0b010;
+const FCF_ACK_REQUEST: u16 = 1 << 5;
+const FCF_DST_ADDR_MODE_SHIFT: u16 = 10;
+const ADDR_MODE_SHORT: u16 = 0b10;
+const ADDR_MODE_EXTENDED: u16 = 0b11;
+
 // TODO expose the other variants in `pac::CCAMODE_A`
 /// Clear Channel Assessment method
 pub enum Cca {
@@ -30,10 +45,55 @@ pub enum Cca {
     },
 }
 
+/// Addresses of this device, used to acknowledge the frames sent to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AddressFilter {
+    /// PAN identifier
+    pub pan_id: u16,
+    /// Short address
+    pub short_address: u16,
+    /// Extended address
+    pub extended_address: u64,
+}
+
+impl AddressFilter {
+    /// Whether `frame` is addressed to this device, and not broadcast, so it must be acknowledged
+    /// when it requests it
+    fn matches(&self, frame: &[u8]) -> bool {
+        let Some(fcf) = frame.get(0..2) else {
+            return false;
+        };
+        let fcf = u16::from_le_bytes([fcf[0], fcf[1]]);
+
+        // Destination PAN identifier and address follow the sequence number
+        let Some(pan_id) = frame.get(3..5) else {
+            return false;
+        };
+        let pan_id = u16::from_le_bytes([pan_id[0], pan_id[1]]);
+        if pan_id != self.pan_id && pan_id != 0xFFFF {
+            return false;
+        }
+
+        match (fcf >> FCF_DST_ADDR_MODE_SHIFT) & 0b11 {
+            ADDR_MODE_SHORT => frame
+                .get(5..7)
+                .map_or(false, |a| u16::from_le_bytes([a[0], a[1]]) == self.short_address),
+            ADDR_MODE_EXTENDED => frame
+                .get(5..13)
+                .map_or(false, |a| a == self.extended_address.to_le_bytes()),
+            _ => false,
+        }
+    }
+}
+
 /// IEEE 802.15.4 radio driver.
 pub struct Radio<'d, T: Instance> {
     _p: PeripheralRef<'d, T>,
     needs_enable: bool,
+    auto_ack: Option<AddressFilter>,
+    /// Acknowledgment frame: PHR, frame control field and sequence number
+    ack: [u8; 4],
 }
 
 impl<'d, T: Instance> Radio<'d, T> {
@@ -107,6 +167,8 @@ impl<'d, T: Instance> Radio<'d, T> {
         let mut radio = Self {
             _p: radio,
             needs_enable: false,
+            auto_ack: None,
+            ack: [0; 4],
         };
 
         radio.set_sfd(DEFAULT_SFD);
@@ -144,6 +206,16 @@ impl<'d, T: Instance> Radio<'d, T> {
         }
     }
 
+    /// Acknowledges received frames addressed to `filter` which request it, or disables
+    /// acknowledgments with `None`
+    ///
+    /// The acknowledgment is sent by [`receive`](Self::receive) right after the frame, which must
+    /// be polled promptly to meet the 192 us turnaround time: run it in a task that isn't delayed
+    /// by long-running tasks in the same executor.
+    pub fn set_auto_ack(&mut self, filter: Option<AddressFilter>) {
+        self.auto_ack = filter;
+    }
+
     /// Changes the Start of Frame Delimiter (SFD)
     pub fn set_sfd(&mut self, sfd: u8) {
         let r = T::regs();
@@ -320,6 +392,7 @@ impl<'d, T: Instance> Radio<'d, T> {
         let r = T::regs();
 
         // Start the read
+        r.events_framestart.reset();
         self.receive_start(packet);
 
         let dropper = OnDrop::new(|| Self::receive_cancel());
@@ -334,7 +407,8 @@ impl<'d, T: Instance> Radio<'d, T> {
                 trace!("RX done poll");
                 return Poll::Ready(());
             } else {
-                r.intenset.write(|w| w.phyend().set());
+                // the interrupt handler timestamps FRAMESTART
+                r.intenset.write(|w| w.phyend().set().framestart().set());
             };
 
             Poll::Pending
@@ -344,12 +418,77 @@ impl<'d, T: Instance> Radio<'d, T> {
         dma_end_fence();
         dropper.defuse();
 
+        #[cfg(feature = "time")]
+        {
+            packet.timestamp = Some(Self::framestart_instant());
+        }
+
         let crc = r.rxcrc.read().rxcrc().bits() as u16;
-        if r.crcstatus.read().crcstatus().bit_is_set() {
-            Ok(())
-        } else {
-            Err(Error::CrcFailed(crc))
+        if !r.crcstatus.read().crcstatus().bit_is_set() {
+            return Err(Error::CrcFailed(crc));
         }
+
+        if packet.ack_request() && self.auto_ack.map_or(false, |f| f.matches(packet)) {
+            self.send_ack(packet[2]).await;
+        }
+        Ok(())
+    }
+
+    /// Instant of the last FRAMESTART event, from the low bits of the ticks stored by the
+    /// interrupt handler
+    #[cfg(feature = "time")]
+    fn framestart_instant() -> Instant {
+        let now = Instant::now().as_ticks();
+        let elapsed = (now as u32).wrapping_sub(T::state().framestart.load(Ordering::Relaxed));
+        Instant::from_ticks(now - elapsed as u64)
+    }
+
+    /// Sends an acknowledgment for the frame just received, with sequence number `seq`
+    async fn send_ack(&mut self, seq: u8) {
+        let s = T::state();
+        let r = T::regs();
+
+        self.ack = [3 + Packet::CRC, FCF_FRAME_TYPE_ACK as u8, 0, seq];
+
+        // The radio is in RX_IDLE after the received frame:
+        //
+        // disable → enable TX → ramp up TX → start TX → TX → end (PHYEND) → disabled
+        r.shorts.write(|w| {
+            w.disabled_txen()
+                .enabled()
+                .txready_start()
+                .enabled()
+                .phyend_disable()
+                .enabled()
+        });
+        r.events_phyend.reset();
+        r.packetptr.write(|w| unsafe { w.bits(self.ack.as_ptr() as u32) });
+
+        let dropper = OnDrop::new(|| {
+            r.shorts.reset();
+            r.tasks_disable.write(|w| w.tasks_disable().set_bit());
+        });
+
+        dma_start_fence();
+        r.tasks_disable.write(|w| w.tasks_disable().set_bit());
+
+        self.clear_all_interrupts();
+        core::future::poll_fn(|cx| {
+            s.event_waker.register(cx.waker());
+
+            if r.events_phyend.read().events_phyend().bit_is_set() {
+                r.events_phyend.reset();
+                trace!("ACK done poll");
+                return Poll::Ready(());
+            }
+            r.intenset.write(|w| w.phyend().set());
+
+            Poll::Pending
+        })
+        .await;
+
+        dropper.defuse();
+        r.shorts.reset();
     }
 
     /// Tries to send the given `packet`
@@ -441,6 +580,31 @@ impl<'d, T: Instance> Radio<'d, T> {
             TransmitResult::ChannelInUse => Err(Error::ChannelInUse),
         }
     }
+
+    /// Tries to send the given `packet`, then waits for its acknowledgment if it requests one
+    ///
+    /// Like [`try_send`](Self::try_send), the packet is only sent if the channel is clear. Returns
+    /// [`Error::NoAck`] if no acknowledgment with the packet's sequence number is received within
+    /// macAckWaitDuration. Retransmissions are left to the caller.
+    #[cfg(feature = "time")]
+    pub async fn send_with_ack(&mut self, packet: &mut Packet) -> Result<(), Error> {
+        self.try_send(packet).await?;
+        if !packet.ack_request() {
+            return Ok(());
+        }
+
+        let seq = packet[2];
+        let mut ack = Packet::new();
+        with_timeout(ACK_WAIT, async {
+            loop {
+                if self.receive(&mut ack).await.is_ok() && ack.is_ack_for(seq) {
+                    return;
+                }
+            }
+        })
+        .await
+        .map_err(|_| Error::NoAck)
+    }
 }
 
 /// An IEEE 802.15.4 packet
@@ -456,6 +620,8 @@ impl<'d, T: Instance> Radio<'d, T> {
 /// See figure 119 in the Product Specification of the nRF52840 for more details
 pub struct Packet {
     buffer: [u8; Self::SIZE],
+    #[cfg(feature = "time")]
+    timestamp: Option<Instant>,
 }
 
 // See figure 124 in nRF52840-PS
@@ -474,6 +640,8 @@ impl Packet {
     pub fn new() -> Self {
         let mut packet = Self {
             buffer: [0; Self::SIZE],
+            #[cfg(feature = "time")]
+            timestamp: None,
         };
         packet.set_len(0);
         packet
@@ -518,6 +686,35 @@ impl Packet {
     pub fn lqi(&self) -> u8 {
         self.buffer[1 /* PHY_HDR */ + self.len() as usize /* data */]
     }
+
+    /// Returns the time the received packet started, right after its Start of Frame Delimiter
+    ///
+    /// The timestamp is taken by the RADIO interrupt handler, so its accuracy depends on the
+    /// interrupt latency and on the time driver tick rate. It's `None` until a packet is received.
+    #[cfg(feature = "time")]
+    pub fn timestamp(&self) -> Option<Instant> {
+        self.timestamp
+    }
+
+    fn frame_control(&self) -> Option<u16> {
+        let fcf = self.get(0..2)?;
+        Some(u16::from_le_bytes([fcf[0], fcf[1]]))
+    }
+
+    /// Whether the frame requests an acknowledgment, and has a sequence number
+    fn ack_request(&self) -> bool {
+        self.len() >= 3 && self.frame_control().map_or(false, |fcf| fcf & FCF_ACK_REQUEST != 0)
+    }
+
+    /// Whether the frame acknowledges the frame with sequence number `seq`
+    #[cfg(feature = "time")]
+    fn is_ack_for(&self, seq: u8) -> bool {
+        self.len() >= 3
+            && self
+                .frame_control()
+                .map_or(false, |fcf| fcf & FCF_FRAME_TYPE_MASK == FCF_FRAME_TYPE_ACK)
+            && self[2] == seq
+    }
 }
 
 impl core::ops::Deref for Packet {
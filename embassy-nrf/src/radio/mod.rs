@@ -40,6 +40,8 @@ pub enum Error {
     ChannelInUse,
     /// CRC check failed
     CrcFailed(u16),
+    /// No acknowledgment was received
+    NoAck,
 }
 
 /// Interrupt handler
@@ -51,6 +53,15 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
     unsafe fn on_interrupt() {
         let r = T::regs();
         let s = T::state();
+        // timestamp the start of a frame as close as possible to the event
+        #[cfg(feature = "time")]
+        if r.intenset.read().framestart().is_enabled() && r.events_framestart.read().events_framestart().bit_is_set() {
+            r.events_framestart.reset();
+            s.framestart.store(
+                embassy_time::Instant::now().as_ticks() as u32,
+                core::sync::atomic::Ordering::Relaxed,
+            );
+        }
         // clear all interrupts
         r.intenclr.write(|w| w.bits(0xffff_ffff));
         s.event_waker.wake();
@@ -60,11 +71,16 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
 pub(crate) struct State {
     /// end packet transmission or reception
     event_waker: AtomicWaker,
+    /// low 32 bits of the time driver ticks at the last FRAMESTART event
+    #[cfg(feature = "time")]
+    framestart: core::sync::atomic::AtomicU32,
 }
 impl State {
     pub(crate) const fn new() -> Self {
         Self {
             event_waker: AtomicWaker::new(),
+            #[cfg(feature = "time")]
+            framestart: core::sync::atomic::AtomicU32::new(0),
         }
     }
 }
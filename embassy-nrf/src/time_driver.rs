@@ -14,6 +14,51 @@ fn rtc() -> &'static pac::rtc0::RegisterBlock {
     unsafe { &*pac::RTC1::ptr() }
 }
 
+#[cfg(all(feature = "shared-time-driver", not(feature = "_nrf5340")))]
+compile_error!("shared-time-driver is only supported on the nRF5340.");
+
+// NOTE regarding the shared time driver:
+//
+// Each core of the nRF5340 has its own RTC1, so each runs its own time driver. With
+// `shared-time-driver`, the network core offsets its clock to match the application core's:
+//
+// - At init, the network core reads its clock, signals the application core on IPC channel
+//   `SYNC_REQUEST` and waits for the reply on `SYNC_REPLY`, then reads its clock again.
+// - The application core answers from its IPC interrupt: it writes its current time into
+//   `SHARED`, which lives in the `.shared_time_driver` section in application core RAM, then replies.
+// - The application core read its clock between the network core's two reads, so the offset is
+//   taken from their midpoint. The round trip is well below a tick, and both RTCs run from the
+//   same LFCLK, so the clocks then agree within a tick and don't drift apart.
+
+/// IPC channel the network core requests a time sync on.
+#[cfg(feature = "shared-time-driver")]
+const SYNC_REQUEST: usize = 15;
+/// IPC channel the application core replies on.
+#[cfg(feature = "shared-time-driver")]
+const SYNC_REPLY: usize = 14;
+/// Ticks the network core waits for the sync reply, i.e. one second, before panicking.
+#[cfg(all(feature = "shared-time-driver", feature = "_nrf5340-net"))]
+const SYNC_TIMEOUT_TICKS: u64 = 32768;
+
+#[cfg(feature = "shared-time-driver")]
+struct SharedState {
+    /// Application core time when it answered the last sync request.
+    now_lo: AtomicU32,
+    now_hi: AtomicU32,
+}
+
+#[cfg(feature = "shared-time-driver")]
+#[link_section = ".shared_time_driver"]
+static SHARED: SharedState = SharedState {
+    now_lo: AtomicU32::new(0),
+    now_hi: AtomicU32::new(0),
+};
+
+#[cfg(feature = "shared-time-driver")]
+fn ipc() -> &'static pac::ipc::RegisterBlock {
+    unsafe { &*pac::IPC::ptr() }
+}
+
 /// Calculate the timestamp from the period count and the tick count.
 ///
 /// The RTC counter is 24 bit. Ticking at 32768hz, it overflows every ~8 minutes. This is
@@ -113,6 +158,9 @@ const ALARM_COUNT: usize = 3;
 struct RtcDriver {
     /// Number of 2^23 periods elapsed since boot.
     period: AtomicU32,
+    /// Offset from the RTC to the application core's clock, on the network core with a shared time driver.
+    offset_lo: AtomicU32,
+    offset_hi: AtomicU32,
     alarm_count: AtomicU8,
     /// Timestamp at which to fire alarm. u64::MAX if no alarm is scheduled.
     alarms: Mutex<[AlarmState; ALARM_COUNT]>,
@@ -121,6 +169,8 @@ struct RtcDriver {
 const ALARM_STATE_NEW: AlarmState = AlarmState::new();
 embassy_time_driver::time_driver_impl!(static DRIVER: RtcDriver = RtcDriver {
     period: AtomicU32::new(0),
+    offset_lo: AtomicU32::new(0),
+    offset_hi: AtomicU32::new(0),
     alarm_count: AtomicU8::new(0),
     alarms: Mutex::const_new(CriticalSectionRawMutex::new(), [ALARM_STATE_NEW; ALARM_COUNT]),
 });
@@ -144,6 +194,79 @@ impl RtcDriver {
 
         interrupt::RTC1.set_priority(irq_prio);
         unsafe { interrupt::RTC1.enable() };
+
+        #[cfg(all(feature = "shared-time-driver", feature = "_nrf5340-app"))]
+        {
+            let ipc = ipc();
+            ipc.receive_cnf[SYNC_REQUEST].write(|w| unsafe { w.bits(1 << SYNC_REQUEST) });
+            ipc.send_cnf[SYNC_REPLY].write(|w| unsafe { w.bits(1 << SYNC_REPLY) });
+            ipc.events_receive[SYNC_REQUEST].write(|w| w);
+            ipc.intenset.write(|w| unsafe { w.bits(1 << SYNC_REQUEST) });
+
+            interrupt::IPC.set_priority(irq_prio);
+            unsafe { interrupt::IPC.enable() };
+        }
+
+        #[cfg(all(feature = "shared-time-driver", feature = "_nrf5340-net"))]
+        self.sync();
+    }
+
+    /// Offset the clock to match the application core's.
+    ///
+    /// The application core must have initialized its time driver, panics if it doesn't reply
+    /// within [`SYNC_TIMEOUT_TICKS`].
+    #[cfg(all(feature = "shared-time-driver", feature = "_nrf5340-net"))]
+    fn sync(&self) {
+        let ipc = ipc();
+        ipc.send_cnf[SYNC_REQUEST].write(|w| unsafe { w.bits(1 << SYNC_REQUEST) });
+        ipc.receive_cnf[SYNC_REPLY].write(|w| unsafe { w.bits(1 << SYNC_REPLY) });
+        ipc.events_receive[SYNC_REPLY].write(|w| w);
+
+        let before = self.local_now();
+        ipc.tasks_send[SYNC_REQUEST].write(|w| unsafe { w.bits(1) });
+        while ipc.events_receive[SYNC_REPLY].read().bits() == 0 {
+            if self.local_now() - before > SYNC_TIMEOUT_TICKS {
+                panic!("time sync: no reply from the application core, is its time driver running?");
+            }
+        }
+        let after = self.local_now();
+        ipc.events_receive[SYNC_REPLY].write(|w| w);
+        compiler_fence(Ordering::Acquire);
+
+        let remote =
+            ((SHARED.now_hi.load(Ordering::Relaxed) as u64) << 32) | SHARED.now_lo.load(Ordering::Relaxed) as u64;
+        // The application core read its clock somewhere between `before` and `after`.
+        let offset = remote.wrapping_sub(before + (after - before) / 2);
+        self.offset_lo.store(offset as u32, Ordering::Relaxed);
+        self.offset_hi.store((offset >> 32) as u32, Ordering::Relaxed);
+    }
+
+    /// Answer a sync request from the network core.
+    #[cfg(all(feature = "shared-time-driver", feature = "_nrf5340-app"))]
+    fn on_ipc_interrupt(&self) {
+        let ipc = ipc();
+        if ipc.events_receive[SYNC_REQUEST].read().bits() == 1 {
+            ipc.events_receive[SYNC_REQUEST].write(|w| w);
+
+            let now = self.local_now();
+            SHARED.now_lo.store(now as u32, Ordering::Relaxed);
+            SHARED.now_hi.store((now >> 32) as u32, Ordering::Relaxed);
+            compiler_fence(Ordering::Release);
+            ipc.tasks_send[SYNC_REPLY].write(|w| unsafe { w.bits(1) });
+        }
+    }
+
+    fn offset(&self) -> u64 {
+        ((self.offset_hi.load(Ordering::Relaxed) as u64) << 32) | self.offset_lo.load(Ordering::Relaxed) as u64
+    }
+
+    /// Time of the RTC, without the offset to the other core's clock.
+    fn local_now(&self) -> u64 {
+        // `period` MUST be read before `counter`, see comment at the top for details.
+        let period = self.period.load(Ordering::Relaxed);
+        compiler_fence(Ordering::Acquire);
+        let counter = rtc().counter.read().bits();
+        calc_now(period, counter)
     }
 
     fn on_interrupt(&self) {
@@ -212,11 +335,7 @@ impl RtcDriver {
 
 impl Driver for RtcDriver {
     fn now(&self) -> u64 {
-        // `period` MUST be read before `counter`, see comment at the top for details.
-        let period = self.period.load(Ordering::Relaxed);
-        compiler_fence(Ordering::Acquire);
-        let counter = rtc().counter.read().bits();
-        calc_now(period, counter)
+        self.local_now().wrapping_add(self.offset())
     }
 
     unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
@@ -241,6 +360,13 @@ impl Driver for RtcDriver {
     }
 
     fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) -> bool {
+        // Alarms are kept in RTC time, with the same wrapping arithmetic as `now`. `u64::MAX` means
+        // no alarm, and must stay out of reach whatever the offset.
+        let timestamp = match timestamp {
+            u64::MAX => u64::MAX,
+            t => t.wrapping_sub(self.offset()),
+        };
+
         critical_section::with(|cs| {
             let n = alarm.id() as _;
             let alarm = self.get_alarm(cs, alarm);
@@ -248,7 +374,7 @@ impl Driver for RtcDriver {
 
             let r = rtc();
 
-            let t = self.now();
+            let t = self.local_now();
             if timestamp <= t {
                 // If alarm timestamp has passed the alarm will not fire.
                 // Disarm the alarm and return `false` to indicate that.
@@ -299,6 +425,12 @@ fn RTC1() {
     DRIVER.on_interrupt()
 }
 
+#[cfg(all(feature = "rt", feature = "shared-time-driver", feature = "_nrf5340-app"))]
+#[interrupt]
+fn IPC() {
+    DRIVER.on_ipc_interrupt()
+}
+
 pub(crate) fn init(irq_prio: crate::interrupt::Priority) {
     DRIVER.init(irq_prio)
 }
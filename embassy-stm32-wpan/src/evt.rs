@@ -2,7 +2,8 @@ use core::marker::PhantomData;
 use core::{ptr, slice};
 
 use super::PacketHeader;
-use crate::consts::TL_EVT_HEADER_SIZE;
+use crate::cmd::AclDataSerialStub;
+use crate::consts::{TlPacketType, TL_EVT_HEADER_SIZE};
 
 /**
  * The payload of `Evt` for a command status event
@@ -137,7 +138,13 @@ impl<T: MemoryManager> EvtBox<T> {
             let evt_serial: *const EvtSerial = &(*self.ptr).evt_serial;
             let evt_serial_buf: *const u8 = evt_serial.cast();
 
-            let len = (*evt_serial).evt.payload_len as usize + TL_EVT_HEADER_SIZE;
+            let len = if (*evt_serial).kind == TlPacketType::AclData as u8 {
+                // ACL data packets have a 16-bit length, after the connection handle
+                let acl_serial: *const AclDataSerialStub = evt_serial.cast();
+                (*acl_serial).length as usize + core::mem::size_of::<AclDataSerialStub>()
+            } else {
+                (*evt_serial).evt.payload_len as usize + TL_EVT_HEADER_SIZE
+            };
 
             slice::from_raw_parts(evt_serial_buf, len)
         }
@@ -1,9 +1,10 @@
 use core::ptr;
 
+use embassy_embedded_hal::hci::{PacketKind, Transport};
 use embassy_stm32::ipcc::Ipcc;
 use hci::Opcode;
 
-use crate::cmd::CmdPacket;
+use crate::cmd::{AclDataPacket, CmdPacket};
 use crate::consts::{TlPacketType, TL_BLEEVT_CC_OPCODE, TL_BLEEVT_CS_OPCODE};
 use crate::evt::{EvtBox, EvtPacket, EvtStub};
 use crate::sub::mm;
@@ -53,7 +54,7 @@ impl Ble {
     /// `TL_BLE_SendAclData`
     pub async fn acl_write(&self, handle: u16, payload: &[u8]) {
         Ipcc::send(channels::cpu1::IPCC_HCI_ACL_DATA_CHANNEL, || unsafe {
-            CmdPacket::write_into(
+            AclDataPacket::write_into(
                 HCI_ACL_DATA_BUFFER.as_mut_ptr() as *mut _,
                 TlPacketType::AclData,
                 handle,
@@ -93,3 +94,51 @@ impl hci::Controller for Ble {
         buf[..evt_serial.len()].copy_from_slice(evt_serial);
     }
 }
+
+/// HCI transport error of [`Ble`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TransportError {
+    /// The packet doesn't fit in the buffer. It was discarded.
+    BufferTooSmall,
+    /// The packet is malformed, or of a kind CPU2 doesn't exchange.
+    InvalidPacket,
+}
+
+/// HCI transport over IPCC, for host stacks running on CPU1 with the BLE HCI layer firmware on
+/// CPU2.
+impl Transport for Ble {
+    type Error = TransportError;
+
+    async fn read(&self, buf: &mut [u8]) -> Result<(PacketKind, usize), Self::Error> {
+        let evt_box = self.tl_read().await;
+        let (indicator, packet) = evt_box.serial().split_first().ok_or(TransportError::InvalidPacket)?;
+        let kind = PacketKind::from_indicator(*indicator).ok_or(TransportError::InvalidPacket)?;
+
+        let buf = buf.get_mut(..packet.len()).ok_or(TransportError::BufferTooSmall)?;
+        buf.copy_from_slice(packet);
+        Ok((kind, packet.len()))
+    }
+
+    async fn write(&self, kind: PacketKind, packet: &[u8]) -> Result<(), Self::Error> {
+        if packet.len() < kind.header_len() || packet.len() != kind.header_len() + kind.payload_len(packet) {
+            return Err(TransportError::InvalidPacket);
+        }
+
+        match kind {
+            PacketKind::Command => {
+                let opcode = u16::from_le_bytes([packet[0], packet[1]]);
+                self.tl_write(opcode, &packet[3..]).await;
+            }
+            PacketKind::AclData => {
+                let handle = u16::from_le_bytes([packet[0], packet[1]]);
+                if packet.len() > 4 + 251 {
+                    return Err(TransportError::InvalidPacket);
+                }
+                self.acl_write(handle, &packet[4..]).await;
+            }
+            _ => return Err(TransportError::InvalidPacket),
+        }
+        Ok(())
+    }
+}
@@ -195,6 +195,13 @@ fn main() {
         Err(GetOneError::Multiple) => panic!("Multiple stm32xx Cargo features enabled"),
     };
 
+    // CM4 is the second core on H7 dual-core chips (next to the CM7), CM0+ on WL dual-core chips
+    // (next to the CM4).
+    let is_second_core =
+        chip_name.ends_with("_cm0p") || (chip_name.starts_with("stm32h7") && chip_name.ends_with("_cm4"));
+    // With a shared time driver, both cores use the same timer, which needs CC2 and CC3 for their alarms.
+    let shared_time_driver = env::var("CARGO_FEATURE_SHARED_TIME_DRIVER").is_ok();
+
     let time_driver_singleton = match time_driver.as_ref().map(|x| x.as_ref()) {
         None => "",
         Some("tim1") => "TIM1",
@@ -223,7 +230,12 @@ fn main() {
                 "TIM20", "TIM8", "TIM1", //ADV
             ]
             .iter()
-            .find(|tim| singletons.contains(&tim.to_string())).expect("time-driver-any requested, but the chip doesn't have TIM1, TIM2, TIM3, TIM4, TIM5, TIM8, TIM9, TIM12, TIM15, TIM20, TIM21, TIM22, TIM23 or TIM24.")
+            .filter(|tim| singletons.contains(&tim.to_string()))
+            .filter(|tim| !shared_time_driver || !["TIM22", "TIM21", "TIM12", "TIM9", "TIM15"].contains(tim))
+            // Unless they share one, each core of a dual-core chip runs its own time driver. Make
+            // the second core pick a different timer than the first, so both can use time-driver-any.
+            .nth(if is_second_core && !shared_time_driver { 1 } else { 0 })
+            .expect("time-driver-any requested, but the chip doesn't have TIM1, TIM2, TIM3, TIM4, TIM5, TIM8, TIM9, TIM12, TIM15, TIM20, TIM21, TIM22, TIM23 or TIM24.")
         }
         _ => panic!("unknown time_driver {:?}", time_driver),
     };
@@ -231,6 +243,10 @@ fn main() {
     if !time_driver_singleton.is_empty() {
         cfgs.enable(format!("time_driver_{}", time_driver_singleton.to_lowercase()));
     }
+    if is_second_core {
+        cfgs.enable("time_driver_second_core");
+    }
+    cfgs.declare("time_driver_second_core");
     for tim in [
         "tim1", "tim2", "tim3", "tim4", "tim5", "tim8", "tim9", "tim12", "tim15", "tim20", "tim21", "tim22", "tim23",
         "tim24",
@@ -788,6 +804,15 @@ fn main() {
         (("sai", "MCLK_A"), quote!(crate::sai::MclkPin<A>)),
         (("sai", "MCLK_B"), quote!(crate::sai::MclkPin<B>)),
         (("sai", "WS"), quote!(crate::sai::WsPin)),
+        (("dfsdm", "CKOUT"), quote!(crate::dfsdm::CkOutPin)),
+        (("dfsdm", "DATIN0"), quote!(crate::dfsdm::DatinPin<Ch0>)),
+        (("dfsdm", "DATIN1"), quote!(crate::dfsdm::DatinPin<Ch1>)),
+        (("dfsdm", "DATIN2"), quote!(crate::dfsdm::DatinPin<Ch2>)),
+        (("dfsdm", "DATIN3"), quote!(crate::dfsdm::DatinPin<Ch3>)),
+        (("dfsdm", "DATIN4"), quote!(crate::dfsdm::DatinPin<Ch4>)),
+        (("dfsdm", "DATIN5"), quote!(crate::dfsdm::DatinPin<Ch5>)),
+        (("dfsdm", "DATIN6"), quote!(crate::dfsdm::DatinPin<Ch6>)),
+        (("dfsdm", "DATIN7"), quote!(crate::dfsdm::DatinPin<Ch7>)),
         (("spi", "SCK"), quote!(crate::spi::SckPin)),
         (("spi", "MOSI"), quote!(crate::spi::MosiPin)),
         (("spi", "MISO"), quote!(crate::spi::MisoPin)),
@@ -797,6 +822,7 @@ fn main() {
         (("spi", "I2S_WS"), quote!(crate::spi::WsPin)),
         (("i2c", "SDA"), quote!(crate::i2c::SdaPin)),
         (("i2c", "SCL"), quote!(crate::i2c::SclPin)),
+        (("i2c", "SMBA"), quote!(crate::i2c::SmbaPin)),
         (("rcc", "MCO_1"), quote!(crate::rcc::McoPin)),
         (("rcc", "MCO_2"), quote!(crate::rcc::McoPin)),
         (("rcc", "MCO"), quote!(crate::rcc::McoPin)),
@@ -1099,7 +1125,23 @@ fn main() {
 
                     g.extend(quote! {
                         pin_trait_impl!(#tr, #peri, #pin_name, #af);
-                    })
+                    });
+
+                    // FMC address lines also implement `AddressPin`, which tells which line they are.
+                    if regs.kind == "fmc" {
+                        if let Some(line) = pin.signal.strip_prefix('A').and_then(|n| n.parse::<u8>().ok()) {
+                            g.extend(quote! {
+                                impl crate::fmc::AddressPin<crate::peripherals::#peri> for crate::peripherals::#pin_name {
+                                    fn af_num(&self) -> u8 {
+                                        #af
+                                    }
+                                    fn address_line(&self) -> u8 {
+                                        #line
+                                    }
+                                }
+                            });
+                        }
+                    }
                 }
 
                 // ADC is special
@@ -1186,6 +1228,10 @@ fn main() {
         (("lpuart", "TX"), quote!(crate::usart::TxDma)),
         (("sai", "A"), quote!(crate::sai::Dma<A>)),
         (("sai", "B"), quote!(crate::sai::Dma<B>)),
+        (("dfsdm", "FLT0"), quote!(crate::dfsdm::Dma<Flt0>)),
+        (("dfsdm", "FLT1"), quote!(crate::dfsdm::Dma<Flt1>)),
+        (("dfsdm", "FLT2"), quote!(crate::dfsdm::Dma<Flt2>)),
+        (("dfsdm", "FLT3"), quote!(crate::dfsdm::Dma<Flt3>)),
         (("spi", "RX"), quote!(crate::spi::RxDma)),
         (("spi", "TX"), quote!(crate::spi::TxDma)),
         (("i2c", "RX"), quote!(crate::i2c::RxDma)),
@@ -1196,6 +1242,10 @@ fn main() {
         (("sdmmc", "RX"), quote!(crate::sdmmc::SdmmcDma)),
         (("quadspi", "QUADSPI"), quote!(crate::qspi::QuadDma)),
         (("octospi", "OCTOSPI1"), quote!(crate::ospi::OctoDma)),
+        (("adc", "ADC"), quote!(crate::adc::RxDma)),
+        (("adc", "ADC1"), quote!(crate::adc::RxDma)),
+        (("adc", "ADC2"), quote!(crate::adc::RxDma)),
+        (("adc", "ADC3"), quote!(crate::adc::RxDma)),
         (("dac", "CH1"), quote!(crate::dac::DacDma1)),
         (("dac", "CH2"), quote!(crate::dac::DacDma2)),
         (("timer", "UP"), quote!(crate::timer::UpDma)),
@@ -1561,9 +1611,11 @@ fn main() {
                 #[cfg(feature = "rt")]
                 #[crate::interrupt]
                 unsafe fn #irq () {
+                    let guard = crate::_private::IsrGuard::new();
                     #(
                         <crate::peripherals::#channels as crate::dma::ChannelInterrupt>::on_irq();
                     )*
+                    guard.finish();
                 }
             }
         })
@@ -1617,7 +1669,7 @@ fn main() {
         let mut s = chip_name.split('_');
         let mut chip_name: String = s.next().unwrap().to_string();
         let core_name = if let Some(c) = s.next() {
-            if !c.starts_with("CM") {
+            if !c.starts_with("cm") {
                 chip_name.push('_');
                 chip_name.push_str(c);
                 None
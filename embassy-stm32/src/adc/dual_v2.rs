@@ -0,0 +1,158 @@
+use embassy_hal_internal::drop::OnDrop;
+use embassy_hal_internal::into_ref;
+
+use crate::adc::{Adc, AnyAdcChannel, Instance, RxDma, SampleTime};
+use crate::dma::Transfer;
+use crate::Peripheral;
+
+/// ADC_CCR fields.
+const CCR_MULTI_MASK: u32 = 0b1_1111;
+const CCR_DELAY_SHIFT: u32 = 8;
+const CCR_DELAY_MASK: u32 = 0b1111 << CCR_DELAY_SHIFT;
+const CCR_DDS: u32 = 1 << 13;
+const CCR_DMA_MASK: u32 = 0b11 << 14;
+/// DMA mode 2: each request transfers the two results as one word.
+const CCR_DMA_MODE2: u32 = 0b10 << 14;
+
+/// ADC_CCR MULTI values.
+const MULTI_INDEPENDENT: u32 = 0b0_0000;
+const MULTI_DUAL_SIMULTANEOUS: u32 = 0b0_0110;
+const MULTI_DUAL_INTERLEAVED: u32 = 0b0_0111;
+
+/// Offset of ADC_CDR, the common data register, in words.
+const CDR_OFFSET: usize = 2;
+
+/// How the two ADCs of a [`DualAdc`] sample.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DualMode {
+    /// Both ADCs convert their sequence at the same instant, to sample two signals in sync.
+    ///
+    /// The sequences must have the same length, and each pair of channels should have the same
+    /// sample time.
+    Simultaneous,
+    /// Both ADCs convert the same channel, the slave `delay` ADC clock cycles after the master,
+    /// to sample one signal at twice the rate of one ADC.
+    ///
+    /// `delay` is from 5 to 20 cycles. With a 12-bit resolution and the shortest sample time, a
+    /// conversion takes 15 cycles, so a delay of 7 or 8 cycles gives evenly spaced samples.
+    Interleaved {
+        /// Delay between the two conversions, in ADC clock cycles.
+        delay: u8,
+    },
+}
+
+/// The master and slave ADCs (ADC1 and ADC2) sampling together, in dual mode.
+///
+/// Each DMA transfer carries a pair of results: the master's in the lower half-word, and the
+/// slave's in the upper one.
+pub struct DualAdc<'d, M: Instance, S: Instance> {
+    master: Adc<'d, M>,
+    slave: Adc<'d, S>,
+}
+
+impl<'d, M: Instance, S: Instance> DualAdc<'d, M, S> {
+    /// Pair ADC1, the master, with ADC2, the slave.
+    pub fn new(master: Adc<'d, M>, slave: Adc<'d, S>) -> Self {
+        let master_base = M::regs().as_ptr() as usize;
+        assert_eq!(
+            master_base,
+            crate::pac::ADC1.as_ptr() as usize,
+            "the master must be ADC1"
+        );
+        assert_eq!(
+            S::regs().as_ptr() as usize,
+            master_base + 0x100,
+            "the slave must be ADC2"
+        );
+
+        Self { master, slave }
+    }
+
+    /// Split back into the two ADCs.
+    pub fn split(self) -> (Adc<'d, M>, Adc<'d, S>) {
+        (self.master, self.slave)
+    }
+
+    /// Convert in dual `mode` until `readings` is full, with DMA.
+    ///
+    /// The master converts `master_channels` and the slave `slave_channels`, over and over.
+    /// Each reading holds the master result in its lower 16 bits and the slave result in its
+    /// upper 16 bits: with [`DualMode::Simultaneous`], readings follow the sequences, and with
+    /// [`DualMode::Interleaved`], the master result of a reading was sampled before the slave one.
+    pub async fn read(
+        &mut self,
+        dma: impl Peripheral<P = impl RxDma<M>>,
+        mode: DualMode,
+        master_channels: &[(&AnyAdcChannel<M>, SampleTime)],
+        slave_channels: &[(&AnyAdcChannel<S>, SampleTime)],
+        readings: &mut [u32],
+    ) {
+        assert!(
+            !master_channels.is_empty() && master_channels.len() <= 16,
+            "1 to 16 channels can be converted"
+        );
+        assert_eq!(
+            master_channels.len(),
+            slave_channels.len(),
+            "both ADCs must convert as many channels"
+        );
+        let multi = match mode {
+            DualMode::Simultaneous => MULTI_DUAL_SIMULTANEOUS,
+            DualMode::Interleaved { delay } => {
+                assert!(
+                    (5..=20).contains(&delay),
+                    "the interleaved delay is from 5 to 20 cycles"
+                );
+                assert_eq!(master_channels.len(), 1, "interleaved mode converts a single channel");
+                MULTI_DUAL_INTERLEAVED | ((delay as u32 - 5) << CCR_DELAY_SHIFT)
+            }
+        };
+        into_ref!(dma);
+
+        Adc::<M>::set_sequence(master_channels);
+        Adc::<S>::set_sequence(slave_channels);
+
+        for r in [M::regs(), S::regs()] {
+            r.sr().modify(|reg| {
+                reg.set_ovr(false);
+                reg.set_eoc(false);
+                reg.set_strt(false);
+            });
+            r.cr1().modify(|reg| reg.set_scan(true));
+            r.cr2().modify(|reg| {
+                reg.set_cont(true);
+                // The common DMA mode serves both ADCs
+                reg.set_dma(false);
+            });
+        }
+
+        let ccr = M::common_regs();
+        ccr.ccr().modify(|reg| {
+            reg.0 &= !(CCR_MULTI_MASK | CCR_DELAY_MASK | CCR_DDS | CCR_DMA_MASK);
+            reg.0 |= multi | CCR_DMA_MODE2;
+        });
+
+        // Go back to independent mode, even if the future is dropped.
+        let _cleanup = OnDrop::new(|| {
+            ccr.ccr().modify(|reg| {
+                reg.0 &= !(CCR_MULTI_MASK | CCR_DELAY_MASK | CCR_DMA_MASK);
+                reg.0 |= MULTI_INDEPENDENT;
+            });
+            for r in [M::regs(), S::regs()] {
+                r.cr2().modify(|reg| reg.set_cont(false));
+                r.cr1().modify(|reg| reg.set_scan(false));
+                r.sqr1().modify(|reg| reg.set_l(0));
+                r.sr().modify(|reg| reg.set_ovr(false));
+            }
+        });
+
+        let request = dma.request();
+        let cdr = unsafe { (ccr.as_ptr() as *mut u32).add(CDR_OFFSET) };
+        let transfer = unsafe { Transfer::new_read(dma, request, cdr, readings, Default::default()) };
+
+        // The master starts both ADCs.
+        M::regs().cr2().modify(|reg| reg.set_swstart(true));
+        transfer.await;
+    }
+}
@@ -30,6 +30,30 @@ const TEMP_CHANNEL: u8 = 18;
 // TODO this should be 14 for H7a/b/35
 const VBAT_CHANNEL: u8 = 17;
 
+/// ADC calibration factors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Calibration {
+    /// Calibration factor for single-ended inputs (7 bits).
+    pub single_ended: u8,
+    /// Calibration factor for differential inputs (7 bits).
+    pub differential: u8,
+}
+
+/// Hardware oversampling configuration.
+///
+/// `2^ratio_log2` conversions are summed, then shifted right by `shift` bits. A 256x sum of 12-bit
+/// conversions is 20 bits wide, but the data register only keeps the low 16 bits, so `shift` must
+/// be at least `ratio_log2 - 4` at 12-bit resolution. `shift == ratio_log2` averages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Oversampling {
+    /// Base 2 logarithm of the number of accumulated conversions, from 1 (2x) to 8 (256x).
+    pub ratio_log2: u8,
+    /// Right shift applied to the accumulated result, from 0 to 8 bits.
+    pub shift: u8,
+}
+
 // NOTE: Vrefint/Temperature/Vbat are not available on all ADCs, this currently cannot be modeled with stm32-data, so these are available from the software on all ADCs
 /// Internal voltage reference channel.
 pub struct VrefInt;
@@ -150,7 +174,7 @@ impl<'d, T: Instance> Adc<'d, T> {
         s.power_up();
         s.configure_differential_inputs();
 
-        s.calibrate();
+        s.run_calibration();
         blocking_delay_us(1);
 
         s.enable();
@@ -159,6 +183,89 @@ impl<'d, T: Instance> Adc<'d, T> {
         s
     }
 
+    /// Re-run the ADC self-calibration, for both single-ended and differential inputs.
+    ///
+    /// The calibration is done on creation, running it again after a significant temperature or
+    /// supply voltage change improves accuracy. The ADC is disabled while calibrating.
+    pub fn calibrate(&mut self) -> Calibration {
+        self.disable();
+        self.run_calibration();
+        blocking_delay_us(1);
+        self.enable();
+
+        self.calibration()
+    }
+
+    /// Get the current calibration factors.
+    pub fn calibration(&self) -> Calibration {
+        let calfact = T::regs().calfact().read();
+        Calibration {
+            single_ended: calfact.calfact_s(),
+            differential: calfact.calfact_d(),
+        }
+    }
+
+    /// Set the calibration factors, e.g. restoring ones saved by a previous [`calibrate`](Self::calibrate).
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        T::regs().calfact().write(|w| {
+            w.set_calfact_s(calibration.single_ended & 0x7f);
+            w.set_calfact_d(calibration.differential & 0x7f);
+        });
+    }
+
+    /// Set user offset `index` (0..=3), subtracted from every conversion of `channel`.
+    ///
+    /// Results below the offset saturate at 0.
+    pub fn set_offset(&mut self, index: usize, channel: &impl AdcChannel<T>, offset: u16) {
+        assert!(index < 4);
+        assert!(offset <= 0xfff);
+
+        T::regs().ofr(index).write(|w| {
+            w.set_offset_ch(channel.channel());
+            w.set_offset(offset);
+            w.set_offsetpos(false);
+            w.set_saten(true);
+            w.set_offset_en(true);
+        });
+    }
+
+    /// Disable user offset `index` (0..=3).
+    pub fn clear_offset(&mut self, index: usize) {
+        assert!(index < 4);
+
+        T::regs().ofr(index).write(|_| {});
+    }
+
+    /// Make `positive` a differential input, with `negative` as its negative input.
+    ///
+    /// `negative` must be the channel following `positive`, and can't be converted on its own while
+    /// `positive` is differential. Conversions of `positive` then measure the voltage between
+    /// the two inputs, as offset binary: mid-scale (2048 at 12 bits) is 0 V. The ADC is disabled
+    /// meanwhile.
+    pub fn set_differential(&mut self, positive: &mut impl AdcChannel<T>, negative: &mut impl AdcChannel<T>) {
+        assert_eq!(
+            negative.channel(),
+            positive.channel() + 1,
+            "the negative input must be the next channel"
+        );
+        positive.setup();
+        negative.setup();
+
+        self.set_difsel(positive.channel(), Difsel::DIFFERENTIAL);
+    }
+
+    /// Make `channel` a single-ended input again, see [`set_differential`](Self::set_differential).
+    pub fn set_single_ended(&mut self, channel: &impl AdcChannel<T>) {
+        self.set_difsel(channel.channel(), Difsel::SINGLEENDED);
+    }
+
+    /// DIFSEL can only be written while the ADC is disabled.
+    fn set_difsel(&mut self, channel: u8, difsel: Difsel) {
+        self.disable();
+        T::regs().difsel().modify(|w| w.set_difsel(channel as _, difsel));
+        self.enable();
+    }
+
     fn power_up(&mut self) {
         T::regs().cr().modify(|reg| {
             reg.set_deeppwd(false);
@@ -176,14 +283,23 @@ impl<'d, T: Instance> Adc<'d, T> {
         });
     }
 
-    fn calibrate(&mut self) {
-        T::regs().cr().modify(|w| {
-            w.set_adcaldif(Adcaldif::SINGLEENDED);
-        });
+    fn run_calibration(&mut self) {
+        for adcaldif in [Adcaldif::SINGLEENDED, Adcaldif::DIFFERENTIAL] {
+            T::regs().cr().modify(|w| {
+                w.set_adcaldif(adcaldif);
+            });
+
+            T::regs().cr().modify(|w| w.set_adcal(true));
 
-        T::regs().cr().modify(|w| w.set_adcal(true));
+            while T::regs().cr().read().adcal() {}
+        }
+    }
 
-        while T::regs().cr().read().adcal() {}
+    fn disable(&mut self) {
+        if T::regs().cr().read().aden() {
+            T::regs().cr().modify(|w| w.set_addis(true));
+            while T::regs().cr().read().aden() {}
+        }
     }
 
     fn enable(&mut self) {
@@ -238,6 +354,24 @@ impl<'d, T: Instance> Adc<'d, T> {
         T::regs().cfgr().modify(|reg| reg.set_res(resolution.into()));
     }
 
+    /// Enable or disable the hardware oversampler for regular conversions.
+    ///
+    /// [`read`](Self::read) then returns the shifted sum instead of a single conversion.
+    pub fn set_oversampling(&mut self, oversampling: Option<Oversampling>) {
+        if let Some(o) = oversampling {
+            assert!(o.ratio_log2 >= 1 && o.ratio_log2 <= 8);
+            assert!(o.shift <= 8);
+        }
+
+        T::regs().cfgr2().modify(|reg| {
+            reg.set_rovse(oversampling.is_some());
+            if let Some(o) = oversampling {
+                reg.set_ovsr(o.ratio_log2 - 1);
+                reg.set_ovss(o.shift);
+            }
+        });
+    }
+
     /// Perform a single conversion.
     fn convert(&mut self) -> u16 {
         T::regs().isr().modify(|reg| {
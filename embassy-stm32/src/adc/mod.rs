@@ -15,13 +15,22 @@
 #[cfg_attr(adc_g4, path = "g4.rs")]
 mod _version;
 
+#[cfg(adc_v2)]
+mod dual_v2;
+#[cfg(adc_v2)]
+mod ringbuffered_v2;
+
 use core::marker::PhantomData;
 
 #[allow(unused)]
 #[cfg(not(adc_f3_v2))]
 pub use _version::*;
-#[cfg(any(adc_f1, adc_f3, adc_v1, adc_l0, adc_f3_v1_1))]
+#[cfg(adc_v2)]
+pub use dual_v2::*;
+#[cfg(any(adc_f1, adc_f3, adc_v1, adc_l0, adc_v2, adc_f3_v1_1))]
 use embassy_sync::waitqueue::AtomicWaker;
+#[cfg(adc_v2)]
+pub use ringbuffered_v2::*;
 
 #[cfg(not(any(adc_f1, adc_f3_v2)))]
 pub use crate::pac::adc::vals::Res as Resolution;
@@ -36,12 +45,21 @@ pub struct Adc<'d, T: Instance> {
     sample_time: SampleTime,
 }
 
-#[cfg(any(adc_f1, adc_f3, adc_v1, adc_l0, adc_f3_v1_1))]
+#[cfg(not(adc_f3_v2))]
+impl<'d, T: Instance> Adc<'d, T> {
+    /// The conversion data register, as the source of a
+    /// [peripheral-to-peripheral](crate::dma::Transfer::new_peripheral_to_peripheral) DMA transfer.
+    pub fn data_register(&mut self) -> crate::dma::PeripheralRegister<'_, u16> {
+        unsafe { crate::dma::PeripheralRegister::new(T::regs().dr().as_ptr() as *mut u16) }
+    }
+}
+
+#[cfg(any(adc_f1, adc_f3, adc_v1, adc_l0, adc_v2, adc_f3_v1_1))]
 pub struct State {
     pub waker: AtomicWaker,
 }
 
-#[cfg(any(adc_f1, adc_f3, adc_v1, adc_l0, adc_f3_v1_1))]
+#[cfg(any(adc_f1, adc_f3, adc_v1, adc_l0, adc_v2, adc_f3_v1_1))]
 impl State {
     pub const fn new() -> Self {
         Self {
@@ -56,7 +74,7 @@ trait SealedInstance {
     #[cfg(not(any(adc_f1, adc_v1, adc_l0, adc_f3_v2, adc_f3_v1_1, adc_g0)))]
     #[allow(unused)]
     fn common_regs() -> crate::pac::adccommon::AdcCommon;
-    #[cfg(any(adc_f1, adc_f3, adc_v1, adc_l0, adc_f3_v1_1))]
+    #[cfg(any(adc_f1, adc_f3, adc_v1, adc_l0, adc_v2, adc_f3_v1_1))]
     fn state() -> &'static State;
 }
 
@@ -121,6 +139,8 @@ pub trait Instance: SealedInstance + crate::Peripheral<P = Self> + crate::rcc::R
     type Interrupt: crate::interrupt::typelevel::Interrupt;
 }
 
+dma_trait!(RxDma, Instance);
+
 /// ADC channel.
 #[allow(private_bounds)]
 pub trait AdcChannel<T>: SealedAdcChannel<T> + Sized {
@@ -164,7 +184,7 @@ foreach_adc!(
                 return crate::pac::$common_inst
             }
 
-            #[cfg(any(adc_f1, adc_f3, adc_v1, adc_l0, adc_f3_v1_1))]
+            #[cfg(any(adc_f1, adc_f3, adc_v1, adc_l0, adc_v2, adc_f3_v1_1))]
             fn state() -> &'static State {
                 static STATE: State = State::new();
                 &STATE
@@ -0,0 +1,141 @@
+use core::marker::PhantomData;
+
+use embassy_hal_internal::into_ref;
+
+use crate::adc::{Adc, AnyAdcChannel, Instance, RxDma, SampleTime};
+use crate::dma::{ReadableRingBuffer, TransferOptions};
+use crate::Peripheral;
+
+/// Samples were lost, because they weren't read from the ring buffer in time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OverrunError;
+
+/// Continuous conversion of a sequence of channels into a DMA ring buffer.
+///
+/// Created with [`Adc::read_continuous`]. The ADC converts the channels one after the other, back
+/// to back, and DMA writes the samples into the ring buffer in the background. Conversion stops
+/// when this is dropped.
+pub struct ContinuousAdc<'a, T: Instance> {
+    _phantom: PhantomData<&'a mut T>,
+    ring_buf: ReadableRingBuffer<'a, u16>,
+}
+
+impl<'d, T: Instance> Adc<'d, T> {
+    /// Start converting `channels` continuously into `dma_buf`, in scan and continuous mode with
+    /// circular DMA.
+    ///
+    /// Samples are interleaved in the order of `channels`: with channels `[a, b]`, `dma_buf` holds
+    /// `a, b, a, b, ...`. Its length must be a multiple of the number of channels, and is
+    /// typically twice the length read at once with [`ContinuousAdc::read`], so that one half
+    /// is read while DMA fills the other.
+    ///
+    /// The sample rate is set by the ADC clock and the sample time of each channel, which is
+    /// `sample_time` plus 12 cycles for a 12-bit conversion.
+    pub fn read_continuous<'a>(
+        &'a mut self,
+        dma: impl Peripheral<P = impl RxDma<T>> + 'a,
+        channels: &[(&AnyAdcChannel<T>, SampleTime)],
+        dma_buf: &'a mut [u16],
+    ) -> ContinuousAdc<'a, T> {
+        assert!(!channels.is_empty() && channels.len() <= 16);
+        assert!(!dma_buf.is_empty() && dma_buf.len() % channels.len() == 0 && dma_buf.len() <= 0xFFFF);
+        into_ref!(dma);
+
+        let r = T::regs();
+
+        Self::set_sequence(channels);
+
+        let request = dma.request();
+        let opts = TransferOptions {
+            half_transfer_ir: true,
+            ..Default::default()
+        };
+        let mut ring_buf = unsafe { ReadableRingBuffer::new(dma, request, r.dr().as_ptr() as *mut u16, dma_buf, opts) };
+        ring_buf.start();
+
+        r.sr().modify(|w| {
+            w.set_ovr(false);
+            w.set_eoc(false);
+            w.set_strt(false);
+        });
+        r.cr1().modify(|w| w.set_scan(true));
+        r.cr2().modify(|w| {
+            w.set_cont(true);
+            w.set_dma(true);
+            // Keep issuing DMA requests after the last transfer, for circular DMA
+            w.set_dds(true);
+        });
+        r.cr2().modify(|w| w.set_swstart(true));
+
+        ContinuousAdc {
+            _phantom: PhantomData,
+            ring_buf,
+        }
+    }
+}
+
+impl<'a, T: Instance> ContinuousAdc<'a, T> {
+    /// Read exactly `buf.len()` samples, waiting until they're converted.
+    ///
+    /// DMA wakes the task each time half of the ring buffer is filled, so reading half of it at
+    /// a time yields each half as soon as it's full.
+    ///
+    /// If samples weren't read in time, the unread samples are discarded and [`OverrunError`] is
+    /// returned. Reading again continues with the newest samples, which may start at any
+    /// channel of the sequence: [`position`](Self::position) modulo the number of channels gives
+    /// the channel of the next sample.
+    pub async fn read(&mut self, buf: &mut [u16]) -> Result<usize, OverrunError> {
+        self.check_overrun()?;
+        match self.ring_buf.read_exact(buf).await {
+            Ok(remaining) => {
+                self.check_overrun()?;
+                Ok(remaining)
+            }
+            Err(_) => {
+                self.ring_buf.clear();
+                Err(OverrunError)
+            }
+        }
+    }
+
+    /// The capacity of the ring buffer, in samples.
+    pub const fn capacity(&self) -> usize {
+        self.ring_buf.capacity()
+    }
+
+    /// The total number of samples converted, wrapping around at `usize::MAX`.
+    pub fn position(&self) -> usize {
+        self.ring_buf.get_position()
+    }
+
+    /// The ADC stops requesting DMA transfers when a conversion isn't read before the next one
+    /// ends, so restart it.
+    fn check_overrun(&mut self) -> Result<(), OverrunError> {
+        let r = T::regs();
+        if !r.sr().read().ovr() {
+            return Ok(());
+        }
+
+        r.cr2().modify(|w| w.set_dma(false));
+        r.sr().modify(|w| w.set_ovr(false));
+        self.ring_buf.clear();
+        r.cr2().modify(|w| w.set_dma(true));
+        r.cr2().modify(|w| w.set_swstart(true));
+        Err(OverrunError)
+    }
+}
+
+impl<'a, T: Instance> Drop for ContinuousAdc<'a, T> {
+    fn drop(&mut self) {
+        let r = T::regs();
+        r.cr2().modify(|w| {
+            w.set_cont(false);
+            w.set_dma(false);
+            w.set_dds(false);
+        });
+        r.cr1().modify(|w| w.set_scan(false));
+        // Leave the sequence as `Adc::read` expects it, with a single conversion
+        r.sqr1().modify(|w| w.set_l(0));
+    }
+}
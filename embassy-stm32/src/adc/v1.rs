@@ -32,6 +32,21 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
     }
 }
 
+/// Hardware oversampling configuration, only available on STM32L0.
+///
+/// `2^ratio_log2` conversions, from 2 to 256, are summed and shifted right by `shift` bits, from 0
+/// to 8. Only the low 16 bits of the shifted sum are kept: at 12-bit resolution, ratios above 16x
+/// need a shift of at least `ratio_log2 - 4`.
+#[cfg(adc_l0)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Oversampling {
+    /// Base 2 logarithm of the number of accumulated conversions, from 1 (2x) to 8 (256x).
+    pub ratio_log2: u8,
+    /// Right shift applied to the accumulated result, from 0 to 8 bits.
+    pub shift: u8,
+}
+
 #[cfg(not(adc_l0))]
 pub struct Vbat;
 
@@ -149,6 +164,25 @@ impl<'d, T: Instance> Adc<'d, T> {
         T::regs().cfgr1().modify(|reg| reg.set_res(resolution.into()));
     }
 
+    /// Enable or disable the hardware oversampler.
+    ///
+    /// Each [`read`](Self::read) then takes `2^ratio_log2` conversions, so it takes that many times longer.
+    #[cfg(adc_l0)]
+    pub fn set_oversampling(&mut self, oversampling: Option<Oversampling>) {
+        if let Some(o) = oversampling {
+            assert!(o.ratio_log2 >= 1 && o.ratio_log2 <= 8);
+            assert!(o.shift <= 8);
+        }
+
+        T::regs().cfgr2().modify(|reg| {
+            reg.set_ovse(oversampling.is_some());
+            if let Some(o) = oversampling {
+                reg.set_ovsr(o.ratio_log2 - 1);
+                reg.set_ovss(o.shift);
+            }
+        });
+    }
+
     #[cfg(adc_l0)]
     pub fn set_ckmode(&mut self, ckmode: Ckmode) {
         // set ADC clock mode
@@ -1,16 +1,139 @@
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy_hal_internal::drop::OnDrop;
 use embassy_hal_internal::into_ref;
 
 use super::blocking_delay_us;
-use crate::adc::{Adc, AdcChannel, Instance, Resolution, SampleTime};
+use crate::adc::{Adc, AdcChannel, AnyAdcChannel, Instance, Resolution, RxDma, SampleTime};
+use crate::dma::Transfer;
+use crate::interrupt::typelevel::Interrupt;
+use crate::pac::adc::vals::Jexten;
 use crate::peripherals::ADC1;
 use crate::time::Hertz;
-use crate::{rcc, Peripheral};
+use crate::{interrupt, rcc, Peripheral};
+
+/// Interrupt handler, needed for injected conversions and the analog watchdog.
+pub struct InterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        let r = T::regs();
+        let (cr1, sr) = (r.cr1().read(), r.sr().read());
+        let injected = cr1.jeocie() && sr.jeoc();
+        let watchdog = cr1.awdie() && sr.awd();
+        if !injected && !watchdog {
+            return;
+        }
+
+        r.cr1().modify(|w| {
+            if injected {
+                w.set_jeocie(false);
+            }
+            if watchdog {
+                w.set_awdie(false);
+            }
+        });
+        T::state().waker.wake();
+    }
+}
+
+/// Edge of the external trigger that starts a conversion.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TriggerEdge {
+    /// Rising edge.
+    Rising,
+    /// Falling edge.
+    Falling,
+    /// Rising and falling edges.
+    Both,
+}
+
+/// Trigger of the injected sequence, see [`Adc::configure_injected`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InjectedTrigger {
+    /// Started by [`Adc::read_injected`].
+    Software,
+    /// Started by hardware, on an edge of an external event.
+    External {
+        /// Event, the `JEXTSEL` value from the reference manual: a timer event or EXTI line 15.
+        source: u8,
+        /// Edge of the event.
+        edge: TriggerEdge,
+    },
+}
+
+/// A sample taken on an external trigger.
+#[cfg(all(feature = "exti", feature = "time"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TriggeredSample {
+    /// Conversion result.
+    pub value: u16,
+    /// Time the trigger was handled.
+    ///
+    /// The conversion itself is started by hardware on the edge, but this is taken when the
+    /// waiting task resumes, so it's late by the interrupt and executor latency.
+    pub timestamp: embassy_time::Instant,
+}
+
+/// Stream of samples taken on an external trigger, see [`Adc::triggered_samples`].
+#[cfg(all(feature = "exti", feature = "time"))]
+pub struct TriggeredSamples<'a, 'd, 'e, T: Instance, C: AdcChannel<T>> {
+    adc: &'a mut Adc<'d, T>,
+    channel: &'a mut C,
+    trigger: &'a mut crate::exti::ExtiInput<'e>,
+    edge: TriggerEdge,
+}
+
+#[cfg(all(feature = "exti", feature = "time"))]
+impl<'a, 'd, 'e, T: Instance, C: AdcChannel<T>> embassy_futures::stream::Stream for TriggeredSamples<'a, 'd, 'e, T, C> {
+    type Item = TriggeredSample;
+
+    async fn next(&mut self) -> TriggeredSample {
+        self.adc.read_on_exti11(self.channel, self.trigger, self.edge).await
+    }
+}
 
 /// Default VREF voltage used for sample conversion to millivolts.
 pub const VREF_DEFAULT_MV: u32 = 3300;
 /// VREF voltage used for factory calibration of VREFINTCAL register.
 pub const VREF_CALIB_MV: u32 = 3300;
 
+/// Minimum sample time of the internal channels, see [`Adc::read_internal`].
+const INTERNAL_SAMPLE_TIME_US: u32 = 10;
+
+/// Factory calibration values, taken at 30 °C with VDDA = [`VREF_CALIB_MV`], 12-bit resolution.
+#[cfg(stm32f4)]
+mod cal {
+    pub const VREFINT: *const u16 = 0x1FFF_7A2A as _;
+    pub const TS_30: *const u16 = 0x1FFF_7A2C as _;
+    pub const TS_110: *const u16 = 0x1FFF_7A2E as _;
+}
+#[cfg(any(stm32f72x, stm32f73x))]
+mod cal {
+    pub const VREFINT: *const u16 = 0x1FF0_7A4A as _;
+    pub const TS_30: *const u16 = 0x1FF0_7A4C as _;
+    pub const TS_110: *const u16 = 0x1FF0_7A4E as _;
+}
+#[cfg(all(stm32f7, not(any(stm32f72x, stm32f73x))))]
+mod cal {
+    pub const VREFINT: *const u16 = 0x1FF0_F44A as _;
+    pub const TS_30: *const u16 = 0x1FF0_F44C as _;
+    pub const TS_110: *const u16 = 0x1FF0_F44E as _;
+}
+
+/// Convert a 12-bit `sample` to millivolts, given VDDA in millivolts, e.g. from
+/// [`VrefInt::vdda_mv`].
+pub fn to_millivolts(sample: u16, vdda_mv: u32) -> u32 {
+    sample as u32 * vdda_mv / 4095
+}
+
 pub struct VrefInt;
 impl AdcChannel<ADC1> for VrefInt {}
 impl super::SealedAdcChannel<ADC1> for VrefInt {
@@ -24,6 +147,25 @@ impl VrefInt {
     pub fn start_time_us() -> u32 {
         10
     }
+
+    /// Factory calibration: the 12-bit reading of VREFINT with VDDA = [`VREF_CALIB_MV`].
+    #[cfg(not(stm32f2))]
+    pub fn calibrated_value() -> u16 {
+        unsafe { cal::VREFINT.read_volatile() }
+    }
+
+    /// VDDA in millivolts, from a 12-bit reading of VREFINT.
+    ///
+    /// STM32F2 chips have no factory calibration, so the typical VREFINT voltage is used instead.
+    pub fn vdda_mv(sample: u16) -> u32 {
+        #[cfg(not(stm32f2))]
+        let cal = VREF_CALIB_MV * Self::calibrated_value() as u32;
+        // Typical VREFINT of 1.21 V
+        #[cfg(stm32f2)]
+        let cal = 1210 * 4095;
+
+        cal / sample.max(1) as u32
+    }
 }
 
 pub struct Temperature;
@@ -45,6 +187,27 @@ impl Temperature {
     pub fn start_time_us() -> u32 {
         10
     }
+
+    /// Convert a 12-bit `sample` to degrees Celsius, given VDDA in millivolts, e.g. from
+    /// [`VrefInt::vdda_mv`].
+    ///
+    /// The sensor is calibrated in factory at 30 °C and 110 °C. STM32F2 chips have no factory
+    /// calibration, so the typical characteristics are used instead, which is only accurate to a
+    /// few degrees.
+    pub fn to_celsius(sample: u16, vdda_mv: u32) -> f32 {
+        #[cfg(not(stm32f2))]
+        {
+            // Scale the sample to the calibration VDDA
+            let sample = sample as f32 * vdda_mv as f32 / VREF_CALIB_MV as f32;
+            let (cal30, cal110) = unsafe { (cal::TS_30.read_volatile() as f32, cal::TS_110.read_volatile() as f32) };
+            (sample - cal30) * (110.0 - 30.0) / (cal110 - cal30) + 30.0
+        }
+        #[cfg(stm32f2)]
+        {
+            // 760 mV at 25 °C, 2.5 mV/°C
+            (to_millivolts(sample, vdda_mv) as f32 - 760.0) / 2.5 + 25.0
+        }
+    }
 }
 
 pub struct Vbat;
@@ -55,6 +218,21 @@ impl super::SealedAdcChannel<ADC1> for Vbat {
     }
 }
 
+impl Vbat {
+    /// Convert a 12-bit `sample` to the VBAT voltage in millivolts, given VDDA in millivolts,
+    /// e.g. from [`VrefInt::vdda_mv`].
+    ///
+    /// VBAT is measured through a bridge dividing it by 2, or by 4 on STM32F42x and later.
+    pub fn to_millivolts(sample: u16, vdda_mv: u32) -> u32 {
+        #[cfg(any(stm32f2, stm32f40x, stm32f41x))]
+        const DIVIDER: u32 = 2;
+        #[cfg(not(any(stm32f2, stm32f40x, stm32f41x)))]
+        const DIVIDER: u32 = 4;
+
+        to_millivolts(sample, vdda_mv) * DIVIDER
+    }
+}
+
 enum Prescaler {
     Div2,
     Div4,
@@ -80,6 +258,15 @@ impl Prescaler {
         }
     }
 
+    fn divisor(&self) -> u32 {
+        match self {
+            Prescaler::Div2 => 2,
+            Prescaler::Div4 => 4,
+            Prescaler::Div6 => 6,
+            Prescaler::Div8 => 8,
+        }
+    }
+
     fn adcpre(&self) -> crate::pac::adccommon::vals::Adcpre {
         match self {
             Prescaler::Div2 => crate::pac::adccommon::vals::Adcpre::DIV2,
@@ -190,6 +377,350 @@ where
         self.convert()
     }
 
+    /// Read an internal channel: [`VrefInt`], [`Temperature`] or [`Vbat`].
+    ///
+    /// These need a sample time of at least 10 µs, which is used if the sample time set with
+    /// [`Adc::set_sample_time`] is shorter. The channel must have been enabled for its
+    /// `start_time_us`.
+    pub fn read_internal(&mut self, channel: &mut impl AdcChannel<T>) -> u16 {
+        let channel = channel.channel();
+        T::regs().sqr3().write(|reg| reg.set_sq(0, channel));
+        let min = Self::internal_sample_time();
+        let sample_time = if self.sample_time.to_bits() < min.to_bits() {
+            min
+        } else {
+            self.sample_time
+        };
+        Self::set_channel_sample_time(channel, sample_time);
+
+        self.convert()
+    }
+
+    /// Shortest sample time of at least [`INTERNAL_SAMPLE_TIME_US`] at the ADC clock frequency.
+    fn internal_sample_time() -> SampleTime {
+        let freq = T::frequency().0 / Prescaler::from_pclk2(T::frequency()).divisor();
+        let cycles = (freq as u64 * INTERNAL_SAMPLE_TIME_US as u64).div_ceil(1_000_000);
+        match cycles {
+            0..=3 => SampleTime::CYCLES3,
+            4..=15 => SampleTime::CYCLES15,
+            16..=28 => SampleTime::CYCLES28,
+            29..=56 => SampleTime::CYCLES56,
+            57..=84 => SampleTime::CYCLES84,
+            85..=112 => SampleTime::CYCLES112,
+            113..=144 => SampleTime::CYCLES144,
+            _ => SampleTime::CYCLES480,
+        }
+    }
+
+    /// Convert a sequence of channels, in scan mode with DMA.
+    ///
+    /// Each channel is sampled for its own sample time, and `readings` receives one sample per
+    /// channel, in the order of `channels`. This is faster than reading the channels one by one,
+    /// as the ADC converts them back to back.
+    pub async fn read_sequence(
+        &mut self,
+        dma: impl Peripheral<P = impl RxDma<T>>,
+        channels: &[(&AnyAdcChannel<T>, SampleTime)],
+        readings: &mut [u16],
+    ) {
+        assert!(
+            !channels.is_empty() && channels.len() <= 16,
+            "1 to 16 channels can be converted"
+        );
+        assert_eq!(channels.len(), readings.len(), "one reading per channel is needed");
+        into_ref!(dma);
+
+        let r = T::regs();
+        Self::set_sequence(channels);
+
+        r.sr().modify(|reg| {
+            reg.set_ovr(false);
+            reg.set_eoc(false);
+            reg.set_strt(false);
+        });
+        r.cr1().modify(|reg| reg.set_scan(true));
+        r.cr2().modify(|reg| {
+            reg.set_cont(false);
+            reg.set_dma(true);
+            reg.set_dds(false);
+        });
+
+        // Leave the sequence as `read` expects it, even if the future is dropped.
+        let _cleanup = OnDrop::new(|| {
+            r.cr2().modify(|reg| reg.set_dma(false));
+            r.cr1().modify(|reg| reg.set_scan(false));
+            r.sqr1().modify(|reg| reg.set_l(0));
+        });
+
+        let request = dma.request();
+        let transfer =
+            unsafe { Transfer::new_read(dma, request, r.dr().as_ptr() as *mut u16, readings, Default::default()) };
+
+        r.cr2().modify(|reg| reg.set_swstart(true));
+        transfer.await;
+    }
+
+    /// Sample `channel` on the next edge of EXTI line 11.
+    ///
+    /// The conversion is started by hardware on the edge, with no software latency, which makes this
+    /// suitable for capturing a signal at the exact time of an event. `trigger` must be the
+    /// [`ExtiInput`](crate::exti::ExtiInput) of a pin 11 (PA11, PB11, ...), it is used to wait for
+    /// the edge and to configure EXTI line 11. Panics if it isn't on line 11.
+    #[cfg(all(feature = "exti", feature = "time"))]
+    pub async fn read_on_exti11(
+        &mut self,
+        channel: &mut impl AdcChannel<T>,
+        trigger: &mut crate::exti::ExtiInput<'_>,
+        edge: TriggerEdge,
+    ) -> TriggeredSample {
+        use crate::pac::adc::vals::Exten;
+
+        assert_eq!(trigger.line(), 11, "the ADC can only be triggered by EXTI line 11");
+
+        channel.setup();
+        let channel = channel.channel();
+        T::regs().sqr3().write(|reg| reg.set_sq(0, channel));
+        Self::set_channel_sample_time(channel, self.sample_time);
+
+        T::regs().sr().modify(|reg| {
+            reg.set_eoc(false);
+            reg.set_strt(false);
+        });
+
+        // Don't leave the trigger armed if the future is dropped.
+        let _disarm = OnDrop::new(|| T::regs().cr2().modify(|reg| reg.set_exten(Exten::DISABLED)));
+
+        T::regs().cr2().modify(|reg| {
+            // EXTI line 11
+            reg.set_extsel(0b1111);
+            reg.set_exten(match edge {
+                TriggerEdge::Rising => Exten::RISINGEDGE,
+                TriggerEdge::Falling => Exten::FALLINGEDGE,
+                TriggerEdge::Both => Exten::BOTHEDGES,
+            });
+        });
+
+        match edge {
+            TriggerEdge::Rising => trigger.wait_for_rising_edge().await,
+            TriggerEdge::Falling => trigger.wait_for_falling_edge().await,
+            TriggerEdge::Both => trigger.wait_for_any_edge().await,
+        }
+        let timestamp = embassy_time::Instant::now();
+
+        while T::regs().sr().read().eoc() == false {
+            // spin //wait for finish
+        }
+
+        TriggeredSample {
+            value: T::regs().dr().read().0 as u16,
+            timestamp,
+        }
+    }
+
+    /// Stream samples of `channel` taken on the edges of EXTI line 11, see [`Adc::read_on_exti11`].
+    #[cfg(all(feature = "exti", feature = "time"))]
+    pub fn triggered_samples<'a, 'e, C: AdcChannel<T>>(
+        &'a mut self,
+        channel: &'a mut C,
+        trigger: &'a mut crate::exti::ExtiInput<'e>,
+        edge: TriggerEdge,
+    ) -> TriggeredSamples<'a, 'd, 'e, T, C> {
+        TriggeredSamples {
+            adc: self,
+            channel,
+            trigger,
+            edge,
+        }
+    }
+
+    /// Configure the injected sequence.
+    ///
+    /// Injected conversions have their own sequence, trigger and result registers, and take
+    /// priority over regular conversions: a trigger interrupts the regular sequence, which resumes
+    /// once the injected channels are converted. Use them to sample a few channels at exact times,
+    /// e.g. on a timer event, while [`Adc::read_sequence`] or [`Adc::read_continuous`] run.
+    ///
+    /// Up to 4 channels are converted in the order of `channels`, each for its own sample time.
+    pub fn configure_injected(
+        &mut self,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        channels: &[(&AnyAdcChannel<T>, SampleTime)],
+        trigger: InjectedTrigger,
+    ) {
+        assert!(
+            !channels.is_empty() && channels.len() <= 4,
+            "1 to 4 injected channels can be converted"
+        );
+
+        let r = T::regs();
+        // With less than 4 channels, the sequence uses the last JSQ fields.
+        let first = 4 - channels.len();
+        r.jsqr().write(|reg| {
+            reg.set_jl((channels.len() - 1) as u8);
+            for (i, (channel, _)) in channels.iter().enumerate() {
+                reg.set_jsq(first + i, channel.channel);
+            }
+        });
+        for (channel, sample_time) in channels {
+            Self::set_channel_sample_time(channel.channel, *sample_time);
+        }
+
+        // All injected channels are only converted in scan mode.
+        r.cr1().modify(|reg| reg.set_scan(true));
+        r.cr2().modify(|reg| match trigger {
+            InjectedTrigger::Software => reg.set_jexten(Jexten::DISABLED),
+            InjectedTrigger::External { source, edge } => {
+                reg.set_jextsel(source);
+                reg.set_jexten(match edge {
+                    TriggerEdge::Rising => Jexten::RISINGEDGE,
+                    TriggerEdge::Falling => Jexten::FALLINGEDGE,
+                    TriggerEdge::Both => Jexten::BOTHEDGES,
+                });
+            }
+        });
+
+        T::Interrupt::unpend();
+        unsafe { T::Interrupt::enable() };
+    }
+
+    /// Convert the injected sequence set by [`Adc::configure_injected`].
+    ///
+    /// With a software trigger, this starts the conversions, otherwise it waits for the next
+    /// trigger event. `readings` receives one sample per injected channel, in order.
+    pub async fn read_injected(&mut self, readings: &mut [u16]) {
+        let r = T::regs();
+        let len = r.jsqr().read().jl() as usize + 1;
+        assert_eq!(readings.len(), len, "one reading per injected channel is needed");
+
+        r.cr1().modify(|reg| reg.set_scan(true));
+        r.sr().modify(|reg| {
+            reg.set_jeoc(false);
+            reg.set_jstrt(false);
+        });
+        if r.cr2().read().jexten() == Jexten::DISABLED {
+            r.cr2().modify(|reg| reg.set_jswstart(true));
+        }
+
+        let _disable = OnDrop::new(|| r.cr1().modify(|reg| reg.set_jeocie(false)));
+        poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+
+            if r.sr().read().jeoc() {
+                Poll::Ready(())
+            } else {
+                r.cr1().modify(|reg| reg.set_jeocie(true));
+                Poll::Pending
+            }
+        })
+        .await;
+
+        for (i, reading) in readings.iter_mut().enumerate() {
+            *reading = r.jdr(i).read().jdata();
+        }
+    }
+
+    /// Configure the analog watchdog, which flags conversions outside of `low..=high`.
+    ///
+    /// The watchdog guards `channel`, or all channels if `None`, both in regular and injected
+    /// conversions. The thresholds are compared to 12-bit values, whatever the resolution.
+    pub fn configure_watchdog(
+        &mut self,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        channel: Option<&AnyAdcChannel<T>>,
+        low: u16,
+        high: u16,
+    ) {
+        let r = T::regs();
+        r.ltr().write(|reg| reg.set_lt(low));
+        r.htr().write(|reg| reg.set_ht(high));
+        r.cr1().modify(|reg| {
+            reg.set_awdsgl(channel.is_some());
+            reg.set_awdch(channel.map_or(0, |ch| ch.channel));
+            reg.set_awden(true);
+            reg.set_jawden(true);
+        });
+
+        T::Interrupt::unpend();
+        unsafe { T::Interrupt::enable() };
+    }
+
+    /// Disable the analog watchdog.
+    pub fn disable_watchdog(&mut self) {
+        T::regs().cr1().modify(|reg| {
+            reg.set_awden(false);
+            reg.set_jawden(false);
+            reg.set_awdie(false);
+        });
+    }
+
+    /// Convert `channels` continuously until the analog watchdog flags a conversion.
+    ///
+    /// The channels are converted back to back, with their own sample times, without any CPU
+    /// involvement until a conversion falls outside of the window set by
+    /// [`Adc::configure_watchdog`]. Use long sample times to save power.
+    pub async fn wait_for_out_of_window(&mut self, channels: &[(&AnyAdcChannel<T>, SampleTime)]) {
+        assert!(
+            !channels.is_empty() && channels.len() <= 16,
+            "1 to 16 channels can be converted"
+        );
+
+        let r = T::regs();
+        Self::set_sequence(channels);
+
+        r.sr().modify(|reg| {
+            reg.set_awd(false);
+            reg.set_ovr(false);
+            reg.set_eoc(false);
+            reg.set_strt(false);
+        });
+        r.cr1().modify(|reg| reg.set_scan(true));
+        // Without DMA nor EOCS, the unread conversions don't stop the ADC with an overrun.
+        r.cr2().modify(|reg| {
+            reg.set_cont(true);
+            reg.set_dma(false);
+            reg.set_eocs(false);
+        });
+
+        // Stop converting and leave the sequence as `read` expects it, even if the future is dropped.
+        let _cleanup = OnDrop::new(|| {
+            r.cr1().modify(|reg| {
+                reg.set_awdie(false);
+                reg.set_scan(false);
+            });
+            r.cr2().modify(|reg| reg.set_cont(false));
+            r.sqr1().modify(|reg| reg.set_l(0));
+        });
+
+        r.cr2().modify(|reg| reg.set_swstart(true));
+
+        poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+
+            if r.sr().read().awd() {
+                Poll::Ready(())
+            } else {
+                r.cr1().modify(|reg| reg.set_awdie(true));
+                Poll::Pending
+            }
+        })
+        .await;
+    }
+
+    /// Set the regular sequence to `channels`, in order, with their sample times.
+    pub(super) fn set_sequence(channels: &[(&AnyAdcChannel<T>, SampleTime)]) {
+        let r = T::regs();
+        r.sqr1().modify(|reg| reg.set_l((channels.len() - 1) as u8));
+        for (i, (channel, sample_time)) in channels.iter().enumerate() {
+            let ch = channel.channel;
+            match i {
+                0..=5 => r.sqr3().modify(|reg| reg.set_sq(i, ch)),
+                6..=11 => r.sqr2().modify(|reg| reg.set_sq(i - 6, ch)),
+                _ => r.sqr1().modify(|reg| reg.set_sq(i - 12, ch)),
+            }
+            Self::set_channel_sample_time(ch, *sample_time);
+        }
+    }
+
     fn set_channel_sample_time(ch: u8, sample_time: SampleTime) {
         let sample_time = sample_time.into();
         if ch <= 9 {
@@ -91,6 +91,21 @@ cfg_if! {
     }
 }
 
+/// Hardware oversampling configuration.
+///
+/// Ratios from 2x to 256x and shifts up to 8 bits are supported. The shifted sum is truncated to
+/// 16 bits, which a 12-bit conversion exceeds above 16x unless `shift >= ratio_log2 - 4`.
+/// Averaging in hardware pairs well with `measure_periodically`, which can't afford to wake the CPU
+/// for each conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Oversampling {
+    /// Base 2 logarithm of the number of accumulated conversions, from 1 (2x) to 8 (256x).
+    pub ratio_log2: u8,
+    /// Right shift applied to the accumulated result, from 0 to 8 bits.
+    pub shift: u8,
+}
+
 impl<'d, T: Instance> Adc<'d, T> {
     pub fn new(adc: impl Peripheral<P = T> + 'd) -> Self {
         into_ref!(adc);
@@ -192,6 +207,48 @@ impl<'d, T: Instance> Adc<'d, T> {
         T::regs().cfgr1().modify(|reg| reg.set_res(resolution.into()));
     }
 
+    /// Enable or disable the hardware oversampler.
+    ///
+    /// On G0 and U0, which have no injected channels, this sets `OVSE`, elsewhere `ROVSE`: either
+    /// way it applies to the conversions of [`read`](Self::read).
+    pub fn set_oversampling(&mut self, oversampling: Option<Oversampling>) {
+        if let Some(o) = oversampling {
+            assert!(o.ratio_log2 >= 1 && o.ratio_log2 <= 8);
+            assert!(o.shift <= 8);
+        }
+
+        T::regs().cfgr2().modify(|reg| {
+            #[cfg(not(any(adc_g0, adc_u0)))]
+            reg.set_rovse(oversampling.is_some());
+            #[cfg(any(adc_g0, adc_u0))]
+            reg.set_ovse(oversampling.is_some());
+            if let Some(o) = oversampling {
+                reg.set_ovsr(o.ratio_log2 - 1);
+                reg.set_ovss(o.shift);
+            }
+        });
+    }
+
+    /// Enable or disable auto-off mode.
+    ///
+    /// The ADC is then powered off whenever it isn't converting, and powered on again at the start
+    /// of each conversion, which saves power between sparse conversions.
+    #[cfg(any(adc_g0, adc_u0))]
+    pub fn set_auto_off(&mut self, enable: bool) {
+        T::regs().cfgr1().modify(|reg| reg.set_autoff(enable));
+    }
+
+    /// Enable or disable auto-delayed conversion mode.
+    ///
+    /// A new conversion then only starts once the result of the previous one has been read, so
+    /// results are never overwritten and the ADC doesn't convert more often than needed.
+    pub fn set_auto_delay(&mut self, enable: bool) {
+        #[cfg(not(any(adc_g0, adc_u0)))]
+        T::regs().cfgr().modify(|reg| reg.set_autdly(enable));
+        #[cfg(any(adc_g0, adc_u0))]
+        T::regs().cfgr1().modify(|reg| reg.set_wait(enable));
+    }
+
     /*
     /// Convert a raw sample from the `Temperature` to deg C
     pub fn to_degrees_centigrade(sample: u16) -> f32 {
@@ -295,3 +352,51 @@ impl<'d, T: Instance> Adc<'d, T> {
         }
     }
 }
+
+/// Take a measurement every `period`, sending the results to `sender`.
+///
+/// For each measurement, the ADC is powered up and calibrated, `measure` is called to configure
+/// it and read the channel(s), and the ADC is powered down again. Between measurements the ADC
+/// clock is disabled, so the low-power executor can stay in Stop mode and be woken by the RTC,
+/// for microamp average currents when measuring for example the battery voltage or the
+/// temperature every few seconds. Use [`Adc::set_oversampling`] in `measure` to average many
+/// conversions in hardware.
+///
+/// The ADC is only powered down between measurements here: dropping an [`Adc`] leaves it powered,
+/// as with the other ADC versions.
+///
+/// ```rust,ignore
+/// static CHANNEL: Channel<CriticalSectionRawMutex, u16, 4> = Channel::new();
+///
+/// measure_periodically(p.ADC1, Duration::from_secs(10), CHANNEL.sender(), |adc| {
+///     adc.set_oversampling(Some(Oversampling { ratio_log2: 4, shift: 4 }));
+///     let mut vrefint = adc.enable_vrefint();
+///     adc.read(&mut vrefint)
+/// })
+/// .await
+/// ```
+#[cfg(feature = "time")]
+pub async fn measure_periodically<T: Instance, M: embassy_sync::blocking_mutex::raw::RawMutex, const N: usize>(
+    adc: impl Peripheral<P = T>,
+    period: embassy_time::Duration,
+    sender: embassy_sync::channel::Sender<'_, M, u16, N>,
+    mut measure: impl FnMut(&mut Adc<'_, T>) -> u16,
+) -> ! {
+    into_ref!(adc);
+    let mut ticker = embassy_time::Ticker::every(period);
+    loop {
+        ticker.next().await;
+        let value = measure(&mut Adc::new(adc.reborrow()));
+
+        // `read` already disables the ADC after each conversion, power down its voltage regulator
+        // and clock too.
+        T::regs().cr().modify(|reg| {
+            reg.set_advregen(false);
+            #[cfg(not(any(adc_g0, adc_u0)))]
+            reg.set_deeppwd(true);
+        });
+        rcc::disable::<T>();
+
+        sender.send(value).await;
+    }
+}
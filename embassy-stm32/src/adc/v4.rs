@@ -31,6 +31,31 @@ const TEMP_CHANNEL: u8 = 18;
 const VBAT_CHANNEL: u8 = 17;
 
 // NOTE: Vrefint/Temperature/Vbat are not available on all ADCs, this currently cannot be modeled with stm32-data, so these are available from the software on all ADCs
+/// Hardware oversampling configuration.
+///
+/// The H7 oversampler sums up to 1024 conversions of up to 16 bits, and shifts the 26-bit sum
+/// right by up to 11 bits. [`Adc::read`] returns 16 bits, so at 16-bit resolution use
+/// `shift >= ratio_log2` to keep the whole average. Ratios are powers of two here, although the
+/// hardware accepts any ratio from 1 to 1024.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Oversampling {
+    /// Base 2 logarithm of the number of accumulated conversions, from 1 (2x) to 10 (1024x).
+    pub ratio_log2: u8,
+    /// Right shift applied to the accumulated result, from 0 to 11 bits.
+    pub shift: u8,
+}
+
+/// ADC calibration factors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Calibration {
+    /// Calibration factor for single-ended inputs (11 bits).
+    pub single_ended: u16,
+    /// Calibration factor for differential inputs (11 bits).
+    pub differential: u16,
+}
+
 /// Internal voltage reference channel.
 pub struct VrefInt;
 impl<T: Instance> AdcChannel<T> for VrefInt {}
@@ -163,7 +188,7 @@ impl<'d, T: Instance> Adc<'d, T> {
         s.power_up();
         s.configure_differential_inputs();
 
-        s.calibrate();
+        s.run_calibration();
         blocking_delay_us(1);
 
         s.enable();
@@ -172,6 +197,70 @@ impl<'d, T: Instance> Adc<'d, T> {
         s
     }
 
+    /// Re-run the ADC self-calibration: offset and linearity for single-ended inputs, and offset
+    /// for differential inputs.
+    ///
+    /// The calibration is done on creation, running it again after a significant temperature or
+    /// supply voltage change improves accuracy. The ADC is disabled while calibrating.
+    pub fn calibrate(&mut self) -> Calibration {
+        self.disable();
+        self.run_calibration();
+        blocking_delay_us(1);
+        self.enable();
+
+        self.calibration()
+    }
+
+    /// Get the current offset calibration factors.
+    ///
+    /// The linearity calibration factors stay in the ADC, they are kept as long as it is powered.
+    pub fn calibration(&self) -> Calibration {
+        let calfact = T::regs().calfact().read();
+        Calibration {
+            single_ended: calfact.calfact_s(),
+            differential: calfact.calfact_d(),
+        }
+    }
+
+    /// Set the offset calibration factors, e.g. restoring ones saved by a previous
+    /// [`calibrate`](Self::calibrate).
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        T::regs().calfact().write(|w| {
+            w.set_calfact_s(calibration.single_ended & 0x7ff);
+            w.set_calfact_d(calibration.differential & 0x7ff);
+        });
+    }
+
+    /// Make `positive` a differential input, with `negative` as its negative input.
+    ///
+    /// `negative` must be the channel following `positive`, and can't be converted on its own while
+    /// `positive` is differential. Conversions of `positive` then measure the voltage between
+    /// the two inputs, as offset binary: mid-scale (2048 at 12 bits) is 0 V. The ADC is disabled
+    /// meanwhile.
+    pub fn set_differential(&mut self, positive: &mut impl AdcChannel<T>, negative: &mut impl AdcChannel<T>) {
+        assert_eq!(
+            negative.channel(),
+            positive.channel() + 1,
+            "the negative input must be the next channel"
+        );
+        positive.setup();
+        negative.setup();
+
+        self.set_difsel(positive.channel(), Difsel::DIFFERENTIAL);
+    }
+
+    /// Make `channel` a single-ended input again, see [`set_differential`](Self::set_differential).
+    pub fn set_single_ended(&mut self, channel: &impl AdcChannel<T>) {
+        self.set_difsel(channel.channel(), Difsel::SINGLEENDED);
+    }
+
+    /// DIFSEL can only be written while the ADC is disabled.
+    fn set_difsel(&mut self, channel: u8, difsel: Difsel) {
+        self.disable();
+        T::regs().difsel().modify(|w| w.set_difsel(channel as _, difsel));
+        self.enable();
+    }
+
     fn power_up(&mut self) {
         T::regs().cr().modify(|reg| {
             reg.set_deeppwd(false);
@@ -189,15 +278,25 @@ impl<'d, T: Instance> Adc<'d, T> {
         });
     }
 
-    fn calibrate(&mut self) {
-        T::regs().cr().modify(|w| {
-            w.set_adcaldif(Adcaldif::SINGLEENDED);
-            w.set_adcallin(true);
-        });
+    fn run_calibration(&mut self) {
+        // The linearity calibration is only done along with the single-ended one.
+        for (adcaldif, adcallin) in [(Adcaldif::SINGLEENDED, true), (Adcaldif::DIFFERENTIAL, false)] {
+            T::regs().cr().modify(|w| {
+                w.set_adcaldif(adcaldif);
+                w.set_adcallin(adcallin);
+            });
 
-        T::regs().cr().modify(|w| w.set_adcal(true));
+            T::regs().cr().modify(|w| w.set_adcal(true));
 
-        while T::regs().cr().read().adcal() {}
+            while T::regs().cr().read().adcal() {}
+        }
+    }
+
+    fn disable(&mut self) {
+        if T::regs().cr().read().aden() {
+            T::regs().cr().modify(|w| w.set_addis(true));
+            while T::regs().cr().read().aden() {}
+        }
     }
 
     fn enable(&mut self) {
@@ -252,6 +351,25 @@ impl<'d, T: Instance> Adc<'d, T> {
         T::regs().cfgr().modify(|reg| reg.set_res(resolution.into()));
     }
 
+    /// Enable or disable the hardware oversampler.
+    ///
+    /// Unlike on other families, `OSVR` holds the ratio minus one rather than its logarithm.
+    pub fn set_oversampling(&mut self, oversampling: Option<Oversampling>) {
+        if let Some(o) = oversampling {
+            assert!(o.ratio_log2 >= 1 && o.ratio_log2 <= 10);
+            assert!(o.shift <= 11);
+        }
+
+        T::regs().cfgr2().modify(|reg| {
+            reg.set_rovse(oversampling.is_some());
+            if let Some(o) = oversampling {
+                // Any ratio from 1 to 1024 is supported, as `osvr + 1`
+                reg.set_osvr((1 << o.ratio_log2) - 1);
+                reg.set_ovss(o.shift);
+            }
+        });
+    }
+
     /// Perform a single conversion.
     fn convert(&mut self) -> u16 {
         T::regs().isr().modify(|reg| {
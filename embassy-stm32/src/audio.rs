@@ -0,0 +1,146 @@
+//! Full-duplex audio streaming.
+//!
+//! [`run`] captures audio from a ring-buffered I2S or SAI input, hands it to a user callback one
+//! block at a time, and plays back what the callback produced, for example to run effects
+//! between an audio codec's ADC and DAC.
+//!
+//! Capture and playback can be the same full-duplex stream, e.g. a [`RingBufferedI2S`] created
+//! with both buffers, or a pair `(input, output)` of two streams, e.g. two SAI sub-blocks. Two
+//! streams clocked from different sources never run at exactly the same rate: the difference
+//! slowly drains or fills the playback buffer. [`run`] compensates by slipping samples: it drops
+//! the last frame of a block when playback falls behind, and repeats it when playback runs
+//! ahead.
+//!
+//! The streams implement [`AudioSource`] and [`AudioSink`]. DAC channels implement
+//! [`AudioSink`] too, which is what the DAC audio player plays through.
+//!
+//! [`RingBufferedI2S`]: crate::i2s::RingBufferedI2S
+
+use crate::dma::word::Word;
+
+/// Audio streaming error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The capture or playback buffer overran: the processing didn't keep up with the stream.
+    Overrun,
+    /// The output ran out of samples between two writes.
+    Underrun,
+    /// Read from a stream that doesn't capture audio.
+    NotAReceiver,
+    /// Write to a stream that doesn't play audio.
+    NotATransmitter,
+}
+
+/// Audio capture stream.
+pub trait AudioSource<W: Word> {
+    /// Read samples, waiting until enough of them were received.
+    async fn read(&mut self, data: &mut [W]) -> Result<(), Error>;
+}
+
+/// Audio playback stream, such as a ring-buffered I2S or SAI output, or a DAC channel.
+pub trait AudioSink<W: Word> {
+    /// Convert a signed 16 bit sample to the format [`write`](AudioSink::write) expects.
+    fn encode(sample: i16) -> W;
+
+    /// Write samples, waiting for room in the buffer.
+    ///
+    /// This may return once the samples have been handed to the hardware, before they have
+    /// actually been played.
+    async fn write(&mut self, data: &[W]) -> Result<(), Error>;
+
+    /// Size of the playback buffer, in samples, or 0 if the output isn't buffered.
+    fn capacity(&self) -> usize;
+
+    /// Total number of samples sent since the stream was created, or 0 if the output isn't
+    /// buffered.
+    ///
+    /// This wraps around at `usize::MAX`.
+    fn position(&self) -> usize;
+}
+
+impl<W: Word, I: AudioSource<W>, O> AudioSource<W> for (I, O) {
+    async fn read(&mut self, data: &mut [W]) -> Result<(), Error> {
+        self.0.read(data).await
+    }
+}
+
+impl<W: Word, I, O: AudioSink<W>> AudioSink<W> for (I, O) {
+    fn encode(sample: i16) -> W {
+        O::encode(sample)
+    }
+
+    async fn write(&mut self, data: &[W]) -> Result<(), Error> {
+        self.1.write(data).await
+    }
+
+    fn capacity(&self) -> usize {
+        self.1.capacity()
+    }
+
+    fn position(&self) -> usize {
+        self.1.position()
+    }
+}
+
+/// Stream audio through `process`, one block at a time.
+///
+/// Each block of `input.len()` samples read from the capture stream is passed to `process`, which
+/// fills `output` with the samples to play back. Both blocks must be the same size, a multiple of
+/// `frame_len`, the number of samples per frame (2 for stereo). To hand blocks to another task
+/// instead, send them to a channel from `process`.
+///
+/// The streams must be started, with the playback buffer holding silence, before calling this.
+/// The latency from input to output is about the size of the playback buffer. Samples are only
+/// slipped with a buffered output, whose [`capacity`](AudioSink::capacity) isn't 0.
+///
+/// This only returns on error.
+pub async fn run<W: Word, S: AudioSource<W> + AudioSink<W>>(
+    stream: &mut S,
+    frame_len: usize,
+    input: &mut [W],
+    output: &mut [W],
+    mut process: impl FnMut(&[W], &mut [W]),
+) -> Error {
+    let len = output.len();
+    assert!(frame_len > 0 && len > frame_len && len % frame_len == 0);
+    assert_eq!(input.len(), len);
+
+    // The ring buffer counts its initial content, a full buffer, as written.
+    let mut written = stream.capacity();
+    let mut reference = None;
+    // Allowed deviation from the initial playback buffer level before slipping.
+    let slack = len / 2;
+
+    loop {
+        if let Err(e) = stream.read(input).await {
+            return e;
+        }
+
+        process(input, output);
+
+        let queued = written.wrapping_sub(stream.position());
+        let reference = *reference.get_or_insert(queued);
+        let buffered = stream.capacity() != 0;
+
+        let result = if buffered && queued > reference + slack {
+            // Playback is slower than capture: drop a frame.
+            written = written.wrapping_add(len - frame_len);
+            stream.write(&output[..len - frame_len]).await
+        } else if buffered && queued + slack < reference {
+            // Playback is faster than capture: repeat a frame.
+            written = written.wrapping_add(len + frame_len);
+            match stream.write(output).await {
+                Ok(()) => stream.write(&output[len - frame_len..]).await,
+                Err(e) => Err(e),
+            }
+        } else {
+            written = written.wrapping_add(len);
+            stream.write(output).await
+        };
+
+        if let Err(e) = result {
+            return e;
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Throughput and latency measurement helpers.
+//!
+//! These use the DWT cycle counter to time SPI, UART and DMA transfers and interrupt entry,
+//! so you can check that a clock configuration gives the throughput you expect, and catch
+//! regressions on your own board. Results are in CPU cycles, and can be converted to
+//! time or throughput using the frozen clock configuration.
+//!
+//! Call [`enable_cycle_counter`] once after [`crate::init`] before measuring anything.
+//!
+//! With the `isr-budget` feature, interrupt handlers are also timed against a budget set with
+//! [`set_isr_budget`], to catch handlers that run for too long.
+
+use core::future::Future;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::peripheral::DWT;
+use embassy_hal_internal::Peripheral;
+
+use crate::interrupt::InterruptExt;
+use crate::time::Hertz;
+
+/// Result of a single measurement.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Measurement {
+    /// Number of bytes transferred during the measurement, zero for latency measurements.
+    pub bytes: usize,
+    /// Elapsed CPU cycles.
+    pub cycles: u32,
+}
+
+impl Measurement {
+    /// Elapsed time in microseconds.
+    pub fn micros(&self) -> u32 {
+        (self.cycles as u64 * 1_000_000 / cpu_frequency().0 as u64) as u32
+    }
+
+    /// Achieved throughput in bytes per second.
+    pub fn bytes_per_second(&self) -> u32 {
+        if self.cycles == 0 {
+            return 0;
+        }
+        (self.bytes as u64 * cpu_frequency().0 as u64 / self.cycles as u64) as u32
+    }
+}
+
+fn cpu_frequency() -> Hertz {
+    unsafe { crate::rcc::get_freqs() }.sys.unwrap()
+}
+
+/// Enable the DWT cycle counter.
+pub fn enable_cycle_counter() {
+    let mut cp = unsafe { cortex_m::Peripherals::steal() };
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+}
+
+/// Current value of the DWT cycle counter.
+#[inline(always)]
+pub fn cycles() -> u32 {
+    DWT::cycle_count()
+}
+
+/// Measure a blocking operation that transfers `bytes` bytes.
+pub fn measure<R>(bytes: usize, f: impl FnOnce() -> R) -> (R, Measurement) {
+    let start = cycles();
+    let r = f();
+    let cycles = cycles().wrapping_sub(start);
+    (r, Measurement { bytes, cycles })
+}
+
+/// Measure an async operation that transfers `bytes` bytes.
+///
+/// The measurement includes any time the executor spends running other tasks
+/// while the operation is pending.
+pub async fn measure_async<F: Future>(bytes: usize, fut: F) -> (F::Output, Measurement) {
+    let start = cycles();
+    let r = fut.await;
+    let cycles = cycles().wrapping_sub(start);
+    (r, Measurement { bytes, cycles })
+}
+
+/// Measure SPI write throughput by writing `data` once.
+#[cfg(spi)]
+pub async fn spi_write(
+    spi: &mut crate::spi::Spi<'_, crate::mode::Async>,
+    data: &[u8],
+) -> Result<Measurement, crate::spi::Error> {
+    let (r, m) = measure_async(data.len(), spi.write(data)).await;
+    r.map(|_| m)
+}
+
+/// Measure UART write throughput by writing `data` once, including the final flush.
+#[cfg(usart)]
+pub async fn uart_write(
+    tx: &mut crate::usart::UartTx<'_, crate::mode::Async>,
+    data: &[u8],
+) -> Result<Measurement, crate::usart::Error> {
+    let (r, m) = measure_async(data.len(), async {
+        tx.write(data).await?;
+        tx.flush().await
+    })
+    .await;
+    r.map(|_| m)
+}
+
+/// Measure DMA memory-to-memory copy throughput by copying `src` into `dst` once.
+///
+/// The channel must be able to do memory-to-memory transfers (see [`crate::dma::Transfer::new_memcpy`]).
+#[cfg(any(dma, bdma))]
+pub async fn dma_memcpy<'a>(
+    channel: impl Peripheral<P = impl crate::dma::Channel> + 'a,
+    src: &'a [u32],
+    dst: &'a mut [u32],
+) -> Measurement {
+    let bytes = core::mem::size_of_val(src);
+    let transfer = unsafe { crate::dma::Transfer::new_memcpy(channel, src, dst, Default::default()) };
+    measure_async(bytes, transfer).await.1
+}
+
+static LATENCY_START: AtomicU32 = AtomicU32::new(0);
+static LATENCY_CYCLES: AtomicU32 = AtomicU32::new(0);
+
+/// Measure interrupt entry latency.
+///
+/// This pends `irq` and returns the number of cycles until the interrupt handler called
+/// [`on_latency_interrupt`]. The interrupt must be enabled and its handler must call
+/// [`on_latency_interrupt`] as its very first statement; use an otherwise unused interrupt.
+pub fn interrupt_latency(irq: impl InterruptExt) -> Measurement {
+    LATENCY_CYCLES.store(u32::MAX, Ordering::Relaxed);
+    LATENCY_START.store(cycles(), Ordering::Relaxed);
+    irq.pend();
+    while LATENCY_CYCLES.load(Ordering::Relaxed) == u32::MAX {}
+    Measurement {
+        bytes: 0,
+        cycles: LATENCY_CYCLES.load(Ordering::Relaxed),
+    }
+}
+
+/// Record interrupt entry for [`interrupt_latency`]. Call this first thing in the interrupt handler.
+#[inline(always)]
+pub fn on_latency_interrupt() {
+    let now = cycles();
+    LATENCY_CYCLES.store(
+        now.wrapping_sub(LATENCY_START.load(Ordering::Relaxed)),
+        Ordering::Relaxed,
+    );
+}
+
+#[cfg(feature = "isr-budget")]
+static ISR_BUDGET: AtomicU32 = AtomicU32::new(u32::MAX);
+#[cfg(feature = "isr-budget")]
+static ISR_OVERRUNS: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "isr-budget")]
+static ISR_WORST_CYCLES: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "isr-budget")]
+static ISR_WORST_IRQ: AtomicU32 = AtomicU32::new(0);
+
+/// Set the interrupt handler execution time budget, in CPU cycles.
+///
+/// Interrupt handlers bound with [`bind_interrupts!`](crate::bind_interrupts), and the DMA and
+/// EXTI handlers of this crate, are timed from entry to exit. A handler exceeding the budget is
+/// logged with a warning and counted in [`isr_stats`]. The time of a handler includes the time
+/// spent in higher-priority handlers preempting it.
+#[cfg(feature = "isr-budget")]
+pub fn set_isr_budget(cycles: u32) {
+    ISR_BUDGET.store(cycles, Ordering::Relaxed);
+}
+
+/// Interrupt handler execution time statistics.
+#[cfg(feature = "isr-budget")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IsrStats {
+    /// Number of handler runs that exceeded the budget.
+    pub overruns: u32,
+    /// IRQ number of the slowest handler run.
+    pub worst_irq: u16,
+    /// Execution time of the slowest handler run.
+    pub worst: Measurement,
+}
+
+/// Get the interrupt handler execution time statistics since the last [`reset_isr_stats`].
+#[cfg(feature = "isr-budget")]
+pub fn isr_stats() -> IsrStats {
+    critical_section::with(|_| IsrStats {
+        overruns: ISR_OVERRUNS.load(Ordering::Relaxed),
+        worst_irq: ISR_WORST_IRQ.load(Ordering::Relaxed) as u16,
+        worst: Measurement {
+            bytes: 0,
+            cycles: ISR_WORST_CYCLES.load(Ordering::Relaxed),
+        },
+    })
+}
+
+/// Reset the interrupt handler execution time statistics.
+#[cfg(feature = "isr-budget")]
+pub fn reset_isr_stats() {
+    critical_section::with(|_| {
+        ISR_OVERRUNS.store(0, Ordering::Relaxed);
+        ISR_WORST_IRQ.store(0, Ordering::Relaxed);
+        ISR_WORST_CYCLES.store(0, Ordering::Relaxed);
+    })
+}
+
+/// Times an interrupt handler from its creation to [`IsrGuard::finish`].
+#[cfg(feature = "isr-budget")]
+#[doc(hidden)]
+pub struct IsrGuard {
+    start: u32,
+}
+
+#[cfg(feature = "isr-budget")]
+#[allow(clippy::new_without_default)]
+impl IsrGuard {
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self { start: cycles() }
+    }
+
+    /// Stop timing and record the handler run.
+    ///
+    /// The guard is released and the statistics updated before a budget overrun is logged, so
+    /// the time spent logging is not charged to the handler.
+    pub fn finish(self) {
+        let elapsed = cycles().wrapping_sub(self.start);
+        let irq = match cortex_m::peripheral::SCB::vect_active() {
+            cortex_m::peripheral::scb::VectActive::Interrupt { irqn } => irqn as u16,
+            _ => return,
+        };
+
+        let budget = ISR_BUDGET.load(Ordering::Relaxed);
+        let overrun = critical_section::with(|_| {
+            if elapsed > ISR_WORST_CYCLES.load(Ordering::Relaxed) {
+                ISR_WORST_CYCLES.store(elapsed, Ordering::Relaxed);
+                ISR_WORST_IRQ.store(irq as u32, Ordering::Relaxed);
+            }
+            if elapsed > budget {
+                ISR_OVERRUNS.fetch_add(1, Ordering::Relaxed);
+            }
+            elapsed > budget
+        });
+
+        if overrun {
+            warn!("interrupt {} took {} cycles, budget is {}", irq, elapsed, budget);
+        }
+    }
+}
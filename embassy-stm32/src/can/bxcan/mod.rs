@@ -838,6 +838,30 @@ impl Drop for Can<'_> {
     }
 }
 
+impl embassy_futures::stream::Stream for Can<'_> {
+    type Item = Result<Envelope, BusError>;
+
+    async fn next(&mut self) -> Self::Item {
+        self.read().await
+    }
+}
+
+impl embassy_futures::stream::Stream for CanRx<'_> {
+    type Item = Result<Envelope, BusError>;
+
+    async fn next(&mut self) -> Self::Item {
+        self.read().await
+    }
+}
+
+impl<const RX_BUF_SIZE: usize> embassy_futures::stream::Stream for BufferedCanRx<'_, RX_BUF_SIZE> {
+    type Item = Result<Envelope, BusError>;
+
+    async fn next(&mut self) -> Self::Item {
+        self.read().await
+    }
+}
+
 /// Identifies one of the two receive FIFOs.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
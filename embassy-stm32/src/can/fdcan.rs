@@ -653,6 +653,22 @@ impl<'d> CanRx<'d> {
     }
 }
 
+impl embassy_futures::stream::Stream for Can<'_> {
+    type Item = Result<Envelope, BusError>;
+
+    async fn next(&mut self) -> Self::Item {
+        self.read().await
+    }
+}
+
+impl embassy_futures::stream::Stream for CanRx<'_> {
+    type Item = Result<Envelope, BusError>;
+
+    async fn next(&mut self) -> Self::Item {
+        self.read().await
+    }
+}
+
 /// FDCAN Tx only Instance
 pub struct CanTx<'d> {
     _phantom: PhantomData<&'d ()>,
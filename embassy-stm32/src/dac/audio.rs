@@ -0,0 +1,309 @@
+//! Audio playback service.
+//!
+//! Plays PCM buffers queued from any task through an [`AudioSink`]: a DAC channel driven by
+//! a timer trigger and DMA, or a SAI block. Buffers may be 8-bit unsigned or 16-bit signed
+//! PCM at any sample rate; they are format-converted and resampled (linear interpolation) to
+//! the output rate on the fly. This is meant for alert tones and voice prompts, not hi-fi audio.
+//!
+//! For DAC output, configure a timer to generate a trigger at the output sample rate, then
+//! select it with [`DacChannel::set_trigger`] and enable it with [`DacChannel::set_triggering`]
+//! before starting the player.
+#![cfg_attr(gpdma, allow(unused))]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embassy_futures::join::join;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::Channel;
+
+use super::{DacChannel, DacDma1, DacDma2, Instance};
+pub use crate::audio::AudioSink;
+use crate::audio::Error;
+
+/// Sample data of a [`PcmBuffer`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Samples {
+    /// Unsigned 8 bit samples, centered on 128.
+    U8(&'static [u8]),
+    /// Signed 16 bit samples.
+    I16(&'static [i16]),
+}
+
+impl Samples {
+    fn len(&self) -> usize {
+        match self {
+            Samples::U8(s) => s.len(),
+            Samples::I16(s) => s.len(),
+        }
+    }
+
+    fn get(&self, i: usize) -> i16 {
+        match self {
+            Samples::U8(s) => ((s[i] as i16) - 128) << 8,
+            Samples::I16(s) => s[i],
+        }
+    }
+}
+
+/// A buffer of mono PCM audio.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PcmBuffer {
+    /// Sample data.
+    pub samples: Samples,
+    /// Sample rate of `samples`, in Hz.
+    pub sample_rate: u32,
+    /// Whether this is the last buffer of a stream.
+    ///
+    /// Running out of queued buffers after a buffer without this flag is reported as an underrun.
+    pub end_of_stream: bool,
+}
+
+/// Queue of buffers waiting to be played by an [`AudioPlayer`].
+pub struct AudioQueue<M: RawMutex, const N: usize> {
+    channel: Channel<M, PcmBuffer, N>,
+    underruns: AtomicU32,
+}
+
+impl<M: RawMutex, const N: usize> AudioQueue<M, N> {
+    /// Create a new, empty queue.
+    pub const fn new() -> Self {
+        Self {
+            channel: Channel::new(),
+            underruns: AtomicU32::new(0),
+        }
+    }
+
+    /// Queue a buffer for playback, waiting until there is space in the queue.
+    pub async fn send(&self, buffer: PcmBuffer) {
+        self.channel.send(buffer).await
+    }
+
+    /// Queue a buffer for playback, returning it back if the queue is full.
+    pub fn try_send(&self, buffer: PcmBuffer) -> Result<(), PcmBuffer> {
+        self.channel.try_send(buffer).map_err(|e| match e {
+            embassy_sync::channel::TrySendError::Full(b) => b,
+        })
+    }
+
+    /// Number of underruns since creation.
+    ///
+    /// An underrun is counted when the player ran out of samples in the middle of a stream,
+    /// or when the output hardware ran out of samples before the next chunk was handed to it.
+    pub fn underruns(&self) -> u32 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    fn count_underrun(&self) {
+        #[cfg(not(armv6m))]
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+        #[cfg(armv6m)]
+        critical_section::with(|_| {
+            let x = self.underruns.load(Ordering::Relaxed);
+            self.underruns.store(x + 1, Ordering::Relaxed);
+        });
+    }
+}
+
+macro_rules! impl_dac_sink {
+    ($n:literal, $trait:ident) => {
+        #[cfg(not(gpdma))]
+        impl<'d, T: Instance, DMA: $trait<T>> AudioSink<u16> for DacChannel<'d, T, $n, DMA> {
+            fn encode(sample: i16) -> u16 {
+                ((sample as i32 + 0x8000) >> 4) as u16
+            }
+
+            async fn write(&mut self, data: &[u16]) -> Result<(), Error> {
+                // Unlike the inherent `write`, leave the channel enabled afterwards so it holds its output between chunks.
+                T::regs().cr().modify(|w| {
+                    w.set_en(Self::IDX, true);
+                    w.set_dmaen(Self::IDX, true);
+                });
+
+                let request = self.dma.request();
+                let transfer = unsafe {
+                    crate::dma::Transfer::new_write(
+                        &mut self.dma,
+                        request,
+                        data,
+                        T::regs().dhr12r(Self::IDX).as_ptr() as *mut u16,
+                        Default::default(),
+                    )
+                };
+                transfer.await;
+
+                #[cfg(not(dac_v1))]
+                if T::regs().sr().read().dmaudr(Self::IDX) {
+                    // The DAC stops requesting DMA after an underrun until DMAEN is toggled.
+                    T::regs().sr().write(|w| w.set_dmaudr(Self::IDX, true));
+                    T::regs().cr().modify(|w| w.set_dmaen(Self::IDX, false));
+                    return Err(Error::Underrun);
+                }
+
+                Ok(())
+            }
+
+            // Each chunk is played out before `write` returns, there's no buffer.
+            fn capacity(&self) -> usize {
+                0
+            }
+
+            fn position(&self) -> usize {
+                0
+            }
+        }
+    };
+}
+
+impl_dac_sink!(1, DacDma1);
+impl_dac_sink!(2, DacDma2);
+
+/// Read position in a buffer, in 16.16 fixed point input samples.
+struct Cursor {
+    buffer: PcmBuffer,
+    pos: u64,
+    step: u64,
+}
+
+impl Cursor {
+    fn new(buffer: PcmBuffer, output_rate: u32) -> Self {
+        Self {
+            buffer,
+            pos: 0,
+            step: ((buffer.sample_rate as u64) << 16) / output_rate as u64,
+        }
+    }
+
+    fn next_sample(&mut self) -> Option<i16> {
+        let samples = &self.buffer.samples;
+        let i = (self.pos >> 16) as usize;
+        if i >= samples.len() {
+            return None;
+        }
+
+        let a = samples.get(i) as i32;
+        let b = if i + 1 < samples.len() {
+            samples.get(i + 1) as i32
+        } else {
+            a
+        };
+        let frac = (self.pos & 0xFFFF) as i32;
+        self.pos += self.step;
+
+        Some((a + (((b - a) * frac) >> 16)) as i16)
+    }
+}
+
+/// Audio playback service.
+///
+/// Takes buffers from an [`AudioQueue`] and plays them through an [`AudioSink`] at a fixed output rate.
+pub struct AudioPlayer<'a, S: AudioSink<u16>, M: RawMutex, const N: usize> {
+    sink: S,
+    queue: &'a AudioQueue<M, N>,
+    output_rate: u32,
+    scratch: &'a mut [u16],
+}
+
+impl<'a, S: AudioSink<u16>, M: RawMutex, const N: usize> AudioPlayer<'a, S, M, N> {
+    /// Create a new player.
+    ///
+    /// `output_rate` is the rate the sink consumes samples at, e.g. the DAC trigger frequency.
+    /// `scratch` is split in two halves: one is played while the other is filled. Each half must
+    /// hold more samples than can be played during the worst-case latency of the player task.
+    pub fn new(sink: S, queue: &'a AudioQueue<M, N>, output_rate: u32, scratch: &'a mut [u16]) -> Self {
+        assert!(output_rate > 0);
+        assert!(scratch.len() >= 2);
+        Self {
+            sink,
+            queue,
+            output_rate,
+            scratch,
+        }
+    }
+
+    /// Run the player. This never returns.
+    pub async fn run(&mut self) -> ! {
+        let Self {
+            sink,
+            queue,
+            output_rate,
+            scratch,
+        } = self;
+        let queue: &AudioQueue<M, N> = queue;
+        let rate = *output_rate;
+        let half = scratch.len() / 2;
+
+        loop {
+            // Idle until a stream starts.
+            let mut cursor = Some(Cursor::new(queue.channel.receive().await, rate));
+            let (mut cur, mut next) = scratch.split_at_mut(half);
+
+            let (mut len, mut ended) = fill::<S, M, N>(cur, &mut cursor, queue, rate);
+            loop {
+                if len == 0 {
+                    if ended {
+                        break;
+                    }
+                    // Ran dry mid-stream: the output holds its last sample until more data arrives.
+                    queue.count_underrun();
+                    cursor = Some(Cursor::new(queue.channel.receive().await, rate));
+                    (len, ended) = fill::<S, M, N>(cur, &mut cursor, queue, rate);
+                    continue;
+                }
+
+                let (res, (next_len, next_ended)) = if ended {
+                    (sink.write(&cur[..len]).await, (0, true))
+                } else {
+                    join(sink.write(&cur[..len]), async {
+                        fill::<S, M, N>(next, &mut cursor, queue, rate)
+                    })
+                    .await
+                };
+                if res.is_err() {
+                    queue.count_underrun();
+                }
+
+                core::mem::swap(&mut cur, &mut next);
+                len = next_len;
+                ended = next_ended;
+            }
+        }
+    }
+}
+
+/// Fill `out` with encoded samples, pulling new buffers from the queue as needed.
+///
+/// Returns the number of samples written, and whether the end of the stream was reached.
+fn fill<S: AudioSink<u16>, M: RawMutex, const N: usize>(
+    out: &mut [u16],
+    cursor: &mut Option<Cursor>,
+    queue: &AudioQueue<M, N>,
+    output_rate: u32,
+) -> (usize, bool) {
+    let mut n = 0;
+    while n < out.len() {
+        if cursor.is_none() {
+            match queue.channel.try_receive() {
+                Ok(buffer) => *cursor = Some(Cursor::new(buffer, output_rate)),
+                Err(_) => return (n, false),
+            }
+        }
+        let c = cursor.as_mut().unwrap();
+
+        match c.next_sample() {
+            Some(sample) => {
+                out[n] = S::encode(sample);
+                n += 1;
+            }
+            None => {
+                let end_of_stream = c.buffer.end_of_stream;
+                *cursor = None;
+                if end_of_stream {
+                    return (n, true);
+                }
+            }
+        }
+    }
+    (n, false)
+}
@@ -11,6 +11,7 @@ use crate::pac::dac;
 use crate::rcc::{self, RccPeripheral};
 use crate::{peripherals, Peripheral};
 
+pub mod audio;
 mod tsel;
 pub use tsel::TriggerSel;
 
@@ -243,6 +244,12 @@ impl<'d, T: Instance, const N: u8, DMA> DacChannel<'d, T, N, DMA> {
         }
     }
 
+    /// The 12-bit right-aligned data holding register, as the destination of a
+    /// [peripheral-to-peripheral](crate::dma::Transfer::new_peripheral_to_peripheral) DMA transfer.
+    pub fn data_register(&mut self) -> crate::dma::PeripheralRegister<'_, u16> {
+        unsafe { crate::dma::PeripheralRegister::new(T::regs().dhr12r(Self::IDX).as_ptr() as *mut u16) }
+    }
+
     /// Read the current output value of the DAC.
     pub fn read(&self) -> u16 {
         T::regs().dor(Self::IDX).read().dor()
@@ -347,6 +354,68 @@ macro_rules! impl_dma_methods {
                     w.set_dmaen(Self::IDX, false);
                 });
             }
+
+            /// Output the 12-bit right-aligned samples of `data` over and over, at `sample_rate`.
+            ///
+            /// `timer` is set to update at `sample_rate` and triggers a conversion on each update;
+            /// `trigger` must be its update event, e.g. [`TriggerSel::Tim6`] for TIM6. Samples are
+            /// fed with a circular DMA transfer, so the CPU is not involved once the output is
+            /// started.
+            ///
+            /// This only returns if the DMA transfer stops on an error. Drop the future to stop
+            /// the output: the timer is stopped and the channel is disabled.
+            #[cfg(not(gpdma))]
+            pub async fn write_waveform<TIM: crate::timer::BasicInstance>(
+                &mut self,
+                timer: &crate::timer::low_level::Timer<'_, TIM>,
+                trigger: TriggerSel,
+                sample_rate: crate::time::Hertz,
+                data: &[u16],
+            ) {
+                self.set_trigger(trigger);
+                self.set_triggering(true);
+                T::regs().cr().modify(|w| {
+                    w.set_en(Self::IDX, true);
+                    w.set_dmaen(Self::IDX, true);
+                });
+
+                timer.stop();
+                timer.set_frequency(sample_rate);
+                timer
+                    .regs_basic()
+                    .cr2()
+                    .modify(|w| w.set_mms(crate::pac::timer::vals::Mms::UPDATE));
+
+                let _stop = embassy_hal_internal::drop::OnDrop::new(|| {
+                    timer.stop();
+                    T::regs().cr().modify(|w| {
+                        w.set_en(Self::IDX, false);
+                        w.set_dmaen(Self::IDX, false);
+                    });
+                });
+
+                let request = self.dma.request();
+                let tx_options = crate::dma::TransferOptions {
+                    circular: true,
+                    half_transfer_ir: false,
+                    complete_transfer_ir: false,
+                    ..Default::default()
+                };
+                let transfer = unsafe {
+                    crate::dma::Transfer::new_write(
+                        &mut self.dma,
+                        request,
+                        data,
+                        T::regs().dhr12r(Self::IDX).as_ptr() as *mut u16,
+                        tx_options,
+                    )
+                };
+
+                // The DMA is ready, start triggering conversions.
+                timer.reset();
+                timer.start();
+                transfer.await;
+            }
         }
     };
 }
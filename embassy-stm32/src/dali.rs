@@ -0,0 +1,224 @@
+//! DALI (IEC 62386) lighting bus controller.
+//!
+//! DALI is a 1200 bit/s Manchester (bi-phase) encoded bus. The controller sends 16-bit forward
+//! frames (an address byte and an opcode byte), and control gear answers queries with 8-bit
+//! backward frames. Each bit is two half-bit periods (`Te`, 416.7 µs): a `1` is the bus active
+//! (low) followed by idle (high), a `0` is the opposite. Every frame starts with a `1` start bit.
+//!
+//! This driver bit-bangs the bus through an [`Output`] pin and an [`ExtiInput`] pin connected to
+//! a DALI transceiver, timed with the embassy time driver like
+//! [`SoftPwm`](crate::timer::soft_pwm::SoftPwm). It uses no timer peripheral, so it works on any
+//! pins, at the cost of timing jitter.
+//!
+//! # Jitter
+//!
+//! Transmitted half-bits are scheduled against absolute instants, and received half-bits are
+//! sampled in their middle. The ±10% timing tolerance of the standard leaves about 40 µs for
+//! each edge to be late by:
+//!
+//! - the resolution of the time driver: one tick of `embassy_time::TICK_HZ`, which is 30 µs at
+//!   32.768 kHz, so use a tick rate of at least 1 MHz,
+//! - the interrupt latency of the time driver alarm,
+//! - the time until the executor polls the DALI task. Any task of the same executor running for
+//!   more than a few tens of µs without yielding corrupts the frame in progress.
+//!
+//! Run the driver from a high-priority interrupt executor if other tasks can't guarantee this.
+
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+
+use crate::exti::ExtiInput;
+use crate::gpio::{Level, Output};
+
+/// Duration of a half bit.
+const TE: Duration = Duration::from_micros(417);
+/// Minimum idle time between the end of a frame and the next forward frame.
+const SETTLING_TIME: Duration = Duration::from_micros(417 * 22);
+/// Maximum time from the end of a forward frame to the start of the backward frame answering it.
+const BACKWARD_TIMEOUT: Duration = Duration::from_micros(10_500);
+
+/// DALI bus configuration.
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// The transceiver pulls the bus low when the TX pin is high.
+    pub invert_tx: bool,
+    /// The transceiver drives the RX pin high while the bus is low.
+    pub invert_rx: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            invert_tx: false,
+            invert_rx: false,
+        }
+    }
+}
+
+/// DALI error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A backward frame was received but couldn't be decoded.
+    ///
+    /// This usually means several devices answered at once, which for yes/no queries means "yes".
+    Framing,
+}
+
+/// Destination of a forward frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Address {
+    /// A single device, by short address (0 to 63).
+    Short(u8),
+    /// A group of devices (0 to 15).
+    Group(u8),
+    /// All devices on the bus.
+    Broadcast,
+}
+
+impl Address {
+    fn to_byte(self, command: bool) -> u8 {
+        let a = match self {
+            Address::Short(a) => {
+                assert!(a < 64);
+                a << 1
+            }
+            Address::Group(g) => {
+                assert!(g < 16);
+                0x80 | g << 1
+            }
+            Address::Broadcast => 0xFE,
+        };
+        a | command as u8
+    }
+}
+
+/// DALI bus controller.
+pub struct Dali<'d> {
+    tx: Output<'d>,
+    rx: ExtiInput<'d>,
+    config: Config,
+    last_frame: Instant,
+}
+
+impl<'d> Dali<'d> {
+    /// Create a new DALI controller. The bus is released (idle) immediately.
+    pub fn new(tx: Output<'d>, rx: ExtiInput<'d>, config: Config) -> Self {
+        let mut this = Self {
+            tx,
+            rx,
+            config,
+            last_frame: Instant::now(),
+        };
+        this.set_bus(false);
+        this
+    }
+
+    /// Set the arc power level of the addressed devices (direct arc power control).
+    ///
+    /// 254 is full power, 1 is minimum, 0 is off, and 255 stops a running fade ("MASK").
+    pub async fn set_arc_power(&mut self, address: Address, level: u8) {
+        self.send_forward(address.to_byte(false), level).await
+    }
+
+    /// Send a command to the addressed devices.
+    ///
+    /// Configuration commands (opcodes 32 to 129) are only accepted if they are received twice in a
+    /// row within 100 ms; use [`send_twice`](Self::send_twice) for those.
+    pub async fn send(&mut self, address: Address, opcode: u8) {
+        self.send_forward(address.to_byte(true), opcode).await
+    }
+
+    /// Send a configuration command twice, as required for it to be accepted.
+    pub async fn send_twice(&mut self, address: Address, opcode: u8) {
+        self.send(address, opcode).await;
+        self.send(address, opcode).await;
+    }
+
+    /// Send a query command, and wait for the answer.
+    ///
+    /// Returns `Ok(None)` if no device answered. For yes/no queries, that means "no".
+    pub async fn query(&mut self, address: Address, opcode: u8) -> Result<Option<u8>, Error> {
+        self.send(address, opcode).await;
+        self.receive_backward().await
+    }
+
+    /// Send a raw 16-bit forward frame.
+    ///
+    /// This is needed for special commands, which use their own address byte.
+    pub async fn send_forward(&mut self, address: u8, opcode: u8) {
+        Timer::at(self.last_frame + SETTLING_TIME).await;
+
+        // Start bit, then MSB first.
+        let frame = 1 << 16 | (address as u32) << 8 | opcode as u32;
+        let mut t = Instant::now();
+        for i in (0..17).rev() {
+            let one = frame & (1 << i) != 0;
+            self.set_bus(one);
+            t += TE;
+            Timer::at(t).await;
+            self.set_bus(!one);
+            t += TE;
+            Timer::at(t).await;
+        }
+
+        // Stop bits.
+        self.set_bus(false);
+        t += TE * 4;
+        Timer::at(t).await;
+        self.last_frame = t;
+    }
+
+    async fn receive_backward(&mut self) -> Result<Option<u8>, Error> {
+        if with_timeout(BACKWARD_TIMEOUT, self.wait_bus_active()).await.is_err() {
+            return Ok(None);
+        }
+
+        // Sample each half bit in its middle.
+        let start = Instant::now();
+        let mut frame = 0u32;
+        for half in 0..18u32 {
+            Timer::at(start + TE * half + TE / 2).await;
+            frame = frame << 1 | self.bus_active() as u32;
+        }
+
+        // Let the stop bits pass before a new forward frame may be sent.
+        self.last_frame = start + TE * 22;
+
+        // Start bit, then 8 data bits MSB first. Each bit must be active-idle or idle-active.
+        let mut value = 0u32;
+        for i in (0..9).rev() {
+            value <<= 1;
+            match (frame >> (2 * i)) & 0b11 {
+                0b10 => value |= 1,
+                0b01 => {}
+                _ => return Err(Error::Framing),
+            }
+        }
+        if value & 0x100 == 0 {
+            return Err(Error::Framing);
+        }
+        Ok(Some(value as u8))
+    }
+
+    fn set_bus(&mut self, active: bool) {
+        self.tx.set_level(match active != self.config.invert_tx {
+            true => Level::Low,
+            false => Level::High,
+        });
+    }
+
+    async fn wait_bus_active(&mut self) {
+        match self.config.invert_rx {
+            false => self.rx.wait_for_low().await,
+            true => self.rx.wait_for_high().await,
+        }
+    }
+
+    fn bus_active(&self) -> bool {
+        self.rx.is_low() != self.config.invert_rx
+    }
+}
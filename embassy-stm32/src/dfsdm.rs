@@ -0,0 +1,457 @@
+//! Digital Filter for Sigma-Delta Modulators (DFSDM)
+//!
+//! The DFSDM decimates the 1-bit streams of sigma-delta modulators, such as MEMS microphones
+//! with a PDM output or isolated sigma-delta ADCs, into PCM samples: each of its filters takes
+//! the bitstream of one channel through a sinc filter and an integrator, and outputs 24-bit
+//! samples, streamed to memory with circular DMA.
+//!
+//! The peripheral is first split into its filters with [`split_filters`], which also starts the
+//! clock output feeding the modulators. A [`Filter`] is then created for each input:
+//!
+//! ```rust,ignore
+//! // A PDM microphone clocked at 3.072 MHz, with 48 kHz samples.
+//! let (flt0, _, _, _) = dfsdm::split_filters(p.DFSDM1, p.PC2, Hertz::khz(3072));
+//! let mut config = dfsdm::Config::default();
+//! config.oversampling = 64;
+//! let mut mic = Filter::new(flt0, p.PC3, p.DMA1_CH0, dma_buf, config);
+//! mic.start();
+//! loop {
+//!     mic.read(&mut samples).await?;
+//! }
+//! ```
+//!
+//! Two microphones can share a data line, one of them outputting on the rising edges of the clock
+//! and the other on the falling edges: read them with a filter each, one created with
+//! [`Filter::new`] and the other with [`Filter::new_shared`], and start them together with
+//! [`Config::synchronous`].
+#![macro_use]
+#![cfg_attr(gpdma, allow(unused))]
+
+use core::marker::PhantomData;
+
+use embassy_hal_internal::{into_ref, PeripheralRef};
+
+#[cfg(not(gpdma))]
+use crate::dma::{ringbuffer, Channel, ReadableRingBuffer, TransferOptions};
+use crate::gpio::{AfType, AnyPin, OutputType, Pull, SealedPin as _, Speed};
+use crate::rcc::{self, RccPeripheral};
+use crate::time::Hertz;
+use crate::{peripherals, Peripheral};
+
+/// Register offsets, from the channel and filter bases.
+const CHCFGR1: usize = 0x00;
+const CHCFGR2: usize = 0x04;
+const FLTCR1: usize = 0x00;
+const FLTFCR: usize = 0x14;
+const FLTRDATAR: usize = 0x1C;
+
+const CHCFGR1_DFSDMEN: u32 = 1 << 31;
+const CHCFGR1_CKOUTDIV_SHIFT: u32 = 16;
+const CHCFGR1_CHINSEL: u32 = 1 << 8;
+const CHCFGR1_CHEN: u32 = 1 << 7;
+const CHCFGR1_SPICKSEL_SHIFT: u32 = 2;
+
+const FLTCR1_FAST: u32 = 1 << 29;
+const FLTCR1_RCH_SHIFT: u32 = 24;
+const FLTCR1_RDMAEN: u32 = 1 << 21;
+const FLTCR1_RSYNC: u32 = 1 << 19;
+const FLTCR1_RCONT: u32 = 1 << 18;
+const FLTCR1_RSWSTART: u32 = 1 << 17;
+const FLTCR1_DFEN: u32 = 1 << 0;
+
+/// DFSDM error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Samples were lost because they were not read in time.
+    Overrun,
+}
+
+#[cfg(not(gpdma))]
+impl From<ringbuffer::OverrunError> for Error {
+    fn from(_: ringbuffer::OverrunError) -> Self {
+        Self::Overrun
+    }
+}
+
+/// Clock edge.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Edge {
+    /// Rising edge.
+    Rising,
+    /// Falling edge.
+    Falling,
+}
+
+/// Serial input format of a channel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Input {
+    /// Data clocked by the clock output (SPI format), sampled on `edge`, as for PDM microphones.
+    Spi {
+        /// Edge the data is sampled on.
+        edge: Edge,
+    },
+    /// Manchester-coded data, with its clock recovered from the data.
+    ///
+    /// The clock output must run at least 6 times faster than the data rate.
+    Manchester {
+        /// Edge coding a 1: with a rising edge, a 0 is coded by a falling edge and vice versa.
+        one: Edge,
+    },
+}
+
+impl Input {
+    /// SITP and SPICKSEL values.
+    fn bits(self) -> (u32, u32) {
+        match self {
+            Input::Spi { edge: Edge::Rising } => (0b00, 0b01),
+            Input::Spi { edge: Edge::Falling } => (0b01, 0b01),
+            Input::Manchester { one: Edge::Falling } => (0b10, 0b00),
+            Input::Manchester { one: Edge::Rising } => (0b11, 0b00),
+        }
+    }
+}
+
+/// Order of the sinc filter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(missing_docs)]
+pub enum SincOrder {
+    FastSinc = 0,
+    Sinc1 = 1,
+    Sinc2 = 2,
+    Sinc3 = 3,
+    Sinc4 = 4,
+    Sinc5 = 5,
+}
+
+/// Filter configuration.
+///
+/// The output sample rate is the bit rate divided by `oversampling` and `integrator`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Config {
+    /// Serial input format.
+    pub input: Input,
+    /// Order of the sinc filter.
+    pub order: SincOrder,
+    /// Decimation ratio of the sinc filter, from 1 to 1024.
+    pub oversampling: u16,
+    /// Decimation ratio of the integrator following the sinc filter, from 1 (no integration) to
+    /// 256.
+    pub integrator: u16,
+    /// Offset subtracted from the samples, to cancel that of the modulator, in 24-bit units.
+    pub offset: i32,
+    /// Start along with filter 0, for filters 1 to 3, so that their samples are taken at the same
+    /// instants.
+    ///
+    /// [`Filter::start`] then only arms the filter, and starting filter 0 starts it.
+    pub synchronous: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            input: Input::Spi { edge: Edge::Rising },
+            order: SincOrder::Sinc3,
+            oversampling: 64,
+            integrator: 1,
+            offset: 0,
+            synchronous: false,
+        }
+    }
+}
+
+impl Config {
+    /// Right shift fitting the largest filter output in 24 bits.
+    fn right_shift(&self) -> u32 {
+        let fosr = self.oversampling as u64;
+        let gain = match self.order {
+            SincOrder::FastSinc => 2 * fosr * fosr,
+            order => fosr.pow(order as u32),
+        } * self.integrator as u64;
+
+        let bits = 64 - (gain - 1).leading_zeros();
+        bits.saturating_sub(23).min(31)
+    }
+}
+
+/// A filter of the DFSDM, to create a [`Filter`] driver.
+///
+/// You can obtain them with [`split_filters`].
+pub struct FilterBlock<'d, T: Instance, F: FilterInstance> {
+    peri: PeripheralRef<'d, T>,
+    _phantom: PhantomData<F>,
+}
+
+/// Split the DFSDM into its four filters, and start its clock output at `clock_frequency`.
+///
+/// The clock output is derived from the DFSDM kernel clock, divided by 2 to 256: the closest
+/// frequency not above `clock_frequency` is used.
+pub fn split_filters<'d, T: Instance>(
+    peri: impl Peripheral<P = T> + 'd,
+    ckout: impl Peripheral<P = impl CkOutPin<T>> + 'd,
+    clock_frequency: Hertz,
+) -> (
+    FilterBlock<'d, T, Flt0>,
+    FilterBlock<'d, T, Flt1>,
+    FilterBlock<'d, T, Flt2>,
+    FilterBlock<'d, T, Flt3>,
+) {
+    into_ref!(peri, ckout);
+    rcc::enable_and_reset::<T>();
+
+    // The pin stays assigned to the clock output, as long as the DFSDM runs.
+    ckout.set_as_af(ckout.af_num(), AfType::output(OutputType::PushPull, Speed::VeryHigh));
+
+    let divider = T::frequency().0.div_ceil(clock_frequency.0).clamp(2, 256);
+    unsafe {
+        let ch0 = channel_reg::<T>(0, CHCFGR1);
+        ch0.write_volatile((divider - 1) << CHCFGR1_CKOUTDIV_SHIFT);
+        ch0.write_volatile(ch0.read_volatile() | CHCFGR1_DFSDMEN);
+    }
+
+    (
+        FilterBlock {
+            peri: unsafe { peri.clone_unchecked() },
+            _phantom: PhantomData,
+        },
+        FilterBlock {
+            peri: unsafe { peri.clone_unchecked() },
+            _phantom: PhantomData,
+        },
+        FilterBlock {
+            peri: unsafe { peri.clone_unchecked() },
+            _phantom: PhantomData,
+        },
+        FilterBlock {
+            peri,
+            _phantom: PhantomData,
+        },
+    )
+}
+
+/// DFSDM filter driver, outputting the samples of one channel.
+#[cfg(not(gpdma))]
+pub struct Filter<'d, T: Instance, F: FilterInstance> {
+    _peri: PeripheralRef<'d, T>,
+    _phantom: PhantomData<F>,
+    channel: u8,
+    datin: Option<PeripheralRef<'d, AnyPin>>,
+    ring_buffer: ReadableRingBuffer<'d, u32>,
+    synchronous: bool,
+}
+
+#[cfg(not(gpdma))]
+impl<'d, T: Instance, F: FilterInstance> Filter<'d, T, F> {
+    /// Create a filter reading channel `C`, whose data input is `datin`.
+    ///
+    /// Samples are streamed to `dma_buf` with circular DMA, which should hold a few reads' worth
+    /// of samples.
+    pub fn new<C: ChannelInstance>(
+        peri: FilterBlock<'d, T, F>,
+        datin: impl Peripheral<P = impl DatinPin<T, C>> + 'd,
+        dma: impl Peripheral<P = impl Channel + Dma<T, F>> + 'd,
+        dma_buf: &'d mut [u32],
+        config: Config,
+    ) -> Self {
+        into_ref!(datin);
+        datin.set_as_af(datin.af_num(), AfType::input(Pull::None));
+
+        Self::new_inner(peri, C::INDEX, false, Some(datin.map_into()), dma, dma_buf, config)
+    }
+
+    /// Create a filter reading channel `C`, whose data comes from the data input of channel
+    /// `C + 1`, set up by the filter reading that channel.
+    ///
+    /// This reads the second of two microphones sharing a data line.
+    pub fn new_shared<C: ChannelInstance>(
+        peri: FilterBlock<'d, T, F>,
+        dma: impl Peripheral<P = impl Channel + Dma<T, F>> + 'd,
+        dma_buf: &'d mut [u32],
+        config: Config,
+    ) -> Self {
+        assert!(C::INDEX < 7, "channel 7 has no next channel");
+        Self::new_inner(peri, C::INDEX, true, None, dma, dma_buf, config)
+    }
+
+    fn new_inner(
+        peri: FilterBlock<'d, T, F>,
+        channel: u8,
+        shared: bool,
+        datin: Option<PeripheralRef<'d, AnyPin>>,
+        dma: impl Peripheral<P = impl Channel + Dma<T, F>> + 'd,
+        dma_buf: &'d mut [u32],
+        config: Config,
+    ) -> Self {
+        assert!((1..=1024).contains(&config.oversampling));
+        assert!((1..=256).contains(&config.integrator));
+        assert!(F::INDEX != 0 || !config.synchronous, "filter 0 can't be synchronous");
+        into_ref!(dma);
+
+        let (sitp, spicksel) = config.input.bits();
+        let offset = (config.offset as u32 & 0xFF_FFFF) << 8;
+        unsafe {
+            let cfgr1 = channel_reg::<T>(channel, CHCFGR1);
+            // Keep the global enable and clock output fields of channel 0.
+            let global = cfgr1.read_volatile() & 0xFFFF_0000;
+            cfgr1.write_volatile(global);
+            channel_reg::<T>(channel, CHCFGR2).write_volatile(offset | config.right_shift() << 3);
+            let chinsel = if shared { CHCFGR1_CHINSEL } else { 0 };
+            cfgr1.write_volatile(global | chinsel | CHCFGR1_CHEN | spicksel << CHCFGR1_SPICKSEL_SHIFT | sitp);
+
+            filter_reg::<T>(F::INDEX, FLTCR1).write_volatile(0);
+            filter_reg::<T>(F::INDEX, FLTFCR).write_volatile(
+                (config.order as u32) << 29 | (config.oversampling as u32 - 1) << 16 | (config.integrator as u32 - 1),
+            );
+            let rsync = if config.synchronous { FLTCR1_RSYNC } else { 0 };
+            filter_reg::<T>(F::INDEX, FLTCR1).write_volatile(
+                FLTCR1_FAST | (channel as u32) << FLTCR1_RCH_SHIFT | FLTCR1_RDMAEN | rsync | FLTCR1_RCONT,
+            );
+        }
+
+        let request = dma.request();
+        let opts = TransferOptions {
+            half_transfer_ir: true,
+            ..Default::default()
+        };
+        let ring_buffer =
+            unsafe { ReadableRingBuffer::new(dma, request, filter_reg::<T>(F::INDEX, FLTRDATAR), dma_buf, opts) };
+
+        Self {
+            _peri: peri.peri,
+            _phantom: PhantomData,
+            channel,
+            datin,
+            ring_buffer,
+            synchronous: config.synchronous,
+        }
+    }
+
+    /// Start converting.
+    ///
+    /// Synchronous filters start along with filter 0, so start them before it.
+    pub fn start(&mut self) {
+        self.ring_buffer.start();
+        unsafe {
+            let cr1 = filter_reg::<T>(F::INDEX, FLTCR1);
+            cr1.write_volatile(cr1.read_volatile() | FLTCR1_DFEN);
+            if !self.synchronous {
+                cr1.write_volatile(cr1.read_volatile() | FLTCR1_RSWSTART);
+            }
+        }
+    }
+
+    /// Read samples, waiting until `data` is full.
+    ///
+    /// Samples are signed, with 24 significant bits. An overrun is reported if the DMA buffer
+    /// filled up since the last read; reading again then returns the newest samples.
+    pub async fn read(&mut self, data: &mut [i32]) -> Result<(), Error> {
+        // Samples are read in place: RDATAR holds a sample in its upper 24 bits.
+        let raw = unsafe { core::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u32, data.len()) };
+        let result = self.ring_buffer.read_exact(raw).await;
+        if result.is_err() {
+            self.ring_buffer.clear();
+        }
+        result?;
+
+        for sample in data.iter_mut() {
+            *sample >>= 8;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(gpdma))]
+impl<'d, T: Instance, F: FilterInstance> Drop for Filter<'d, T, F> {
+    fn drop(&mut self) {
+        unsafe {
+            filter_reg::<T>(F::INDEX, FLTCR1).write_volatile(0);
+            let cfgr1 = channel_reg::<T>(self.channel, CHCFGR1);
+            cfgr1.write_volatile(cfgr1.read_volatile() & !CHCFGR1_CHEN);
+        }
+        self.datin.as_ref().map(|x| x.set_as_disconnected());
+    }
+}
+
+fn channel_reg<T: Instance>(channel: u8, offset: usize) -> *mut u32 {
+    unsafe { T::regs().add(0x20 * channel as usize + offset) as *mut u32 }
+}
+
+fn filter_reg<T: Instance>(filter: u8, offset: usize) -> *mut u32 {
+    unsafe { T::regs().add(0x100 + 0x80 * filter as usize + offset) as *mut u32 }
+}
+
+trait SealedInstance {
+    /// Base address of the registers.
+    fn regs() -> *mut u8;
+}
+
+trait SealedChannelInstance {
+    const INDEX: u8;
+}
+
+trait SealedFilterInstance {
+    const INDEX: u8;
+}
+
+/// Channel instance trait.
+#[allow(private_bounds)]
+pub trait ChannelInstance: SealedChannelInstance {}
+
+/// Filter instance trait.
+#[allow(private_bounds)]
+pub trait FilterInstance: SealedFilterInstance {}
+
+macro_rules! impl_channel {
+    ($($name:ident: $index:expr),*) => {
+        $(
+            #[doc = concat!("Channel ", $index, ".")]
+            pub enum $name {}
+            impl SealedChannelInstance for $name {
+                const INDEX: u8 = $index;
+            }
+            impl ChannelInstance for $name {}
+        )*
+    };
+}
+
+macro_rules! impl_filter {
+    ($($name:ident: $index:expr),*) => {
+        $(
+            #[doc = concat!("Filter ", $index, ".")]
+            pub enum $name {}
+            impl SealedFilterInstance for $name {
+                const INDEX: u8 = $index;
+            }
+            impl FilterInstance for $name {}
+        )*
+    };
+}
+
+impl_channel!(Ch0: 0, Ch1: 1, Ch2: 2, Ch3: 3, Ch4: 4, Ch5: 5, Ch6: 6, Ch7: 7);
+impl_filter!(Flt0: 0, Flt1: 1, Flt2: 2, Flt3: 3);
+
+/// DFSDM instance trait.
+#[allow(private_bounds)]
+pub trait Instance: Peripheral<P = Self> + SealedInstance + RccPeripheral {}
+
+pin_trait!(CkOutPin, Instance);
+pin_trait!(DatinPin, Instance, ChannelInstance);
+
+dma_trait!(Dma, Instance, FilterInstance);
+
+foreach_peripheral!(
+    (dfsdm, $inst:ident) => {
+        impl SealedInstance for peripherals::$inst {
+            fn regs() -> *mut u8 {
+                crate::pac::$inst.as_ptr() as *mut u8
+            }
+        }
+
+        impl Instance for peripherals::$inst {}
+    };
+);
@@ -0,0 +1,300 @@
+//! Parallel display bus (Intel 8080 style).
+//!
+//! Display controllers such as the ILI9341 or ST7789 take commands and pixel data over an 8 or
+//! 16-bit parallel bus, with a data/command (D/C, also called RS) line telling them apart. The
+//! [`DisplayBus`] trait provides the command and data writes a display driver needs, implemented
+//! by:
+//!
+//! - [`FmcDisplayBus`], for a display on an FMC/FSMC NOR/SRAM bank. The D/C line is an address
+//!   line, so commands and data are written to two different addresses. Pixel data is
+//!   transferred by memory-to-memory DMA.
+//! - [`GpioDisplayBus`], which bit-bangs an 8-bit bus on a GPIO port, writing the eight data pins
+//!   at once through the port's set/reset register.
+
+#[cfg(fmc)]
+use embassy_hal_internal::into_ref;
+#[cfg(all(fmc, any(dma, bdma)))]
+use embassy_hal_internal::PeripheralRef;
+
+use crate::dma::word::Word;
+#[cfg(all(fmc, any(dma, bdma)))]
+use crate::dma::{AnyChannel, Channel, Transfer};
+#[cfg(fmc)]
+use crate::fmc;
+#[cfg(fmc)]
+use crate::gpio::{AfType, OutputType};
+use crate::gpio::{Level, Output, Pin, SealedPin as _, Speed};
+use crate::Peripheral;
+
+/// Parallel display bus.
+pub trait DisplayBus<W: Word> {
+    /// Write a command, with the D/C line low.
+    async fn write_command(&mut self, command: W);
+
+    /// Write data (command parameters or pixels), with the D/C line high.
+    async fn write_data(&mut self, data: &[W]);
+
+    /// Write the same data word `count` times, with the D/C line high, for example to fill an
+    /// area with one color.
+    async fn fill(&mut self, value: W, count: usize);
+}
+
+/// Address of the first chip select (NE1) of the FMC NOR/SRAM bank 1.
+#[cfg(fmc)]
+const BANK1_NE1_ADDRESS: usize = 0x6000_0000;
+
+/// Display on an FMC NOR/SRAM bank.
+///
+/// The bank must be configured as SRAM, with the timings required by the display, and its chip
+/// select, read, write and data pins set up, before creating the bus.
+#[cfg(fmc)]
+pub struct FmcDisplayBus<'d, W: Word> {
+    command: *mut W,
+    data: *mut W,
+    #[cfg(any(dma, bdma))]
+    dma: Option<PeripheralRef<'d, AnyChannel>>,
+    #[cfg(not(any(dma, bdma)))]
+    _phantom: core::marker::PhantomData<&'d ()>,
+}
+
+#[cfg(fmc)]
+impl<'d, W: Word> FmcDisplayBus<'d, W> {
+    /// Create a new FMC display bus, writing with the CPU.
+    ///
+    /// `bank_address` is the start of the NOR/SRAM bank, for example `0x6000_0000` for bank 1, and
+    /// `dc_address_line` the FMC address line (`Ax`) connected to the display's D/C input.
+    ///
+    /// # Safety
+    ///
+    /// The bank must be configured, and not be used by anything else.
+    pub unsafe fn new(bank_address: usize, dc_address_line: u8) -> Self {
+        let (command, data) = Self::addresses(bank_address, dc_address_line);
+        Self {
+            command,
+            data,
+            #[cfg(any(dma, bdma))]
+            dma: None,
+            #[cfg(not(any(dma, bdma)))]
+            _phantom: core::marker::PhantomData,
+        }
+    }
+
+    /// Create a new FMC display bus, writing data with DMA.
+    ///
+    /// The DMA channel must be able to do memory-to-memory transfers; on STM32F2/F4/F7 only the
+    /// streams of DMA2 can.
+    ///
+    /// # Safety
+    ///
+    /// The bank must be configured, and not be used by anything else.
+    #[cfg(any(dma, bdma))]
+    pub unsafe fn new_with_dma(
+        bank_address: usize,
+        dc_address_line: u8,
+        dma: impl Peripheral<P = impl Channel> + 'd,
+    ) -> Self {
+        into_ref!(dma);
+        let (command, data) = Self::addresses(bank_address, dc_address_line);
+        Self {
+            command,
+            data,
+            dma: Some(dma.map_into()),
+        }
+    }
+
+    fn addresses(bank_address: usize, dc_address_line: u8) -> (*mut W, *mut W) {
+        assert!(dc_address_line < 26);
+        // The FMC drives HADDR shifted right by the width of the bus: A0 is byte address bit 1 on
+        // a 16-bit bus.
+        let shift = match W::size() {
+            crate::dma::word::WordSize::OneByte => 0,
+            crate::dma::word::WordSize::TwoBytes => 1,
+            crate::dma::word::WordSize::FourBytes => 2,
+        };
+        let data = bank_address | 1 << (dc_address_line + shift);
+        (bank_address as *mut W, data as *mut W)
+    }
+}
+
+#[cfg(fmc)]
+impl<'d> FmcDisplayBus<'d, u16> {
+    /// Create a new 16-bit FMC display bus on the first chip select of bank 1, writing with the
+    /// CPU, and configure its pins.
+    ///
+    /// The pins are checked to be FMC pins at compile time, and the D/C address line is taken from
+    /// the `dc` pin.
+    ///
+    /// # Safety
+    ///
+    /// The bank must be configured, and not be used by anything else.
+    pub unsafe fn new_16bit<T: fmc::Instance>(
+        _fmc: impl Peripheral<P = T> + 'd,
+        d0: impl Peripheral<P = impl fmc::D0Pin<T>> + 'd,
+        d1: impl Peripheral<P = impl fmc::D1Pin<T>> + 'd,
+        d2: impl Peripheral<P = impl fmc::D2Pin<T>> + 'd,
+        d3: impl Peripheral<P = impl fmc::D3Pin<T>> + 'd,
+        d4: impl Peripheral<P = impl fmc::D4Pin<T>> + 'd,
+        d5: impl Peripheral<P = impl fmc::D5Pin<T>> + 'd,
+        d6: impl Peripheral<P = impl fmc::D6Pin<T>> + 'd,
+        d7: impl Peripheral<P = impl fmc::D7Pin<T>> + 'd,
+        d8: impl Peripheral<P = impl fmc::D8Pin<T>> + 'd,
+        d9: impl Peripheral<P = impl fmc::D9Pin<T>> + 'd,
+        d10: impl Peripheral<P = impl fmc::D10Pin<T>> + 'd,
+        d11: impl Peripheral<P = impl fmc::D11Pin<T>> + 'd,
+        d12: impl Peripheral<P = impl fmc::D12Pin<T>> + 'd,
+        d13: impl Peripheral<P = impl fmc::D13Pin<T>> + 'd,
+        d14: impl Peripheral<P = impl fmc::D14Pin<T>> + 'd,
+        d15: impl Peripheral<P = impl fmc::D15Pin<T>> + 'd,
+        noe: impl Peripheral<P = impl fmc::NOEPin<T>> + 'd,
+        nwe: impl Peripheral<P = impl fmc::NWEPin<T>> + 'd,
+        ne1: impl Peripheral<P = impl fmc::NE1Pin<T>> + 'd,
+        dc: impl Peripheral<P = impl fmc::AddressPin<T>> + 'd,
+    ) -> Self {
+        into_ref!(d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15, noe, nwe, ne1, dc);
+
+        let af = AfType::output(OutputType::PushPull, Speed::VeryHigh);
+        critical_section::with(|_| {
+            d0.set_as_af(d0.af_num(), af);
+            d1.set_as_af(d1.af_num(), af);
+            d2.set_as_af(d2.af_num(), af);
+            d3.set_as_af(d3.af_num(), af);
+            d4.set_as_af(d4.af_num(), af);
+            d5.set_as_af(d5.af_num(), af);
+            d6.set_as_af(d6.af_num(), af);
+            d7.set_as_af(d7.af_num(), af);
+            d8.set_as_af(d8.af_num(), af);
+            d9.set_as_af(d9.af_num(), af);
+            d10.set_as_af(d10.af_num(), af);
+            d11.set_as_af(d11.af_num(), af);
+            d12.set_as_af(d12.af_num(), af);
+            d13.set_as_af(d13.af_num(), af);
+            d14.set_as_af(d14.af_num(), af);
+            d15.set_as_af(d15.af_num(), af);
+            noe.set_as_af(noe.af_num(), af);
+            nwe.set_as_af(nwe.af_num(), af);
+            ne1.set_as_af(ne1.af_num(), af);
+            dc.set_as_af(dc.af_num(), af);
+        });
+
+        Self::new(BANK1_NE1_ADDRESS, dc.address_line())
+    }
+}
+
+#[cfg(fmc)]
+impl<'d, W: Word> DisplayBus<W> for FmcDisplayBus<'d, W> {
+    async fn write_command(&mut self, command: W) {
+        unsafe { self.command.write_volatile(command) };
+    }
+
+    async fn write_data(&mut self, data: &[W]) {
+        #[cfg(any(dma, bdma))]
+        if let Some(dma) = &mut self.dma {
+            for chunk in data.chunks(0xFFFF) {
+                unsafe { Transfer::new_memcpy_to_fixed(dma.reborrow(), chunk, self.data, Default::default()) }.await;
+            }
+            return;
+        }
+
+        for &word in data {
+            unsafe { self.data.write_volatile(word) };
+        }
+    }
+
+    async fn fill(&mut self, value: W, mut count: usize) {
+        #[cfg(any(dma, bdma))]
+        if let Some(dma) = &mut self.dma {
+            while count > 0 {
+                let chunk = count.min(0xFFFF);
+                unsafe { Transfer::new_memset_to_fixed(dma.reborrow(), &value, chunk, self.data, Default::default()) }
+                    .await;
+                count -= chunk;
+            }
+            return;
+        }
+
+        while count > 0 {
+            unsafe { self.data.write_volatile(value) };
+            count -= 1;
+        }
+    }
+}
+
+/// 8-bit display bus bit-banged on GPIO.
+///
+/// The eight data pins must be consecutive pins of one port, D0 being the lowest, so that the
+/// data is written at once. The chip select must be held low by hardware or by the user, and the
+/// read strobe high.
+pub struct GpioDisplayBus<'d> {
+    data: [Output<'d>; 8],
+    wr: Output<'d>,
+    dc: Output<'d>,
+}
+
+impl<'d> GpioDisplayBus<'d> {
+    /// Create a new GPIO display bus.
+    pub fn new(
+        d0: impl Peripheral<P = impl Pin> + 'd,
+        d1: impl Peripheral<P = impl Pin> + 'd,
+        d2: impl Peripheral<P = impl Pin> + 'd,
+        d3: impl Peripheral<P = impl Pin> + 'd,
+        d4: impl Peripheral<P = impl Pin> + 'd,
+        d5: impl Peripheral<P = impl Pin> + 'd,
+        d6: impl Peripheral<P = impl Pin> + 'd,
+        d7: impl Peripheral<P = impl Pin> + 'd,
+        wr: impl Peripheral<P = impl Pin> + 'd,
+        dc: impl Peripheral<P = impl Pin> + 'd,
+    ) -> Self {
+        let data = [
+            Output::new(d0, Level::Low, Speed::VeryHigh),
+            Output::new(d1, Level::Low, Speed::VeryHigh),
+            Output::new(d2, Level::Low, Speed::VeryHigh),
+            Output::new(d3, Level::Low, Speed::VeryHigh),
+            Output::new(d4, Level::Low, Speed::VeryHigh),
+            Output::new(d5, Level::Low, Speed::VeryHigh),
+            Output::new(d6, Level::Low, Speed::VeryHigh),
+            Output::new(d7, Level::Low, Speed::VeryHigh),
+        ];
+        let d0 = &data[0].pin.pin;
+        for (i, d) in data.iter().enumerate() {
+            assert!(d.pin.pin._port() == d0._port() && d.pin.pin._pin() == d0._pin() + i as u8);
+        }
+
+        Self {
+            data,
+            wr: Output::new(wr, Level::High, Speed::VeryHigh),
+            dc: Output::new(dc, Level::High, Speed::VeryHigh),
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        let d0 = &self.data[0].pin.pin;
+        let shift = d0._pin();
+        let set = (byte as u32) << shift;
+        let reset = (!byte as u32 & 0xFF) << shift;
+
+        // The display latches the data on the rising edge of WR.
+        self.wr.set_low();
+        d0.block().bsrr().write(|w| w.0 = set | reset << 16);
+        self.wr.set_high();
+    }
+}
+
+impl<'d> DisplayBus<u8> for GpioDisplayBus<'d> {
+    async fn write_command(&mut self, command: u8) {
+        self.dc.set_low();
+        self.write_byte(command);
+        self.dc.set_high();
+    }
+
+    async fn write_data(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.write_byte(byte);
+        }
+    }
+
+    async fn fill(&mut self, value: u8, count: usize) {
+        for _ in 0..count {
+            self.write_byte(value);
+        }
+    }
+}
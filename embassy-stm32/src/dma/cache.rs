@@ -0,0 +1,126 @@
+//! Data cache maintenance for DMA.
+//!
+//! Cortex-M7 chips (STM32F7, STM32H7) have a data cache the DMA controllers don't see. When it is
+//! enabled, a DMA transfer reading from memory can see stale data still sitting in the cache, and
+//! the CPU can read stale cached data after a DMA transfer wrote to memory.
+//!
+//! [`Transfer`](super::Transfer) takes care of this: before starting it cleans the cache lines
+//! covering the memory buffer, so the DMA reads up to date data, and after a peripheral-to-memory
+//! transfer it invalidates them, so the CPU reads what the DMA wrote. Nothing is done when the
+//! cache is disabled, or on chips without one.
+//!
+//! Cache maintenance works on whole 32-byte cache lines. Only the lines entirely covered by a
+//! buffer the DMA writes to are invalidated; the lines at its ends, which it may share with other
+//! data, are cleaned and invalidated, so CPU writes to that other data are never lost. If the CPU
+//! writes to data sharing a line with the buffer during the transfer, though, cleaning that line
+//! can overwrite the bytes of the buffer the DMA wrote meanwhile. Buffers aligned to cache lines
+//! and a multiple of their size, which [`DmaBuf`] provides, avoid this. Buffers in memory
+//! the cache doesn't cover need none of this: the DTCM on STM32F7/H7, which all DMA controllers
+//! but the H7 BDMA can access, or a region configured as non-cacheable in the
+//! [MPU](crate::mpu).
+//!
+//! Circular transfers (ring buffers) are not maintained; their buffers must be non-cacheable.
+
+use core::ops::{Deref, DerefMut};
+
+/// Size of a data cache line, in bytes.
+pub const CACHE_LINE_SIZE: usize = 32;
+
+/// DMA buffer aligned to data cache lines.
+///
+/// The alignment also pads the buffer to a multiple of the cache line size, so that no other data
+/// shares a cache line with it. See the [module documentation](self).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C, align(32))]
+pub struct DmaBuf<T>(pub T);
+
+impl<T> DmaBuf<T> {
+    /// Create a new buffer.
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// Get the inner value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for DmaBuf<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for DmaBuf<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// Memory range accessed by a DMA transfer.
+#[derive(Clone, Copy)]
+#[cfg_attr(not(armv7em), allow(dead_code))]
+pub(crate) struct Region {
+    addr: usize,
+    len: usize,
+}
+
+impl Region {
+    pub(crate) fn new<T>(addr: *const T, len: usize) -> Self {
+        Self {
+            addr: addr as usize,
+            len,
+        }
+    }
+
+    /// Write dirty cache lines of the region back to memory, before the DMA reads it.
+    pub(crate) fn clean(self) {
+        #[cfg(armv7em)]
+        if cortex_m::peripheral::SCB::dcache_enabled() {
+            let mut scb = unsafe { cortex_m::Peripherals::steal() }.SCB;
+            scb.clean_dcache_by_address(self.addr, self.len);
+        }
+    }
+
+    /// Write dirty cache lines of the region back to memory and drop them, before the DMA writes it.
+    ///
+    /// Dirty lines could otherwise be evicted during the transfer, overwriting what the DMA wrote.
+    pub(crate) fn clean_invalidate(self) {
+        #[cfg(armv7em)]
+        if cortex_m::peripheral::SCB::dcache_enabled() {
+            let mut scb = unsafe { cortex_m::Peripherals::steal() }.SCB;
+            scb.clean_invalidate_dcache_by_address(self.addr, self.len);
+        }
+    }
+
+    /// Drop the cache lines of the region, after the DMA wrote it.
+    ///
+    /// Only the lines entirely inside the region are dropped. The lines it shares with other data
+    /// at its ends are cleaned and dropped instead, so that whatever the CPU wrote there is kept.
+    pub(crate) fn invalidate(self) {
+        #[cfg(armv7em)]
+        if cortex_m::peripheral::SCB::dcache_enabled() {
+            let mut scb = unsafe { cortex_m::Peripherals::steal() }.SCB;
+            let end = self.addr + self.len;
+            let inner_start = self.addr.next_multiple_of(CACHE_LINE_SIZE);
+            let inner_end = end & !(CACHE_LINE_SIZE - 1);
+
+            if inner_start >= inner_end {
+                scb.clean_invalidate_dcache_by_address(self.addr, self.len);
+                return;
+            }
+            if self.addr < inner_start {
+                scb.clean_invalidate_dcache_by_address(self.addr, inner_start - self.addr);
+            }
+            // Safety: these lines hold nothing but the buffer, which the DMA just wrote.
+            unsafe { scb.invalidate_dcache_by_address(inner_start, inner_end - inner_start) };
+            if inner_end < end {
+                scb.clean_invalidate_dcache_by_address(inner_end, end - inner_end);
+            }
+        }
+    }
+}
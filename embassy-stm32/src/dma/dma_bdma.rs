@@ -1,14 +1,16 @@
 use core::future::{poll_fn, Future};
+use core::marker::PhantomData;
 use core::pin::Pin;
-use core::sync::atomic::{fence, AtomicUsize, Ordering};
+use core::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
 use core::task::{Context, Poll, Waker};
 
 use embassy_hal_internal::{into_ref, Peripheral, PeripheralRef};
 use embassy_sync::waitqueue::AtomicWaker;
 
+use super::cache::Region;
 use super::ringbuffer::{DmaCtrl, OverrunError, ReadableDmaRingBuffer, WritableDmaRingBuffer};
 use super::word::{Word, WordSize};
-use super::{AnyChannel, Channel, Dir, Request, STATE};
+use super::{AnyChannel, Channel, Dir, PeripheralRegister, Request, STATE};
 use crate::interrupt::typelevel::Interrupt;
 use crate::{interrupt, pac};
 
@@ -241,13 +243,36 @@ mod bdma_only {
 pub(crate) struct ChannelState {
     waker: AtomicWaker,
     complete_count: AtomicUsize,
+    /// Transfer complete count since the channel was configured, never reset by readers.
+    total_complete_count: AtomicUsize,
+    /// Segments of a scatter-gather transfer, chained from the irq.
+    sg_segments: AtomicPtr<RawSegment>,
+    sg_count: AtomicUsize,
+    /// Next segment not yet handed to the hardware.
+    sg_next: AtomicUsize,
+    /// Segment the hardware is currently transferring.
+    sg_active: AtomicUsize,
+    /// Set while a double-buffered stream is being stopped to finish its last segment on its own.
+    sg_tail: AtomicBool,
 }
 
 impl ChannelState {
     pub(crate) const NEW: Self = Self {
         waker: AtomicWaker::new(),
         complete_count: AtomicUsize::new(0),
+        total_complete_count: AtomicUsize::new(0),
+        sg_segments: AtomicPtr::new(core::ptr::null_mut()),
+        sg_count: AtomicUsize::new(0),
+        sg_next: AtomicUsize::new(0),
+        sg_active: AtomicUsize::new(0),
+        sg_tail: AtomicBool::new(false),
     };
+
+    /// Only called from the channel's irq, so a plain load/store is enough (and works on armv6m).
+    fn count_total_complete(&self) {
+        let x = self.total_complete_count.load(Ordering::Relaxed);
+        self.total_complete_count.store(x.wrapping_add(1), Ordering::Release);
+    }
 }
 
 /// safety: must be called only once
@@ -291,7 +316,11 @@ impl AnyChannel {
                 } else if isr.tcif(info.num % 4) && cr.read().tcie() {
                     // Acknowledge  transfer complete interrupt
                     r.ifcr(info.num / 4).write(|w| w.set_tcif(info.num % 4, true));
+                    if self.start_next_segment() {
+                        return;
+                    }
                     state.complete_count.fetch_add(1, Ordering::Release);
+                    state.count_total_complete();
                 } else {
                     return;
                 }
@@ -313,13 +342,17 @@ impl AnyChannel {
                 } else if isr.tcif(info.num) && cr.read().tcie() {
                     // Acknowledge transfer complete interrupt
                     r.ifcr().write(|w| w.set_tcif(info.num, true));
+                    if self.start_next_segment() {
+                        return;
+                    }
                     #[cfg(not(armv6m))]
                     state.complete_count.fetch_add(1, Ordering::Release);
                     #[cfg(armv6m)]
                     critical_section::with(|_| {
                         let x = state.complete_count.load(Ordering::Relaxed);
                         state.complete_count.store(x + 1, Ordering::Release);
-                    })
+                    });
+                    state.count_total_complete();
                 } else {
                     return;
                 }
@@ -347,6 +380,8 @@ impl AnyChannel {
 
         assert!(mem_len > 0 && mem_len <= 0xFFFF);
 
+        STATE[self.id as usize].total_complete_count.store(0, Ordering::Release);
+
         match self.info().dma {
             #[cfg(dma)]
             DmaInfo::Dma(r) => {
@@ -421,6 +456,163 @@ impl AnyChannel {
         }
     }
 
+    /// Program a stopped channel with segment `index` of a scatter-gather transfer.
+    ///
+    /// On DMA streams, a following segment of the same length goes into the second memory target
+    /// and the stream is put in double-buffer mode, so the hardware switches to it by itself.
+    unsafe fn load_segment(&self, segments: &[RawSegment], index: usize) {
+        let info = self.info();
+        let state = &STATE[self.id as usize];
+        let seg = &segments[index];
+        #[allow(unused_mut)]
+        let mut next = index + 1;
+
+        match self.info().dma {
+            #[cfg(dma)]
+            DmaInfo::Dma(r) => {
+                let ch = r.st(info.num);
+                // NDTR is reloaded with the same count for both memory targets.
+                let dbm = segments.get(next).is_some_and(|s| s.len == seg.len);
+                ch.m0ar().write_value(seg.addr as u32);
+                if dbm {
+                    ch.m1ar().write_value(segments[next].addr as u32);
+                    next += 1;
+                }
+                ch.ndtr().write_value(pac::dma::regs::Ndtr(seg.len as _));
+                ch.cr().modify(|w| {
+                    w.set_dbm(dbm);
+                    w.set_ct(pac::dma::vals::Ct::MEMORY0);
+                });
+            }
+            #[cfg(bdma)]
+            DmaInfo::Bdma(r) => {
+                let ch = r.ch(info.num);
+                ch.mar().write_value(seg.addr as u32);
+                ch.ndtr().write(|w| w.set_ndt(seg.len as u16));
+            }
+        }
+
+        state.sg_active.store(index, Ordering::Relaxed);
+        state.sg_next.store(next, Ordering::Relaxed);
+    }
+
+    /// Chain the next pending segment of a scatter-gather transfer, if any.
+    ///
+    /// Must be called from the irq, after the transfer complete flag of the previous
+    /// segment has been acknowledged. Returns `true` if the transfer goes on.
+    unsafe fn start_next_segment(&self) -> bool {
+        let info = self.info();
+        let state = &STATE[self.id as usize];
+
+        let count = state.sg_count.load(Ordering::Relaxed);
+        if count == 0 {
+            return false;
+        }
+        let segments = core::slice::from_raw_parts(state.sg_segments.load(Ordering::Relaxed), count);
+        let next = state.sg_next.load(Ordering::Relaxed);
+
+        match self.info().dma {
+            #[cfg(dma)]
+            DmaInfo::Dma(r) => {
+                let ch = r.st(info.num);
+                let cr = ch.cr().read();
+                let active = state.sg_active.load(Ordering::Relaxed);
+
+                if cr.dbm() && cr.en() {
+                    // The stream has switched to its other memory target, the one it just
+                    // finished is free for the segment after the now active one.
+                    let active = active + 1;
+                    state.sg_active.store(active, Ordering::Relaxed);
+                    if next < count && segments[next].len == segments[active].len {
+                        let idle = match cr.ct() {
+                            pac::dma::vals::Ct::MEMORY1 => ch.m0ar(),
+                            _ => ch.m1ar(),
+                        };
+                        idle.write_value(segments[next].addr as u32);
+                        state.sg_next.store(next + 1, Ordering::Relaxed);
+                    } else {
+                        // A stream never stops by itself in double-buffer mode. Stop it while it has only
+                        // just started the active segment; the stop raises TCIF again and the rest of
+                        // the segment is then transferred single-buffered.
+                        state.sg_tail.store(true, Ordering::Relaxed);
+                        ch.cr().modify(|w| w.set_en(false));
+                    }
+                    return true;
+                }
+
+                if state.sg_tail.load(Ordering::Relaxed) {
+                    state.sg_tail.store(false, Ordering::Relaxed);
+                    let remaining = ch.ndtr().read().ndt() as usize;
+                    if remaining > 0 {
+                        let seg = &segments[active];
+                        let word_bytes = match cr.msize() {
+                            pac::dma::vals::Size::BITS8 => 1,
+                            pac::dma::vals::Size::BITS16 => 2,
+                            _ => 4,
+                        };
+                        ch.m0ar()
+                            .write_value(seg.addr as u32 + ((seg.len - remaining) * word_bytes) as u32);
+                        ch.ndtr().write_value(pac::dma::regs::Ndtr(remaining as _));
+                        ch.cr().modify(|w| {
+                            w.set_dbm(false);
+                            w.set_ct(pac::dma::vals::Ct::MEMORY0);
+                            w.set_en(true);
+                        });
+                        return true;
+                    }
+                }
+
+                // A single-buffered stream clears EN by itself before raising TCIF.
+                if next >= count {
+                    return false;
+                }
+                self.load_segment(segments, next);
+                ch.cr().modify(|w| w.set_en(true));
+            }
+            #[cfg(bdma)]
+            DmaInfo::Bdma(r) => {
+                if next >= count {
+                    return false;
+                }
+                // BDMA channels stay enabled after completion, and MAR/NDTR are only writable while disabled.
+                let ch = r.ch(info.num);
+                ch.cr().modify(|w| w.set_en(false));
+                self.load_segment(segments, next);
+                ch.cr().modify(|w| w.set_en(true));
+            }
+        }
+
+        true
+    }
+
+    /// Switch a channel configured peripheral-to-memory (but not started) to memory-to-memory, the
+    /// "peripheral" address being the source, incremented only if `incr_src` is set.
+    fn set_mem2mem(&self, incr_src: bool) {
+        let info = self.info();
+        match self.info().dma {
+            #[cfg(dma)]
+            DmaInfo::Dma(r) => r.st(info.num).cr().modify(|w| {
+                w.set_dir(pac::dma::vals::Dir::MEMORYTOMEMORY);
+                w.set_pinc(incr_src);
+            }),
+            #[cfg(bdma)]
+            DmaInfo::Bdma(r) => r.ch(info.num).cr().modify(|w| {
+                w.set_mem2mem(true);
+                w.set_pinc(incr_src);
+            }),
+        }
+    }
+
+    /// Drop any pending scatter-gather segments so the irq no longer re-arms the channel.
+    fn clear_segments(&self) {
+        let state = &STATE[self.id as usize];
+        state.sg_count.store(0, Ordering::Relaxed);
+        state.sg_next.store(0, Ordering::Relaxed);
+        state.sg_active.store(0, Ordering::Relaxed);
+        state.sg_tail.store(false, Ordering::Relaxed);
+        state.sg_segments.store(core::ptr::null_mut(), Ordering::Relaxed);
+    }
+
     fn start(&self) {
         let info = self.info();
         match self.info().dma {
@@ -511,6 +703,51 @@ impl AnyChannel {
         }
     }
 
+    /// Raw values of the channel's CR and NDTR registers, for debugging.
+    pub(crate) fn raw_regs(&self) -> (u32, u32) {
+        let info = self.info();
+        match self.info().dma {
+            #[cfg(dma)]
+            DmaInfo::Dma(r) => (r.st(info.num).cr().read().0, r.st(info.num).ndtr().read().0),
+            #[cfg(bdma)]
+            DmaInfo::Bdma(r) => (r.ch(info.num).cr().read().0, r.ch(info.num).ndtr().read().0),
+        }
+    }
+
+    fn is_complete_flag_set(&self) -> bool {
+        let info = self.info();
+        match self.info().dma {
+            #[cfg(dma)]
+            DmaInfo::Dma(r) => r.isr(info.num / 4).read().tcif(info.num % 4),
+            #[cfg(bdma)]
+            DmaInfo::Bdma(r) => r.isr().read().tcif(info.num),
+        }
+    }
+
+    /// Get the number of completed transfers since the channel was configured, and the remaining
+    /// transfers of the current one, as a consistent pair.
+    ///
+    /// A transfer complete that happened but hasn't been handled by the irq yet is already counted.
+    fn get_total_complete_count_and_remaining(&self) -> (usize, u16) {
+        critical_section::with(|_| {
+            // The irq can't run in here, so this can only change if a new completion gets flagged.
+            let count = STATE[self.id as usize].total_complete_count.load(Ordering::Acquire);
+            loop {
+                let pending = self.is_complete_flag_set();
+                let remaining = self.get_remaining_transfers();
+                if pending == self.is_complete_flag_set() {
+                    return (count.wrapping_add(pending as usize), remaining);
+                }
+            }
+        })
+    }
+
+    /// Get the total number of elements transferred in circular mode since the channel was configured.
+    fn get_position(&self, len: usize) -> usize {
+        let (count, remaining) = self.get_total_complete_count_and_remaining();
+        count.wrapping_mul(len).wrapping_add(len - remaining as usize)
+    }
+
     fn disable_circular_mode(&self) {
         let info = self.info();
         match self.info().dma {
@@ -541,6 +778,7 @@ impl AnyChannel {
 #[must_use = "futures do nothing unless you `.await` or poll them"]
 pub struct Transfer<'a> {
     channel: PeripheralRef<'a, AnyChannel>,
+    invalidate: Option<Region>,
 }
 
 impl<'a> Transfer<'a> {
@@ -612,6 +850,45 @@ impl<'a> Transfer<'a> {
         )
     }
 
+    /// Create a new peripheral-to-peripheral DMA transfer.
+    ///
+    /// Copies `count` words from the `src` register to the `dst` register, one word each time
+    /// `request` is asserted, without any CPU involvement. The request paces the transfer: it is
+    /// either one of the two peripherals' own, e.g. the ADC's to forward each conversion as
+    /// soon as it is done, or a timer's update request to copy at a fixed rate, e.g. from the
+    /// ADC data register to the DAC holding register, or from a GPIO input to an output data
+    /// register.
+    ///
+    /// With [`TransferOptions::circular`] set, the transfer restarts by itself after `count`
+    /// words and only stops when dropped, for a continuous pass-through.
+    ///
+    /// Unlike the transfers to or from memory this is safe: both endpoints are registers whose
+    /// drivers stay borrowed for `'a`, and no buffer is left borrowed if the transfer is leaked.
+    pub fn new_peripheral_to_peripheral<W: Word>(
+        channel: impl Peripheral<P = impl Channel> + 'a,
+        request: Request,
+        src: PeripheralRegister<'a, W>,
+        count: usize,
+        dst: PeripheralRegister<'a, W>,
+        options: TransferOptions,
+    ) -> Self {
+        into_ref!(channel);
+
+        unsafe {
+            Self::new_inner(
+                channel.map_into(),
+                request,
+                Dir::PeripheralToMemory,
+                src.as_ptr() as *const u32,
+                dst.as_ptr() as *mut u32,
+                count,
+                false,
+                W::size(),
+                options,
+            )
+        }
+    }
+
     /// Create a new write DMA transfer (memory to peripheral), writing the same value repeatedly.
     pub unsafe fn new_write_repeated<W: Word>(
         channel: impl Peripheral<P = impl Channel> + 'a,
@@ -636,6 +913,100 @@ impl<'a> Transfer<'a> {
         )
     }
 
+    /// Create a new memory-to-memory DMA transfer.
+    ///
+    /// `src` and `dst` must have the same length. Not every DMA controller supports this; on
+    /// STM32F2/F4/F7 only the streams of DMA2 can do memory-to-memory transfers.
+    pub unsafe fn new_memcpy<W: Word>(
+        channel: impl Peripheral<P = impl Channel> + 'a,
+        src: &'a [W],
+        dst: &'a mut [W],
+        options: TransferOptions,
+    ) -> Self {
+        into_ref!(channel);
+        assert_eq!(src.len(), dst.len());
+        assert!(!dst.is_empty() && dst.len() <= 0xFFFF);
+
+        let channel: PeripheralRef<'a, AnyChannel> = channel.map_into();
+        let dst_region = Region::new(dst.as_ptr(), core::mem::size_of_val(dst));
+        Region::new(src.as_ptr(), core::mem::size_of_val(src)).clean();
+        dst_region.clean_invalidate();
+        channel.configure(
+            Request::default(),
+            Dir::PeripheralToMemory,
+            src.as_ptr() as *const u32,
+            dst.as_mut_ptr() as *mut u32,
+            dst.len(),
+            true,
+            W::size(),
+            options,
+        );
+        channel.set_mem2mem(true);
+        channel.start();
+
+        Self {
+            channel,
+            invalidate: Some(dst_region),
+        }
+    }
+
+    /// Create a new memory-to-memory DMA transfer to a fixed address.
+    ///
+    /// This is for memory-mapped registers of external devices, such as a display on the FMC.
+    pub unsafe fn new_memcpy_to_fixed<W: Word>(
+        channel: impl Peripheral<P = impl Channel> + 'a,
+        src: &'a [W],
+        dst: *mut W,
+        options: TransferOptions,
+    ) -> Self {
+        into_ref!(channel);
+        Self::new_mem2mem_fixed(channel.map_into(), src.as_ptr(), true, src.len(), dst, options)
+    }
+
+    /// Create a new memory-to-memory DMA transfer writing the same value repeatedly to a fixed address.
+    pub unsafe fn new_memset_to_fixed<W: Word>(
+        channel: impl Peripheral<P = impl Channel> + 'a,
+        repeated: &'a W,
+        count: usize,
+        dst: *mut W,
+        options: TransferOptions,
+    ) -> Self {
+        into_ref!(channel);
+        Self::new_mem2mem_fixed(channel.map_into(), repeated, false, count, dst, options)
+    }
+
+    unsafe fn new_mem2mem_fixed<W: Word>(
+        channel: PeripheralRef<'a, AnyChannel>,
+        src: *const W,
+        incr_src: bool,
+        count: usize,
+        dst: *mut W,
+        options: TransferOptions,
+    ) -> Self {
+        assert!(count > 0 && count <= 0xFFFF);
+
+        let src_len = if incr_src { count } else { 1 };
+        Region::new(src, src_len * W::size().bytes()).clean();
+
+        channel.configure(
+            Request::default(),
+            Dir::PeripheralToMemory,
+            src as *const u32,
+            dst as *mut u32,
+            count,
+            false,
+            W::size(),
+            options,
+        );
+        channel.set_mem2mem(incr_src);
+        channel.start();
+
+        Self {
+            channel,
+            invalidate: None,
+        }
+    }
+
     unsafe fn new_inner(
         channel: PeripheralRef<'a, AnyChannel>,
         _request: Request,
@@ -649,12 +1020,24 @@ impl<'a> Transfer<'a> {
     ) -> Self {
         assert!(mem_len > 0 && mem_len <= 0xFFFF);
 
+        let region = Region::new(mem_addr, if incr_mem { mem_len } else { 1 } * data_size.bytes());
+        let invalidate = match dir {
+            Dir::MemoryToPeripheral => {
+                region.clean();
+                None
+            }
+            Dir::PeripheralToMemory => {
+                region.clean_invalidate();
+                Some(region)
+            }
+        };
+
         channel.configure(
             _request, dir, peri_addr, mem_addr, mem_len, incr_mem, data_size, options,
         );
         channel.start();
 
-        Self { channel }
+        Self { channel, invalidate }
     }
 
     /// Request the transfer to stop.
@@ -685,6 +1068,10 @@ impl<'a> Transfer<'a> {
         // "Subsequent reads and writes cannot be moved ahead of preceding reads."
         fence(Ordering::SeqCst);
 
+        if let Some(region) = self.invalidate {
+            region.invalidate();
+        }
+
         core::mem::forget(self);
     }
 }
@@ -696,6 +1083,10 @@ impl<'a> Drop for Transfer<'a> {
 
         // "Subsequent reads and writes cannot be moved ahead of preceding reads."
         fence(Ordering::SeqCst);
+
+        if let Some(region) = self.invalidate {
+            region.invalidate();
+        }
     }
 }
 
@@ -715,6 +1106,237 @@ impl<'a> Future for Transfer<'a> {
     }
 }
 
+/// Raw memory segment, as seen by the irq handler.
+#[repr(C)]
+pub(crate) struct RawSegment {
+    addr: *mut u32,
+    len: usize,
+}
+
+// Only an address and a length, the buffer itself is borrowed by the `Segment`.
+unsafe impl Sync for RawSegment {}
+
+impl RawSegment {
+    fn region(&self, word_bytes: usize) -> Region {
+        Region::new(self.addr, self.len * word_bytes)
+    }
+}
+
+/// One memory segment of a [`ScatterGatherTransfer`].
+///
+/// A segment is a plain buffer; a list of them is transferred back-to-back to or from
+/// the same peripheral address.
+#[repr(transparent)]
+pub struct Segment<'a, W: Word> {
+    raw: RawSegment,
+    _phantom: PhantomData<&'a mut [W]>,
+}
+
+impl<'a, W: Word> Segment<'a, W> {
+    /// Create a segment the DMA writes into (for peripheral to memory transfers).
+    pub fn new_read(buf: &'a mut [W]) -> Self {
+        Self::new_raw(buf.as_mut_ptr(), buf.len())
+    }
+
+    /// Create a segment the DMA reads from (for memory to peripheral transfers).
+    pub fn new_write(buf: &'a [W]) -> Self {
+        Self::new_raw(buf.as_ptr() as *mut W, buf.len())
+    }
+
+    fn new_raw(ptr: *mut W, len: usize) -> Self {
+        assert!(len > 0 && len <= 0xFFFF);
+        Self {
+            raw: RawSegment {
+                addr: ptr as *mut u32,
+                len,
+            },
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Scatter-gather DMA transfer.
+///
+/// Transfers a list of [`Segment`]s back-to-back to or from a single peripheral address,
+/// so that e.g. a header and a payload can be sent without copying them into one contiguous buffer.
+///
+/// On DMA streams, consecutive segments of the same length are chained in double-buffer mode: the
+/// hardware switches to the next segment by itself and the interrupt only loads the one after it.
+/// Otherwise (BDMA channels, or a change of length between segments) the channel is re-armed from
+/// the transfer complete interrupt, so there is a short gap between segments equal to the DMA
+/// interrupt latency. The peripheral stalls on its DMA request during that gap; no data is lost or
+/// repeated.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ScatterGatherTransfer<'a> {
+    channel: PeripheralRef<'a, AnyChannel>,
+    invalidate: Option<(&'a [RawSegment], usize)>,
+}
+
+impl<'a> ScatterGatherTransfer<'a> {
+    /// Create a new scatter-gather read transfer (peripheral to memory).
+    ///
+    /// The segments must have been created with [`Segment::new_read`].
+    pub unsafe fn new_read<W: Word>(
+        channel: impl Peripheral<P = impl Channel> + 'a,
+        request: Request,
+        peri_addr: *mut W,
+        segments: &'a [Segment<'a, W>],
+        options: TransferOptions,
+    ) -> Self {
+        into_ref!(channel);
+
+        Self::new_inner(
+            channel.map_into(),
+            request,
+            Dir::PeripheralToMemory,
+            peri_addr as *const u32,
+            segments,
+            W::size(),
+            options,
+        )
+    }
+
+    /// Create a new scatter-gather write transfer (memory to peripheral).
+    ///
+    /// The segments must have been created with [`Segment::new_write`].
+    pub unsafe fn new_write<W: Word>(
+        channel: impl Peripheral<P = impl Channel> + 'a,
+        request: Request,
+        segments: &'a [Segment<'a, W>],
+        peri_addr: *mut W,
+        options: TransferOptions,
+    ) -> Self {
+        into_ref!(channel);
+
+        Self::new_inner(
+            channel.map_into(),
+            request,
+            Dir::MemoryToPeripheral,
+            peri_addr as *const u32,
+            segments,
+            W::size(),
+            options,
+        )
+    }
+
+    unsafe fn new_inner<W: Word>(
+        channel: PeripheralRef<'a, AnyChannel>,
+        request: Request,
+        dir: Dir,
+        peri_addr: *const u32,
+        segments: &'a [Segment<'a, W>],
+        data_size: WordSize,
+        mut options: TransferOptions,
+    ) -> Self {
+        assert!(!segments.is_empty());
+
+        // The transfer complete interrupt is what chains the segments together.
+        options.complete_transfer_ir = true;
+        options.circular = false;
+
+        // Safety: `Segment` is a transparent wrapper around `RawSegment`.
+        let raw = unsafe { core::slice::from_raw_parts(segments.as_ptr() as *const RawSegment, segments.len()) };
+        let word_bytes = data_size.bytes();
+        for segment in raw {
+            match dir {
+                Dir::MemoryToPeripheral => segment.region(word_bytes).clean(),
+                Dir::PeripheralToMemory => segment.region(word_bytes).clean_invalidate(),
+            }
+        }
+        let invalidate = (dir == Dir::PeripheralToMemory).then_some((raw, word_bytes));
+
+        let first = &segments[0].raw;
+        channel.configure(request, dir, peri_addr, first.addr, first.len, true, data_size, options);
+
+        let state = &STATE[channel.id as usize];
+        state
+            .sg_segments
+            .store(raw.as_ptr() as *mut RawSegment, Ordering::Relaxed);
+        state.sg_count.store(raw.len(), Ordering::Relaxed);
+        state.sg_tail.store(false, Ordering::Relaxed);
+        channel.load_segment(raw, 0);
+
+        channel.start();
+
+        Self { channel, invalidate }
+    }
+
+    /// Request the transfer to stop.
+    ///
+    /// Pending segments are discarded. This doesn't immediately stop the transfer,
+    /// you have to wait until [`is_running`](Self::is_running) returns false.
+    pub fn request_stop(&mut self) {
+        self.channel.clear_segments();
+        self.channel.request_stop()
+    }
+
+    /// Return whether this transfer is still running.
+    pub fn is_running(&mut self) -> bool {
+        let state = &STATE[self.channel.id as usize];
+        state.sg_next.load(Ordering::Relaxed) < state.sg_count.load(Ordering::Relaxed)
+            || state.sg_tail.load(Ordering::Relaxed)
+            || self.channel.is_running()
+    }
+
+    /// Gets the index of the segment currently being transferred.
+    pub fn current_segment(&self) -> usize {
+        STATE[self.channel.id as usize].sg_active.load(Ordering::Relaxed)
+    }
+
+    /// Gets the remaining transfers of the current segment.
+    pub fn get_remaining_transfers(&self) -> u16 {
+        self.channel.get_remaining_transfers()
+    }
+
+    /// Blocking wait until the transfer finishes.
+    pub fn blocking_wait(mut self) {
+        while self.is_running() {}
+
+        // "Subsequent reads and writes cannot be moved ahead of preceding reads."
+        fence(Ordering::SeqCst);
+
+        self.channel.clear_segments();
+        if let Some((segments, word_bytes)) = self.invalidate {
+            for segment in segments {
+                segment.region(word_bytes).invalidate();
+            }
+        }
+        core::mem::forget(self);
+    }
+}
+
+impl<'a> Drop for ScatterGatherTransfer<'a> {
+    fn drop(&mut self) {
+        self.request_stop();
+        while self.channel.is_running() {}
+
+        // "Subsequent reads and writes cannot be moved ahead of preceding reads."
+        fence(Ordering::SeqCst);
+
+        if let Some((segments, word_bytes)) = self.invalidate {
+            for segment in segments {
+                segment.region(word_bytes).invalidate();
+            }
+        }
+    }
+}
+
+impl<'a> Unpin for ScatterGatherTransfer<'a> {}
+impl<'a> Future for ScatterGatherTransfer<'a> {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state: &ChannelState = &STATE[self.channel.id as usize];
+
+        state.waker.register(cx.waker());
+
+        if self.is_running() {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
+
 // ==============================
 
 struct DmaCtrlImpl<'a>(PeripheralRef<'a, AnyChannel>);
@@ -831,6 +1453,21 @@ impl<'a, W: Word> ReadableRingBuffer<'a, W> {
         self.ringbuf.cap()
     }
 
+    /// Get the number of times the DMA has wrapped around the buffer since it was created.
+    ///
+    /// Unlike the count used internally to detect overruns, this is not reset by reading or clearing.
+    pub fn get_complete_count(&self) -> usize {
+        self.channel.get_total_complete_count_and_remaining().0
+    }
+
+    /// Get the total number of elements the DMA has written to the buffer since it was created.
+    ///
+    /// This keeps counting across wraparounds, so it can be used to compute exactly how far a
+    /// stream has progressed. It wraps around at `usize::MAX`.
+    pub fn get_position(&self) -> usize {
+        self.channel.get_position(self.ringbuf.cap())
+    }
+
     /// Set a waker to be woken when at least one byte is received.
     pub fn set_waker(&mut self, waker: &Waker) {
         DmaCtrlImpl(self.channel.reborrow()).set_waker(waker);
@@ -961,6 +1598,21 @@ impl<'a, W: Word> WritableRingBuffer<'a, W> {
         self.ringbuf.cap()
     }
 
+    /// Get the number of times the DMA has wrapped around the buffer since it was created.
+    ///
+    /// Unlike the count used internally to detect overruns, this is not reset by reading or clearing.
+    pub fn get_complete_count(&self) -> usize {
+        self.channel.get_total_complete_count_and_remaining().0
+    }
+
+    /// Get the total number of elements the DMA has read from the buffer since it was created.
+    ///
+    /// This keeps counting across wraparounds, so it can be used to compute exactly how far a
+    /// stream has progressed. It wraps around at `usize::MAX`.
+    pub fn get_position(&self) -> usize {
+        self.channel.get_position(self.ringbuf.cap())
+    }
+
     /// Set a waker to be woken when at least one byte is received.
     pub fn set_waker(&mut self, waker: &Waker) {
         DmaCtrlImpl(self.channel.reborrow()).set_waker(waker);
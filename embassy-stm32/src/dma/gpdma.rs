@@ -8,8 +8,9 @@ use core::task::{Context, Poll};
 use embassy_hal_internal::{into_ref, Peripheral, PeripheralRef};
 use embassy_sync::waitqueue::AtomicWaker;
 
+use super::cache::Region;
 use super::word::{Word, WordSize};
-use super::{AnyChannel, Channel, Dir, Request, STATE};
+use super::{AnyChannel, Channel, Dir, PeripheralRegister, Request, STATE};
 use crate::interrupt::typelevel::Interrupt;
 use crate::interrupt::Priority;
 use crate::pac;
@@ -101,6 +102,7 @@ impl AnyChannel {
 #[must_use = "futures do nothing unless you `.await` or poll them"]
 pub struct Transfer<'a> {
     channel: PeripheralRef<'a, AnyChannel>,
+    invalidate: Option<Region>,
 }
 
 impl<'a> Transfer<'a> {
@@ -172,6 +174,42 @@ impl<'a> Transfer<'a> {
         )
     }
 
+    /// Create a new peripheral-to-peripheral DMA transfer.
+    ///
+    /// Copies `count` words from the `src` register to the `dst` register, one word each time
+    /// `request` is asserted, without any CPU involvement. The request paces the transfer: it is
+    /// either one of the two peripherals' own, e.g. the ADC's to forward each conversion as
+    /// soon as it is done, or a timer's update request to copy at a fixed rate, e.g. from the
+    /// ADC data register to the DAC holding register, or from a GPIO input to an output data
+    /// register.
+    ///
+    /// Unlike the transfers to or from memory this is safe: both endpoints are registers whose
+    /// drivers stay borrowed for `'a`, and no buffer is left borrowed if the transfer is leaked.
+    pub fn new_peripheral_to_peripheral<W: Word>(
+        channel: impl Peripheral<P = impl Channel> + 'a,
+        request: Request,
+        src: PeripheralRegister<'a, W>,
+        count: usize,
+        dst: PeripheralRegister<'a, W>,
+        options: TransferOptions,
+    ) -> Self {
+        into_ref!(channel);
+
+        unsafe {
+            Self::new_inner(
+                channel.map_into(),
+                request,
+                Dir::PeripheralToMemory,
+                src.as_ptr() as *const u32,
+                dst.as_ptr() as *mut u32,
+                count,
+                false,
+                W::size(),
+                options,
+            )
+        }
+    }
+
     /// Create a new write DMA transfer (memory to peripheral), writing the same value repeatedly.
     pub unsafe fn new_write_repeated<W: Word>(
         channel: impl Peripheral<P = impl Channel> + 'a,
@@ -212,10 +250,22 @@ impl<'a> Transfer<'a> {
         let info = channel.info();
         let ch = info.dma.ch(info.num);
 
+        let region = Region::new(mem_addr, if incr_mem { mem_len } else { 1 } * data_size.bytes());
+        let invalidate = match dir {
+            Dir::MemoryToPeripheral => {
+                region.clean();
+                None
+            }
+            Dir::PeripheralToMemory => {
+                region.clean_invalidate();
+                Some(region)
+            }
+        };
+
         // "Preceding reads and writes cannot be moved past subsequent writes."
         fence(Ordering::SeqCst);
 
-        let this = Self { channel };
+        let this = Self { channel, invalidate };
 
         #[cfg(dmamux)]
         super::dmamux::configure_dmamux(&*this.channel, request);
@@ -304,6 +354,10 @@ impl<'a> Transfer<'a> {
         // "Subsequent reads and writes cannot be moved ahead of preceding reads."
         fence(Ordering::SeqCst);
 
+        if let Some(region) = self.invalidate {
+            region.invalidate();
+        }
+
         core::mem::forget(self);
     }
 }
@@ -315,6 +369,10 @@ impl<'a> Drop for Transfer<'a> {
 
         // "Subsequent reads and writes cannot be moved ahead of preceding reads."
         fence(Ordering::SeqCst);
+
+        if let Some(region) = self.invalidate {
+            region.invalidate();
+        }
     }
 }
 
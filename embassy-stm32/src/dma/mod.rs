@@ -19,6 +19,15 @@ pub(crate) use dmamux::*;
 mod util;
 pub(crate) use util::*;
 
+mod pool;
+pub use pool::*;
+
+mod register;
+pub use register::*;
+
+pub mod cache;
+pub use cache::DmaBuf;
+
 pub(crate) mod ringbuffer;
 pub mod word;
 
@@ -108,6 +117,26 @@ impl Channel for AnyChannel {}
 const CHANNEL_COUNT: usize = crate::_generated::DMA_CHANNELS.len();
 static STATE: [ChannelState; CHANNEL_COUNT] = [ChannelState::NEW; CHANNEL_COUNT];
 
+/// Error returned when a DMA transfer doesn't complete within its timeout.
+#[cfg(feature = "time")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeoutError;
+
+#[cfg(feature = "time")]
+impl<'a> Transfer<'a> {
+    /// Wait for the transfer to complete, giving up after `timeout`.
+    ///
+    /// If the timeout expires the transfer is stopped before returning, so the buffer is no longer
+    /// accessed by the DMA. This is useful when the peripheral may never issue the remaining DMA
+    /// requests, for example an SPI slave whose master stops clocking.
+    pub async fn with_timeout(self, timeout: embassy_time::Duration) -> Result<(), TimeoutError> {
+        embassy_time::with_timeout(timeout, self)
+            .await
+            .map_err(|_| TimeoutError)
+    }
+}
+
 /// "No DMA" placeholder.
 ///
 /// You may pass this in place of a real DMA channel when creating a driver
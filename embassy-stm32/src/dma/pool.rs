@@ -0,0 +1,125 @@
+//! Runtime DMA channel allocation.
+//!
+//! Most drivers statically own the DMA channels they use. That is wasteful for code that only
+//! needs DMA occasionally, such as memory copies or one-shot transfers: a [`DmaPool`] owns a set
+//! of channels and hands out whichever one is free when it's needed.
+//!
+//! Pooled channels are type-erased [`AnyChannel`]s, so they can be used anywhere a generic
+//! [`Channel`](super::Channel) is accepted, like [`Transfer`](super::Transfer). Drivers that
+//! require a channel of a specific type (e.g. `RxDma<T>`) can't take pooled channels. On chips
+//! without a DMAMUX each request is only routed to some channels, so only put channels in a pool
+//! that can serve every request it will be used with.
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::ops::{Deref, DerefMut};
+use core::task::Poll;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::waitqueue::WakerRegistration;
+
+use super::{AnyChannel, SealedChannel};
+
+struct PoolState {
+    used: u32,
+    waker: WakerRegistration,
+}
+
+/// Pool of DMA channels allocated at runtime.
+///
+/// Holds up to 32 channels.
+pub struct DmaPool<const N: usize> {
+    ids: [u8; N],
+    state: Mutex<CriticalSectionRawMutex, RefCell<PoolState>>,
+}
+
+impl<const N: usize> DmaPool<N> {
+    /// Create a new pool owning `channels`.
+    pub fn new(channels: [AnyChannel; N]) -> Self {
+        assert!(N <= 32);
+        Self {
+            ids: channels.map(|ch| ch.id()),
+            state: Mutex::new(RefCell::new(PoolState {
+                used: 0,
+                waker: WakerRegistration::new(),
+            })),
+        }
+    }
+
+    /// Take a free channel from the pool, or return `None` if all channels are in use.
+    pub fn try_acquire(&self) -> Option<PooledChannel<'_, N>> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let index = (!s.used).trailing_zeros() as usize;
+            if index >= N {
+                return None;
+            }
+            s.used |= 1 << index;
+            Some(PooledChannel {
+                pool: self,
+                index,
+                channel: AnyChannel { id: self.ids[index] },
+            })
+        })
+    }
+
+    /// Take a free channel from the pool, waiting until one is released if all channels are in use.
+    pub async fn acquire(&self) -> PooledChannel<'_, N> {
+        poll_fn(|cx| match self.try_acquire() {
+            Some(ch) => Poll::Ready(ch),
+            None => {
+                self.state.lock(|s| s.borrow_mut().waker.register(cx.waker()));
+                // A channel may have been released between `try_acquire` and registering the waker.
+                match self.try_acquire() {
+                    Some(ch) => Poll::Ready(ch),
+                    None => Poll::Pending,
+                }
+            }
+        })
+        .await
+    }
+
+    /// Number of channels currently free.
+    pub fn free(&self) -> usize {
+        self.state.lock(|s| N - s.borrow().used.count_ones() as usize)
+    }
+
+    fn release(&self, index: usize) {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.used &= !(1 << index);
+            s.waker.wake();
+        })
+    }
+}
+
+/// DMA channel borrowed from a [`DmaPool`].
+///
+/// The channel is returned to the pool when this is dropped. Transfers borrow the channel, so it
+/// can't be returned while still in use.
+pub struct PooledChannel<'a, const N: usize> {
+    pool: &'a DmaPool<N>,
+    index: usize,
+    channel: AnyChannel,
+}
+
+impl<'a, const N: usize> Deref for PooledChannel<'a, N> {
+    type Target = AnyChannel;
+
+    fn deref(&self) -> &AnyChannel {
+        &self.channel
+    }
+}
+
+impl<'a, const N: usize> DerefMut for PooledChannel<'a, N> {
+    fn deref_mut(&mut self) -> &mut AnyChannel {
+        &mut self.channel
+    }
+}
+
+impl<'a, const N: usize> Drop for PooledChannel<'a, N> {
+    fn drop(&mut self) {
+        self.pool.release(self.index);
+    }
+}
@@ -0,0 +1,33 @@
+use core::marker::PhantomData;
+
+use super::word::Word;
+
+/// A peripheral data register, as an endpoint of a peripheral-to-peripheral DMA transfer.
+///
+/// Drivers hand these out for the registers they own, e.g. [`Adc::data_register`](crate::adc::Adc::data_register)
+/// or [`DacChannel::data_register`](crate::dac::DacChannel::data_register). The driver stays borrowed
+/// for `'a`, so it can't be reconfigured or dropped while a transfer uses the register.
+pub struct PeripheralRegister<'a, W: Word> {
+    addr: *mut W,
+    _phantom: PhantomData<&'a mut W>,
+}
+
+impl<'a, W: Word> PeripheralRegister<'a, W> {
+    /// Create an endpoint from the raw address of a register.
+    ///
+    /// # Safety
+    ///
+    /// `addr` must be a peripheral register that can be accessed with `W`-sized accesses, and the
+    /// peripheral must stay configured for `'a`. It must not be a memory address: DMA transfers
+    /// between two registers don't keep any buffer borrowed.
+    pub unsafe fn new(addr: *mut W) -> Self {
+        Self {
+            addr,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut W {
+        self.addr
+    }
+}
@@ -2,6 +2,8 @@ use embassy_hal_internal::PeripheralRef;
 
 use super::word::Word;
 use super::{AnyChannel, Request, Transfer, TransferOptions};
+#[cfg(any(bdma, dma))]
+use super::{ScatterGatherTransfer, Segment};
 
 /// Convenience wrapper, contains a channel and a request number.
 ///
@@ -58,4 +60,26 @@ impl<'d> ChannelAndRequest<'d> {
     ) -> Transfer<'a> {
         Transfer::new_write_repeated(&mut self.channel, self.request, repeated, count, peri_addr, options)
     }
+
+    #[cfg(any(bdma, dma))]
+    #[allow(dead_code)]
+    pub unsafe fn write_segments<'a, W: Word>(
+        &'a mut self,
+        segments: &'a [Segment<'a, W>],
+        peri_addr: *mut W,
+        options: TransferOptions,
+    ) -> ScatterGatherTransfer<'a> {
+        ScatterGatherTransfer::new_write(&mut self.channel, self.request, segments, peri_addr, options)
+    }
+
+    #[cfg(any(bdma, dma))]
+    #[allow(dead_code)]
+    pub unsafe fn read_segments<'a, W: Word>(
+        &'a mut self,
+        peri_addr: *mut W,
+        segments: &'a [Segment<'a, W>],
+        options: TransferOptions,
+    ) -> ScatterGatherTransfer<'a> {
+        ScatterGatherTransfer::new_read(&mut self.channel, self.request, peri_addr, segments, options)
+    }
 }
@@ -0,0 +1,389 @@
+//! Chrom-ART Accelerator (DMA2D)
+//!
+//! 2D DMA engine for graphics: fills rectangles with a color, copies rectangles between
+//! framebuffers with optional pixel format conversion, and alpha-blends two images.
+//!
+//! Images are described by a byte slice, a [`PixelFormat`] and a line offset, which is the
+//! number of pixels to skip at the end of each line. This allows operating on a rectangle
+//! within a larger framebuffer.
+//!
+//! The DMA2D accesses memory directly, so on chips with a data cache (F7, H7) the buffers must
+//! be cleaned/invalidated, or placed in non-cacheable memory.
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy_hal_internal::drop::OnDrop;
+use embassy_hal_internal::{into_ref, PeripheralRef};
+use embassy_sync::waitqueue::AtomicWaker;
+
+use crate::interrupt::typelevel::Interrupt;
+use crate::pac::dma2d::vals;
+use crate::{interrupt, pac, peripherals, rcc, Peripheral};
+
+static DMA2D_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// DMA2D interrupt handler.
+pub struct InterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        let isr = T::regs().isr().read();
+        if isr.tcif() || isr.tif() || isr.ceif() {
+            T::regs().cr().modify(|w| {
+                w.set_tcie(false);
+                w.set_tie(false);
+                w.set_ceie(false);
+            });
+            DMA2D_WAKER.wake();
+        }
+    }
+}
+
+/// DMA2D error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A bus error occurred while accessing one of the images.
+    Transfer,
+    /// The hardware rejected the configuration, e.g. because of a misaligned address.
+    Configuration,
+}
+
+/// Pixel format.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PixelFormat {
+    /// 32 bits per pixel, 8 bits per channel.
+    Argb8888,
+    /// 24 bits per pixel, 8 bits per channel.
+    Rgb888,
+    /// 16 bits per pixel: 5 bits red, 6 bits green, 5 bits blue.
+    Rgb565,
+    /// 16 bits per pixel: 1 bit alpha, 5 bits per color channel.
+    Argb1555,
+    /// 16 bits per pixel, 4 bits per channel.
+    Argb4444,
+}
+
+impl PixelFormat {
+    /// Size of a pixel in bytes.
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Argb8888 => 4,
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Rgb565 | PixelFormat::Argb1555 | PixelFormat::Argb4444 => 2,
+        }
+    }
+
+    /// Value of the CM fields of the PFC control registers.
+    fn bits(self) -> u8 {
+        match self {
+            PixelFormat::Argb8888 => 0,
+            PixelFormat::Rgb888 => 1,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Argb1555 => 3,
+            PixelFormat::Argb4444 => 4,
+        }
+    }
+
+    /// Convert an ARGB8888 color to this format, as expected by the output color register.
+    fn encode(self, argb: u32) -> u32 {
+        let [b, g, r, a] = argb.to_le_bytes().map(|c| c as u32);
+        match self {
+            PixelFormat::Argb8888 | PixelFormat::Rgb888 => argb,
+            PixelFormat::Rgb565 => ((r >> 3) << 11) | ((g >> 2) << 5) | (b >> 3),
+            PixelFormat::Argb1555 => ((a >> 7) << 15) | ((r >> 3) << 10) | ((g >> 3) << 5) | (b >> 3),
+            PixelFormat::Argb4444 => ((a >> 4) << 12) | ((r >> 4) << 8) | ((g >> 4) << 4) | (b >> 4),
+        }
+    }
+}
+
+/// How the alpha channel of a source image is modified before blending.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Alpha {
+    /// Use the alpha of each pixel as is (fully opaque for formats without alpha).
+    Keep,
+    /// Replace the alpha of every pixel with this value.
+    Replace(u8),
+    /// Multiply the alpha of every pixel with this value, divided by 255.
+    Multiply(u8),
+}
+
+/// Source image of a copy or blend.
+pub struct Source<'a> {
+    /// Pixel data, starting at the top-left pixel of the rectangle.
+    pub data: &'a [u8],
+    /// Pixel format of `data`.
+    pub format: PixelFormat,
+    /// Number of pixels to skip between the end of a line and the start of the next one.
+    pub line_offset: u16,
+    /// Alpha modification.
+    pub alpha: Alpha,
+}
+
+impl<'a> Source<'a> {
+    /// Create a source for a contiguous image, with its alpha unmodified.
+    pub fn new(data: &'a [u8], format: PixelFormat) -> Self {
+        Self {
+            data,
+            format,
+            line_offset: 0,
+            alpha: Alpha::Keep,
+        }
+    }
+}
+
+/// Destination image of a DMA2D operation.
+pub struct Target<'a> {
+    /// Pixel data, starting at the top-left pixel of the rectangle.
+    pub data: &'a mut [u8],
+    /// Pixel format of `data`.
+    pub format: PixelFormat,
+    /// Number of pixels to skip between the end of a line and the start of the next one.
+    pub line_offset: u16,
+}
+
+impl<'a> Target<'a> {
+    /// Create a target for a contiguous image.
+    pub fn new(data: &'a mut [u8], format: PixelFormat) -> Self {
+        Self {
+            data,
+            format,
+            line_offset: 0,
+        }
+    }
+}
+
+/// Check an image covers a `width` x `height` rectangle, and return its address.
+fn check_image(data: *const u8, len: usize, format: PixelFormat, line_offset: u16, width: u16, height: u16) -> u32 {
+    assert!(width > 0 && height > 0);
+    assert!(width < 1 << 14);
+    assert!(line_offset < 1 << 14);
+    let pixels = (width as usize + line_offset as usize) * (height as usize - 1) + width as usize;
+    assert!(len >= pixels * format.bytes_per_pixel());
+    data as u32
+}
+
+/// DMA2D driver.
+pub struct Dma2d<'d, T: Instance> {
+    _peri: PeripheralRef<'d, T>,
+}
+
+impl<'d, T: Instance> Dma2d<'d, T> {
+    /// Create a new DMA2D driver.
+    pub fn new(
+        peri: impl Peripheral<P = T> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+    ) -> Self {
+        into_ref!(peri);
+        rcc::enable_and_reset::<T>();
+
+        T::Interrupt::unpend();
+        unsafe { T::Interrupt::enable() };
+
+        Self { _peri: peri }
+    }
+
+    /// Fill a `width` x `height` rectangle of `dst` with an ARGB8888 `color`.
+    ///
+    /// The color is converted to the target format.
+    pub async fn fill(&mut self, dst: &mut Target<'_>, width: u16, height: u16, color: u32) -> Result<(), Error> {
+        self.set_output(dst, width, height);
+        T::regs()
+            .ocolr()
+            .write_value(pac::dma2d::regs::Ocolr(dst.format.encode(color)));
+
+        self.run(vals::Mode::REGISTERTOMEMORY).await
+    }
+
+    /// Copy a `width` x `height` rectangle from `src` to `dst`.
+    ///
+    /// Pixels are converted if the formats differ, or if the source alpha is modified.
+    pub async fn copy(&mut self, src: &Source<'_>, dst: &mut Target<'_>, width: u16, height: u16) -> Result<(), Error> {
+        self.set_output(dst, width, height);
+        self.set_foreground(src, width, height);
+
+        let mode = if src.format == dst.format && src.alpha == Alpha::Keep {
+            vals::Mode::MEMORYTOMEMORY
+        } else {
+            vals::Mode::MEMORYTOMEMORYPFC
+        };
+        self.run(mode).await
+    }
+
+    /// Blend a `width` x `height` rectangle of `fg` over `bg`, writing the result to `dst`.
+    ///
+    /// `bg` and `dst` may be the same framebuffer. To do that, build the `Source` from a raw
+    /// pointer to the target data, since the borrow checker won't allow both.
+    pub async fn blend(
+        &mut self,
+        fg: &Source<'_>,
+        bg: &Source<'_>,
+        dst: &mut Target<'_>,
+        width: u16,
+        height: u16,
+    ) -> Result<(), Error> {
+        self.set_output(dst, width, height);
+        self.set_foreground(fg, width, height);
+
+        let r = T::regs();
+        let addr = check_image(
+            bg.data.as_ptr(),
+            bg.data.len(),
+            bg.format,
+            bg.line_offset,
+            width,
+            height,
+        );
+        r.bgmar().write(|w| w.set_ma(addr));
+        r.bgor().write(|w| w.set_lo(bg.line_offset));
+        r.bgpfccr().write(|w| {
+            w.set_cm(vals::BgpfccrCm::from_bits(bg.format.bits()));
+            let (am, alpha) = alpha_bits(bg.alpha);
+            w.set_am(vals::BgpfccrAm::from_bits(am));
+            w.set_alpha(alpha);
+        });
+
+        self.run(vals::Mode::MEMORYTOMEMORYPFCBLENDING).await
+    }
+
+    fn set_output(&mut self, dst: &mut Target<'_>, width: u16, height: u16) {
+        let r = T::regs();
+        let addr = check_image(
+            dst.data.as_mut_ptr(),
+            dst.data.len(),
+            dst.format,
+            dst.line_offset,
+            width,
+            height,
+        );
+        r.omar().write(|w| w.set_ma(addr));
+        r.oor().write(|w| w.set_lo(dst.line_offset));
+        r.opfccr()
+            .write(|w| w.set_cm(vals::OpfccrCm::from_bits(dst.format.bits())));
+        r.nlr().write(|w| {
+            w.set_pl(width);
+            w.set_nl(height);
+        });
+    }
+
+    fn set_foreground(&mut self, src: &Source<'_>, width: u16, height: u16) {
+        let r = T::regs();
+        let addr = check_image(
+            src.data.as_ptr(),
+            src.data.len(),
+            src.format,
+            src.line_offset,
+            width,
+            height,
+        );
+        r.fgmar().write(|w| w.set_ma(addr));
+        r.fgor().write(|w| w.set_lo(src.line_offset));
+        r.fgpfccr().write(|w| {
+            w.set_cm(vals::FgpfccrCm::from_bits(src.format.bits()));
+            let (am, alpha) = alpha_bits(src.alpha);
+            w.set_am(vals::FgpfccrAm::from_bits(am));
+            w.set_alpha(alpha);
+        });
+    }
+
+    async fn run(&mut self, mode: vals::Mode) -> Result<(), Error> {
+        let r = T::regs();
+
+        r.ifcr().write(|w| {
+            w.set_ctcif(true);
+            w.set_ctif(true);
+            w.set_cceif(true);
+        });
+
+        // Abort the transfer if this future is dropped, so the buffers are no longer accessed.
+        let on_drop = OnDrop::new(|| {
+            r.cr().modify(|w| {
+                w.set_tcie(false);
+                w.set_tie(false);
+                w.set_ceie(false);
+                w.set_abort(true);
+            });
+            while r.cr().read().start() {}
+        });
+
+        r.cr().modify(|w| {
+            w.set_mode(mode);
+            w.set_tcie(true);
+            w.set_tie(true);
+            w.set_ceie(true);
+            w.set_start(true);
+        });
+
+        let res = poll_fn(|cx| {
+            DMA2D_WAKER.register(cx.waker());
+
+            let isr = r.isr().read();
+            if isr.ceif() {
+                Poll::Ready(Err(Error::Configuration))
+            } else if isr.tif() {
+                Poll::Ready(Err(Error::Transfer))
+            } else if isr.tcif() {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        // The hardware stops by itself on errors, so there's nothing to abort anymore.
+        on_drop.defuse();
+
+        r.ifcr().write(|w| {
+            w.set_ctcif(true);
+            w.set_ctif(true);
+            w.set_cceif(true);
+        });
+
+        res
+    }
+}
+
+impl<'d, T: Instance> Drop for Dma2d<'d, T> {
+    fn drop(&mut self) {
+        rcc::disable::<T>();
+    }
+}
+
+/// Values of the AM and ALPHA fields of the PFC control registers.
+fn alpha_bits(alpha: Alpha) -> (u8, u8) {
+    match alpha {
+        Alpha::Keep => (0, 0xFF),
+        Alpha::Replace(a) => (1, a),
+        Alpha::Multiply(a) => (2, a),
+    }
+}
+
+trait SealedInstance: crate::rcc::SealedRccPeripheral {
+    fn regs() -> pac::dma2d::Dma2d;
+}
+
+/// DMA2D instance trait.
+#[allow(private_bounds)]
+pub trait Instance: SealedInstance + Peripheral<P = Self> + crate::rcc::RccPeripheral + 'static + Send {
+    /// Interrupt for this DMA2D instance.
+    type Interrupt: interrupt::typelevel::Interrupt;
+}
+
+foreach_interrupt!(
+    ($inst:ident, dma2d, DMA2D, GLOBAL, $irq:ident) => {
+        impl Instance for peripherals::$inst {
+            type Interrupt = crate::interrupt::typelevel::$irq;
+        }
+
+        impl SealedInstance for peripherals::$inst {
+            fn regs() -> crate::pac::dma2d::Dma2d {
+                crate::pac::$inst
+            }
+        }
+    };
+);
@@ -3,11 +3,17 @@ use core::convert::Infallible;
 use core::future::Future;
 use core::marker::PhantomData;
 use core::pin::Pin;
+#[cfg(feature = "time")]
+use core::sync::atomic::{AtomicPtr, AtomicU16, AtomicU8, AtomicUsize, Ordering};
 use core::task::{Context, Poll};
 
 use embassy_hal_internal::{impl_peripheral, into_ref};
 use embassy_sync::waitqueue::AtomicWaker;
+#[cfg(feature = "time")]
+use embassy_time::Instant;
 
+#[cfg(feature = "time")]
+use crate::gpio::SealedPin as _;
 use crate::gpio::{AnyPin, Input, Level, Pin as GpioPin, Pull};
 use crate::pac::exti::regs::Lines;
 use crate::pac::EXTI;
@@ -52,8 +58,23 @@ unsafe fn on_irq() {
     // We don't handle or change any EXTI lines above 16.
     let bits = bits & 0x0000FFFF;
 
-    // Mask all the channels that fired.
-    cpu_regs().imr(0).modify(|w| w.0 &= !bits);
+    // Record the edges of the lines streaming them, which stay unmasked.
+    #[cfg(feature = "time")]
+    let oneshot = {
+        let streaming = bits & EDGE_LINES.load(Ordering::Relaxed) as u32;
+        if streaming != 0 {
+            let now = Instant::now();
+            for pin in BitIter(streaming) {
+                EDGE_QUEUES[pin as usize].push(pin as u8, now);
+            }
+        }
+        bits & !streaming
+    };
+    #[cfg(not(feature = "time"))]
+    let oneshot = bits;
+
+    // Mask all the other channels that fired.
+    cpu_regs().imr(0).modify(|w| w.0 &= !oneshot);
 
     // Wake the tasks
     for pin in BitIter(bits) {
@@ -70,6 +91,74 @@ unsafe fn on_irq() {
     }
 }
 
+/// Lines streaming their edges with [`ExtiInput::edges`].
+#[cfg(feature = "time")]
+static EDGE_LINES: AtomicU16 = AtomicU16::new(0);
+#[cfg(feature = "time")]
+const NEW_EQ: EdgeQueue = EdgeQueue::new();
+#[cfg(feature = "time")]
+static EDGE_QUEUES: [EdgeQueue; EXTI_COUNT] = [NEW_EQ; EXTI_COUNT];
+
+/// Edge buffer of a line, filled by the irq handler and emptied by [`Edges`].
+///
+/// `head` and `tail` count pushed and popped edges, each is only written by one side.
+#[cfg(feature = "time")]
+struct EdgeQueue {
+    buf: AtomicPtr<(Instant, Level)>,
+    len: AtomicUsize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    dropped: AtomicUsize,
+    port: AtomicU8,
+}
+
+#[cfg(feature = "time")]
+impl EdgeQueue {
+    const fn new() -> Self {
+        Self {
+            buf: AtomicPtr::new(core::ptr::null_mut()),
+            len: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+            port: AtomicU8::new(0),
+        }
+    }
+
+    /// Only called from the irq handler.
+    fn push(&self, pin: u8, now: Instant) {
+        let port = self.port.load(Ordering::Relaxed);
+        let high = unsafe { AnyPin::steal(port * 16 + pin) }
+            .block()
+            .idr()
+            .read()
+            .idr(pin as _)
+            == crate::pac::gpio::vals::Idr::HIGH;
+
+        let len = self.len.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Relaxed);
+        if head.wrapping_sub(self.tail.load(Ordering::Acquire)) < len {
+            let buf = self.buf.load(Ordering::Relaxed);
+            unsafe { buf.add(head % len).write((now, high.into())) };
+            self.head.store(head.wrapping_add(1), Ordering::Release);
+        } else {
+            let dropped = self.dropped.load(Ordering::Relaxed);
+            self.dropped.store(dropped.wrapping_add(1), Ordering::Relaxed);
+        }
+    }
+
+    fn pop(&self) -> Option<(Instant, Level)> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+        let buf = self.buf.load(Ordering::Relaxed);
+        let edge = unsafe { buf.add(tail % self.len.load(Ordering::Relaxed)).read() };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(edge)
+    }
+}
+
 struct BitIter(u32);
 
 impl Iterator for BitIter {
@@ -116,6 +205,11 @@ impl<'d> ExtiInput<'d> {
         }
     }
 
+    /// EXTI line of the pin, i.e. its number within its port.
+    pub(crate) fn line(&self) -> u8 {
+        self.pin.pin.pin.pin()
+    }
+
     /// Get whether the pin is high.
     pub fn is_high(&self) -> bool {
         self.pin.is_high()
@@ -171,6 +265,105 @@ impl<'d> ExtiInput<'d> {
     pub async fn wait_for_any_edge(&mut self) {
         ExtiInputFuture::new(self.pin.pin.pin.pin(), self.pin.pin.pin.port(), true, true).await
     }
+
+    /// Stream the edges of the pin, with the time they happened and the level after them.
+    ///
+    /// Unlike the `wait_for_*` methods, which can miss edges happening while the future isn't
+    /// being polled, this records every edge from the interrupt handler into `buf` until it is
+    /// read. When the buffer is full, new edges are dropped and counted in
+    /// [`Edges::dropped`].
+    ///
+    /// The level is read from the pin in the interrupt handler, so it can be wrong for a pulse
+    /// shorter than the interrupt latency. Two edges in a row with the same level tell that a
+    /// pulse was missed.
+    ///
+    /// The buffer is `'static` because the interrupt handler writes to it until [`Edges`] is
+    /// dropped, which can't be relied on: a leaked [`Edges`] keeps the line streaming.
+    #[cfg(feature = "time")]
+    pub fn edges<'a>(&'a mut self, buf: &'static mut [(Instant, Level)]) -> Edges<'a> {
+        assert!(!buf.is_empty());
+
+        let pin = self.pin.pin.pin.pin();
+        let port = self.pin.pin.pin.port();
+        let queue = &EDGE_QUEUES[pin as usize];
+        queue.buf.store(buf.as_mut_ptr(), Ordering::Relaxed);
+        queue.len.store(buf.len(), Ordering::Relaxed);
+        queue.head.store(0, Ordering::Relaxed);
+        queue.tail.store(0, Ordering::Relaxed);
+        queue.dropped.store(0, Ordering::Relaxed);
+        queue.port.store(port, Ordering::Relaxed);
+
+        critical_section::with(|_| {
+            let lines = EDGE_LINES.load(Ordering::Relaxed);
+            EDGE_LINES.store(lines | 1 << pin, Ordering::Relaxed);
+        });
+
+        // The line stays unmasked until `Edges` is dropped.
+        configure_line(pin, port, true, true);
+
+        Edges {
+            pin,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Stream of the edges of an [`ExtiInput`].
+///
+/// Created with [`ExtiInput::edges`].
+#[cfg(feature = "time")]
+pub struct Edges<'a> {
+    pin: u8,
+    _phantom: PhantomData<&'a mut ()>,
+}
+
+#[cfg(feature = "time")]
+impl<'a> Edges<'a> {
+    /// Wait for the next edge.
+    pub async fn next(&mut self) -> (Instant, Level) {
+        core::future::poll_fn(|cx| self.poll_edge(cx)).await
+    }
+
+    /// Get the next edge, if there is one.
+    pub fn try_next(&mut self) -> Option<(Instant, Level)> {
+        EDGE_QUEUES[self.pin as usize].pop()
+    }
+
+    /// Get the number of edges dropped because the buffer was full.
+    pub fn dropped(&self) -> usize {
+        EDGE_QUEUES[self.pin as usize].dropped.load(Ordering::Relaxed)
+    }
+
+    fn poll_edge(&mut self, cx: &mut Context<'_>) -> Poll<(Instant, Level)> {
+        EXTI_WAKERS[self.pin as usize].register(cx.waker());
+
+        match self.try_next() {
+            Some(edge) => Poll::Ready(edge),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+impl<'a> embassy_futures::stream::Stream for Edges<'a> {
+    type Item = (Instant, Level);
+
+    async fn next(&mut self) -> (Instant, Level) {
+        Edges::next(self).await
+    }
+}
+
+#[cfg(feature = "time")]
+impl<'a> Drop for Edges<'a> {
+    fn drop(&mut self) {
+        let pin = self.pin as usize;
+        critical_section::with(|_| {
+            cpu_regs().imr(0).modify(|w| w.set_line(pin, false));
+            let lines = EDGE_LINES.load(Ordering::Relaxed);
+            EDGE_LINES.store(lines & !(1 << pin), Ordering::Relaxed);
+        });
+        EDGE_QUEUES[pin].buf.store(core::ptr::null_mut(), Ordering::Relaxed);
+    }
 }
 
 impl<'d> embedded_hal_02::digital::v2::InputPin for ExtiInput<'d> {
@@ -232,25 +425,30 @@ struct ExtiInputFuture<'a> {
     phantom: PhantomData<&'a mut AnyPin>,
 }
 
+/// Route `port` to the EXTI line `pin`, select its edges, and unmask it.
+fn configure_line(pin: u8, port: u8, rising: bool, falling: bool) {
+    critical_section::with(|_| {
+        let pin = pin as usize;
+        exticr_regs().exticr(pin / 4).modify(|w| w.set_exti(pin % 4, port));
+        EXTI.rtsr(0).modify(|w| w.set_line(pin, rising));
+        EXTI.ftsr(0).modify(|w| w.set_line(pin, falling));
+
+        // clear pending bit
+        #[cfg(not(any(exti_c0, exti_g0, exti_u0, exti_l5, exti_u5, exti_h5, exti_h50)))]
+        EXTI.pr(0).write(|w| w.set_line(pin, true));
+        #[cfg(any(exti_c0, exti_g0, exti_u0, exti_l5, exti_u5, exti_h5, exti_h50))]
+        {
+            EXTI.rpr(0).write(|w| w.set_line(pin, true));
+            EXTI.fpr(0).write(|w| w.set_line(pin, true));
+        }
+
+        cpu_regs().imr(0).modify(|w| w.set_line(pin, true));
+    });
+}
+
 impl<'a> ExtiInputFuture<'a> {
     fn new(pin: u8, port: u8, rising: bool, falling: bool) -> Self {
-        critical_section::with(|_| {
-            let pin = pin as usize;
-            exticr_regs().exticr(pin / 4).modify(|w| w.set_exti(pin % 4, port));
-            EXTI.rtsr(0).modify(|w| w.set_line(pin, rising));
-            EXTI.ftsr(0).modify(|w| w.set_line(pin, falling));
-
-            // clear pending bit
-            #[cfg(not(any(exti_c0, exti_g0, exti_u0, exti_l5, exti_u5, exti_h5, exti_h50)))]
-            EXTI.pr(0).write(|w| w.set_line(pin, true));
-            #[cfg(any(exti_c0, exti_g0, exti_u0, exti_l5, exti_u5, exti_h5, exti_h50))]
-            {
-                EXTI.rpr(0).write(|w| w.set_line(pin, true));
-                EXTI.fpr(0).write(|w| w.set_line(pin, true));
-            }
-
-            cpu_regs().imr(0).modify(|w| w.set_line(pin, true));
-        });
+        configure_line(pin, port, rising, falling);
 
         Self {
             pin,
@@ -323,7 +521,9 @@ macro_rules! impl_irq {
         #[cfg(feature = "rt")]
         #[interrupt]
         unsafe fn $e() {
-            on_irq()
+            let guard = crate::_private::IsrGuard::new();
+            on_irq();
+            guard.finish();
         }
     };
 }
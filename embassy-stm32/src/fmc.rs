@@ -300,6 +300,18 @@ pin_trait!(DA13Pin, Instance);
 pin_trait!(DA14Pin, Instance);
 pin_trait!(DA15Pin, Instance);
 
+/// FMC address line pin, of any line.
+///
+/// This is implemented for every pin that has one of the `A0`..`A25` signals, for drivers using an
+/// address line as a control signal, such as the D/C line of a [display](crate::display_bus).
+pub trait AddressPin<T: Instance>: crate::gpio::Pin {
+    /// Get the AF number needed to use this pin as an address line.
+    fn af_num(&self) -> u8;
+
+    /// Get the number of the address line, e.g. 16 for `A16`.
+    fn address_line(&self) -> u8;
+}
+
 pin_trait!(A0Pin, Instance);
 pin_trait!(A1Pin, Instance);
 pin_trait!(A2Pin, Instance);
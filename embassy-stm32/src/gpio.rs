@@ -328,6 +328,14 @@ impl<'d> Input<'d> {
         self.pin.is_low()
     }
 
+    /// The input data register of the pin's port, as the source of a
+    /// [peripheral-to-peripheral](crate::dma::Transfer::new_peripheral_to_peripheral) DMA transfer.
+    ///
+    /// The register holds the level of all 16 pins of the port; this pin's is bit [`Pin::pin`].
+    pub fn data_register(&self) -> crate::dma::PeripheralRegister<'_, u16> {
+        unsafe { crate::dma::PeripheralRegister::new(self.pin.pin.block().idr().as_ptr() as *mut u16) }
+    }
+
     /// Get the current pin input level.
     #[inline]
     pub fn get_level(&self) -> Level {
@@ -674,6 +682,35 @@ fn set_as_analog(pin_port: u8) {
     r.moder().modify(|w| w.set_moder(n, vals::Moder::ANALOG));
 }
 
+#[inline(never)]
+fn set_af_bypassed(pin_port: u8, bypassed: bool) {
+    let pin = unsafe { AnyPin::steal(pin_port) };
+    let r = pin.block();
+    let n = pin._pin() as usize;
+
+    #[cfg(gpio_v1)]
+    r.cr(n / 8).modify(|w| {
+        w.set_cnf_out(
+            n % 8,
+            match bypassed {
+                true => vals::CnfOut::OPENDRAIN,
+                false => vals::CnfOut::ALTOPENDRAIN,
+            },
+        )
+    });
+
+    #[cfg(gpio_v2)]
+    r.moder().modify(|w| {
+        w.set_moder(
+            n,
+            match bypassed {
+                true => vals::Moder::OUTPUT,
+                false => vals::Moder::ALTERNATE,
+            },
+        )
+    });
+}
+
 #[inline(never)]
 fn get_pull(pin_port: u8) -> Pull {
     let pin = unsafe { AnyPin::steal(pin_port) };
@@ -743,6 +780,19 @@ pub(crate) trait SealedPin {
         set_as_analog(self.pin_port());
     }
 
+    /// Drive a pin configured as an open-drain alternate function as a GPIO instead, with the same
+    /// speed and pull, or give it back to the peripheral.
+    #[inline]
+    fn set_af_bypassed(&self, bypassed: bool) {
+        set_af_bypassed(self.pin_port(), bypassed);
+    }
+
+    /// Get whether the pin input level is high.
+    #[inline]
+    fn is_input_high(&self) -> bool {
+        self.block().idr().read().idr(self._pin() as _) == vals::Idr::HIGH
+    }
+
     /// Set the pin as "disconnected", ie doing nothing and consuming the lowest
     /// amount of power possible.
     ///
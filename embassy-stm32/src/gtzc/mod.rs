@@ -0,0 +1,237 @@
+//! Global TrustZone controller (GTZC)
+//!
+//! With TrustZone enabled by the TZEN option bit, the secure firmware decides which peripherals
+//! and memory the non-secure firmware may access, before starting it:
+//!
+//! - [`set_peripheral_attributes`] makes a [`Securable`] peripheral secure or non-secure, and
+//!   privileged or not, with the TrustZone security controller (TZSC).
+//! - [`set_sram_attributes`] does the same for blocks of SRAM, with the block-based memory
+//!   protection controllers (MPCBB).
+//! - [`enable_illegal_access_interrupt`] reports accesses violating these attributes, with the
+//!   TrustZone illegal access controller (TZIC).
+//!
+//! The secure firmware can then offer services to the non-secure firmware through non-secure
+//! callable functions, see [`nsc`].
+//!
+//! This module needs the `trustzone-secure` feature: the GTZC is only accessible to secure
+//! firmware. Non-secure firmware is built without it, and only uses the peripherals it's given.
+
+pub mod nsc;
+
+use core::ops::Range;
+
+use crate::pac;
+
+/// Security attributes of a peripheral or memory block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Attributes {
+    /// Only secure accesses are allowed.
+    pub secure: bool,
+    /// Only privileged accesses are allowed.
+    pub privileged: bool,
+}
+
+impl Attributes {
+    /// Non-secure and unprivileged, accessible to all.
+    pub const NON_SECURE: Self = Self {
+        secure: false,
+        privileged: false,
+    };
+    /// Secure and unprivileged.
+    pub const SECURE: Self = Self {
+        secure: true,
+        privileged: false,
+    };
+}
+
+// TZSC registers
+const TZSC_CR: usize = 0x00;
+const TZSC_SECCFGR: usize = 0x10;
+const TZSC_PRIVCFGR: usize = 0x20;
+
+// MPCBB registers
+const MPCBB_CR: usize = 0x000;
+const MPCBB_CFGLOCKR1: usize = 0x010;
+const MPCBB_SECCFGR: usize = 0x100;
+const MPCBB_PRIVCFGR: usize = 0x200;
+
+// TZIC registers
+const TZIC_IER: usize = 0x00;
+const TZIC_SR: usize = 0x10;
+const TZIC_FCR: usize = 0x20;
+
+/// Size of an SRAM block, the unit of [`set_sram_attributes`].
+pub const SRAM_BLOCK_SIZE: usize = 512;
+/// Blocks per super-block, the unit of [`lock_sram`].
+const BLOCKS_PER_SUPER_BLOCK: usize = 32;
+
+unsafe fn reg_ptr(base: *mut (), offset: usize) -> *mut u32 {
+    (base as *mut u8).add(offset) as *mut u32
+}
+
+unsafe fn reg_read(base: *mut (), offset: usize) -> u32 {
+    reg_ptr(base, offset).read_volatile()
+}
+
+unsafe fn modify_bit(base: *mut (), offset: usize, bit: usize, value: bool) {
+    let reg = reg_ptr(base, offset);
+    let mut v = reg.read_volatile();
+    if value {
+        v |= 1 << bit;
+    } else {
+        v &= !(1 << bit);
+    }
+    reg.write_volatile(v);
+}
+
+fn tzsc() -> *mut () {
+    pac::GTZC1_TZSC.as_ptr() as *mut ()
+}
+
+fn tzic() -> *mut () {
+    pac::GTZC1_TZIC.as_ptr() as *mut ()
+}
+
+pub(crate) trait SealedSecurable {
+    /// TZSC_SECCFGRx and TZSC_PRIVCFGRx register index, from 0.
+    const REG: usize;
+    /// Bit in the registers.
+    const BIT: usize;
+}
+
+/// Peripheral whose security attributes are set by the TZSC.
+#[allow(private_bounds)]
+pub trait Securable: SealedSecurable {}
+
+/// Set the security attributes of `peri`.
+///
+/// Secure firmware keeps using the peripheral's secure alias, which works for either attribute.
+pub fn set_peripheral_attributes<T: Securable>(_peri: &T, attributes: Attributes) {
+    set_peripheral_attributes_raw(T::REG, T::BIT, attributes)
+}
+
+/// Set the security attributes of the peripheral at bit `bit` of TZSC_SECCFGR`reg + 1`, for the
+/// peripherals without a [`Securable`] implementation. See the reference manual for the bits.
+pub fn set_peripheral_attributes_raw(reg: usize, bit: usize, attributes: Attributes) {
+    assert!(reg < 4 && bit < 32);
+    unsafe {
+        modify_bit(tzsc(), TZSC_SECCFGR + reg * 4, bit, attributes.secure);
+        modify_bit(tzsc(), TZSC_PRIVCFGR + reg * 4, bit, attributes.privileged);
+    }
+}
+
+/// Lock the TZSC configuration, including peripheral attributes, until the next reset.
+pub fn lock_peripheral_attributes() {
+    unsafe { modify_bit(tzsc(), TZSC_CR, 0, true) }
+}
+
+/// SRAM protected by a block-based memory protection controller.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Sram {
+    /// SRAM1.
+    Sram1,
+    /// SRAM2.
+    Sram2,
+    /// SRAM3.
+    #[cfg(any(stm32u57x, stm32u58x, stm32u59x, stm32u5ax, stm32h56x, stm32h57x))]
+    Sram3,
+}
+
+impl Sram {
+    fn mpcbb(self) -> *mut () {
+        match self {
+            Sram::Sram1 => pac::GTZC1_MPCBB1.as_ptr() as *mut (),
+            Sram::Sram2 => pac::GTZC1_MPCBB2.as_ptr() as *mut (),
+            #[cfg(any(stm32u57x, stm32u58x, stm32u59x, stm32u5ax, stm32h56x, stm32h57x))]
+            Sram::Sram3 => pac::GTZC1_MPCBB3.as_ptr() as *mut (),
+        }
+    }
+}
+
+/// Set the security attributes of the bytes `range` of `sram`, from its start.
+///
+/// Attributes are set per block of [`SRAM_BLOCK_SIZE`] bytes, so `range` must be aligned to
+/// blocks. All of SRAM is secure after reset.
+pub fn set_sram_attributes(sram: Sram, range: Range<usize>, attributes: Attributes) {
+    assert!(range.start % SRAM_BLOCK_SIZE == 0 && range.end % SRAM_BLOCK_SIZE == 0);
+    let base = sram.mpcbb();
+    for block in range.start / SRAM_BLOCK_SIZE..range.end / SRAM_BLOCK_SIZE {
+        let (reg, bit) = (block / 32, block % 32);
+        unsafe {
+            modify_bit(base, MPCBB_SECCFGR + reg * 4, bit, attributes.secure);
+            modify_bit(base, MPCBB_PRIVCFGR + reg * 4, bit, attributes.privileged);
+        }
+    }
+}
+
+/// Lock the attributes of the blocks `range` of `sram`, and the MPCBB configuration, until the
+/// next reset.
+///
+/// Locking is per super-block of 32 blocks, so `range` must be aligned to 16 KB.
+pub fn lock_sram(sram: Sram, range: Range<usize>) {
+    const SUPER_BLOCK_SIZE: usize = SRAM_BLOCK_SIZE * BLOCKS_PER_SUPER_BLOCK;
+    assert!(range.start % SUPER_BLOCK_SIZE == 0 && range.end % SUPER_BLOCK_SIZE == 0);
+    let base = sram.mpcbb();
+    for super_block in range.start / SUPER_BLOCK_SIZE..range.end / SUPER_BLOCK_SIZE {
+        unsafe { modify_bit(base, MPCBB_CFGLOCKR1, super_block, true) };
+    }
+    unsafe { modify_bit(base, MPCBB_CR, 0, true) };
+}
+
+/// Enable the illegal access interrupt of the peripheral at bit `bit` of TZIC_IER`reg + 1`.
+///
+/// Illegal accesses then pend the GTZC interrupt, whose handler can read them with
+/// [`illegal_access`] and clear them with [`clear_illegal_access`].
+pub fn enable_illegal_access_interrupt(reg: usize, bit: usize, enable: bool) {
+    assert!(reg < 4 && bit < 32);
+    unsafe { modify_bit(tzic(), TZIC_IER + reg * 4, bit, enable) }
+}
+
+/// Whether an illegal access to the peripheral at bit `bit` of TZIC_SR`reg + 1` was detected.
+pub fn illegal_access(reg: usize, bit: usize) -> bool {
+    assert!(reg < 4 && bit < 32);
+    unsafe { (reg_read(tzic(), TZIC_SR + reg * 4) & (1 << bit)) != 0 }
+}
+
+/// Clear the illegal access flag of the peripheral at bit `bit` of TZIC_SR`reg + 1`.
+pub fn clear_illegal_access(reg: usize, bit: usize) {
+    assert!(reg < 4 && bit < 32);
+    unsafe { reg_ptr(tzic(), TZIC_FCR + reg * 4).write_volatile(1 << bit) }
+}
+
+macro_rules! impl_securable {
+    ($inst:ident, $reg:expr, $bit:expr) => {
+        impl SealedSecurable for crate::peripherals::$inst {
+            const REG: usize = $reg;
+            const BIT: usize = $bit;
+        }
+        impl Securable for crate::peripherals::$inst {}
+    };
+}
+
+// Bits of TZSC_SECCFGR1..3 of STM32U5, see RM0456.
+#[cfg(stm32u5)]
+foreach_peripheral!(
+    (timer, TIM2) => { impl_securable!(TIM2, 0, 0); };
+    (timer, TIM3) => { impl_securable!(TIM3, 0, 1); };
+    (timer, TIM4) => { impl_securable!(TIM4, 0, 2); };
+    (timer, TIM5) => { impl_securable!(TIM5, 0, 3); };
+    (timer, TIM6) => { impl_securable!(TIM6, 0, 4); };
+    (timer, TIM7) => { impl_securable!(TIM7, 0, 5); };
+    (spi, SPI2) => { impl_securable!(SPI2, 0, 8); };
+    (usart, USART2) => { impl_securable!(USART2, 0, 9); };
+    (usart, USART3) => { impl_securable!(USART3, 0, 10); };
+    (usart, UART4) => { impl_securable!(UART4, 0, 11); };
+    (usart, UART5) => { impl_securable!(UART5, 0, 12); };
+    (i2c, I2C1) => { impl_securable!(I2C1, 0, 13); };
+    (i2c, I2C2) => { impl_securable!(I2C2, 0, 14); };
+    (timer, TIM1) => { impl_securable!(TIM1, 1, 0); };
+    (spi, SPI1) => { impl_securable!(SPI1, 1, 1); };
+    (timer, TIM8) => { impl_securable!(TIM8, 1, 2); };
+    (usart, USART1) => { impl_securable!(USART1, 1, 3); };
+    (crc, CRC) => { impl_securable!(CRC, 2, 3); };
+    (hash, HASH) => { impl_securable!(HASH, 2, 12); };
+    (rng, RNG) => { impl_securable!(RNG, 2, 13); };
+);
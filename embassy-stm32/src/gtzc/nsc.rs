@@ -0,0 +1,125 @@
+//! Non-secure callable (NSC) services.
+//!
+//! Non-secure firmware calls secure services through entry functions with the
+//! `C-cmse-nonsecure-entry` ABI, which the linker places in a non-secure callable region. That
+//! ABI is unstable, so the secure firmware defines the entry functions itself, on nightly, and
+//! implements them with the services here.
+//!
+//! Entry functions must not trust the pointers they're given: the non-secure firmware could pass
+//! secure memory to read or overwrite it. The services check buffers with the TT instruction
+//! before using them.
+//!
+//! ```rust,ignore
+//! #![feature(abi_c_cmse_nonsecure_entry)]
+//!
+//! static RNG: Mutex<CriticalSectionRawMutex, RefCell<Option<Rng<'static, RNG>>>> =
+//!     Mutex::new(RefCell::new(None));
+//!
+//! #[no_mangle]
+//! pub extern "C-cmse-nonsecure-entry" fn secure_rng_fill(ptr: *mut u8, len: usize) -> i32 {
+//!     RNG.lock(|rng| match nsc::rng_fill(rng.borrow_mut().as_mut().unwrap(), ptr, len) {
+//!         Ok(()) => 0,
+//!         Err(e) => e.code(),
+//!     })
+//! }
+//! ```
+
+use core::ops::Range;
+
+use cortex_m::cmse::{AccessType, TestTarget};
+#[cfg(rng)]
+use rand_core::RngCore;
+
+use crate::flash::{Blocking, Flash};
+#[cfg(rng)]
+use crate::rng::{self, Rng};
+
+/// NSC service error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A buffer isn't fully accessible to the non-secure caller, or is out of the allowed range.
+    InvalidBuffer,
+    /// The RNG failed.
+    Rng,
+    /// The flash operation failed.
+    Flash(crate::flash::Error),
+}
+
+impl Error {
+    /// Negative error code, to return to the non-secure caller.
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::InvalidBuffer => -1,
+            Error::Rng => -2,
+            Error::Flash(_) => -3,
+        }
+    }
+}
+
+/// Check that the non-secure caller may read `len` bytes at `ptr`, and borrow them.
+///
+/// # Safety
+///
+/// The non-secure firmware may change the bytes while they're borrowed, so they must be copied
+/// before being validated.
+pub unsafe fn nonsecure_slice<'a>(ptr: *const u8, len: usize) -> Result<&'a [u8], Error> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    match TestTarget::check_range(ptr as *mut u32, len, AccessType::CurrentNonSecure) {
+        Some(target) if target.nonsecure_readable() => Ok(core::slice::from_raw_parts(ptr, len)),
+        _ => Err(Error::InvalidBuffer),
+    }
+}
+
+/// Check that the non-secure caller may write `len` bytes at `ptr`, and borrow them.
+///
+/// # Safety
+///
+/// The non-secure firmware may access the bytes while they're borrowed, so they must only be
+/// written.
+pub unsafe fn nonsecure_slice_mut<'a>(ptr: *mut u8, len: usize) -> Result<&'a mut [u8], Error> {
+    if len == 0 {
+        return Ok(&mut []);
+    }
+    match TestTarget::check_range(ptr as *mut u32, len, AccessType::CurrentNonSecure) {
+        Some(target) if target.nonsecure_read_and_writable() => Ok(core::slice::from_raw_parts_mut(ptr, len)),
+        _ => Err(Error::InvalidBuffer),
+    }
+}
+
+/// Fill the non-secure buffer of `len` bytes at `ptr` with random bytes from the secure RNG.
+#[cfg(rng)]
+pub fn rng_fill<T: rng::Instance>(rng: &mut Rng<'_, T>, ptr: *mut u8, len: usize) -> Result<(), Error> {
+    let buf = unsafe { nonsecure_slice_mut(ptr, len)? };
+    rng.try_fill_bytes(buf).map_err(|_| Error::Rng)
+}
+
+/// Write the non-secure buffer of `len` bytes at `ptr` to flash at `offset`, which must be within
+/// `allowed`.
+///
+/// `allowed` is the part of flash the non-secure firmware may write, typically its own DFU
+/// partition, so it can't overwrite the secure firmware.
+pub fn flash_write(
+    flash: &mut Flash<'_, Blocking>,
+    allowed: Range<u32>,
+    offset: u32,
+    ptr: *const u8,
+    len: usize,
+) -> Result<(), Error> {
+    let end = offset.checked_add(len as u32).ok_or(Error::InvalidBuffer)?;
+    if offset < allowed.start || end > allowed.end {
+        return Err(Error::InvalidBuffer);
+    }
+    let buf = unsafe { nonsecure_slice(ptr, len)? };
+    flash.blocking_write(offset, buf).map_err(Error::Flash)
+}
+
+/// Erase flash from `from` to `to`, which must be within `allowed`, see [`flash_write`].
+pub fn flash_erase(flash: &mut Flash<'_, Blocking>, allowed: Range<u32>, from: u32, to: u32) -> Result<(), Error> {
+    if from > to || from < allowed.start || to > allowed.end {
+        return Err(Error::InvalidBuffer);
+    }
+    flash.blocking_erase(from, to).map_err(Error::Flash)
+}
@@ -44,7 +44,28 @@ pub enum Error {
     ZeroLengthTransfer,
 }
 
+/// I2C config error.
+///
+/// Returned by `set_config` when the bus frequency can't be produced from the kernel clock. The
+/// constructors panic with it instead.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// The bus frequency is above that of the fastest mode supported.
+    FrequencyTooHigh,
+    /// The bus frequency is too low to be reached from the kernel clock.
+    FrequencyTooLow,
+    /// The kernel clock is out of the range supported by the peripheral, or too fast or too slow
+    /// to meet the timings of the bus frequency.
+    KernelClockOutOfRange,
+}
+
 /// I2C config
+///
+/// The bus frequency given to the constructors selects the mode: standard mode up to 100 kHz, fast
+/// mode up to 400 kHz, fast mode plus up to 1 MHz. On I2C v2 and v3, the bus timings are computed
+/// from the kernel clock and the noise filters. Fast mode plus may also need the 20 mA drive of the
+/// pins to be enabled in SYSCFG.
 #[non_exhaustive]
 #[derive(Copy, Clone)]
 pub struct Config {
@@ -63,6 +84,22 @@ pub struct Config {
     /// Timeout.
     #[cfg(feature = "time")]
     pub timeout: embassy_time::Duration,
+    /// Minimum transfer length, in bytes, for async transfers to use DMA.
+    ///
+    /// Shorter transfers are done by polling the peripheral instead, which avoids the DMA setup
+    /// overhead for e.g. single register accesses. The default of 0 uses DMA for all transfers.
+    #[cfg(any(i2c_v2, i2c_v3))]
+    pub dma_threshold: usize,
+    /// SMBus mode, `None` for plain I2C.
+    #[cfg(any(i2c_v2, i2c_v3))]
+    pub smbus: Option<SmbusConfig>,
+    /// Enable the analog noise filter on SCL and SDA, suppressing spikes shorter than 50 ns.
+    #[cfg(any(i2c_v2, i2c_v3))]
+    pub analog_filter: bool,
+    /// Digital noise filter on SCL and SDA, suppressing spikes shorter than this many I2C kernel
+    /// clock cycles, from 0 (disabled) to 15.
+    #[cfg(any(i2c_v2, i2c_v3))]
+    pub digital_filter: u8,
 }
 
 impl Default for Config {
@@ -74,6 +111,45 @@ impl Default for Config {
             scl_pullup: false,
             #[cfg(feature = "time")]
             timeout: embassy_time::Duration::from_millis(1000),
+            #[cfg(any(i2c_v2, i2c_v3))]
+            dma_threshold: 0,
+            #[cfg(any(i2c_v2, i2c_v3))]
+            smbus: None,
+            #[cfg(any(i2c_v2, i2c_v3))]
+            analog_filter: true,
+            #[cfg(any(i2c_v2, i2c_v3))]
+            digital_filter: 0,
+        }
+    }
+}
+
+/// SMBus configuration.
+///
+/// Transfers with PEC are done with the `smbus_*` methods of [`I2c`]. SMBus host notify messages,
+/// written by devices to the host address `0x08`, can be received with an [`I2cSlave`] listening
+/// to that address.
+#[cfg(any(i2c_v2, i2c_v3))]
+#[non_exhaustive]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SmbusConfig {
+    /// Send a PEC (packet error code) at the end of `smbus_*` writes, and check the one at the
+    /// end of `smbus_*` reads.
+    pub pec: bool,
+    /// Fail transfers with [`Error::Timeout`] when SCL is held low for more than 25 ms, or a
+    /// message is stretched by slaves for more than 10 ms in total, as specified by SMBus.
+    ///
+    /// The longest timeout is `4096 * 2048` I2C kernel clock cycles, which is shorter than 25 ms
+    /// above 335 MHz.
+    pub timeouts: bool,
+}
+
+#[cfg(any(i2c_v2, i2c_v3))]
+impl Default for SmbusConfig {
+    fn default() -> Self {
+        Self {
+            pec: true,
+            timeouts: true,
         }
     }
 }
@@ -115,10 +191,14 @@ pub struct I2c<'d, M: Mode> {
     kernel_clock: Hertz,
     scl: Option<PeripheralRef<'d, AnyPin>>,
     sda: Option<PeripheralRef<'d, AnyPin>>,
+    #[cfg(any(i2c_v2, i2c_v3))]
+    smba: Option<PeripheralRef<'d, AnyPin>>,
     tx_dma: Option<ChannelAndRequest<'d>>,
     rx_dma: Option<ChannelAndRequest<'d>>,
     #[cfg(feature = "time")]
     timeout: Duration,
+    #[cfg(any(i2c_v2, i2c_v3))]
+    dma_threshold: usize,
     _phantom: PhantomData<M>,
 }
 
@@ -146,6 +226,40 @@ impl<'d> I2c<'d, Async> {
             config,
         )
     }
+
+    /// Create a new I2C driver acting as SMBus host, with an SMBALERT input.
+    ///
+    /// Wait for a device to signal an alert with [`wait_alert`](Self::wait_alert). `config.smbus`
+    /// must be set.
+    #[cfg(any(i2c_v2, i2c_v3))]
+    pub fn new_with_smba<T: Instance>(
+        peri: impl Peripheral<P = T> + 'd,
+        scl: impl Peripheral<P = impl SclPin<T>> + 'd,
+        sda: impl Peripheral<P = impl SdaPin<T>> + 'd,
+        smba: impl Peripheral<P = impl SmbaPin<T>> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::EventInterrupt, EventInterruptHandler<T>>
+            + interrupt::typelevel::Binding<T::ErrorInterrupt, ErrorInterruptHandler<T>>
+            + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        freq: Hertz,
+        config: Config,
+    ) -> Self {
+        assert!(config.smbus.is_some());
+
+        let mut this = Self::new_inner(
+            peri,
+            new_pin!(scl, config.scl_af()),
+            new_pin!(sda, config.sda_af()),
+            new_dma!(tx_dma),
+            new_dma!(rx_dma),
+            freq,
+            config,
+        );
+        this.smba = new_pin!(smba, AfType::output(OutputType::OpenDrain, Speed::Medium));
+        this.enable_alert();
+        this
+    }
 }
 
 impl<'d> I2c<'d, Blocking> {
@@ -189,10 +303,14 @@ impl<'d, M: Mode> I2c<'d, M> {
             kernel_clock: T::frequency(),
             scl,
             sda,
+            #[cfg(any(i2c_v2, i2c_v3))]
+            smba: None,
             tx_dma,
             rx_dma,
             #[cfg(feature = "time")]
             timeout: config.timeout,
+            #[cfg(any(i2c_v2, i2c_v3))]
+            dma_threshold: config.dma_threshold,
             _phantom: PhantomData,
         };
         this.enable_and_init(freq, config);
@@ -210,9 +328,213 @@ impl<'d, M: Mode> I2c<'d, M> {
             deadline: Instant::now() + self.timeout,
         }
     }
+
+    /// Free the bus from a slave stuck in the middle of a transfer.
+    ///
+    /// A slave reset or interrupted during a read can keep SDA low forever, waiting for the clock
+    /// pulses of the byte it is sending, which makes every transfer fail with [`Error::Timeout`] or
+    /// [`Error::Arbitration`]. This drives SCL as a GPIO for up to 9 pulses at about 100 kHz, until
+    /// the slave releases SDA, then sends a STOP and resets the peripheral.
+    ///
+    /// Returns [`Error::Bus`] if SDA is still held low.
+    pub fn recover_bus(&mut self) -> Result<(), Error> {
+        let (Some(scl), Some(sda)) = (self.scl.as_ref(), self.sda.as_ref()) else {
+            return Err(Error::Bus);
+        };
+        let half_period = unsafe { crate::rcc::get_freqs() }.sys.unwrap().0 / 200_000;
+
+        scl.set_high();
+        sda.set_high();
+        scl.set_af_bypassed(true);
+        sda.set_af_bypassed(true);
+        cortex_m::asm::delay(half_period);
+
+        for _ in 0..9 {
+            if sda.is_input_high() {
+                break;
+            }
+            scl.set_low();
+            cortex_m::asm::delay(half_period);
+            scl.set_high();
+            cortex_m::asm::delay(half_period);
+        }
+
+        // STOP: SDA rising while SCL is high.
+        scl.set_low();
+        cortex_m::asm::delay(half_period);
+        sda.set_low();
+        cortex_m::asm::delay(half_period);
+        scl.set_high();
+        cortex_m::asm::delay(half_period);
+        sda.set_high();
+        cortex_m::asm::delay(half_period);
+
+        let released = sda.is_input_high() && scl.is_input_high();
+
+        scl.set_af_bypassed(false);
+        sda.set_af_bypassed(false);
+        self.reset_peripheral();
+
+        match released {
+            true => Ok(()),
+            false => Err(Error::Bus),
+        }
+    }
 }
 
 impl<'d, M: Mode> Drop for I2c<'d, M> {
+    fn drop(&mut self) {
+        self.scl.as_ref().map(|x| x.set_as_disconnected());
+        self.sda.as_ref().map(|x| x.set_as_disconnected());
+        #[cfg(any(i2c_v2, i2c_v3))]
+        self.smba.as_ref().map(|x| x.set_as_disconnected());
+
+        self.info.rcc.disable()
+    }
+}
+
+/// Own address of an I2C slave.
+#[cfg(any(i2c_v2, i2c_v3))]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Address {
+    /// 7-bit address.
+    SevenBit(u8),
+    /// 10-bit address.
+    TenBit(u16),
+}
+
+/// Number of low bits of the secondary own address ignored when matching it.
+///
+/// With `Mask3`, a secondary address of `0x28` makes the slave respond to `0x28..=0x2F`. Reserved
+/// addresses are never matched.
+#[cfg(any(i2c_v2, i2c_v3))]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddrMask {
+    /// All bits are compared.
+    NoMask,
+    /// Bit 0 is ignored.
+    Mask1,
+    /// Bits 0 to 1 are ignored.
+    Mask2,
+    /// Bits 0 to 2 are ignored.
+    Mask3,
+    /// Bits 0 to 3 are ignored.
+    Mask4,
+    /// Bits 0 to 4 are ignored.
+    Mask5,
+    /// Bits 0 to 5 are ignored.
+    Mask6,
+    /// All bits are ignored, every non-reserved address is matched.
+    Mask7,
+}
+
+/// Secondary own address of an I2C slave.
+#[cfg(any(i2c_v2, i2c_v3))]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SecondaryAddress {
+    /// 7-bit address.
+    pub addr: u8,
+    /// Address bits ignored when matching.
+    pub mask: AddrMask,
+}
+
+/// I2C slave address configuration.
+#[cfg(any(i2c_v2, i2c_v3))]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SlaveAddrConfig {
+    /// Primary own address.
+    pub primary: Address,
+    /// Optional secondary own address, 7-bit only.
+    pub secondary: Option<SecondaryAddress>,
+    /// Also respond to the general call address, `0x00`.
+    pub general_call: bool,
+}
+
+#[cfg(any(i2c_v2, i2c_v3))]
+impl SlaveAddrConfig {
+    /// Respond to a single 7-bit address.
+    pub const fn new(addr: u8) -> Self {
+        Self {
+            primary: Address::SevenBit(addr),
+            secondary: None,
+            general_call: false,
+        }
+    }
+}
+
+/// Direction of a transaction started by the master.
+#[cfg(any(i2c_v2, i2c_v3))]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SlaveCommandKind {
+    /// The master writes, respond with [`I2cSlave::respond_to_write`].
+    Write,
+    /// The master reads, respond with [`I2cSlave::respond_to_read`].
+    Read,
+}
+
+/// Transaction started by the master, returned by [`I2cSlave::listen`].
+#[cfg(any(i2c_v2, i2c_v3))]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SlaveCommand {
+    /// Direction of the transaction.
+    pub kind: SlaveCommandKind,
+    /// Address the master addressed, one of the own addresses or `0x00` for a general call.
+    pub address: Address,
+}
+
+/// I2C slave driver.
+///
+/// The slave stretches the clock while waiting for the application: after the address of a
+/// transaction is matched, [`listen`](Self::listen) returns and the bus is held until the
+/// application responds with [`respond_to_write`](Self::respond_to_write) or
+/// [`respond_to_read`](Self::respond_to_read).
+#[cfg(any(i2c_v2, i2c_v3))]
+pub struct I2cSlave<'d> {
+    info: &'static Info,
+    state: &'static State,
+    scl: Option<PeripheralRef<'d, AnyPin>>,
+    sda: Option<PeripheralRef<'d, AnyPin>>,
+}
+
+#[cfg(any(i2c_v2, i2c_v3))]
+impl<'d> I2cSlave<'d> {
+    /// Create a new I2C slave driver.
+    ///
+    /// `freq` is the expected bus frequency, used to set the data setup and hold times.
+    pub fn new<T: Instance>(
+        _peri: impl Peripheral<P = T> + 'd,
+        scl: impl Peripheral<P = impl SclPin<T>> + 'd,
+        sda: impl Peripheral<P = impl SdaPin<T>> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::EventInterrupt, EventInterruptHandler<T>>
+            + interrupt::typelevel::Binding<T::ErrorInterrupt, ErrorInterruptHandler<T>>
+            + 'd,
+        freq: Hertz,
+        config: Config,
+        addr_config: SlaveAddrConfig,
+    ) -> Self {
+        unsafe { T::EventInterrupt::enable() };
+        unsafe { T::ErrorInterrupt::enable() };
+
+        let this = Self {
+            info: T::info(),
+            state: T::state(),
+            scl: new_pin!(scl, config.scl_af()),
+            sda: new_pin!(sda, config.sda_af()),
+        };
+        this.info.rcc.enable_and_reset();
+        this.init(T::frequency(), freq, config, addr_config);
+        this
+    }
+}
+
+#[cfg(any(i2c_v2, i2c_v3))]
+impl<'d> Drop for I2cSlave<'d> {
     fn drop(&mut self) {
         self.scl.as_ref().map(|x| x.set_as_disconnected());
         self.sda.as_ref().map(|x| x.set_as_disconnected());
@@ -280,6 +602,7 @@ peri_trait!(
 
 pin_trait!(SclPin, Instance);
 pin_trait!(SdaPin, Instance);
+pin_trait!(SmbaPin, Instance);
 dma_trait!(RxDma, Instance);
 dma_trait!(TxDma, Instance);
 
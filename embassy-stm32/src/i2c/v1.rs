@@ -74,7 +74,7 @@ impl<'d, M: PeriMode> I2c<'d, M> {
             reg.set_swrst(false);
         });
 
-        let timings = Timings::new(self.kernel_clock, freq);
+        let timings = unwrap!(Timings::new(self.kernel_clock, freq));
 
         self.info.regs.cr2().modify(|reg| {
             reg.set_freq(timings.freq);
@@ -93,6 +93,30 @@ impl<'d, M: PeriMode> I2c<'d, M> {
         });
     }
 
+    /// Reset the peripheral state machine, e.g. after it saw a glitch on the bus, keeping the
+    /// timing configuration.
+    pub(crate) fn reset_peripheral(&mut self) {
+        let cr2 = self.info.regs.cr2().read();
+        let ccr = self.info.regs.ccr().read();
+        let trise = self.info.regs.trise().read();
+
+        self.info.regs.cr1().modify(|reg| {
+            reg.set_pe(false);
+            reg.set_swrst(true);
+        });
+        self.info.regs.cr1().modify(|reg| {
+            reg.set_swrst(false);
+        });
+
+        self.info.regs.cr2().write_value(cr2);
+        self.info.regs.ccr().write_value(ccr);
+        self.info.regs.trise().write_value(trise);
+
+        self.info.regs.cr1().modify(|reg| {
+            reg.set_pe(true);
+        });
+    }
+
     fn check_and_clear_error_flags(info: &'static Info) -> Result<i2c::regs::Sr1, Error> {
         // Note that flags should only be cleared once they have been registered. If flags are
         // cleared otherwise, there may be an inherent race condition and flags may be missed.
@@ -127,6 +151,8 @@ impl<'d, M: PeriMode> I2c<'d, M> {
                 reg.0 = !0;
                 reg.set_af(false);
             });
+            // The master must release the bus with a STOP after a NACK, or it keeps holding SCL.
+            info.regs.cr1().modify(|reg| reg.set_stop(true));
             return Err(Error::Nack);
         }
 
@@ -501,18 +527,18 @@ impl<'d> I2c<'d, Async> {
 
     /// Write.
     pub async fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Error> {
-        self.write_frame(address, write, FrameOptions::FirstAndLastFrame)
-            .await?;
-
-        Ok(())
+        let timeout = self.timeout();
+        timeout
+            .with(self.write_frame(address, write, FrameOptions::FirstAndLastFrame))
+            .await
     }
 
     /// Read.
     pub async fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
-        self.read_frame(address, buffer, FrameOptions::FirstAndLastFrame)
-            .await?;
-
-        Ok(())
+        let timeout = self.timeout();
+        timeout
+            .with(self.read_frame(address, buffer, FrameOptions::FirstAndLastFrame))
+            .await
     }
 
     async fn read_frame(&mut self, address: u8, buffer: &mut [u8], frame: FrameOptions) -> Result<(), Error> {
@@ -679,8 +705,13 @@ impl<'d> I2c<'d, Async> {
             return Err(Error::Overrun);
         }
 
-        self.write_frame(address, write, FrameOptions::FirstFrame).await?;
-        self.read_frame(address, read, FrameOptions::FirstAndLastFrame).await
+        let timeout = self.timeout();
+        timeout
+            .with(async {
+                self.write_frame(address, write, FrameOptions::FirstFrame).await?;
+                self.read_frame(address, read, FrameOptions::FirstAndLastFrame).await
+            })
+            .await
     }
 
     /// Transaction with operations.
@@ -689,14 +720,19 @@ impl<'d> I2c<'d, Async> {
     ///
     /// [transaction contract]: embedded_hal_1::i2c::I2c::transaction
     pub async fn transaction(&mut self, addr: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
-        for (op, frame) in operation_frames(operations)? {
-            match op {
-                Operation::Read(read) => self.read_frame(addr, read, frame).await?,
-                Operation::Write(write) => self.write_frame(addr, write, frame).await?,
-            }
-        }
+        let timeout = self.timeout();
+        timeout
+            .with(async {
+                for (op, frame) in operation_frames(operations)? {
+                    match op {
+                        Operation::Read(read) => self.read_frame(addr, read, frame).await?,
+                        Operation::Write(write) => self.write_frame(addr, write, frame).await?,
+                    }
+                }
 
-        Ok(())
+                Ok(())
+            })
+            .await
     }
 }
 
@@ -737,12 +773,17 @@ struct Timings {
 }
 
 impl Timings {
-    fn new(i2cclk: Hertz, speed: Hertz) -> Self {
+    fn new(i2cclk: Hertz, speed: Hertz) -> Result<Self, ConfigError> {
         // Calculate settings for I2C speed modes
         let speed = speed.0;
         let clock = i2cclk.0;
         let freq = clock / 1_000_000;
-        assert!((2..=50).contains(&freq));
+        if !(2..=50).contains(&freq) {
+            return Err(ConfigError::KernelClockOutOfRange);
+        }
+        if speed > 400_000 {
+            return Err(ConfigError::FrequencyTooHigh);
+        }
 
         // Configure bus frequency into I2C peripheral
         let trise = if speed <= 100_000 {
@@ -785,7 +826,12 @@ impl Timings {
             }
         }
 
-        Self {
+        // CCR is 12 bits wide.
+        if ccr > 0xFFF {
+            return Err(ConfigError::FrequencyTooLow);
+        }
+
+        Ok(Self {
             freq: freq as u8,
             trise: trise as u8,
             ccr: ccr as u16,
@@ -796,15 +842,15 @@ impl Timings {
             //sclh,
             //sdadel,
             //scldel,
-        }
+        })
     }
 }
 
 impl<'d, M: PeriMode> SetConfig for I2c<'d, M> {
     type Config = Hertz;
-    type ConfigError = ();
-    fn set_config(&mut self, config: &Self::Config) -> Result<(), ()> {
-        let timings = Timings::new(self.kernel_clock, *config);
+    type ConfigError = ConfigError;
+    fn set_config(&mut self, config: &Self::Config) -> Result<(), ConfigError> {
+        let timings = Timings::new(self.kernel_clock, *config)?;
         self.info.regs.cr2().modify(|reg| {
             reg.set_freq(timings.freq);
         });
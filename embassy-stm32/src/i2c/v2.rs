@@ -1,4 +1,3 @@
-use core::cmp;
 use core::future::poll_fn;
 use core::task::Poll;
 
@@ -13,24 +12,67 @@ pub(crate) unsafe fn on_interrupt<T: Instance>() {
     let regs = T::info().regs;
     let isr = regs.isr().read();
 
-    if isr.tcr() || isr.tc() {
+    if isr.tcr() || isr.tc() || isr.nackf() || isr.arlo() || isr.berr() {
+        T::state().waker.wake();
+    }
+    // SMBus events.
+    if isr.alert() || isr.timeout() || isr.pecerr() {
+        T::state().waker.wake();
+    }
+    // Slave events.
+    if isr.addr() || isr.stopf() || isr.rxne() || isr.txis() || isr.ovr() {
         T::state().waker.wake();
     }
     // The flag can only be cleared by writting to nbytes, we won't do that here, so disable
-    // the interrupt
+    // the interrupt. Error flags are cleared by the woken task, which re-enables their interrupts,
+    // as are the slave events.
     critical_section::with(|_| {
-        regs.cr1().modify(|w| w.set_tcie(false));
+        regs.cr1().modify(|w| {
+            w.set_tcie(false);
+            w.set_nackie(false);
+            w.set_errie(false);
+            w.set_addrie(false);
+            w.set_rxie(false);
+            w.set_txie(false);
+            w.set_stopie(false);
+        });
     });
 }
 
 impl<'d, M: Mode> I2c<'d, M> {
-    pub(crate) fn init(&mut self, freq: Hertz, _config: Config) {
+    pub(crate) fn init(&mut self, freq: Hertz, config: Config) {
+        assert!(config.digital_filter < 16);
+
         self.info.regs.cr1().modify(|reg| {
             reg.set_pe(false);
-            reg.set_anfoff(false);
+            reg.set_anfoff(!config.analog_filter);
+            reg.set_dnf(i2c::vals::Dnf::from_bits(config.digital_filter));
+            reg.set_pecen(config.smbus.map_or(false, |c| c.pec));
         });
 
-        let timings = Timings::new(self.kernel_clock, freq.into());
+        if config.smbus.map_or(false, |c| c.timeouts) {
+            // Timeouts count in units of 2048 kernel clock cycles.
+            let units =
+                |ms: u32| ((self.kernel_clock.0 as u64 * ms as u64).div_ceil(2048 * 1000)).clamp(1, 4096) as u16 - 1;
+            self.info.regs.timeoutr().write(|reg| {
+                // tTIMEOUT: SCL low.
+                reg.set_timeouta(units(25));
+                reg.set_tidle(false);
+                reg.set_timouten(true);
+                // tLOW:MEXT: cumulative clock stretching of a byte by slaves.
+                reg.set_timeoutb(units(10));
+                reg.set_texten(true);
+            });
+        } else {
+            self.info.regs.timeoutr().write(|_| {});
+        }
+
+        let timings = unwrap!(Timings::new(
+            self.kernel_clock,
+            freq,
+            config.analog_filter,
+            config.digital_filter
+        ));
 
         self.info.regs.timingr().write(|reg| {
             reg.set_presc(timings.prescale);
@@ -45,6 +87,25 @@ impl<'d, M: Mode> I2c<'d, M> {
         });
     }
 
+    /// Reset the peripheral state machine, e.g. after it saw a glitch on the bus, keeping the
+    /// timing configuration.
+    pub(crate) fn reset_peripheral(&mut self) {
+        self.info.regs.cr1().modify(|reg| reg.set_pe(false));
+        // PE must stay low for at least 3 APB clock cycles.
+        for _ in 0..3 {
+            let _ = self.info.regs.cr1().read();
+        }
+        self.info.regs.cr1().modify(|reg| reg.set_pe(true));
+    }
+
+    /// Enable the SMBALERT input, as SMBus host.
+    pub(crate) fn enable_alert(&mut self) {
+        self.info.regs.cr1().modify(|reg| {
+            reg.set_smbhen(true);
+            reg.set_alerten(true);
+        });
+    }
+
     fn master_stop(&mut self) {
         self.info.regs.cr2().write(|w| w.set_stop(true));
     }
@@ -161,6 +222,27 @@ impl<'d, M: Mode> I2c<'d, M> {
         }
     }
 
+    /// Check for a NACK, bus error or lost arbitration during an interrupt-driven transfer.
+    ///
+    /// The peripheral sends a STOP by itself after a NACK, and releases the bus after losing
+    /// arbitration.
+    fn check_errors(&self) -> Result<(), Error> {
+        let isr = self.info.regs.isr().read();
+        if isr.berr() {
+            self.info.regs.icr().write(|reg| reg.set_berrcf(true));
+            Err(Error::Bus)
+        } else if isr.arlo() {
+            self.info.regs.icr().write(|reg| reg.set_arlocf(true));
+            Err(Error::Arbitration)
+        } else if isr.nackf() {
+            self.info.regs.icr().write(|reg| reg.set_nackcf(true));
+            self.flush_txdr();
+            Err(Error::Nack)
+        } else {
+            Ok(())
+        }
+    }
+
     fn wait_txe(&self, timeout: Timeout) -> Result<(), Error> {
         loop {
             let isr = self.info.regs.isr().read();
@@ -339,9 +421,117 @@ impl<'d, M: Mode> I2c<'d, M> {
     ///
     /// [transaction contract]: embedded_hal_1::i2c::I2c::transaction
     pub fn blocking_transaction(&mut self, addr: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
-        let _ = addr;
-        let _ = operations;
-        todo!()
+        self.transaction_internal(addr, operations, self.timeout())
+    }
+
+    fn transaction_internal(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+        timeout: Timeout,
+    ) -> Result<(), Error> {
+        check_reads(operations)?;
+
+        let mut first = true;
+        let mut rest = operations;
+        while !rest.is_empty() {
+            let (is_read, segment, tail) = next_segment(rest);
+            let last = tail.is_empty();
+
+            let result = if is_read {
+                self.read_segment(address, segment, !first, last, timeout)
+            } else {
+                self.write_segment(address, segment, last, timeout)
+            };
+            if let Err(err) = result {
+                self.master_stop();
+                return Err(err);
+            }
+
+            first = false;
+            rest = tail;
+        }
+
+        Ok(())
+    }
+
+    /// Read into consecutive read operations, in chunks of up to 255 bytes.
+    ///
+    /// The last segment of a transaction ends with an automatic STOP, others wait for the
+    /// repeated start of the next segment.
+    fn read_segment(
+        &mut self,
+        address: u8,
+        segment: &mut [Operation<'_>],
+        restart: bool,
+        last: bool,
+        timeout: Timeout,
+    ) -> Result<(), Error> {
+        let mut remaining: usize = segment.iter().map(operation_len).sum();
+        let mut chunk = remaining.min(255);
+        remaining -= chunk;
+
+        let stop = if last { Stop::Automatic } else { Stop::Software };
+        Self::master_read(self.info, address, chunk, stop, remaining > 0, restart, timeout)?;
+
+        for op in segment {
+            let Operation::Read(read) = op else { unreachable!() };
+            for byte in read.iter_mut() {
+                if chunk == 0 {
+                    chunk = remaining.min(255);
+                    remaining -= chunk;
+                    Self::master_continue(self.info, chunk, remaining > 0, timeout)?;
+                }
+
+                self.wait_rxne(timeout)?;
+                *byte = self.info.regs.rxdr().read().rxdata();
+                chunk -= 1;
+            }
+        }
+
+        if !last {
+            self.wait_tc(timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Write consecutive write operations, in chunks of up to 255 bytes.
+    ///
+    /// The last segment of a transaction ends with a STOP, others wait for the repeated start of
+    /// the next segment.
+    fn write_segment(
+        &mut self,
+        address: u8,
+        segment: &[Operation<'_>],
+        last: bool,
+        timeout: Timeout,
+    ) -> Result<(), Error> {
+        let mut remaining: usize = segment.iter().map(operation_len).sum();
+        let mut chunk = remaining.min(255);
+        remaining -= chunk;
+
+        Self::master_write(self.info, address, chunk, Stop::Software, remaining > 0, timeout)?;
+
+        for op in segment {
+            let Operation::Write(write) = op else { unreachable!() };
+            for byte in write.iter() {
+                if chunk == 0 {
+                    chunk = remaining.min(255);
+                    remaining -= chunk;
+                    Self::master_continue(self.info, chunk, remaining > 0, timeout)?;
+                }
+
+                self.wait_txe(timeout)?;
+                self.info.regs.txdr().write(|w| w.set_txdata(*byte));
+                chunk -= 1;
+            }
+        }
+
+        self.wait_tc(timeout)?;
+        if last {
+            self.master_stop();
+        }
+        Ok(())
     }
 
     /// Blocking write multiple buffers.
@@ -424,15 +614,183 @@ impl<'d, M: Mode> I2c<'d, M> {
         self.master_stop();
         result
     }
+
+    // =========================
+    //  SMBus
+
+    /// Write, followed by a PEC if enabled in [`SmbusConfig`], then a STOP.
+    ///
+    /// At most 254 bytes can be written.
+    pub fn blocking_smbus_write(&mut self, address: u8, write: &[u8]) -> Result<(), Error> {
+        let timeout = self.timeout();
+        self.smbus_start(address, i2c::vals::Dir::WRITE, write.len(), true, timeout)?;
+
+        for byte in write {
+            self.wait_txe(timeout)?;
+            self.info.regs.txdr().write(|w| w.set_txdata(*byte));
+        }
+        self.wait_smbus_stop(timeout)
+    }
+
+    /// Read, checking the PEC following the data if enabled in [`SmbusConfig`].
+    ///
+    /// At most 254 bytes can be read. Returns [`Error::Crc`] if the PEC doesn't match.
+    pub fn blocking_smbus_read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Error> {
+        let timeout = self.timeout();
+        self.smbus_start(address, i2c::vals::Dir::READ, read.len(), true, timeout)?;
+        self.smbus_read_data(read, timeout)
+    }
+
+    /// Write, restart, read, checking the PEC following the data if enabled in [`SmbusConfig`].
+    ///
+    /// This is the SMBus read byte, read word and block read protocols, where `write` holds the
+    /// command code. At most 255 bytes can be written, and 254 read. Returns [`Error::Crc`] if
+    /// the PEC, covering the whole message, doesn't match.
+    pub fn blocking_smbus_write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        let timeout = self.timeout();
+        self.smbus_start(address, i2c::vals::Dir::WRITE, write.len(), false, timeout)?;
+
+        for byte in write {
+            self.wait_txe(timeout)?;
+            self.info.regs.txdr().write(|w| w.set_txdata(*byte));
+        }
+        if let Err(err) = self.wait_tc(timeout) {
+            self.master_stop();
+            return Err(err);
+        }
+
+        self.smbus_start(address, i2c::vals::Dir::READ, read.len(), true, timeout)?;
+        self.smbus_read_data(read, timeout)
+    }
+
+    /// Start an SMBus transfer of `length` data bytes, plus the PEC if enabled and `last`.
+    ///
+    /// The last transfer of a message ends with an automatic STOP after the PEC.
+    fn smbus_start(
+        &mut self,
+        address: u8,
+        dir: i2c::vals::Dir,
+        length: usize,
+        last: bool,
+        timeout: Timeout,
+    ) -> Result<(), Error> {
+        let pec = last && self.info.regs.cr1().read().pecen();
+        let nbytes = length + pec as usize;
+        assert!(nbytes < 256);
+
+        while self.info.regs.cr2().read().start() {
+            timeout.check()?;
+        }
+
+        self.info.regs.cr2().modify(|w| {
+            w.set_sadd((address << 1 | 0) as u16);
+            w.set_add10(i2c::vals::Addmode::BIT7);
+            w.set_dir(dir);
+            w.set_nbytes(nbytes as u8);
+            w.set_reload(i2c::vals::Reload::COMPLETED);
+            w.set_autoend(match last {
+                true => i2c::vals::Autoend::AUTOMATIC,
+                false => i2c::vals::Autoend::SOFTWARE,
+            });
+            w.set_pecbyte(pec);
+            w.set_start(true);
+        });
+
+        Ok(())
+    }
+
+    fn smbus_read_data(&mut self, read: &mut [u8], timeout: Timeout) -> Result<(), Error> {
+        for byte in read {
+            self.wait_rxne(timeout)?;
+            *byte = self.info.regs.rxdr().read().rxdata();
+        }
+        if self.info.regs.cr1().read().pecen() {
+            // The PEC byte is checked by the peripheral, and also lands in RXDR.
+            self.wait_rxne(timeout)?;
+            let _ = self.info.regs.rxdr().read();
+        }
+        self.wait_smbus_stop(timeout)
+    }
+
+    /// Wait for the automatic STOP ending a message, and report PEC errors and SMBus timeouts.
+    fn wait_smbus_stop(&self, timeout: Timeout) -> Result<(), Error> {
+        loop {
+            let isr = self.info.regs.isr().read();
+            if isr.timeout() {
+                // The peripheral released the bus, with a STOP as master.
+                self.info.regs.icr().write(|reg| {
+                    reg.set_timoutcf(true);
+                    reg.set_stopcf(true);
+                });
+                return Err(Error::Timeout);
+            } else if isr.stopf() {
+                self.info.regs.icr().write(|reg| reg.set_stopcf(true));
+                if isr.pecerr() {
+                    self.info.regs.icr().write(|reg| reg.set_peccf(true));
+                    return Err(Error::Crc);
+                }
+                return self.check_errors();
+            } else if isr.berr() {
+                self.info.regs.icr().write(|reg| reg.set_berrcf(true));
+                return Err(Error::Bus);
+            } else if isr.arlo() {
+                self.info.regs.icr().write(|reg| reg.set_arlocf(true));
+                return Err(Error::Arbitration);
+            }
+
+            timeout.check()?;
+        }
+    }
+}
+
+fn operation_len(op: &Operation<'_>) -> usize {
+    match op {
+        Operation::Read(read) => read.len(),
+        Operation::Write(write) => write.len(),
+    }
+}
+
+/// Check empty reads before starting, like `operation_frames`, so a transaction isn't aborted in
+/// the middle.
+fn check_reads(operations: &[Operation<'_>]) -> Result<(), Error> {
+    if operations
+        .iter()
+        .any(|op| matches!(op, Operation::Read(read) if read.is_empty()))
+    {
+        return Err(Error::Overrun);
+    }
+    Ok(())
+}
+
+/// Split the next segment of a transaction off `operations`, returning whether it is a read.
+///
+/// Consecutive operations of the same type are merged into a single segment, segments are
+/// separated by a repeated start.
+fn next_segment<'a, 'b>(
+    operations: &'a mut [Operation<'b>],
+) -> (bool, &'a mut [Operation<'b>], &'a mut [Operation<'b>]) {
+    let is_read = matches!(operations[0], Operation::Read(_));
+    let n = operations
+        .iter()
+        .position(|op| matches!(op, Operation::Read(_)) != is_read)
+        .unwrap_or(operations.len());
+    let (segment, tail) = operations.split_at_mut(n);
+    (is_read, segment, tail)
 }
 
 impl<'d> I2c<'d, Async> {
+    /// Whether a transfer of `len` bytes should use DMA, or be done by polling.
+    fn use_dma(&self, len: usize) -> bool {
+        len != 0 && len >= self.dma_threshold
+    }
+
     async fn write_dma_internal(
         &mut self,
         address: u8,
         write: &[u8],
         first_slice: bool,
         last_slice: bool,
+        send_stop: bool,
         timeout: Timeout,
     ) -> Result<(), Error> {
         let total_len = write.len();
@@ -459,12 +817,24 @@ impl<'d> I2c<'d, Async> {
                     w.set_txdmaen(false);
                 }
                 w.set_tcie(false);
+                w.set_nackie(false);
+                w.set_errie(false);
             })
         });
 
         poll_fn(|cx| {
             self.state.waker.register(cx.waker());
 
+            if let Err(e) = self.check_errors() {
+                // The transfer is over, the DMA request won't come.
+                self.info.regs.cr1().modify(|w| w.set_txdmaen(false));
+                return Poll::Ready(Err(e));
+            }
+            self.info.regs.cr1().modify(|w| {
+                w.set_nackie(true);
+                w.set_errie(true);
+            });
+
             let isr = self.info.regs.isr().read();
             if remaining_len == total_len {
                 if first_slice {
@@ -504,7 +874,9 @@ impl<'d> I2c<'d, Async> {
         if last_slice {
             // This should be done already
             self.wait_tc(timeout)?;
-            self.master_stop();
+            if send_stop {
+                self.master_stop();
+            }
         }
 
         drop(on_drop);
@@ -517,6 +889,7 @@ impl<'d> I2c<'d, Async> {
         address: u8,
         buffer: &mut [u8],
         restart: bool,
+        send_stop: bool,
         timeout: Timeout,
     ) -> Result<(), Error> {
         let total_len = buffer.len();
@@ -539,12 +912,22 @@ impl<'d> I2c<'d, Async> {
             regs.cr1().modify(|w| {
                 w.set_rxdmaen(false);
                 w.set_tcie(false);
+                w.set_nackie(false);
+                w.set_errie(false);
             })
         });
 
         poll_fn(|cx| {
             self.state.waker.register(cx.waker());
 
+            if let Err(e) = self.check_errors() {
+                return Poll::Ready(Err(e));
+            }
+            self.info.regs.cr1().modify(|w| {
+                w.set_nackie(true);
+                w.set_errie(true);
+            });
+
             let isr = self.info.regs.isr().read();
             if remaining_len == total_len {
                 Self::master_read(
@@ -579,7 +962,9 @@ impl<'d> I2c<'d, Async> {
 
         // This should be done already
         self.wait_tc(timeout)?;
-        self.master_stop();
+        if send_stop {
+            self.master_stop();
+        }
 
         drop(on_drop);
 
@@ -592,11 +977,11 @@ impl<'d> I2c<'d, Async> {
     /// Write.
     pub async fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Error> {
         let timeout = self.timeout();
-        if write.is_empty() {
+        if !self.use_dma(write.len()) {
             self.write_internal(address, write, true, timeout)
         } else {
             timeout
-                .with(self.write_dma_internal(address, write, true, true, timeout))
+                .with(self.write_dma_internal(address, write, true, true, true, timeout))
                 .await
         }
     }
@@ -610,6 +995,9 @@ impl<'d> I2c<'d, Async> {
         if write.is_empty() {
             return Err(Error::ZeroLengthTransfer);
         }
+        if !self.use_dma(write.iter().map(|w| w.len()).sum()) {
+            return self.blocking_write_vectored(address, write);
+        }
         let mut iter = write.iter();
 
         let mut first = true;
@@ -618,7 +1006,7 @@ impl<'d> I2c<'d, Async> {
             let next = iter.next();
             let is_last = next.is_none();
 
-            let fut = self.write_dma_internal(address, c, first, is_last, timeout);
+            let fut = self.write_dma_internal(address, c, first, is_last, true, timeout);
             timeout.with(fut).await?;
             first = false;
             current = next;
@@ -630,10 +1018,10 @@ impl<'d> I2c<'d, Async> {
     pub async fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
         let timeout = self.timeout();
 
-        if buffer.is_empty() {
+        if !self.use_dma(buffer.len()) {
             self.read_internal(address, buffer, false, timeout)
         } else {
-            let fut = self.read_dma_internal(address, buffer, false, timeout);
+            let fut = self.read_dma_internal(address, buffer, false, true, timeout);
             timeout.with(fut).await
         }
     }
@@ -642,17 +1030,17 @@ impl<'d> I2c<'d, Async> {
     pub async fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
         let timeout = self.timeout();
 
-        if write.is_empty() {
+        if !self.use_dma(write.len()) {
             self.write_internal(address, write, false, timeout)?;
         } else {
-            let fut = self.write_dma_internal(address, write, true, true, timeout);
+            let fut = self.write_dma_internal(address, write, true, true, true, timeout);
             timeout.with(fut).await?;
         }
 
-        if read.is_empty() {
+        if !self.use_dma(read.len()) {
             self.read_internal(address, read, true, timeout)?;
         } else {
-            let fut = self.read_dma_internal(address, read, true, timeout);
+            let fut = self.read_dma_internal(address, read, true, true, timeout);
             timeout.with(fut).await?;
         }
 
@@ -663,11 +1051,352 @@ impl<'d> I2c<'d, Async> {
     ///
     /// Consecutive operations of same type are merged. See [transaction contract] for details.
     ///
+    /// Write segments of at least [`Config::dma_threshold`] bytes, and reads of a single buffer of
+    /// at least as many bytes, use DMA. Other segments are polled.
+    ///
     /// [transaction contract]: embedded_hal_1::i2c::I2c::transaction
     pub async fn transaction(&mut self, addr: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
-        let _ = addr;
-        let _ = operations;
-        todo!()
+        let timeout = self.timeout();
+        check_reads(operations)?;
+
+        let mut first = true;
+        let mut rest = operations;
+        while !rest.is_empty() {
+            let (is_read, segment, tail) = next_segment(rest);
+            let last = tail.is_empty();
+
+            let result = if is_read {
+                self.read_segment_async(addr, segment, !first, last, timeout).await
+            } else {
+                self.write_segment_async(addr, segment, last, timeout).await
+            };
+            if let Err(err) = result {
+                self.master_stop();
+                return Err(err);
+            }
+
+            first = false;
+            rest = tail;
+        }
+
+        Ok(())
+    }
+
+    /// Read segment of a transaction, see [`read_segment`](Self::read_segment).
+    async fn read_segment_async(
+        &mut self,
+        address: u8,
+        segment: &mut [Operation<'_>],
+        restart: bool,
+        last: bool,
+        timeout: Timeout,
+    ) -> Result<(), Error> {
+        match segment {
+            [Operation::Read(read)] if self.use_dma(read.len()) => {
+                let fut = self.read_dma_internal(address, read, restart, last, timeout);
+                timeout.with(fut).await
+            }
+            // The DMA transfer of a read covers a single buffer.
+            _ => self.read_segment(address, segment, restart, last, timeout),
+        }
+    }
+
+    /// Write segment of a transaction, see [`write_segment`](Self::write_segment).
+    async fn write_segment_async(
+        &mut self,
+        address: u8,
+        segment: &[Operation<'_>],
+        last: bool,
+        timeout: Timeout,
+    ) -> Result<(), Error> {
+        if !self.use_dma(segment.iter().map(operation_len).sum()) {
+            return self.write_segment(address, segment, last, timeout);
+        }
+
+        // Empty writes have nothing to transfer, the others are written as slices of one write.
+        let mut writes = segment
+            .iter()
+            .filter_map(|op| match op {
+                Operation::Write(write) if !write.is_empty() => Some(*write),
+                _ => None,
+            })
+            .peekable();
+        let mut first_slice = true;
+        while let Some(write) = writes.next() {
+            let last_slice = writes.peek().is_none();
+            let fut = self.write_dma_internal(address, write, first_slice, last_slice, last, timeout);
+            timeout.with(fut).await?;
+            first_slice = false;
+        }
+        Ok(())
+    }
+
+    /// Wait for a device to pull the SMBALERT line low.
+    ///
+    /// Only available with [`I2c::new_with_smba`]. Find the device with
+    /// [`smbus_alert_response`](Self::smbus_alert_response), which makes it release the line.
+    pub async fn wait_alert(&mut self) {
+        assert!(self.smba.is_some());
+
+        poll_fn(|cx| {
+            self.state.waker.register(cx.waker());
+            if self.info.regs.isr().read().alert() {
+                self.info.regs.icr().write(|reg| reg.set_alertcf(true));
+                return Poll::Ready(());
+            }
+            self.info.regs.cr1().modify(|w| w.set_errie(true));
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Read the alert response address, returning the 7-bit address of the device signaling an
+    /// alert with the lowest address.
+    pub async fn smbus_alert_response(&mut self) -> Result<u8, Error> {
+        let mut buf = [0];
+        self.read(0x0C, &mut buf).await?;
+        Ok(buf[0] >> 1)
+    }
+}
+
+impl From<AddrMask> for i2c::vals::Oamsk {
+    fn from(mask: AddrMask) -> Self {
+        match mask {
+            AddrMask::NoMask => i2c::vals::Oamsk::NOMASK,
+            AddrMask::Mask1 => i2c::vals::Oamsk::MASK1,
+            AddrMask::Mask2 => i2c::vals::Oamsk::MASK2,
+            AddrMask::Mask3 => i2c::vals::Oamsk::MASK3,
+            AddrMask::Mask4 => i2c::vals::Oamsk::MASK4,
+            AddrMask::Mask5 => i2c::vals::Oamsk::MASK5,
+            AddrMask::Mask6 => i2c::vals::Oamsk::MASK6,
+            AddrMask::Mask7 => i2c::vals::Oamsk::MASK7,
+        }
+    }
+}
+
+/// Event ending a wait of the slave driver.
+enum SlaveEvent {
+    /// An own address was matched, a transaction starts.
+    Addr,
+    /// A byte was received.
+    Rxne,
+    /// A byte must be written to send.
+    Txis,
+    /// The master didn't acknowledge the last byte sent.
+    Nack,
+    /// The master sent a STOP.
+    Stop,
+}
+
+impl<'d> I2cSlave<'d> {
+    pub(crate) fn init(&self, kernel_clock: Hertz, freq: Hertz, config: Config, addr_config: SlaveAddrConfig) {
+        let regs = self.info.regs;
+
+        regs.cr1().modify(|reg| {
+            reg.set_pe(false);
+            reg.set_anfoff(!config.analog_filter);
+            reg.set_dnf(i2c::vals::Dnf::from_bits(config.digital_filter));
+        });
+
+        // Only the data setup and hold times matter in slave mode.
+        let timings = unwrap!(Timings::new(
+            kernel_clock,
+            freq,
+            config.analog_filter,
+            config.digital_filter
+        ));
+        regs.timingr().write(|reg| {
+            reg.set_presc(timings.prescale);
+            reg.set_scll(timings.scll);
+            reg.set_sclh(timings.sclh);
+            reg.set_sdadel(timings.sdadel);
+            reg.set_scldel(timings.scldel);
+        });
+
+        regs.oar1().write(|reg| reg.set_oa1en(false));
+        regs.oar2().write(|reg| reg.set_oa2en(false));
+
+        regs.oar1().write(|reg| {
+            match addr_config.primary {
+                Address::SevenBit(addr) => {
+                    reg.set_oa1((addr as u16) << 1);
+                    reg.set_oa1mode(i2c::vals::Addmode::BIT7);
+                }
+                Address::TenBit(addr) => {
+                    reg.set_oa1(addr);
+                    reg.set_oa1mode(i2c::vals::Addmode::BIT10);
+                }
+            }
+            reg.set_oa1en(true);
+        });
+
+        if let Some(secondary) = addr_config.secondary {
+            regs.oar2().write(|reg| {
+                reg.set_oa2(secondary.addr);
+                reg.set_oa2msk(secondary.mask.into());
+                reg.set_oa2en(true);
+            });
+        }
+
+        regs.cr1().modify(|reg| {
+            reg.set_gcen(addr_config.general_call);
+            reg.set_nostretch(false);
+            reg.set_sbc(false);
+            reg.set_pe(true);
+        });
+    }
+
+    /// Wait for the master to address this slave.
+    ///
+    /// The clock is stretched until the transaction is answered with
+    /// [`respond_to_write`](Self::respond_to_write) or [`respond_to_read`](Self::respond_to_read),
+    /// which must be called next.
+    pub async fn listen(&mut self) -> Result<SlaveCommand, Error> {
+        loop {
+            match self.wait_event().await? {
+                SlaveEvent::Addr => break,
+                // Leftovers of a previous transaction, e.g. one dropped while in progress.
+                SlaveEvent::Rxne => {
+                    let _ = self.info.regs.rxdr().read();
+                }
+                SlaveEvent::Txis => self.info.regs.txdr().write(|w| w.set_txdata(0xFF)),
+                SlaveEvent::Nack => self.info.regs.icr().write(|w| w.set_nackcf(true)),
+                SlaveEvent::Stop => self.info.regs.icr().write(|w| w.set_stopcf(true)),
+            }
+        }
+
+        let isr = self.info.regs.isr().read();
+        let oar1 = self.info.regs.oar1().read();
+        let addcode = isr.addcode();
+
+        let address = if oar1.oa1mode() == i2c::vals::Addmode::BIT10 && addcode & 0x7C == 0x78 {
+            // 10-bit addresses only report the header, which only the primary address can match.
+            Address::TenBit(oar1.oa1())
+        } else {
+            Address::SevenBit(addcode)
+        };
+        let kind = match isr.dir() {
+            i2c::vals::Dir::WRITE => SlaveCommandKind::Write,
+            i2c::vals::Dir::READ => SlaveCommandKind::Read,
+        };
+
+        Ok(SlaveCommand { kind, address })
+    }
+
+    /// Receive the data of a write transaction returned by [`listen`](Self::listen).
+    ///
+    /// Returns the number of bytes received, when the master sends a STOP or a repeated start.
+    /// Bytes beyond the size of `buffer` are not acknowledged, and dropped.
+    pub async fn respond_to_write(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let regs = self.info.regs;
+        let mut len = 0;
+
+        if buffer.is_empty() {
+            regs.cr2().modify(|w| w.set_nack(true));
+        }
+        regs.icr().write(|w| w.set_addrcf(true));
+
+        loop {
+            match self.wait_event().await? {
+                SlaveEvent::Rxne => {
+                    let byte = regs.rxdr().read().rxdata();
+                    if len < buffer.len() {
+                        buffer[len] = byte;
+                        len += 1;
+                    }
+                    if len == buffer.len() {
+                        regs.cr2().modify(|w| w.set_nack(true));
+                    }
+                }
+                SlaveEvent::Stop => {
+                    regs.icr().write(|w| w.set_stopcf(true));
+                    return Ok(len);
+                }
+                // Repeated start, left for the next `listen`.
+                SlaveEvent::Addr => return Ok(len),
+                SlaveEvent::Txis | SlaveEvent::Nack => {}
+            }
+        }
+    }
+
+    /// Send the data of a read transaction returned by [`listen`](Self::listen).
+    ///
+    /// Returns the number of bytes sent, when the master ends the transaction. If the master reads
+    /// more than `data`, `0xFF` is sent for the extra bytes, which are counted too.
+    pub async fn respond_to_read(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let regs = self.info.regs;
+        let mut written = 0;
+
+        // Discard a byte left over from a previous transaction.
+        regs.isr().modify(|w| w.set_txe(true));
+        regs.icr().write(|w| w.set_addrcf(true));
+
+        loop {
+            match self.wait_event().await? {
+                SlaveEvent::Txis => {
+                    let byte = data.get(written).copied().unwrap_or(0xFF);
+                    regs.txdr().write(|w| w.set_txdata(byte));
+                    written += 1;
+                }
+                SlaveEvent::Nack => regs.icr().write(|w| w.set_nackcf(true)),
+                SlaveEvent::Stop | SlaveEvent::Addr => {
+                    // The byte written after the last one acknowledged was never sent.
+                    let pending = !regs.isr().read().txe();
+                    regs.isr().modify(|w| w.set_txe(true));
+                    // A repeated start is left for the next `listen`.
+                    if regs.isr().read().stopf() {
+                        regs.icr().write(|w| w.set_stopcf(true));
+                    }
+                    return Ok(written - pending as usize);
+                }
+                SlaveEvent::Rxne => {}
+            }
+        }
+    }
+
+    async fn wait_event(&mut self) -> Result<SlaveEvent, Error> {
+        let regs = self.info.regs;
+
+        poll_fn(|cx| {
+            self.state.waker.register(cx.waker());
+
+            let isr = regs.isr().read();
+            if isr.berr() {
+                regs.icr().write(|w| w.set_berrcf(true));
+                return Poll::Ready(Err(Error::Bus));
+            } else if isr.arlo() {
+                regs.icr().write(|w| w.set_arlocf(true));
+                return Poll::Ready(Err(Error::Arbitration));
+            } else if isr.ovr() {
+                regs.icr().write(|w| w.set_ovrcf(true));
+                return Poll::Ready(Err(Error::Overrun));
+            }
+
+            // Received bytes are handled before the STOP or repeated start which follows them.
+            let event = if isr.rxne() {
+                SlaveEvent::Rxne
+            } else if isr.txis() {
+                SlaveEvent::Txis
+            } else if isr.nackf() {
+                SlaveEvent::Nack
+            } else if isr.stopf() {
+                SlaveEvent::Stop
+            } else if isr.addr() {
+                SlaveEvent::Addr
+            } else {
+                regs.cr1().modify(|w| {
+                    w.set_addrie(true);
+                    w.set_rxie(true);
+                    w.set_txie(true);
+                    w.set_nackie(true);
+                    w.set_stopie(true);
+                    w.set_errie(true);
+                });
+                return Poll::Pending;
+            };
+            Poll::Ready(Ok(event))
+        })
+        .await
     }
 }
 
@@ -700,106 +1429,115 @@ struct Timings {
     scldel: u8,
 }
 
-impl Timings {
-    fn new(i2cclk: Hertz, freq: Hertz) -> Self {
-        let i2cclk = i2cclk.0;
-        let freq = freq.0;
-        // Refer to RM0433 Rev 7 Figure 539 for setup and hold timing:
-        //
-        // t_I2CCLK = 1 / PCLK1
-        // t_PRESC  = (PRESC + 1) * t_I2CCLK
-        // t_SCLL   = (SCLL + 1) * t_PRESC
-        // t_SCLH   = (SCLH + 1) * t_PRESC
-        //
-        // t_SYNC1 + t_SYNC2 > 4 * t_I2CCLK
-        // t_SCL ~= t_SYNC1 + t_SYNC2 + t_SCLL + t_SCLH
-        let ratio = i2cclk / freq;
-
-        // For the standard-mode configuration method, we must have a ratio of 4
-        // or higher
-        assert!(ratio >= 4, "The I2C PCLK must be at least 4 times the bus frequency!");
-
-        let (presc_reg, scll, sclh, sdadel, scldel) = if freq > 100_000 {
-            // Fast-mode (Fm) or Fast-mode Plus (Fm+)
-            // here we pick SCLL + 1 = 2 * (SCLH + 1)
-
-            // Prescaler, 384 ticks for sclh/scll. Round up then subtract 1
-            let presc_reg = ((ratio - 1) / 384) as u8;
-            // ratio < 1200 by pclk 120MHz max., therefore presc < 16
-
-            // Actual precale value selected
-            let presc = (presc_reg + 1) as u32;
-
-            let sclh = ((ratio / presc) - 3) / 3;
-            let scll = (2 * (sclh + 1)) - 1;
-
-            let (sdadel, scldel) = if freq > 400_000 {
-                // Fast-mode Plus (Fm+)
-                assert!(i2cclk >= 17_000_000); // See table in datsheet
-
-                let sdadel = i2cclk / 8_000_000 / presc;
-                let scldel = i2cclk / 4_000_000 / presc - 1;
-
-                (sdadel, scldel)
-            } else {
-                // Fast-mode (Fm)
-                assert!(i2cclk >= 8_000_000); // See table in datsheet
-
-                let sdadel = i2cclk / 4_000_000 / presc;
-                let scldel = i2cclk / 2_000_000 / presc - 1;
-
-                (sdadel, scldel)
-            };
-
-            (presc_reg, scll as u8, sclh as u8, sdadel as u8, scldel as u8)
-        } else {
-            // Standard-mode (Sm)
-            // here we pick SCLL = SCLH
-            assert!(i2cclk >= 2_000_000); // See table in datsheet
-
-            // Prescaler, 512 ticks for sclh/scll. Round up then
-            // subtract 1
-            let presc = (ratio - 1) / 512;
-            let presc_reg = cmp::min(presc, 15) as u8;
-
-            // Actual prescale value selected
-            let presc = (presc_reg + 1) as u32;
-
-            let sclh = ((ratio / presc) - 2) / 2;
-            let scll = sclh;
-
-            // Speed check
-            assert!(sclh < 256, "The I2C PCLK is too fast for this bus frequency!");
+/// Bus timing characteristics of an I2C mode, in ns, from the I2C specification (UM10204).
+struct BusTiming {
+    /// Minimum SCL low period.
+    low: u64,
+    /// Minimum SCL high period.
+    high: u64,
+    /// Maximum rise time.
+    rise: u64,
+    /// Maximum fall time.
+    fall: u64,
+    /// Minimum data setup time.
+    setup: u64,
+    /// Maximum data valid time.
+    valid: u64,
+}
 
-            let sdadel = i2cclk / 2_000_000 / presc;
-            let scldel = i2cclk / 500_000 / presc - 1;
+impl BusTiming {
+    const STANDARD: Self = Self {
+        low: 4700,
+        high: 4000,
+        rise: 1000,
+        fall: 300,
+        setup: 250,
+        valid: 3450,
+    };
+    const FAST: Self = Self {
+        low: 1300,
+        high: 600,
+        rise: 300,
+        fall: 300,
+        setup: 100,
+        valid: 900,
+    };
+    const FAST_PLUS: Self = Self {
+        low: 500,
+        high: 260,
+        rise: 120,
+        fall: 120,
+        setup: 50,
+        valid: 450,
+    };
+}
 
-            (presc_reg, scll as u8, sclh as u8, sdadel as u8, scldel as u8)
+impl Timings {
+    /// Compute the timings for a bus frequency of up to 100 kHz (standard mode), 400 kHz (fast
+    /// mode) or 1 MHz (fast mode plus), from the kernel clock and the input filters delaying SCL
+    /// and SDA, following the method of AN4235.
+    ///
+    /// The frequency is rounded down, with the SCL low and high periods split in proportion of
+    /// the minimum periods of the mode. The smallest prescaler fitting the periods is used, for
+    /// the finest resolution.
+    fn new(i2cclk: Hertz, freq: Hertz, analog_filter: bool, dnf: u8) -> Result<Self, ConfigError> {
+        let bus = match freq.0 {
+            0..=100_000 => BusTiming::STANDARD,
+            100_001..=400_000 => BusTiming::FAST,
+            400_001..=1_000_000 => BusTiming::FAST_PLUS,
+            _ => return Err(ConfigError::FrequencyTooHigh),
         };
 
-        // Sanity check
-        assert!(presc_reg < 16);
+        // Times in ps.
+        let clk = 1_000_000_000_000 / i2cclk.0 as u64;
+        let period = 1_000_000_000_000 / freq.0 as u64;
+        let (af_min, af_max) = if analog_filter { (50_000, 260_000) } else { (0, 0) };
+        let dnf = dnf as u64;
+        let ns = |t: u64| t * 1000;
+
+        for presc in 0..16u64 {
+            let tpresc = (presc + 1) * clk;
+
+            // Data hold time, covering the fall time: SDADEL * tPRESC >= tf - tAF(min) - (DNF + 3) * tI2CCLK,
+            // and keeping the data valid time.
+            let sdadel = ns(bus.fall).saturating_sub(af_min + (dnf + 3) * clk).div_ceil(tpresc);
+            let hold_max = ns(bus.valid).saturating_sub(ns(bus.rise) + af_max + (dnf + 4) * clk);
+            // Data setup time: (SCLDEL + 1) * tPRESC >= tr + tSU;DAT(min).
+            let scldel = (ns(bus.rise) + ns(bus.setup)).div_ceil(tpresc).saturating_sub(1);
+            if sdadel > 15 || scldel > 15 || sdadel * tpresc > hold_max {
+                continue;
+            }
 
-        // Keep values within reasonable limits for fast per_ck
-        let sdadel = cmp::max(sdadel, 2);
-        let scldel = cmp::max(scldel, 4);
+            // Each SCL edge is delayed by its rise or fall time, the filters and the synchronization.
+            let sync = ns(bus.rise) + ns(bus.fall) + 2 * (af_min + (dnf + 2) * clk);
+            let low_min = ns(bus.low).div_ceil(tpresc);
+            let high_min = ns(bus.high).div_ceil(tpresc);
+            let ticks = period.saturating_sub(sync).div_ceil(tpresc).max(low_min + high_min);
+            let low = (ticks * bus.low / (bus.low + bus.high)).max(low_min);
+            let high = (ticks - low).max(high_min);
+            if low > 256 || high > 256 {
+                continue;
+            }
 
-        //(presc_reg, scll, sclh, sdadel, scldel)
-        Self {
-            prescale: presc_reg,
-            scll,
-            sclh,
-            sdadel,
-            scldel,
+            return Ok(Self {
+                prescale: presc as u8,
+                scll: (low - 1) as u8,
+                sclh: (high - 1) as u8,
+                sdadel: sdadel as u8,
+                scldel: scldel as u8,
+            });
         }
+
+        Err(ConfigError::KernelClockOutOfRange)
     }
 }
 
 impl<'d, M: Mode> SetConfig for I2c<'d, M> {
     type Config = Hertz;
-    type ConfigError = ();
-    fn set_config(&mut self, config: &Self::Config) -> Result<(), ()> {
-        let timings = Timings::new(self.kernel_clock, *config);
+    type ConfigError = ConfigError;
+    fn set_config(&mut self, config: &Self::Config) -> Result<(), ConfigError> {
+        let cr1 = self.info.regs.cr1().read();
+        let timings = Timings::new(self.kernel_clock, *config, !cr1.anfoff(), cr1.dnf().to_bits())?;
         self.info.regs.timingr().write(|reg| {
             reg.set_presc(timings.prescale);
             reg.set_scll(timings.scll);
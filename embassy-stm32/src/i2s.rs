@@ -3,13 +3,38 @@
 use embassy_hal_internal::into_ref;
 
 use crate::dma::ChannelAndRequest;
-use crate::gpio::{AfType, AnyPin, OutputType, SealedPin, Speed};
+#[cfg(any(dma, bdma))]
+use crate::dma::{ReadableRingBuffer, TransferOptions, WritableRingBuffer};
+use crate::gpio::{AfType, AnyPin, OutputType, Pull, SealedPin, Speed};
 use crate::mode::Async;
 use crate::pac::spi::vals;
 use crate::spi::{Config as SpiConfig, *};
 use crate::time::Hertz;
 use crate::{Peripheral, PeripheralRef};
 
+/// Ring-buffered I2S error
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Data to write given to a receiver.
+    NotATransmitter,
+    /// Data to read requested from a transmitter.
+    NotAReceiver,
+    /// Overrun
+    Overrun,
+}
+
+#[cfg(any(dma, bdma))]
+impl From<Error> for crate::audio::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::NotATransmitter => Self::NotATransmitter,
+            Error::NotAReceiver => Self::NotAReceiver,
+            Error::Overrun => Self::Overrun,
+        }
+    }
+}
+
 /// I2S mode
 #[derive(Copy, Clone)]
 pub enum Mode {
@@ -209,7 +234,7 @@ impl<'d> I2S<'d> {
         Self::new_inner(
             peri,
             None,
-            new_pin!(sd, AfType::output(OutputType::PushPull, Speed::VeryHigh)),
+            new_pin!(sd, AfType::input(Pull::None)),
             ws,
             ck,
             mck,
@@ -220,9 +245,6 @@ impl<'d> I2S<'d> {
             new_dma!(rxdma),
             freq,
             config,
-            #[cfg(not(spi_v3))]
-            Function::Transmit,
-            #[cfg(spi_v3)]
             Function::Receive,
         )
     }
@@ -245,7 +267,7 @@ impl<'d> I2S<'d> {
         Self::new_inner(
             peri,
             new_pin!(txsd, AfType::output(OutputType::PushPull, Speed::VeryHigh)),
-            new_pin!(rxsd, AfType::output(OutputType::PushPull, Speed::VeryHigh)),
+            new_pin!(rxsd, AfType::input(Pull::None)),
             ws,
             ck,
             mck,
@@ -258,25 +280,67 @@ impl<'d> I2S<'d> {
     }
 
     /// Write audio data.
-    pub async fn read<W: Word>(&mut self, data: &mut [W]) -> Result<(), Error> {
+    pub async fn read<W: Word>(&mut self, data: &mut [W]) -> Result<(), crate::spi::Error> {
         self._peri.read(data).await
     }
 
     /// Write audio data.
-    pub async fn write<W: Word>(&mut self, data: &[W]) -> Result<(), Error> {
+    pub async fn write<W: Word>(&mut self, data: &[W]) -> Result<(), crate::spi::Error> {
         self._peri.write(data).await
     }
 
     /// Transfer audio data.
-    pub async fn transfer<W: Word>(&mut self, read: &mut [W], write: &[W]) -> Result<(), Error> {
+    pub async fn transfer<W: Word>(&mut self, read: &mut [W], write: &[W]) -> Result<(), crate::spi::Error> {
         self._peri.transfer(read, write).await
     }
 
     /// Transfer audio data in place.
-    pub async fn transfer_in_place<W: Word>(&mut self, data: &mut [W]) -> Result<(), Error> {
+    pub async fn transfer_in_place<W: Word>(&mut self, data: &mut [W]) -> Result<(), crate::spi::Error> {
         self._peri.transfer_in_place(data).await
     }
 
+    /// Convert this driver into a [`RingBufferedI2S`], streaming audio data continuously with
+    /// circular DMA.
+    ///
+    /// `tx_buf` must be given for a transmitter and `rx_buf` for a receiver (both for full
+    /// duplex), otherwise [`Error::NotATransmitter`] or [`Error::NotAReceiver`] is returned. The
+    /// DMA wraps around them, so they must be large enough for the samples produced or consumed
+    /// in the time it takes to process half of them.
+    #[cfg(any(dma, bdma))]
+    pub fn into_ring_buffered<W: Word>(
+        mut self,
+        tx_buf: Option<&'d mut [W]>,
+        rx_buf: Option<&'d mut [W]>,
+    ) -> Result<RingBufferedI2S<'d, W>, Error> {
+        if tx_buf.is_some() && self.txsd.is_none() {
+            return Err(Error::NotATransmitter);
+        }
+        if rx_buf.is_some() && self.rxsd.is_none() {
+            return Err(Error::NotAReceiver);
+        }
+
+        let regs = self._peri.info.regs;
+        let opts = TransferOptions {
+            half_transfer_ir: true,
+            ..Default::default()
+        };
+
+        // Safety: the channels are cloned out of the `Spi`, which doesn't use them again. It is
+        // kept in the ring-buffered driver to be dropped along with it.
+        let tx = tx_buf.map(|buf| {
+            let dma = self._peri.tx_dma.as_mut().unwrap();
+            let channel = unsafe { dma.channel.clone_unchecked() };
+            unsafe { WritableRingBuffer::new(channel, dma.request, regs.tx_ptr(), buf, opts) }
+        });
+        let rx = rx_buf.map(|buf| {
+            let dma = self._peri.rx_dma.as_mut().unwrap();
+            let channel = unsafe { dma.channel.clone_unchecked() };
+            unsafe { ReadableRingBuffer::new(channel, dma.request, regs.rx_ptr(), buf, opts) }
+        });
+
+        Ok(RingBufferedI2S { i2s: self, tx, rx })
+    }
+
     fn new_inner<T: Instance>(
         peri: impl Peripheral<P = T> + 'd,
         txsd: Option<PeripheralRef<'d, AnyPin>>,
@@ -415,6 +479,140 @@ impl<'d> Drop for I2S<'d> {
     }
 }
 
+/// I2S driver streaming audio data with circular DMA.
+///
+/// Created with [`I2S::into_ring_buffered`]. Once [started](Self::start), the DMA keeps
+/// transferring samples between the peripheral and the ring buffers in the background; reads and
+/// writes only have to keep up with it.
+#[cfg(any(dma, bdma))]
+pub struct RingBufferedI2S<'d, W: Word> {
+    i2s: I2S<'d>,
+    tx: Option<WritableRingBuffer<'d, W>>,
+    rx: Option<ReadableRingBuffer<'d, W>>,
+}
+
+#[cfg(any(dma, bdma))]
+impl<'d, W: Word> RingBufferedI2S<'d, W> {
+    /// Start streaming.
+    ///
+    /// A transmitter sends what is in its ring buffer right away, so fill it first with
+    /// [`write_immediate`](Self::write_immediate).
+    pub fn start(&mut self) {
+        let regs = self.i2s._peri.info.regs;
+
+        if let Some(rx) = &mut self.rx {
+            // Clear the overrun flag, set by data received before the DMA was running.
+            #[cfg(any(spi_v1, spi_f1))]
+            {
+                let _ = regs.dr().read();
+                let _ = regs.sr().read();
+            }
+
+            rx.clear();
+            rx.start();
+            set_rxdmaen(regs, true);
+        }
+
+        if let Some(tx) = &mut self.tx {
+            tx.start();
+            set_txdmaen(regs, true);
+        }
+
+        #[cfg(spi_v3)]
+        regs.cr1().modify(|w| w.set_cstart(true));
+    }
+
+    /// Stop streaming, once the DMA reaches the end of the current half of the ring buffers.
+    pub async fn stop(&mut self) {
+        let regs = self.i2s._peri.info.regs;
+
+        if let Some(tx) = &mut self.tx {
+            tx.stop().await;
+            set_txdmaen(regs, false);
+        }
+
+        if let Some(rx) = &mut self.rx {
+            rx.stop().await;
+            set_rxdmaen(regs, false);
+        }
+    }
+
+    /// Clear the ring buffers.
+    pub fn clear(&mut self) {
+        if let Some(tx) = &mut self.tx {
+            tx.clear();
+        }
+        if let Some(rx) = &mut self.rx {
+            rx.clear();
+        }
+    }
+
+    /// Write audio data, waiting for room in the ring buffer.
+    ///
+    /// Returns [`Error::Overrun`] if the DMA caught up with the written data, i.e. the writes
+    /// didn't keep up and old samples were sent again, and [`Error::NotATransmitter`] on a
+    /// receiver.
+    pub async fn write(&mut self, data: &[W]) -> Result<(), Error> {
+        let tx = self.tx.as_mut().ok_or(Error::NotATransmitter)?;
+        tx.write_exact(data).await.map_err(|_| Error::Overrun)?;
+        Ok(())
+    }
+
+    /// Write audio data to the ring buffer without checking the DMA position, returning the
+    /// number of samples written.
+    ///
+    /// This is for filling the buffer before [`start`](Self::start).
+    pub fn write_immediate(&mut self, data: &[W]) -> Result<usize, Error> {
+        let tx = self.tx.as_mut().ok_or(Error::NotATransmitter)?;
+        let (written, _) = tx.write_immediate(data).map_err(|_| Error::Overrun)?;
+        Ok(written)
+    }
+
+    /// Read audio data, waiting until enough of it was received.
+    ///
+    /// Returns [`Error::Overrun`] if received samples were overwritten before being read, and
+    /// [`Error::NotAReceiver`] on a transmitter.
+    pub async fn read(&mut self, data: &mut [W]) -> Result<(), Error> {
+        let rx = self.rx.as_mut().ok_or(Error::NotAReceiver)?;
+        rx.read_exact(data).await.map_err(|_| Error::Overrun)?;
+        Ok(())
+    }
+}
+
+#[cfg(any(dma, bdma))]
+impl<'d, W: Word> crate::audio::AudioSource<W> for RingBufferedI2S<'d, W> {
+    async fn read(&mut self, data: &mut [W]) -> Result<(), crate::audio::Error> {
+        Ok(RingBufferedI2S::read(self, data).await?)
+    }
+}
+
+macro_rules! impl_audio_sink {
+    ($w:ty, $sample:ident => $encode:expr) => {
+        #[cfg(any(dma, bdma))]
+        impl<'d> crate::audio::AudioSink<$w> for RingBufferedI2S<'d, $w> {
+            fn encode($sample: i16) -> $w {
+                $encode
+            }
+
+            async fn write(&mut self, data: &[$w]) -> Result<(), crate::audio::Error> {
+                Ok(RingBufferedI2S::write(self, data).await?)
+            }
+
+            fn capacity(&self) -> usize {
+                self.tx.as_ref().map_or(0, |tx| tx.capacity())
+            }
+
+            fn position(&self) -> usize {
+                self.tx.as_ref().map_or(0, |tx| tx.get_position())
+            }
+        }
+    };
+}
+
+impl_audio_sink!(u16, sample => sample as u16);
+// Left aligned in the 32 bit frame, for 24 and 32 bit data formats.
+impl_audio_sink!(u32, sample => ((sample as i32) << 16) as u32);
+
 // Note, calculation details:
 // Fs = i2s_clock / [256 * ((2 * div) + odd)] when master clock is enabled
 // Fs = i2s_clock / [(channel_length * 2) * ((2 * div) + odd)]` when master clock is disabled
@@ -15,6 +15,8 @@ mod fmt;
 include!(concat!(env!("OUT_DIR"), "/_macros.rs"));
 
 // Utilities
+#[cfg(all(feature = "bench", not(armv6m)))]
+pub mod bench;
 mod macros;
 pub mod time;
 /// Operating modes for peripherals.
@@ -53,6 +55,8 @@ pub mod timer;
 
 #[cfg(adc)]
 pub mod adc;
+#[cfg(any(dma, bdma))]
+pub mod audio;
 #[cfg(can)]
 pub mod can;
 // FIXME: Cordic driver cause stm32u5a5zj crash
@@ -62,10 +66,17 @@ pub mod cordic;
 pub mod crc;
 #[cfg(cryp)]
 pub mod cryp;
+#[cfg(all(feature = "exti", feature = "time"))]
+pub mod dali;
 #[cfg(dac)]
 pub mod dac;
 #[cfg(dcmi)]
 pub mod dcmi;
+#[cfg(dfsdm)]
+pub mod dfsdm;
+pub mod display_bus;
+#[cfg(dma2d)]
+pub mod dma2d;
 #[cfg(dsihost)]
 pub mod dsihost;
 #[cfg(eth)]
@@ -75,6 +86,8 @@ pub mod exti;
 pub mod flash;
 #[cfg(fmc)]
 pub mod fmc;
+#[cfg(all(feature = "trustzone-secure", any(stm32u5, stm32h5)))]
+pub mod gtzc;
 #[cfg(hash)]
 pub mod hash;
 #[cfg(hrtim)]
@@ -87,26 +100,34 @@ pub mod i2c;
 pub mod i2s;
 #[cfg(stm32wb)]
 pub mod ipcc;
+#[cfg(feature = "time")]
+pub mod lora;
 #[cfg(feature = "low-power")]
 pub mod low_power;
 #[cfg(ltdc)]
 pub mod ltdc;
+pub mod mpu;
 #[cfg(opamp)]
 pub mod opamp;
+pub mod nor_flash;
 #[cfg(octospi)]
 pub mod ospi;
 #[cfg(quadspi)]
 pub mod qspi;
 #[cfg(rng)]
 pub mod rng;
+#[cfg(all(feature = "exti", feature = "time"))]
+pub mod rotary_encoder;
 #[cfg(all(rtc, not(rtc_v1)))]
 pub mod rtc;
 #[cfg(sai)]
 pub mod sai;
 #[cfg(sdmmc)]
 pub mod sdmmc;
+pub mod snapshot;
 #[cfg(spi)]
 pub mod spi;
+pub mod swd;
 #[cfg(tsc)]
 pub mod tsc;
 #[cfg(ucpd)]
@@ -171,9 +192,11 @@ macro_rules! bind_interrupts {
             #[allow(non_snake_case)]
             #[no_mangle]
             unsafe extern "C" fn $irq() {
+                let guard = $crate::_private::IsrGuard::new();
                 $(
                     <$handler as $crate::interrupt::typelevel::Handler<$crate::interrupt::typelevel::$irq>>::on_interrupt();
                 )*
+                guard.finish();
             }
 
             $(
@@ -183,6 +206,73 @@ macro_rules! bind_interrupts {
     };
 }
 
+/// Macro to group the peripherals used by a board into named resources.
+///
+/// Each resource is a struct holding the peripheral singletons it needs, and the board struct
+/// holds all resources. `new` moves the singletons out of [`Peripherals`], so the board must
+/// list every peripheral used by the binary. The resources can then be handed to the tasks or
+/// drivers using them, instead of passing individual pins around.
+///
+/// ```rust,ignore
+/// use embassy_stm32::board;
+///
+/// board!(pub struct Board {
+///     led: Led { pin: PB7 },
+///     sensor_i2c: SensorI2c { peri: I2C1, scl: PB8, sda: PB9, tx_dma: DMA1_CH6, rx_dma: DMA1_CH0 },
+/// });
+///
+/// let board = Board::new(embassy_stm32::init(Default::default()));
+/// let led = Output::new(board.led.pin, Level::Low, Speed::Low);
+/// spawner.spawn(sensor_task(board.sensor_i2c)).unwrap();
+/// ```
+#[macro_export]
+macro_rules! board {
+    ($vis:vis struct $name:ident { $($field:ident : $group:ident { $($res:ident : $periph:ident),* $(,)? }),* $(,)? }) => {
+        #[allow(missing_docs)]
+        $vis struct $name {
+            $(pub $field: $group,)*
+        }
+
+        $(
+            #[allow(missing_docs)]
+            $vis struct $group {
+                $(pub $res: $crate::peripherals::$periph,)*
+            }
+        )*
+
+        impl $name {
+            /// Take the board resources from the peripherals.
+            $vis fn new(p: $crate::Peripherals) -> Self {
+                Self {
+                    $($field: $group { $($res: p.$periph,)* },)*
+                }
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+pub mod _private {
+    #[cfg(all(feature = "isr-budget", not(armv6m)))]
+    pub use crate::bench::IsrGuard;
+
+    /// No-op stand-in for `bench::IsrGuard` without the `isr-budget` feature.
+    #[cfg(not(all(feature = "isr-budget", not(armv6m))))]
+    pub struct IsrGuard;
+
+    #[cfg(not(all(feature = "isr-budget", not(armv6m))))]
+    #[allow(clippy::new_without_default)]
+    impl IsrGuard {
+        #[inline(always)]
+        pub fn new() -> Self {
+            Self
+        }
+
+        #[inline(always)]
+        pub fn finish(self) {}
+    }
+}
+
 // Reexports
 pub use _generated::{peripherals, Peripherals};
 pub use embassy_hal_internal::{into_ref, Peripheral, PeripheralRef};
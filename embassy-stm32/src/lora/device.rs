@@ -0,0 +1,198 @@
+//! LoRaWAN end device: join and send over a [`Radio`], respecting the duty cycle.
+
+use embassy_time::{Duration, Instant, Timer};
+
+use super::{DutyCycle, Error, Radio, RxConfig, RxQuality, TxConfig};
+
+/// Largest LoRaWAN PHY payload, in bytes.
+const MAX_FRAME_LEN: usize = 255;
+
+/// Receive window opened after an uplink.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RxWindow {
+    /// Time from the end of the uplink to the start of the window.
+    pub delay: Duration,
+    /// Reception configuration, whose timeout is the length of the window.
+    pub config: RxConfig,
+}
+
+/// Downlink frame decoded by the [`Mac`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DownlinkFrame {
+    /// The frame acknowledges the last confirmed uplink.
+    pub ack: bool,
+    /// Port of the application payload, or `None` for a frame without one.
+    pub port: Option<u8>,
+    /// Length of the application payload.
+    pub len: usize,
+}
+
+/// Downlink received after an uplink, see [`Device::send`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Downlink {
+    /// Decoded frame.
+    pub frame: DownlinkFrame,
+    /// Signal quality of the frame.
+    pub quality: RxQuality,
+}
+
+/// LoRaWAN MAC layer, such as the `lorawan-device` crate.
+///
+/// The MAC encodes, encrypts and authenticates frames, keeps the session state, and chooses the
+/// channel and data rate of each exchange. [`Device`] handles the radio timing around it.
+pub trait Mac {
+    /// MAC error.
+    type Error;
+
+    /// Write a join request into `buf`, returning its length.
+    fn join_request(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Process a frame received after a join request.
+    ///
+    /// Returns whether it is a valid join accept, which starts the session.
+    fn join_accept(&mut self, frame: &[u8]) -> Result<bool, Self::Error>;
+
+    /// Write an uplink carrying `data` on `port` into `buf`, returning its length.
+    fn uplink(&mut self, port: u8, data: &[u8], confirmed: bool, buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Process a frame received after an uplink, decrypting its application payload into
+    /// `payload`.
+    ///
+    /// Returns `None` if the frame isn't a valid downlink for this device.
+    fn downlink(&mut self, frame: &[u8], payload: &mut [u8]) -> Result<Option<DownlinkFrame>, Self::Error>;
+
+    /// Transmission configuration of the next join request, or uplink of `len` bytes.
+    fn tx_config(&mut self, join: bool, len: usize) -> TxConfig;
+
+    /// Receive windows following a join request or an uplink, usually RX1 and RX2.
+    fn rx_windows(&self, join: bool) -> [RxWindow; 2];
+}
+
+/// LoRaWAN end device error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeviceError<E> {
+    /// Radio error.
+    Radio(Error),
+    /// MAC error.
+    Mac(E),
+    /// No join accept was received.
+    NoJoinAccept,
+}
+
+impl<E> From<Error> for DeviceError<E> {
+    fn from(e: Error) -> Self {
+        DeviceError::Radio(e)
+    }
+}
+
+/// LoRaWAN end device, see the [module documentation](super).
+///
+/// Each uplink waits until its band is available in the [`DutyCycle`] tracker, then the receive
+/// windows are opened at the times given by the [`Mac`]. The radio sleeps between exchanges.
+pub struct Device<R: Radio, M: Mac, const N: usize> {
+    radio: R,
+    mac: M,
+    duty_cycle: DutyCycle<N>,
+}
+
+impl<R: Radio, M: Mac, const N: usize> Device<R, M, N> {
+    /// Create a device.
+    pub fn new(radio: R, mac: M, duty_cycle: DutyCycle<N>) -> Self {
+        Self { radio, mac, duty_cycle }
+    }
+
+    /// Get the MAC.
+    pub fn mac(&mut self) -> &mut M {
+        &mut self.mac
+    }
+
+    /// Release the radio, the MAC and the duty cycle tracker.
+    pub fn release(self) -> (R, M, DutyCycle<N>) {
+        (self.radio, self.mac, self.duty_cycle)
+    }
+
+    /// Join the network, with a single join request.
+    ///
+    /// Returns [`DeviceError::NoJoinAccept`] if no join accept is received in the receive
+    /// windows. Retries, and the back-off between them required by LoRaWAN, are up to the caller.
+    pub async fn join(&mut self) -> Result<(), DeviceError<M::Error>> {
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = self.mac.join_request(&mut buf).map_err(DeviceError::Mac)?;
+        let config = self.mac.tx_config(true, len);
+        let end = self.uplink(&config, &buf[..len]).await?;
+
+        for window in self.mac.rx_windows(true) {
+            let Some((len, _)) = self.receive(end, &window, &mut buf).await? else {
+                continue;
+            };
+            if self.mac.join_accept(&buf[..len]).map_err(DeviceError::Mac)? {
+                self.radio.sleep().await?;
+                return Ok(());
+            }
+        }
+
+        self.radio.sleep().await?;
+        Err(DeviceError::NoJoinAccept)
+    }
+
+    /// Send `data` on `port`, then listen for a downlink, whose application payload is written
+    /// to `payload`.
+    ///
+    /// Returns `None` if no downlink was received. For a confirmed uplink, check
+    /// [`DownlinkFrame::ack`] to know whether it was acknowledged.
+    pub async fn send(
+        &mut self,
+        port: u8,
+        data: &[u8],
+        confirmed: bool,
+        payload: &mut [u8],
+    ) -> Result<Option<Downlink>, DeviceError<M::Error>> {
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = self
+            .mac
+            .uplink(port, data, confirmed, &mut buf)
+            .map_err(DeviceError::Mac)?;
+        let config = self.mac.tx_config(false, len);
+        let end = self.uplink(&config, &buf[..len]).await?;
+
+        for window in self.mac.rx_windows(false) {
+            let Some((len, quality)) = self.receive(end, &window, &mut buf).await? else {
+                continue;
+            };
+            if let Some(frame) = self.mac.downlink(&buf[..len], payload).map_err(DeviceError::Mac)? {
+                self.radio.sleep().await?;
+                return Ok(Some(Downlink { frame, quality }));
+            }
+        }
+
+        self.radio.sleep().await?;
+        Ok(None)
+    }
+
+    /// Transmit a frame once its band is available, returning when it ended.
+    async fn uplink(&mut self, config: &TxConfig, frame: &[u8]) -> Result<Instant, Error> {
+        self.duty_cycle.transmit(&mut self.radio, config, frame).await?;
+        Ok(Instant::now())
+    }
+
+    /// Listen in `window`, opened relative to the end of the uplink.
+    ///
+    /// Returns `None` if nothing, or only a damaged frame, was received.
+    async fn receive(
+        &mut self,
+        uplink_end: Instant,
+        window: &RxWindow,
+        buf: &mut [u8],
+    ) -> Result<Option<(usize, RxQuality)>, Error> {
+        Timer::at(uplink_end + window.delay).await;
+        match self.radio.receive(&window.config, buf).await {
+            Ok(received) => Ok(Some(received)),
+            Err(Error::Timeout | Error::Header | Error::Crc) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
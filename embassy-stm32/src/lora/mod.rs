@@ -0,0 +1,271 @@
+//! LoRa radios.
+//!
+//! [`Radio`] is the interface a LoRaWAN MAC, such as the `lorawan-device` crate, needs from the
+//! radio: transmit a packet, receive one in a window, and sleep between exchanges. It is
+//! implemented by:
+//!
+//! - [`Sx126x`], for the SX126x family of radios: the SUBGHZ radio of STM32WL chips through
+//!   [`SubGhz`], and external SX1261/SX1262/SX1268 through [`SpiInterface`].
+//! - [`Sx127x`], for external SX1276/SX1277/SX1278/SX1279 through [`sx127x::SpiInterface`].
+//!
+//! LoRaWAN regional parameters limit the share of time a device may transmit in each sub-band,
+//! e.g. 1% in most of the EU868 band. [`DutyCycle`] tracks the time-on-air of the packets sent on
+//! each band, and [`DutyCycle::transmit`] waits until the band is available before transmitting.
+//!
+//! [`Device`] joins a LoRaWAN network and sends uplinks with a [`Radio`] and a [`Mac`]: it waits
+//! for the duty cycle, transmits, and listens in the receive windows, while the MAC encodes and
+//! encrypts the frames.
+
+mod device;
+mod sx126x;
+pub mod sx127x;
+
+pub use device::*;
+use embassy_time::{Duration, Instant, Timer};
+pub use sx126x::*;
+pub use sx127x::Sx127x;
+
+use crate::spi;
+
+/// LoRa radio error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// SPI error.
+    Spi(spi::Error),
+    /// Nothing was received, or the packet couldn't be sent, in time.
+    Timeout,
+    /// A packet was received with an invalid header.
+    Header,
+    /// A packet was received with an invalid CRC.
+    Crc,
+    /// The received packet doesn't fit in the buffer.
+    Overflow,
+    /// The radio stayed busy for too long, e.g. because it is missing or not powered.
+    Busy,
+    /// The radio or the modulation isn't supported by the driver.
+    Unsupported,
+}
+
+impl From<spi::Error> for Error {
+    fn from(e: spi::Error) -> Self {
+        Error::Spi(e)
+    }
+}
+
+/// LoRa spreading factor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(missing_docs)]
+pub enum SpreadingFactor {
+    SF5 = 5,
+    SF6 = 6,
+    SF7 = 7,
+    SF8 = 8,
+    SF9 = 9,
+    SF10 = 10,
+    SF11 = 11,
+    SF12 = 12,
+}
+
+/// LoRa bandwidth.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bandwidth {
+    /// 125 kHz.
+    Khz125,
+    /// 250 kHz.
+    Khz250,
+    /// 500 kHz.
+    Khz500,
+}
+
+impl Bandwidth {
+    fn hz(self) -> u64 {
+        match self {
+            Bandwidth::Khz125 => 125_000,
+            Bandwidth::Khz250 => 250_000,
+            Bandwidth::Khz500 => 500_000,
+        }
+    }
+}
+
+/// LoRa forward error correction coding rate.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(missing_docs)]
+pub enum CodingRate {
+    Cr4_5 = 1,
+    Cr4_6 = 2,
+    Cr4_7 = 3,
+    Cr4_8 = 4,
+}
+
+/// LoRa modulation parameters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Modulation {
+    /// Spreading factor.
+    pub spreading_factor: SpreadingFactor,
+    /// Bandwidth.
+    pub bandwidth: Bandwidth,
+    /// Coding rate.
+    pub coding_rate: CodingRate,
+}
+
+impl Modulation {
+    /// Duration of a symbol, in microseconds.
+    fn symbol_us(&self) -> u64 {
+        (1_000_000u64 << self.spreading_factor as u32) / self.bandwidth.hz()
+    }
+
+    /// Whether low data rate optimization is needed, for symbols of 16 ms or more.
+    pub fn low_data_rate_optimize(&self) -> bool {
+        self.symbol_us() >= 16_000
+    }
+
+    /// Time-on-air of a packet with an explicit header and CRC, carrying `len` bytes after a
+    /// preamble of `preamble_len` symbols.
+    pub fn time_on_air(&self, len: usize, preamble_len: u16) -> Duration {
+        let sf = self.spreading_factor as i64;
+        let de = self.low_data_rate_optimize() as i64;
+        // Payload symbols, from the Semtech SX126x datasheet: 8 * len bits plus the CRC and
+        // header, in blocks of 4 * (sf - 2 * de) bits coded with 4 + cr bits each.
+        let bits = 8 * len as i64 - 4 * sf + 28 + 16;
+        let blocks = (bits.max(0) + 4 * (sf - 2 * de) - 1) / (4 * (sf - 2 * de));
+        let payload_symbols = 8 + blocks * (self.coding_rate as i64 + 4);
+
+        // The preamble is followed by 4.25 symbols of sync word and start frame delimiter.
+        let quarter_symbols = 4 * preamble_len as u64 + 17 + 4 * payload_symbols as u64;
+        Duration::from_micros(quarter_symbols * self.symbol_us() / 4)
+    }
+}
+
+/// Transmission configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TxConfig {
+    /// Carrier frequency, in Hz.
+    pub frequency: u32,
+    /// Output power, in dBm.
+    pub power: i8,
+    /// Modulation parameters.
+    pub modulation: Modulation,
+    /// Preamble length, in symbols. LoRaWAN uses 8.
+    pub preamble_len: u16,
+    /// Invert the I and Q signals, as LoRaWAN gateways do for downlinks.
+    pub iq_inverted: bool,
+}
+
+/// Reception configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RxConfig {
+    /// Carrier frequency, in Hz.
+    pub frequency: u32,
+    /// Modulation parameters.
+    pub modulation: Modulation,
+    /// Preamble length, in symbols. LoRaWAN uses 8.
+    pub preamble_len: u16,
+    /// Invert the I and Q signals. LoRaWAN devices receive downlinks with inverted IQ.
+    pub iq_inverted: bool,
+    /// Time to wait for a packet to start.
+    pub timeout: Duration,
+}
+
+/// Signal quality of a received packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RxQuality {
+    /// Received signal strength, in dBm.
+    pub rssi: i16,
+    /// Signal-to-noise ratio, in dB.
+    pub snr: i16,
+}
+
+/// LoRa radio, see the [module documentation](self).
+pub trait Radio {
+    /// Transmit a packet, returning once it is sent.
+    async fn transmit(&mut self, config: &TxConfig, data: &[u8]) -> Result<(), Error>;
+
+    /// Receive a packet into `buf`, returning its length and signal quality.
+    ///
+    /// Returns [`Error::Timeout`] if no packet starts within `config.timeout`.
+    async fn receive(&mut self, config: &RxConfig, buf: &mut [u8]) -> Result<(usize, RxQuality), Error>;
+
+    /// Put the radio to sleep, until the next transmission or reception.
+    async fn sleep(&mut self) -> Result<(), Error>;
+}
+
+/// Sub-band with a duty cycle limit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Band {
+    /// Lowest frequency of the band, in Hz.
+    pub min_frequency: u32,
+    /// Highest frequency of the band, in Hz.
+    pub max_frequency: u32,
+    /// Maximum share of time spent transmitting, as `1 / divisor`, e.g. `100` for 1%.
+    pub divisor: u32,
+    available_at: Instant,
+}
+
+impl Band {
+    /// Create a band, available right away.
+    pub const fn new(min_frequency: u32, max_frequency: u32, divisor: u32) -> Self {
+        Self {
+            min_frequency,
+            max_frequency,
+            divisor,
+            available_at: Instant::from_ticks(0),
+        }
+    }
+}
+
+/// Duty cycle tracker over `N` bands, see the [module documentation](self).
+///
+/// After transmitting a packet for `t` on a band of divisor `d`, the band is unavailable for
+/// `t * (d - 1)`. Frequencies outside all bands aren't limited.
+pub struct DutyCycle<const N: usize> {
+    bands: [Band; N],
+}
+
+impl<const N: usize> DutyCycle<N> {
+    /// Create a tracker for `bands`.
+    pub const fn new(bands: [Band; N]) -> Self {
+        Self { bands }
+    }
+
+    /// The instant transmitting on `frequency` is allowed again.
+    pub fn available_at(&self, frequency: u32) -> Instant {
+        self.band(frequency)
+            .map_or(Instant::from_ticks(0), |i| self.bands[i].available_at)
+    }
+
+    /// Record a transmission of `time_on_air` on `frequency`, ending now.
+    pub fn record(&mut self, frequency: u32, time_on_air: Duration) {
+        if let Some(i) = self.band(frequency) {
+            let band = &mut self.bands[i];
+            band.available_at = Instant::now() + time_on_air * band.divisor.saturating_sub(1);
+        }
+    }
+
+    /// Wait until the band of `config.frequency` is available, then transmit a packet and record
+    /// its time-on-air.
+    pub async fn transmit<R: Radio>(&mut self, radio: &mut R, config: &TxConfig, data: &[u8]) -> Result<(), Error> {
+        Timer::at(self.available_at(config.frequency)).await;
+        radio.transmit(config, data).await?;
+        self.record(
+            config.frequency,
+            config.modulation.time_on_air(data.len(), config.preamble_len),
+        );
+        Ok(())
+    }
+
+    fn band(&self, frequency: u32) -> Option<usize> {
+        self.bands
+            .iter()
+            .position(|b| (b.min_frequency..=b.max_frequency).contains(&frequency))
+    }
+}
@@ -0,0 +1,478 @@
+//! SX126x family LoRa driver.
+
+#[cfg(stm32wl)]
+use core::future::poll_fn;
+#[cfg(stm32wl)]
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(stm32wl)]
+use core::task::Poll;
+
+#[cfg(any(stm32wl, feature = "exti"))]
+use embassy_time::{Duration, Instant, Timer};
+
+use super::{Error, Radio, RxConfig, RxQuality, TxConfig};
+#[cfg(feature = "exti")]
+use crate::exti::ExtiInput;
+#[cfg(feature = "exti")]
+use crate::gpio::{Input, Output};
+#[cfg(stm32wl)]
+use crate::interrupt;
+#[cfg(stm32wl)]
+use crate::interrupt::typelevel::Interrupt;
+#[cfg(any(stm32wl, feature = "exti"))]
+use crate::mode::Async;
+#[cfg(any(stm32wl, feature = "exti"))]
+use crate::spi::Spi;
+
+/// Crystal frequency of the radio.
+const XTAL_FREQ: u64 = 32_000_000;
+
+/// Interrupt sources.
+const IRQ_TX_DONE: u16 = 1 << 0;
+const IRQ_RX_DONE: u16 = 1 << 1;
+const IRQ_HEADER_ERR: u16 = 1 << 5;
+const IRQ_CRC_ERR: u16 = 1 << 6;
+const IRQ_TIMEOUT: u16 = 1 << 9;
+
+/// Commands.
+const SET_SLEEP: u8 = 0x84;
+const SET_STANDBY: u8 = 0x80;
+const SET_TX: u8 = 0x83;
+const SET_RX: u8 = 0x82;
+const SET_REGULATOR_MODE: u8 = 0x96;
+const CALIBRATE: u8 = 0x89;
+const SET_PA_CONFIG: u8 = 0x95;
+const SET_DIO_IRQ_PARAMS: u8 = 0x08;
+const GET_IRQ_STATUS: u8 = 0x12;
+const CLEAR_IRQ_STATUS: u8 = 0x02;
+const SET_DIO2_AS_RF_SWITCH_CTRL: u8 = 0x9D;
+const SET_DIO3_AS_TCXO_CTRL: u8 = 0x97;
+const SET_RF_FREQUENCY: u8 = 0x86;
+const SET_PACKET_TYPE: u8 = 0x8A;
+const SET_TX_PARAMS: u8 = 0x8E;
+const SET_MODULATION_PARAMS: u8 = 0x8B;
+const SET_PACKET_PARAMS: u8 = 0x8C;
+const SET_BUFFER_BASE_ADDRESS: u8 = 0x8F;
+const WRITE_REGISTER: u8 = 0x0D;
+const WRITE_BUFFER: u8 = 0x0E;
+const READ_BUFFER: u8 = 0x1E;
+const GET_RX_BUFFER_STATUS: u8 = 0x13;
+const GET_PACKET_STATUS: u8 = 0x14;
+
+/// Longest time the radio may stay busy, e.g. calibrating with a TCXO starting up.
+#[cfg(any(stm32wl, feature = "exti"))]
+const BUSY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// LoRa sync word register, set to the LoRaWAN public network value.
+const REG_LORA_SYNC_WORD: u16 = 0x0740;
+const PUBLIC_SYNC_WORD: [u8; 2] = [0x34, 0x44];
+
+/// Bus to an SX126x radio.
+pub trait Interface {
+    /// Send a command with its parameters, once the radio isn't busy.
+    async fn write(&mut self, command: &[u8]) -> Result<(), Error>;
+
+    /// Send a command, then read its response, once the radio isn't busy. The response starts
+    /// with the status byte.
+    async fn read(&mut self, command: &[u8], response: &mut [u8]) -> Result<(), Error>;
+
+    /// Wait for the radio to raise its interrupt line.
+    async fn wait_irq(&mut self);
+
+    /// Reset the radio.
+    async fn reset(&mut self) -> Result<(), Error>;
+}
+
+/// Interface to the SUBGHZ radio of STM32WL chips.
+///
+/// RF switches between the antenna and the radio outputs are board specific, and must be set by
+/// the application before transmitting or receiving.
+#[cfg(stm32wl)]
+pub struct SubGhz<'d> {
+    spi: Spi<'d, Async>,
+}
+
+#[cfg(stm32wl)]
+static SUBGHZ_IRQ: AtomicBool = AtomicBool::new(false);
+#[cfg(stm32wl)]
+static SUBGHZ_WAKER: embassy_sync::waitqueue::AtomicWaker = embassy_sync::waitqueue::AtomicWaker::new();
+
+/// SUBGHZ radio interrupt handler.
+#[cfg(stm32wl)]
+pub struct SubGhzInterruptHandler {}
+
+#[cfg(stm32wl)]
+impl interrupt::typelevel::Handler<interrupt::typelevel::SUBGHZ_RADIO> for SubGhzInterruptHandler {
+    unsafe fn on_interrupt() {
+        // The interrupt line stays high until the radio's interrupt status is cleared.
+        interrupt::typelevel::SUBGHZ_RADIO::disable();
+        SUBGHZ_IRQ.store(true, Ordering::Release);
+        SUBGHZ_WAKER.wake();
+    }
+}
+
+#[cfg(stm32wl)]
+impl<'d> SubGhz<'d> {
+    /// Create a new SUBGHZ interface, with a SPI created by [`Spi::new_subghz`].
+    pub fn new(
+        spi: Spi<'d, Async>,
+        _irq: impl interrupt::typelevel::Binding<interrupt::typelevel::SUBGHZ_RADIO, SubGhzInterruptHandler> + 'd,
+    ) -> Self {
+        interrupt::typelevel::SUBGHZ_RADIO::unpend();
+        Self { spi }
+    }
+
+    fn wait_busy(&self) -> Result<(), Error> {
+        let deadline = Instant::now() + BUSY_TIMEOUT;
+        while crate::pac::PWR.sr2().read().rfbusys() {
+            if Instant::now() > deadline {
+                return Err(Error::Busy);
+            }
+        }
+        Ok(())
+    }
+
+    fn select(&self, selected: bool) {
+        crate::pac::PWR.subghzspicr().modify(|w| w.set_nss(!selected));
+    }
+}
+
+#[cfg(stm32wl)]
+impl<'d> Interface for SubGhz<'d> {
+    async fn write(&mut self, command: &[u8]) -> Result<(), Error> {
+        self.wait_busy()?;
+        self.select(true);
+        let res = self.spi.write(command).await;
+        self.select(false);
+        Ok(res?)
+    }
+
+    async fn read(&mut self, command: &[u8], response: &mut [u8]) -> Result<(), Error> {
+        self.wait_busy()?;
+        self.select(true);
+        let res = match self.spi.write(command).await {
+            Ok(()) => self.spi.read(response).await,
+            Err(e) => Err(e),
+        };
+        self.select(false);
+        Ok(res?)
+    }
+
+    async fn wait_irq(&mut self) {
+        poll_fn(|cx| {
+            SUBGHZ_WAKER.register(cx.waker());
+            if SUBGHZ_IRQ.swap(false, Ordering::AcqRel) {
+                return Poll::Ready(());
+            }
+            unsafe { interrupt::typelevel::SUBGHZ_RADIO::enable() };
+            Poll::Pending
+        })
+        .await
+    }
+
+    async fn reset(&mut self) -> Result<(), Error> {
+        crate::pac::RCC.csr().modify(|w| w.set_rfrst(true));
+        Timer::after(Duration::from_micros(100)).await;
+        crate::pac::RCC.csr().modify(|w| w.set_rfrst(false));
+        SUBGHZ_IRQ.store(false, Ordering::Release);
+        self.wait_busy()
+    }
+}
+
+/// Interface to an external SX126x radio on a SPI bus.
+#[cfg(feature = "exti")]
+pub struct SpiInterface<'d> {
+    spi: Spi<'d, Async>,
+    nss: Output<'d>,
+    busy: Input<'d>,
+    dio1: ExtiInput<'d>,
+    reset: Output<'d>,
+}
+
+#[cfg(feature = "exti")]
+impl<'d> SpiInterface<'d> {
+    /// Create a new interface. `spi` must be configured in mode 0, at up to 16 MHz.
+    ///
+    /// DIO1 is the interrupt line, the other DIOs aren't used.
+    pub fn new(spi: Spi<'d, Async>, nss: Output<'d>, busy: Input<'d>, dio1: ExtiInput<'d>, reset: Output<'d>) -> Self {
+        Self {
+            spi,
+            nss,
+            busy,
+            dio1,
+            reset,
+        }
+    }
+
+    async fn wait_busy(&self) -> Result<(), Error> {
+        let deadline = Instant::now() + BUSY_TIMEOUT;
+        while self.busy.is_high() {
+            if Instant::now() > deadline {
+                return Err(Error::Busy);
+            }
+            Timer::after(Duration::from_micros(10)).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "exti")]
+impl<'d> Interface for SpiInterface<'d> {
+    async fn write(&mut self, command: &[u8]) -> Result<(), Error> {
+        self.wait_busy().await?;
+        self.nss.set_low();
+        let res = self.spi.write(command).await;
+        self.nss.set_high();
+        Ok(res?)
+    }
+
+    async fn read(&mut self, command: &[u8], response: &mut [u8]) -> Result<(), Error> {
+        self.wait_busy().await?;
+        self.nss.set_low();
+        let res = match self.spi.write(command).await {
+            Ok(()) => self.spi.read(response).await,
+            Err(e) => Err(e),
+        };
+        self.nss.set_high();
+        Ok(res?)
+    }
+
+    async fn wait_irq(&mut self) {
+        self.dio1.wait_for_high().await
+    }
+
+    async fn reset(&mut self) -> Result<(), Error> {
+        self.reset.set_low();
+        Timer::after(Duration::from_micros(100)).await;
+        self.reset.set_high();
+        Timer::after(Duration::from_millis(10)).await;
+        self.wait_busy().await
+    }
+}
+
+/// Power amplifier.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerAmplifier {
+    /// Low power PA, up to +15 dBm: SX1261, or the STM32WL `RFO_LP` output.
+    LowPower,
+    /// High power PA, up to +22 dBm: SX1262, SX1268, or the STM32WL `RFO_HP` output.
+    HighPower,
+}
+
+/// TCXO supply voltage, provided by DIO3.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(missing_docs)]
+pub enum TcxoVoltage {
+    V1_6 = 0,
+    V1_7 = 1,
+    V1_8 = 2,
+    V2_2 = 3,
+    V2_4 = 4,
+    V2_7 = 5,
+    V3_0 = 6,
+    V3_3 = 7,
+}
+
+/// SX126x configuration.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Power amplifier used for transmission.
+    pub power_amplifier: PowerAmplifier,
+    /// Supply the crystal oscillator from DIO3, for boards with a TCXO.
+    pub tcxo: Option<TcxoVoltage>,
+    /// Use the DC-DC converter instead of the LDO.
+    pub use_dcdc: bool,
+    /// Drive the RF switch from DIO2.
+    pub dio2_rf_switch: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            power_amplifier: PowerAmplifier::HighPower,
+            tcxo: None,
+            use_dcdc: false,
+            dio2_rf_switch: false,
+        }
+    }
+}
+
+/// SX126x LoRa radio.
+pub struct Sx126x<I: Interface> {
+    iface: I,
+    config: Config,
+}
+
+impl<I: Interface> Sx126x<I> {
+    /// Reset and initialize the radio, in LoRa mode.
+    pub async fn new(mut iface: I, config: Config) -> Result<Self, Error> {
+        iface.reset().await?;
+
+        let mut this = Self { iface, config };
+        this.iface.write(&[SET_STANDBY, 0]).await?;
+        if let Some(voltage) = config.tcxo {
+            // 5 ms startup time, in units of 15.625 µs.
+            this.iface
+                .write(&[SET_DIO3_AS_TCXO_CTRL, voltage as u8, 0x00, 0x01, 0x40])
+                .await?;
+        }
+        this.iface.write(&[CALIBRATE, 0x7F]).await?;
+        this.iface.write(&[SET_REGULATOR_MODE, config.use_dcdc as u8]).await?;
+        if config.dio2_rf_switch {
+            this.iface.write(&[SET_DIO2_AS_RF_SWITCH_CTRL, 1]).await?;
+        }
+        this.iface.write(&[SET_BUFFER_BASE_ADDRESS, 0, 0]).await?;
+        this.iface.write(&[SET_PACKET_TYPE, 0x01]).await?;
+        this.write_register(REG_LORA_SYNC_WORD, &PUBLIC_SYNC_WORD).await?;
+        Ok(this)
+    }
+
+    /// Release the interface.
+    pub fn release(self) -> I {
+        self.iface
+    }
+
+    async fn write_register(&mut self, addr: u16, data: &[u8]) -> Result<(), Error> {
+        let mut cmd = [0; 8];
+        let [hi, lo] = addr.to_be_bytes();
+        cmd[..3].copy_from_slice(&[WRITE_REGISTER, hi, lo]);
+        cmd[3..3 + data.len()].copy_from_slice(data);
+        self.iface.write(&cmd[..3 + data.len()]).await
+    }
+
+    async fn set_frequency(&mut self, frequency: u32) -> Result<(), Error> {
+        let steps = ((frequency as u64) << 25) / XTAL_FREQ;
+        let [_, _, _, _, a, b, c, d] = steps.to_be_bytes();
+        self.iface.write(&[SET_RF_FREQUENCY, a, b, c, d]).await
+    }
+
+    async fn set_modulation(&mut self, m: &super::Modulation) -> Result<(), Error> {
+        let bw = match m.bandwidth {
+            super::Bandwidth::Khz125 => 0x04,
+            super::Bandwidth::Khz250 => 0x05,
+            super::Bandwidth::Khz500 => 0x06,
+        };
+        let ldro = m.low_data_rate_optimize() as u8;
+        self.iface
+            .write(&[
+                SET_MODULATION_PARAMS,
+                m.spreading_factor as u8,
+                bw,
+                m.coding_rate as u8,
+                ldro,
+            ])
+            .await
+    }
+
+    async fn set_packet(&mut self, preamble_len: u16, len: u8, iq_inverted: bool) -> Result<(), Error> {
+        let [hi, lo] = preamble_len.to_be_bytes();
+        // Explicit header, CRC on.
+        self.iface
+            .write(&[SET_PACKET_PARAMS, hi, lo, 0x00, len, 0x01, iq_inverted as u8])
+            .await
+    }
+
+    async fn set_irqs(&mut self, mask: u16) -> Result<(), Error> {
+        let [hi, lo] = mask.to_be_bytes();
+        self.iface
+            .write(&[SET_DIO_IRQ_PARAMS, hi, lo, hi, lo, 0, 0, 0, 0])
+            .await
+    }
+
+    /// Wait for one of the enabled interrupts, then clear them all.
+    async fn wait_irqs(&mut self) -> Result<u16, Error> {
+        self.iface.wait_irq().await;
+        let mut status = [0; 3];
+        self.iface.read(&[GET_IRQ_STATUS], &mut status).await?;
+        self.iface.write(&[CLEAR_IRQ_STATUS, 0xFF, 0xFF]).await?;
+        Ok(u16::from_be_bytes([status[1], status[2]]))
+    }
+}
+
+impl<I: Interface> Radio for Sx126x<I> {
+    async fn transmit(&mut self, config: &TxConfig, data: &[u8]) -> Result<(), Error> {
+        assert!(data.len() <= 255);
+
+        self.iface.write(&[SET_STANDBY, 0]).await?;
+        self.set_frequency(config.frequency).await?;
+
+        let (pa, power) = match self.config.power_amplifier {
+            PowerAmplifier::LowPower => ([0x04, 0x00, 0x01, 0x01], config.power.clamp(-17, 14)),
+            PowerAmplifier::HighPower => ([0x04, 0x07, 0x00, 0x01], config.power.clamp(-9, 22)),
+        };
+        self.iface.write(&[SET_PA_CONFIG, pa[0], pa[1], pa[2], pa[3]]).await?;
+        // 200 µs ramp time.
+        self.iface.write(&[SET_TX_PARAMS, power as u8, 0x04]).await?;
+
+        self.set_modulation(&config.modulation).await?;
+        self.set_packet(config.preamble_len, data.len() as u8, config.iq_inverted)
+            .await?;
+
+        let mut cmd = [0; 2 + 255];
+        cmd[..2].copy_from_slice(&[WRITE_BUFFER, 0]);
+        cmd[2..2 + data.len()].copy_from_slice(data);
+        self.iface.write(&cmd[..2 + data.len()]).await?;
+
+        self.set_irqs(IRQ_TX_DONE | IRQ_TIMEOUT).await?;
+        // Time out at twice the time-on-air, in units of 15.625 µs.
+        let toa = config.modulation.time_on_air(data.len(), config.preamble_len);
+        let [_, a, b, c] = ((toa.as_micros() * 2 * 64 / 1000).min(0xFF_FFFF) as u32).to_be_bytes();
+        self.iface.write(&[SET_TX, a, b, c]).await?;
+
+        match self.wait_irqs().await? {
+            irqs if irqs & IRQ_TX_DONE != 0 => Ok(()),
+            _ => Err(Error::Timeout),
+        }
+    }
+
+    async fn receive(&mut self, config: &RxConfig, buf: &mut [u8]) -> Result<(usize, RxQuality), Error> {
+        self.iface.write(&[SET_STANDBY, 0]).await?;
+        self.set_frequency(config.frequency).await?;
+        self.set_modulation(&config.modulation).await?;
+        self.set_packet(config.preamble_len, 0xFF, config.iq_inverted).await?;
+
+        self.set_irqs(IRQ_RX_DONE | IRQ_TIMEOUT | IRQ_HEADER_ERR | IRQ_CRC_ERR)
+            .await?;
+        // In units of 15.625 µs, 0 would mean no timeout.
+        let timeout = (config.timeout.as_micros() * 64 / 1000).clamp(1, 0xFF_FFFE) as u32;
+        let [_, a, b, c] = timeout.to_be_bytes();
+        self.iface.write(&[SET_RX, a, b, c]).await?;
+
+        let irqs = self.wait_irqs().await?;
+        if irqs & IRQ_HEADER_ERR != 0 {
+            return Err(Error::Header);
+        } else if irqs & IRQ_CRC_ERR != 0 {
+            return Err(Error::Crc);
+        } else if irqs & IRQ_RX_DONE == 0 {
+            return Err(Error::Timeout);
+        }
+
+        let mut status = [0; 3];
+        self.iface.read(&[GET_RX_BUFFER_STATUS], &mut status).await?;
+        let (len, start) = (status[1] as usize, status[2]);
+        let dst = buf.get_mut(..len).ok_or(Error::Overflow)?;
+
+        // The status byte precedes the data.
+        let mut data = [0; 1 + 255];
+        self.iface.read(&[READ_BUFFER, start], &mut data[..1 + len]).await?;
+        dst.copy_from_slice(&data[1..1 + len]);
+
+        let mut packet = [0; 4];
+        self.iface.read(&[GET_PACKET_STATUS], &mut packet).await?;
+        let quality = RxQuality {
+            rssi: -(packet[1] as i16) / 2,
+            snr: (packet[2] as i8) as i16 / 4,
+        };
+
+        Ok((len, quality))
+    }
+
+    async fn sleep(&mut self) -> Result<(), Error> {
+        // Warm start, keeping the configuration.
+        self.iface.write(&[SET_SLEEP, 0x04]).await
+    }
+}
@@ -0,0 +1,403 @@
+//! SX127x family LoRa driver.
+//!
+//! Supports the SX1276, SX1277, SX1278 and SX1279, and modules built around them such as the
+//! RFM95/RFM96. The SX1272/SX1273 have a different modem configuration layout and aren't
+//! supported. Unlike the SX126x, the SX127x is configured through registers, and can't use
+//! spreading factors below 7 with an explicit header.
+
+#[cfg(feature = "exti")]
+use embassy_time::Timer;
+use embassy_time::{with_timeout, Duration};
+
+use super::{Error, Modulation, Radio, RxConfig, RxQuality, SpreadingFactor, TxConfig};
+#[cfg(feature = "exti")]
+use crate::exti::ExtiInput;
+#[cfg(feature = "exti")]
+use crate::gpio::Output;
+#[cfg(feature = "exti")]
+use crate::mode::Async;
+#[cfg(feature = "exti")]
+use crate::spi::Spi;
+
+/// Crystal frequency of the radio.
+const XTAL_FREQ: u64 = 32_000_000;
+
+/// Registers, in LoRa mode.
+const REG_FIFO: u8 = 0x00;
+const REG_OP_MODE: u8 = 0x01;
+const REG_FRF_MSB: u8 = 0x06;
+const REG_PA_CONFIG: u8 = 0x09;
+const REG_OCP: u8 = 0x0B;
+const REG_LNA: u8 = 0x0C;
+const REG_FIFO_ADDR_PTR: u8 = 0x0D;
+const REG_FIFO_TX_BASE_ADDR: u8 = 0x0E;
+const REG_FIFO_RX_BASE_ADDR: u8 = 0x0F;
+const REG_FIFO_RX_CURRENT_ADDR: u8 = 0x10;
+const REG_IRQ_FLAGS: u8 = 0x12;
+const REG_RX_NB_BYTES: u8 = 0x13;
+const REG_PKT_SNR_VALUE: u8 = 0x19;
+const REG_MODEM_CONFIG_1: u8 = 0x1D;
+const REG_MODEM_CONFIG_2: u8 = 0x1E;
+const REG_SYMB_TIMEOUT_LSB: u8 = 0x1F;
+const REG_PREAMBLE_MSB: u8 = 0x20;
+const REG_PAYLOAD_LENGTH: u8 = 0x22;
+const REG_MAX_PAYLOAD_LENGTH: u8 = 0x23;
+const REG_MODEM_CONFIG_3: u8 = 0x26;
+const REG_INVERT_IQ: u8 = 0x33;
+const REG_HIGH_BW_OPTIMIZE_1: u8 = 0x36;
+const REG_SYNC_WORD: u8 = 0x39;
+const REG_HIGH_BW_OPTIMIZE_2: u8 = 0x3A;
+const REG_INVERT_IQ_2: u8 = 0x3B;
+const REG_DIO_MAPPING_1: u8 = 0x40;
+const REG_VERSION: u8 = 0x42;
+const REG_PA_DAC: u8 = 0x4D;
+
+/// Silicon version of the SX1276/77/78/79.
+const VERSION: u8 = 0x12;
+
+/// Operating modes.
+const MODE_LORA: u8 = 0x80;
+const MODE_LOW_FREQUENCY: u8 = 0x08;
+const MODE_SLEEP: u8 = 0x00;
+const MODE_STANDBY: u8 = 0x01;
+const MODE_TX: u8 = 0x03;
+const MODE_RX_SINGLE: u8 = 0x06;
+
+/// Interrupt flags.
+const IRQ_RX_TIMEOUT: u8 = 1 << 7;
+const IRQ_RX_DONE: u8 = 1 << 6;
+const IRQ_PAYLOAD_CRC_ERROR: u8 = 1 << 5;
+const IRQ_TX_DONE: u8 = 1 << 3;
+
+/// DIO mappings: DIO0 on TxDone, or DIO0 on RxDone and DIO1 on RxTimeout.
+const DIO_MAPPING_TX: u8 = 0x40;
+const DIO_MAPPING_RX: u8 = 0x00;
+
+/// LoRaWAN public network sync word.
+const PUBLIC_SYNC_WORD: u8 = 0x34;
+
+/// Frequencies below this use the low frequency port (bands 2 and 3, e.g. 433 MHz).
+const LOW_FREQUENCY_LIMIT: u32 = 525_000_000;
+
+/// Longest receive timeout, in symbols.
+const MAX_SYMBOL_TIMEOUT: u64 = 0x3FF;
+
+/// Bus to an SX127x radio.
+pub trait Interface {
+    /// Write consecutive registers, starting at `addr`.
+    async fn write_registers(&mut self, addr: u8, data: &[u8]) -> Result<(), Error>;
+
+    /// Read consecutive registers, starting at `addr`.
+    async fn read_registers(&mut self, addr: u8, data: &mut [u8]) -> Result<(), Error>;
+
+    /// Wait for the radio to raise one of its interrupt lines, DIO0 or DIO1.
+    async fn wait_irq(&mut self);
+
+    /// Reset the radio.
+    async fn reset(&mut self) -> Result<(), Error>;
+}
+
+/// Interface to an SX127x radio on a SPI bus.
+#[cfg(feature = "exti")]
+pub struct SpiInterface<'d> {
+    spi: Spi<'d, Async>,
+    nss: Output<'d>,
+    dio0: ExtiInput<'d>,
+    dio1: ExtiInput<'d>,
+    reset: Output<'d>,
+}
+
+#[cfg(feature = "exti")]
+impl<'d> SpiInterface<'d> {
+    /// Create a new interface. `spi` must be configured in mode 0, at up to 10 MHz.
+    ///
+    /// DIO0 signals the end of transmissions and receptions, DIO1 receive timeouts. The other
+    /// DIOs aren't used.
+    pub fn new(
+        spi: Spi<'d, Async>,
+        nss: Output<'d>,
+        dio0: ExtiInput<'d>,
+        dio1: ExtiInput<'d>,
+        reset: Output<'d>,
+    ) -> Self {
+        Self {
+            spi,
+            nss,
+            dio0,
+            dio1,
+            reset,
+        }
+    }
+}
+
+#[cfg(feature = "exti")]
+impl<'d> Interface for SpiInterface<'d> {
+    async fn write_registers(&mut self, addr: u8, data: &[u8]) -> Result<(), Error> {
+        self.nss.set_low();
+        let res = match self.spi.write(&[addr | 0x80]).await {
+            Ok(()) => self.spi.write(data).await,
+            Err(e) => Err(e),
+        };
+        self.nss.set_high();
+        Ok(res?)
+    }
+
+    async fn read_registers(&mut self, addr: u8, data: &mut [u8]) -> Result<(), Error> {
+        self.nss.set_low();
+        let res = match self.spi.write(&[addr & 0x7F]).await {
+            Ok(()) => self.spi.read(data).await,
+            Err(e) => Err(e),
+        };
+        self.nss.set_high();
+        Ok(res?)
+    }
+
+    async fn wait_irq(&mut self) {
+        embassy_futures::select::select(self.dio0.wait_for_high(), self.dio1.wait_for_high()).await;
+    }
+
+    async fn reset(&mut self) -> Result<(), Error> {
+        self.reset.set_low();
+        Timer::after(Duration::from_micros(100)).await;
+        self.reset.set_high();
+        Timer::after(Duration::from_millis(5)).await;
+        Ok(())
+    }
+}
+
+/// SX127x configuration.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Transmit on the `PA_BOOST` pin, up to +20 dBm, instead of the `RFO` pin, up to +14 dBm.
+    ///
+    /// Which one is connected to the antenna is board specific; most modules use `PA_BOOST`.
+    pub pa_boost: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { pa_boost: true }
+    }
+}
+
+/// SX127x LoRa radio.
+pub struct Sx127x<I: Interface> {
+    iface: I,
+    config: Config,
+    low_frequency: bool,
+}
+
+impl<I: Interface> Sx127x<I> {
+    /// Reset and initialize the radio, in LoRa mode.
+    ///
+    /// Returns [`Error::Unsupported`] if the radio isn't an SX1276/77/78/79.
+    pub async fn new(mut iface: I, config: Config) -> Result<Self, Error> {
+        iface.reset().await?;
+
+        let mut this = Self {
+            iface,
+            config,
+            low_frequency: false,
+        };
+        if this.read_register(REG_VERSION).await? != VERSION {
+            return Err(Error::Unsupported);
+        }
+
+        // LoRa mode can only be selected in sleep mode.
+        this.write_register(REG_OP_MODE, MODE_SLEEP).await?;
+        this.set_mode(MODE_SLEEP).await?;
+        this.set_mode(MODE_STANDBY).await?;
+
+        this.write_register(REG_FIFO_TX_BASE_ADDR, 0).await?;
+        this.write_register(REG_FIFO_RX_BASE_ADDR, 0).await?;
+        // Maximum LNA gain, with the boost for the high frequency port.
+        this.write_register(REG_LNA, 0x23).await?;
+        this.write_register(REG_SYNC_WORD, PUBLIC_SYNC_WORD).await?;
+        Ok(this)
+    }
+
+    /// Release the interface.
+    pub fn release(self) -> I {
+        self.iface
+    }
+
+    async fn write_register(&mut self, addr: u8, value: u8) -> Result<(), Error> {
+        self.iface.write_registers(addr, &[value]).await
+    }
+
+    async fn read_register(&mut self, addr: u8) -> Result<u8, Error> {
+        let mut value = [0];
+        self.iface.read_registers(addr, &mut value).await?;
+        Ok(value[0])
+    }
+
+    async fn set_mode(&mut self, mode: u8) -> Result<(), Error> {
+        let low_frequency = if self.low_frequency { MODE_LOW_FREQUENCY } else { 0 };
+        self.write_register(REG_OP_MODE, MODE_LORA | low_frequency | mode).await
+    }
+
+    async fn set_frequency(&mut self, frequency: u32) -> Result<(), Error> {
+        self.low_frequency = frequency < LOW_FREQUENCY_LIMIT;
+        self.set_mode(MODE_STANDBY).await?;
+
+        let steps = ((frequency as u64) << 19) / XTAL_FREQ;
+        let [_, _, _, _, _, a, b, c] = steps.to_be_bytes();
+        self.iface.write_registers(REG_FRF_MSB, &[a, b, c]).await
+    }
+
+    async fn set_modulation(&mut self, m: &Modulation, preamble_len: u16, symbol_timeout: u16) -> Result<(), Error> {
+        if matches!(m.spreading_factor, SpreadingFactor::SF5 | SpreadingFactor::SF6) {
+            return Err(Error::Unsupported);
+        }
+
+        let bw = match m.bandwidth {
+            super::Bandwidth::Khz125 => 7,
+            super::Bandwidth::Khz250 => 8,
+            super::Bandwidth::Khz500 => 9,
+        };
+        // Explicit header, CRC on.
+        let [timeout_hi, timeout_lo] = symbol_timeout.to_be_bytes();
+        self.write_register(REG_MODEM_CONFIG_1, bw << 4 | (m.coding_rate as u8) << 1)
+            .await?;
+        self.write_register(REG_MODEM_CONFIG_2, (m.spreading_factor as u8) << 4 | 0x04 | timeout_hi)
+            .await?;
+        self.write_register(REG_SYMB_TIMEOUT_LSB, timeout_lo).await?;
+        // Automatic gain control.
+        let ldro = m.low_data_rate_optimize() as u8;
+        self.write_register(REG_MODEM_CONFIG_3, ldro << 3 | 0x04).await?;
+        self.iface
+            .write_registers(REG_PREAMBLE_MSB, &preamble_len.to_be_bytes())
+            .await?;
+
+        // Errata 2.1: sensitivity optimization with a 500 kHz bandwidth.
+        match m.bandwidth {
+            super::Bandwidth::Khz500 => {
+                self.write_register(REG_HIGH_BW_OPTIMIZE_1, 0x02).await?;
+                let optimize_2 = if self.low_frequency { 0x7F } else { 0x64 };
+                self.write_register(REG_HIGH_BW_OPTIMIZE_2, optimize_2).await
+            }
+            _ => self.write_register(REG_HIGH_BW_OPTIMIZE_1, 0x03).await,
+        }
+    }
+
+    async fn set_iq(&mut self, tx: bool, inverted: bool) -> Result<(), Error> {
+        let (iq, iq_2) = match (tx, inverted) {
+            (_, false) => (0x27, 0x1D),
+            (true, true) => (0x26, 0x19),
+            (false, true) => (0x67, 0x19),
+        };
+        self.write_register(REG_INVERT_IQ, iq).await?;
+        self.write_register(REG_INVERT_IQ_2, iq_2).await
+    }
+
+    async fn set_power(&mut self, power: i8) -> Result<(), Error> {
+        let (pa_config, pa_dac, ocp) = match self.config.pa_boost {
+            // Pout = 17 - (15 - OutputPower), or 20 - (15 - OutputPower) with the +20 dBm DAC.
+            true => match power.clamp(2, 20) {
+                p if p > 17 => (0xF0 | (p - 5) as u8, 0x87, 0x3B),
+                p => (0xF0 | (p - 2) as u8, 0x84, 0x2B),
+            },
+            // With MaxPower at 7, Pout = OutputPower.
+            false => (0x70 | power.clamp(0, 14) as u8, 0x84, 0x2B),
+        };
+        self.write_register(REG_PA_CONFIG, pa_config).await?;
+        self.write_register(REG_PA_DAC, pa_dac).await?;
+        // Over-current protection, at 100 mA, or 240 mA above +17 dBm.
+        self.write_register(REG_OCP, ocp).await
+    }
+
+    /// Wait for an interrupt, then clear all the interrupt flags.
+    async fn wait_irqs(&mut self) -> Result<u8, Error> {
+        self.iface.wait_irq().await;
+        self.clear_irqs().await
+    }
+
+    async fn clear_irqs(&mut self) -> Result<u8, Error> {
+        let flags = self.read_register(REG_IRQ_FLAGS).await?;
+        self.write_register(REG_IRQ_FLAGS, 0xFF).await?;
+        Ok(flags)
+    }
+}
+
+impl<I: Interface> Radio for Sx127x<I> {
+    async fn transmit(&mut self, config: &TxConfig, data: &[u8]) -> Result<(), Error> {
+        assert!(data.len() <= 255);
+
+        self.set_frequency(config.frequency).await?;
+        self.set_power(config.power).await?;
+        self.set_modulation(&config.modulation, config.preamble_len, 0).await?;
+        self.set_iq(true, config.iq_inverted).await?;
+
+        self.write_register(REG_PAYLOAD_LENGTH, data.len() as u8).await?;
+        self.write_register(REG_FIFO_ADDR_PTR, 0).await?;
+        self.iface.write_registers(REG_FIFO, data).await?;
+
+        self.write_register(REG_DIO_MAPPING_1, DIO_MAPPING_TX).await?;
+        self.clear_irqs().await?;
+        self.set_mode(MODE_TX).await?;
+
+        // The radio has no transmit timeout, give up at twice the time-on-air.
+        let toa = config.modulation.time_on_air(data.len(), config.preamble_len);
+        let res = with_timeout(toa * 2 + Duration::from_millis(10), self.iface.wait_irq()).await;
+        let flags = self.clear_irqs().await?;
+        match res {
+            Ok(()) if flags & IRQ_TX_DONE != 0 => Ok(()),
+            _ => {
+                self.set_mode(MODE_STANDBY).await?;
+                Err(Error::Timeout)
+            }
+        }
+    }
+
+    /// Receive a packet into `buf`, returning its length and signal quality.
+    ///
+    /// The SX127x counts the receive timeout in symbols, up to 1023: longer timeouts are
+    /// shortened to that.
+    async fn receive(&mut self, config: &RxConfig, buf: &mut [u8]) -> Result<(usize, RxQuality), Error> {
+        let symbols = (config.timeout.as_micros() / config.modulation.symbol_us()).clamp(4, MAX_SYMBOL_TIMEOUT);
+
+        self.set_frequency(config.frequency).await?;
+        self.set_modulation(&config.modulation, config.preamble_len, symbols as u16)
+            .await?;
+        self.set_iq(false, config.iq_inverted).await?;
+
+        self.write_register(REG_MAX_PAYLOAD_LENGTH, 0xFF).await?;
+        self.write_register(REG_FIFO_ADDR_PTR, 0).await?;
+        self.write_register(REG_DIO_MAPPING_1, DIO_MAPPING_RX).await?;
+        self.clear_irqs().await?;
+        self.set_mode(MODE_RX_SINGLE).await?;
+
+        let flags = self.wait_irqs().await?;
+        if flags & IRQ_RX_TIMEOUT != 0 || flags & IRQ_RX_DONE == 0 {
+            return Err(Error::Timeout);
+        } else if flags & IRQ_PAYLOAD_CRC_ERROR != 0 {
+            return Err(Error::Crc);
+        }
+
+        let len = self.read_register(REG_RX_NB_BYTES).await? as usize;
+        let start = self.read_register(REG_FIFO_RX_CURRENT_ADDR).await?;
+        let dst = buf.get_mut(..len).ok_or(Error::Overflow)?;
+        self.write_register(REG_FIFO_ADDR_PTR, start).await?;
+        self.iface.read_registers(REG_FIFO, dst).await?;
+
+        // The packet RSSI register follows the SNR one.
+        let mut packet = [0; 2];
+        self.iface.read_registers(REG_PKT_SNR_VALUE, &mut packet).await?;
+        let snr = (packet[0] as i8) as i16 / 4;
+        let offset = if self.low_frequency { -164 } else { -157 };
+        // Below the noise floor, the packet RSSI is corrected by the SNR.
+        let quality = RxQuality {
+            rssi: offset + packet[1] as i16 + snr.min(0),
+            snr,
+        };
+
+        Ok((len, quality))
+    }
+
+    async fn sleep(&mut self) -> Result<(), Error> {
+        // The configuration is kept in sleep mode.
+        self.set_mode(MODE_SLEEP).await
+    }
+}
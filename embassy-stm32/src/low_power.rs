@@ -50,6 +50,9 @@
 //!     // your application here...
 //! }
 //! ```
+//!
+//! With the `low-power-profiling` feature, the executor records what woke it up after each sleep
+//! and how long it slept, see [`wakeup_stats`].
 
 use core::arch::asm;
 use core::marker::PhantomData;
@@ -152,6 +155,9 @@ impl Executor {
                 time_driver: get_driver(),
             });
 
+            #[cfg(feature = "low-power-profiling")]
+            EXECUTOR.as_mut().unwrap().scb.set_sevonpend();
+
             EXECUTOR.as_mut().unwrap()
         })
     }
@@ -191,7 +197,8 @@ impl Executor {
         });
     }
 
-    fn configure_pwr(&mut self) {
+    /// Returns whether the core will enter a stop mode.
+    fn configure_pwr(&mut self) -> bool {
         self.scb.clear_sleepdeep();
 
         compiler_fence(Ordering::SeqCst);
@@ -200,12 +207,12 @@ impl Executor {
 
         if stop_mode.is_none() {
             trace!("low power: not ready to stop");
-            return;
+            return false;
         }
 
         if self.time_driver.pause_time().is_err() {
             trace!("low power: failed to pause time");
-            return;
+            return false;
         }
 
         let stop_mode = stop_mode.unwrap();
@@ -217,6 +224,31 @@ impl Executor {
 
         #[cfg(not(feature = "low-power-debug-with-sleep"))]
         self.scb.set_sleepdeep();
+
+        true
+    }
+
+    /// Sleep like `configure_pwr` + `wfe`, recording the wakeup source and the time slept.
+    #[cfg(feature = "low-power-profiling")]
+    unsafe fn profiled_wfe(&mut self) {
+        let start = embassy_time::Instant::now();
+        let stop = self.configure_pwr();
+
+        // With interrupts masked, a pending interrupt still ends the WFE (SEVONPEND) but is not
+        // taken yet, so it can be read from the ICSR before its handler runs.
+        cortex_m::interrupt::disable();
+        asm!("wfe");
+        let source = match (*SCB::PTR).icsr.read() >> 12 & 0x1FF {
+            0 => WakeupSource::Event,
+            n @ 1..=15 => WakeupSource::Exception(n as u8),
+            n => WakeupSource::Interrupt(n as u16 - 16),
+        };
+        cortex_m::interrupt::enable();
+
+        if stop {
+            self.time_driver.resume_time();
+        }
+        profiling::record(source, stop, start.elapsed());
     }
 
     /// Run the executor.
@@ -243,9 +275,90 @@ impl Executor {
         loop {
             unsafe {
                 EXECUTOR.as_mut().unwrap().inner.poll();
-                self.configure_pwr();
-                asm!("wfe");
+                #[cfg(not(feature = "low-power-profiling"))]
+                {
+                    self.configure_pwr();
+                    asm!("wfe");
+                }
+                #[cfg(feature = "low-power-profiling")]
+                self.profiled_wfe();
             };
         }
     }
 }
+
+/// What ended a sleep of the low-power executor.
+#[cfg(feature = "low-power-profiling")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WakeupSource {
+    /// An interrupt, by IRQ number (`interrupt::USART1 as u16`, for example).
+    Interrupt(u16),
+    /// A system exception, by exception number (15 for SysTick, for example).
+    Exception(u8),
+    /// An event with no interrupt pending, for example a task woken just before the core went to
+    /// sleep.
+    Event,
+}
+
+/// Sleep statistics for one wakeup source.
+#[cfg(feature = "low-power-profiling")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WakeupStats {
+    /// Number of sleeps ended by this source.
+    pub wakeups: u32,
+    /// Number of those sleeps spent in a stop mode rather than in sleep mode.
+    pub stop_wakeups: u32,
+    /// Total time slept before being woken by this source.
+    pub sleep_time: embassy_time::Duration,
+}
+
+/// Call `f` with the statistics of each wakeup source recorded since the last
+/// [`reset_wakeup_stats`].
+///
+/// At most 16 sources are tracked; sleeps ended by further sources are not recorded.
+#[cfg(feature = "low-power-profiling")]
+pub fn wakeup_stats(mut f: impl FnMut(WakeupSource, WakeupStats)) {
+    let table = critical_section::with(|_| unsafe { profiling::TABLE });
+    for (source, stats) in table.into_iter().flatten() {
+        f(source, stats);
+    }
+}
+
+/// Clear the recorded wakeup statistics.
+#[cfg(feature = "low-power-profiling")]
+pub fn reset_wakeup_stats() {
+    critical_section::with(|_| unsafe { profiling::TABLE = [None; profiling::SOURCES] });
+}
+
+#[cfg(feature = "low-power-profiling")]
+mod profiling {
+    use embassy_time::Duration;
+
+    use super::{WakeupSource, WakeupStats};
+
+    pub(super) const SOURCES: usize = 16;
+
+    pub(super) static mut TABLE: [Option<(WakeupSource, WakeupStats)>; SOURCES] = [None; SOURCES];
+
+    pub(super) fn record(source: WakeupSource, stop: bool, slept: Duration) {
+        critical_section::with(|_| {
+            let table = unsafe { &mut *core::ptr::addr_of_mut!(TABLE) };
+            let Some(entry) = table.iter_mut().find(|e| e.map_or(true, |(s, _)| s == source)) else {
+                return;
+            };
+            let (_, stats) = entry.get_or_insert((
+                source,
+                WakeupStats {
+                    wakeups: 0,
+                    stop_wakeups: 0,
+                    sleep_time: Duration::from_ticks(0),
+                },
+            ));
+            stats.wakeups += 1;
+            stats.stop_wakeups += stop as u32;
+            stats.sleep_time += slept;
+        })
+    }
+}
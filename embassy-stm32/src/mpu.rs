@@ -0,0 +1,321 @@
+//! Memory Protection Unit (MPU)
+//!
+//! The MPU overrides the memory attributes of up to 8 or 16 regions: their memory type, access
+//! permissions, and whether code can be executed from them. [`Mpu`] programs regions described
+//! by [`Region`], with constructors for the common hardening cases:
+//!
+//! - [`Region::dma_buffer`], a non-cacheable [`DmaBuf`], for DMA ring buffers or to avoid
+//!   cache maintenance on Cortex-M7 chips, see [`dma::cache`](crate::dma::cache).
+//! - [`Region::stack_guard`], a read-only page at the bottom of the stack, turning a stack
+//!   overflow into a MemManage fault instead of silent memory corruption.
+//! - [`Region::peripherals`], the peripheral address space as device memory code can't be
+//!   executed from.
+//!
+//! On ARMv6-M and ARMv7-M cores (PMSAv7), the size of a region is a power of two, of at least
+//! 256 or 32 bytes respectively, and its base is aligned to its size. On ARMv8-M cores (PMSAv8),
+//! the base and size are multiples of 32 bytes. When regions overlap, the one with the highest
+//! number takes precedence on PMSAv7, and accesses fault on PMSAv8.
+
+use core::mem::size_of;
+
+use crate::dma::DmaBuf;
+
+/// MPU registers, as word offsets from the MPU base.
+const TYPE: usize = 0x00 / 4;
+const CTRL: usize = 0x04 / 4;
+const RNR: usize = 0x08 / 4;
+const RBAR: usize = 0x0C / 4;
+/// RASR on PMSAv7, RLAR on PMSAv8.
+const RASR_RLAR: usize = 0x10 / 4;
+#[cfg(armv8m)]
+const MAIR0: usize = 0x30 / 4;
+
+const CTRL_ENABLE: u32 = 1 << 0;
+const CTRL_PRIVDEFENA: u32 = 1 << 2;
+
+/// Smallest region size.
+#[cfg(armv6m)]
+const MIN_SIZE: u32 = 256;
+#[cfg(not(armv6m))]
+const MIN_SIZE: u32 = 32;
+
+/// Memory type of a region.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Memory {
+    /// Normal memory, write-back cacheable, like RAM and flash are by default.
+    Normal,
+    /// Normal memory, not cacheable.
+    NonCacheable,
+    /// Device memory: accesses are not merged, reordered or speculated, like peripherals by default.
+    Device,
+}
+
+/// Access permissions of a region.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Access {
+    /// Read and write from any mode.
+    ReadWrite,
+    /// Read-only from any mode.
+    ReadOnly,
+    /// Read and write in privileged mode only.
+    PrivilegedReadWrite,
+    /// Read-only in privileged mode only.
+    PrivilegedReadOnly,
+}
+
+/// MPU error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The region number is higher than the number of regions of the MPU.
+    InvalidRegion,
+    /// The base or size of the region doesn't meet the MPU requirements, see the
+    /// [module documentation](self).
+    Misaligned,
+}
+
+/// MPU region, see the [module documentation](self).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Region {
+    base: u32,
+    size: u32,
+    memory: Memory,
+    access: Access,
+    execute: bool,
+}
+
+impl Region {
+    /// Region of `size` bytes at `base`, of normal memory readable, writable and executable from
+    /// any mode.
+    pub const fn new(base: u32, size: u32) -> Self {
+        Self {
+            base,
+            size,
+            memory: Memory::Normal,
+            access: Access::ReadWrite,
+            execute: true,
+        }
+    }
+
+    /// Non-cacheable region covering `buf`.
+    ///
+    /// The buffer needs the alignment of its size on PMSAv7, which [`DmaBuf`] only provides
+    /// for buffers of at most 32 bytes, so larger buffers should be placed with a linker section.
+    pub fn dma_buffer<T>(buf: &'static DmaBuf<T>) -> Self {
+        Self::new(buf as *const _ as u32, size_of::<DmaBuf<T>>() as u32)
+            .memory(Memory::NonCacheable)
+            .no_execute()
+    }
+
+    /// Guard region at `stack_bottom`, the lowest address of a full-descending stack.
+    ///
+    /// The region is the smallest the MPU supports, read-only and not executable, so pushing
+    /// past the bottom of the stack faults. The stack must be at least as big as the region
+    /// plus the largest frame that could skip over it.
+    pub const fn stack_guard(stack_bottom: u32) -> Self {
+        Self::new(stack_bottom, MIN_SIZE)
+            .access(Access::PrivilegedReadOnly)
+            .no_execute()
+    }
+
+    /// Region covering the peripherals (`0x4000_0000` to `0x5FFF_FFFF`), as device memory
+    /// which can't be executed from.
+    pub const fn peripherals() -> Self {
+        Self::new(0x4000_0000, 0x2000_0000)
+            .memory(Memory::Device)
+            .access(Access::PrivilegedReadWrite)
+            .no_execute()
+    }
+
+    /// Set the memory type.
+    pub const fn memory(mut self, memory: Memory) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    /// Set the access permissions.
+    pub const fn access(mut self, access: Access) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Forbid executing code from the region.
+    pub const fn no_execute(mut self) -> Self {
+        self.execute = false;
+        self
+    }
+
+    /// Base address.
+    pub const fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Size, in bytes.
+    pub const fn size(&self) -> u32 {
+        self.size
+    }
+
+    #[cfg(not(armv8m))]
+    fn check(&self) -> Result<(), Error> {
+        if !self.size.is_power_of_two() || self.size < MIN_SIZE || self.base & (self.size - 1) != 0 {
+            return Err(Error::Misaligned);
+        }
+        Ok(())
+    }
+
+    #[cfg(armv8m)]
+    fn check(&self) -> Result<(), Error> {
+        if self.size == 0
+            || self.size % 32 != 0
+            || self.base % 32 != 0
+            || self.base.checked_add(self.size - 1).is_none()
+        {
+            return Err(Error::Misaligned);
+        }
+        Ok(())
+    }
+
+    /// RBAR and RASR values.
+    #[cfg(not(armv8m))]
+    fn encode(&self) -> (u32, u32) {
+        let ap = match self.access {
+            Access::ReadWrite => 0b011,
+            Access::ReadOnly => 0b110,
+            Access::PrivilegedReadWrite => 0b001,
+            Access::PrivilegedReadOnly => 0b101,
+        };
+        // TEX, S, C, B
+        let (tex, s, c, b) = match self.memory {
+            Memory::Normal => (0b001, 0, 1, 1),
+            Memory::NonCacheable => (0b001, 0, 0, 0),
+            Memory::Device => (0b000, 1, 0, 1),
+        };
+        let size = self.size.trailing_zeros() - 1;
+
+        let rasr = (!self.execute as u32) << 28 | ap << 24 | tex << 19 | s << 18 | c << 17 | b << 16 | size << 1 | 1;
+        (self.base, rasr)
+    }
+
+    /// RBAR and RLAR values, the memory type being the index of its MAIR attribute.
+    #[cfg(armv8m)]
+    fn encode(&self) -> (u32, u32) {
+        let ap = match self.access {
+            Access::PrivilegedReadWrite => 0b00,
+            Access::ReadWrite => 0b01,
+            Access::PrivilegedReadOnly => 0b10,
+            Access::ReadOnly => 0b11,
+        };
+        let attr = match self.memory {
+            Memory::Normal => 0,
+            Memory::NonCacheable => 1,
+            Memory::Device => 2,
+        };
+
+        let rbar = self.base | ap << 1 | !self.execute as u32;
+        let rlar = (self.base + self.size - 32) | attr << 1 | 1;
+        (rbar, rlar)
+    }
+}
+
+/// MPU driver.
+pub struct Mpu {
+    mpu: cortex_m::peripheral::MPU,
+}
+
+impl Mpu {
+    /// Create a driver, taking the MPU from the core peripherals.
+    pub fn new(mpu: cortex_m::peripheral::MPU) -> Self {
+        #[cfg(armv8m)]
+        {
+            // Attributes of `Memory`: normal write-back, normal non-cacheable, device nGnRE.
+            write(MAIR0, 0x00_04_44_FF);
+        }
+
+        Self { mpu }
+    }
+
+    /// Number of regions of the MPU, 0 if there is none.
+    pub fn regions(&self) -> u8 {
+        (read(TYPE) >> 8) as u8
+    }
+
+    /// Configure region `index` and enable it.
+    ///
+    /// Cached data of the region is written back and dropped before making it non-cacheable or
+    /// device memory, so no dirty cache line can later overwrite it.
+    pub fn set_region(&mut self, index: u8, region: Region) -> Result<(), Error> {
+        if index >= self.regions() {
+            return Err(Error::InvalidRegion);
+        }
+        region.check()?;
+
+        if region.memory != Memory::Normal {
+            crate::dma::cache::Region::new(region.base as *const u8, region.size as usize).clean_invalidate();
+        }
+
+        let (rbar, rasr_rlar) = region.encode();
+        cortex_m::asm::dsb();
+        write(RNR, index as u32);
+        // Disable the region while it is half-written.
+        write(RASR_RLAR, 0);
+        write(RBAR, rbar);
+        write(RASR_RLAR, rasr_rlar);
+        cortex_m::asm::dsb();
+        cortex_m::asm::isb();
+        Ok(())
+    }
+
+    /// Disable region `index`.
+    pub fn clear_region(&mut self, index: u8) -> Result<(), Error> {
+        if index >= self.regions() {
+            return Err(Error::InvalidRegion);
+        }
+
+        cortex_m::asm::dsb();
+        write(RNR, index as u32);
+        write(RASR_RLAR, 0);
+        cortex_m::asm::dsb();
+        cortex_m::asm::isb();
+        Ok(())
+    }
+
+    /// Enable the MPU.
+    ///
+    /// With `default_map`, privileged code can access memory outside of the regions with the
+    /// default attributes, otherwise all such accesses fault.
+    pub fn enable(&mut self, default_map: bool) {
+        let privdefena = if default_map { CTRL_PRIVDEFENA } else { 0 };
+        cortex_m::asm::dsb();
+        write(CTRL, CTRL_ENABLE | privdefena);
+        cortex_m::asm::dsb();
+        cortex_m::asm::isb();
+    }
+
+    /// Disable the MPU, restoring the default memory attributes.
+    pub fn disable(&mut self) {
+        cortex_m::asm::dsb();
+        write(CTRL, 0);
+        cortex_m::asm::dsb();
+        cortex_m::asm::isb();
+    }
+
+    /// Return the MPU.
+    pub fn free(self) -> cortex_m::peripheral::MPU {
+        self.mpu
+    }
+}
+
+fn reg(offset: usize) -> *mut u32 {
+    unsafe { (cortex_m::peripheral::MPU::PTR as *mut u32).add(offset) }
+}
+
+fn read(offset: usize) -> u32 {
+    unsafe { reg(offset).read_volatile() }
+}
+
+fn write(offset: usize, value: u32) {
+    unsafe { reg(offset).write_volatile(value) }
+}
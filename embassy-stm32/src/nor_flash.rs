@@ -0,0 +1,134 @@
+//! External serial NOR flash memory.
+//!
+//! The [`SpiNorFlash`] driver lives in [`embassy_embedded_hal::nor_flash`], along with the SPI
+//! [`SpiNorBus`]. This module implements its [`NorBus`] for:
+//! - [`Qspi`](crate::qspi::Qspi), which can also map the flash into memory for execute in place,
+//!   see [`memory_mapped`],
+//! - [`Ospi`](crate::ospi::Ospi) in async mode.
+
+pub use embassy_embedded_hal::nor_flash::{Command, Error, NorBus, SpiNorBus, SpiNorFlash, ERASE_SIZE};
+
+#[cfg(quadspi)]
+pub use self::qspi_bus::memory_mapped;
+
+#[cfg(quadspi)]
+mod qspi_bus {
+    use core::convert::Infallible;
+
+    use super::{Command, NorBus, SpiNorFlash};
+    use crate::mode::Mode as PeriMode;
+    use crate::qspi::enums::{AddressSize, DummyCycles, QspiWidth};
+    use crate::qspi::{MemoryMapped, Qspi, TransferConfig};
+
+    fn transfer_config<M: PeriMode>(qspi: &mut Qspi<'_, M>, command: &Command, has_data: bool) -> TransferConfig {
+        qspi.set_address_size(match command.four_byte_address {
+            true => AddressSize::_32bit,
+            false => AddressSize::_24bit,
+        });
+        TransferConfig {
+            iwidth: QspiWidth::SING,
+            awidth: match command.address {
+                Some(_) => QspiWidth::SING,
+                None => QspiWidth::NONE,
+            },
+            dwidth: match (has_data, command.quad_data) {
+                (false, _) => QspiWidth::NONE,
+                (true, false) => QspiWidth::SING,
+                (true, true) => QspiWidth::QUAD,
+            },
+            instruction: command.instruction,
+            address: command.address,
+            dummy: match command.dummy {
+                true => DummyCycles::_8,
+                false => DummyCycles::_0,
+            },
+            ..Default::default()
+        }
+    }
+
+    impl<'d, M: PeriMode> NorBus for Qspi<'d, M> {
+        type Error = Infallible;
+        const QUAD: bool = true;
+
+        async fn command(&mut self, command: &Command) -> Result<(), Infallible> {
+            let transaction = transfer_config(self, command, false);
+            Qspi::command(self, transaction);
+            Ok(())
+        }
+
+        async fn read(&mut self, command: &Command, buf: &mut [u8]) -> Result<(), Infallible> {
+            let transaction = transfer_config(self, command, true);
+            self.blocking_read(buf, transaction);
+            Ok(())
+        }
+
+        async fn write(&mut self, command: &Command, buf: &[u8]) -> Result<(), Infallible> {
+            let transaction = transfer_config(self, command, true);
+            self.blocking_write(buf, transaction);
+            Ok(())
+        }
+    }
+
+    /// Map the flash memory, for reading or executing in place.
+    ///
+    /// [`Config::memory_size`](crate::qspi::Config::memory_size) must be set to the size of the
+    /// memory. The memory goes back to indirect mode when the returned guard is dropped.
+    pub fn memory_mapped<'a, 'd, M: PeriMode>(flash: &'a mut SpiNorFlash<Qspi<'d, M>>) -> MemoryMapped<'a, 'd, M> {
+        let command = flash.read_command();
+        let bus = flash.bus();
+        let transaction = transfer_config(bus, &command, true);
+        bus.memory_mapped(transaction)
+    }
+}
+
+#[cfg(octospi)]
+mod ospi_bus {
+    use super::{Command, NorBus};
+    use crate::mode::Async;
+    use crate::ospi::enums::{AddressSize, DummyCycles, OspiWidth};
+    use crate::ospi::{Instance, Ospi, OspiError, TransferConfig};
+
+    fn transfer_config(command: &Command, has_data: bool) -> TransferConfig {
+        TransferConfig {
+            iwidth: OspiWidth::SING,
+            instruction: Some(command.instruction as u32),
+            isize: AddressSize::_8Bit,
+            adwidth: match command.address {
+                Some(_) => OspiWidth::SING,
+                None => OspiWidth::NONE,
+            },
+            address: command.address,
+            adsize: match command.four_byte_address {
+                true => AddressSize::_32bit,
+                false => AddressSize::_24bit,
+            },
+            dwidth: match (has_data, command.quad_data) {
+                (false, _) => OspiWidth::NONE,
+                (true, false) => OspiWidth::SING,
+                (true, true) => OspiWidth::QUAD,
+            },
+            dummy: match command.dummy {
+                true => DummyCycles::_8,
+                false => DummyCycles::_0,
+            },
+            ..Default::default()
+        }
+    }
+
+    impl<'d, T: Instance> NorBus for Ospi<'d, T, Async> {
+        type Error = OspiError;
+        const QUAD: bool = true;
+
+        async fn command(&mut self, command: &Command) -> Result<(), OspiError> {
+            Ospi::command(self, &transfer_config(command, false)).await
+        }
+
+        async fn read(&mut self, command: &Command, buf: &mut [u8]) -> Result<(), OspiError> {
+            Ospi::read(self, buf, transfer_config(command, true)).await
+        }
+
+        async fn write(&mut self, command: &Command, buf: &[u8]) -> Result<(), OspiError> {
+            Ospi::write(self, buf, transfer_config(command, true)).await
+        }
+    }
+}
@@ -113,6 +113,8 @@ pub struct TransferConfig {
     pub dwidth: OspiWidth,
     /// Data buffer
     pub ddtr: bool,
+    /// Data strobe (DQS) enable, for memories sampling data on DQS, e.g. HyperRAM or Octo-NOR in DTR mode
+    pub dqse: bool,
 
     /// Number of dummy cycles (DCYC)
     pub dummy: DummyCycles,
@@ -138,6 +140,7 @@ impl Default for TransferConfig {
 
             dwidth: OspiWidth::NONE,
             ddtr: false,
+            dqse: false,
 
             dummy: DummyCycles::_0,
         }
@@ -330,11 +333,12 @@ impl<'d, T: Instance, M: PeriMode> Ospi<'d, T, M> {
             w.set_isize(SizeInBits::from_bits(command.isize.into()));
 
             w.set_admode(PhaseMode::from_bits(command.adwidth.into()));
-            w.set_addtr(command.idtr);
+            w.set_addtr(command.addtr);
             w.set_adsize(SizeInBits::from_bits(command.adsize.into()));
 
             w.set_dmode(PhaseMode::from_bits(command.dwidth.into()));
             w.set_ddtr(command.ddtr);
+            w.set_dqse(command.dqse);
         });
 
         // Set informationrequired to initiate transaction
@@ -460,6 +464,95 @@ impl<'d, T: Instance, M: PeriMode> Ospi<'d, T, M> {
         Ok(())
     }
 
+    /// Enter memory-mapped mode.
+    ///
+    /// The external memory is read with `read` and, if given, written with `write` whenever the
+    /// mapped region is accessed; the address of both commands is ignored. Writes are for memories
+    /// supporting them, such as HyperRAM or PSRAM. Indirect transfers are impossible while the
+    /// returned guard is alive; dropping it aborts the ongoing access and returns to indirect mode.
+    pub fn memory_mapped(
+        &mut self,
+        read: &TransferConfig,
+        write: Option<&TransferConfig>,
+    ) -> Result<MemoryMapped<'_, 'd, T, M>, OspiError> {
+        for command in core::iter::once(read).chain(write) {
+            if <enums::OspiWidth as Into<u8>>::into(command.iwidth) > <enums::OspiWidth as Into<u8>>::into(self.width)
+                || <enums::OspiWidth as Into<u8>>::into(command.adwidth)
+                    > <enums::OspiWidth as Into<u8>>::into(self.width)
+                || <enums::OspiWidth as Into<u8>>::into(command.abwidth)
+                    > <enums::OspiWidth as Into<u8>>::into(self.width)
+                || <enums::OspiWidth as Into<u8>>::into(command.dwidth)
+                    > <enums::OspiWidth as Into<u8>>::into(self.width)
+            {
+                return Err(OspiError::InvalidCommand);
+            }
+        }
+
+        // Wait for peripheral to be free
+        while T::REGS.sr().read().busy() {}
+
+        T::REGS.cr().modify(|w| {
+            w.set_dmaen(false);
+            w.set_fmode(vals::FunctionalMode::MEMORYMAPPED);
+        });
+
+        // Read command
+        T::REGS.ccr().write(|w| {
+            w.set_imode(PhaseMode::from_bits(read.iwidth.into()));
+            w.set_idtr(read.idtr);
+            w.set_isize(SizeInBits::from_bits(read.isize.into()));
+
+            w.set_admode(PhaseMode::from_bits(read.adwidth.into()));
+            w.set_addtr(read.addtr);
+            w.set_adsize(SizeInBits::from_bits(read.adsize.into()));
+
+            w.set_abmode(PhaseMode::from_bits(read.abwidth.into()));
+            w.set_abdtr(read.abdtr);
+            w.set_absize(SizeInBits::from_bits(read.absize.into()));
+
+            w.set_dmode(PhaseMode::from_bits(read.dwidth.into()));
+            w.set_ddtr(read.ddtr);
+            w.set_dqse(read.dqse);
+        });
+        T::REGS.tcr().modify(|w| w.set_dcyc(read.dummy.into()));
+        if let Some(instruction) = read.instruction {
+            T::REGS.ir().write(|v| v.set_instruction(instruction));
+        }
+        if let Some(ab) = read.alternate_bytes {
+            T::REGS.abr().write(|v| v.set_alternate(ab));
+        }
+
+        // Write command
+        if let Some(write) = write {
+            T::REGS.wccr().write(|w| {
+                w.set_imode(PhaseMode::from_bits(write.iwidth.into()));
+                w.set_idtr(write.idtr);
+                w.set_isize(SizeInBits::from_bits(write.isize.into()));
+
+                w.set_admode(PhaseMode::from_bits(write.adwidth.into()));
+                w.set_addtr(write.addtr);
+                w.set_adsize(SizeInBits::from_bits(write.adsize.into()));
+
+                w.set_abmode(PhaseMode::from_bits(write.abwidth.into()));
+                w.set_abdtr(write.abdtr);
+                w.set_absize(SizeInBits::from_bits(write.absize.into()));
+
+                w.set_dmode(PhaseMode::from_bits(write.dwidth.into()));
+                w.set_ddtr(write.ddtr);
+                w.set_dqse(write.dqse);
+            });
+            T::REGS.wtcr().write(|w| w.set_dcyc(write.dummy.into()));
+            if let Some(instruction) = write.instruction {
+                T::REGS.wir().write(|v| v.set_instruction(instruction));
+            }
+            if let Some(ab) = write.alternate_bytes {
+                T::REGS.wabr().write(|v| v.set_alternate(ab));
+            }
+        }
+
+        Ok(MemoryMapped { _ospi: self })
+    }
+
     /// Set new bus configuration
     pub fn set_config(&mut self, config: &Config) {
         // Wait for busy flag to clear
@@ -1082,6 +1175,58 @@ pin_trait!(DQSPin, Instance);
 pin_trait!(NSSPin, Instance);
 dma_trait!(OctoDma, Instance);
 
+/// Start address of the memory-mapped region of OCTOSPI1.
+const OCTOSPI1_MEMORY_MAPPED_BASE: usize = 0x9000_0000;
+/// Start address of the memory-mapped region of OCTOSPI2.
+const OCTOSPI2_MEMORY_MAPPED_BASE: usize = 0x7000_0000;
+/// Size of the memory-mapped region of each OCTOSPI.
+const MEMORY_MAPPED_SIZE: usize = 0x1000_0000;
+
+/// OSPI in memory-mapped mode, see [`Ospi::memory_mapped`].
+pub struct MemoryMapped<'a, 'd, T: Instance, M: PeriMode> {
+    _ospi: &'a mut Ospi<'d, T, M>,
+}
+
+impl<'a, 'd, T: Instance, M: PeriMode> MemoryMapped<'a, 'd, T, M> {
+    /// Pointer to the start of the mapped external memory.
+    pub fn as_ptr(&self) -> *mut u8 {
+        if T::REGS.as_ptr() == crate::pac::OCTOSPI1.as_ptr() {
+            OCTOSPI1_MEMORY_MAPPED_BASE as *mut u8
+        } else {
+            OCTOSPI2_MEMORY_MAPPED_BASE as *mut u8
+        }
+    }
+
+    /// The mapped external memory, of the size set in [`Config::device_size`], limited to the
+    /// memory-mapped region.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.len()) }
+    }
+
+    /// The mapped external memory, for memories mapped with a write command.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.as_ptr(), self.len()) }
+    }
+
+    fn len(&self) -> usize {
+        let devsize = T::REGS.dcr1().read().devsize() as u32;
+        1usize
+            .checked_shl(devsize + 1)
+            .map_or(MEMORY_MAPPED_SIZE, |len| len.min(MEMORY_MAPPED_SIZE))
+    }
+}
+
+impl<'a, 'd, T: Instance, M: PeriMode> Drop for MemoryMapped<'a, 'd, T, M> {
+    fn drop(&mut self) {
+        T::REGS.cr().modify(|w| w.set_abort(true));
+        while T::REGS.cr().read().abort() {}
+        while T::REGS.sr().read().busy() {}
+        T::REGS
+            .cr()
+            .modify(|w| w.set_fmode(vals::FunctionalMode::INDIRECTWRITE));
+    }
+}
+
 foreach_peripheral!(
     (octospi, $inst:ident) => {
         impl SealedInstance for peripherals::$inst {
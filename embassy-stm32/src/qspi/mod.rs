@@ -6,15 +6,15 @@ pub mod enums;
 
 use core::marker::PhantomData;
 
-use embassy_hal_internal::{into_ref, PeripheralRef};
+use embassy_hal_internal::PeripheralRef;
 use enums::*;
 
-use crate::dma::ChannelAndRequest;
+use crate::dma::{ChannelAndRequest, Transfer};
 use crate::gpio::{AfType, AnyPin, OutputType, Pull, Speed};
 use crate::mode::{Async, Blocking, Mode as PeriMode};
 use crate::pac::quadspi::Quadspi as Regs;
-use crate::rcc::{self, RccPeripheral};
-use crate::{peripherals, Peripheral};
+use crate::rcc::RccInfo;
+use crate::Peripheral;
 
 /// QSPI transfer configuration.
 pub struct TransferConfig {
@@ -28,6 +28,12 @@ pub struct TransferConfig {
     pub instruction: u8,
     /// Flash memory address
     pub address: Option<u32>,
+    /// Alternate bytes width (ABMODE), must be `NONE` if `alternate_bytes` is `None`
+    pub abwidth: QspiWidth,
+    /// Alternate bytes size (ABSIZE)
+    pub absize: AddressSize,
+    /// Alternate bytes, sent after the address, e.g. the mode bits of fast read commands
+    pub alternate_bytes: Option<u32>,
     /// Number of dummy cycles (DCYC)
     pub dummy: DummyCycles,
 }
@@ -40,6 +46,9 @@ impl Default for TransferConfig {
             dwidth: QspiWidth::NONE,
             instruction: 0,
             address: None,
+            abwidth: QspiWidth::NONE,
+            absize: AddressSize::_8Bit,
+            alternate_bytes: None,
             dummy: DummyCycles::_0,
         }
     }
@@ -74,8 +83,8 @@ impl Default for Config {
 
 /// QSPI driver.
 #[allow(dead_code)]
-pub struct Qspi<'d, T: Instance, M: PeriMode> {
-    _peri: PeripheralRef<'d, T>,
+pub struct Qspi<'d, M: PeriMode> {
+    info: &'static Info,
     sck: Option<PeripheralRef<'d, AnyPin>>,
     d0: Option<PeripheralRef<'d, AnyPin>>,
     d1: Option<PeripheralRef<'d, AnyPin>>,
@@ -87,9 +96,9 @@ pub struct Qspi<'d, T: Instance, M: PeriMode> {
     config: Config,
 }
 
-impl<'d, T: Instance, M: PeriMode> Qspi<'d, T, M> {
-    fn new_inner(
-        peri: impl Peripheral<P = T> + 'd,
+impl<'d, M: PeriMode> Qspi<'d, M> {
+    fn new_inner<T: Instance>(
+        _peri: impl Peripheral<P = T> + 'd,
         d0: Option<PeripheralRef<'d, AnyPin>>,
         d1: Option<PeripheralRef<'d, AnyPin>>,
         d2: Option<PeripheralRef<'d, AnyPin>>,
@@ -100,26 +109,25 @@ impl<'d, T: Instance, M: PeriMode> Qspi<'d, T, M> {
         config: Config,
         fsel: FlashSelection,
     ) -> Self {
-        into_ref!(peri);
-
-        rcc::enable_and_reset::<T>();
+        let info = T::info();
+        info.rcc.enable_and_reset();
 
-        while T::REGS.sr().read().busy() {}
+        while info.regs.sr().read().busy() {}
 
         #[cfg(stm32h7)]
         {
             use stm32_metapac::quadspi::regs::Cr;
             // Apply precautionary steps according to the errata...
-            T::REGS.cr().write_value(Cr(0));
-            while T::REGS.sr().read().busy() {}
-            T::REGS.cr().write_value(Cr(0xFF000001));
-            T::REGS.ccr().write(|w| w.set_frcm(true));
-            T::REGS.ccr().write(|w| w.set_frcm(true));
-            T::REGS.cr().write_value(Cr(0));
-            while T::REGS.sr().read().busy() {}
+            info.regs.cr().write_value(Cr(0));
+            while info.regs.sr().read().busy() {}
+            info.regs.cr().write_value(Cr(0xFF000001));
+            info.regs.ccr().write(|w| w.set_frcm(true));
+            info.regs.ccr().write(|w| w.set_frcm(true));
+            info.regs.cr().write_value(Cr(0));
+            while info.regs.sr().read().busy() {}
         }
 
-        T::REGS.cr().modify(|w| {
+        info.regs.cr().modify(|w| {
             w.set_en(true);
             //w.set_tcen(false);
             w.set_sshift(false);
@@ -127,14 +135,14 @@ impl<'d, T: Instance, M: PeriMode> Qspi<'d, T, M> {
             w.set_prescaler(config.prescaler);
             w.set_fsel(fsel.into());
         });
-        T::REGS.dcr().modify(|w| {
+        info.regs.dcr().modify(|w| {
             w.set_fsize(config.memory_size.into());
             w.set_csht(config.cs_high_time.into());
             w.set_ckmode(true);
         });
 
         Self {
-            _peri: peri,
+            info,
             sck,
             d0,
             d1,
@@ -150,93 +158,126 @@ impl<'d, T: Instance, M: PeriMode> Qspi<'d, T, M> {
     /// Do a QSPI command.
     pub fn command(&mut self, transaction: TransferConfig) {
         #[cfg(not(stm32h7))]
-        T::REGS.cr().modify(|v| v.set_dmaen(false));
+        self.info.regs.cr().modify(|v| v.set_dmaen(false));
         self.setup_transaction(QspiMode::IndirectWrite, &transaction, None);
 
-        while !T::REGS.sr().read().tcf() {}
-        T::REGS.fcr().modify(|v| v.set_ctcf(true));
+        while !self.info.regs.sr().read().tcf() {}
+        self.info.regs.fcr().modify(|v| v.set_ctcf(true));
     }
 
     /// Blocking read data.
     pub fn blocking_read(&mut self, buf: &mut [u8], transaction: TransferConfig) {
         #[cfg(not(stm32h7))]
-        T::REGS.cr().modify(|v| v.set_dmaen(false));
+        self.info.regs.cr().modify(|v| v.set_dmaen(false));
         self.setup_transaction(QspiMode::IndirectWrite, &transaction, Some(buf.len()));
 
-        let current_ar = T::REGS.ar().read().address();
-        T::REGS.ccr().modify(|v| {
+        let current_ar = self.info.regs.ar().read().address();
+        self.info.regs.ccr().modify(|v| {
             v.set_fmode(QspiMode::IndirectRead.into());
         });
-        T::REGS.ar().write(|v| {
+        self.info.regs.ar().write(|v| {
             v.set_address(current_ar);
         });
 
         for b in buf {
-            while !T::REGS.sr().read().tcf() && !T::REGS.sr().read().ftf() {}
-            *b = unsafe { (T::REGS.dr().as_ptr() as *mut u8).read_volatile() };
+            while !self.info.regs.sr().read().tcf() && !self.info.regs.sr().read().ftf() {}
+            *b = unsafe { (self.info.regs.dr().as_ptr() as *mut u8).read_volatile() };
         }
 
-        while !T::REGS.sr().read().tcf() {}
-        T::REGS.fcr().modify(|v| v.set_ctcf(true));
+        while !self.info.regs.sr().read().tcf() {}
+        self.info.regs.fcr().modify(|v| v.set_ctcf(true));
     }
 
     /// Blocking write data.
     pub fn blocking_write(&mut self, buf: &[u8], transaction: TransferConfig) {
         // STM32H7 does not have dmaen
         #[cfg(not(stm32h7))]
-        T::REGS.cr().modify(|v| v.set_dmaen(false));
+        self.info.regs.cr().modify(|v| v.set_dmaen(false));
 
         self.setup_transaction(QspiMode::IndirectWrite, &transaction, Some(buf.len()));
 
-        T::REGS.ccr().modify(|v| {
+        self.info.regs.ccr().modify(|v| {
             v.set_fmode(QspiMode::IndirectWrite.into());
         });
 
         for &b in buf {
-            while !T::REGS.sr().read().ftf() {}
-            unsafe { (T::REGS.dr().as_ptr() as *mut u8).write_volatile(b) };
+            while !self.info.regs.sr().read().ftf() {}
+            unsafe { (self.info.regs.dr().as_ptr() as *mut u8).write_volatile(b) };
         }
 
-        while !T::REGS.sr().read().tcf() {}
-        T::REGS.fcr().modify(|v| v.set_ctcf(true));
+        while !self.info.regs.sr().read().tcf() {}
+        self.info.regs.fcr().modify(|v| v.set_ctcf(true));
+    }
+
+    /// Set the address size used by the following transactions.
+    ///
+    /// This is needed to switch a flash memory between 3-byte and 4-byte addressing.
+    pub fn set_address_size(&mut self, address_size: AddressSize) {
+        self.config.address_size = address_size;
+    }
+
+    /// Enter memory-mapped mode.
+    ///
+    /// The flash memory is read with `transaction`, whose address is ignored, whenever the mapped
+    /// region is accessed. Indirect transfers are impossible while the returned guard is alive;
+    /// dropping it aborts the ongoing read and returns to indirect mode.
+    pub fn memory_mapped(&mut self, transaction: TransferConfig) -> MemoryMapped<'_, 'd, M> {
+        #[cfg(not(stm32h7))]
+        self.info.regs.cr().modify(|v| v.set_dmaen(false));
+        self.setup_transaction(QspiMode::MemoryMapped, &transaction, None);
+
+        MemoryMapped { qspi: self }
     }
 
     fn setup_transaction(&mut self, fmode: QspiMode, transaction: &TransferConfig, data_len: Option<usize>) {
-        T::REGS.fcr().modify(|v| {
+        self.info.regs.fcr().modify(|v| {
             v.set_csmf(true);
             v.set_ctcf(true);
             v.set_ctef(true);
             v.set_ctof(true);
         });
 
-        while T::REGS.sr().read().busy() {}
+        while self.info.regs.sr().read().busy() {}
+
+        assert!(
+            matches!(transaction.abwidth, QspiWidth::NONE) || transaction.alternate_bytes.is_some(),
+            "alternate bytes width set without alternate bytes"
+        );
 
         if let Some(len) = data_len {
-            T::REGS.dlr().write(|v| v.set_dl(len as u32 - 1));
+            self.info.regs.dlr().write(|v| v.set_dl(len as u32 - 1));
         }
 
-        T::REGS.ccr().write(|v| {
+        // ABR must be written before CCR: the transfer may start as soon as CCR is written.
+        if let Some(alternate_bytes) = transaction.alternate_bytes {
+            self.info.regs.abr().write(|v| {
+                v.set_alternate(alternate_bytes);
+            });
+        }
+
+        self.info.regs.ccr().write(|v| {
             v.set_fmode(fmode.into());
             v.set_imode(transaction.iwidth.into());
             v.set_instruction(transaction.instruction);
             v.set_admode(transaction.awidth.into());
             v.set_adsize(self.config.address_size.into());
             v.set_dmode(transaction.dwidth.into());
-            v.set_abmode(QspiWidth::NONE.into());
+            v.set_abmode(transaction.abwidth.into());
+            v.set_absize(transaction.absize.into());
             v.set_dcyc(transaction.dummy.into());
         });
 
         if let Some(addr) = transaction.address {
-            T::REGS.ar().write(|v| {
+            self.info.regs.ar().write(|v| {
                 v.set_address(addr);
             });
         }
     }
 }
 
-impl<'d, T: Instance> Qspi<'d, T, Blocking> {
+impl<'d> Qspi<'d, Blocking> {
     /// Create a new QSPI driver for bank 1, in blocking mode.
-    pub fn new_blocking_bank1(
+    pub fn new_blocking_bank1<T: Instance>(
         peri: impl Peripheral<P = T> + 'd,
         d0: impl Peripheral<P = impl BK1D0Pin<T>> + 'd,
         d1: impl Peripheral<P = impl BK1D1Pin<T>> + 'd,
@@ -264,7 +305,7 @@ impl<'d, T: Instance> Qspi<'d, T, Blocking> {
     }
 
     /// Create a new QSPI driver for bank 2, in blocking mode.
-    pub fn new_blocking_bank2(
+    pub fn new_blocking_bank2<T: Instance>(
         peri: impl Peripheral<P = T> + 'd,
         d0: impl Peripheral<P = impl BK2D0Pin<T>> + 'd,
         d1: impl Peripheral<P = impl BK2D1Pin<T>> + 'd,
@@ -292,9 +333,9 @@ impl<'d, T: Instance> Qspi<'d, T, Blocking> {
     }
 }
 
-impl<'d, T: Instance> Qspi<'d, T, Async> {
+impl<'d> Qspi<'d, Async> {
     /// Create a new QSPI driver for bank 1.
-    pub fn new_bank1(
+    pub fn new_bank1<T: Instance>(
         peri: impl Peripheral<P = T> + 'd,
         d0: impl Peripheral<P = impl BK1D0Pin<T>> + 'd,
         d1: impl Peripheral<P = impl BK1D1Pin<T>> + 'd,
@@ -323,7 +364,7 @@ impl<'d, T: Instance> Qspi<'d, T, Async> {
     }
 
     /// Create a new QSPI driver for bank 2.
-    pub fn new_bank2(
+    pub fn new_bank2<T: Instance>(
         peri: impl Peripheral<P = T> + 'd,
         d0: impl Peripheral<P = impl BK2D0Pin<T>> + 'd,
         d1: impl Peripheral<P = impl BK2D1Pin<T>> + 'd,
@@ -353,13 +394,32 @@ impl<'d, T: Instance> Qspi<'d, T, Async> {
 
     /// Blocking read data, using DMA.
     pub fn blocking_read_dma(&mut self, buf: &mut [u8], transaction: TransferConfig) {
+        self.start_read_dma(buf, transaction).blocking_wait();
+    }
+
+    /// Read data, using DMA.
+    pub async fn read_dma(&mut self, buf: &mut [u8], transaction: TransferConfig) {
+        self.start_read_dma(buf, transaction).await;
+    }
+
+    /// Blocking write data, using DMA.
+    pub fn blocking_write_dma(&mut self, buf: &[u8], transaction: TransferConfig) {
+        self.start_write_dma(buf, transaction).blocking_wait();
+    }
+
+    /// Write data, using DMA.
+    pub async fn write_dma(&mut self, buf: &[u8], transaction: TransferConfig) {
+        self.start_write_dma(buf, transaction).await;
+    }
+
+    fn start_read_dma<'a>(&'a mut self, buf: &'a mut [u8], transaction: TransferConfig) -> Transfer<'a> {
         self.setup_transaction(QspiMode::IndirectWrite, &transaction, Some(buf.len()));
 
-        T::REGS.ccr().modify(|v| {
+        self.info.regs.ccr().modify(|v| {
             v.set_fmode(QspiMode::IndirectRead.into());
         });
-        let current_ar = T::REGS.ar().read().address();
-        T::REGS.ar().write(|v| {
+        let current_ar = self.info.regs.ar().read().address();
+        self.info.regs.ar().write(|v| {
             v.set_address(current_ar);
         });
 
@@ -367,21 +427,20 @@ impl<'d, T: Instance> Qspi<'d, T, Async> {
             self.dma
                 .as_mut()
                 .unwrap()
-                .read(T::REGS.dr().as_ptr() as *mut u8, buf, Default::default())
+                .read(self.info.regs.dr().as_ptr() as *mut u8, buf, Default::default())
         };
 
         // STM32H7 does not have dmaen
         #[cfg(not(stm32h7))]
-        T::REGS.cr().modify(|v| v.set_dmaen(true));
+        self.info.regs.cr().modify(|v| v.set_dmaen(true));
 
-        transfer.blocking_wait();
+        transfer
     }
 
-    /// Blocking write data, using DMA.
-    pub fn blocking_write_dma(&mut self, buf: &[u8], transaction: TransferConfig) {
+    fn start_write_dma<'a>(&'a mut self, buf: &'a [u8], transaction: TransferConfig) -> Transfer<'a> {
         self.setup_transaction(QspiMode::IndirectWrite, &transaction, Some(buf.len()));
 
-        T::REGS.ccr().modify(|v| {
+        self.info.regs.ccr().modify(|v| {
             v.set_fmode(QspiMode::IndirectWrite.into());
         });
 
@@ -389,24 +448,66 @@ impl<'d, T: Instance> Qspi<'d, T, Async> {
             self.dma
                 .as_mut()
                 .unwrap()
-                .write(buf, T::REGS.dr().as_ptr() as *mut u8, Default::default())
+                .write(buf, self.info.regs.dr().as_ptr() as *mut u8, Default::default())
         };
 
         // STM32H7 does not have dmaen
         #[cfg(not(stm32h7))]
-        T::REGS.cr().modify(|v| v.set_dmaen(true));
+        self.info.regs.cr().modify(|v| v.set_dmaen(true));
 
-        transfer.blocking_wait();
+        transfer
     }
 }
 
-trait SealedInstance {
-    const REGS: Regs;
+/// Start address of the memory-mapped flash region.
+const MEMORY_MAPPED_BASE: usize = 0x9000_0000;
+/// Size of the memory-mapped flash region.
+const MEMORY_MAPPED_SIZE: usize = 0x1000_0000;
+
+/// QSPI in memory-mapped mode, see [`Qspi::memory_mapped`].
+pub struct MemoryMapped<'a, 'd, M: PeriMode> {
+    qspi: &'a mut Qspi<'d, M>,
 }
 
-/// QSPI instance trait.
-#[allow(private_bounds)]
-pub trait Instance: Peripheral<P = Self> + SealedInstance + RccPeripheral {}
+impl<'a, 'd, M: PeriMode> MemoryMapped<'a, 'd, M> {
+    /// Pointer to the start of the mapped flash memory.
+    pub fn as_ptr(&self) -> *const u8 {
+        MEMORY_MAPPED_BASE as *const u8
+    }
+
+    /// The mapped flash memory, of the size set in [`Config::memory_size`], up to the 256 MB of
+    /// the mapped region.
+    pub fn as_slice(&self) -> &[u8] {
+        let fsize = self.qspi.info.regs.dcr().read().fsize() as u32;
+        let len = 1usize
+            .checked_shl(fsize + 1)
+            .map_or(MEMORY_MAPPED_SIZE, |len| len.min(MEMORY_MAPPED_SIZE));
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), len) }
+    }
+}
+
+impl<'a, 'd, M: PeriMode> Drop for MemoryMapped<'a, 'd, M> {
+    fn drop(&mut self) {
+        self.qspi.info.regs.cr().modify(|v| v.set_abort(true));
+        while self.qspi.info.regs.cr().read().abort() {}
+        while self.qspi.info.regs.sr().read().busy() {}
+    }
+}
+
+pub(crate) struct Info {
+    pub(crate) regs: Regs,
+    pub(crate) rcc: RccInfo,
+}
+
+struct State {}
+
+impl State {
+    const fn new() -> Self {
+        Self {}
+    }
+}
+
+peri_trait!();
 
 pin_trait!(SckPin, Instance);
 pin_trait!(BK1D0Pin, Instance);
@@ -425,10 +526,9 @@ dma_trait!(QuadDma, Instance);
 
 foreach_peripheral!(
     (quadspi, $inst:ident) => {
-        impl SealedInstance for peripherals::$inst {
-            const REGS: Regs = crate::pac::$inst;
-        }
-
-        impl Instance for peripherals::$inst {}
+        peri_trait_impl!($inst, Info {
+            regs: crate::pac::$inst,
+            rcc: crate::peripherals::$inst::RCC_INFO,
+        });
     };
 );
@@ -32,6 +32,8 @@ pub use crate::pac::rcc::vals::Mcosel as McoSource;
 ))]
 pub use crate::pac::rcc::vals::{Mco1sel as Mco1Source, Mco2sel as Mco2Source};
 use crate::pac::RCC;
+use crate::time::Hertz;
+use crate::timer::frequency_output::{within_tolerance, ToleranceError};
 use crate::{peripherals, Peripheral};
 
 #[cfg(any(stm32f1, rcc_f0v1, rcc_f3v1, rcc_f37))]
@@ -40,6 +42,59 @@ pub enum McoPrescaler {
     DIV1,
 }
 
+/// Prescalers and their division factors, in increasing order.
+#[cfg(any(stm32f1, rcc_f0v1, rcc_f3v1, rcc_f37))]
+const PRESCALERS: &[(McoPrescaler, u32)] = &[(McoPrescaler::DIV1, 1)];
+#[cfg(any(rcc_f2, rcc_f410, rcc_f4, rcc_f7))]
+const PRESCALERS: &[(McoPrescaler, u32)] = &[
+    (McoPrescaler::DIV1, 1),
+    (McoPrescaler::DIV2, 2),
+    (McoPrescaler::DIV3, 3),
+    (McoPrescaler::DIV4, 4),
+    (McoPrescaler::DIV5, 5),
+];
+#[cfg(any(rcc_h50, rcc_h5, rcc_h7ab, rcc_h7rm0433, rcc_h7, rcc_h7rs))]
+const PRESCALERS: &[(McoPrescaler, u32)] = &[
+    (McoPrescaler::DIV1, 1),
+    (McoPrescaler::DIV2, 2),
+    (McoPrescaler::DIV3, 3),
+    (McoPrescaler::DIV4, 4),
+    (McoPrescaler::DIV5, 5),
+    (McoPrescaler::DIV6, 6),
+    (McoPrescaler::DIV7, 7),
+    (McoPrescaler::DIV8, 8),
+    (McoPrescaler::DIV9, 9),
+    (McoPrescaler::DIV10, 10),
+    (McoPrescaler::DIV11, 11),
+    (McoPrescaler::DIV12, 12),
+    (McoPrescaler::DIV13, 13),
+    (McoPrescaler::DIV14, 14),
+    (McoPrescaler::DIV15, 15),
+];
+#[cfg(not(any(
+    stm32f1,
+    rcc_f0v1,
+    rcc_f3v1,
+    rcc_f37,
+    rcc_f2,
+    rcc_f410,
+    rcc_f4,
+    rcc_f7,
+    rcc_h50,
+    rcc_h5,
+    rcc_h7ab,
+    rcc_h7rm0433,
+    rcc_h7,
+    rcc_h7rs
+)))]
+const PRESCALERS: &[(McoPrescaler, u32)] = &[
+    (McoPrescaler::DIV1, 1),
+    (McoPrescaler::DIV2, 2),
+    (McoPrescaler::DIV4, 4),
+    (McoPrescaler::DIV8, 8),
+    (McoPrescaler::DIV16, 16),
+];
+
 pub(crate) trait SealedMcoInstance {}
 
 #[allow(private_bounds)]
@@ -106,4 +161,30 @@ impl<'d, T: McoInstance> Mco<'d, T> {
 
         Self { phantom: PhantomData }
     }
+
+    /// Create a new MCO instance outputting the frequency closest to `freq`.
+    ///
+    /// `source_freq` is the frequency of `source`. The prescaler is picked to get within
+    /// `tolerance_ppm` of `freq`; if that's not possible the output is not enabled and the closest
+    /// achievable frequency is returned in the error. Returns the achieved frequency.
+    pub fn new_frequency(
+        peri: impl Peripheral<P = T> + 'd,
+        pin: impl Peripheral<P = impl McoPin<T>> + 'd,
+        source: T::Source,
+        source_freq: Hertz,
+        freq: Hertz,
+        tolerance_ppm: u32,
+    ) -> Result<(Self, Hertz), ToleranceError> {
+        assert!(freq.0 > 0);
+
+        let error = |div: u32| (source_freq.0 as u64).abs_diff(freq.0 as u64 * div as u64) / div as u64;
+        let (prescaler, div) = *unwrap!(PRESCALERS.iter().min_by_key(|(_, div)| error(*div)));
+        let achieved = Hertz(((source_freq.0 as u64 + div as u64 / 2) / div as u64) as u32);
+
+        if !within_tolerance(source_freq.0 as u64, div as u64, freq.0, tolerance_ppm) {
+            return Err(ToleranceError { closest: achieved });
+        }
+
+        Ok((Self::new(peri, pin, source, prescaler), achieved))
+    }
 }
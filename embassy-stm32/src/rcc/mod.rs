@@ -135,7 +135,17 @@ impl RccInfo {
     }
 
     // TODO: should this be `unsafe`?
-    pub(crate) fn enable_and_reset_with_cs(&self, _cs: CriticalSection) {
+    pub(crate) fn enable_and_reset_with_cs(&self, cs: CriticalSection) {
+        self.enable_with_cs_inner(cs, true)
+    }
+
+    /// Enable the peripheral without resetting it, for peripherals another core may already be using.
+    #[allow(unused)]
+    pub(crate) fn enable_with_cs(&self, cs: CriticalSection) {
+        self.enable_with_cs_inner(cs, false)
+    }
+
+    fn enable_with_cs_inner(&self, _cs: CriticalSection, reset: bool) {
         if self.refcount_idx_or_0xff != 0xff {
             let refcount_idx = self.refcount_idx_or_0xff as usize;
             unsafe {
@@ -158,7 +168,7 @@ impl RccInfo {
         }
 
         // set the xxxRST bit
-        let reset_ptr = self.reset_ptr();
+        let reset_ptr = if reset { self.reset_ptr() } else { None };
         if let Some(reset_ptr) = reset_ptr {
             unsafe {
                 let val = reset_ptr.read_volatile();
@@ -295,6 +305,12 @@ pub fn enable_and_reset_with_cs<T: RccPeripheral>(cs: CriticalSection) {
     T::RCC_INFO.enable_and_reset_with_cs(cs);
 }
 
+/// Enables peripheral `T` without resetting it.
+#[allow(unused)]
+pub(crate) fn enable_with_cs<T: RccPeripheral>(cs: CriticalSection) {
+    T::RCC_INFO.enable_with_cs(cs);
+}
+
 /// Disables peripheral `T`.
 ///
 /// # Safety
@@ -222,6 +222,64 @@ impl<'d, T: Instance> RngCore for Rng<'d, T> {
 
 impl<'d, T: Instance> CryptoRng for Rng<'d, T> {}
 
+/// Return a random duration between zero and `max`, both included.
+#[cfg(feature = "time")]
+pub fn jitter(rng: &mut impl RngCore, max: embassy_time::Duration) -> embassy_time::Duration {
+    let ticks = max.as_ticks();
+    if ticks == 0 {
+        return max;
+    }
+    embassy_time::Duration::from_ticks(rng.next_u64() % (ticks + 1))
+}
+
+/// Exponential backoff with random jitter, for retrying network operations.
+///
+/// The delay ceiling starts at `base` and doubles on every attempt up to `max`. Each delay is
+/// picked uniformly between zero and the ceiling ("full jitter"), so devices that fail at the
+/// same time, e.g. after a broker restart, don't all retry in lockstep.
+#[cfg(feature = "time")]
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: embassy_time::Duration,
+    max: embassy_time::Duration,
+    attempt: u32,
+}
+
+#[cfg(feature = "time")]
+impl Backoff {
+    /// Create a new backoff with the given initial and maximum delay ceilings.
+    pub const fn new(base: embassy_time::Duration, max: embassy_time::Duration) -> Self {
+        Self { base, max, attempt: 0 }
+    }
+
+    /// Number of delays handed out since creation or the last [`reset`](Self::reset).
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Start over from the initial delay, typically after a successful attempt.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Return the delay to wait before the next attempt.
+    pub fn next_delay(&mut self, rng: &mut impl RngCore) -> embassy_time::Duration {
+        let ceiling = self
+            .base
+            .as_ticks()
+            .checked_shl(self.attempt)
+            .filter(|&t| t >> self.attempt == self.base.as_ticks())
+            .map_or(self.max, |t| embassy_time::Duration::from_ticks(t).min(self.max));
+        self.attempt = self.attempt.saturating_add(1);
+        jitter(rng, ceiling)
+    }
+
+    /// Wait for the delay before the next attempt.
+    pub async fn wait(&mut self, rng: &mut impl RngCore) {
+        embassy_time::Timer::after(self.next_delay(rng)).await
+    }
+}
+
 trait SealedInstance {
     fn regs() -> pac::rng::Rng;
 }
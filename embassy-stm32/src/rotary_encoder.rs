@@ -0,0 +1,171 @@
+//! Rotary encoder with a pushbutton, as found on the knobs of UI-heavy devices.
+//!
+//! [`RotaryEncoder`] decodes the two quadrature signals of the encoder and debounces its button,
+//! and reports both as a single stream of [`Event`]s: a turn by one detent in either direction,
+//! a short press, or a long press.
+//!
+//! The quadrature signals are decoded from EXTI edges rather than with a timer in encoder mode
+//! ([`Qei`](crate::timer::qei::Qei)), which has no interrupt to wait for: waiting for an event
+//! doesn't need polling. Missed edges, e.g. from contact bounce, are skipped by the decoder, as a
+//! transition changing both signals is invalid.
+
+use embassy_futures::select::{select, select3, Either3};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::exti::ExtiInput;
+
+/// Encoder event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// Turned clockwise by one detent.
+    Clockwise,
+    /// Turned counter-clockwise by one detent.
+    CounterClockwise,
+    /// The button was pressed and released before [`Config::long_press`].
+    Press,
+    /// The button has been held for [`Config::long_press`]. Releasing it then gives no event.
+    LongPress,
+}
+
+/// Encoder configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Config {
+    /// Number of quadrature steps from one detent to the next: 4 for most encoders, 2 or 1 for
+    /// encoders with a detent at each half or quarter cycle.
+    pub steps_per_detent: u8,
+    /// Time the button level must be stable to be taken into account.
+    pub debounce: Duration,
+    /// Time the button must be held for a long press.
+    pub long_press: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            steps_per_detent: 4,
+            debounce: Duration::from_millis(10),
+            long_press: Duration::from_millis(800),
+        }
+    }
+}
+
+/// Quadrature step for each transition, indexed by `previous << 2 | current` with the state as
+/// `a << 1 | b`: 1 clockwise, -1 counter-clockwise, 0 for no change or an invalid transition.
+const STEPS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Rotary encoder with a pushbutton, see the [module documentation](self).
+pub struct RotaryEncoder<'d> {
+    a: ExtiInput<'d>,
+    b: ExtiInput<'d>,
+    button: ExtiInput<'d>,
+    config: Config,
+    state: u8,
+    steps: i8,
+    /// Time the button was pressed, if it is and no long press was reported yet.
+    pressed_at: Option<Instant>,
+    pressed: bool,
+}
+
+impl<'d> RotaryEncoder<'d> {
+    /// Create an encoder driver from its A and B signals, and its button.
+    ///
+    /// The button is taken as pressed when low, so its pin is usually pulled up. Swap `a` and `b`
+    /// to swap the directions.
+    pub fn new(a: ExtiInput<'d>, b: ExtiInput<'d>, button: ExtiInput<'d>, config: Config) -> Self {
+        assert!(config.steps_per_detent >= 1 && config.steps_per_detent <= 4);
+
+        let state = (a.is_high() as u8) << 1 | b.is_high() as u8;
+        let pressed = button.is_low();
+        Self {
+            a,
+            b,
+            button,
+            config,
+            state,
+            steps: 0,
+            pressed_at: None,
+            pressed,
+        }
+    }
+
+    /// Wait for the next event.
+    pub async fn next(&mut self) -> Event {
+        loop {
+            let long_press_at = self.pressed_at.map(|t| t + self.config.long_press);
+            let (a, b, button) = (&mut self.a, &mut self.b, &mut self.button);
+
+            let encoder = select(a.wait_for_any_edge(), b.wait_for_any_edge());
+            let long_press = async {
+                match long_press_at {
+                    Some(at) => Timer::at(at).await,
+                    None => core::future::pending().await,
+                }
+            };
+
+            match select3(encoder, button.wait_for_any_edge(), long_press).await {
+                Either3::First(_) => {
+                    if let Some(event) = self.decode() {
+                        return event;
+                    }
+                }
+                Either3::Second(_) => {
+                    Timer::after(self.config.debounce).await;
+                    if let Some(event) = self.update_button() {
+                        return event;
+                    }
+                }
+                Either3::Third(_) => {
+                    self.pressed_at = None;
+                    return Event::LongPress;
+                }
+            }
+        }
+    }
+
+    /// Update the quadrature state from the signal levels, returning an event once a detent is
+    /// reached.
+    fn decode(&mut self) -> Option<Event> {
+        let state = (self.a.is_high() as u8) << 1 | self.b.is_high() as u8;
+        self.steps += STEPS[(self.state << 2 | state) as usize];
+        self.state = state;
+
+        let detent = self.config.steps_per_detent as i8;
+        if self.steps >= detent {
+            self.steps = 0;
+            Some(Event::Clockwise)
+        } else if self.steps <= -detent {
+            self.steps = 0;
+            Some(Event::CounterClockwise)
+        } else {
+            None
+        }
+    }
+
+    /// Update the button state from its debounced level, returning an event on a short press.
+    fn update_button(&mut self) -> Option<Event> {
+        let pressed = self.button.is_low();
+        if pressed == self.pressed {
+            return None;
+        }
+        self.pressed = pressed;
+
+        if pressed {
+            self.pressed_at = Some(Instant::now());
+            None
+        } else {
+            // Reported already if it was a long press.
+            self.pressed_at.take().map(|_| Event::Press)
+        }
+    }
+}
+
+impl<'d> embassy_futures::stream::Stream for RotaryEncoder<'d> {
+    type Item = Event;
+
+    async fn next(&mut self) -> Event {
+        RotaryEncoder::next(self).await
+    }
+}
@@ -1,9 +1,54 @@
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embassy_sync::waitqueue::AtomicWaker;
 use stm32_metapac::rtc::vals::{Osel, Pol};
 
-use super::SealedInstance;
+use super::{byte_to_bcd2, DateTime, RtcError, SealedInstance};
+use crate::interrupt;
+use crate::interrupt::typelevel::{Binding, Interrupt as _};
 use crate::pac::rtc::Rtc;
 use crate::peripherals::RTC;
 
+/// Interrupt of alarm A.
+#[cfg(any(stm32f0, stm32l0))]
+pub type AlarmInterrupt = crate::interrupt::typelevel::RTC;
+/// Interrupt of alarm A.
+#[cfg(not(any(stm32f0, stm32l0)))]
+pub type AlarmInterrupt = crate::interrupt::typelevel::RTC_ALARM;
+
+/// EXTI line of the RTC alarms, which wakes the chip from Stop.
+#[cfg(stm32l4)]
+const ALARM_EXTI_LINE: usize = 18;
+#[cfg(not(stm32l4))]
+const ALARM_EXTI_LINE: usize = 17;
+
+static ALARM_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Alarm A interrupt handler, for [`Rtc::wait_until`](super::Rtc::wait_until).
+pub struct AlarmInterruptHandler {
+    _private: (),
+}
+
+impl interrupt::typelevel::Handler<AlarmInterrupt> for AlarmInterruptHandler {
+    unsafe fn on_interrupt() {
+        // The interrupt comes from the rising edge of the EXTI line, ALRF stays set until the
+        // alarm is armed again or disabled.
+        crate::pac::EXTI.pr(0).write(|w| w.set_line(ALARM_EXTI_LINE, true));
+        ALARM_WAKER.wake();
+    }
+}
+
+#[cfg(exti_w)]
+fn exti_cpu_regs() -> crate::pac::exti::Cpu {
+    crate::pac::EXTI.cpu(crate::pac::CORE_INDEX)
+}
+
+#[cfg(not(exti_w))]
+fn exti_cpu_regs() -> crate::pac::exti::Exti {
+    crate::pac::EXTI
+}
+
 #[allow(dead_code)]
 impl super::Rtc {
     /// Applies the RTC config
@@ -93,6 +138,111 @@ impl super::Rtc {
         })
     }
 
+    /// Arm alarm A to fire at `at`.
+    ///
+    /// The alarm matches the day of month, hour, minute and second of `at`, so it must be less
+    /// than a month away. It wakes the chip from Stop, through its EXTI line, and from Standby;
+    /// after a wakeup from Standby, check [`alarm_fired`](Self::alarm_fired) to find out whether
+    /// the alarm was the cause.
+    ///
+    /// This is only available on RTC v2 chips.
+    pub fn set_alarm(&mut self, at: &DateTime) {
+        // Compare all fields (MSKx = 0) and match the day of month (WDSEL = 0).
+        let alrmr = (byte_to_bcd2(at.day()).1 as u32) << 24
+            | (byte_to_bcd2(at.hour()).1 as u32) << 16
+            | (byte_to_bcd2(at.minute()).1 as u32) << 8
+            | byte_to_bcd2(at.second()).1 as u32;
+
+        self.write(false, |r| {
+            r.cr().modify(|w| {
+                w.set_alrie(0, false);
+                w.set_alre(0, false);
+            });
+            while !r.isr().read().alrwf(0) {}
+
+            r.alrmr(0).write_value(crate::pac::rtc::regs::Alrmr(alrmr));
+            r.isr().modify(|w| w.set_alrf(0, false));
+
+            r.cr().modify(|w| {
+                w.set_alrie(0, true);
+                w.set_alre(0, true);
+            });
+        });
+
+        critical_section::with(|_| {
+            crate::pac::EXTI.rtsr(0).modify(|w| w.set_line(ALARM_EXTI_LINE, true));
+            exti_cpu_regs().imr(0).modify(|w| w.set_line(ALARM_EXTI_LINE, true));
+        });
+    }
+
+    /// Wait until the RTC reaches `at`, with alarm A.
+    ///
+    /// This returns immediately if `at` is in the past. Nothing else runs on the RTC, so with the
+    /// low-power executor the chip stays in Stop mode until the alarm. Dates more than a month
+    /// away are reached by re-arming the alarm each time it fires early.
+    ///
+    /// Standby mode resets the chip and the waiting task with it: to wake from Standby at `at`,
+    /// arm the alarm with [`set_alarm`](Self::set_alarm), and check
+    /// [`alarm_fired`](Self::alarm_fired) at startup.
+    ///
+    /// This is only available on RTC v2 chips.
+    pub async fn wait_until(
+        &mut self,
+        at: &DateTime,
+        _irq: impl Binding<AlarmInterrupt, AlarmInterruptHandler>,
+    ) -> Result<(), RtcError> {
+        AlarmInterrupt::unpend();
+        unsafe { AlarmInterrupt::enable() };
+
+        while !reached(&self.now()?, at) {
+            self.set_alarm(at);
+            poll_fn(|cx| {
+                ALARM_WAKER.register(cx.waker());
+                if self.alarm_fired() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await;
+        }
+
+        self.disable_alarm();
+        Ok(())
+    }
+
+    /// Run `f` at `at`.
+    ///
+    /// This waits with [`wait_until`](Self::wait_until), then calls `f` and awaits its result.
+    /// Call this in a loop with the next due time for periodic jobs, such as daily reports.
+    pub async fn schedule_at<F: core::future::Future>(
+        &mut self,
+        at: &DateTime,
+        irq: impl Binding<AlarmInterrupt, AlarmInterruptHandler>,
+        f: impl FnOnce() -> F,
+    ) -> Result<F::Output, RtcError> {
+        self.wait_until(at, irq).await?;
+        Ok(f().await)
+    }
+
+    /// Disarm alarm A, and clear its flag.
+    pub fn disable_alarm(&mut self) {
+        self.write(false, |r| {
+            r.cr().modify(|w| {
+                w.set_alrie(0, false);
+                w.set_alre(0, false);
+            });
+            r.isr().modify(|w| w.set_alrf(0, false));
+        });
+    }
+
+    /// Whether alarm A has fired since it was armed.
+    ///
+    /// The flag is kept in the backup domain, so it survives Standby and resets.
+    pub fn alarm_fired(&self) -> bool {
+        RTC::regs().isr().read().alrf(0)
+    }
+
     pub(super) fn write<F, R>(&self, init_mode: bool, f: F) -> R
     where
         F: FnOnce(crate::pac::rtc::Rtc) -> R,
@@ -154,3 +304,9 @@ impl SealedInstance for crate::peripherals::RTC {
         }
     }
 }
+
+/// Whether `now` is at or after `at`.
+fn reached(now: &DateTime, at: &DateTime) -> bool {
+    let fields = |t: &DateTime| (t.year(), t.month(), t.day(), t.hour(), t.minute(), t.second());
+    fields(now) >= fields(at)
+}
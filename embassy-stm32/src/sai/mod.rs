@@ -950,6 +950,9 @@ impl<'d, T: Instance, W: word::Word> Sai<'d, T, W> {
     }
 
     /// Start the SAI driver.
+    ///
+    /// A transmitter sends what its buffer holds right away, so fill it first with
+    /// [`write_immediate`](Self::write_immediate).
     pub fn start(&mut self) {
         match self.ring_buffer {
             RingBuffer::Writable(ref mut rb) => {
@@ -959,6 +962,31 @@ impl<'d, T: Instance, W: word::Word> Sai<'d, T, W> {
                 rb.start();
             }
         }
+
+        // The sub-block is enabled when created, but not after `stop`.
+        let ch = T::REGS.ch(self.sub_block as usize);
+        ch.cr1().modify(|w| w.set_saien(true));
+    }
+
+    /// Stop the SAI driver, once the DMA reaches the end of the current half of the buffer.
+    ///
+    /// The buffer is cleared, so the driver can be started again with [`start`](Self::start).
+    pub async fn stop(&mut self) {
+        match self.ring_buffer {
+            RingBuffer::Writable(ref mut rb) => {
+                rb.stop().await;
+                rb.clear();
+            }
+            RingBuffer::Readable(ref mut rb) => {
+                rb.stop().await;
+                rb.clear();
+            }
+        }
+
+        let ch = T::REGS.ch(self.sub_block as usize);
+        ch.cr1().modify(|w| w.set_saien(false));
+        while ch.cr1().read().saien() {}
+        ch.cr2().modify(|w| w.set_fflush(true));
     }
 
     fn is_transmitter(ring_buffer: &RingBuffer<W>) -> bool {
@@ -1003,6 +1031,16 @@ impl<'d, T: Instance, W: word::Word> Sai<'d, T, W> {
         }
     }
 
+    /// Write data to the SAI ringbuffer without waiting, returning the number of samples written.
+    ///
+    /// This is for filling the buffer, for example with silence, before [`start`](Self::start).
+    pub fn write_immediate(&mut self, data: &[W]) -> Result<usize, Error> {
+        match &mut self.ring_buffer {
+            RingBuffer::Writable(buffer) => Ok(buffer.write_immediate(data)?.0),
+            _ => Err(Error::NotATransmitter),
+        }
+    }
+
     /// Read data from the SAI ringbuffer.
     ///
     /// SAI is always receiving data in the background. This function pops already-received
@@ -1020,6 +1058,57 @@ impl<'d, T: Instance, W: word::Word> Sai<'d, T, W> {
     }
 }
 
+#[cfg(not(gpdma))]
+impl From<Error> for crate::audio::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::NotATransmitter => Self::NotATransmitter,
+            Error::NotAReceiver => Self::NotAReceiver,
+            Error::Overrun => Self::Overrun,
+        }
+    }
+}
+
+#[cfg(not(gpdma))]
+impl<'d, T: Instance, W: word::Word> crate::audio::AudioSource<W> for Sai<'d, T, W> {
+    async fn read(&mut self, data: &mut [W]) -> Result<(), crate::audio::Error> {
+        Ok(Sai::read(self, data).await?)
+    }
+}
+
+macro_rules! impl_audio_sink {
+    ($w:ty, $sample:ident => $encode:expr) => {
+        #[cfg(not(gpdma))]
+        impl<'d, T: Instance> crate::audio::AudioSink<$w> for Sai<'d, T, $w> {
+            fn encode($sample: i16) -> $w {
+                $encode
+            }
+
+            async fn write(&mut self, data: &[$w]) -> Result<(), crate::audio::Error> {
+                Ok(Sai::write(self, data).await?)
+            }
+
+            fn capacity(&self) -> usize {
+                match &self.ring_buffer {
+                    RingBuffer::Writable(buffer) => buffer.capacity(),
+                    RingBuffer::Readable(_) => 0,
+                }
+            }
+
+            fn position(&self) -> usize {
+                match &self.ring_buffer {
+                    RingBuffer::Writable(buffer) => buffer.get_position(),
+                    RingBuffer::Readable(_) => 0,
+                }
+            }
+        }
+    };
+}
+
+impl_audio_sink!(u16, sample => sample as u16);
+// Left aligned in the 32 bit slot.
+impl_audio_sink!(u32, sample => ((sample as i32) << 16) as u32);
+
 impl<'d, T: Instance, W: word::Word> Drop for Sai<'d, T, W> {
     fn drop(&mut self) {
         let ch = T::REGS.ch(self.sub_block as usize);
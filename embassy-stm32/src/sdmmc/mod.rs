@@ -4,7 +4,6 @@
 use core::default::Default;
 use core::future::poll_fn;
 use core::marker::PhantomData;
-use core::ops::{Deref, DerefMut};
 use core::task::Poll;
 
 use embassy_hal_internal::drop::OnDrop;
@@ -72,27 +71,9 @@ impl Default for Signalling {
     }
 }
 
-/// Aligned data block for SDMMC transfers.
-///
-/// This is a 512-byte array, aligned to 4 bytes to satisfy DMA requirements.
-#[repr(align(4))]
-#[derive(Debug, Clone, PartialEq, Eq)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct DataBlock(pub [u8; 512]);
-
-impl Deref for DataBlock {
-    type Target = [u8; 512];
-
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
-
-impl DerefMut for DataBlock {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
-    }
-}
+/// Aligned data block for SDMMC transfers, shared with the other
+/// [`BlockDevice`](embassy_embedded_hal::block_device::BlockDevice) drivers.
+pub use embassy_embedded_hal::block_device::Block as DataBlock;
 
 /// Errors
 #[non_exhaustive]
@@ -1293,6 +1274,22 @@ impl<'d, T: Instance, Dma: SdmmcDma<T> + 'd> Sdmmc<'d, T, Dma> {
     }
 }
 
+impl<'d, T: Instance, Dma: SdmmcDma<T> + 'd> embassy_embedded_hal::block_device::BlockDevice for Sdmmc<'d, T, Dma> {
+    type Error = Error;
+
+    async fn read_block(&mut self, block_idx: u32, buffer: &mut DataBlock) -> Result<(), Error> {
+        self.read_block(block_idx, buffer).await
+    }
+
+    async fn write_block(&mut self, block_idx: u32, buffer: &DataBlock) -> Result<(), Error> {
+        self.write_block(block_idx, buffer).await
+    }
+
+    fn block_count(&self) -> Result<u32, Error> {
+        Ok(self.card()?.csd.block_count() as u32)
+    }
+}
+
 impl<'d, T: Instance, Dma: SdmmcDma<T> + 'd> Drop for Sdmmc<'d, T, Dma> {
     fn drop(&mut self) {
         T::Interrupt::disable();
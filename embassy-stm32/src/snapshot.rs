@@ -0,0 +1,107 @@
+//! Peripheral register snapshots for postmortem debugging.
+//!
+//! [`Snapshot::capture`] reads the registers that are most useful to understand a "DMA hung"
+//! or "UART stopped receiving" report from the field: the state of every DMA channel, the status
+//! of every USART, and the reset flags. It only reads registers and doesn't take any locks, so it
+//! can be called from a panic or fault handler.
+//!
+//! To keep a snapshot across the reset that follows a panic, store it in a `static` placed in a
+//! RAM section that isn't zeroed at startup (e.g. `#[link_section = ".uninit"]`), together with
+//! a marker value to check whether it is valid.
+
+/// State of a DMA channel.
+#[cfg(any(dma, bdma))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct DmaChannelSnapshot {
+    /// Raw value of the channel configuration register (CR).
+    pub cr: u32,
+    /// Raw value of the remaining transfer count register (NDTR).
+    pub ndtr: u32,
+}
+
+/// Status of a USART.
+#[cfg(usart)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct UsartSnapshot {
+    /// Raw value of the control register 1 (CR1).
+    pub cr1: u32,
+    /// Raw value of the status register (SR on usart v1/v2, ISR on later versions).
+    pub sr: u32,
+}
+
+#[cfg(any(dma, bdma))]
+const DMA_CHANNEL_COUNT: usize = crate::_generated::DMA_CHANNELS.len();
+
+#[cfg(usart)]
+const USART_COUNT: usize = {
+    let mut n = 0;
+    foreach_peripheral!(
+        (usart, $inst:ident) => {
+            n += 1;
+        };
+    );
+    n
+};
+
+/// Snapshot of key peripheral registers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct Snapshot {
+    /// Raw value of the RCC register holding the reset flags (CSR, or RSR on H5/H7).
+    pub reset_flags: u32,
+    /// State of each DMA channel, in the order of the generated channel table (DMA1 channels first).
+    #[cfg(any(dma, bdma))]
+    pub dma: [DmaChannelSnapshot; DMA_CHANNEL_COUNT],
+    /// Status of each USART, UART and LPUART, in the order they are declared by the chip metadata.
+    #[cfg(usart)]
+    pub usart: [UsartSnapshot; USART_COUNT],
+}
+
+impl Snapshot {
+    /// Capture the current register values.
+    pub fn capture() -> Self {
+        Self {
+            reset_flags: reset_flags(),
+            #[cfg(any(dma, bdma))]
+            dma: core::array::from_fn(|id| {
+                let (cr, ndtr) = crate::dma::AnyChannel { id: id as u8 }.raw_regs();
+                DmaChannelSnapshot { cr, ndtr }
+            }),
+            #[cfg(usart)]
+            usart: capture_usarts(),
+        }
+    }
+}
+
+fn reset_flags() -> u32 {
+    #[cfg(any(stm32h5, stm32h7, stm32h7rs))]
+    return crate::pac::RCC.rsr().read().0;
+    #[cfg(stm32c0)]
+    return crate::pac::RCC.csr2().read().0;
+    #[cfg(not(any(stm32h5, stm32h7, stm32h7rs, stm32c0)))]
+    return crate::pac::RCC.csr().read().0;
+}
+
+#[cfg(usart)]
+fn capture_usarts() -> [UsartSnapshot; USART_COUNT] {
+    let mut usart = [UsartSnapshot { cr1: 0, sr: 0 }; USART_COUNT];
+    let mut i = 0;
+    foreach_peripheral!(
+        (usart, $inst:ident) => {
+            let r = unsafe { crate::usart::Regs::from_ptr(crate::pac::$inst.as_ptr()) };
+            // Note: on usart v1/v2, reading SR is the first half of the sequence clearing the
+            // error and idle flags, which only completes with a read of DR. This never reads DR.
+            usart[i] = UsartSnapshot {
+                cr1: r.cr1().read().0,
+                sr: crate::usart::sr(r).read().0,
+            };
+            i += 1;
+        };
+    );
+    usart
+}
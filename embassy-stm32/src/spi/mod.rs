@@ -29,6 +29,24 @@ pub enum Error {
     ModeFault,
     /// Overrun.
     Overrun,
+    /// A DMA transfer did not complete within `Config::timeout`, which requires the `time` feature.
+    Timeout,
+}
+
+/// SPI config error.
+///
+/// Returned by [`Spi::set_config`] when the frequency can't be produced from the kernel clock. The
+/// constructors instead run too low frequencies at the kernel clock divided by 256, and panic on
+/// too high ones.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// The frequency is above the kernel clock. Frequencies from half the kernel clock up to the
+    /// kernel clock run at half the kernel clock.
+    FrequencyTooHigh,
+    /// The frequency is below the kernel clock divided by 384: dividing it by 256, the largest
+    /// prescaler, would run more than 1.5 times too fast.
+    FrequencyTooLow,
 }
 
 /// SPI bit order
@@ -40,6 +58,21 @@ pub enum BitOrder {
     MsbFirst,
 }
 
+/// SPI frame format.
+#[cfg(not(spi_f1))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameFormat {
+    /// Motorola SPI, the usual format.
+    Motorola,
+    /// TI synchronous serial frame format.
+    ///
+    /// The NSS pin pulses for one clock cycle before each word. The clock polarity and phase of
+    /// [`Config::mode`] are ignored, and NSS is driven by hardware, so this requires one of the
+    /// `_with_nss` constructors.
+    Ti,
+}
+
 /// SPI configuration.
 #[non_exhaustive]
 #[derive(Copy, Clone)]
@@ -55,6 +88,19 @@ pub struct Config {
     /// There are some ICs that require a pull-up on the MISO pin for some applications.
     /// If you  are unsure, you probably don't need this.
     pub miso_pull: Pull,
+    /// Timeout for async (DMA) transfers.
+    ///
+    /// If set, async transfers that don't complete in time are aborted and return [`Error::Timeout`]
+    /// instead of waiting forever, e.g. when the SPI clock stops.
+    #[cfg(feature = "time")]
+    pub timeout: Option<embassy_time::Duration>,
+    /// Pulse the hardware NSS output between words (NSSP), for devices latching each word on the
+    /// rising edge of NSS. Only used by the `_with_nss` constructors.
+    #[cfg(not(any(spi_v1, spi_f1)))]
+    pub nss_pulse: bool,
+    /// Frame format.
+    #[cfg(not(spi_f1))]
+    pub frame_format: FrameFormat,
 }
 
 impl Default for Config {
@@ -64,6 +110,12 @@ impl Default for Config {
             bit_order: BitOrder::MsbFirst,
             frequency: Hertz(1_000_000),
             miso_pull: Pull::None,
+            #[cfg(feature = "time")]
+            timeout: None,
+            #[cfg(not(any(spi_v1, spi_f1)))]
+            nss_pulse: false,
+            #[cfg(not(spi_f1))]
+            frame_format: FrameFormat::Motorola,
         }
     }
 }
@@ -90,6 +142,22 @@ impl Config {
         }
     }
 
+    #[cfg(any(spi_v1, spi_v2))]
+    fn raw_frame_format(&self) -> vals::Frf {
+        match self.frame_format {
+            FrameFormat::Motorola => vals::Frf::MOTOROLA,
+            FrameFormat::Ti => vals::Frf::TI,
+        }
+    }
+
+    #[cfg(any(spi_v3, spi_v4, spi_v5))]
+    fn raw_frame_format(&self) -> vals::Sp {
+        match self.frame_format {
+            FrameFormat::Motorola => vals::Sp::MOTOROLA,
+            FrameFormat::Ti => vals::Sp::TI,
+        }
+    }
+
     #[cfg(gpio_v1)]
     fn sck_af(&self) -> AfType {
         AfType::output(OutputType::PushPull, Speed::VeryHigh)
@@ -108,16 +176,24 @@ impl Config {
     }
 }
 /// SPI driver.
+///
+/// The transfer methods are generic over the word type, which sets the data frame size: `u8` or
+/// `u16` on all SPI versions, any of 4 to 16 bits on SPIv2 and 4 to 32 bits on SPIv3 and later
+/// using the [`word`](crate::dma::word) types. Words are moved by DMA at their own size.
 pub struct Spi<'d, M: PeriMode> {
     pub(crate) info: &'static Info,
     kernel_clock: Hertz,
     sck: Option<PeripheralRef<'d, AnyPin>>,
     mosi: Option<PeripheralRef<'d, AnyPin>>,
     miso: Option<PeripheralRef<'d, AnyPin>>,
-    tx_dma: Option<ChannelAndRequest<'d>>,
-    rx_dma: Option<ChannelAndRequest<'d>>,
+    nss: Option<PeripheralRef<'d, AnyPin>>,
+    pub(crate) tx_dma: Option<ChannelAndRequest<'d>>,
+    pub(crate) rx_dma: Option<ChannelAndRequest<'d>>,
     _phantom: PhantomData<M>,
     current_word_size: word_impl::Config,
+    #[cfg(feature = "time")]
+    timeout: Option<embassy_time::Duration>,
+    half_duplex: bool,
 }
 
 impl<'d, M: PeriMode> Spi<'d, M> {
@@ -136,17 +212,26 @@ impl<'d, M: PeriMode> Spi<'d, M> {
             sck,
             mosi,
             miso,
+            nss: None,
             tx_dma,
             rx_dma,
             current_word_size: <u8 as SealedWord>::CONFIG,
             _phantom: PhantomData,
+            #[cfg(feature = "time")]
+            timeout: config.timeout,
+            half_duplex: false,
         };
         this.enable_and_init(config);
         this
     }
 
     fn enable_and_init(&mut self, config: Config) {
-        let br = compute_baud_rate(self.kernel_clock, config.frequency);
+        // Constructors keep clamping too low frequencies to the largest prescaler.
+        let br = match compute_baud_rate(self.kernel_clock, config.frequency) {
+            Ok(br) => br,
+            Err(ConfigError::FrequencyTooLow) => Br::from_bits(0b111),
+            Err(ConfigError::FrequencyTooHigh) => panic!("You are trying to reach a frequency higher than the clock"),
+        };
         let cpha = config.raw_phase();
         let cpol = config.raw_polarity();
         let lsbfirst = config.raw_byte_order();
@@ -158,6 +243,8 @@ impl<'d, M: PeriMode> Spi<'d, M> {
         {
             regs.cr2().modify(|w| {
                 w.set_ssoe(false);
+                #[cfg(spi_v1)]
+                w.set_frf(config.raw_frame_format());
             });
             regs.cr1().modify(|w| {
                 w.set_cpha(cpha);
@@ -185,6 +272,7 @@ impl<'d, M: PeriMode> Spi<'d, M> {
                 w.set_frxth(frxth);
                 w.set_ds(ds);
                 w.set_ssoe(false);
+                w.set_frf(config.raw_frame_format());
             });
             regs.cr1().modify(|w| {
                 w.set_cpha(cpha);
@@ -217,6 +305,7 @@ impl<'d, M: PeriMode> Spi<'d, M> {
                 w.set_mssi(0);
                 w.set_afcntr(true);
                 w.set_ssiop(vals::Ssiop::ACTIVEHIGH);
+                w.set_sp(config.raw_frame_format());
             });
             regs.cfg1().modify(|w| {
                 w.set_crcen(false);
@@ -234,22 +323,164 @@ impl<'d, M: PeriMode> Spi<'d, M> {
         }
     }
 
+    /// Switch from software slave management to driving the NSS pin from the peripheral.
+    #[allow(unused_variables)]
+    fn enable_hardware_nss(&mut self, nss: Option<PeripheralRef<'d, AnyPin>>, config: &Config) {
+        self.nss = nss;
+
+        let regs = self.info.regs;
+        regs.cr1().modify(|w| w.set_spe(false));
+        #[cfg(any(spi_v1, spi_f1, spi_v2))]
+        {
+            regs.cr1().modify(|w| w.set_ssm(false));
+            regs.cr2().modify(|w| {
+                w.set_ssoe(true);
+                #[cfg(spi_v2)]
+                w.set_nssp(config.nss_pulse);
+            });
+        }
+        #[cfg(any(spi_v3, spi_v4, spi_v5))]
+        regs.cfg2().modify(|w| {
+            w.set_ssm(false);
+            w.set_ssoe(true);
+            w.set_ssiop(vals::Ssiop::ACTIVELOW);
+            w.set_ssom(match config.nss_pulse {
+                true => vals::Ssom::NOTASSERTED,
+                false => vals::Ssom::ASSERTED,
+            });
+        });
+        regs.cr1().modify(|w| w.set_spe(true));
+    }
+
+    /// Switch to half-duplex mode, with a single bidirectional data line on MOSI.
+    ///
+    /// The line is left in the transmit direction between operations: on SPI versions before
+    /// SPIv3 the clock runs as long as the peripheral is enabled in the receive direction.
+    fn enable_half_duplex(&mut self) {
+        self.half_duplex = true;
+
+        let regs = self.info.regs;
+        regs.cr1().modify(|w| w.set_spe(false));
+        #[cfg(any(spi_v1, spi_f1, spi_v2))]
+        regs.cr1().modify(|w| {
+            w.set_bidimode(vals::Bidimode::BIDIRECTIONAL);
+            w.set_bidioe(vals::Bidioe::OUTPUTENABLED);
+        });
+        #[cfg(any(spi_v3, spi_v4, spi_v5))]
+        {
+            regs.cfg2().modify(|w| w.set_comm(vals::Comm::HALFDUPLEX));
+            regs.cr1().modify(|w| w.set_hddir(vals::Hddir::TRANSMITTER));
+        }
+        regs.cr1().modify(|w| w.set_spe(true));
+    }
+
+    /// Blocking read in half-duplex mode, turning the data line around for its duration.
+    #[cfg(any(spi_v1, spi_f1, spi_v2))]
+    fn blocking_read_half_duplex<W: Word>(&mut self, words: &mut [W]) -> Result<(), Error> {
+        let regs = self.info.regs;
+        self.set_word_size(W::CONFIG);
+        regs.cr1().modify(|w| w.set_spe(false));
+        flush_rx_fifo(regs);
+        regs.cr1().modify(|w| w.set_bidioe(vals::Bidioe::OUTPUTDISABLED));
+
+        // The clock runs from enabling the peripheral until disabling it, which ends the frame in
+        // progress. Disable it once the second to last word was received, without interruption so
+        // that the last frame is still in progress.
+        let len = words.len();
+        let result = critical_section::with(|_| {
+            regs.cr1().modify(|w| w.set_spe(true));
+            if len == 1 {
+                regs.cr1().modify(|w| w.set_spe(false));
+            }
+            for (i, word) in words.iter_mut().enumerate() {
+                if let Err(e) = spin_until_rx_ready(regs) {
+                    regs.cr1().modify(|w| w.set_spe(false));
+                    return Err(e);
+                }
+                if i + 2 == len {
+                    regs.cr1().modify(|w| w.set_spe(false));
+                }
+                *word = unsafe { ptr::read_volatile(regs.rx_ptr()) };
+            }
+            regs.cr1().modify(|w| w.set_spe(false));
+            Ok(())
+        });
+
+        while regs.sr().read().bsy() {}
+        flush_rx_fifo(regs);
+        regs.cr1().modify(|w| w.set_bidioe(vals::Bidioe::OUTPUTENABLED));
+        regs.cr1().modify(|w| w.set_spe(true));
+
+        result
+    }
+
+    /// Blocking read in half-duplex mode, turning the data line around for its duration.
+    #[cfg(any(spi_v3, spi_v4, spi_v5))]
+    fn blocking_read_half_duplex<W: Word>(&mut self, words: &mut [W]) -> Result<(), Error> {
+        let regs = self.info.regs;
+        self.set_word_size(W::CONFIG);
+        regs.cr1().modify(|w| {
+            w.set_spe(false);
+            w.set_hddir(vals::Hddir::RECEIVER);
+        });
+
+        let mut result = Ok(());
+        'chunks: for chunk in words.chunks_mut(u16::MAX.into()) {
+            // The peripheral stops the clock by itself after TSIZE words.
+            regs.cr2().modify(|w| w.set_tsize(chunk.len() as u16));
+            regs.ifcr().write(|w| w.0 = 0xffff_ffff);
+            regs.cr1().modify(|w| w.set_spe(true));
+            regs.cr1().modify(|w| w.set_cstart(true));
+
+            for word in chunk.iter_mut() {
+                if let Err(e) = spin_until_rx_ready(regs) {
+                    result = Err(e);
+                    break 'chunks;
+                }
+                *word = unsafe { ptr::read_volatile(regs.rx_ptr()) };
+            }
+            while !regs.sr().read().eot() {}
+            regs.cr1().modify(|w| w.set_spe(false));
+        }
+
+        regs.cr1().modify(|w| {
+            w.set_spe(false);
+            w.set_hddir(vals::Hddir::TRANSMITTER);
+        });
+        regs.cr2().modify(|w| w.set_tsize(0));
+        regs.cr1().modify(|w| w.set_spe(true));
+
+        result
+    }
+
     /// Reconfigures it with the supplied config.
-    pub fn set_config(&mut self, config: &Config) -> Result<(), ()> {
+    ///
+    /// This can be called between transfers, e.g. to talk to devices using different SPI modes
+    /// on the same bus. The peripheral is briefly disabled, so the clock pin isn't driven meanwhile.
+    pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
         let cpha = config.raw_phase();
         let cpol = config.raw_polarity();
 
         let lsbfirst = config.raw_byte_order();
 
-        let br = compute_baud_rate(self.kernel_clock, config.frequency);
+        let br = compute_baud_rate(self.kernel_clock, config.frequency)?;
+
+        // The configuration can't change while the peripheral is enabled.
+        self.info.regs.cr1().modify(|w| w.set_spe(false));
 
         #[cfg(any(spi_v1, spi_f1, spi_v2))]
-        self.info.regs.cr1().modify(|w| {
-            w.set_cpha(cpha);
-            w.set_cpol(cpol);
-            w.set_br(br);
-            w.set_lsbfirst(lsbfirst);
-        });
+        {
+            #[cfg(any(spi_v1, spi_v2))]
+            self.info.regs.cr2().modify(|w| {
+                w.set_frf(config.raw_frame_format());
+            });
+            self.info.regs.cr1().modify(|w| {
+                w.set_cpha(cpha);
+                w.set_cpol(cpol);
+                w.set_br(br);
+                w.set_lsbfirst(lsbfirst);
+            });
+        }
 
         #[cfg(any(spi_v3, spi_v4, spi_v5))]
         {
@@ -257,11 +488,20 @@ impl<'d, M: PeriMode> Spi<'d, M> {
                 w.set_cpha(cpha);
                 w.set_cpol(cpol);
                 w.set_lsbfirst(lsbfirst);
+                w.set_sp(config.raw_frame_format());
             });
             self.info.regs.cfg1().modify(|w| {
                 w.set_mbr(br);
             });
         }
+
+        self.info.regs.cr1().modify(|w| w.set_spe(true));
+
+        #[cfg(feature = "time")]
+        {
+            self.timeout = config.timeout;
+        }
+
         Ok(())
     }
 
@@ -303,14 +543,46 @@ impl<'d, M: PeriMode> Spi<'d, M> {
 
         let frequency = compute_frequency(self.kernel_clock, br);
 
+        #[cfg(any(spi_v1, spi_v2))]
+        let frame_format = match self.info.regs.cr2().read().frf() {
+            vals::Frf::MOTOROLA => FrameFormat::Motorola,
+            vals::Frf::TI => FrameFormat::Ti,
+        };
+        #[cfg(any(spi_v3, spi_v4, spi_v5))]
+        let frame_format = match cfg.sp() {
+            vals::Sp::TI => FrameFormat::Ti,
+            _ => FrameFormat::Motorola,
+        };
+
+        #[cfg(spi_v2)]
+        let nss_pulse = self.info.regs.cr2().read().nssp();
+        #[cfg(any(spi_v3, spi_v4, spi_v5))]
+        let nss_pulse = cfg.ssom() == vals::Ssom::NOTASSERTED;
+
         Config {
             mode: Mode { polarity, phase },
             bit_order,
             frequency,
             miso_pull,
+            #[cfg(feature = "time")]
+            timeout: self.timeout,
+            #[cfg(not(any(spi_v1, spi_f1)))]
+            nss_pulse,
+            #[cfg(not(spi_f1))]
+            frame_format,
         }
     }
 
+    #[cfg(feature = "time")]
+    fn timeout(&self) -> Option<embassy_time::Duration> {
+        self.timeout
+    }
+
+    #[cfg(not(feature = "time"))]
+    fn timeout(&self) -> Option<()> {
+        None
+    }
+
     fn set_word_size(&mut self, word_size: word_impl::Config) {
         if self.current_word_size == word_size {
             return;
@@ -384,8 +656,13 @@ impl<'d, M: PeriMode> Spi<'d, M> {
             // Luckily this doesn't affect SPIv2+.
             // See http://efton.sk/STM32/gotcha/g68.html
             // ST doesn't seem to document this in errata sheets (?)
+            // In half-duplex mode nothing is received while transmitting, there is no RXNE to wait for.
             #[cfg(any(spi_v1, spi_f1))]
-            transfer_word(self.info.regs, *word)?;
+            if self.half_duplex {
+                write_word(self.info.regs, *word)?;
+            } else {
+                transfer_word(self.info.regs, *word)?;
+            }
         }
 
         // wait until last word is transmitted. (except on v1, see above)
@@ -393,12 +670,24 @@ impl<'d, M: PeriMode> Spi<'d, M> {
         while !self.info.regs.sr().read().txc() {}
         #[cfg(spi_v2)]
         while self.info.regs.sr().read().bsy() {}
+        #[cfg(any(spi_v1, spi_f1))]
+        if self.half_duplex {
+            while !self.info.regs.sr().read().txe() {}
+            while self.info.regs.sr().read().bsy() {}
+        }
 
         Ok(())
     }
 
     /// Blocking read.
     pub fn blocking_read<W: Word>(&mut self, words: &mut [W]) -> Result<(), Error> {
+        if self.half_duplex {
+            if words.is_empty() {
+                return Ok(());
+            }
+            return self.blocking_read_half_duplex(words);
+        }
+
         // needed in v3+ to avoid overrun causing the SPI RX state machine to get stuck...?
         #[cfg(any(spi_v3, spi_v4, spi_v5))]
         self.info.regs.cr1().modify(|w| w.set_spe(false));
@@ -414,7 +703,9 @@ impl<'d, M: PeriMode> Spi<'d, M> {
     /// Blocking in-place bidirectional transfer.
     ///
     /// This writes the contents of `data` on MOSI, and puts the received data on MISO in `data`, at the same time.
+    /// Not available in half-duplex mode.
     pub fn blocking_transfer_in_place<W: Word>(&mut self, words: &mut [W]) -> Result<(), Error> {
+        assert!(!self.half_duplex);
         // needed in v3+ to avoid overrun causing the SPI RX state machine to get stuck...?
         #[cfg(any(spi_v3, spi_v4, spi_v5))]
         self.info.regs.cr1().modify(|w| w.set_spe(false));
@@ -432,8 +723,9 @@ impl<'d, M: PeriMode> Spi<'d, M> {
     /// This transfers both buffers at the same time, so it is NOT equivalent to `write` followed by `read`.
     ///
     /// The transfer runs for `max(read.len(), write.len())` bytes. If `read` is shorter extra bytes are ignored.
-    /// If `write` is shorter it is padded with zero bytes.
+    /// If `write` is shorter it is padded with zero bytes. Not available in half-duplex mode.
     pub fn blocking_transfer<W: Word>(&mut self, read: &mut [W], write: &[W]) -> Result<(), Error> {
+        assert!(!self.half_duplex);
         // needed in v3+ to avoid overrun causing the SPI RX state machine to get stuck...?
         #[cfg(any(spi_v3, spi_v4, spi_v5))]
         self.info.regs.cr1().modify(|w| w.set_spe(false));
@@ -472,6 +764,33 @@ impl<'d> Spi<'d, Blocking> {
         )
     }
 
+    /// Create a new blocking SPI driver, with the NSS pin driven by the peripheral.
+    ///
+    /// See [`Spi::new_with_nss`].
+    pub fn new_blocking_with_nss<T: Instance>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T>> + 'd,
+        mosi: impl Peripheral<P = impl MosiPin<T>> + 'd,
+        miso: impl Peripheral<P = impl MisoPin<T>> + 'd,
+        nss: impl Peripheral<P = impl CsPin<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        let mut this = Self::new_inner(
+            peri,
+            new_pin!(sck, config.sck_af()),
+            new_pin!(mosi, AfType::output(OutputType::PushPull, Speed::VeryHigh)),
+            new_pin!(miso, AfType::input(config.miso_pull)),
+            None,
+            None,
+            config,
+        );
+        this.enable_hardware_nss(
+            new_pin!(nss, AfType::output(OutputType::PushPull, Speed::VeryHigh)),
+            &config,
+        );
+        this
+    }
+
     /// Create a new blocking SPI driver, in RX-only mode (only MISO pin, no MOSI).
     pub fn new_blocking_rxonly<T: Instance>(
         peri: impl Peripheral<P = T> + 'd,
@@ -508,6 +827,28 @@ impl<'d> Spi<'d, Blocking> {
         )
     }
 
+    /// Create a new blocking SPI driver, in half-duplex mode (3-wire SPI).
+    ///
+    /// See [`Spi::new_half_duplex`].
+    pub fn new_blocking_half_duplex<T: Instance>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T>> + 'd,
+        sdio: impl Peripheral<P = impl MosiPin<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        let mut this = Self::new_inner(
+            peri,
+            new_pin!(sck, config.sck_af()),
+            new_pin!(sdio, AfType::output(OutputType::PushPull, Speed::VeryHigh)),
+            None,
+            None,
+            None,
+            config,
+        );
+        this.enable_half_duplex();
+        this
+    }
+
     /// Create a new SPI driver, in TX-only mode, without SCK pin.
     ///
     /// This can be useful for bit-banging non-SPI protocols.
@@ -550,6 +891,44 @@ impl<'d> Spi<'d, Async> {
         )
     }
 
+    /// Create a new SPI driver, with the NSS pin driven by the peripheral.
+    ///
+    /// This is for a single device on the bus; to share the bus between devices, use a GPIO chip
+    /// select per device with the `SpiDevice` implementations of `embassy-embedded-hal`'s
+    /// `shared_bus` module instead.
+    ///
+    /// On SPIv3 and later, NSS is asserted for the duration of each operation. On older SPI
+    /// versions, NSS is asserted whenever the peripheral is enabled: it stays asserted between
+    /// operations, and is briefly deasserted when the driver disables the peripheral, at the start
+    /// of DMA transfers and on word size or configuration changes. It therefore does not frame
+    /// operations, use a GPIO chip select for devices needing that. With [`Config::nss_pulse`],
+    /// NSS is additionally pulsed between words.
+    pub fn new_with_nss<T: Instance>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T>> + 'd,
+        mosi: impl Peripheral<P = impl MosiPin<T>> + 'd,
+        miso: impl Peripheral<P = impl MisoPin<T>> + 'd,
+        nss: impl Peripheral<P = impl CsPin<T>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        let mut this = Self::new_inner(
+            peri,
+            new_pin!(sck, config.sck_af()),
+            new_pin!(mosi, AfType::output(OutputType::PushPull, Speed::VeryHigh)),
+            new_pin!(miso, AfType::input(config.miso_pull)),
+            new_dma!(tx_dma),
+            new_dma!(rx_dma),
+            config,
+        );
+        this.enable_hardware_nss(
+            new_pin!(nss, AfType::output(OutputType::PushPull, Speed::VeryHigh)),
+            &config,
+        );
+        this
+    }
+
     /// Create a new SPI driver, in RX-only mode (only MISO pin, no MOSI).
     pub fn new_rxonly<T: Instance>(
         peri: impl Peripheral<P = T> + 'd,
@@ -592,6 +971,39 @@ impl<'d> Spi<'d, Async> {
         )
     }
 
+    /// Create a new SPI driver, in half-duplex mode (3-wire SPI).
+    ///
+    /// Data is both sent and received on the MOSI pin, `sdio`, as done by many sensors and display
+    /// controllers with a single data pin. The direction of the line is switched by `read` and
+    /// `write`; the full-duplex `transfer` methods aren't available.
+    ///
+    /// Only SPIv3 and later can stop the clock after an exact number of words while receiving.
+    /// On older SPI versions reads are done without DMA, in a critical section, stopping the clock
+    /// as the last word is received.
+    pub fn new_half_duplex<T: Instance>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T>> + 'd,
+        sdio: impl Peripheral<P = impl MosiPin<T>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        #[cfg(any(spi_v3, spi_v4, spi_v5))] rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        let mut this = Self::new_inner(
+            peri,
+            new_pin!(sck, config.sck_af()),
+            new_pin!(sdio, AfType::output(OutputType::PushPull, Speed::VeryHigh)),
+            None,
+            new_dma!(tx_dma),
+            #[cfg(any(spi_v3, spi_v4, spi_v5))]
+            new_dma!(rx_dma),
+            #[cfg(any(spi_v1, spi_f1, spi_v2))]
+            None,
+            config,
+        );
+        this.enable_half_duplex();
+        this
+    }
+
     /// Create a new SPI driver, in TX-only mode, without SCK pin.
     ///
     /// This can be useful for bit-banging non-SPI protocols.
@@ -649,6 +1061,7 @@ impl<'d> Spi<'d, Async> {
         }
 
         self.set_word_size(W::CONFIG);
+        let timeout = self.timeout();
         self.info.regs.cr1().modify(|w| {
             w.set_spe(false);
         });
@@ -665,7 +1078,7 @@ impl<'d> Spi<'d, Async> {
             w.set_cstart(true);
         });
 
-        tx_f.await;
+        wait_dma(self.info.regs, timeout, tx_f).await?;
 
         finish_dma(self.info.regs);
 
@@ -687,9 +1100,14 @@ impl<'d> Spi<'d, Async> {
 
         let comm = regs.cfg2().modify(|w| {
             let prev = w.comm();
-            w.set_comm(vals::Comm::RECEIVER);
+            if !self.half_duplex {
+                w.set_comm(vals::Comm::RECEIVER);
+            }
             prev
         });
+        if self.half_duplex {
+            regs.cr1().modify(|w| w.set_hddir(vals::Hddir::RECEIVER));
+        }
 
         #[cfg(spi_v3)]
         let i2scfg = regs.i2scfgr().modify(|w| {
@@ -705,6 +1123,8 @@ impl<'d> Spi<'d, Async> {
         });
 
         let rx_src = regs.rx_ptr();
+        let timeout = self.timeout();
+        let mut result = Ok(());
 
         for mut chunk in data.chunks_mut(u16::max_value().into()) {
             self.set_word_size(W::CONFIG);
@@ -731,7 +1151,10 @@ impl<'d> Spi<'d, Async> {
                 w.set_cstart(true);
             });
 
-            transfer.await;
+            result = wait_dma(regs, timeout, transfer).await;
+            if result.is_err() {
+                break;
+            }
 
             finish_dma(regs);
         }
@@ -743,6 +1166,9 @@ impl<'d> Spi<'d, Async> {
         regs.cfg2().modify(|w| {
             w.set_comm(comm);
         });
+        if self.half_duplex {
+            regs.cr1().modify(|w| w.set_hddir(vals::Hddir::TRANSMITTER));
+        }
 
         regs.cr2().modify(|w| {
             w.set_tsize(0);
@@ -755,17 +1181,24 @@ impl<'d> Spi<'d, Async> {
             });
         }
 
-        Ok(())
+        result
     }
 
     /// SPI read, using DMA.
+    ///
+    /// In half-duplex mode, this is a blocking read, see [`Spi::new_half_duplex`].
     #[cfg(any(spi_v1, spi_f1, spi_v2))]
     pub async fn read<W: Word>(&mut self, data: &mut [W]) -> Result<(), Error> {
         if data.is_empty() {
             return Ok(());
         }
 
+        if self.half_duplex {
+            return self.blocking_read_half_duplex(data);
+        }
+
         self.set_word_size(W::CONFIG);
+        let timeout = self.timeout();
 
         self.info.regs.cr1().modify(|w| {
             w.set_spe(false);
@@ -783,12 +1216,14 @@ impl<'d> Spi<'d, Async> {
         let rx_f = unsafe { self.rx_dma.as_mut().unwrap().read(rx_src, data, Default::default()) };
 
         let tx_dst = self.info.regs.tx_ptr();
-        let clock_byte = 0x00u8;
+        // Clock out words of the same size as the frame, a narrower DMA write to the data register
+        // would be packed differently on SPIv2.
+        let clock_word = W::default();
         let tx_f = unsafe {
             self.tx_dma
                 .as_mut()
                 .unwrap()
-                .write_repeated(&clock_byte, clock_byte_count, tx_dst, Default::default())
+                .write_repeated(&clock_word, clock_byte_count, tx_dst, Default::default())
         };
 
         set_txdmaen(self.info.regs, true);
@@ -800,7 +1235,7 @@ impl<'d> Spi<'d, Async> {
             w.set_cstart(true);
         });
 
-        join(tx_f, rx_f).await;
+        wait_dma(self.info.regs, timeout, join(tx_f, rx_f)).await?;
 
         finish_dma(self.info.regs);
 
@@ -808,12 +1243,14 @@ impl<'d> Spi<'d, Async> {
     }
 
     async fn transfer_inner<W: Word>(&mut self, read: *mut [W], write: *const [W]) -> Result<(), Error> {
+        assert!(!self.half_duplex);
         assert_eq!(read.len(), write.len());
         if read.len() == 0 {
             return Ok(());
         }
 
         self.set_word_size(W::CONFIG);
+        let timeout = self.timeout();
         self.info.regs.cr1().modify(|w| {
             w.set_spe(false);
         });
@@ -844,7 +1281,7 @@ impl<'d> Spi<'d, Async> {
             w.set_cstart(true);
         });
 
-        join(tx_f, rx_f).await;
+        wait_dma(self.info.regs, timeout, join(tx_f, rx_f)).await?;
 
         finish_dma(self.info.regs);
 
@@ -857,8 +1294,23 @@ impl<'d> Spi<'d, Async> {
     ///
     /// The transfer runs for `max(read.len(), write.len())` bytes. If `read` is shorter extra bytes are ignored.
     /// If `write` is shorter it is padded with zero bytes.
+    ///
+    /// The TX and RX DMA channels run together for the common length. The remainder is a separate
+    /// [`write`](Self::write) or [`read`](Self::read), so there may be a short pause in the clock
+    /// between the two.
     pub async fn transfer<W: Word>(&mut self, read: &mut [W], write: &[W]) -> Result<(), Error> {
-        self.transfer_inner(read, write).await
+        let common = read.len().min(write.len());
+        let (read, read_rest) = read.split_at_mut(common);
+        let (write, write_rest) = write.split_at(common);
+
+        self.transfer_inner(read, write).await?;
+        if !write_rest.is_empty() {
+            self.write(write_rest).await?;
+        }
+        if !read_rest.is_empty() {
+            self.read(read_rest).await?;
+        }
+        Ok(())
     }
 
     /// In-place bidirectional transfer, using DMA.
@@ -874,6 +1326,7 @@ impl<'d, M: PeriMode> Drop for Spi<'d, M> {
         self.sck.as_ref().map(|x| x.set_as_disconnected());
         self.mosi.as_ref().map(|x| x.set_as_disconnected());
         self.miso.as_ref().map(|x| x.set_as_disconnected());
+        self.nss.as_ref().map(|x| x.set_as_disconnected());
 
         self.info.rcc.disable();
     }
@@ -884,9 +1337,9 @@ use vals::Br;
 #[cfg(any(spi_v3, spi_v4, spi_v5))]
 use vals::Mbr as Br;
 
-fn compute_baud_rate(kernel_clock: Hertz, freq: Hertz) -> Br {
+fn compute_baud_rate(kernel_clock: Hertz, freq: Hertz) -> Result<Br, ConfigError> {
     let val = match kernel_clock.0 / freq.0 {
-        0 => panic!("You are trying to reach a frequency higher than the clock"),
+        0 => return Err(ConfigError::FrequencyTooHigh),
         1..=2 => 0b000,
         3..=5 => 0b001,
         6..=11 => 0b010,
@@ -894,10 +1347,11 @@ fn compute_baud_rate(kernel_clock: Hertz, freq: Hertz) -> Br {
         24..=39 => 0b100,
         40..=95 => 0b101,
         96..=191 => 0b110,
-        _ => 0b111,
+        192..=383 => 0b111,
+        _ => return Err(ConfigError::FrequencyTooLow),
     };
 
-    Br::from_bits(val)
+    Ok(Br::from_bits(val))
 }
 
 fn compute_frequency(kernel_clock: Hertz, br: Br) -> Hertz {
@@ -915,7 +1369,7 @@ fn compute_frequency(kernel_clock: Hertz, br: Br) -> Hertz {
     kernel_clock / div
 }
 
-trait RegsExt {
+pub(crate) trait RegsExt {
     fn tx_ptr<W>(&self) -> *mut W;
     fn rx_ptr<W>(&self) -> *mut W;
 }
@@ -1017,7 +1471,7 @@ fn flush_rx_fifo(regs: Regs) {
     }
 }
 
-fn set_txdmaen(regs: Regs, val: bool) {
+pub(crate) fn set_txdmaen(regs: Regs, val: bool) {
     #[cfg(not(any(spi_v3, spi_v4, spi_v5)))]
     regs.cr2().modify(|reg| {
         reg.set_txdmaen(val);
@@ -1028,7 +1482,7 @@ fn set_txdmaen(regs: Regs, val: bool) {
     });
 }
 
-fn set_rxdmaen(regs: Regs, val: bool) {
+pub(crate) fn set_rxdmaen(regs: Regs, val: bool) {
     #[cfg(not(any(spi_v3, spi_v4, spi_v5)))]
     regs.cr2().modify(|reg| {
         reg.set_rxdmaen(val);
@@ -1039,6 +1493,54 @@ fn set_rxdmaen(regs: Regs, val: bool) {
     });
 }
 
+/// Await a DMA transfer, aborting it if it doesn't complete within `timeout`.
+#[cfg(feature = "time")]
+async fn wait_dma<R>(
+    regs: Regs,
+    timeout: Option<embassy_time::Duration>,
+    fut: impl core::future::Future<Output = R>,
+) -> Result<R, Error> {
+    match timeout {
+        Some(timeout) => match embassy_time::with_timeout(timeout, fut).await {
+            Ok(r) => Ok(r),
+            Err(_) => {
+                // The DMA transfer was stopped when the future was dropped. Don't wait for the
+                // peripheral to go idle like `finish_dma` does, it's most likely stuck.
+                abort_dma(regs);
+                Err(Error::Timeout)
+            }
+        },
+        None => Ok(fut.await),
+    }
+}
+
+#[cfg(not(feature = "time"))]
+async fn wait_dma<R>(
+    _regs: Regs,
+    _timeout: Option<()>,
+    fut: impl core::future::Future<Output = R>,
+) -> Result<R, Error> {
+    Ok(fut.await)
+}
+
+#[cfg(feature = "time")]
+fn abort_dma(regs: Regs) {
+    regs.cr1().modify(|w| {
+        w.set_spe(false);
+    });
+
+    #[cfg(not(any(spi_v3, spi_v4, spi_v5)))]
+    regs.cr2().modify(|reg| {
+        reg.set_txdmaen(false);
+        reg.set_rxdmaen(false);
+    });
+    #[cfg(any(spi_v3, spi_v4, spi_v5))]
+    regs.cfg1().modify(|reg| {
+        reg.set_txdmaen(false);
+        reg.set_rxdmaen(false);
+    });
+}
+
 fn finish_dma(regs: Regs) {
     #[cfg(spi_v2)]
     while regs.sr().read().ftlvl().to_bits() > 0 {}
@@ -1163,6 +1665,7 @@ impl embedded_hal_1::spi::Error for Error {
             Self::Crc => embedded_hal_1::spi::ErrorKind::Other,
             Self::ModeFault => embedded_hal_1::spi::ErrorKind::ModeFault,
             Self::Overrun => embedded_hal_1::spi::ErrorKind::Overrun,
+            Self::Timeout => embedded_hal_1::spi::ErrorKind::Other,
         }
     }
 }
@@ -1310,8 +1813,8 @@ foreach_peripheral!(
 
 impl<'d, M: PeriMode> SetConfig for Spi<'d, M> {
     type Config = Config;
-    type ConfigError = ();
-    fn set_config(&mut self, config: &Self::Config) -> Result<(), ()> {
+    type ConfigError = ConfigError;
+    fn set_config(&mut self, config: &Self::Config) -> Result<(), ConfigError> {
         self.set_config(config)
     }
 }
@@ -0,0 +1,396 @@
+//! Bit-banged Serial Wire Debug (SWD) host
+//!
+//! Drives the SWD wire protocol on two GPIOs to access the debug port of a companion MCU, for
+//! example to inspect its memory or flash it in-system. Only the ARM ADIv5 DP and MEM-AP are
+//! handled here; flash programming algorithms are specific to the target and up to the user.
+//!
+//! SWCLK is paced by a timer, whose update event marks each half clock period, so the clock rate
+//! doesn't depend on the CPU clock or on the time spent driving the pins. Within a transaction
+//! (about 50 clock cycles) the driver polls the update flag, blocking the executor for the
+//! transaction's duration: the pins are switched between input and output mid-transaction, which
+//! DMA to the GPIO registers can't follow. The driver yields to other tasks between transactions
+//! and while the target answers WAIT, so long memory transfers don't starve the rest of the
+//! application.
+use embassy_futures::yield_now;
+use embassy_hal_internal::Peripheral;
+
+use crate::gpio::{Flex, Pin, Pull, Speed};
+use crate::time::Hertz;
+use crate::timer::low_level::Timer;
+use crate::timer::CoreInstance;
+
+/// SWD error
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The target kept answering WAIT.
+    Wait,
+    /// The target answered FAULT. Sticky error flags are cleared before returning.
+    Fault,
+    /// No valid acknowledge was received, e.g. because no target is connected.
+    NoAck,
+    /// Parity error in the data received from the target.
+    Parity,
+}
+
+/// SWD configuration
+#[non_exhaustive]
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// SWCLK frequency. The timer runs at twice this frequency, which must be reachable from its
+    /// clock, and the CPU must be fast enough to drive the pins within a half period.
+    pub frequency: Hertz,
+    /// Number of times a transaction is retried while the target answers WAIT.
+    pub wait_retries: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            frequency: Hertz::mhz(1),
+            wait_retries: 100,
+        }
+    }
+}
+
+/// Debug port register
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum DpRegister {
+    /// Identification (read) / ABORT (write)
+    IdcodeAbort = 0x0,
+    /// Control/status
+    CtrlStat = 0x4,
+    /// AP select (write) / RESEND (read)
+    Select = 0x8,
+    /// Read buffer (read)
+    RdBuff = 0xC,
+}
+
+// ACK values
+const ACK_OK: u8 = 0b001;
+const ACK_WAIT: u8 = 0b010;
+const ACK_FAULT: u8 = 0b100;
+
+// DP registers bits
+const ABORT_CLEAR_ERRORS: u32 = 0x1E;
+const CTRLSTAT_CDBGPWRUPREQ: u32 = 1 << 28;
+const CTRLSTAT_CDBGPWRUPACK: u32 = 1 << 29;
+const CTRLSTAT_CSYSPWRUPREQ: u32 = 1 << 30;
+const CTRLSTAT_CSYSPWRUPACK: u32 = 1 << 31;
+
+// MEM-AP registers
+const AP_CSW: u8 = 0x00;
+const AP_TAR: u8 = 0x04;
+const AP_DRW: u8 = 0x0C;
+/// 32-bit accesses, single auto-increment, debug software access enabled.
+const CSW_WORD_AUTOINC: u32 = 0x2300_0012;
+
+/// The TAR auto-increment only works within a 1KB block.
+const AUTOINC_BLOCK: u32 = 0x400;
+
+/// Bit-banged SWD host driver.
+pub struct Swd<'d, T: CoreInstance> {
+    swclk: Flex<'d>,
+    swdio: Flex<'d>,
+    timer: Timer<'d, T>,
+    wait_retries: u32,
+}
+
+impl<'d, T: CoreInstance> Swd<'d, T> {
+    /// Create a new SWD host on the given pins, with SWCLK paced by `tim`.
+    pub fn new(
+        tim: impl Peripheral<P = T> + 'd,
+        swclk: impl Peripheral<P = impl Pin> + 'd,
+        swdio: impl Peripheral<P = impl Pin> + 'd,
+        config: Config,
+    ) -> Self {
+        let mut swclk = Flex::new(swclk);
+        swclk.set_high();
+        swclk.set_as_output(Speed::VeryHigh);
+
+        let mut swdio = Flex::new(swdio);
+        swdio.set_high();
+        swdio.set_as_output(Speed::VeryHigh);
+
+        // One update event per half clock period.
+        let timer = Timer::new(tim);
+        timer.set_frequency(config.frequency * 2u32);
+        timer.start();
+
+        Self {
+            swclk,
+            swdio,
+            timer,
+            wait_retries: config.wait_retries,
+        }
+    }
+
+    /// Reset the debug port, switch it from JTAG to SWD, and power up the debug domain.
+    ///
+    /// Returns the DPIDR of the target.
+    pub async fn connect(&mut self) -> Result<u32, Error> {
+        self.restart_clock();
+        self.line_reset();
+        // JTAG-to-SWD select sequence
+        self.write_bits(0xE79E, 16);
+        self.line_reset();
+        self.write_bits(0, 8);
+
+        let idcode = self.read_dp(DpRegister::IdcodeAbort).await?;
+
+        self.write_dp(DpRegister::IdcodeAbort, ABORT_CLEAR_ERRORS).await?;
+        self.write_dp(DpRegister::Select, 0).await?;
+        self.write_dp(DpRegister::CtrlStat, CTRLSTAT_CDBGPWRUPREQ | CTRLSTAT_CSYSPWRUPREQ)
+            .await?;
+
+        let acks = CTRLSTAT_CDBGPWRUPACK | CTRLSTAT_CSYSPWRUPACK;
+        let mut retries = 0;
+        while self.read_dp(DpRegister::CtrlStat).await? & acks != acks {
+            retries += 1;
+            if retries > self.wait_retries {
+                return Err(Error::Wait);
+            }
+            yield_now().await;
+        }
+
+        Ok(idcode)
+    }
+
+    /// Read a debug port register.
+    pub async fn read_dp(&mut self, reg: DpRegister) -> Result<u32, Error> {
+        self.read(false, reg as u8).await
+    }
+
+    /// Write a debug port register.
+    pub async fn write_dp(&mut self, reg: DpRegister, value: u32) -> Result<(), Error> {
+        self.write(false, reg as u8, value).await
+    }
+
+    /// Read a register of the access port selected in the DP SELECT register.
+    ///
+    /// AP reads are posted: this returns the result of the previous AP read. Read
+    /// [`DpRegister::RdBuff`] to get the result of the last one.
+    pub async fn read_ap(&mut self, addr: u8) -> Result<u32, Error> {
+        self.read(true, addr).await
+    }
+
+    /// Write a register of the access port selected in the DP SELECT register.
+    pub async fn write_ap(&mut self, addr: u8, value: u32) -> Result<(), Error> {
+        self.write(true, addr, value).await
+    }
+
+    /// Read words from target memory through MEM-AP 0, starting at `addr`.
+    pub async fn read_memory(&mut self, addr: u32, data: &mut [u32]) -> Result<(), Error> {
+        assert!(addr % 4 == 0);
+        self.select_mem_ap().await?;
+
+        let mut addr = addr;
+        let mut data = data;
+        for chunk in chunk_by_block(addr, data.len()) {
+            let (words, rest) = core::mem::take(&mut data).split_at_mut(chunk);
+            self.write_ap(AP_TAR, addr).await?;
+            // Each AP read returns the result of the previous one, so the first result is stale.
+            self.read_ap(AP_DRW).await?;
+            for i in 1..words.len() {
+                words[i - 1] = self.read_ap(AP_DRW).await?;
+            }
+            words[chunk - 1] = self.read_dp(DpRegister::RdBuff).await?;
+            data = rest;
+            addr += 4 * chunk as u32;
+        }
+        Ok(())
+    }
+
+    /// Write words to target memory through MEM-AP 0, starting at `addr`.
+    ///
+    /// This writes RAM and memory-mapped registers; writing flash requires running the target's
+    /// flash programming sequence through its flash controller registers.
+    pub async fn write_memory(&mut self, addr: u32, data: &[u32]) -> Result<(), Error> {
+        assert!(addr % 4 == 0);
+        self.select_mem_ap().await?;
+
+        let mut addr = addr;
+        let mut data = data;
+        for chunk in chunk_by_block(addr, data.len()) {
+            self.write_ap(AP_TAR, addr).await?;
+            for &word in &data[..chunk] {
+                self.write_ap(AP_DRW, word).await?;
+            }
+            data = &data[chunk..];
+            addr += 4 * chunk as u32;
+        }
+        // Make sure the last write has completed.
+        self.read_dp(DpRegister::RdBuff).await?;
+        Ok(())
+    }
+
+    async fn select_mem_ap(&mut self) -> Result<(), Error> {
+        self.write_dp(DpRegister::Select, 0).await?;
+        self.write_ap(AP_CSW, CSW_WORD_AUTOINC).await
+    }
+
+    async fn read(&mut self, ap: bool, addr: u8) -> Result<u32, Error> {
+        let mut retries = 0;
+        loop {
+            match self.transfer(ap, true, addr, 0) {
+                Err(Error::Wait) if retries < self.wait_retries => {
+                    retries += 1;
+                    yield_now().await;
+                }
+                res => return self.check(res).await,
+            }
+        }
+    }
+
+    async fn write(&mut self, ap: bool, addr: u8, value: u32) -> Result<(), Error> {
+        let mut retries = 0;
+        loop {
+            match self.transfer(ap, false, addr, value) {
+                Err(Error::Wait) if retries < self.wait_retries => {
+                    retries += 1;
+                    yield_now().await;
+                }
+                res => return self.check(res).await.map(|_| ()),
+            }
+        }
+    }
+
+    /// Clear sticky errors after a FAULT, so the next transaction can proceed.
+    async fn check(&mut self, res: Result<u32, Error>) -> Result<u32, Error> {
+        if res == Err(Error::Fault) {
+            let _ = self.transfer(false, false, DpRegister::IdcodeAbort as u8, ABORT_CLEAR_ERRORS);
+        }
+        yield_now().await;
+        res
+    }
+
+    /// Perform a single SWD transaction.
+    fn transfer(&mut self, ap: bool, read: bool, addr: u8, value: u32) -> Result<u32, Error> {
+        self.restart_clock();
+
+        let a = (addr >> 2) & 0b11;
+        let parity = (ap as u8 + read as u8 + (a & 1) + (a >> 1)) & 1;
+        let request = 1 | (ap as u8) << 1 | (read as u8) << 2 | a << 3 | parity << 5 | 1 << 7;
+        self.write_bits(request as u32, 8);
+
+        self.turnaround_to_target();
+        let ack = self.read_bits(3) as u8;
+
+        match ack {
+            ACK_OK => {}
+            _ => {
+                self.turnaround_to_host();
+                return Err(match ack {
+                    ACK_WAIT => Error::Wait,
+                    ACK_FAULT => Error::Fault,
+                    _ => Error::NoAck,
+                });
+            }
+        }
+
+        let res = if read {
+            let data = self.read_bits(32);
+            let parity = self.read_bits(1);
+            self.turnaround_to_host();
+            if parity != data.count_ones() & 1 {
+                Err(Error::Parity)
+            } else {
+                Ok(data)
+            }
+        } else {
+            self.turnaround_to_host();
+            self.write_bits(value, 32);
+            self.write_bits(value.count_ones() & 1, 1);
+            Ok(0)
+        };
+
+        // Idle cycles, so the target can finish the transaction.
+        self.write_bits(0, 8);
+        res
+    }
+
+    fn line_reset(&mut self) {
+        self.write_bits(u32::MAX, 32);
+        self.write_bits(u32::MAX, 24);
+    }
+
+    /// Write `n` bits LSB first. The target samples SWDIO on the rising edge of SWCLK.
+    fn write_bits(&mut self, bits: u32, n: u32) {
+        for i in 0..n {
+            if bits & (1 << i) != 0 {
+                self.swdio.set_high();
+            } else {
+                self.swdio.set_low();
+            }
+            self.clock_low();
+            self.clock_high();
+        }
+    }
+
+    /// Read `n` bits LSB first. The target drives SWDIO after the rising edge of SWCLK.
+    fn read_bits(&mut self, n: u32) -> u32 {
+        let mut bits = 0;
+        for i in 0..n {
+            self.clock_low();
+            if self.swdio.is_high() {
+                bits |= 1 << i;
+            }
+            self.clock_high();
+        }
+        bits
+    }
+
+    /// Release SWDIO and let the target drive it after one clock cycle.
+    fn turnaround_to_target(&mut self) {
+        self.swdio.set_as_input(Pull::Up);
+        self.clock_low();
+        self.clock_high();
+    }
+
+    /// Take SWDIO back from the target after one clock cycle.
+    fn turnaround_to_host(&mut self) {
+        self.clock_low();
+        self.clock_high();
+        self.swdio.set_as_output(Speed::VeryHigh);
+    }
+
+    /// Start a full half period, discarding the update event left pending while the driver was
+    /// idle, so the first clock phase isn't cut short.
+    fn restart_clock(&mut self) {
+        self.timer.reset();
+        self.timer.clear_update_interrupt();
+    }
+
+    fn wait_half_period(&mut self) {
+        while !self.timer.clear_update_interrupt() {}
+    }
+
+    fn clock_low(&mut self) {
+        self.swclk.set_low();
+        self.wait_half_period();
+    }
+
+    fn clock_high(&mut self) {
+        self.swclk.set_high();
+        self.wait_half_period();
+    }
+}
+
+/// Split a transfer of `len` words starting at `addr` into chunks that don't cross a TAR
+/// auto-increment block boundary.
+fn chunk_by_block(addr: u32, len: usize) -> impl Iterator<Item = usize> {
+    let mut addr = addr;
+    let mut len = len;
+    core::iter::from_fn(move || {
+        if len == 0 {
+            return None;
+        }
+        let in_block = ((AUTOINC_BLOCK - addr % AUTOINC_BLOCK) / 4) as usize;
+        let chunk = in_block.min(len);
+        addr += 4 * chunk as u32;
+        len -= chunk;
+        Some(chunk)
+    })
+}
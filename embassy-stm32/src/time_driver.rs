@@ -30,13 +30,127 @@ use crate::{interrupt, peripherals};
 // CC1, CC2, CC3, and CC4, so it can provide ALARM_COUNT = 3.
 
 cfg_if::cfg_if! {
-    if #[cfg(any(time_driver_tim9, time_driver_tim12, time_driver_tim15, time_driver_tim21, time_driver_tim22))] {
+    if #[cfg(feature = "shared-time-driver")] {
+        // CC2 is the first core's alarm, CC3 the second core's.
+        const ALARM_COUNT: usize = 1;
+    } else if #[cfg(any(time_driver_tim9, time_driver_tim12, time_driver_tim15, time_driver_tim21, time_driver_tim22))] {
         const ALARM_COUNT: usize = 1;
     } else {
         const ALARM_COUNT: usize = 3;
     }
 }
 
+/// Index of the CC channel of the first alarm.
+#[cfg(not(all(feature = "shared-time-driver", time_driver_second_core)))]
+const ALARM_CC: usize = 1;
+#[cfg(all(feature = "shared-time-driver", time_driver_second_core))]
+const ALARM_CC: usize = 2;
+
+#[cfg(all(
+    feature = "shared-time-driver",
+    not(any(stm32h745, stm32h747, stm32h755, stm32h757, stm32wl54, stm32wl55))
+))]
+compile_error!("shared-time-driver is only supported on dual-core chips.");
+
+#[cfg(all(
+    feature = "shared-time-driver",
+    any(
+        time_driver_tim9,
+        time_driver_tim12,
+        time_driver_tim15,
+        time_driver_tim21,
+        time_driver_tim22
+    )
+))]
+compile_error!("shared-time-driver needs a timer with at least 3 capture/compare channels.");
+
+#[cfg(all(feature = "shared-time-driver", feature = "low-power"))]
+compile_error!("shared-time-driver is not supported with low-power.");
+
+// NOTE regarding the shared time driver:
+//
+// With `shared-time-driver`, both cores of a dual-core chip use the same timer, so they see the same
+// `now()`. The first core (CM7 on H7, CM4 on WL) configures and starts the timer, handles the
+// overflow and half-overflow interrupts and keeps `period` up to date. The second core only waits
+// for the timer to run, and reads `period` when computing `now()`.
+//
+// - `period` lives in the `.shared_time_driver` section, which both cores' linker scripts must put
+//   at the same address in RAM accessible to both.
+// - Each core owns one alarm CC channel, and only clears its own flags in SR (clearing is a single
+//   write, so it doesn't race the other core).
+// - DIER is shared and updated with read-modify-writes, which are guarded by HSEM semaphore
+//   `HSEM_ID` on top of the core-local critical section.
+// - The timer interrupt is pended on both cores. The second core re-evaluates its alarm on every
+//   interrupt, and computes the period start from `now()` in case the first core hasn't updated
+//   `period` yet.
+
+/// HSEM semaphore guarding the timer registers shared by both cores.
+#[cfg(feature = "shared-time-driver")]
+const HSEM_ID: usize = 15;
+
+/// Value of `SharedState::ready` once the first core has started the timer.
+#[cfg(feature = "shared-time-driver")]
+const SHARED_READY: u32 = 0x7715_ead1;
+
+#[cfg(feature = "shared-time-driver")]
+struct SharedState {
+    /// Number of 2^15 periods elapsed since the first core started the timer.
+    period: AtomicU32,
+    ready: AtomicU32,
+}
+
+#[cfg(feature = "shared-time-driver")]
+#[link_section = ".shared_time_driver"]
+static SHARED: SharedState = SharedState {
+    period: AtomicU32::new(0),
+    ready: AtomicU32::new(0),
+};
+
+/// Run `f` with exclusive access to the timer registers that both cores modify.
+fn with_timer_lock<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "shared-time-driver")]
+    {
+        let hsem = crate::pac::HSEM;
+        let core_id = crate::hsem::get_current_coreid() as u8;
+
+        // 1-step lock: reading RLR takes the semaphore if it's free.
+        loop {
+            let r = hsem.rlr(HSEM_ID).read();
+            if r.lock() && r.coreid() == core_id && r.procid() == 0 {
+                break;
+            }
+        }
+        let res = f();
+        hsem.r(HSEM_ID).write(|w| {
+            w.set_procid(0);
+            w.set_coreid(core_id);
+            w.set_lock(false);
+        });
+        res
+    }
+    #[cfg(not(feature = "shared-time-driver"))]
+    f()
+}
+
+/// SR flags handled by this core. With a shared time driver, the other ones belong to the other core.
+fn own_flags() -> u32 {
+    #[cfg(feature = "shared-time-driver")]
+    {
+        let mut own = regs::SrGp16(0);
+        #[cfg(not(time_driver_second_core))]
+        {
+            own.set_uif(true);
+            own.set_ccif(0, true);
+        }
+        for n in 0..ALARM_COUNT {
+            own.set_ccif(n + ALARM_CC, true);
+        }
+        own.0
+    }
+    #[cfg(not(feature = "shared-time-driver"))]
+    u32::MAX
+}
+
 #[cfg(time_driver_tim1)]
 type T = peripherals::TIM1;
 #[cfg(time_driver_tim2)]
@@ -253,6 +367,7 @@ impl AlarmState {
 
 pub(crate) struct RtcDriver {
     /// Number of 2^15 periods elapsed since boot.
+    #[cfg(not(feature = "shared-time-driver"))]
     period: AtomicU32,
     alarm_count: AtomicU8,
     /// Timestamp at which to fire alarm. u64::MAX if no alarm is scheduled.
@@ -265,6 +380,7 @@ pub(crate) struct RtcDriver {
 const ALARM_STATE_NEW: AlarmState = AlarmState::new();
 
 embassy_time_driver::time_driver_impl!(static DRIVER: RtcDriver = RtcDriver {
+    #[cfg(not(feature = "shared-time-driver"))]
     period: AtomicU32::new(0),
     alarm_count: AtomicU8::new(0),
     alarms: Mutex::const_new(CriticalSectionRawMutex::new(), [ALARM_STATE_NEW; ALARM_COUNT]),
@@ -273,10 +389,35 @@ embassy_time_driver::time_driver_impl!(static DRIVER: RtcDriver = RtcDriver {
 });
 
 impl RtcDriver {
+    fn period(&self) -> &AtomicU32 {
+        #[cfg(feature = "shared-time-driver")]
+        return &SHARED.period;
+        #[cfg(not(feature = "shared-time-driver"))]
+        return &self.period;
+    }
+
+    #[cfg(all(feature = "shared-time-driver", time_driver_second_core))]
+    fn init(&'static self, cs: critical_section::CriticalSection) {
+        // The first core owns the timer: don't reset it, wait until it's running.
+        rcc::enable_with_cs::<T>(cs);
+        rcc::enable_with_cs::<peripherals::HSEM>(cs);
+        while SHARED.ready.load(Ordering::Acquire) != SHARED_READY {}
+
+        <T as GeneralInstance1Channel>::CaptureCompareInterrupt::unpend();
+        unsafe { <T as GeneralInstance1Channel>::CaptureCompareInterrupt::enable() };
+    }
+
+    #[cfg(not(all(feature = "shared-time-driver", time_driver_second_core)))]
     fn init(&'static self, cs: critical_section::CriticalSection) {
         let r = regs_gp16();
 
         rcc::enable_and_reset_with_cs::<T>(cs);
+        #[cfg(feature = "shared-time-driver")]
+        {
+            rcc::enable_with_cs::<peripherals::HSEM>(cs);
+            SHARED.ready.store(0, Ordering::Relaxed);
+            SHARED.period.store(0, Ordering::Relaxed);
+        }
 
         let timer_freq = T::frequency();
 
@@ -310,6 +451,9 @@ impl RtcDriver {
         unsafe { <T as GeneralInstance1Channel>::CaptureCompareInterrupt::enable() };
 
         r.cr1().modify(|w| w.set_cen(true));
+
+        #[cfg(feature = "shared-time-driver")]
+        SHARED.ready.store(SHARED_READY, Ordering::Release);
     }
 
     fn on_interrupt(&self) {
@@ -323,45 +467,58 @@ impl RtcDriver {
             // Clear all interrupt flags. Bits in SR are "write 0 to clear", so write the bitwise NOT.
             // Other approaches such as writing all zeros, or RMWing won't work, they can
             // miss interrupts.
-            r.sr().write_value(regs::SrGp16(!sr.0));
+            r.sr().write_value(regs::SrGp16(!(sr.0 & own_flags())));
 
-            // Overflow
-            if sr.uif() {
-                self.next_period();
-            }
+            #[cfg(not(all(feature = "shared-time-driver", time_driver_second_core)))]
+            {
+                // Overflow
+                if sr.uif() {
+                    self.next_period();
+                }
 
-            // Half overflow
-            if sr.ccif(0) {
-                self.next_period();
+                // Half overflow
+                if sr.ccif(0) {
+                    self.next_period();
+                }
             }
 
+            // The first core handles the period interrupts, but they may bring our alarm in range.
+            #[cfg(all(feature = "shared-time-driver", time_driver_second_core))]
+            self.enable_alarms_in_range(self.now() & !0x7fff);
+
             for n in 0..ALARM_COUNT {
-                if sr.ccif(n + 1) && dier.ccie(n + 1) {
+                if sr.ccif(n + ALARM_CC) && dier.ccie(n + ALARM_CC) {
                     self.trigger_alarm(n, cs);
                 }
             }
         })
     }
 
+    #[allow(unused)]
     fn next_period(&self) {
-        let r = regs_gp16();
-
         // We only modify the period from the timer interrupt, so we know this can't race.
-        let period = self.period.load(Ordering::Relaxed) + 1;
-        self.period.store(period, Ordering::Relaxed);
-        let t = (period as u64) << 15;
+        let period = self.period().load(Ordering::Relaxed) + 1;
+        self.period().store(period, Ordering::Relaxed);
+        self.enable_alarms_in_range((period as u64) << 15);
+    }
+
+    /// Enable the alarms due before the end of the period after the one starting at `t`.
+    fn enable_alarms_in_range(&self, t: u64) {
+        let r = regs_gp16();
 
         critical_section::with(move |cs| {
-            r.dier().modify(move |w| {
-                for n in 0..ALARM_COUNT {
-                    let alarm = &self.alarms.borrow(cs)[n];
-                    let at = alarm.timestamp.get();
-
-                    if at < t + 0xc000 {
-                        // just enable it. `set_alarm` has already set the correct CCR val.
-                        w.set_ccie(n + 1, true);
+            with_timer_lock(|| {
+                r.dier().modify(move |w| {
+                    for n in 0..ALARM_COUNT {
+                        let alarm = &self.alarms.borrow(cs)[n];
+                        let at = alarm.timestamp.get();
+
+                        if at < t + 0xc000 {
+                            // just enable it. `set_alarm` has already set the correct CCR val.
+                            w.set_ccie(n + ALARM_CC, true);
+                        }
                     }
-                }
+                })
             })
         })
     }
@@ -409,7 +566,7 @@ impl RtcDriver {
     fn add_time(&self, offset: embassy_time::Duration, cs: CriticalSection) {
         let offset = offset.as_ticks();
         let cnt = regs_gp16().cnt().read().cnt() as u32;
-        let period = self.period.load(Ordering::SeqCst);
+        let period = self.period().load(Ordering::SeqCst);
 
         // Correct the race, if it exists
         let period = if period & 1 == 1 && cnt < u16::MAX as u32 / 2 {
@@ -433,7 +590,7 @@ impl RtcDriver {
 
         let period = if cnt > u16::MAX as u32 / 2 { period + 1 } else { period };
 
-        self.period.store(period, Ordering::SeqCst);
+        self.period().store(period, Ordering::SeqCst);
         regs_gp16().cnt().write(|w| w.set_cnt(cnt as u16));
 
         // Now, recompute all alarms
@@ -519,7 +676,7 @@ impl Driver for RtcDriver {
     fn now(&self) -> u64 {
         let r = regs_gp16();
 
-        let period = self.period.load(Ordering::Relaxed);
+        let period = self.period().load(Ordering::Relaxed);
         compiler_fence(Ordering::Acquire);
         let counter = r.cnt().read().cnt();
         calc_now(period, counter)
@@ -558,7 +715,7 @@ impl Driver for RtcDriver {
             if timestamp <= t {
                 // If alarm timestamp has passed the alarm will not fire.
                 // Disarm the alarm and return `false` to indicate that.
-                r.dier().modify(|w| w.set_ccie(n + 1, false));
+                with_timer_lock(|| r.dier().modify(|w| w.set_ccie(n + ALARM_CC, false)));
 
                 alarm.timestamp.set(u64::MAX);
 
@@ -567,11 +724,11 @@ impl Driver for RtcDriver {
 
             // Write the CCR value regardless of whether we're going to enable it now or not.
             // This way, when we enable it later, the right value is already set.
-            r.ccr(n + 1).write(|w| w.set_ccr(timestamp as u16));
+            r.ccr(n + ALARM_CC).write(|w| w.set_ccr(timestamp as u16));
 
             // Enable it if it'll happen soon. Otherwise, `next_period` will enable it.
             let diff = timestamp - t;
-            r.dier().modify(|w| w.set_ccie(n + 1, diff < 0xc000));
+            with_timer_lock(|| r.dier().modify(|w| w.set_ccie(n + ALARM_CC, diff < 0xc000)));
 
             // Reevaluate if the alarm timestamp is still in the future
             let t = self.now();
@@ -580,7 +737,7 @@ impl Driver for RtcDriver {
                 // the alarm may or may not have fired.
                 // Disarm the alarm and return `false` to indicate that.
                 // It is the caller's responsibility to handle this ambiguity.
-                r.dier().modify(|w| w.set_ccie(n + 1, false));
+                with_timer_lock(|| r.dier().modify(|w| w.set_ccie(n + ALARM_CC, false)));
 
                 alarm.timestamp.set(u64::MAX);
 
@@ -0,0 +1,178 @@
+//! Buzzer driver: tones and melodies on a PWM channel.
+//!
+//! Drives a piezo buzzer, or a magnetic one through a transistor, with a square wave at the
+//! frequency of each note. The volume is set through the duty cycle, loudest at 50%. The timer
+//! frequency changes with each note, so the other channels of the timer can't be used for PWM
+//! meanwhile.
+//!
+//! ```rust,ignore
+//! use embassy_stm32::timer::buzzer::{Buzzer, Note, Pitch};
+//!
+//! const MELODY: &[Note] = &[
+//!     Note::new(Pitch::C, 5, 150),
+//!     Note::new(Pitch::E, 5, 150),
+//!     Note::new(Pitch::G, 5, 150),
+//!     Note::rest(100),
+//!     Note::new(Pitch::C, 6, 400),
+//! ];
+//!
+//! let mut buzzer = Buzzer::new(pwm, Channel::Ch1);
+//! buzzer.set_volume(30);
+//! buzzer.play(MELODY).await;
+//! ```
+
+use embassy_hal_internal::drop::OnDrop;
+use embassy_time::{Duration, Instant, Timer};
+
+use super::simple_pwm::SimplePwm;
+use super::{Channel, GeneralInstance4Channel};
+use crate::time::Hertz;
+
+/// Pitch class of a note, in the equal temperament with A4 at 440 Hz.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(missing_docs)]
+pub enum Pitch {
+    C,
+    CSharp,
+    D,
+    DSharp,
+    E,
+    F,
+    FSharp,
+    G,
+    GSharp,
+    A,
+    ASharp,
+    B,
+}
+
+impl Pitch {
+    /// Frequency in octave 0, in millihertz.
+    const fn octave0_mhz(self) -> u32 {
+        match self {
+            Pitch::C => 16_352,
+            Pitch::CSharp => 17_324,
+            Pitch::D => 18_354,
+            Pitch::DSharp => 19_445,
+            Pitch::E => 20_602,
+            Pitch::F => 21_827,
+            Pitch::FSharp => 23_125,
+            Pitch::G => 24_500,
+            Pitch::GSharp => 25_957,
+            Pitch::A => 27_500,
+            Pitch::ASharp => 29_135,
+            Pitch::B => 30_868,
+        }
+    }
+}
+
+/// A note, or a rest, of a melody.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Note {
+    /// Frequency, or `None` for a rest.
+    pub frequency: Option<Hertz>,
+    /// Duration, in milliseconds.
+    pub duration_ms: u32,
+}
+
+impl Note {
+    /// A note of `pitch` in `octave` (4 for the octave starting at middle C), lasting
+    /// `duration_ms` milliseconds.
+    pub const fn new(pitch: Pitch, octave: u8, duration_ms: u32) -> Self {
+        Self::tone(Hertz((pitch.octave0_mhz() << octave) / 1000), duration_ms)
+    }
+
+    /// A tone of `frequency`, lasting `duration_ms` milliseconds.
+    pub const fn tone(frequency: Hertz, duration_ms: u32) -> Self {
+        Self {
+            frequency: Some(frequency),
+            duration_ms,
+        }
+    }
+
+    /// Silence, lasting `duration_ms` milliseconds.
+    pub const fn rest(duration_ms: u32) -> Self {
+        Self {
+            frequency: None,
+            duration_ms,
+        }
+    }
+}
+
+/// Buzzer driver, see the [module documentation](self).
+pub struct Buzzer<'d, T: GeneralInstance4Channel> {
+    pwm: SimplePwm<'d, T>,
+    channel: Channel,
+    volume: u8,
+}
+
+impl<'d, T: GeneralInstance4Channel> Buzzer<'d, T> {
+    /// Create a buzzer on `channel` of `pwm`, silent and at full volume.
+    pub fn new(mut pwm: SimplePwm<'d, T>, channel: Channel) -> Self {
+        pwm.set_duty(channel, 0);
+        pwm.enable(channel);
+        Self {
+            pwm,
+            channel,
+            volume: 100,
+        }
+    }
+
+    /// Set the volume, from 0 to 100%, for the next notes.
+    ///
+    /// The perceived volume is far from linear in the duty cycle, low values make a big
+    /// difference.
+    pub fn set_volume(&mut self, percent: u8) {
+        self.volume = percent.min(100);
+    }
+
+    /// Sound `frequency` until [`stop`](Self::stop) is called or another tone is played.
+    pub fn start(&mut self, frequency: Hertz) {
+        self.pwm.set_frequency(frequency);
+        // 50% duty cycle at full volume
+        let duty = self.pwm.get_max_duty() as u64 * self.volume as u64 / 200;
+        self.pwm.set_duty(self.channel, duty as u32);
+    }
+
+    /// Silence the buzzer.
+    pub fn stop(&mut self) {
+        self.pwm.set_duty(self.channel, 0);
+    }
+
+    /// Sound `frequency` for `duration`.
+    pub async fn tone(&mut self, frequency: Hertz, duration: Duration) {
+        self.play(&[Note::tone(frequency, duration.as_millis() as u32)]).await
+    }
+
+    /// Play `melody`, returning when it is over.
+    ///
+    /// Notes are played back to back, so add short rests between repeated notes to tell them
+    /// apart. The buzzer is silenced if the future is dropped.
+    pub async fn play(&mut self, melody: &[Note]) {
+        let mut at = Instant::now();
+        for note in melody {
+            match note.frequency {
+                Some(frequency) => self.start(frequency),
+                None => self.stop(),
+            }
+
+            let channel = self.channel;
+            let pwm = &mut self.pwm;
+            let silence = OnDrop::new(|| pwm.set_duty(channel, 0));
+
+            // Scheduled against absolute instants, so timing errors don't accumulate.
+            at += Duration::from_millis(note.duration_ms as u64);
+            Timer::at(at).await;
+            silence.defuse();
+        }
+        self.stop();
+    }
+
+    /// Return the PWM driver.
+    pub fn free(mut self) -> SimplePwm<'d, T> {
+        self.stop();
+        self.pwm
+    }
+}
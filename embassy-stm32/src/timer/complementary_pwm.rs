@@ -5,13 +5,13 @@ use core::marker::PhantomData;
 use embassy_hal_internal::{into_ref, PeripheralRef};
 use stm32_metapac::timer::vals::Ckd;
 
-use super::low_level::{CountingMode, OutputPolarity, Timer};
+use super::low_level::{BreakPolarity, CountingMode, OutputPolarity, Timer};
 use super::simple_pwm::{Ch1, Ch2, Ch3, Ch4, PwmPin};
 use super::{
-    AdvancedInstance4Channel, Channel, Channel1ComplementaryPin, Channel2ComplementaryPin, Channel3ComplementaryPin,
-    Channel4ComplementaryPin,
+    AdvancedInstance4Channel, BreakInputPin, Channel, Channel1ComplementaryPin, Channel2ComplementaryPin,
+    Channel3ComplementaryPin, Channel4ComplementaryPin,
 };
-use crate::gpio::{AnyPin, OutputType};
+use crate::gpio::{AnyPin, OutputType, Pull};
 use crate::time::Hertz;
 use crate::timer::low_level::OutputCompareMode;
 use crate::Peripheral;
@@ -51,6 +51,56 @@ complementary_channel_impl!(new_ch2, Ch2, Channel2ComplementaryPin);
 complementary_channel_impl!(new_ch3, Ch3, Channel3ComplementaryPin);
 complementary_channel_impl!(new_ch4, Ch4, Channel4ComplementaryPin);
 
+/// Break input pin wrapper.
+///
+/// A break disables the outputs of the timer, e.g. on an overcurrent signal from a motor driver.
+pub struct BreakPin<'d, T> {
+    _pin: PeripheralRef<'d, AnyPin>,
+    phantom: PhantomData<T>,
+}
+
+impl<'d, T: AdvancedInstance4Channel> BreakPin<'d, T> {
+    /// Create a new break input pin instance.
+    pub fn new(pin: impl Peripheral<P = impl BreakInputPin<T>> + 'd, pull: Pull) -> Self {
+        into_ref!(pin);
+        critical_section::with(|_| {
+            pin.set_as_af(pin.af_num(), crate::gpio::AfType::input(pull));
+        });
+        BreakPin {
+            _pin: pin.map_into(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Break input configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct BreakConfig {
+    /// Level of the break input that triggers a break.
+    pub polarity: BreakPolarity,
+    /// Digital filter of the break input, from 0 (no filter) to 15. Ignored by timers without a
+    /// break filter, such as those of the STM32F1 and STM32F4.
+    pub filter: u8,
+    /// Enable the outputs again at the next update event once the break input is inactive. When
+    /// false, the outputs stay disabled until [`ComplementaryPwm::resume`] is called.
+    pub automatic_resume: bool,
+    /// Drive the outputs to their inactive level during a break, instead of releasing them.
+    pub drive_inactive: bool,
+}
+
+impl Default for BreakConfig {
+    fn default() -> Self {
+        Self {
+            polarity: BreakPolarity::ActiveLow,
+            filter: 0,
+            automatic_resume: false,
+            drive_inactive: true,
+        }
+    }
+}
+
 /// PWM driver with support for standard and complementary outputs.
 pub struct ComplementaryPwm<'d, T: AdvancedInstance4Channel> {
     inner: Timer<'d, T>,
@@ -147,6 +197,38 @@ impl<'d, T: AdvancedInstance4Channel> ComplementaryPwm<'d, T> {
         self.inner.set_dead_time_clock_division(ckd);
         self.inner.set_dead_time_value(value);
     }
+
+    /// Enable the break input on `pin`, disabling all outputs when it becomes active.
+    pub fn enable_break(&mut self, _pin: BreakPin<'d, T>, config: BreakConfig) {
+        self.inner
+            .set_off_state_selection(config.drive_inactive, config.drive_inactive);
+        self.inner.set_automatic_output_enable(config.automatic_resume);
+        self.inner.clear_break_flag();
+        self.inner.set_break_input(true, config.polarity, config.filter);
+    }
+
+    /// Disable the break input.
+    pub fn disable_break(&mut self) {
+        self.inner.set_break_input(false, BreakPolarity::ActiveLow, 0);
+    }
+
+    /// Whether a break occurred since the last call to [`resume`](Self::resume).
+    pub fn is_break_triggered(&self) -> bool {
+        self.inner.get_break_flag()
+    }
+
+    /// Whether the outputs are disabled, by a break.
+    pub fn is_stopped(&self) -> bool {
+        !self.inner.get_moe()
+    }
+
+    /// Enable the outputs again after a break.
+    ///
+    /// If the break input is still active, the outputs stay disabled.
+    pub fn resume(&mut self) {
+        self.inner.clear_break_flag();
+        self.inner.set_moe(true);
+    }
 }
 
 impl<'d, T: AdvancedInstance4Channel> embedded_hal_02::Pwm for ComplementaryPwm<'d, T> {
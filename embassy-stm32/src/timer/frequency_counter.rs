@@ -0,0 +1,215 @@
+//! Frequency counter driver.
+//!
+//! Measures the frequency of a signal by counting its edges during a gate time, which works from
+//! below 1 Hz up to several MHz, unlike measuring the period with input capture, which loses
+//! precision as the frequency rises. The timer is clocked by the signal itself: from a channel
+//! input (external clock mode 1), or from the external trigger input (external clock mode 2),
+//! whose hardware prescaler allows counting signals faster than the timer clock.
+//!
+//! The gate is timed with the embassy time driver. The counter is sampled during the gate, often
+//! enough for a 16-bit counter not to wrap twice between two samples at the highest input
+//! frequency, so measure from a task that isn't delayed by long-running tasks in the same executor.
+//! A 32-bit timer, or a counter prescaler, makes sampling less frequent.
+
+use embassy_hal_internal::into_ref;
+use embassy_time::{Duration, Instant, Timer as Delay, TICK_HZ};
+
+use super::low_level::{FilterValue, InputCaptureMode, InputTISelection, SlaveMode, Timer, TriggerSource};
+use super::{Channel, Channel1Pin, Channel2Pin, ExternalTriggerPin, GeneralInstance4Channel, TimerBits};
+use crate::gpio::{AfType, Pull};
+use crate::pac::timer::vals::Etps;
+use crate::time::Hertz;
+use crate::Peripheral;
+
+/// Prescaler of the external trigger input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EtrPrescaler {
+    /// Count every edge.
+    Div1,
+    /// Count every 2nd edge.
+    Div2,
+    /// Count every 4th edge.
+    Div4,
+    /// Count every 8th edge.
+    Div8,
+}
+
+impl EtrPrescaler {
+    fn divider(self) -> u64 {
+        match self {
+            EtrPrescaler::Div1 => 1,
+            EtrPrescaler::Div2 => 2,
+            EtrPrescaler::Div4 => 4,
+            EtrPrescaler::Div8 => 8,
+        }
+    }
+}
+
+impl From<EtrPrescaler> for Etps {
+    fn from(prescaler: EtrPrescaler) -> Self {
+        match prescaler {
+            EtrPrescaler::Div1 => Etps::DIV1,
+            EtrPrescaler::Div2 => Etps::DIV2,
+            EtrPrescaler::Div4 => Etps::DIV4,
+            EtrPrescaler::Div8 => Etps::DIV8,
+        }
+    }
+}
+
+/// Frequency counter configuration.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Duration of a measurement. The resolution of the result is `1 / gate_time`.
+    pub gate_time: Duration,
+    /// The counter counts every `prescaler + 1` input edges.
+    pub prescaler: u16,
+    /// Digital filter of the input.
+    pub filter: FilterValue,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            gate_time: Duration::from_secs(1),
+            prescaler: 0,
+            filter: FilterValue::NOFILTER,
+        }
+    }
+}
+
+/// Frequency counter driver.
+pub struct FrequencyCounter<'d, T: GeneralInstance4Channel> {
+    inner: Timer<'d, T>,
+    gate_time: Duration,
+    /// Input edges per count, including the ETR prescaler.
+    divider: u64,
+    /// Longest time between two samples of the counter.
+    sample_period: Duration,
+}
+
+impl<'d, T: GeneralInstance4Channel> FrequencyCounter<'d, T> {
+    /// Create a new frequency counter on channel 1.
+    pub fn new_ch1(
+        tim: impl Peripheral<P = T> + 'd,
+        pin: impl Peripheral<P = impl Channel1Pin<T>> + 'd,
+        pull: Pull,
+        config: Config,
+    ) -> Self {
+        into_ref!(pin);
+
+        pin.set_as_af(pin.af_num(), AfType::input(pull));
+
+        Self::new_channel(tim, Channel::Ch1, TriggerSource::TI1FP1, config)
+    }
+
+    /// Create a new frequency counter on channel 2.
+    pub fn new_ch2(
+        tim: impl Peripheral<P = T> + 'd,
+        pin: impl Peripheral<P = impl Channel2Pin<T>> + 'd,
+        pull: Pull,
+        config: Config,
+    ) -> Self {
+        into_ref!(pin);
+
+        pin.set_as_af(pin.af_num(), AfType::input(pull));
+
+        Self::new_channel(tim, Channel::Ch2, TriggerSource::TI2FP2, config)
+    }
+
+    /// Create a new frequency counter on the external trigger input.
+    ///
+    /// The input, divided by `etr_prescaler`, must be at most a quarter of the timer clock.
+    pub fn new_etr(
+        tim: impl Peripheral<P = T> + 'd,
+        pin: impl Peripheral<P = impl ExternalTriggerPin<T>> + 'd,
+        pull: Pull,
+        etr_prescaler: EtrPrescaler,
+        config: Config,
+    ) -> Self {
+        into_ref!(pin);
+
+        pin.set_as_af(pin.af_num(), AfType::input(pull));
+
+        let inner = Timer::new(tim);
+        inner.set_external_clock_mode_2(true, etr_prescaler.into(), config.filter);
+
+        Self::new_inner(inner, etr_prescaler.divider(), config)
+    }
+
+    fn new_channel(tim: impl Peripheral<P = T> + 'd, channel: Channel, ts: TriggerSource, config: Config) -> Self {
+        let inner = Timer::new(tim);
+
+        inner.set_input_ti_selection(channel, InputTISelection::Normal);
+        inner.set_input_capture_filter(channel, config.filter);
+        inner.set_input_capture_mode(channel, InputCaptureMode::Rising);
+        inner.set_trigger_source(ts);
+        inner.set_slave_mode(SlaveMode::EXT_CLOCK_MODE);
+
+        Self::new_inner(inner, 1, config)
+    }
+
+    fn new_inner(inner: Timer<'d, T>, divider: u64, config: Config) -> Self {
+        let max_reload = match T::BITS {
+            TimerBits::Bits16 => u16::MAX as u32,
+            #[cfg(not(stm32l0))]
+            TimerBits::Bits32 => u32::MAX,
+        };
+        inner.set_prescaler_and_reload(config.prescaler, max_reload);
+
+        // The input is resynchronized to the timer clock, so the counter counts at most at half of it.
+        let max_rate = inner.get_clock_frequency().0 as u64 / 2 / (config.prescaler as u64 + 1);
+        let half_wrap = (max_reload as u64 + 1) / 2;
+        let sample_period = Duration::from_ticks((half_wrap * TICK_HZ / max_rate).max(1));
+
+        inner.start();
+
+        Self {
+            inner,
+            gate_time: config.gate_time,
+            divider: divider * (config.prescaler as u64 + 1),
+            sample_period,
+        }
+    }
+
+    /// Set the duration of a measurement.
+    pub fn set_gate_time(&mut self, gate_time: Duration) {
+        self.gate_time = gate_time;
+    }
+
+    /// Measure the input frequency, rounded to the nearest Hz.
+    pub async fn measure(&mut self) -> Hertz {
+        let mask = match T::BITS {
+            TimerBits::Bits16 => u16::MAX as u64,
+            #[cfg(not(stm32l0))]
+            TimerBits::Bits32 => u32::MAX as u64,
+        };
+
+        let (start, mut last) = self.sample();
+        let end = start + self.gate_time;
+        let mut counts = 0u64;
+
+        let elapsed = loop {
+            Delay::at((Instant::now() + self.sample_period).min(end)).await;
+
+            let (now, counter) = self.sample();
+            counts += counter.wrapping_sub(last) as u64 & mask;
+            last = counter;
+
+            if now >= end {
+                break now - start;
+            }
+        };
+
+        let edges = counts as u128 * self.divider as u128;
+        let ticks = elapsed.as_ticks() as u128;
+        Hertz(((edges * TICK_HZ as u128 + ticks / 2) / ticks) as u32)
+    }
+
+    /// Read the counter along with the time it was read at.
+    fn sample(&self) -> (Instant, u32) {
+        critical_section::with(|_| (Instant::now(), self.inner.get_counter()))
+    }
+}
@@ -0,0 +1,172 @@
+//! Frequency output driver.
+//!
+//! Outputs a square wave on a timer channel, for example to provide a reference clock to an
+//! external chip. Unlike [`SimplePwm`](super::simple_pwm::SimplePwm), which only picks the
+//! smallest prescaler that fits, this searches all prescaler and auto-reload combinations for the
+//! one closest to the requested frequency, and reports the frequency actually achieved.
+//!
+//! The MCO pins can be driven the same way with [`Mco::new_frequency`](crate::rcc::Mco::new_frequency).
+
+use super::low_level::{OutputCompareMode, Timer};
+use super::simple_pwm::{Ch1, Ch2, Ch3, Ch4, PwmPin};
+use super::{Channel, GeneralInstance4Channel, TimerBits};
+use crate::time::Hertz;
+use crate::Peripheral;
+
+/// The requested frequency can't be generated within the tolerance.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ToleranceError {
+    /// Closest frequency that can be generated.
+    pub closest: Hertz,
+}
+
+/// Frequency output driver.
+pub struct FrequencyOutput<'d, T: GeneralInstance4Channel> {
+    inner: Timer<'d, T>,
+    channel: Channel,
+    frequency: Hertz,
+}
+
+macro_rules! channel_impl {
+    ($new_chx:ident, $channel:ident) => {
+        impl<'d, T: GeneralInstance4Channel> FrequencyOutput<'d, T> {
+            #[doc = concat!("Create a new frequency output on ", stringify!($channel), ".")]
+            ///
+            /// `tolerance_ppm` is the maximum deviation from `freq`, in parts per million. The output
+            /// is enabled only if the frequency is within tolerance.
+            pub fn $new_chx(
+                tim: impl Peripheral<P = T> + 'd,
+                _pin: PwmPin<'d, T, $channel>,
+                freq: Hertz,
+                tolerance_ppm: u32,
+            ) -> Result<Self, ToleranceError> {
+                Self::new_inner(tim, Channel::$channel, freq, tolerance_ppm)
+            }
+        }
+    };
+}
+
+channel_impl!(new_ch1, Ch1);
+channel_impl!(new_ch2, Ch2);
+channel_impl!(new_ch3, Ch3);
+channel_impl!(new_ch4, Ch4);
+
+impl<'d, T: GeneralInstance4Channel> FrequencyOutput<'d, T> {
+    fn new_inner(
+        tim: impl Peripheral<P = T> + 'd,
+        channel: Channel,
+        freq: Hertz,
+        tolerance_ppm: u32,
+    ) -> Result<Self, ToleranceError> {
+        let mut this = Self {
+            inner: Timer::new(tim),
+            channel,
+            frequency: Hertz(0),
+        };
+
+        this.inner.set_output_compare_mode(channel, OutputCompareMode::PwmMode1);
+        this.inner.set_output_compare_preload(channel, true);
+        this.inner.enable_outputs(); // Required for advanced timers, see GeneralInstance4Channel for details
+        this.set_frequency(freq, tolerance_ppm)?;
+        this.inner.start();
+
+        Ok(this)
+    }
+
+    /// Change the output frequency.
+    ///
+    /// Returns the achieved frequency. If it's not within `tolerance_ppm` of `freq`, the output is
+    /// left unchanged and the closest achievable frequency is returned in the error.
+    pub fn set_frequency(&mut self, freq: Hertz, tolerance_ppm: u32) -> Result<Hertz, ToleranceError> {
+        assert!(freq.0 > 0);
+
+        let max_reload = match T::BITS {
+            TimerBits::Bits16 => u16::MAX as u64,
+            #[cfg(not(stm32l0))]
+            TimerBits::Bits32 => u32::MAX as u64,
+        };
+        let timer_f = self.inner.get_clock_frequency().0 as u64;
+        let (psc, counts) = best_divider(timer_f, freq.0 as u64, max_reload);
+
+        let divider = (psc as u64 + 1) * counts;
+        let achieved = Hertz(((timer_f + divider / 2) / divider) as u32);
+
+        if !within_tolerance(timer_f, divider, freq.0, tolerance_ppm) {
+            return Err(ToleranceError { closest: achieved });
+        }
+
+        // The compare value is preloaded, so set it first for the update event to apply both at once.
+        self.inner.set_compare_value(self.channel, (counts / 2) as u32);
+        self.inner.set_prescaler_and_reload(psc, (counts - 1) as u32);
+        self.inner.enable_channel(self.channel, true);
+        self.frequency = achieved;
+        Ok(achieved)
+    }
+
+    /// Get the output frequency, rounded to the nearest Hz.
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// Enable the output.
+    pub fn enable(&mut self) {
+        self.inner.enable_channel(self.channel, true);
+    }
+
+    /// Disable the output.
+    pub fn disable(&mut self) {
+        self.inner.enable_channel(self.channel, false);
+    }
+}
+
+/// Whether `input_f / divider` is within `tolerance_ppm` of `freq`.
+pub(crate) fn within_tolerance(input_f: u64, divider: u64, freq: u32, tolerance_ppm: u32) -> bool {
+    // |input_f / divider - freq| / freq, in ppm.
+    let error = (input_f as u128).abs_diff(freq as u128 * divider as u128);
+    error * 1_000_000 <= tolerance_ppm as u128 * freq as u128 * divider as u128
+}
+
+/// Find the prescaler and period (in timer counts, at least 2) whose output is closest to `freq`.
+fn best_divider(timer_f: u64, freq: u64, max_reload: u64) -> (u16, u64) {
+    let (timer_f, freq) = (timer_f as u128, freq as u128);
+
+    // Smaller prescalers can't fit the period in the counter.
+    let min_div = timer_f.div_ceil(freq * (max_reload as u128 + 1)).max(1);
+    if min_div > u16::MAX as u128 + 1 {
+        // `freq` is below the lowest achievable frequency.
+        return (u16::MAX, max_reload + 1);
+    }
+
+    let mut best = (u16::MAX, max_reload + 1);
+    let mut best_error = u128::MAX;
+
+    for psc in (min_div - 1) as u16..=u16::MAX {
+        let div = psc as u128 + 1;
+        let counts = (timer_f + div * freq / 2) / (div * freq);
+        if counts < 2 {
+            // Higher prescalers only make it worse.
+            if best_error == u128::MAX {
+                best = (psc, 2);
+            }
+            break;
+        }
+        // Rounding to the nearest count can still overshoot the counter.
+        if counts - 1 > max_reload as u128 {
+            continue;
+        }
+
+        // Compare errors relative to the divider, so they're comparable across prescalers.
+        let divider = div * counts;
+        let error = timer_f.abs_diff(freq * divider) * 1_000_000 / divider;
+        if error < best_error {
+            best = (psc, counts as u64);
+            best_error = error;
+            if error == 0 {
+                break;
+            }
+        }
+    }
+
+    best
+}
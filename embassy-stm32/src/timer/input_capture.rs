@@ -176,6 +176,82 @@ impl<'d, T: GeneralInstance4Channel> InputCapture<'d, T> {
         self.new_future(channel, InputCaptureMode::BothEdges, InputTISelection::Alternate)
             .await
     }
+
+    /// Frequency of the counter, i.e. the unit of the captured values.
+    pub fn get_tick_frequency(&self) -> Hertz {
+        let psc = self.inner.regs_core().psc().read() as u32;
+        self.inner.get_clock_frequency() / (psc + 1)
+    }
+
+    /// Number of counter ticks from `start` to `end`, two values captured on the same channel.
+    ///
+    /// The counter wraps around, so durations of more than one counter period can't be measured:
+    /// lower the tick frequency given to [`new`](Self::new) to measure longer durations.
+    pub fn ticks_between(&self, start: u32, end: u32) -> u32 {
+        let period = self.inner.get_max_compare_value() as u64 + 1;
+        ((end as u64 + period - start as u64) % period) as u32
+    }
+
+    /// Measure the period of the signal on `channel` in counter ticks, between two rising edges.
+    pub async fn measure_period_ticks(&mut self, channel: Channel) -> u32 {
+        let start = self.wait_for_rising_edge(channel).await;
+        let end = self.wait_for_rising_edge(channel).await;
+        self.ticks_between(start, end)
+    }
+
+    /// Measure the frequency of the signal on `channel`, from one period.
+    ///
+    /// Returns 0 Hz if the period is a whole number of counter periods, which can't be told apart
+    /// from no period.
+    pub async fn measure_frequency(&mut self, channel: Channel) -> Hertz {
+        let ticks = self.measure_period_ticks(channel).await;
+        match ticks {
+            0 => Hertz(0),
+            ticks => Hertz(self.get_tick_frequency().0 / ticks),
+        }
+    }
+
+    /// Measure the width of a high pulse on `channel` in counter ticks, from a rising edge to the
+    /// next falling edge.
+    ///
+    /// The capture is switched to the falling edge after the rising edge is handled, so pulses
+    /// shorter than the interrupt latency are missed.
+    pub async fn measure_pulse_width_ticks(&mut self, channel: Channel) -> u32 {
+        let start = self.wait_for_rising_edge(channel).await;
+        let end = self.wait_for_falling_edge(channel).await;
+        self.ticks_between(start, end)
+    }
+
+    /// Stream the counter values captured on `channel` at the edges selected by `mode`.
+    pub fn captures(
+        &mut self,
+        channel: Channel,
+        mode: InputCaptureMode,
+        tisel: InputTISelection,
+    ) -> Captures<'_, 'd, T> {
+        Captures {
+            capture: self,
+            channel,
+            mode,
+            tisel,
+        }
+    }
+}
+
+/// Stream of captured counter values, see [`InputCapture::captures`].
+pub struct Captures<'a, 'd, T: GeneralInstance4Channel> {
+    capture: &'a mut InputCapture<'d, T>,
+    channel: Channel,
+    mode: InputCaptureMode,
+    tisel: InputTISelection,
+}
+
+impl<'a, 'd, T: GeneralInstance4Channel> embassy_futures::stream::Stream for Captures<'a, 'd, T> {
+    type Item = u32;
+
+    async fn next(&mut self) -> u32 {
+        self.capture.new_future(self.channel, self.mode, self.tisel).await
+    }
 }
 
 #[must_use = "futures do nothing unless you `.await` or poll them"]
@@ -177,6 +177,34 @@ impl From<OutputPolarity> for bool {
     }
 }
 
+/// Break input polarity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BreakPolarity {
+    /// The break is triggered when the input is low.
+    ActiveLow,
+    /// The break is triggered when the input is high.
+    ActiveHigh,
+}
+
+impl From<BreakPolarity> for bool {
+    fn from(polarity: BreakPolarity) -> Self {
+        match polarity {
+            BreakPolarity::ActiveLow => false,
+            BreakPolarity::ActiveHigh => true,
+        }
+    }
+}
+
+// BDTR and SR fields, at the same place on all advanced timers.
+const BDTR_OSSI: u32 = 1 << 10;
+const BDTR_OSSR: u32 = 1 << 11;
+const BDTR_BKE: u32 = 1 << 12;
+const BDTR_BKP: u32 = 1 << 13;
+const BDTR_AOE: u32 = 1 << 14;
+const BDTR_BKF_SHIFT: u32 = 16;
+const SR_BIF: u32 = 1 << 7;
+
 /// Low-level timer driver.
 pub struct Timer<'d, T: CoreInstance> {
     tim: PeripheralRef<'d, T>,
@@ -213,6 +241,15 @@ impl<'d, T: CoreInstance> Timer<'d, T> {
         unsafe { crate::pac::timer::TimGp32::from_ptr(T::regs()) }
     }
 
+    /// Get the counter value.
+    pub fn get_counter(&self) -> u32 {
+        match T::BITS {
+            TimerBits::Bits16 => self.regs_core().cnt().read().cnt() as u32,
+            #[cfg(not(stm32l0))]
+            TimerBits::Bits32 => self.regs_gp32_unchecked().cnt().read(),
+        }
+    }
+
     /// Start the timer.
     pub fn start(&self) {
         self.regs_core().cr1().modify(|r| r.set_cen(true));
@@ -276,6 +313,24 @@ impl<'d, T: CoreInstance> Timer<'d, T> {
         }
     }
 
+    /// Set the raw prescaler and auto-reload values.
+    ///
+    /// The timer counts `0..=arr` at the timer clock frequency divided by `psc + 1`.
+    /// `arr` must fit in the counter, i.e. be at most `u16::MAX` for 16-bit timers.
+    pub fn set_prescaler_and_reload(&self, psc: u16, arr: u32) {
+        let regs = self.regs_core();
+        regs.psc().write_value(psc);
+        match T::BITS {
+            TimerBits::Bits16 => regs.arr().write(|r| r.set_arr(unwrap!(u16::try_from(arr)))),
+            #[cfg(not(stm32l0))]
+            TimerBits::Bits32 => self.regs_gp32_unchecked().arr().write_value(arr),
+        }
+
+        regs.cr1().modify(|r| r.set_urs(vals::Urs::COUNTERONLY));
+        regs.egr().write(|r| r.set_ug(true));
+        regs.cr1().modify(|r| r.set_urs(vals::Urs::ANYEVENT));
+    }
+
     /// Set tick frequency.
     pub fn set_tick_freq(&mut self, freq: Hertz) {
         let f = freq;
@@ -601,6 +656,16 @@ impl<'d, T: GeneralInstance4Channel> Timer<'d, T> {
     pub fn set_trigger_source(&self, ts: TriggerSource) {
         self.regs_gp16().smcr().modify(|r| r.set_ts(ts));
     }
+
+    /// Clock the counter from the external trigger input (external clock mode 2).
+    pub fn set_external_clock_mode_2(&self, enable: bool, prescaler: vals::Etps, filter: FilterValue) {
+        self.regs_gp16().smcr().modify(|r| {
+            r.set_etps(prescaler);
+            r.set_etf(filter);
+            r.set_etp(vals::Etp::NOTINVERTED);
+            r.set_ece(enable);
+        });
+    }
 }
 
 #[cfg(not(stm32l0))]
@@ -642,6 +707,64 @@ impl<'d, T: AdvancedInstance1Channel> Timer<'d, T> {
     pub fn set_moe(&self, enable: bool) {
         self.regs_1ch_cmp().bdtr().modify(|w| w.set_moe(enable));
     }
+
+    /// Get state of the MOE-bit, cleared by hardware on a break.
+    pub fn get_moe(&self) -> bool {
+        self.regs_1ch_cmp().bdtr().read().moe()
+    }
+
+    /// Configure the break input.
+    ///
+    /// `filter` is the BKF value, from 0 (no filter) to 15. It is ignored by timers without a
+    /// break filter, such as those of the STM32F1 and STM32F4.
+    pub fn set_break_input(&self, enable: bool, polarity: BreakPolarity, filter: u8) {
+        assert!(filter < 16);
+        self.regs_1ch_cmp().bdtr().modify(|w| {
+            w.0 &= !(BDTR_BKE | BDTR_BKP | 0xF << BDTR_BKF_SHIFT);
+            if enable {
+                w.0 |= BDTR_BKE;
+            }
+            if bool::from(polarity) {
+                w.0 |= BDTR_BKP;
+            }
+            w.0 |= (filter as u32) << BDTR_BKF_SHIFT;
+        });
+    }
+
+    /// Set the automatic output enable: when set, MOE is set again at the next update event
+    /// once the break input is inactive.
+    pub fn set_automatic_output_enable(&self, enable: bool) {
+        self.regs_1ch_cmp().bdtr().modify(|w| match enable {
+            true => w.0 |= BDTR_AOE,
+            false => w.0 &= !BDTR_AOE,
+        });
+    }
+
+    /// Set the off-state selection for run mode (OSSR) and idle mode (OSSI).
+    ///
+    /// When set, disabled outputs are driven to their inactive level instead of being released to
+    /// the GPIO, while MOE is set (run) or cleared (idle), e.g. after a break.
+    pub fn set_off_state_selection(&self, run: bool, idle: bool) {
+        self.regs_1ch_cmp().bdtr().modify(|w| {
+            w.0 &= !(BDTR_OSSR | BDTR_OSSI);
+            if run {
+                w.0 |= BDTR_OSSR;
+            }
+            if idle {
+                w.0 |= BDTR_OSSI;
+            }
+        });
+    }
+
+    /// Get the break interrupt flag, set by hardware on a break.
+    pub fn get_break_flag(&self) -> bool {
+        self.regs_1ch_cmp().sr().read().0 & SR_BIF != 0
+    }
+
+    /// Clear the break interrupt flag.
+    pub fn clear_break_flag(&self) {
+        self.regs_1ch_cmp().sr().modify(|w| w.0 = !SR_BIF);
+    }
 }
 
 #[cfg(not(stm32l0))]
@@ -4,13 +4,22 @@ use core::marker::PhantomData;
 
 use embassy_sync::waitqueue::AtomicWaker;
 
+#[cfg(feature = "time")]
+pub mod buzzer;
 #[cfg(not(stm32l0))]
 pub mod complementary_pwm;
+#[cfg(feature = "time")]
+pub mod frequency_counter;
+pub mod frequency_output;
 pub mod input_capture;
 pub mod low_level;
 pub mod pwm_input;
 pub mod qei;
+#[cfg(not(gpdma))]
+pub mod sent;
 pub mod simple_pwm;
+#[cfg(feature = "time")]
+pub mod soft_pwm;
 
 use crate::interrupt;
 use crate::rcc::RccPeripheral;
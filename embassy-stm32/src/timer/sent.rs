@@ -0,0 +1,271 @@
+//! SENT (SAE J2716) decoder.
+//!
+//! SENT is a unidirectional single-wire protocol used by automotive sensors. Each frame is a
+//! sequence of pulses, measured from falling edge to falling edge, whose length encodes a nibble:
+//!
+//! - a calibration (sync) pulse of 56 clock ticks,
+//! - a status nibble, 1 to 6 data nibbles and a CRC nibble, each 12 to 27 ticks long (value + 12),
+//! - optionally a pause pulse, so that every frame has the same length.
+//!
+//! The nominal clock tick is 3 to 90 µs, and the sensor's actual tick may be off by up to 20%.
+//! This driver captures the falling edges with a timer channel and streams the capture values to
+//! a DMA ring buffer, so no edge is lost regardless of interrupt latency. Every frame is decoded
+//! against its own sync pulse, which compensates for the sensor's clock error.
+//!
+//! Only fast channel frames are decoded. Slow channel messages, which are spread over the status
+//! nibbles of 16 or 18 consecutive frames, can be assembled from [`Frame::status`].
+
+use embassy_hal_internal::into_ref;
+
+use super::input_capture::{CapturePin, Ch1, Ch2, Ch3, Ch4};
+use super::low_level::{FilterValue, InputCaptureMode, InputTISelection, Timer};
+use super::{Channel, GeneralInstance4Channel};
+use crate::dma::ReadableRingBuffer;
+use crate::pac::timer::vals::Ccds;
+use crate::time::Hertz;
+use crate::Peripheral;
+
+/// Timer counts per nominal clock tick.
+const COUNTS_PER_TICK: u32 = 16;
+/// Sync pulse length, in clock ticks.
+const SYNC_TICKS: u32 = 56;
+
+/// CRC variant used by the sensor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CrcMode {
+    /// CRC over the data nibbles, augmented with a trailing zero nibble (J2716 JAN2010 and later).
+    Recommended,
+    /// CRC over the data nibbles only, without augmentation (J2716 FEB2008 and earlier).
+    Legacy,
+}
+
+/// SENT decoder configuration.
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Nominal clock tick of the sensor, in nanoseconds.
+    pub tick_ns: u32,
+    /// Number of data nibbles per frame, from 1 to 6.
+    pub data_nibbles: usize,
+    /// CRC variant.
+    pub crc_mode: CrcMode,
+    /// Whether the sensor sends a pause pulse after each frame.
+    pub pause_pulse: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tick_ns: 3_000,
+            data_nibbles: 6,
+            crc_mode: CrcMode::Recommended,
+            pause_pulse: false,
+        }
+    }
+}
+
+/// SENT decoding error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Edges were lost because the DMA buffer was not read fast enough.
+    Overrun,
+    /// A pulse didn't have a valid nibble length.
+    Framing,
+    /// The CRC nibble didn't match.
+    Crc,
+}
+
+/// A decoded fast channel frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame {
+    /// Status and communication nibble.
+    pub status: u8,
+    data: [u8; 6],
+    len: usize,
+}
+
+impl Frame {
+    /// Data nibbles, in transmission order.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+/// SENT decoder.
+pub struct SentDecoder<'d, T: GeneralInstance4Channel> {
+    ring_buf: ReadableRingBuffer<'d, u16>,
+    _inner: Timer<'d, T>,
+    config: Config,
+    last_edge: Option<u16>,
+    sync_counts: u32,
+    in_frame: bool,
+    // Status, data and CRC nibbles received so far.
+    nibbles: [u8; 8],
+    received: usize,
+    skip_pause: bool,
+}
+
+macro_rules! channel_impl {
+    ($new_chx:ident, $channel:ident, $ch_marker:ident, $dma_ch:ident) => {
+        impl<'d, T: GeneralInstance4Channel> SentDecoder<'d, T> {
+            #[doc = concat!("Create a new SENT decoder on ", stringify!($channel), ".")]
+            ///
+            /// `dma_buf` receives the capture values. Each frame takes up to 10 entries; it should
+            /// hold at least two frames so that the decoder task can fall behind by one frame.
+            pub fn $new_chx(
+                tim: impl Peripheral<P = T> + 'd,
+                _pin: CapturePin<'d, T, $ch_marker>,
+                dma: impl Peripheral<P = impl super::$dma_ch<T>> + 'd,
+                dma_buf: &'d mut [u16],
+                config: Config,
+            ) -> Self {
+                into_ref!(dma);
+                let request = dma.request();
+                let inner = Timer::new(tim);
+                let ccr = inner.regs_gp16().ccr(Channel::$channel.index()).as_ptr() as *mut u16;
+                let ring_buf = unsafe { ReadableRingBuffer::new(dma, request, ccr, dma_buf, Default::default()) };
+                Self::new_inner(inner, ring_buf, Channel::$channel, config)
+            }
+        }
+    };
+}
+
+channel_impl!(new_ch1, Ch1, Ch1, Ch1Dma);
+channel_impl!(new_ch2, Ch2, Ch2, Ch2Dma);
+channel_impl!(new_ch3, Ch3, Ch3, Ch3Dma);
+channel_impl!(new_ch4, Ch4, Ch4, Ch4Dma);
+
+impl<'d, T: GeneralInstance4Channel> SentDecoder<'d, T> {
+    fn new_inner(
+        mut inner: Timer<'d, T>,
+        mut ring_buf: ReadableRingBuffer<'d, u16>,
+        channel: Channel,
+        config: Config,
+    ) -> Self {
+        assert!(config.tick_ns > 0);
+        assert!(config.data_nibbles >= 1 && config.data_nibbles <= 6);
+
+        // The longest pulse (a 768 tick pause pulse, 20% slow) must fit in the 16-bit capture range.
+        inner.set_tick_freq(Hertz(
+            (1_000_000_000u64 * COUNTS_PER_TICK as u64 / config.tick_ns as u64) as u32,
+        ));
+        inner.enable_outputs(); // Required for advanced timers, see GeneralInstance4Channel for details
+
+        inner.set_input_ti_selection(channel, InputTISelection::Normal);
+        inner.set_input_capture_filter(channel, FilterValue::NOFILTER);
+        inner.set_input_capture_mode(channel, InputCaptureMode::Falling);
+        inner.set_input_capture_prescaler(channel, 0);
+        inner.set_cc_dma_selection(Ccds::ONCOMPARE);
+        inner.set_cc_dma_enable_state(channel, true);
+        inner.enable_channel(channel, true);
+
+        ring_buf.start();
+        inner.start();
+
+        Self {
+            ring_buf,
+            _inner: inner,
+            config,
+            last_edge: None,
+            sync_counts: 0,
+            in_frame: false,
+            nibbles: [0; 8],
+            received: 0,
+            skip_pause: false,
+        }
+    }
+
+    /// Wait for the next valid frame.
+    ///
+    /// Errors are reported once per bad frame; decoding resumes at the next sync pulse.
+    pub async fn read(&mut self) -> Result<Frame, Error> {
+        loop {
+            let mut edge = [0u16; 1];
+            if self.ring_buf.read_exact(&mut edge).await.is_err() {
+                self.ring_buf.clear();
+                self.last_edge = None;
+                self.in_frame = false;
+                return Err(Error::Overrun);
+            }
+
+            let Some(last) = self.last_edge.replace(edge[0]) else {
+                continue;
+            };
+            let period = edge[0].wrapping_sub(last) as u32;
+
+            if let Some(frame) = self.push_pulse(period)? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    fn push_pulse(&mut self, period: u32) -> Result<Option<Frame>, Error> {
+        if self.skip_pause {
+            self.skip_pause = false;
+            return Ok(None);
+        }
+
+        // The sensor's clock may be off by 20%; a nibble pulse is at most 27 * 1.2 ticks, far from 56 * 0.8.
+        let nominal_sync = SYNC_TICKS * COUNTS_PER_TICK;
+        if period > nominal_sync * 3 / 4 && period < nominal_sync * 5 / 4 {
+            self.sync_counts = period;
+            self.in_frame = true;
+            self.received = 0;
+            return Ok(None);
+        }
+
+        if !self.in_frame {
+            // Wait for a sync pulse.
+            return Ok(None);
+        }
+
+        // Round to the nearest tick, scaling by the measured sync pulse.
+        let ticks = (period * SYNC_TICKS * 2 + self.sync_counts) / (self.sync_counts * 2);
+        if !(12..=27).contains(&ticks) {
+            self.in_frame = false;
+            return Err(Error::Framing);
+        }
+        self.nibbles[self.received] = (ticks - 12) as u8;
+        self.received += 1;
+
+        // Status, data, CRC.
+        let len = self.config.data_nibbles;
+        if self.received < len + 2 {
+            return Ok(None);
+        }
+        self.in_frame = false;
+        self.skip_pause = self.config.pause_pulse;
+
+        let data = &self.nibbles[1..=len];
+        if crc4(data, self.config.crc_mode) != self.nibbles[len + 1] {
+            return Err(Error::Crc);
+        }
+
+        let mut frame = Frame {
+            status: self.nibbles[0],
+            data: [0; 6],
+            len,
+        };
+        frame.data[..len].copy_from_slice(data);
+        Ok(Some(frame))
+    }
+}
+
+fn crc4(data: &[u8], mode: CrcMode) -> u8 {
+    // x^4 + x^3 + x^2 + 1, seed 0b0101.
+    const TABLE: [u8; 16] = [0, 13, 7, 10, 14, 3, 9, 4, 1, 12, 6, 11, 15, 2, 8, 5];
+
+    let mut crc = 5;
+    for &nibble in data {
+        crc = nibble ^ TABLE[crc as usize];
+    }
+    if mode == CrcMode::Recommended {
+        crc = TABLE[crc as usize];
+    }
+    crc
+}
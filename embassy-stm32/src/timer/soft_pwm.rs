@@ -0,0 +1,129 @@
+//! Software PWM driver.
+//!
+//! Generates PWM on any GPIO pins using the embassy time driver instead of a timer peripheral.
+//! This is a fallback for boards where all suitable timer channels are already in use, and is only
+//! suitable for low frequencies such as LED dimming or slow heaters/fans.
+//!
+//! # Jitter
+//!
+//! Edges are produced by a task waking up on an embassy [`Timer`], so they are subject to:
+//!
+//! - the resolution of the time driver (one tick of `embassy_time::TICK_HZ`),
+//! - the interrupt latency of the time driver alarm,
+//! - the time until the executor gets around to polling the PWM task, which depends on what
+//!   other tasks in the same executor are doing. A task that runs for 1 ms without yielding
+//!   delays every edge by up to 1 ms.
+//!
+//! Edges are scheduled against absolute instants, so jitter does not accumulate over time; a late
+//! edge only shortens or stretches that single pulse. Use a higher-priority interrupt executor for
+//! the PWM task if you need tighter edges, and a hardware timer if you need exact ones.
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::gpio::Output;
+use crate::time::Hertz;
+
+/// Software PWM driver for `N` channels.
+///
+/// Create it with a set of [`Output`] pins, then run [`run`](Self::run) in a task. Duty cycles can
+/// be changed at any time through a shared reference, and take effect at the start of the next period.
+pub struct SoftPwm<'d, const N: usize> {
+    pins: Mutex<CriticalSectionRawMutex, RefCell<[Output<'d>; N]>>,
+    period: Duration,
+    duty: [AtomicU32; N],
+}
+
+impl<'d, const N: usize> SoftPwm<'d, N> {
+    /// Create a new software PWM driver.
+    ///
+    /// All channels start at 0% duty.
+    pub fn new(pins: [Output<'d>; N], freq: Hertz) -> Self {
+        let period = Duration::from_hz(freq.0 as u64);
+        assert!(period.as_ticks() > 0 && period.as_ticks() <= u32::MAX as u64);
+
+        Self {
+            pins: Mutex::new(RefCell::new(pins)),
+            period,
+            duty: core::array::from_fn(|_| AtomicU32::new(0)),
+        }
+    }
+
+    /// Get max duty value.
+    ///
+    /// This is the PWM period expressed in ticks of the embassy time driver.
+    pub fn get_max_duty(&self) -> u32 {
+        self.period.as_ticks() as u32
+    }
+
+    /// Set the duty for a given channel.
+    ///
+    /// The value ranges from 0 for 0% duty, to [`get_max_duty`](Self::get_max_duty) for 100% duty, both included.
+    pub fn set_duty(&self, channel: usize, duty: u32) {
+        assert!(duty <= self.get_max_duty());
+        self.duty[channel].store(duty, Ordering::Relaxed);
+    }
+
+    /// Get the duty for a given channel.
+    ///
+    /// The value ranges from 0 for 0% duty, to [`get_max_duty`](Self::get_max_duty) for 100% duty, both included.
+    pub fn get_duty(&self, channel: usize) -> u32 {
+        self.duty[channel].load(Ordering::Relaxed)
+    }
+
+    /// Run the PWM generation.
+    ///
+    /// This never returns; run it in its own task, or `join` it with the code updating the duty cycles.
+    pub async fn run(&self) -> ! {
+        let mut start = Instant::now();
+        loop {
+            // Snapshot duties so a period is always generated from a consistent set of values.
+            let duty: [u32; N] = core::array::from_fn(|i| self.duty[i].load(Ordering::Relaxed));
+
+            self.with_pins(|pins| {
+                for (pin, &d) in pins.iter_mut().zip(duty.iter()) {
+                    if d > 0 {
+                        pin.set_high();
+                    } else {
+                        pin.set_low();
+                    }
+                }
+            });
+
+            // Walk through the falling edges of this period in increasing order.
+            let mut done = 0u32;
+            while let Some(edge) = duty
+                .iter()
+                .copied()
+                .filter(|&d| d > done && d < self.get_max_duty())
+                .min()
+            {
+                Timer::at(start + Duration::from_ticks(edge as u64)).await;
+                self.with_pins(|pins| {
+                    for (pin, &d) in pins.iter_mut().zip(duty.iter()) {
+                        if d == edge {
+                            pin.set_low();
+                        }
+                    }
+                });
+                done = edge;
+            }
+
+            start += self.period;
+            let now = Instant::now();
+            if now > start + self.period {
+                // We fell more than a full period behind, don't try to catch up with a burst of short pulses.
+                start = now;
+            }
+            Timer::at(start).await;
+        }
+    }
+
+    fn with_pins<R>(&self, f: impl FnOnce(&mut [Output<'d>; N]) -> R) -> R {
+        self.pins.lock(|pins| f(&mut pins.borrow_mut()))
+    }
+}
@@ -0,0 +1,440 @@
+//! AT command engine for cellular modems.
+//!
+//! Modems such as the SIMCom SIM7000 or Quectel BG96 are driven with AT commands over a UART:
+//! the host sends a command line, the modem answers with response lines ended by a final result
+//! code (`OK`, `ERROR`, `+CME ERROR: <n>`...). Unsolicited result codes (URCs), such as `+CREG: 1`
+//! or `RING`, may arrive at any time, including between the lines of a response.
+//!
+//! The engine is split in two halves sharing an [`AtState`]:
+//! - [`AtIngress`] owns the receive half of a [`BufferedUart`](super::BufferedUart) and runs in
+//!   its own task. It splits the input into lines, hands response lines to the pending command
+//!   and publishes URCs to subscribers.
+//! - [`AtClient`] owns the transmit half, sends commands and waits for their response, with a
+//!   timeout.
+//!
+//! Lines received while no command is pending, and lines starting with one of the URC prefixes
+//! given to [`AtIngress::new`], are URCs. Lines starting with `AT` are the modem echoing the
+//! command, and are ignored.
+//!
+//! For PPP or transparent socket modes, [`AtClient::connect`] sends a dial command and waits for
+//! `CONNECT`, after which [`AtIngress::run`] returns: release both halves to hand the raw UART to
+//! the data mode, e.g. `embassy-net-ppp`.
+//!
+//! ```rust,ignore
+//! static STATE: AtState<128, 8, 2> = AtState::new();
+//!
+//! #[embassy_executor::task]
+//! async fn ingress_task(mut ingress: AtIngress<'static, 'static, 128, 8, 2>) {
+//!     ingress.run().await;
+//! }
+//!
+//! let (tx, rx) = uart.split();
+//! spawner.spawn(ingress_task(AtIngress::new(rx, &STATE, &[b"+CREG:", b"+CEREG:"])))?;
+//! let mut client = AtClient::new(tx, &STATE, Config::default());
+//! let mut urcs = STATE.subscribe()?;
+//!
+//! let mut buf = [0; 64];
+//! let n = client.send(b"AT+CSQ", &mut buf).await?;
+//! ```
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pubsub::{self, PubSubChannel, Subscriber};
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration};
+use embedded_io_async::{BufRead, Write};
+
+use super::{BufferedUartRx, BufferedUartTx};
+
+/// AT command error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// UART error.
+    Uart(super::Error),
+    /// No final result code was received in time.
+    Timeout,
+    /// The modem answered `ERROR`, or `NO CARRIER`, `BUSY`, `NO ANSWER`, `NO DIALTONE`.
+    Error,
+    /// The modem answered `+CME ERROR: <n>`.
+    CmeError(u16),
+    /// The modem answered `+CMS ERROR: <n>`.
+    CmsError(u16),
+    /// The response doesn't fit in the buffer.
+    Overflow,
+}
+
+/// AT client configuration.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Time to wait for the final result code of a command.
+    pub timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A line of up to `LEN` bytes, without its line ending.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Line<const LEN: usize> {
+    data: [u8; LEN],
+    len: usize,
+}
+
+impl<const LEN: usize> Line<LEN> {
+    const fn new() -> Self {
+        Self { data: [0; LEN], len: 0 }
+    }
+
+    /// The line, truncated to `LEN` bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Append bytes, returning `false` if they were truncated.
+    fn push(&mut self, bytes: &[u8]) -> bool {
+        let n = bytes.len().min(LEN - self.len);
+        self.data[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        self.len += n;
+        n == bytes.len()
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+/// Final result code of a command.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Final {
+    Ok,
+    /// The modem waits for data after `>`.
+    Prompt,
+    Connect,
+    Error(Error),
+}
+
+/// Response to the command with sequence number `seq`.
+struct Response<const LEN: usize> {
+    seq: u32,
+    result: Final,
+    /// Response lines, separated by `\n`.
+    data: Line<LEN>,
+    overflow: bool,
+}
+
+/// State shared by an [`AtClient`] and an [`AtIngress`].
+///
+/// Responses and URCs are up to `LEN` bytes. Up to `SUBS` URC subscribers each receive the last
+/// `CAP` URCs.
+pub struct AtState<const LEN: usize, const CAP: usize, const SUBS: usize> {
+    /// Sequence number of the last command sent.
+    seq: AtomicU32,
+    /// A command is waiting for its response.
+    pending: AtomicBool,
+    response: Signal<CriticalSectionRawMutex, Response<LEN>>,
+    urcs: PubSubChannel<CriticalSectionRawMutex, Line<LEN>, CAP, SUBS, 1>,
+}
+
+impl<const LEN: usize, const CAP: usize, const SUBS: usize> AtState<LEN, CAP, SUBS> {
+    /// Create a new state.
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+            pending: AtomicBool::new(false),
+            response: Signal::new(),
+            urcs: PubSubChannel::new(),
+        }
+    }
+
+    /// Subscribe to URCs.
+    ///
+    /// A subscriber that doesn't keep up misses the oldest URCs, and is told how many it missed.
+    pub fn subscribe(&self) -> Result<Subscriber<'_, CriticalSectionRawMutex, Line<LEN>, CAP, SUBS, 1>, pubsub::Error> {
+        self.urcs.subscriber()
+    }
+}
+
+/// Receive half of the AT engine, see the [module documentation](self).
+pub struct AtIngress<'a, 'd, const LEN: usize, const CAP: usize, const SUBS: usize> {
+    rx: BufferedUartRx<'d>,
+    parser: Parser<'a, LEN, CAP, SUBS>,
+}
+
+impl<'a, 'd, const LEN: usize, const CAP: usize, const SUBS: usize> AtIngress<'a, 'd, LEN, CAP, SUBS> {
+    /// Create a new ingress.
+    ///
+    /// Lines starting with one of `urc_prefixes` are URCs even while a command is pending.
+    pub fn new(rx: BufferedUartRx<'d>, state: &'a AtState<LEN, CAP, SUBS>, urc_prefixes: &'a [&'a [u8]]) -> Self {
+        Self {
+            rx,
+            parser: Parser {
+                state,
+                urc_prefixes,
+                line: Line::new(),
+                response: Line::new(),
+                seq: 0,
+                overflow: false,
+            },
+        }
+    }
+
+    /// Release the receive half of the UART.
+    pub fn release(self) -> BufferedUartRx<'d> {
+        self.rx
+    }
+
+    /// Process the input, until the modem enters data mode with `CONNECT`.
+    ///
+    /// UART errors, such as overruns, drop the line being received.
+    pub async fn run(&mut self) {
+        loop {
+            let buf = match self.rx.fill_buf().await {
+                Ok(buf) => buf,
+                Err(_) => {
+                    self.parser.line.clear();
+                    continue;
+                }
+            };
+
+            let mut n = 0;
+            let mut connected = false;
+            // Leave the data following `CONNECT` in the UART buffer.
+            while n < buf.len() && !connected {
+                connected = self.parser.feed(buf[n]);
+                n += 1;
+            }
+            self.rx.consume(n);
+
+            if connected {
+                return;
+            }
+        }
+    }
+}
+
+/// Splits the input into lines and routes them.
+struct Parser<'a, const LEN: usize, const CAP: usize, const SUBS: usize> {
+    state: &'a AtState<LEN, CAP, SUBS>,
+    urc_prefixes: &'a [&'a [u8]],
+    line: Line<LEN>,
+    response: Line<LEN>,
+    /// Sequence number of the command `response` belongs to.
+    seq: u32,
+    overflow: bool,
+}
+
+impl<'a, const LEN: usize, const CAP: usize, const SUBS: usize> Parser<'a, LEN, CAP, SUBS> {
+    /// Handle a received byte, returning `true` on `CONNECT`.
+    fn feed(&mut self, byte: u8) -> bool {
+        match byte {
+            b'\r' | b'\n' if self.line.len > 0 => {
+                let connected = self.dispatch();
+                self.line.clear();
+                connected
+            }
+            b'\r' | b'\n' => false,
+            // The prompt isn't followed by a line ending.
+            b'>' if self.line.len == 0 && self.state.pending.load(Ordering::Acquire) => {
+                self.finish(Final::Prompt);
+                false
+            }
+            _ => {
+                self.line.push(&[byte]);
+                false
+            }
+        }
+    }
+
+    /// Handle a received line, returning `true` on `CONNECT`.
+    fn dispatch(&mut self) -> bool {
+        let line = self.line.as_bytes();
+        if line.starts_with(b"AT") {
+            return false;
+        }
+
+        let is_urc = self.urc_prefixes.iter().any(|p| line.starts_with(p));
+        if is_urc || !self.state.pending.load(Ordering::Acquire) {
+            self.state.urcs.immediate_publisher().publish_immediate(self.line);
+            return false;
+        }
+
+        let result = match line {
+            b"OK" => Final::Ok,
+            b"ERROR" | b"NO CARRIER" | b"BUSY" | b"NO ANSWER" | b"NO DIALTONE" => Final::Error(Error::Error),
+            _ if line.starts_with(b"CONNECT") => Final::Connect,
+            _ if line.starts_with(b"+CME ERROR:") => Final::Error(Error::CmeError(parse_code(&line[11..]))),
+            _ if line.starts_with(b"+CMS ERROR:") => Final::Error(Error::CmsError(parse_code(&line[11..]))),
+            _ => {
+                self.sync();
+                let sep_ok = self.response.len == 0 || self.response.push(b"\n");
+                self.overflow |= !(sep_ok && self.response.push(self.line.as_bytes()));
+                return false;
+            }
+        };
+
+        self.finish(result);
+        result == Final::Connect
+    }
+
+    /// Complete the pending command.
+    fn finish(&mut self, result: Final) {
+        self.sync();
+        if result != Final::Prompt {
+            self.state.pending.store(false, Ordering::Release);
+        }
+        self.state.response.signal(Response {
+            seq: self.seq,
+            result,
+            data: self.response,
+            overflow: self.overflow,
+        });
+        self.response.clear();
+        self.overflow = false;
+    }
+
+    /// Drop the response lines of an abandoned command.
+    fn sync(&mut self) {
+        let seq = self.state.seq.load(Ordering::Acquire);
+        if seq != self.seq {
+            self.seq = seq;
+            self.response.clear();
+            self.overflow = false;
+        }
+    }
+}
+
+/// Transmit half of the AT engine, see the [module documentation](self).
+pub struct AtClient<'a, 'd, const LEN: usize, const CAP: usize, const SUBS: usize> {
+    tx: BufferedUartTx<'d>,
+    state: &'a AtState<LEN, CAP, SUBS>,
+    config: Config,
+}
+
+impl<'a, 'd, const LEN: usize, const CAP: usize, const SUBS: usize> AtClient<'a, 'd, LEN, CAP, SUBS> {
+    /// Create a new client.
+    pub fn new(tx: BufferedUartTx<'d>, state: &'a AtState<LEN, CAP, SUBS>, config: Config) -> Self {
+        Self { tx, state, config }
+    }
+
+    /// Release the transmit half of the UART.
+    pub fn release(self) -> BufferedUartTx<'d> {
+        self.tx
+    }
+
+    /// Set the time to wait for the final result code of a command.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.config.timeout = timeout;
+    }
+
+    /// Send a command, such as `b"AT+CSQ"`, and wait for its final result code.
+    ///
+    /// The response lines, without the final result code, are copied to `response` separated by
+    /// `\n`, and their length is returned.
+    pub async fn send(&mut self, command: &[u8], response: &mut [u8]) -> Result<usize, Error> {
+        let seq = self.start(command).await?;
+        match self.wait(seq).await? {
+            (Final::Ok, data) => copy_response(&data, response),
+            (Final::Error(e), _) => Err(e),
+            // Unexpected for this command: report it as an error rather than losing data mode.
+            (Final::Prompt | Final::Connect, _) => Err(Error::Error),
+        }
+    }
+
+    /// Send a command, such as `b"AT+CIPSEND=0,5"`, then `data` once the modem prompts for it
+    /// with `>`, and wait for the final result code.
+    ///
+    /// Terminators expected by the modem after the data, such as Ctrl-Z, must be part of `data`.
+    pub async fn send_with_data(&mut self, command: &[u8], data: &[u8], response: &mut [u8]) -> Result<usize, Error> {
+        let seq = self.start(command).await?;
+        match self.wait(seq).await? {
+            (Final::Prompt, _) => {}
+            (Final::Error(e), _) => return Err(e),
+            _ => return Err(Error::Error),
+        }
+
+        self.tx.write_all(data).await.map_err(Error::Uart)?;
+        match self.wait(seq).await? {
+            (Final::Ok, data) => copy_response(&data, response),
+            (Final::Error(e), _) => Err(e),
+            _ => Err(Error::Error),
+        }
+    }
+
+    /// Send a dial command, such as `b"ATD*99#"`, and wait for `CONNECT`.
+    ///
+    /// On success, the modem is in data mode and [`AtIngress::run`] returns. Release both halves
+    /// of the engine to use the UART for PPP or raw data.
+    pub async fn connect(&mut self, command: &[u8]) -> Result<(), Error> {
+        let seq = self.start(command).await?;
+        match self.wait(seq).await? {
+            (Final::Connect, _) => Ok(()),
+            (Final::Error(e), _) => Err(e),
+            _ => Err(Error::Error),
+        }
+    }
+
+    /// Send a command line, returning its sequence number.
+    async fn start(&mut self, command: &[u8]) -> Result<u32, Error> {
+        let seq = self.state.seq.fetch_add(1, Ordering::AcqRel).wrapping_add(1);
+        self.state.response.reset();
+        self.state.pending.store(true, Ordering::Release);
+
+        let res = async {
+            self.tx.write_all(command).await?;
+            self.tx.write_all(b"\r").await
+        }
+        .await;
+        if let Err(e) = res {
+            self.state.pending.store(false, Ordering::Release);
+            return Err(Error::Uart(e));
+        }
+        Ok(seq)
+    }
+
+    /// Wait for the next result of the command `seq`.
+    async fn wait(&mut self, seq: u32) -> Result<(Final, Line<LEN>), Error> {
+        let res = with_timeout(self.config.timeout, async {
+            loop {
+                let response = self.state.response.wait().await;
+                if response.seq == seq {
+                    return response;
+                }
+            }
+        })
+        .await;
+
+        match res {
+            Ok(response) if response.overflow => Err(Error::Overflow),
+            Ok(response) => Ok((response.result, response.data)),
+            Err(_) => {
+                self.state.pending.store(false, Ordering::Release);
+                Err(Error::Timeout)
+            }
+        }
+    }
+}
+
+fn copy_response<const LEN: usize>(data: &Line<LEN>, response: &mut [u8]) -> Result<usize, Error> {
+    let data = data.as_bytes();
+    let dst = response.get_mut(..data.len()).ok_or(Error::Overflow)?;
+    dst.copy_from_slice(data);
+    Ok(data.len())
+}
+
+/// Parse the decimal error code of `+CME ERROR:` and `+CMS ERROR:`.
+fn parse_code(s: &[u8]) -> u16 {
+    s.iter()
+        .skip_while(|b| **b == b' ')
+        .take_while(|b| b.is_ascii_digit())
+        .fold(0u16, |n, b| n.wrapping_mul(10).wrapping_add((b - b'0') as u16))
+}
@@ -12,8 +12,8 @@ use embassy_sync::waitqueue::AtomicWaker;
 #[cfg(not(any(usart_v1, usart_v2)))]
 use super::DePin;
 use super::{
-    clear_interrupt_flags, configure, rdr, reconfigure, sr, tdr, Config, ConfigError, CtsPin, Error, Info, Instance,
-    Regs, RtsPin, RxPin, TxPin,
+    clear_interrupt_flags, configure, rdr, reconfigure, send_break, sr, tdr, Config, ConfigError, CtsPin, Error, Info,
+    Instance, Regs, RtsPin, RxPin, TxPin,
 };
 use crate::gpio::{AfType, AnyPin, OutputType, Pull, SealedPin as _, Speed};
 use crate::interrupt::{self, InterruptExt};
@@ -32,17 +32,36 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
 
 unsafe fn on_interrupt(r: Regs, state: &'static State) {
     // RX
+    let rx_full = state.rx_buf.is_full();
+    if !rx_full && state.rx_buf.len() != 0 && !r.cr1().read().rxneie() {
+        // The reader made room in the buffer and pended the interrupt: resume receiving.
+        r.cr1().modify(|w| {
+            w.set_rxneie(true);
+            w.set_idleie(true);
+        });
+    }
+
     let sr_val = sr(r).read();
     // On v1 & v2, reading DR clears the rxne, error and idle interrupt
     // flags. Keep this close to the SR read to reduce the chance of a
     // flag being set in-between.
-    let dr = if sr_val.rxne() || cfg!(any(usart_v1, usart_v2)) && (sr_val.ore() || sr_val.idle()) {
+    let dr = if !rx_full && (sr_val.rxne() || cfg!(any(usart_v1, usart_v2)) && (sr_val.ore() || sr_val.idle())) {
         Some(rdr(r).read_volatile())
     } else {
         None
     };
     clear_interrupt_flags(r, sr_val);
 
+    if rx_full && (sr_val.rxne() || sr_val.idle()) {
+        // Leave the received byte in RDR and stop receiving until the reader makes room.
+        // RXNE stays set meanwhile, which deasserts RTS if hardware flow control is enabled,
+        // so the sender pauses instead of data being dropped.
+        r.cr1().modify(|w| {
+            w.set_rxneie(false);
+            w.set_idleie(false);
+        });
+    }
+
     if sr_val.pe() {
         warn!("Parity error");
     }
@@ -63,8 +82,6 @@ unsafe fn on_interrupt(r: Regs, state: &'static State) {
                 buf[0] = byte;
                 rx_writer.push_done(1);
             }
-        } else {
-            // FIXME: Should we disable any further RX interrupts when the buffer becomes full.
         }
 
         if !state.rx_buf.is_empty() {
@@ -222,6 +239,8 @@ impl<'d> BufferedUart<'d> {
     }
 
     /// Create a new bidirectional buffered UART driver with request-to-send and clear-to-send pins
+    ///
+    /// RTS is deasserted while the rx buffer is full, so the sender pauses instead of data being lost.
     pub fn new_with_rtscts<T: Instance>(
         peri: impl Peripheral<P = T> + 'd,
         _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
@@ -348,6 +367,11 @@ impl<'d> BufferedUart<'d> {
         (self.tx, self.rx)
     }
 
+    /// Send a break: hold the line low for one frame, or 13 bits in LIN mode.
+    pub fn send_break(&self) {
+        self.tx.send_break();
+    }
+
     /// Reconfigure the driver
     pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
         reconfigure(self.rx.info, self.rx.kernel_clock, config)?;
@@ -521,6 +545,11 @@ impl<'d> BufferedUartTx<'d> {
         }
     }
 
+    /// Send a break: hold the line low for one frame, or 13 bits in LIN mode.
+    pub fn send_break(&self) {
+        send_break(self.info.regs);
+    }
+
     /// Reconfigure the driver
     pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
         reconfigure(self.info, self.kernel_clock, config)?;
@@ -0,0 +1,121 @@
+//! DMX512 output.
+//!
+//! DMX512 sends a universe of up to 512 channel values (slots) as a packet of 250 kbaud 8N2 UART
+//! frames, preceded by a break of at least 88 µs and a mark-after-break of at least 8 µs.
+//!
+//! The USART break feature only holds the line low for a single character time (44 µs at 250 kbaud),
+//! which is too short. Instead, the break is produced by sending a single `0x00` at a lower baud rate:
+//! at 100 kbaud the start bit and 8 data bits hold the line low for 90 µs, and the two stop bits
+//! provide a 20 µs mark-after-break. The slots are then sent with DMA at 250 kbaud.
+//!
+//! The USART is briefly disabled while switching baud rates. Make sure the line idles high while the
+//! TX pin is not driven, e.g. with the RS-485 transceiver's fail-safe biasing or a pull-up.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+
+use super::{Config, ConfigError, Error, StopBits, UartTx};
+use crate::mode::Async;
+
+/// Number of slots in a full DMX512 universe.
+pub const DMX_SLOTS: usize = 512;
+
+/// Channel values shared between [`DmxOutput::run`] and the tasks updating them. Index 0 is
+/// channel 1.
+pub type Universe<M> = Mutex<M, RefCell<[u8; DMX_SLOTS]>>;
+
+/// DMX512 transmitter.
+///
+/// Either hand a shared [`Universe`] to [`run`](Self::run) in a task of its own, and update it from
+/// other tasks, or update channel values with [`set_channel`](Self::set_channel) or
+/// [`slots_mut`](Self::slots_mut) and call [`send`](Self::send) for each packet.
+pub struct DmxOutput<'d> {
+    tx: UartTx<'d, Async>,
+    // Start code followed by the slots.
+    packet: [u8; DMX_SLOTS + 1],
+    slots: usize,
+    break_config: Config,
+    data_config: Config,
+}
+
+impl<'d> DmxOutput<'d> {
+    /// Create a new DMX512 transmitter sending the first `slots` channels of the universe.
+    ///
+    /// `tx` is reconfigured as needed, so its original configuration doesn't matter. The universe
+    /// starts with all channels at 0 and the null start code.
+    pub fn new(mut tx: UartTx<'d, Async>, slots: usize) -> Result<Self, ConfigError> {
+        assert!(slots > 0 && slots <= DMX_SLOTS);
+
+        let mut data_config = Config::default();
+        data_config.baudrate = 250_000;
+        data_config.stop_bits = StopBits::STOP2;
+
+        let mut break_config = data_config;
+        break_config.baudrate = 100_000;
+
+        // Check both configurations up front so switching between them later can't fail.
+        tx.set_config(&break_config)?;
+        tx.set_config(&data_config)?;
+
+        Ok(Self {
+            tx,
+            packet: [0; DMX_SLOTS + 1],
+            slots,
+            break_config,
+            data_config,
+        })
+    }
+
+    /// Set the start code sent before the slots. The default is 0 (dimmer data).
+    pub fn set_start_code(&mut self, start_code: u8) {
+        self.packet[0] = start_code;
+    }
+
+    /// Set the value of a channel. Channels are numbered from 1 to 512, like on the fixtures.
+    pub fn set_channel(&mut self, channel: u16, value: u8) {
+        assert!(channel >= 1 && channel as usize <= DMX_SLOTS);
+        self.packet[channel as usize] = value;
+    }
+
+    /// Get the value of a channel. Channels are numbered from 1 to 512.
+    pub fn channel(&self, channel: u16) -> u8 {
+        assert!(channel >= 1 && channel as usize <= DMX_SLOTS);
+        self.packet[channel as usize]
+    }
+
+    /// Get all slots being sent. Index 0 is channel 1.
+    pub fn slots_mut(&mut self) -> &mut [u8] {
+        &mut self.packet[1..=self.slots]
+    }
+
+    /// Send one packet with the current universe.
+    ///
+    /// Returns once the last slot has been shifted out.
+    pub async fn send(&mut self) -> Result<(), Error> {
+        // Both configurations were checked in `new`.
+        unwrap!(self.tx.set_config(&self.break_config));
+        self.tx.write(&[0]).await?;
+        self.tx.flush().await?;
+
+        unwrap!(self.tx.set_config(&self.data_config));
+        self.tx.write(&self.packet[..=self.slots]).await?;
+        self.tx.flush().await
+    }
+
+    /// Send packets of `universe` back to back forever.
+    ///
+    /// A full universe takes about 23 ms, for a refresh rate of about 44 Hz. Receivers consider the
+    /// link lost after one second without packets, so this should run in its own task, while other
+    /// tasks update `universe`. Each packet sends the values of `universe` when it starts.
+    pub async fn run<M: RawMutex>(&mut self, universe: &Universe<M>) -> ! {
+        loop {
+            let slots = self.slots;
+            universe.lock(|universe| self.packet[1..=slots].copy_from_slice(&universe.borrow()[..slots]));
+            if let Err(_e) = self.send().await {
+                warn!("DMX send failed: {:?}", _e);
+            }
+        }
+    }
+}
@@ -0,0 +1,491 @@
+//! Log records sent to a host over UART, with DMA.
+//!
+//! [`UartLogger`] is a multi-producer queue of log records, drained by a dedicated task running
+//! [`UartLogger::run`]. Tasks and interrupt handlers format their records into a free slot of the
+//! queue without waiting for the UART, instead of formatting straight to a UART shared behind a
+//! mutex, which makes every task logging wait for the others' records to be sent.
+//!
+//! Logging never blocks: a record longer than a slot is truncated, and a record logged while all
+//! slots are full is dropped. Both are counted, and the drain task reports dropped records to the
+//! host. Claiming a slot is lock-free on chips with atomic compare-and-swap; on Cortex-M0 it takes
+//! a short critical section.
+//!
+//! Formatting still takes time in the producer, which matters in interrupt handlers.
+//! [`UartLogger::write_deferred`] only copies the format string reference and the [`Arg`]uments
+//! into the slot, and the drain task formats the record when it sends it.
+//!
+//! ```rust,ignore
+//! static LOGGER: UartLogger<16, 128> = UartLogger::new();
+//!
+//! #[embassy_executor::task]
+//! async fn logger_task(tx: UartTx<'static, Async>) {
+//!     LOGGER.run(tx).await
+//! }
+//!
+//! // From any task or interrupt handler:
+//! write!(LOGGER, "temperature: {}\r\n", temp);
+//!
+//! // Formatted by the drain task:
+//! LOGGER.write_deferred("temperature: {}, status: {:x}\r\n", &[temp.into(), status.into()]);
+//! ```
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write as _};
+use core::future::poll_fn;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use core::task::Poll;
+
+use embassy_sync::waitqueue::AtomicWaker;
+
+use super::UartTx;
+use crate::mode::Async;
+
+struct Slot<const LEN: usize> {
+    /// Sequence number, `pos` when the slot is free for the record at `pos`, `pos + 1` once that
+    /// record is written.
+    seq: AtomicUsize,
+    len: UnsafeCell<usize>,
+    /// Whether `data` holds a deferred record, see [`UartLogger::write_deferred`].
+    deferred: UnsafeCell<bool>,
+    data: UnsafeCell<[u8; LEN]>,
+}
+
+impl<const LEN: usize> Slot<LEN> {
+    const NEW: Self = Self {
+        seq: AtomicUsize::new(0),
+        len: UnsafeCell::new(0),
+        deferred: UnsafeCell::new(false),
+        data: UnsafeCell::new([0; LEN]),
+    };
+}
+
+/// Log record queue drained to a UART, see the [module documentation](self).
+///
+/// The queue holds up to `SLOTS` records of up to `LEN` bytes each. `SLOTS` must be a power of two.
+pub struct UartLogger<const SLOTS: usize, const LEN: usize> {
+    slots: [Slot<LEN>; SLOTS],
+    /// Position of the next record to be claimed by a producer.
+    head: AtomicUsize,
+    /// Position of the next record to be sent, only written by the drain task.
+    tail: AtomicUsize,
+    waker: AtomicWaker,
+    dropped: AtomicU32,
+    truncated: AtomicU32,
+}
+
+// Safety: a slot's data is only accessed by the producer which claimed it, then by the drain task
+// once the producer published it, as tracked by the slot's sequence number.
+unsafe impl<const SLOTS: usize, const LEN: usize> Sync for UartLogger<SLOTS, LEN> {}
+
+impl<const SLOTS: usize, const LEN: usize> UartLogger<SLOTS, LEN> {
+    /// Create a new, empty logger.
+    pub const fn new() -> Self {
+        assert!(SLOTS.is_power_of_two() && LEN > 0);
+
+        let mut slots = [Slot::NEW; SLOTS];
+        let mut i = 0;
+        while i < SLOTS {
+            slots[i].seq = AtomicUsize::new(i);
+            i += 1;
+        }
+
+        Self {
+            slots,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            waker: AtomicWaker::new(),
+            dropped: AtomicU32::new(0),
+            truncated: AtomicU32::new(0),
+        }
+    }
+
+    /// Queue a record of raw bytes.
+    ///
+    /// The record is truncated to `LEN` bytes, or dropped if the queue is full.
+    pub fn write(&self, data: &[u8]) {
+        self.record(false, |buf| {
+            let n = data.len().min(buf.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            (n, n < data.len())
+        });
+    }
+
+    /// Queue a formatted record.
+    ///
+    /// This makes `write!(logger, ...)` queue a record, the output of a single `write!` being a
+    /// single record. The record is truncated to `LEN` bytes, or dropped if the queue is full.
+    pub fn write_fmt(&self, args: fmt::Arguments) {
+        self.record(false, |buf| {
+            let mut w = SlotWriter {
+                buf,
+                len: 0,
+                truncated: false,
+            };
+            let _ = fmt::write(&mut w, args);
+            (w.len, w.truncated)
+        });
+    }
+
+    /// Queue a record formatted later, by the drain task.
+    ///
+    /// `fmt` is a format string with `{}` placeholders, or `{:x}` for hexadecimal, replaced by
+    /// `args` in order. Braces are escaped as `{{` and `}}`. The arguments are stored as they are,
+    /// which only takes a few bytes each and no formatting. Arguments that don't fit in a slot
+    /// are dropped, and show as `?`. The formatted record is truncated to `LEN` bytes.
+    pub fn write_deferred(&self, fmt: &'static str, args: &[Arg]) {
+        self.record(true, |buf| {
+            if buf.len() < DEFERRED_HEADER_LEN {
+                return (0, true);
+            }
+            buf[..WORD].copy_from_slice(&(fmt.as_ptr() as usize).to_ne_bytes());
+            buf[WORD..DEFERRED_HEADER_LEN].copy_from_slice(&fmt.len().to_ne_bytes());
+
+            let mut len = DEFERRED_HEADER_LEN;
+            for arg in args {
+                let Some(n) = arg.encode(&mut buf[len..]) else {
+                    return (len, true);
+                };
+                len += n;
+            }
+            (len, false)
+        });
+    }
+
+    /// Number of records dropped because the queue was full.
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of records truncated because they were longer than a slot.
+    pub fn truncated(&self) -> u32 {
+        self.truncated.load(Ordering::Relaxed)
+    }
+
+    /// Send queued records to the UART. Never returns.
+    ///
+    /// Records dropped since the previous one are reported to the host with a
+    /// `[N log records dropped]` line. UART errors are ignored.
+    pub async fn run(&self, mut tx: UartTx<'_, Async>) -> ! {
+        let mut reported = 0;
+        let mut rendered = [0; LEN];
+
+        loop {
+            let pos = self.tail.load(Ordering::Relaxed);
+            let slot = &self.slots[pos % SLOTS];
+
+            poll_fn(|cx| {
+                self.waker.register(cx.waker());
+                match slot.seq.load(Ordering::Acquire) == pos.wrapping_add(1) {
+                    true => Poll::Ready(()),
+                    false => Poll::Pending,
+                }
+            })
+            .await;
+
+            let dropped = self.dropped();
+            if dropped != reported {
+                let mut buf = [0; 32];
+                let mut w = SlotWriter {
+                    buf: &mut buf,
+                    len: 0,
+                    truncated: false,
+                };
+                let _ = write!(w, "[{} log records dropped]\r\n", dropped.wrapping_sub(reported));
+                let len = w.len;
+                let _ = tx.write(&buf[..len]).await;
+                reported = dropped;
+            }
+
+            // Safety: the record was published by its producer, and the slot is not handed out
+            // again before its sequence number is advanced below.
+            let (data, deferred) = unsafe { (&(&*slot.data.get())[..*slot.len.get()], *slot.deferred.get()) };
+            if deferred {
+                let mut w = SlotWriter {
+                    buf: &mut rendered,
+                    len: 0,
+                    truncated: false,
+                };
+                render(data, &mut w);
+                if w.truncated {
+                    increment(&self.truncated);
+                }
+                let len = w.len;
+                let _ = tx.write(&rendered[..len]).await;
+            } else {
+                let _ = tx.write(data).await;
+            }
+
+            slot.seq.store(pos.wrapping_add(SLOTS), Ordering::Release);
+            self.tail.store(pos.wrapping_add(1), Ordering::Relaxed);
+        }
+    }
+
+    /// Claim a slot, fill it with `f`, which returns the record length and whether it was
+    /// truncated, and publish it.
+    fn record(&self, deferred: bool, f: impl FnOnce(&mut [u8]) -> (usize, bool)) {
+        let Some(pos) = self.claim() else {
+            increment(&self.dropped);
+            return;
+        };
+        let slot = &self.slots[pos % SLOTS];
+
+        // Safety: the slot was claimed by this call, nobody else accesses it until it's published.
+        let truncated = unsafe {
+            let (len, truncated) = f(&mut *slot.data.get());
+            *slot.len.get() = len;
+            *slot.deferred.get() = deferred;
+            truncated
+        };
+        if truncated {
+            increment(&self.truncated);
+        }
+
+        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+        self.waker.wake();
+    }
+
+    /// Claim the slot for the next record, returning its position, or `None` if the queue is full.
+    #[cfg(target_has_atomic = "ptr")]
+    fn claim(&self) -> Option<usize> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let seq = self.slots[pos % SLOTS].seq.load(Ordering::Acquire);
+            match seq.wrapping_sub(pos) as isize {
+                0 => match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return Some(pos),
+                    Err(current) => pos = current,
+                },
+                // The slot still holds the record from the previous lap: the queue is full.
+                d if d < 0 => return None,
+                // Another producer claimed this position meanwhile.
+                _ => pos = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Claim the slot for the next record, returning its position, or `None` if the queue is full.
+    #[cfg(not(target_has_atomic = "ptr"))]
+    fn claim(&self) -> Option<usize> {
+        critical_section::with(|_| {
+            let pos = self.head.load(Ordering::Relaxed);
+            if self.slots[pos % SLOTS].seq.load(Ordering::Acquire) != pos {
+                return None;
+            }
+            self.head.store(pos.wrapping_add(1), Ordering::Relaxed);
+            Some(pos)
+        })
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+fn increment(counter: &AtomicU32) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+#[cfg(not(target_has_atomic = "ptr"))]
+fn increment(counter: &AtomicU32) {
+    critical_section::with(|_| counter.store(counter.load(Ordering::Relaxed).wrapping_add(1), Ordering::Relaxed));
+}
+
+#[cfg(feature = "log")]
+impl<const SLOTS: usize, const LEN: usize> log::Log for UartLogger<SLOTS, LEN> {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.write_fmt(format_args!("{}\r\n", record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Formats into a buffer, truncating what doesn't fit.
+struct SlotWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    truncated: bool,
+}
+
+impl fmt::Write for SlotWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..][..n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        self.truncated |= n < s.len();
+        Ok(())
+    }
+}
+
+const WORD: usize = size_of::<usize>();
+/// Format string address and length.
+const DEFERRED_HEADER_LEN: usize = 2 * WORD;
+
+/// Argument of a deferred record, see [`UartLogger::write_deferred`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Arg {
+    /// Unsigned integer.
+    Unsigned(u32),
+    /// Signed integer.
+    Signed(i32),
+    /// Floating point number.
+    Float(f32),
+    /// Boolean.
+    Bool(bool),
+    /// String.
+    Str(&'static str),
+}
+
+macro_rules! impl_arg_from {
+    ($variant:ident, $($ty:ty),*) => {
+        $(
+            impl From<$ty> for Arg {
+                fn from(value: $ty) -> Self {
+                    Arg::$variant(value.into())
+                }
+            }
+        )*
+    };
+}
+
+impl_arg_from!(Unsigned, u8, u16, u32);
+impl_arg_from!(Signed, i8, i16, i32);
+impl_arg_from!(Float, f32);
+impl_arg_from!(Bool, bool);
+impl_arg_from!(Str, &'static str);
+
+impl From<usize> for Arg {
+    fn from(value: usize) -> Self {
+        Arg::Unsigned(value as u32)
+    }
+}
+
+impl From<isize> for Arg {
+    fn from(value: isize) -> Self {
+        Arg::Signed(value as i32)
+    }
+}
+
+impl Arg {
+    /// Encode as a tag byte and the value into `buf`, returning the encoded length, or `None` if
+    /// it doesn't fit.
+    fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let n = 1 + match self {
+            Arg::Str(_) => 2 * WORD,
+            _ => 4,
+        };
+        let (tag, payload) = buf.get_mut(..n)?.split_first_mut()?;
+
+        match *self {
+            Arg::Unsigned(v) => {
+                *tag = 0;
+                payload.copy_from_slice(&v.to_ne_bytes());
+            }
+            Arg::Signed(v) => {
+                *tag = 1;
+                payload.copy_from_slice(&v.to_ne_bytes());
+            }
+            Arg::Float(v) => {
+                *tag = 2;
+                payload.copy_from_slice(&v.to_bits().to_ne_bytes());
+            }
+            Arg::Bool(v) => {
+                *tag = 3;
+                payload.copy_from_slice(&(v as u32).to_ne_bytes());
+            }
+            Arg::Str(s) => {
+                *tag = 4;
+                payload[..WORD].copy_from_slice(&(s.as_ptr() as usize).to_ne_bytes());
+                payload[WORD..].copy_from_slice(&s.len().to_ne_bytes());
+            }
+        }
+        Some(n)
+    }
+
+    /// Decode an argument encoded by [`Arg::encode`], returning it and its encoded length.
+    fn decode(buf: &[u8]) -> Option<(Self, usize)> {
+        let (&tag, payload) = buf.split_first()?;
+        let value = || payload.get(..4).map(|b| u32::from_ne_bytes(b.try_into().unwrap()));
+
+        match tag {
+            0 => Some((Arg::Unsigned(value()?), 5)),
+            1 => Some((Arg::Signed(value()? as i32), 5)),
+            2 => Some((Arg::Float(f32::from_bits(value()?)), 5)),
+            3 => Some((Arg::Bool(value()? != 0), 5)),
+            4 => {
+                let b = payload.get(..2 * WORD)?;
+                let ptr = usize::from_ne_bytes(b[..WORD].try_into().unwrap());
+                let len = usize::from_ne_bytes(b[WORD..].try_into().unwrap());
+                // Safety: encoded from a `&'static str`.
+                let s = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr as *const u8, len)) };
+                Some((Arg::Str(s), 1 + 2 * WORD))
+            }
+            _ => None,
+        }
+    }
+
+    fn format(&self, w: &mut impl fmt::Write, hex: bool) -> fmt::Result {
+        match *self {
+            Arg::Unsigned(v) if hex => write!(w, "{:x}", v),
+            Arg::Unsigned(v) => write!(w, "{}", v),
+            Arg::Signed(v) if hex => write!(w, "{:x}", v),
+            Arg::Signed(v) => write!(w, "{}", v),
+            Arg::Float(v) => write!(w, "{}", v),
+            Arg::Bool(v) => write!(w, "{}", v),
+            Arg::Str(s) => w.write_str(s),
+        }
+    }
+}
+
+/// Format a deferred record written by [`UartLogger::write_deferred`].
+fn render(data: &[u8], w: &mut SlotWriter) {
+    if data.len() < DEFERRED_HEADER_LEN {
+        return;
+    }
+    let word = |i: usize| usize::from_ne_bytes(data[i..i + WORD].try_into().unwrap());
+    // Safety: encoded from a `&'static str`.
+    let fmt = unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(word(0) as *const u8, word(WORD))) };
+    let mut args = &data[DEFERRED_HEADER_LEN..];
+
+    let mut rest = fmt;
+    while let Some(i) = rest.find(|c| c == '{' || c == '}') {
+        let _ = w.write_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let hex = if rest.starts_with("{{") || rest.starts_with("}}") {
+            let _ = w.write_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        } else if rest.starts_with("{}") {
+            rest = &rest[2..];
+            false
+        } else if rest.starts_with("{:x}") {
+            rest = &rest[4..];
+            true
+        } else {
+            let _ = w.write_str(&rest[..1]);
+            rest = &rest[1..];
+            continue;
+        };
+
+        match Arg::decode(args) {
+            Some((arg, n)) => {
+                args = &args[n..];
+                let _ = arg.format(w, hex);
+            }
+            None => {
+                args = &[];
+                let _ = w.write_str("?");
+            }
+        }
+    }
+    let _ = w.write_str(rest);
+}
@@ -13,8 +13,11 @@ use embassy_hal_internal::PeripheralRef;
 use embassy_sync::waitqueue::AtomicWaker;
 use futures_util::future::{select, Either};
 
+use crate::dma::word::Word;
 use crate::dma::ChannelAndRequest;
 use crate::gpio::{AfType, AnyPin, OutputType, Pull, SealedPin as _, Speed};
+#[cfg(any(usart_v1, usart_v2))]
+use crate::gpio::{Level, Output, Pin};
 use crate::interrupt::typelevel::Interrupt as _;
 use crate::interrupt::{self, Interrupt, InterruptExt};
 use crate::mode::{Async, Blocking, Mode};
@@ -24,9 +27,9 @@ use crate::pac::usart::regs::Isr as Sr;
 #[cfg(any(usart_v1, usart_v2))]
 use crate::pac::usart::regs::Sr;
 #[cfg(not(any(usart_v1, usart_v2)))]
-use crate::pac::usart::Lpuart as Regs;
+pub(crate) use crate::pac::usart::Lpuart as Regs;
 #[cfg(any(usart_v1, usart_v2))]
-use crate::pac::usart::Usart as Regs;
+pub(crate) use crate::pac::usart::Usart as Regs;
 use crate::pac::usart::{regs, vals};
 use crate::rcc::{RccInfo, SealedRccPeripheral};
 use crate::time::Hertz;
@@ -46,6 +49,50 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
 unsafe fn on_interrupt(r: Regs, s: &'static State) {
     let (sr, cr1, cr3) = (sr(r).read(), r.cr1().read(), r.cr3().read());
 
+    #[cfg(any(usart_v3, usart_v4))]
+    if cr3.wufie() && sr.wuf() {
+        // Woken up from Stop mode, the received character is handled below.
+        r.icr().write(|w| w.set_wucf(true));
+    }
+
+    #[cfg(any(usart_v3, usart_v4))]
+    if cr1.cmie() && sr.cmf() {
+        // Character match detected
+        r.cr1().modify(|w| {
+            // disable character match interrupt
+            w.set_cmie(false);
+        });
+        compiler_fence(Ordering::SeqCst);
+        s.rx_waker.wake();
+        return;
+    }
+
+    #[cfg(not(any(usart_v1, usart_v2)))]
+    if usart_regs(r).cr1().read().rtoie() && usart_regs(r).isr().read().rtof() {
+        // Receiver timeout
+        usart_regs(r).cr1().modify(|w| w.set_rtoie(false));
+        compiler_fence(Ordering::SeqCst);
+        s.rx_waker.wake();
+        return;
+    }
+
+    #[cfg(any(usart_v1, usart_v2))]
+    if cr1.tcie() && sr.tc() {
+        // Transmission complete, awaited to release the driver-enable pin
+        r.cr1().modify(|w| w.set_tcie(false));
+        compiler_fence(Ordering::SeqCst);
+        s.tx_waker.wake();
+        return;
+    }
+
+    if usart_regs(r).cr2().read().lbdie() && lin_break_detected(r) {
+        // LIN break detected
+        usart_regs(r).cr2().modify(|w| w.set_lbdie(false));
+        compiler_fence(Ordering::SeqCst);
+        s.rx_waker.wake();
+        return;
+    }
+
     let has_errors = (sr.pe() && cr1.peie()) || ((sr.fe() || sr.ne() || sr.ore()) && cr3.eie());
     if has_errors {
         // clear all interrupts and DMA Rx Request
@@ -88,6 +135,9 @@ pub enum DataBits {
     /// 8 Data Bits
     DataBits8,
     /// 9 Data Bits
+    ///
+    /// Use the `_u16` read and write methods to access the 9th bit. Parity is not supported
+    /// with 9 data bits; mark or space parity can be emulated by setting the 9th bit in software.
     DataBits9,
 }
 
@@ -103,6 +153,68 @@ pub enum Parity {
     ParityOdd,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+/// What to do with received bytes that have a framing, parity or noise error
+pub enum RxErrorPolicy {
+    /// Abort the read and return the error
+    Fail,
+    /// Drop the byte and keep receiving.
+    ///
+    /// Overrun errors are still returned, since data was lost. This only applies to blocking
+    /// reads: with DMA the byte is already in the buffer by the time the error is seen.
+    Discard,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+/// LIN break detection length
+pub enum LinBreakDetection {
+    /// Detect breaks of at least 10 bits
+    Bits10,
+    /// Detect breaks of at least 11 bits
+    Bits11,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+/// IrDA SIR power mode
+pub enum IrdaMode {
+    /// Pulses of 3/16 bit time
+    Normal,
+    /// Pulses of 3 periods of a 1.8432 MHz clock derived from the kernel clock, for baud rates up
+    /// to 115200 with a lower power consumption
+    LowPower,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+/// Smartcard (ISO 7816-3) mode configuration
+pub struct SmartcardConfig {
+    /// Divider of the kernel clock to the card clock output on the CK pin: the clock is
+    /// `kernel_clock / (2 * clock_divider)`. From 1 to 31.
+    pub clock_divider: u8,
+    /// Extra guard time after each transmitted character, in bit times
+    pub guard_time: u8,
+    /// Send a NACK when a parity error is received, so the card repeats the character
+    pub nack: bool,
+    /// Number of times a character NACKed by the card is sent again, from 0 to 7
+    #[cfg(any(usart_v3, usart_v4))]
+    pub retries: u8,
+}
+
+impl Default for SmartcardConfig {
+    fn default() -> Self {
+        Self {
+            clock_divider: 5,
+            guard_time: 2,
+            nack: true,
+            #[cfg(any(usart_v3, usart_v4))]
+            retries: 3,
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Number of stop bits
@@ -128,6 +240,20 @@ pub enum ConfigError {
     BaudrateTooHigh,
     /// Rx or Tx not enabled
     RxOrTxNotEnabled,
+    /// LIN mode is not supported by this peripheral (LPUART)
+    LinNotSupported,
+    /// IrDA mode is not supported by this peripheral (LPUART)
+    IrdaNotSupported,
+    /// Smartcard mode is not supported by this peripheral (LPUART)
+    SmartcardNotSupported,
+    /// Smartcard clock divider not in 1..=31
+    SmartcardClockDividerOutOfRange,
+    /// Smartcard retries above 7
+    SmartcardRetriesOutOfRange,
+    /// 9 data bits can't be combined with parity
+    DataBitsWithParityNotSupported,
+    /// Driver-enable assertion or deassertion time above 31
+    DeTimeOutOfRange,
 }
 
 #[non_exhaustive]
@@ -149,6 +275,9 @@ pub struct Config {
     /// If false: the error is ignored and cleared
     pub detect_previous_overrun: bool,
 
+    /// What to do with received bytes that have a framing, parity or noise error
+    pub rx_error_policy: RxErrorPolicy,
+
     /// Set this to true if the line is considered noise free.
     /// This will increase the receiver’s tolerance to clock deviations,
     /// but will effectively disable noise detection.
@@ -167,8 +296,42 @@ pub struct Config {
     #[cfg(any(usart_v3, usart_v4))]
     pub invert_rx: bool,
 
+    /// Time between asserting the driver-enable pin and the start bit of the first byte, in sample
+    /// time units (1/16 bit, or 1/8 bit with 8x oversampling). At most 31.
+    #[cfg(not(any(usart_v1, usart_v2)))]
+    pub de_assertion_time: u8,
+
+    /// Time between the end of the last stop bit and releasing the driver-enable pin, in sample
+    /// time units (1/16 bit, or 1/8 bit with 8x oversampling). At most 31.
+    #[cfg(not(any(usart_v1, usart_v2)))]
+    pub de_deassertion_time: u8,
+
+    /// Set to enable LIN mode, detecting breaks of the given length.
+    ///
+    /// In LIN mode, [`UartTx::send_break`] sends a 13-bit break, and breaks can be awaited with
+    /// [`UartRx::wait_for_break`]. LIN requires 8 data bits, 1 stop bit and no parity.
+    pub lin_break_detection: Option<LinBreakDetection>,
+
+    /// Set to enable the IrDA SIR encoder and decoder. IrDA requires 1 stop bit.
+    pub irda: Option<IrdaMode>,
+
+    /// Set this to true to let the receiver wake the MCU from Stop mode.
+    ///
+    /// The kernel clock must keep running in Stop mode, so LSE or HSI has to be selected for the
+    /// instance with the `rcc::Config::mux` field (for example `lpuart1sel`). Reception must be
+    /// interrupt-driven, as with [`BufferedUart`], since DMA does not run in Stop mode. An LPUART
+    /// clocked from the 32.768 kHz LSE supports baudrates up to 9600.
+    ///
+    /// The wakeup interrupt is enabled on a received character, HSI is kept on in Stop mode if it
+    /// clocks the instance, and the wakeup EXTI line is unmasked.
+    #[cfg(any(usart_v3, usart_v4))]
+    pub wakeup_from_stop: bool,
+
     // private: set by new_half_duplex, not by the user.
     half_duplex: bool,
+
+    // private: set by new_smartcard, not by the user.
+    smartcard: Option<SmartcardConfig>,
 }
 
 impl Config {
@@ -198,6 +361,7 @@ impl Default for Config {
             parity: Parity::ParityNone,
             // historical behavior
             detect_previous_overrun: false,
+            rx_error_policy: RxErrorPolicy::Fail,
             #[cfg(not(usart_v1))]
             assume_noise_free: false,
             #[cfg(any(usart_v3, usart_v4))]
@@ -206,7 +370,16 @@ impl Default for Config {
             invert_tx: false,
             #[cfg(any(usart_v3, usart_v4))]
             invert_rx: false,
+            #[cfg(not(any(usart_v1, usart_v2)))]
+            de_assertion_time: 0,
+            #[cfg(not(any(usart_v1, usart_v2)))]
+            de_deassertion_time: 0,
+            lin_break_detection: None,
+            irda: None,
+            #[cfg(any(usart_v3, usart_v4))]
+            wakeup_from_stop: false,
             half_duplex: false,
+            smartcard: None,
         }
     }
 }
@@ -226,6 +399,19 @@ pub enum Error {
     Parity,
     /// Buffer too large for DMA
     BufferTooLong,
+    /// The instance has no receiver timeout
+    TimeoutNotSupported,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ReadEnd {
+    // Only when the buffer is full
+    Full,
+    // When the line goes idle
+    Idle,
+    // When the receiver timeout expires
+    #[cfg(not(any(usart_v1, usart_v2)))]
+    Timeout,
 }
 
 enum ReadCompletionEvent {
@@ -253,8 +439,7 @@ impl<'d, M: Mode> SetConfig for Uart<'d, M> {
     type ConfigError = ConfigError;
 
     fn set_config(&mut self, config: &Self::Config) -> Result<(), Self::ConfigError> {
-        self.tx.set_config(config)?;
-        self.rx.set_config(config)
+        self.set_config(config)
     }
 }
 
@@ -268,11 +453,18 @@ pub struct UartTx<'d, M: Mode> {
     kernel_clock: Hertz,
     tx: Option<PeripheralRef<'d, AnyPin>>,
     cts: Option<PeripheralRef<'d, AnyPin>>,
-    de: Option<PeripheralRef<'d, AnyPin>>,
+    de: Option<DeOutput<'d>>,
+    ck: Option<PeripheralRef<'d, AnyPin>>,
     tx_dma: Option<ChannelAndRequest<'d>>,
     _phantom: PhantomData<M>,
 }
 
+// Driver-enable pin. Driven by the peripheral where supported (DEM), by software otherwise.
+#[cfg(not(any(usart_v1, usart_v2)))]
+type DeOutput<'d> = PeripheralRef<'d, AnyPin>;
+#[cfg(any(usart_v1, usart_v2))]
+type DeOutput<'d> = Output<'d>;
+
 impl<'d, M: Mode> SetConfig for UartTx<'d, M> {
     type Config = Config;
     type ConfigError = ConfigError;
@@ -319,6 +511,7 @@ pub struct UartRx<'d, M: Mode> {
     rts: Option<PeripheralRef<'d, AnyPin>>,
     rx_dma: Option<ChannelAndRequest<'d>>,
     detect_previous_overrun: bool,
+    rx_error_policy: RxErrorPolicy,
     #[cfg(any(usart_v1, usart_v2))]
     buffered_sr: stm32_metapac::usart::regs::Sr,
     _phantom: PhantomData<M>,
@@ -369,6 +562,17 @@ impl<'d> UartTx<'d, Async> {
 
     /// Initiate an asynchronous UART write
     pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        self.write_inner(buffer).await
+    }
+
+    /// Initiate an asynchronous UART write of 9-bit words
+    ///
+    /// Requires [`DataBits::DataBits9`]. Only the low 9 bits of each word are sent.
+    pub async fn write_u16(&mut self, buffer: &[u16]) -> Result<(), Error> {
+        self.write_inner(buffer).await
+    }
+
+    async fn write_inner<W: Word>(&mut self, buffer: &[W]) -> Result<(), Error> {
         let r = self.info.regs;
 
         // Disable Receiver for Half-Duplex mode
@@ -376,14 +580,52 @@ impl<'d> UartTx<'d, Async> {
             r.cr1().modify(|reg| reg.set_re(false));
         }
 
+        #[cfg(any(usart_v1, usart_v2))]
+        if self.de.is_some() {
+            // TC is only cleared by writing the data register from software, not with DMA.
+            sr(r).modify(|w| w.set_tc(false));
+            self.assert_de();
+        }
+
         let ch = self.tx_dma.as_mut().unwrap();
         r.cr3().modify(|reg| {
             reg.set_dmat(true);
         });
         // If we don't assign future to a variable, the data register pointer
         // is held across an await and makes the future non-Send.
-        let transfer = unsafe { ch.write(buffer, tdr(r), Default::default()) };
+        let transfer = unsafe { ch.write(buffer, tdr(r) as *mut W, Default::default()) };
         transfer.await;
+
+        #[cfg(any(usart_v1, usart_v2))]
+        self.release_de_async().await?;
+        Ok(())
+    }
+
+    /// Release the driver-enable pin once the last byte has left the shift register, waiting for
+    /// it with the TC interrupt.
+    ///
+    /// Only UARTs created with a DE pin have one, and they bind the interrupt.
+    #[cfg(any(usart_v1, usart_v2))]
+    async fn release_de_async(&mut self) -> Result<(), Error> {
+        if let Some(de) = &mut self.de {
+            let r = self.info.regs;
+            let state = self.state;
+            r.cr1().modify(|w| w.set_tcie(true));
+            poll_fn(|cx| {
+                state.tx_waker.register(cx.waker());
+                if sr(r).read().tc() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await;
+            r.cr1().modify(|w| w.set_tcie(false));
+
+            // Re-enables the receiver in half-duplex mode, TC is already set.
+            blocking_flush(self.info)?;
+            de.set_low();
+        }
         Ok(())
     }
 
@@ -443,6 +685,7 @@ impl<'d, M: Mode> UartTx<'d, M> {
             tx,
             cts,
             de: None,
+            ck: None,
             tx_dma,
             _phantom: PhantomData,
         };
@@ -472,6 +715,17 @@ impl<'d, M: Mode> UartTx<'d, M> {
 
     /// Perform a blocking UART write
     pub fn blocking_write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        self.blocking_write_inner(buffer)
+    }
+
+    /// Perform a blocking UART write of 9-bit words
+    ///
+    /// Requires [`DataBits::DataBits9`]. Only the low 9 bits of each word are sent.
+    pub fn blocking_write_u16(&mut self, buffer: &[u16]) -> Result<(), Error> {
+        self.blocking_write_inner(buffer)
+    }
+
+    fn blocking_write_inner<W: Word>(&mut self, buffer: &[W]) -> Result<(), Error> {
         let r = self.info.regs;
 
         // Disable Receiver for Half-Duplex mode
@@ -479,10 +733,16 @@ impl<'d, M: Mode> UartTx<'d, M> {
             r.cr1().modify(|reg| reg.set_re(false));
         }
 
+        #[cfg(any(usart_v1, usart_v2))]
+        self.assert_de();
+
         for &b in buffer {
             while !sr(r).read().txe() {}
-            unsafe { tdr(r).write_volatile(b) };
+            unsafe { (tdr(r) as *mut W).write_volatile(b) };
         }
+
+        #[cfg(any(usart_v1, usart_v2))]
+        self.release_de()?;
         Ok(())
     }
 
@@ -490,6 +750,31 @@ impl<'d, M: Mode> UartTx<'d, M> {
     pub fn blocking_flush(&mut self) -> Result<(), Error> {
         blocking_flush(self.info)
     }
+
+    /// Send a break: hold the line low for one frame, or 13 bits in LIN mode.
+    ///
+    /// The break is sent after the byte currently being transmitted, if any. This only waits
+    /// for a previous break to be sent, not for this one.
+    pub fn send_break(&self) {
+        send_break(self.info.regs);
+    }
+
+    #[cfg(any(usart_v1, usart_v2))]
+    fn assert_de(&mut self) {
+        if let Some(de) = &mut self.de {
+            de.set_high();
+        }
+    }
+
+    /// Release the driver-enable pin once the last byte has left the shift register.
+    #[cfg(any(usart_v1, usart_v2))]
+    fn release_de(&mut self) -> Result<(), Error> {
+        if let Some(de) = &mut self.de {
+            blocking_flush(self.info)?;
+            de.set_low();
+        }
+        Ok(())
+    }
 }
 
 fn blocking_flush(info: &Info) -> Result<(), Error> {
@@ -544,21 +829,137 @@ impl<'d> UartRx<'d, Async> {
 
     /// Initiate an asynchronous UART read
     pub async fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
-        self.inner_read(buffer, false).await?;
+        self.inner_read(buffer, ReadEnd::Full).await?;
 
         Ok(())
     }
 
     /// Initiate an asynchronous read with idle line detection enabled
     pub async fn read_until_idle(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
-        self.inner_read(buffer, true).await
+        self.inner_read(buffer, ReadEnd::Idle).await
     }
 
-    async fn inner_read_run(
-        &mut self,
-        buffer: &mut [u8],
-        enable_idle_line_detection: bool,
-    ) -> Result<ReadCompletionEvent, Error> {
+    /// Initiate an asynchronous read that ends early when nothing has been received for
+    /// `timeout_bits` bit times after the last character.
+    ///
+    /// This uses the receiver timeout of the USART, so the gap is measured in hardware. Unlike
+    /// [`read_until_idle`](Self::read_until_idle), which ends after one idle frame, the gap can
+    /// be set to what the protocol requires, for example 3.5 characters (39 bits with 8E1) for the
+    /// end of a Modbus RTU frame. As with idle line detection, the timeout only runs once a
+    /// character has been received. Returns the number of bytes read.
+    ///
+    /// Returns [`Error::TimeoutNotSupported`] on LPUART instances, which have no receiver
+    /// timeout, and panics if `timeout_bits` doesn't fit in 24 bits. Not all USART instances have
+    /// a receiver timeout either, see the reference manual.
+    #[cfg(not(any(usart_v1, usart_v2)))]
+    pub async fn read_until_timeout(&mut self, buffer: &mut [u8], timeout_bits: u32) -> Result<usize, Error> {
+        if self.info.kind != Kind::Uart {
+            return Err(Error::TimeoutNotSupported);
+        }
+        assert!(timeout_bits <= 0xFF_FFFF);
+
+        let ur = usart_regs(self.info.regs);
+        ur.rtor().modify(|w| w.set_rto(timeout_bits));
+        ur.cr2().modify(|w| w.set_rtoen(true));
+        let _on_drop = OnDrop::new(move || {
+            ur.cr2().modify(|w| w.set_rtoen(false));
+        });
+
+        self.inner_read(buffer, ReadEnd::Timeout).await
+    }
+
+    /// Initiate an asynchronous read of 9-bit words into `buffer`
+    ///
+    /// Requires [`DataBits::DataBits9`].
+    pub async fn read_u16(&mut self, buffer: &mut [u16]) -> Result<(), Error> {
+        self.inner_read(buffer, ReadEnd::Full).await?;
+
+        Ok(())
+    }
+
+    /// Wait until a LIN break is detected.
+    ///
+    /// Requires LIN mode to be enabled with [`Config::lin_break_detection`]. The break
+    /// character itself is discarded, and the flags are cleared so the next read starts
+    /// with the sync byte.
+    pub async fn wait_for_break(&mut self) {
+        let r = self.info.regs;
+
+        // make sure the break interrupt is disabled when this future is dropped
+        let _on_drop = OnDrop::new(move || {
+            usart_regs(r).cr2().modify(|w| w.set_lbdie(false));
+        });
+
+        clear_lin_break_detected(r);
+        usart_regs(r).cr2().modify(|w| w.set_lbdie(true));
+
+        compiler_fence(Ordering::SeqCst);
+
+        let s = self.state;
+        poll_fn(move |cx| {
+            s.rx_waker.register(cx.waker());
+            if lin_break_detected(r) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        clear_lin_break_detected(r);
+
+        // The break is also received as a 0x00 byte with a framing error, discard it.
+        let sr = sr(r).read();
+        unsafe { rdr(r).read_volatile() };
+        clear_interrupt_flags(r, sr);
+    }
+
+    /// Wait until the character `c` is received, ignoring everything before it.
+    ///
+    /// This uses the character match feature of the USART, so the CPU is only interrupted when `c`
+    /// arrives and not for every byte of unrelated bus traffic. Bytes received while waiting,
+    /// including `c` itself, are discarded; use it to wait for a framing byte or node address,
+    /// then read the payload following it.
+    #[cfg(any(usart_v3, usart_v4))]
+    pub async fn wait_for_char(&mut self, c: u8) {
+        let r = self.info.regs;
+
+        // make sure the character match interrupt is disabled when this future is dropped
+        let _on_drop = OnDrop::new(move || {
+            r.cr1().modify(|w| w.set_cmie(false));
+        });
+
+        // ADD can only be written while the receiver is disabled
+        r.cr1().modify(|w| w.set_re(false));
+        r.cr2().modify(|w| w.set_add(c));
+        r.cr1().modify(|w| w.set_re(true));
+
+        // discard stale data and flags
+        unsafe { rdr(r).read_volatile() };
+        clear_interrupt_flags(r, sr(r).read());
+
+        r.cr1().modify(|w| w.set_cmie(true));
+
+        compiler_fence(Ordering::SeqCst);
+
+        let s = self.state;
+        poll_fn(move |cx| {
+            s.rx_waker.register(cx.waker());
+            if sr(r).read().cmf() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        // Bytes before the match were never read, so the matched character may be sitting in RDR,
+        // or may have been dropped with an overrun. Either way, start the next read from a clean state.
+        unsafe { rdr(r).read_volatile() };
+        clear_interrupt_flags(r, sr(r).read());
+    }
+
+    async fn inner_read_run<W: Word>(&mut self, buffer: &mut [W], end: ReadEnd) -> Result<ReadCompletionEvent, Error> {
         let r = self.info.regs;
 
         // Call flush for Half-Duplex mode. It prevents reading of bytes which have just been written.
@@ -584,6 +985,11 @@ impl<'d> UartRx<'d, Async> {
                 // disable DMA Rx Request
                 w.set_dmar(false);
             });
+            // disable receiver timeout interrupt
+            #[cfg(not(any(usart_v1, usart_v2)))]
+            if end == ReadEnd::Timeout {
+                usart_regs(r).cr1().modify(|w| w.set_rtoie(false));
+            }
         });
 
         let ch = self.rx_dma.as_mut().unwrap();
@@ -593,7 +999,7 @@ impl<'d> UartRx<'d, Async> {
         // Start USART DMA
         // will not do anything yet because DMAR is not yet set
         // future which will complete when DMA Read request completes
-        let transfer = unsafe { ch.read(rdr(r), buffer, Default::default()) };
+        let transfer = unsafe { ch.read(rdr(r) as *mut W, buffer, Default::default()) };
 
         // clear ORE flag just before enabling DMA Rx Request: can be mandatory for the second transfer
         if !self.detect_previous_overrun {
@@ -652,7 +1058,7 @@ impl<'d> UartRx<'d, Async> {
             unreachable!();
         }
 
-        if enable_idle_line_detection {
+        if end == ReadEnd::Idle {
             // clear idle flag
             let sr = sr(r).read();
             // This read also clears the error and idle interrupt flags on v1.
@@ -665,6 +1071,13 @@ impl<'d> UartRx<'d, Async> {
             });
         }
 
+        #[cfg(not(any(usart_v1, usart_v2)))]
+        if end == ReadEnd::Timeout {
+            // clear receiver timeout flag and enable its interrupt
+            usart_regs(r).icr().write(|w| w.set_rtocf(true));
+            usart_regs(r).cr1().modify(|w| w.set_rtoie(true));
+        }
+
         compiler_fence(Ordering::SeqCst);
 
         // future which completes when idle line or error is detected
@@ -673,12 +1086,14 @@ impl<'d> UartRx<'d, Async> {
             s.rx_waker.register(cx.waker());
 
             let sr = sr(r).read();
+            #[cfg(not(any(usart_v1, usart_v2)))]
+            let rtof = usart_regs(r).isr().read().rtof();
 
             // This read also clears the error and idle interrupt flags on v1.
             unsafe { rdr(r).read_volatile() };
             clear_interrupt_flags(r, sr);
 
-            if enable_idle_line_detection {
+            if end == ReadEnd::Idle {
                 // enable idle interrupt
                 r.cr1().modify(|w| {
                     w.set_idleie(true);
@@ -706,11 +1121,17 @@ impl<'d> UartRx<'d, Async> {
                 }
             }
 
-            if enable_idle_line_detection && sr.idle() {
+            if end == ReadEnd::Idle && sr.idle() {
                 // Idle line detected
                 return Poll::Ready(Ok(()));
             }
 
+            #[cfg(not(any(usart_v1, usart_v2)))]
+            if end == ReadEnd::Timeout && rtof {
+                // Receiver timeout
+                return Poll::Ready(Ok(()));
+            }
+
             Poll::Pending
         });
 
@@ -721,7 +1142,7 @@ impl<'d> UartRx<'d, Async> {
             // DMA transfer completed first
             Either::Left(((), _)) => Ok(ReadCompletionEvent::DmaCompleted),
 
-            // Idle line detected first
+            // Idle line or receiver timeout detected first
             Either::Right((Ok(()), transfer)) => Ok(ReadCompletionEvent::Idle(
                 buffer_len - transfer.get_remaining_transfers() as usize,
             )),
@@ -735,7 +1156,7 @@ impl<'d> UartRx<'d, Async> {
         r
     }
 
-    async fn inner_read(&mut self, buffer: &mut [u8], enable_idle_line_detection: bool) -> Result<usize, Error> {
+    async fn inner_read<W: Word>(&mut self, buffer: &mut [W], end: ReadEnd) -> Result<usize, Error> {
         if buffer.is_empty() {
             return Ok(0);
         } else if buffer.len() > 0xFFFF {
@@ -745,7 +1166,7 @@ impl<'d> UartRx<'d, Async> {
         let buffer_len = buffer.len();
 
         // wait for DMA to complete or IDLE line detection if requested
-        let res = self.inner_read_run(buffer, enable_idle_line_detection).await;
+        let res = self.inner_read_run(buffer, end).await;
 
         match res {
             Ok(ReadCompletionEvent::DmaCompleted) => Ok(buffer_len),
@@ -801,6 +1222,7 @@ impl<'d, M: Mode> UartRx<'d, M> {
             rts,
             rx_dma,
             detect_previous_overrun: config.detect_previous_overrun,
+            rx_error_policy: config.rx_error_policy,
             #[cfg(any(usart_v1, usart_v2))]
             buffered_sr: stm32_metapac::usart::regs::Sr(0),
         };
@@ -828,6 +1250,7 @@ impl<'d, M: Mode> UartRx<'d, M> {
 
     /// Reconfigure the driver
     pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        self.rx_error_policy = config.rx_error_policy;
         reconfigure(self.info, self.kernel_clock, config)
     }
 
@@ -835,6 +1258,20 @@ impl<'d, M: Mode> UartRx<'d, M> {
     fn check_rx_flags(&mut self) -> Result<bool, Error> {
         let r = self.info.regs;
         loop {
+            if self.rx_error_policy == RxErrorPolicy::Discard
+                && (self.buffered_sr.pe() || self.buffered_sr.fe() || self.buffered_sr.ne())
+            {
+                // Drop the bad byte together with its error flags.
+                self.buffered_sr.set_pe(false);
+                self.buffered_sr.set_fe(false);
+                self.buffered_sr.set_ne(false);
+                if self.buffered_sr.rxne() {
+                    self.buffered_sr.set_rxne(false);
+                    unsafe { rdr(r).read_volatile() };
+                }
+                continue;
+            }
+
             // Handle all buffered error flags.
             if self.buffered_sr.pe() {
                 self.buffered_sr.set_pe(false);
@@ -868,6 +1305,18 @@ impl<'d, M: Mode> UartRx<'d, M> {
     fn check_rx_flags(&mut self) -> Result<bool, Error> {
         let r = self.info.regs;
         let sr = r.isr().read();
+        if self.rx_error_policy == RxErrorPolicy::Discard && (sr.pe() || sr.fe() || sr.ne()) {
+            // Drop the bad byte together with its error flags. A pending overrun is reported on the next call.
+            r.icr().write(|w| {
+                w.set_pe(true);
+                w.set_fe(true);
+                w.set_ne(true);
+            });
+            if sr.rxne() {
+                unsafe { rdr(r).read_volatile() };
+            }
+            return Ok(false);
+        }
         if sr.pe() {
             r.icr().write(|w| w.set_pe(true));
             return Err(Error::Parity);
@@ -909,13 +1358,97 @@ impl<'d, M: Mode> UartRx<'d, M> {
         }
         Ok(())
     }
+
+    /// Perform a blocking read of 9-bit words into `buffer`
+    ///
+    /// Requires [`DataBits::DataBits9`].
+    pub fn blocking_read_u16(&mut self, buffer: &mut [u16]) -> Result<(), Error> {
+        let r = self.info.regs;
+
+        // Call flush for Half-Duplex mode. It prevents reading of bytes which have just been written.
+        if r.cr3().read().hdsel() {
+            blocking_flush(self.info)?;
+        }
+
+        for w in buffer {
+            while !self.check_rx_flags()? {}
+            unsafe { *w = (rdr(r) as *mut u16).read_volatile() & 0x1FF }
+        }
+        Ok(())
+    }
+
+    /// Mute the receiver until an address byte for `address` is received.
+    ///
+    /// An address byte has its most significant bit set, i.e. bit 8 in 9-bit mode, as used by
+    /// multi-drop protocols. While muted, no data is received and no interrupts are raised.
+    /// The address byte that ends mute mode is received like any other byte.
+    ///
+    /// The low 4 bits of the address are compared on usart_v1/v2, 7 bits on later versions.
+    /// On usart_v3/v4 this briefly disables the USART, so don't call it while transmitting, and
+    /// it can't be used together with [`wait_for_char`](UartRx::wait_for_char).
+    pub fn mute_until_address(&mut self, address: u8) {
+        let r = self.info.regs;
+
+        #[cfg(any(usart_v1, usart_v2))]
+        {
+            r.cr1().modify(|w| w.set_wake(vals::Wake::ADDRESSMARK));
+            r.cr2().modify(|w| w.set_add(address & 0x0F));
+            r.cr1().modify(|w| w.set_rwu(vals::Rwu::MUTE));
+        }
+
+        #[cfg(any(usart_v3, usart_v4))]
+        {
+            // WAKE can only be written while the USART is disabled
+            r.cr1().modify(|w| w.set_ue(false));
+            r.cr1().modify(|w| {
+                w.set_wake(vals::Wake::ADDRESSMARK);
+                w.set_mme(true);
+            });
+            r.cr2().modify(|w| {
+                w.set_addm7(vals::Addm7::BIT7);
+                w.set_add(address & 0x7F);
+            });
+            r.cr1().modify(|w| w.set_ue(true));
+            r.rqr().write(|w| w.set_mmrq(true));
+        }
+    }
+
+    /// Perform a blocking read into `buffer`, returning early when the line goes idle.
+    ///
+    /// This waits for at least one byte, then reads until either the buffer is full or no data
+    /// has been received for one frame time. Returns the number of bytes read.
+    pub fn blocking_read_until_idle(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let r = self.info.regs;
+
+        // Call flush for Half-Duplex mode. It prevents reading of bytes which have just been written.
+        if r.cr3().read().hdsel() {
+            blocking_flush(self.info)?;
+        }
+
+        // Clear a stale idle flag. On v1 & v2 it is cleared by reading the first byte instead.
+        #[cfg(any(usart_v3, usart_v4))]
+        r.icr().write(|w| w.set_idle(true));
+
+        let mut len = 0;
+        while len < buffer.len() {
+            if self.check_rx_flags()? {
+                buffer[len] = unsafe { rdr(r).read_volatile() };
+                len += 1;
+            } else if len > 0 && sr(r).read().idle() {
+                break;
+            }
+        }
+        Ok(len)
+    }
 }
 
 impl<'d, M: Mode> Drop for UartTx<'d, M> {
     fn drop(&mut self) {
         self.tx.as_ref().map(|x| x.set_as_disconnected());
         self.cts.as_ref().map(|x| x.set_as_disconnected());
+        #[cfg(not(any(usart_v1, usart_v2)))]
         self.de.as_ref().map(|x| x.set_as_disconnected());
+        self.ck.as_ref().map(|x| x.set_as_disconnected());
         drop_tx_rx(self.info, self.state);
     }
 }
@@ -1015,6 +1548,34 @@ impl<'d> Uart<'d, Async> {
         )
     }
 
+    #[cfg(any(usart_v1, usart_v2))]
+    /// Create a new bidirectional UART with a driver-enable pin
+    ///
+    /// This USART version can't drive the DE pin itself, so any GPIO can be used: it is driven
+    /// high by software for the duration of each write, until the last byte has been sent.
+    pub fn new_with_de<T: Instance>(
+        peri: impl Peripheral<P = T> + 'd,
+        rx: impl Peripheral<P = impl RxPin<T>> + 'd,
+        tx: impl Peripheral<P = impl TxPin<T>> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        de: impl Peripheral<P = impl Pin> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        config: Config,
+    ) -> Result<Self, ConfigError> {
+        Self::new_inner(
+            peri,
+            new_pin!(rx, config.rx_af()),
+            new_pin!(tx, config.tx_af()),
+            None,
+            None,
+            Some(Output::new(de, Level::Low, Speed::Medium)),
+            new_dma!(tx_dma),
+            new_dma!(rx_dma),
+            config,
+        )
+    }
+
     /// Create a single-wire half-duplex Uart transceiver on a single Tx pin.
     ///
     /// See [`new_half_duplex_on_rx`][`Self::new_half_duplex_on_rx`] if you would prefer to use an Rx pin
@@ -1088,6 +1649,44 @@ impl<'d> Uart<'d, Async> {
         )
     }
 
+    /// Create a smartcard (ISO 7816-3) interface.
+    ///
+    /// The card's I/O line is connected to the Tx pin, which is open-drain and used both ways, and
+    /// its clock input to the CK pin. The data bits, parity and stop bits in `config` are ignored:
+    /// smartcard characters are always 8 data bits with even parity. The baud rate is usually the
+    /// card clock divided by 372 until the card's answer to reset says otherwise.
+    #[doc(alias("SCEN"))]
+    pub fn new_smartcard<T: Instance>(
+        peri: impl Peripheral<P = T> + 'd,
+        tx: impl Peripheral<P = impl TxPin<T>> + 'd,
+        ck: impl Peripheral<P = impl CkPin<T>> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        mut config: Config,
+        smartcard: SmartcardConfig,
+    ) -> Result<Self, ConfigError> {
+        #[cfg(not(any(usart_v1, usart_v2)))]
+        {
+            config.swap_rx_tx = false;
+        }
+        config.smartcard = Some(smartcard);
+
+        let mut this = Self::new_inner(
+            peri,
+            None,
+            new_pin!(tx, AfType::output(OutputType::OpenDrain, Speed::Medium)),
+            None,
+            None,
+            None,
+            new_dma!(tx_dma),
+            new_dma!(rx_dma),
+            config,
+        )?;
+        this.tx.ck = new_pin!(ck, AfType::output(OutputType::PushPull, Speed::Medium));
+        Ok(this)
+    }
+
     /// Perform an asynchronous write
     pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error> {
         self.tx.write(buffer).await
@@ -1102,6 +1701,40 @@ impl<'d> Uart<'d, Async> {
     pub async fn read_until_idle(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
         self.rx.read_until_idle(buffer).await
     }
+
+    /// Perform an asynchronous read that ends early when nothing has been received for
+    /// `timeout_bits` bit times after the last character.
+    ///
+    /// See [`UartRx::read_until_timeout`].
+    #[cfg(not(any(usart_v1, usart_v2)))]
+    pub async fn read_until_timeout(&mut self, buffer: &mut [u8], timeout_bits: u32) -> Result<usize, Error> {
+        self.rx.read_until_timeout(buffer, timeout_bits).await
+    }
+
+    /// Perform an asynchronous write of 9-bit words
+    pub async fn write_u16(&mut self, buffer: &[u16]) -> Result<(), Error> {
+        self.tx.write_u16(buffer).await
+    }
+
+    /// Perform an asynchronous read of 9-bit words into `buffer`
+    pub async fn read_u16(&mut self, buffer: &mut [u16]) -> Result<(), Error> {
+        self.rx.read_u16(buffer).await
+    }
+
+    /// Wait until a LIN break is detected.
+    ///
+    /// See [`UartRx::wait_for_break`].
+    pub async fn wait_for_break(&mut self) {
+        self.rx.wait_for_break().await
+    }
+
+    /// Wait until the character `c` is received, ignoring everything before it.
+    ///
+    /// See [`UartRx::wait_for_char`].
+    #[cfg(any(usart_v3, usart_v4))]
+    pub async fn wait_for_char(&mut self, c: u8) {
+        self.rx.wait_for_char(c).await
+    }
 }
 
 impl<'d> Uart<'d, Blocking> {
@@ -1169,6 +1802,31 @@ impl<'d> Uart<'d, Blocking> {
         )
     }
 
+    #[cfg(any(usart_v1, usart_v2))]
+    /// Create a new bidirectional UART with a driver-enable pin
+    ///
+    /// This USART version can't drive the DE pin itself, so any GPIO can be used: it is driven
+    /// high by software for the duration of each write, until the last byte has been sent.
+    pub fn new_blocking_with_de<T: Instance>(
+        peri: impl Peripheral<P = T> + 'd,
+        rx: impl Peripheral<P = impl RxPin<T>> + 'd,
+        tx: impl Peripheral<P = impl TxPin<T>> + 'd,
+        de: impl Peripheral<P = impl Pin> + 'd,
+        config: Config,
+    ) -> Result<Self, ConfigError> {
+        Self::new_inner(
+            peri,
+            new_pin!(rx, config.rx_af()),
+            new_pin!(tx, config.tx_af()),
+            None,
+            None,
+            Some(Output::new(de, Level::Low, Speed::Medium)),
+            None,
+            None,
+            config,
+        )
+    }
+
     /// Create a single-wire half-duplex Uart transceiver on a single Tx pin.
     ///
     /// See [`new_half_duplex_on_rx`][`Self::new_half_duplex_on_rx`] if you would prefer to use an Rx pin
@@ -1235,6 +1893,38 @@ impl<'d> Uart<'d, Blocking> {
             config,
         )
     }
+
+    /// Create a smartcard (ISO 7816-3) interface.
+    ///
+    /// See [`new_smartcard`](Uart::new_smartcard).
+    #[doc(alias("SCEN"))]
+    pub fn new_blocking_smartcard<T: Instance>(
+        peri: impl Peripheral<P = T> + 'd,
+        tx: impl Peripheral<P = impl TxPin<T>> + 'd,
+        ck: impl Peripheral<P = impl CkPin<T>> + 'd,
+        mut config: Config,
+        smartcard: SmartcardConfig,
+    ) -> Result<Self, ConfigError> {
+        #[cfg(not(any(usart_v1, usart_v2)))]
+        {
+            config.swap_rx_tx = false;
+        }
+        config.smartcard = Some(smartcard);
+
+        let mut this = Self::new_inner(
+            peri,
+            None,
+            new_pin!(tx, AfType::output(OutputType::OpenDrain, Speed::Medium)),
+            None,
+            None,
+            None,
+            None,
+            None,
+            config,
+        )?;
+        this.tx.ck = new_pin!(ck, AfType::output(OutputType::PushPull, Speed::Medium));
+        Ok(this)
+    }
 }
 
 impl<'d, M: Mode> Uart<'d, M> {
@@ -1244,7 +1934,7 @@ impl<'d, M: Mode> Uart<'d, M> {
         tx: Option<PeripheralRef<'d, AnyPin>>,
         rts: Option<PeripheralRef<'d, AnyPin>>,
         cts: Option<PeripheralRef<'d, AnyPin>>,
-        de: Option<PeripheralRef<'d, AnyPin>>,
+        de: Option<DeOutput<'d>>,
         tx_dma: Option<ChannelAndRequest<'d>>,
         rx_dma: Option<ChannelAndRequest<'d>>,
         config: Config,
@@ -1262,6 +1952,7 @@ impl<'d, M: Mode> Uart<'d, M> {
                 tx,
                 cts,
                 de,
+                ck: None,
                 tx_dma,
             },
             rx: UartRx {
@@ -1273,6 +1964,7 @@ impl<'d, M: Mode> Uart<'d, M> {
                 rts,
                 rx_dma,
                 detect_previous_overrun: config.detect_previous_overrun,
+                rx_error_policy: config.rx_error_policy,
                 #[cfg(any(usart_v1, usart_v2))]
                 buffered_sr: stm32_metapac::usart::regs::Sr(0),
             },
@@ -1307,6 +1999,11 @@ impl<'d, M: Mode> Uart<'d, M> {
         self.tx.blocking_write(buffer)
     }
 
+    /// Perform a blocking write of 9-bit words
+    pub fn blocking_write_u16(&mut self, buffer: &[u16]) -> Result<(), Error> {
+        self.tx.blocking_write_u16(buffer)
+    }
+
     /// Block until transmission complete
     pub fn blocking_flush(&mut self) -> Result<(), Error> {
         self.tx.blocking_flush()
@@ -1322,12 +2019,61 @@ impl<'d, M: Mode> Uart<'d, M> {
         self.rx.blocking_read(buffer)
     }
 
+    /// Perform a blocking read of 9-bit words into `buffer`
+    pub fn blocking_read_u16(&mut self, buffer: &mut [u16]) -> Result<(), Error> {
+        self.rx.blocking_read_u16(buffer)
+    }
+
+    /// Mute the receiver until an address byte for `address` is received.
+    ///
+    /// See [`UartRx::mute_until_address`].
+    pub fn mute_until_address(&mut self, address: u8) {
+        self.rx.mute_until_address(address)
+    }
+
+    /// Perform a blocking read into `buffer`, returning early when the line goes idle
+    pub fn blocking_read_until_idle(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        self.rx.blocking_read_until_idle(buffer)
+    }
+
+    /// Send a break.
+    ///
+    /// See [`UartTx::send_break`].
+    pub fn send_break(&self) {
+        self.tx.send_break();
+    }
+
+    /// Reconfigure the driver
+    ///
+    /// This can be used to change the baud rate, parity or stop bits at runtime, e.g. after
+    /// negotiating a new speed. Any byte still being transmitted is sent out first, but no
+    /// transfer should be in progress on either half.
+    pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        self.rx.rx_error_policy = config.rx_error_policy;
+        reconfigure(self.tx.info, self.tx.kernel_clock, config)
+    }
+
     /// Split the Uart into a transmitter and receiver, which is
     /// particularly useful when having two tasks correlating to
     /// transmitting and receiving.
     pub fn split(self) -> (UartTx<'d, M>, UartRx<'d, M>) {
         (self.tx, self.rx)
     }
+
+    /// Split the Uart into a transmitter and receiver by mutable reference,
+    /// which is useful to read and write concurrently from the same task,
+    /// e.g. with `join`, while keeping ownership of the Uart.
+    pub fn split_ref(&mut self) -> (&mut UartTx<'d, M>, &mut UartRx<'d, M>) {
+        (&mut self.tx, &mut self.rx)
+    }
+
+    /// Join a transmitter and receiver obtained from [`split`](Self::split) back into a Uart.
+    ///
+    /// Panics if `tx` and `rx` belong to different peripherals.
+    pub fn join(tx: UartTx<'d, M>, rx: UartRx<'d, M>) -> Self {
+        assert!(core::ptr::eq(tx.info, rx.info));
+        Self { tx, rx }
+    }
 }
 
 fn reconfigure(info: &Info, kernel_clock: Hertz, config: &Config) -> Result<(), ConfigError> {
@@ -1335,7 +2081,21 @@ fn reconfigure(info: &Info, kernel_clock: Hertz, config: &Config) -> Result<(),
     let r = info.regs;
 
     let cr = r.cr1().read();
-    configure(info, kernel_clock, config, cr.re(), cr.te())?;
+
+    // Let the byte being shifted out finish, disabling the USART would cut it short.
+    if cr.te() {
+        while !sr(r).read().tc() {}
+    }
+
+    // Half-duplex and smartcard modes are chosen by the constructor, not by the user config.
+    let mut config = *config;
+    config.half_duplex = r.cr3().read().hdsel();
+    config.smartcard = match info.kind {
+        Kind::Uart => smartcard_config(r),
+        #[cfg(any(usart_v3, usart_v4))]
+        Kind::Lpuart => None,
+    };
+    configure(info, kernel_clock, &config, cr.re(), cr.te())?;
 
     info.interrupt.unpend();
     unsafe { info.interrupt.enable() };
@@ -1357,6 +2117,44 @@ fn configure(
         return Err(ConfigError::RxOrTxNotEnabled);
     }
 
+    if config.lin_break_detection.is_some() && kind != Kind::Uart {
+        return Err(ConfigError::LinNotSupported);
+    }
+    if config.irda.is_some() && kind != Kind::Uart {
+        return Err(ConfigError::IrdaNotSupported);
+    }
+    if let Some(sc) = config.smartcard {
+        if kind != Kind::Uart {
+            return Err(ConfigError::SmartcardNotSupported);
+        }
+        if !(1..=31).contains(&sc.clock_divider) {
+            return Err(ConfigError::SmartcardClockDividerOutOfRange);
+        }
+        #[cfg(any(usart_v3, usart_v4))]
+        if sc.retries > 7 {
+            return Err(ConfigError::SmartcardRetriesOutOfRange);
+        }
+    }
+
+    // ISO 7816-3 characters are 8 data bits, even parity and 1.5 stop bits (or 2 when receiving).
+    let mut config = *config;
+    if config.smartcard.is_some() {
+        config.data_bits = DataBits::DataBits8;
+        config.parity = Parity::ParityEven;
+        config.stop_bits = StopBits::STOP1P5;
+    }
+    let config = &config;
+
+    // The parity bit takes the place of the MSB, so 9 data bits plus parity would need 10 bits.
+    if config.data_bits == DataBits::DataBits9 && config.parity != Parity::ParityNone {
+        return Err(ConfigError::DataBitsWithParityNotSupported);
+    }
+
+    #[cfg(not(any(usart_v1, usart_v2)))]
+    if config.de_assertion_time > 31 || config.de_deassertion_time > 31 {
+        return Err(ConfigError::DeTimeOutOfRange);
+    }
+
     #[cfg(not(usart_v4))]
     static DIVS: [(u16, ()); 1] = [(1, ())];
 
@@ -1476,8 +2274,58 @@ fn configure(
         #[cfg(not(usart_v1))]
         w.set_onebit(config.assume_noise_free);
         w.set_hdsel(config.half_duplex);
+        // wake up on a received character, and keep the kernel clock requested in Stop mode
+        #[cfg(any(usart_v3, usart_v4))]
+        {
+            w.set_wus(vals::Wus::RXNE);
+            w.set_wufie(config.wakeup_from_stop);
+        }
+        #[cfg(usart_v3)]
+        w.set_ucesm(config.wakeup_from_stop);
     });
 
+    #[cfg(any(usart_v3, usart_v4))]
+    if config.wakeup_from_stop {
+        enable_wakeup_from_stop(info.regs, kernel_clock);
+    }
+
+    if kind == Kind::Uart {
+        let ur = usart_regs(r);
+        if let Some(lbdl) = config.lin_break_detection {
+            ur.cr2().modify(|w| {
+                w.set_linen(true);
+                w.set_lbdl(match lbdl {
+                    LinBreakDetection::Bits10 => vals::Lbdl::BIT10,
+                    LinBreakDetection::Bits11 => vals::Lbdl::BIT11,
+                });
+            });
+        }
+
+        // In IrDA low-power mode, the prescaler divides the kernel clock down to about 1.8432 MHz.
+        // In smartcard mode, it divides the kernel clock by twice its value for the card clock.
+        let psc = match (config.irda, config.smartcard) {
+            (Some(IrdaMode::LowPower), _) => (kernel_clock.0 + 921_600) / 1_843_200,
+            (_, Some(sc)) => sc.clock_divider as u32,
+            _ => 1,
+        };
+        ur.gtpr().write(|w| {
+            w.set_psc(psc.clamp(1, 255) as u8);
+            w.set_gt(config.smartcard.map_or(0, |sc| sc.guard_time));
+        });
+        ur.cr2().modify(|w| w.set_clken(config.smartcard.is_some()));
+        ur.cr3().modify(|w| {
+            w.set_iren(config.irda.is_some());
+            w.set_irlp(match config.irda {
+                Some(IrdaMode::LowPower) => vals::Irlp::LOWPOWER,
+                _ => vals::Irlp::NORMAL,
+            });
+            w.set_scen(config.smartcard.is_some());
+            w.set_nack(config.smartcard.map_or(false, |sc| sc.nack));
+            #[cfg(any(usart_v3, usart_v4))]
+            w.set_scarcnt(config.smartcard.map_or(0, |sc| sc.retries));
+        });
+    }
+
     r.cr1().write(|w| {
         // enable uart
         w.set_ue(true);
@@ -1485,15 +2333,20 @@ fn configure(
         w.set_te(enable_tx);
         // enable receiver
         w.set_re(enable_rx);
+        // keep the receiver running in Stop mode
+        #[cfg(any(usart_v3, usart_v4))]
+        w.set_uesm(config.wakeup_from_stop);
         // configure word size
         // if using odd or even parity it must be configured to 9bits
-        w.set_m0(if config.parity != Parity::ParityNone {
-            trace!("USART: m0: vals::M0::BIT9");
-            vals::M0::BIT9
-        } else {
-            trace!("USART: m0: vals::M0::BIT8");
-            vals::M0::BIT8
-        });
+        w.set_m0(
+            if config.parity != Parity::ParityNone || config.data_bits == DataBits::DataBits9 {
+                trace!("USART: m0: vals::M0::BIT9");
+                vals::M0::BIT9
+            } else {
+                trace!("USART: m0: vals::M0::BIT8");
+                vals::M0::BIT8
+            },
+        );
         // configure parity
         w.set_pce(config.parity != Parity::ParityNone);
         w.set_ps(match config.parity {
@@ -1517,6 +2370,11 @@ fn configure(
             trace!("USART: set_fifoen: true (usart_v4)");
             w.set_fifoen(true);
         }
+        #[cfg(not(any(usart_v1, usart_v2)))]
+        {
+            w.set_deat(config.de_assertion_time);
+            w.set_dedt(config.de_deassertion_time);
+        }
     });
 
     Ok(())
@@ -1564,6 +2422,7 @@ impl embedded_hal_nb::serial::Error for Error {
             Self::Overrun => embedded_hal_nb::serial::ErrorKind::Overrun,
             Self::Parity => embedded_hal_nb::serial::ErrorKind::Parity,
             Self::BufferTooLong => embedded_hal_nb::serial::ErrorKind::Other,
+            Self::TimeoutNotSupported => embedded_hal_nb::serial::ErrorKind::Other,
         }
     }
 }
@@ -1626,6 +2485,22 @@ impl<M: Mode> embedded_io::ErrorType for UartTx<'_, M> {
     type Error = Error;
 }
 
+impl<M: Mode> embedded_io::ErrorType for UartRx<'_, M> {
+    type Error = Error;
+}
+
+impl<M: Mode> embedded_io::Read for Uart<'_, M> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.blocking_read_until_idle(buf)
+    }
+}
+
+impl<M: Mode> embedded_io::Read for UartRx<'_, M> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.blocking_read_until_idle(buf)
+    }
+}
+
 impl<M: Mode> embedded_io::Write for Uart<'_, M> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         self.blocking_write(buf)?;
@@ -1648,6 +2523,25 @@ impl<M: Mode> embedded_io::Write for UartTx<'_, M> {
     }
 }
 
+impl embedded_io_async::Read for Uart<'_, Async> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        embedded_io_async::Read::read(&mut self.rx, buf).await
+    }
+}
+
+impl embedded_io_async::Read for UartRx<'_, Async> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        // `read` must not return 0 unless `buf` is empty, so retry if the idle line was detected
+        // before any byte was received.
+        loop {
+            let n = self.read_until_idle(buf).await?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+        }
+    }
+}
+
 impl embedded_io_async::Write for Uart<'_, Async> {
     async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         self.write(buf).await?;
@@ -1675,10 +2569,14 @@ pub use buffered::*;
 pub use crate::usart::buffered::InterruptHandler as BufferedInterruptHandler;
 mod buffered;
 
+#[cfg(feature = "time")]
+pub mod at;
+pub mod dmx;
+pub mod logger;
 #[cfg(not(gpdma))]
 mod ringbuffered;
 #[cfg(not(gpdma))]
-pub use ringbuffered::RingBufferedUartRx;
+pub use ringbuffered::{RingBufferedUart, RingBufferedUartRx};
 
 #[cfg(any(usart_v1, usart_v2))]
 fn tdr(r: crate::pac::usart::Usart) -> *mut u8 {
@@ -1691,7 +2589,7 @@ fn rdr(r: crate::pac::usart::Usart) -> *mut u8 {
 }
 
 #[cfg(any(usart_v1, usart_v2))]
-fn sr(r: crate::pac::usart::Usart) -> crate::pac::common::Reg<regs::Sr, crate::pac::common::RW> {
+pub(crate) fn sr(r: crate::pac::usart::Usart) -> crate::pac::common::Reg<regs::Sr, crate::pac::common::RW> {
     r.sr()
 }
 
@@ -1712,7 +2610,7 @@ fn rdr(r: Regs) -> *mut u8 {
 }
 
 #[cfg(any(usart_v3, usart_v4))]
-fn sr(r: Regs) -> crate::pac::common::Reg<regs::Isr, crate::pac::common::R> {
+pub(crate) fn sr(r: Regs) -> crate::pac::common::Reg<regs::Isr, crate::pac::common::R> {
     r.isr()
 }
 
@@ -1722,6 +2620,126 @@ fn clear_interrupt_flags(r: Regs, sr: regs::Isr) {
     r.icr().write(|w| *w = regs::Icr(sr.0));
 }
 
+/// Keep the HSI kernel clock running in Stop mode if the instance uses it, and unmask the EXTI
+/// line of its wakeup event, for [`Config::wakeup_from_stop`].
+#[cfg(any(usart_v3, usart_v4))]
+fn enable_wakeup_from_stop(r: Regs, kernel_clock: Hertz) {
+    #[cfg(any(stm32l4, stm32l5, stm32wb, stm32wl, stm32g0, stm32g4, stm32u5))]
+    if kernel_clock == crate::rcc::HSI_FREQ {
+        crate::pac::RCC.cr().modify(|w| w.set_hsikeron(true));
+    }
+    #[cfg(stm32l0)]
+    if kernel_clock == crate::rcc::HSI_FREQ {
+        crate::pac::RCC.cr().modify(|w| w.set_hsi16keron(true));
+    }
+    #[cfg(not(any(stm32l0, stm32l4, stm32l5, stm32wb, stm32wl, stm32g0, stm32g4, stm32u5)))]
+    let _ = kernel_clock;
+
+    // The wakeup lines are direct lines, which only need unmasking. They are unmasked at reset on
+    // most chips, so the line is only known here for the families that may have it masked.
+    if let Some(line) = exti_wakeup_line(r) {
+        critical_section::with(|_| exti_cpu_regs().imr(0).modify(|w| w.set_line(line, true)));
+    }
+}
+
+/// EXTI line of the wakeup event of an instance.
+#[cfg(any(usart_v3, usart_v4))]
+fn exti_wakeup_line(r: Regs) -> Option<usize> {
+    let lines: &[(*mut (), usize)] = &[
+        #[cfg(all(stm32l0, peri_usart1))]
+        (crate::pac::USART1.as_ptr(), 25),
+        #[cfg(all(stm32l0, peri_usart2))]
+        (crate::pac::USART2.as_ptr(), 26),
+        #[cfg(all(stm32l0, peri_lpuart1))]
+        (crate::pac::LPUART1.as_ptr(), 28),
+        #[cfg(all(stm32l4, peri_usart1))]
+        (crate::pac::USART1.as_ptr(), 26),
+        #[cfg(all(stm32l4, peri_usart2))]
+        (crate::pac::USART2.as_ptr(), 27),
+        #[cfg(all(stm32l4, peri_usart3))]
+        (crate::pac::USART3.as_ptr(), 28),
+        #[cfg(all(stm32l4, peri_uart4))]
+        (crate::pac::UART4.as_ptr(), 29),
+        #[cfg(all(stm32l4, peri_uart5))]
+        (crate::pac::UART5.as_ptr(), 30),
+        #[cfg(all(stm32l4, peri_lpuart1))]
+        (crate::pac::LPUART1.as_ptr(), 31),
+        #[cfg(all(stm32wl, peri_usart1))]
+        (crate::pac::USART1.as_ptr(), 26),
+        #[cfg(all(stm32wl, peri_usart2))]
+        (crate::pac::USART2.as_ptr(), 27),
+        #[cfg(all(stm32wl, peri_lpuart1))]
+        (crate::pac::LPUART1.as_ptr(), 28),
+    ];
+    lines.iter().find(|(ptr, _)| *ptr == r.as_ptr()).map(|&(_, line)| line)
+}
+
+#[cfg(all(any(usart_v3, usart_v4), exti_w))]
+fn exti_cpu_regs() -> crate::pac::exti::Cpu {
+    crate::pac::EXTI.cpu(crate::pac::CORE_INDEX)
+}
+
+#[cfg(all(any(usart_v3, usart_v4), not(exti_w)))]
+fn exti_cpu_regs() -> crate::pac::exti::Exti {
+    crate::pac::EXTI
+}
+
+/// Registers with the LIN, IrDA, smartcard and receiver timeout bits, which the LPUART layout used
+/// for `Regs` doesn't have.
+#[cfg(any(usart_v3, usart_v4))]
+fn usart_regs(r: Regs) -> crate::pac::usart::Usart {
+    unsafe { crate::pac::usart::Usart::from_ptr(r.as_ptr()) }
+}
+
+#[cfg(any(usart_v1, usart_v2))]
+fn usart_regs(r: Regs) -> crate::pac::usart::Usart {
+    r
+}
+
+/// Read back the smartcard configuration, if smartcard mode is enabled.
+fn smartcard_config(r: Regs) -> Option<SmartcardConfig> {
+    let ur = usart_regs(r);
+    let cr3 = ur.cr3().read();
+    if !cr3.scen() {
+        return None;
+    }
+    let gtpr = ur.gtpr().read();
+    Some(SmartcardConfig {
+        clock_divider: gtpr.psc(),
+        guard_time: gtpr.gt(),
+        nack: cr3.nack(),
+        #[cfg(any(usart_v3, usart_v4))]
+        retries: cr3.scarcnt(),
+    })
+}
+
+fn lin_break_detected(r: Regs) -> bool {
+    #[cfg(any(usart_v1, usart_v2))]
+    return r.sr().read().lbd();
+    #[cfg(any(usart_v3, usart_v4))]
+    return usart_regs(r).isr().read().lbdf();
+}
+
+fn clear_lin_break_detected(r: Regs) {
+    #[cfg(any(usart_v1, usart_v2))]
+    r.sr().modify(|w| w.set_lbd(false));
+    #[cfg(any(usart_v3, usart_v4))]
+    usart_regs(r).icr().write(|w| w.set_lbdcf(true));
+}
+
+fn send_break(r: Regs) {
+    // Wait until a previous break has been sent
+    #[cfg(any(usart_v1, usart_v2))]
+    while r.cr1().read().sbk() {}
+    #[cfg(any(usart_v3, usart_v4))]
+    while r.isr().read().sbkf() {}
+
+    #[cfg(any(usart_v1, usart_v2))]
+    r.cr1().modify(|w| w.set_sbk(true));
+    #[cfg(any(usart_v3, usart_v4))]
+    r.rqr().write(|w| w.set_sbkrq(true));
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum Kind {
     Uart,
@@ -1732,6 +2750,7 @@ enum Kind {
 
 struct State {
     rx_waker: AtomicWaker,
+    tx_waker: AtomicWaker,
     tx_rx_refcount: AtomicU8,
 }
 
@@ -1739,6 +2758,7 @@ impl State {
     const fn new() -> Self {
         Self {
             rx_waker: AtomicWaker::new(),
+            tx_waker: AtomicWaker::new(),
             tx_rx_refcount: AtomicU8::new(0),
         }
     }
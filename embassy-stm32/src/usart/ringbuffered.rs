@@ -7,7 +7,9 @@ use embassy_embedded_hal::SetConfig;
 use embassy_hal_internal::PeripheralRef;
 use futures_util::future::{select, Either};
 
-use super::{clear_interrupt_flags, rdr, reconfigure, sr, Config, ConfigError, Error, Info, State, UartRx};
+use super::{
+    clear_interrupt_flags, rdr, reconfigure, sr, Config, ConfigError, Error, Info, State, Uart, UartRx, UartTx,
+};
 use crate::dma::ReadableRingBuffer;
 use crate::gpio::{AnyPin, SealedPin as _};
 use crate::mode::Async;
@@ -70,6 +72,69 @@ impl<'d> UartRx<'d, Async> {
     }
 }
 
+/// Bidirectional UART Driver with a ring-buffered receiver
+///
+/// Created with [Uart::into_ring_buffered]. Received data is continuously written to the ring buffer by
+/// DMA in the background, so bytes arriving while nothing is awaiting [`read`](Self::read) are not lost.
+pub struct RingBufferedUart<'d> {
+    tx: UartTx<'d, Async>,
+    rx: RingBufferedUartRx<'d>,
+}
+
+impl<'d> SetConfig for RingBufferedUart<'d> {
+    type Config = Config;
+    type ConfigError = ConfigError;
+
+    fn set_config(&mut self, config: &Self::Config) -> Result<(), Self::ConfigError> {
+        self.set_config(config)
+    }
+}
+
+impl<'d> Uart<'d, Async> {
+    /// Turn the `Uart` into a uart with a ring-buffered receiver, see [UartRx::into_ring_buffered].
+    pub fn into_ring_buffered(self, dma_buf: &'d mut [u8]) -> RingBufferedUart<'d> {
+        RingBufferedUart {
+            tx: self.tx,
+            rx: self.rx.into_ring_buffered(dma_buf),
+        }
+    }
+}
+
+impl<'d> RingBufferedUart<'d> {
+    /// Split the Uart into a transmitter and receiver, which is
+    /// particularly useful when having two tasks correlating to
+    /// transmitting and receiving.
+    pub fn split(self) -> (UartTx<'d, Async>, RingBufferedUartRx<'d>) {
+        (self.tx, self.rx)
+    }
+
+    /// Clear the ring buffer and start receiving in the background
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.rx.start()
+    }
+
+    /// Reconfigure the driver
+    pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        self.tx.set_config(config)?;
+        self.rx.set_config(config)
+    }
+
+    /// Perform an asynchronous write
+    pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        self.tx.write(buffer).await
+    }
+
+    /// Wait until transmission complete
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.tx.flush().await
+    }
+
+    /// Read bytes that are readily available in the ring buffer, see [RingBufferedUartRx::read].
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.rx.read(buf).await
+    }
+}
+
 impl<'d> RingBufferedUartRx<'d> {
     /// Clear the ring buffer and start receiving in the background
     pub fn start(&mut self) -> Result<(), Error> {
@@ -274,3 +339,34 @@ impl embedded_io_async::Read for RingBufferedUartRx<'_> {
         self.read(buf).await
     }
 }
+
+impl embassy_futures::stream::Stream for RingBufferedUartRx<'_> {
+    type Item = Result<u8, Error>;
+
+    async fn next(&mut self) -> Self::Item {
+        let mut byte = 0;
+        self.read(core::slice::from_mut(&mut byte)).await?;
+        Ok(byte)
+    }
+}
+
+impl embedded_io_async::ErrorType for RingBufferedUart<'_> {
+    type Error = Error;
+}
+
+impl embedded_io_async::Read for RingBufferedUart<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.read(buf).await
+    }
+}
+
+impl embedded_io_async::Write for RingBufferedUart<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write(buf).await?;
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush().await
+    }
+}
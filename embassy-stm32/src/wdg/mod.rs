@@ -6,6 +6,11 @@ use stm32_metapac::iwdg::vals::{Key, Pr};
 
 use crate::rcc::LSI_FREQ;
 
+#[cfg(feature = "time")]
+mod supervisor;
+#[cfg(feature = "time")]
+pub use supervisor::*;
+
 /// Independent watchdog (IWDG) driver.
 pub struct IndependentWatchdog<'d, T: Instance> {
     wdg: PhantomData<&'d mut T>,
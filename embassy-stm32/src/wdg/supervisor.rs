@@ -0,0 +1,108 @@
+//! Task supervision on top of the independent watchdog.
+//!
+//! A single task petting the watchdog only proves that this one task is alive. With a
+//! [`Supervisor`], every supervised task declares how often it reports in, and the watchdog is
+//! only fed while all of them do. When a task gets stuck, the supervisor reports it and stops
+//! feeding the watchdog, which then resets the chip.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
+
+use super::{IndependentWatchdog, Instance};
+
+/// Heartbeat of a supervised task.
+///
+/// Place it in a `static`, call [`heartbeat`](Self::heartbeat) from the task at least once per
+/// interval, and register it with a [`Supervisor`].
+pub struct Supervised {
+    name: &'static str,
+    interval: Duration,
+    last: Mutex<CriticalSectionRawMutex, Cell<Instant>>,
+}
+
+impl Supervised {
+    /// Create a new heartbeat for a task that reports in at least every `interval`.
+    ///
+    /// The task counts as alive until `interval` after boot, to give it time to start.
+    pub const fn new(name: &'static str, interval: Duration) -> Self {
+        Self {
+            name,
+            interval,
+            last: Mutex::new(Cell::new(Instant::from_ticks(0))),
+        }
+    }
+
+    /// Report that the task is alive.
+    pub fn heartbeat(&self) {
+        let now = Instant::now();
+        self.last.lock(|last| last.set(now));
+    }
+
+    /// Name of the task, for reporting.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Maximum time between two heartbeats.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Time of the last heartbeat.
+    pub fn last_heartbeat(&self) -> Instant {
+        self.last.lock(|last| last.get())
+    }
+
+    fn is_fresh(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_heartbeat()) <= self.interval
+    }
+}
+
+/// Feeds an [`IndependentWatchdog`] only while all supervised tasks are alive.
+pub struct Supervisor<'a, 'd, T: Instance> {
+    wdg: IndependentWatchdog<'d, T>,
+    tasks: &'a [&'a Supervised],
+    period: Duration,
+}
+
+impl<'a, 'd, T: Instance> Supervisor<'a, 'd, T> {
+    /// Create a new supervisor.
+    ///
+    /// `period` is how often heartbeats are checked and the watchdog fed; it must be shorter than
+    /// the watchdog timeout. The watchdog is started if it isn't already.
+    pub fn new(mut wdg: IndependentWatchdog<'d, T>, tasks: &'a [&'a Supervised], period: Duration) -> Self {
+        wdg.unleash();
+        Self { wdg, tasks, period }
+    }
+
+    /// Check all heartbeats once, and feed the watchdog if they are all fresh.
+    ///
+    /// Returns the first task that missed its heartbeat otherwise.
+    pub fn check(&mut self) -> Result<(), &'a Supervised> {
+        let now = Instant::now();
+        if let Some(stale) = self.tasks.iter().find(|t| !t.is_fresh(now)) {
+            return Err(stale);
+        }
+        self.wdg.pet();
+        Ok(())
+    }
+
+    /// Supervise the tasks until one of them misses its heartbeat.
+    ///
+    /// `on_stale` is called once with the culprit, e.g. to log it or store it in a backup register,
+    /// after which the watchdog is no longer fed and resets the chip.
+    pub async fn run(&mut self, mut on_stale: impl FnMut(&Supervised)) -> ! {
+        loop {
+            if let Err(stale) = self.check() {
+                on_stale(stale);
+                loop {
+                    Timer::after(self.period).await;
+                }
+            }
+            Timer::after(self.period).await;
+        }
+    }
+}
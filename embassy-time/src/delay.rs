@@ -13,7 +13,10 @@ pub fn block_for(duration: Duration) {
 /// the amount provided, but accuracy can be affected by many factors, including interrupt usage.
 /// Make sure to use a suitable tick rate for your use case. The tick rate is defined by the currently
 /// active driver.
-#[derive(Clone)]
+///
+/// `Delay` holds no state, so drivers that need their own delay object can each be given a copy.
+#[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Delay;
 
 impl embedded_hal_1::delay::DelayNs for Delay {
@@ -13,6 +13,7 @@ pub(crate) mod fmt;
 mod delay;
 mod duration;
 mod instant;
+mod slice;
 mod timer;
 
 #[cfg(feature = "mock-driver")]
@@ -32,6 +33,7 @@ pub use delay::{block_for, Delay};
 pub use duration::Duration;
 pub use embassy_time_driver::TICK_HZ;
 pub use instant::Instant;
+pub use slice::{SlicedJob, Slicer};
 pub use timer::{with_deadline, with_timeout, Ticker, TimeoutError, Timer, WithTimeout};
 
 const fn gcd(a: u64, b: u64) -> u64 {
@@ -0,0 +1,157 @@
+use core::future::poll_fn;
+use core::task::Poll;
+
+use crate::{Duration, Instant};
+
+/// Splits a long computation into slices of bounded time, yielding to other tasks in between.
+///
+/// Cooperative executors only switch tasks when a task awaits, so a long computation (an FFT, a
+/// filter over a large buffer...) delays every other task of its executor until it's done. Calling
+/// [`checkpoint`](Self::checkpoint) between short steps of the computation yields once the current
+/// slice has run for longer than the budget, bounding that delay to about the budget plus one step.
+///
+/// ```rust,no_run
+/// # async fn example(samples: &mut [f32]) {
+/// use embassy_time::{Duration, Slicer};
+///
+/// let mut slicer = Slicer::new(Duration::from_micros(500));
+/// for sample in samples.iter_mut() {
+///     *sample *= 0.5;
+///     slicer.checkpoint().await;
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Slicer {
+    budget: Duration,
+    slice_start: Instant,
+}
+
+impl Slicer {
+    /// Create a slicer running slices of at most `budget`, starting the first slice now.
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            slice_start: Instant::now(),
+        }
+    }
+
+    /// Yield to other tasks if the current slice has used up its budget, then start a new slice.
+    ///
+    /// Returns whether it yielded.
+    pub async fn checkpoint(&mut self) -> bool {
+        if self.slice_start.elapsed() < self.budget {
+            return false;
+        }
+
+        yield_now().await;
+        self.slice_start = Instant::now();
+        true
+    }
+
+    /// Time spent in the current slice.
+    pub fn elapsed(&self) -> Duration {
+        self.slice_start.elapsed()
+    }
+}
+
+/// A computation made of independent steps, run in time slices.
+///
+/// Each call to [`next_slice`](Self::next_slice) yields to other tasks, then runs steps until the
+/// budget is used up, and resolves with the progress so far. Awaiting it in a loop runs the whole
+/// job while reporting its progress; [`run`](Self::run) does just that without reporting.
+///
+/// ```rust,no_run
+/// # async fn example(input: &[i32], output: &mut [i32]) {
+/// use embassy_time::{Duration, SlicedJob};
+///
+/// let mut job = SlicedJob::new(Duration::from_millis(1), input.iter().zip(output.iter_mut()), |(x, y)| {
+///     *y = x * 2;
+/// });
+/// while let Some(done) = job.next_slice().await {
+///     // Report `done` steps of progress.
+/// }
+/// # }
+/// ```
+pub struct SlicedJob<I, F> {
+    steps: I,
+    step: F,
+    budget: Duration,
+    done: usize,
+    finished: bool,
+}
+
+impl<I: Iterator, F: FnMut(I::Item)> SlicedJob<I, F> {
+    /// Create a job calling `step` on each item of `steps`, in slices of at most `budget`.
+    pub fn new(budget: Duration, steps: impl IntoIterator<IntoIter = I>, step: F) -> Self {
+        Self {
+            steps: steps.into_iter(),
+            step,
+            budget,
+            done: 0,
+            finished: false,
+        }
+    }
+
+    /// Run the next slice, returning the number of steps done so far, or `None` if the job was
+    /// already finished.
+    ///
+    /// At least one step is run per slice, so the job makes progress even if a step exceeds the
+    /// budget.
+    pub async fn next_slice(&mut self) -> Option<usize> {
+        if self.finished {
+            return None;
+        }
+
+        yield_now().await;
+        let start = Instant::now();
+        loop {
+            match self.steps.next() {
+                Some(item) => {
+                    (self.step)(item);
+                    self.done += 1;
+                }
+                None => {
+                    self.finished = true;
+                    break;
+                }
+            }
+            if start.elapsed() >= self.budget {
+                break;
+            }
+        }
+        Some(self.done)
+    }
+
+    /// Number of steps done so far.
+    pub fn done(&self) -> usize {
+        self.done
+    }
+
+    /// Whether all steps are done.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Run the job to completion, returning the number of steps done.
+    pub async fn run(mut self) -> usize {
+        while self.next_slice().await.is_some() {}
+        self.done
+    }
+}
+
+/// Yield to other tasks once.
+async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
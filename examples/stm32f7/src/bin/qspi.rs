@@ -6,7 +6,7 @@ use defmt::info;
 use embassy_executor::Spawner;
 use embassy_stm32::mode::Async;
 use embassy_stm32::qspi::enums::{AddressSize, ChipSelectHighTime, FIFOThresholdLevel, MemorySize, *};
-use embassy_stm32::qspi::{Config as QspiCfg, Instance, Qspi, TransferConfig};
+use embassy_stm32::qspi::{Config as QspiCfg, Qspi, TransferConfig};
 use embassy_stm32::time::mhz;
 use embassy_stm32::Config as StmCfg;
 use {defmt_rtt as _, panic_probe as _};
@@ -44,12 +44,12 @@ const MEMORY_ADDR: u32 = 0x00000000u32;
 /// Implementation of access to flash chip.
 /// Chip commands are hardcoded as it depends on used chip.
 /// This implementation is using chip GD25Q64C from Giga Device
-pub struct FlashMemory<I: Instance> {
-    qspi: Qspi<'static, I, Async>,
+pub struct FlashMemory {
+    qspi: Qspi<'static, Async>,
 }
 
-impl<I: Instance> FlashMemory<I> {
-    pub fn new(qspi: Qspi<'static, I, Async>) -> Self {
+impl FlashMemory {
+    pub fn new(qspi: Qspi<'static, Async>) -> Self {
         let mut memory = Self { qspi };
 
         memory.reset_memory();
@@ -71,6 +71,7 @@ impl<I: Instance> FlashMemory<I> {
             instruction: cmd,
             address: None,
             dummy: DummyCycles::_0,
+            ..Default::default()
         };
         self.qspi.command(transaction);
     }
@@ -94,6 +95,7 @@ impl<I: Instance> FlashMemory<I> {
             instruction: CMD_READ_ID,
             address: None,
             dummy: DummyCycles::_0,
+            ..Default::default()
         };
         self.qspi.blocking_read(&mut buffer, transaction);
         buffer
@@ -108,6 +110,7 @@ impl<I: Instance> FlashMemory<I> {
             instruction: CMD_READ_UUID,
             address: Some(0),
             dummy: DummyCycles::_8,
+            ..Default::default()
         };
         self.qspi.blocking_read(&mut buffer, transaction);
         buffer
@@ -121,6 +124,7 @@ impl<I: Instance> FlashMemory<I> {
             instruction: CMD_QUAD_READ,
             address: Some(addr),
             dummy: DummyCycles::_8,
+            ..Default::default()
         };
         if use_dma {
             self.qspi.blocking_read_dma(buffer, transaction);
@@ -141,6 +145,7 @@ impl<I: Instance> FlashMemory<I> {
             instruction: cmd,
             address: Some(addr),
             dummy: DummyCycles::_0,
+            ..Default::default()
         };
         self.enable_write();
         self.qspi.command(transaction);
@@ -178,6 +183,7 @@ impl<I: Instance> FlashMemory<I> {
             instruction: CMD_QUAD_WRITE_PG,
             address: Some(addr),
             dummy: DummyCycles::_0,
+            ..Default::default()
         };
         self.enable_write();
         if use_dma {
@@ -213,6 +219,7 @@ impl<I: Instance> FlashMemory<I> {
             instruction: cmd,
             address: None,
             dummy: DummyCycles::_0,
+            ..Default::default()
         };
         self.qspi.blocking_read(&mut buffer, transaction);
         buffer[0]
@@ -227,6 +234,7 @@ impl<I: Instance> FlashMemory<I> {
             instruction: cmd,
             address: None,
             dummy: DummyCycles::_0,
+            ..Default::default()
         };
         self.qspi.blocking_write(&buffer, transaction);
     }